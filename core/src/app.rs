@@ -1,26 +1,77 @@
 use druid::{
-    AppLauncher, Env, LocalizedString, Size, Widget, WidgetExt, WindowDesc, WindowId,
+    AppLauncher, Env, ExtEventSink, LocalizedString, Size, Target, Widget,
+    WidgetExt, WindowDesc, WindowId,
 };
+use lapce_proxy::shell_env::apply_login_shell_env;
 
 use crate::{
-    data::{ LapceData, LapceWindowLens},
+    command::{LapceUICommand, LAPCE_UI_COMMAND},
+    config::Config,
+    data::{LapceData, LapceWindowLens},
+    location::location_of_arg,
+    recovery,
+    state::LapceWorkspace,
     window::LapceWindowNew,
 };
 
 fn build_window(data: &LapceData) -> impl Widget<LapceData> {
     let (window_id, window_data) = data.windows.iter().next().unwrap();
+    let window_id = *window_id;
     LapceWindowNew::new(window_data)
-        .lens(LapceWindowLens(*window_id))
-        .env_scope(|env: &mut Env, data: &LapceData| data.reload_env(env))
+        .lens(LapceWindowLens(window_id))
+        .env_scope(move |env: &mut Env, data: &LapceData| {
+            data.reload_env(env);
+            if let Some(window) = data.windows.get(&window_id) {
+                window.reload_env(env);
+            }
+        })
     // .debug_widget()
     // .debug_widget_id()
     // .debug_paint_layout()
     // .debug_invalidation()
 }
 
+/// Opens the files given on the command line in the active tab, each at the
+/// `:line:column` it ends with if any.
+fn open_args(data: &LapceData, event_sink: ExtEventSink) {
+    let tab_id = match data.windows.values().next() {
+        Some(window) => window.active_id,
+        None => return,
+    };
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(e) => {
+            log::error!("can't get the current directory: {}", e);
+            return;
+        }
+    };
+    for arg in std::env::args().skip(1).filter(|arg| !arg.starts_with('-')) {
+        match location_of_arg(&arg, &cwd) {
+            Some(location) => {
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::JumpToLocation(None, location),
+                    Target::Widget(tab_id),
+                );
+            }
+            None => log::error!("can't open {}", arg),
+        }
+    }
+}
+
 pub fn lanuch() {
+    // setting the environment is only safe while this is the only thread,
+    // and the proxy, language servers and terminals all inherit it
+    if Config::load(&LapceWorkspace::default())
+        .map(|config| config.lapce.resolve_shell_env)
+        .unwrap_or(true)
+    {
+        apply_login_shell_env();
+    }
+
     let launcher = AppLauncher::new();
     let data = LapceData::load(launcher.get_external_handle());
+    open_args(&data, launcher.get_external_handle());
     let root = build_window(&data);
     let window = WindowDesc::new(root)
         .title(LocalizedString::new("Lapce").with_placeholder("Lapce"))
@@ -29,5 +80,8 @@ pub fn lanuch() {
         .with_min_size(Size::new(800.0, 600.0));
     let launcher = launcher.with_window(window);
     let launcher = launcher.configure_env(|env, data| data.reload_env(env));
+    let db = data.db.clone();
     launcher.launch(data).expect("launch failed");
+    db.wait_for_saves();
+    recovery::end_session();
 }
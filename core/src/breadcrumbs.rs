@@ -0,0 +1,725 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use druid::{
+    piet::{Text, TextLayout as TextLayoutTrait, TextLayoutBuilder},
+    BoxConstraints, Command, Env, Event, EventCtx, FontFamily, LayoutCtx, LifeCycle,
+    LifeCycleCtx, PaintCtx, Point, Rect, RenderContext, Size, Target, TimerToken,
+    UpdateCtx, Widget, WidgetId,
+};
+use lapce_proxy::dispatch::FileNodeItem;
+use lsp_types::{DocumentSymbolResponse, Position, Range, SymbolKind};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    buffer::BufferContent,
+    command::{
+        CommandTarget, LapceCommandNew, LapceUICommand, LapceWorkbenchCommand,
+        LAPCE_UI_COMMAND,
+    },
+    config::LapceTheme,
+    data::LapceTabData,
+    menu::MenuItem,
+    svg::symbol_svg_new,
+};
+
+/// How long the cursor rests before the breadcrumbs follow it.
+const REFRESH_DELAY: Duration = Duration::from_millis(150);
+
+const HEIGHT: f64 = 22.0;
+
+/// A symbol of the document, with the ones it contains.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentSymbolNode {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+    /// Where the name of the symbol is, which is where a jump lands.
+    pub position: Position,
+    pub children: Vec<DocumentSymbolNode>,
+}
+
+/// The symbols of the response as a tree. A flat response is nested by the
+/// ranges of the symbols.
+pub fn symbol_tree(response: DocumentSymbolResponse) -> Vec<DocumentSymbolNode> {
+    fn node(symbol: lsp_types::DocumentSymbol) -> DocumentSymbolNode {
+        DocumentSymbolNode {
+            name: symbol.name,
+            kind: symbol.kind,
+            range: symbol.range,
+            position: symbol.selection_range.start,
+            children: symbol
+                .children
+                .unwrap_or_default()
+                .into_iter()
+                .map(node)
+                .collect(),
+        }
+    }
+
+    fn insert(nodes: &mut Vec<DocumentSymbolNode>, new: DocumentSymbolNode) {
+        if let Some(last) = nodes.last_mut() {
+            if contains(&last.range, &new.range) {
+                insert(&mut last.children, new);
+                return;
+            }
+        }
+        nodes.push(new);
+    }
+
+    match response {
+        DocumentSymbolResponse::Nested(symbols) => {
+            symbols.into_iter().map(node).collect()
+        }
+        DocumentSymbolResponse::Flat(mut symbols) => {
+            // the outer symbols come before the ones they contain
+            symbols.sort_by(|a, b| {
+                a.location
+                    .range
+                    .start
+                    .cmp(&b.location.range.start)
+                    .then(b.location.range.end.cmp(&a.location.range.end))
+            });
+            let mut nodes = Vec::new();
+            for symbol in symbols {
+                insert(
+                    &mut nodes,
+                    DocumentSymbolNode {
+                        name: symbol.name,
+                        kind: symbol.kind,
+                        range: symbol.location.range,
+                        position: symbol.location.range.start,
+                        children: Vec::new(),
+                    },
+                );
+            }
+            nodes
+        }
+    }
+}
+
+fn contains(outer: &Range, inner: &Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+/// The indices leading to the innermost symbol around the position, one for
+/// each level of the tree.
+pub fn symbol_chain(
+    symbols: &[DocumentSymbolNode],
+    position: Position,
+) -> Vec<usize> {
+    let mut chain = Vec::new();
+    let mut symbols = symbols;
+    while let Some(i) = symbols
+        .iter()
+        .position(|s| s.range.start <= position && position <= s.range.end)
+    {
+        chain.push(i);
+        symbols = &symbols[i].children;
+    }
+    chain
+}
+
+/// The symbols at the level of the last index of the chain, and the one it
+/// points to.
+fn symbol_level<'a>(
+    symbols: &'a [DocumentSymbolNode],
+    chain: &[usize],
+) -> Option<(&'a [DocumentSymbolNode], &'a DocumentSymbolNode)> {
+    let (last, parents) = chain.split_last()?;
+    let mut level = symbols;
+    for i in parents {
+        level = &level.get(*i)?.children;
+    }
+    Some((level, level.get(*last)?))
+}
+
+/// The folders from the workspace down to the file, and the file. Outside
+/// of the workspace, only the file.
+pub fn path_segments(path: &Path, workspace: Option<&Path>) -> Vec<PathBuf> {
+    let root = match workspace.filter(|w| path.starts_with(w)) {
+        Some(root) => root,
+        None => return vec![path.to_path_buf()],
+    };
+    let mut segments: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|p| *p != root)
+        .map(|p| p.to_path_buf())
+        .collect();
+    segments.reverse();
+    segments
+}
+
+/// Where a pick of the breadcrumbs' menus goes: a folder is revealed in
+/// the explorer, a file opened at the position.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BreadcrumbTarget {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub position: Option<Position>,
+}
+
+impl BreadcrumbTarget {
+    fn menu_item(text: String, target: BreadcrumbTarget) -> MenuItem {
+        MenuItem {
+            text,
+            command: LapceCommandNew {
+                cmd: LapceWorkbenchCommand::OpenBreadcrumb.to_string(),
+                data: Some(json!(target)),
+                palette_desc: None,
+                target: CommandTarget::Workbench,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Path(PathBuf),
+    /// By the indices leading to it in the symbols of the buffer.
+    Symbol(Vec<usize>),
+}
+
+/// The path of the file of the editor and the symbols around its cursor,
+/// in a strip between its header and its text.
+pub struct LapceEditorBreadcrumbs {
+    view_id: WidgetId,
+    widget_id: WidgetId,
+    /// Off for the editors without a header, like the inputs of the panels.
+    pub display: bool,
+    segments: Vec<(Segment, Rect)>,
+    timer: TimerToken,
+    mouse_pos: Point,
+    /// The revision of the buffer whose symbols were asked for last.
+    symbols_requested: Option<(PathBuf, u64)>,
+}
+
+impl LapceEditorBreadcrumbs {
+    pub fn new(view_id: WidgetId) -> Self {
+        Self {
+            view_id,
+            widget_id: WidgetId::next(),
+            display: true,
+            segments: Vec::new(),
+            timer: TimerToken::INVALID,
+            mouse_pos: Point::ZERO,
+            symbols_requested: None,
+        }
+    }
+
+    fn is_shown(&self, data: &LapceTabData) -> bool {
+        self.display
+            && data.config.editor.show_breadcrumbs
+            && matches!(
+                data.main_split
+                    .editors
+                    .get(&self.view_id)
+                    .map(|e| &e.content),
+                Some(BufferContent::File(_))
+            )
+    }
+
+    /// Takes the path and the symbols at the cursor as they are now, and
+    /// asks for the symbols of the buffer if they're older than its text.
+    fn refresh(&mut self, ctx: &mut EventCtx, data: &LapceTabData) {
+        let editor_data = data.editor_view_content(self.view_id);
+        let path = match &editor_data.buffer.content {
+            BufferContent::File(path) => path.clone(),
+            BufferContent::Local(_) => return,
+        };
+        let mut segments: Vec<Segment> =
+            path_segments(&path, data.workspace.path.as_deref())
+                .into_iter()
+                .map(Segment::Path)
+                .collect();
+        if let Some((_, symbols)) = editor_data.buffer.document_symbols.as_ref() {
+            let offset = editor_data.editor.cursor.offset();
+            let position = editor_data.buffer.offset_to_position(offset);
+            let chain = symbol_chain(symbols, position);
+            segments.extend(
+                (1..=chain.len()).map(|n| Segment::Symbol(chain[..n].to_vec())),
+            );
+        }
+        if segments
+            != self
+                .segments
+                .iter()
+                .map(|(s, _)| s.clone())
+                .collect::<Vec<_>>()
+        {
+            self.segments = segments.into_iter().map(|s| (s, Rect::ZERO)).collect();
+            ctx.request_paint();
+        }
+
+        let rev = editor_data.buffer.rev;
+        let up_to_date = editor_data
+            .buffer
+            .document_symbols
+            .as_ref()
+            .map(|(symbols_rev, _)| *symbols_rev == rev)
+            .unwrap_or(false);
+        let requested = Some((path.clone(), rev));
+        if up_to_date || self.symbols_requested == requested {
+            return;
+        }
+        self.symbols_requested = requested;
+        let tab_id = data.id;
+        let event_sink = ctx.get_external_handle();
+        data.proxy.get_document_symbols(
+            editor_data.buffer.id,
+            Box::new(move |result| {
+                let response = result.ok().and_then(|res| {
+                    serde_json::from_value::<DocumentSymbolResponse>(res).ok()
+                });
+                if let Some(response) = response {
+                    event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::UpdateDocumentSymbols(
+                            path,
+                            rev,
+                            Arc::new(symbol_tree(response)),
+                        ),
+                        Target::Widget(tab_id),
+                    );
+                }
+            }),
+        );
+    }
+
+    fn segment_text(&self, segment: &Segment, data: &LapceTabData) -> String {
+        match segment {
+            Segment::Path(path) => path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_string(),
+            Segment::Symbol(chain) => self
+                .symbols(data)
+                .and_then(|symbols| symbol_level(&symbols, chain))
+                .map(|(_, symbol)| symbol.name.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn symbols(&self, data: &LapceTabData) -> Option<Arc<Vec<DocumentSymbolNode>>> {
+        let buffer = data.main_split.editor_buffer(self.view_id);
+        buffer.document_symbols.as_ref().map(|(_, s)| s.clone())
+    }
+
+    /// Opens the menu of what's next to the segment: the other files of its
+    /// folder, or the other symbols of its level.
+    fn open_menu(&self, ctx: &mut EventCtx, data: &LapceTabData, i: usize) {
+        let (segment, rect) = &self.segments[i];
+        let point = ctx.to_window(Point::new(rect.x0, rect.y1));
+        match segment {
+            Segment::Path(path) => {
+                let dir = match path.parent() {
+                    Some(dir) => dir.to_path_buf(),
+                    None => return,
+                };
+                let widget_id = self.widget_id;
+                let event_sink = ctx.get_external_handle();
+                data.proxy.read_dir(
+                    &dir,
+                    Box::new(move |result| {
+                        let items = result.ok().and_then(|res| {
+                            serde_json::from_value::<Vec<FileNodeItem>>(res).ok()
+                        });
+                        let mut items = match items {
+                            Some(items) => items,
+                            None => return,
+                        };
+                        items.retain(|item| !item.excluded);
+                        items.sort_by(|a, b| {
+                            a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+                        });
+                        let menu_items = items
+                            .into_iter()
+                            .map(|item| {
+                                let mut text = item
+                                    .path_buf
+                                    .file_name()
+                                    .and_then(|s| s.to_str())
+                                    .unwrap_or("")
+                                    .to_string();
+                                if item.is_dir {
+                                    text.push('/');
+                                }
+                                BreadcrumbTarget::menu_item(
+                                    text,
+                                    BreadcrumbTarget {
+                                        path: item.path_buf,
+                                        position: None,
+                                    },
+                                )
+                            })
+                            .collect::<Vec<MenuItem>>();
+                        if !menu_items.is_empty() {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::ShowMenu(
+                                    point,
+                                    Arc::new(menu_items),
+                                ),
+                                Target::Widget(widget_id),
+                            );
+                        }
+                    }),
+                );
+            }
+            Segment::Symbol(chain) => {
+                let path = match &data
+                    .main_split
+                    .editors
+                    .get(&self.view_id)
+                    .unwrap()
+                    .content
+                {
+                    BufferContent::File(path) => path.clone(),
+                    BufferContent::Local(_) => return,
+                };
+                let symbols = match self.symbols(data) {
+                    Some(symbols) => symbols,
+                    None => return,
+                };
+                let level = match symbol_level(&symbols, chain) {
+                    Some((level, _)) => level,
+                    None => return,
+                };
+                let menu_items = level
+                    .iter()
+                    .map(|symbol| {
+                        BreadcrumbTarget::menu_item(
+                            symbol.name.clone(),
+                            BreadcrumbTarget {
+                                path: path.clone(),
+                                position: Some(symbol.position),
+                            },
+                        )
+                    })
+                    .collect();
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowMenu(point, Arc::new(menu_items)),
+                    Target::Auto,
+                ));
+            }
+        }
+    }
+}
+
+impl Widget<LapceTabData> for LapceEditorBreadcrumbs {
+    fn id(&self) -> Option<WidgetId> {
+        Some(self.widget_id)
+    }
+
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        match event {
+            Event::Timer(token) if *token == self.timer => {
+                self.timer = TimerToken::INVALID;
+                if self.is_shown(data) {
+                    self.refresh(ctx, data);
+                }
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
+                // the menus of the folders come from the proxy, and are
+                // shown from here
+                if let LapceUICommand::ShowMenu(point, items) =
+                    cmd.get_unchecked(LAPCE_UI_COMMAND)
+                {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowMenu(*point, items.clone()),
+                        Target::Auto,
+                    ));
+                    ctx.set_handled();
+                }
+            }
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = mouse_event.pos;
+                if self
+                    .segments
+                    .iter()
+                    .any(|(_, rect)| rect.contains(mouse_event.pos))
+                {
+                    ctx.set_cursor(&druid::Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse_event) => {
+                if let Some(i) = self
+                    .segments
+                    .iter()
+                    .position(|(_, rect)| rect.contains(mouse_event.pos))
+                {
+                    self.open_menu(ctx, data, i);
+                    ctx.set_handled();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        match event {
+            LifeCycle::WidgetAdded => {
+                self.timer = ctx.request_timer(Duration::from_millis(0));
+            }
+            LifeCycle::HotChanged(false) => {
+                self.mouse_pos = Point::new(-1.0, -1.0);
+                ctx.request_paint();
+            }
+            _ => {}
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if old_data.config.editor.show_breadcrumbs
+            != data.config.editor.show_breadcrumbs
+        {
+            ctx.request_layout();
+        }
+        if !self.is_shown(data) {
+            return;
+        }
+        let old_editor = old_data.main_split.editors.get(&self.view_id).unwrap();
+        let editor = data.main_split.editors.get(&self.view_id).unwrap();
+        let old_buffer = old_data.main_split.editor_buffer(self.view_id);
+        let buffer = data.main_split.editor_buffer(self.view_id);
+        let changed = old_editor.content != editor.content
+            || old_editor.cursor != editor.cursor
+            || old_buffer.rev != buffer.rev
+            || old_buffer.document_symbols.as_ref().map(|(rev, _)| *rev)
+                != buffer.document_symbols.as_ref().map(|(rev, _)| *rev)
+            || old_data.workspace.path != data.workspace.path;
+        if old_editor.content != editor.content {
+            ctx.request_layout();
+        }
+        if changed && self.timer == TimerToken::INVALID {
+            self.timer = ctx.request_timer(REFRESH_DELAY);
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        if self.is_shown(data) {
+            Size::new(bc.max().width, HEIGHT)
+        } else {
+            Size::new(bc.max().width, 0.0)
+        }
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        if !self.is_shown(data) {
+            return;
+        }
+        let size = ctx.size();
+        ctx.fill(
+            size.to_rect(),
+            data.config
+                .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
+        );
+        let symbols = self.symbols(data);
+        let mut x = 10.0;
+        let texts: Vec<String> = self
+            .segments
+            .iter()
+            .map(|(segment, _)| self.segment_text(segment, data))
+            .collect();
+        for (i, text) in texts.into_iter().enumerate() {
+            if i > 0 {
+                let separator = ctx
+                    .text()
+                    .new_text_layout("\u{203a}")
+                    .font(FontFamily::SYSTEM_UI, 12.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                let height = separator.size().height;
+                ctx.draw_text(&separator, Point::new(x, (HEIGHT - height) / 2.0));
+                x += separator.size().width + 6.0;
+            }
+
+            let start = x;
+            if let Segment::Symbol(chain) = &self.segments[i].0 {
+                let svg = symbols
+                    .as_ref()
+                    .and_then(|symbols| symbol_level(symbols, chain))
+                    .and_then(|(_, symbol)| symbol_svg_new(&symbol.kind));
+                if let Some(svg) = svg {
+                    let icon_size = 12.0;
+                    let rect = Size::new(icon_size, icon_size)
+                        .to_rect()
+                        .with_origin(Point::new(x, (HEIGHT - icon_size) / 2.0));
+                    ctx.draw_svg(
+                        &svg,
+                        rect,
+                        Some(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+                        ),
+                    );
+                    x += icon_size + 4.0;
+                }
+            }
+
+            let text_layout = ctx
+                .text()
+                .new_text_layout(text)
+                .font(FontFamily::SYSTEM_UI, 12.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            let rect =
+                Rect::new(start - 3.0, 0.0, x + text_size.width + 3.0, HEIGHT);
+            self.segments[i].1 = rect;
+            if ctx.is_hot() && rect.contains(self.mouse_pos) {
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+                );
+            }
+            ctx.draw_text(
+                &text_layout,
+                Point::new(x, (HEIGHT - text_size.height) / 2.0),
+            );
+            x += text_size.width + 6.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{DocumentSymbol, Location, SymbolInformation, Url};
+
+    use super::*;
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(start, 0), Position::new(end, 0))
+    }
+
+    #[allow(deprecated)]
+    fn flat(name: &str, start: u32, end: u32) -> SymbolInformation {
+        SymbolInformation {
+            name: name.to_string(),
+            kind: SymbolKind::Function,
+            tags: None,
+            deprecated: None,
+            location: Location::new(
+                Url::parse("file:///a.rs").unwrap(),
+                range(start, end),
+            ),
+            container_name: None,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn nested(
+        name: &str,
+        start: u32,
+        end: u32,
+        children: Vec<DocumentSymbol>,
+    ) -> DocumentSymbol {
+        DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind: SymbolKind::Function,
+            tags: None,
+            deprecated: None,
+            range: range(start, end),
+            selection_range: range(start, start),
+            children: Some(children),
+        }
+    }
+
+    #[test]
+    fn test_symbol_chain() {
+        let symbols = symbol_tree(DocumentSymbolResponse::Nested(vec![
+            nested("a", 0, 5, vec![]),
+            nested(
+                "B",
+                6,
+                20,
+                vec![nested("b1", 7, 9, vec![]), nested("b2", 10, 19, vec![])],
+            ),
+        ]));
+        assert_eq!(symbol_chain(&symbols, Position::new(12, 3)), vec![1, 1]);
+        assert_eq!(symbol_chain(&symbols, Position::new(6, 0)), vec![1]);
+        assert!(symbol_chain(&symbols, Position::new(30, 0)).is_empty());
+        let (level, symbol) = symbol_level(&symbols, &[1, 1]).unwrap();
+        assert_eq!(level.len(), 2);
+        assert_eq!(symbol.name, "b2");
+        assert!(symbol_level(&symbols, &[]).is_none());
+    }
+
+    #[test]
+    fn test_flat_symbols_nest() {
+        let symbols = symbol_tree(DocumentSymbolResponse::Flat(vec![
+            flat("b1", 7, 9),
+            flat("B", 6, 20),
+            flat("a", 0, 5),
+            flat("b2", 10, 19),
+        ]));
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "B"]);
+        assert_eq!(symbols[1].children.len(), 2);
+        assert_eq!(symbols[1].children[1].name, "b2");
+    }
+
+    #[test]
+    fn test_path_segments() {
+        let workspace = Path::new("/w");
+        assert_eq!(
+            path_segments(Path::new("/w/src/main.rs"), Some(workspace)),
+            vec![PathBuf::from("/w/src"), PathBuf::from("/w/src/main.rs")]
+        );
+        assert_eq!(
+            path_segments(Path::new("/tmp/a.rs"), Some(workspace)),
+            vec![PathBuf::from("/tmp/a.rs")]
+        );
+        assert_eq!(
+            path_segments(Path::new("/tmp/a.rs"), None),
+            vec![PathBuf::from("/tmp/a.rs")]
+        );
+    }
+}
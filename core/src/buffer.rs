@@ -9,7 +9,9 @@ use druid::{
 };
 use druid::{Env, PaintCtx, Point};
 use language::{new_highlight_config, LapceLanguage};
+use lapce_proxy::buffer::SaveConflict;
 use lapce_proxy::dispatch::{BufferHeadResponse, NewBufferResponse};
+use lapce_proxy::editorconfig;
 use lsp_types::SemanticTokensServerCapabilities;
 use lsp_types::{CallHierarchyOptions, SemanticTokensLegend};
 use lsp_types::{CodeActionResponse, Position};
@@ -47,16 +49,26 @@ use xi_rope::{
 };
 use xi_unicode::EmojiExt;
 
+use crate::breadcrumbs::DocumentSymbolNode;
 use crate::config::{Config, LapceTheme};
 use crate::editor::EditorLocationNew;
 use crate::find::FindProgress;
+use crate::gutter::{diff_hunks, DiffHunk};
 use crate::language::SCOPES;
+use crate::linked_tag;
+use crate::sanitize::visible_line;
+use crate::scratch::is_scratch;
+use crate::todo::{filter_comments, find_todo_tokens};
+use crate::undo_history::{UndoHistory, UndoStep, MAX_STEPS};
+use crate::word_index::WordIndex;
 use crate::{
     command::LapceUICommand,
     command::LAPCE_UI_COMMAND,
     find::Find,
     language,
-    movement::{ColPosition, LinePosition, Movement, SelRegion, Selection},
+    movement::{
+        ColPosition, InsertDrift, LinePosition, Movement, SelRegion, Selection,
+    },
     proxy::LapceProxy,
     state::{Counter, Mode},
 };
@@ -134,6 +146,64 @@ pub struct Style {
 pub enum UpdateEvent {
     Buffer(BufferUpdate),
     SemanticTokens(BufferUpdate, Vec<(usize, usize, String)>),
+    /// The text of a file with unsaved changes, to back up for a crash.
+    Backup(PathBuf, Rope),
+    /// The changes of the file were saved, or dropped.
+    RemoveBackup(PathBuf),
+    /// The scratch was edited, to be saved once the edits pause.
+    SaveScratch(PathBuf),
+    /// The text changed, to be diffed with a version of the file once the
+    /// edits pause.
+    HistoryDiff(HistoryDiff),
+}
+
+pub struct HistoryDiff {
+    pub id: BufferId,
+    pub path: PathBuf,
+    pub history: String,
+    pub rev: u64,
+    atomic_rev: Arc<AtomicU64>,
+    left_rope: Rope,
+    right_rope: Rope,
+}
+
+impl HistoryDiff {
+    /// Diffs the text on the thread pool, unless it changed again.
+    pub fn spawn(self, event_sink: ExtEventSink, tab_id: WidgetId) {
+        rayon::spawn(move || {
+            let HistoryDiff {
+                id,
+                path,
+                history,
+                rev,
+                atomic_rev,
+                left_rope,
+                right_rope,
+            } = self;
+            if atomic_rev.load(atomic::Ordering::Acquire) != rev {
+                return;
+            }
+            let changes =
+                match rope_diff(left_rope, right_rope, rev, atomic_rev.clone()) {
+                    Some(changes) => changes,
+                    None => return,
+                };
+            if atomic_rev.load(atomic::Ordering::Acquire) != rev {
+                return;
+            }
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::UpdateHisotryChanges {
+                    id,
+                    path,
+                    rev,
+                    history,
+                    changes: Arc::new(changes),
+                },
+                Target::Widget(tab_id),
+            );
+        });
+    }
 }
 
 pub struct BufferUpdate {
@@ -196,8 +266,15 @@ struct Revision {
 #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub enum LocalBufferKind {
     Search,
+    SearchInclude,
+    /// The replacement of the matches of the workspace search.
+    SearchReplace,
     SourceControl,
     Empty,
+    Log,
+    /// The region of a file a narrowed editor shows, by the id of the
+    /// editor.
+    Narrow(u64),
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -223,9 +300,20 @@ pub struct BufferNew {
     pub rev: u64,
     pub atomic_rev: Arc<AtomicU64>,
     pub dirty: bool,
+    /// Why the last save was refused, until it's settled. The text on disk
+    /// is then the `disk` history.
+    pub save_conflict: Option<SaveConflict>,
+    /// Whether the file was deleted while it was open. The text stays, and
+    /// saving writes the file again.
+    pub deleted: bool,
+    /// Whether the file changed on disk while it had no unsaved changes,
+    /// until it's reloaded or the change is ignored. The text on disk is
+    /// then the `disk` history.
+    pub changed_on_disk: bool,
     pub loaded: bool,
     pub start_to_load: Rc<RefCell<bool>>,
     pub local: bool,
+    pub scratch: bool,
     update_sender: Arc<Sender<UpdateEvent>>,
     pub histories: im::HashMap<String, Rope>,
     pub history_styles: im::HashMap<String, Arc<Spans<Style>>>,
@@ -236,6 +324,9 @@ pub struct BufferNew {
 
     pub find: Rc<RefCell<Find>>,
     pub find_progress: Rc<RefCell<FindProgress>>,
+    /// Bumped when the find or the text changes, which tells the worker
+    /// searching the whole text that its search is outdated.
+    find_generation: Arc<AtomicU64>,
 
     revs: Vec<Revision>,
     cur_undo: usize,
@@ -248,12 +339,22 @@ pub struct BufferNew {
 
     this_edit_type: EditType,
     last_edit_type: EditType,
+    /// Whether the undo history of earlier sessions was looked up. It's only
+    /// done once the undo steps of this session run out.
+    pub undo_history_restored: bool,
 
     pub cursor_offset: usize,
     pub scroll_offset: Vec2,
 
     pub code_actions: im::HashMap<usize, CodeActionResponse>,
     pub syntax_tree: Option<Arc<Tree>>,
+    /// The `.editorconfig` properties of the file, which win over the
+    /// settings unless `editor.prefer-editorconfig` is off.
+    pub editorconfig: editorconfig::Properties,
+    /// The words of the buffer, to complete without a language server.
+    pub word_index: WordIndex,
+    /// The symbols of the language server, with the revision they're of.
+    pub document_symbols: Option<(u64, Arc<Vec<DocumentSymbolNode>>)>,
 
     tab_id: WidgetId,
     event_sink: ExtEventSink,
@@ -271,6 +372,10 @@ impl BufferNew {
             BufferContent::File(path) => LapceLanguage::from_path(path),
             BufferContent::Local(_) => None,
         };
+        let scratch = match &content {
+            BufferContent::File(path) => is_scratch(path),
+            BufferContent::Local(_) => false,
+        };
         let buffer = Self {
             id: BufferId::next(),
             rope,
@@ -283,6 +388,7 @@ impl BufferNew {
             line_styles: Rc::new(RefCell::new(Vec::new())),
             find: Rc::new(RefCell::new(Find::new(0))),
             find_progress: Rc::new(RefCell::new(FindProgress::Ready)),
+            find_generation: Arc::new(AtomicU64::new(0)),
             semantic_tokens: false,
             max_len: 0,
             max_len_line: 0,
@@ -292,8 +398,12 @@ impl BufferNew {
             start_to_load: Rc::new(RefCell::new(false)),
             loaded: false,
             dirty: false,
+            save_conflict: None,
+            changed_on_disk: false,
+            deleted: false,
             update_sender,
             local: false,
+            scratch,
             histories: im::HashMap::new(),
             history_styles: im::HashMap::new(),
             history_line_styles: Rc::new(RefCell::new(HashMap::new())),
@@ -316,12 +426,16 @@ impl BufferNew {
 
             last_edit_type: EditType::Other,
             this_edit_type: EditType::Other,
+            undo_history_restored: false,
 
             cursor_offset: 0,
             scroll_offset: Vec2::ZERO,
 
             code_actions: im::HashMap::new(),
             syntax_tree: None,
+            editorconfig: editorconfig::Properties::default(),
+            word_index: WordIndex::default(),
+            document_symbols: None,
             tab_id,
             event_sink,
         };
@@ -358,6 +472,39 @@ impl BufferNew {
         self.retrieve_history_styles(version, content);
     }
 
+    /// Keeps the text on disk a save was refused for, to compare with.
+    pub fn set_save_conflict(&mut self, conflict: SaveConflict, disk: Option<&str>) {
+        self.save_conflict = Some(conflict);
+        if let Some(disk) = disk {
+            self.load_history("disk", Rope::from(disk));
+        }
+    }
+
+    /// Keeps the text the file has on disk now, to offer to reload it.
+    pub fn set_changed_on_disk(&mut self, disk: &str) {
+        self.changed_on_disk = true;
+        self.load_history("disk", Rope::from(disk));
+    }
+
+    pub fn clear_save_conflict(&mut self) {
+        self.save_conflict = None;
+        self.changed_on_disk = false;
+        self.histories.remove("disk");
+        self.history_changes.remove("disk");
+    }
+
+    /// One level of indentation in the file.
+    pub fn indent_unit(&self) -> String {
+        self.editorconfig
+            .indent_unit()
+            .unwrap_or_else(|| "    ".to_string())
+    }
+
+    /// What a new line is made of in the file.
+    pub fn line_ending(&self) -> &'static str {
+        self.editorconfig.line_ending()
+    }
+
     pub fn load_content(&mut self, content: &str) {
         self.reset_revs();
 
@@ -373,6 +520,7 @@ impl BufferNew {
         }
 
         self.code_actions.clear();
+        self.word_index = WordIndex::new(&self.rope);
         let (max_len, max_len_line) = self.get_max_line_len();
         self.max_len = max_len;
         self.max_len_line = max_len_line;
@@ -416,44 +564,32 @@ impl BufferNew {
 
     fn trigger_history_change(&self) {
         if let BufferContent::File(path) = &self.content {
-            if let Some(head) = self.histories.get("head") {
-                let id = self.id;
-                let rev = self.rev;
-                let atomic_rev = self.atomic_rev.clone();
-                let path = path.clone();
-                let left_rope = head.clone();
-                let right_rope = self.rope.clone();
-                let event_sink = self.event_sink.clone();
-                let tab_id = self.tab_id;
-                rayon::spawn(move || {
-                    if atomic_rev.load(atomic::Ordering::Acquire) != rev {
-                        return;
-                    }
-                    let changes =
-                        rope_diff(left_rope, right_rope, rev, atomic_rev.clone());
-                    if changes.is_none() {
-                        return;
-                    }
-                    let changes = changes.unwrap();
-                    if atomic_rev.load(atomic::Ordering::Acquire) != rev {
-                        return;
-                    }
-                    event_sink.submit_command(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::UpdateHisotryChanges {
-                            id: id,
-                            path,
-                            rev,
-                            history: "head".to_string(),
-                            changes: Arc::new(changes),
-                        },
-                        Target::Widget(tab_id),
-                    );
-                });
+            for (history, content) in self.histories.iter() {
+                let _ =
+                    self.update_sender
+                        .send(UpdateEvent::HistoryDiff(HistoryDiff {
+                            id: self.id,
+                            path: path.clone(),
+                            history: history.to_string(),
+                            rev: self.rev,
+                            atomic_rev: self.atomic_rev.clone(),
+                            left_rope: content.clone(),
+                            right_rope: self.rope.clone(),
+                        }));
             }
         }
     }
 
+    /// Drops the backup of the unsaved changes, once they're saved or
+    /// dropped.
+    pub fn clear_backup(&self) {
+        if let BufferContent::File(path) = &self.content {
+            let _ = self
+                .update_sender
+                .send(UpdateEvent::RemoveBackup(path.clone()));
+        }
+    }
+
     pub fn notify_update(&self) {
         if let Some(language) = self.language {
             if let BufferContent::File(path) = &self.content {
@@ -538,6 +674,7 @@ impl BufferNew {
                                     LapceUICommand::LoadBuffer {
                                         path,
                                         content: resp.content,
+                                        editorconfig: resp.editorconfig,
                                         locations,
                                     },
                                     Target::Widget(tab_id),
@@ -553,25 +690,86 @@ impl BufferNew {
     }
 
     pub fn reset_find(&self, current_find: &Find) {
+        if self.find.borrow().same_query(current_find) {
+            return;
+        }
+
         {
-            let find = self.find.borrow();
-            if find.search_string == current_find.search_string
-                && find.case_matching == current_find.case_matching
-                && find.regex.as_ref().map(|r| r.as_str())
-                    == current_find.regex.as_ref().map(|r| r.as_str())
-                && find.whole_words == current_find.whole_words
-            {
-                return;
+            let mut find = self.find.borrow_mut();
+            // a query typed further keeps what was searched for the previous
+            // one, so that a keystroke doesn't start over on a large file
+            let refines = current_find.refines(&find);
+            if !refines {
+                find.unset();
+                *self.find_progress.borrow_mut() = FindProgress::Started;
+            }
+            find.search_string = current_find.search_string.clone();
+            find.case_matching = current_find.case_matching.clone();
+            find.regex = current_find.regex.clone();
+            find.whole_words = current_find.whole_words;
+            if refines {
+                find.refine_occurrences(&self.rope);
+            }
+        }
+        self.find_all();
+    }
+
+    /// Searches the whole text on a worker, while `update_find` searches the
+    /// visible lines right away. The matches are counted once it's done.
+    fn find_all(&self) {
+        let generation =
+            self.find_generation.fetch_add(1, atomic::Ordering::AcqRel) + 1;
+        if *self.find_progress.borrow() == FindProgress::Ready {
+            return;
+        }
+        let path = match &self.content {
+            BufferContent::File(path) => path.clone(),
+            BufferContent::Local(_) => return,
+        };
+        let mut find = self.find.borrow().clone();
+        if find.search_string.is_none() {
+            return;
+        }
+        let text = self.rope.clone();
+        let latest = self.find_generation.clone();
+        let event_sink = self.event_sink.clone();
+        let tab_id = self.tab_id;
+        thread::spawn(move || {
+            let done = find.find_all(&text, || {
+                latest.load(atomic::Ordering::Acquire) != generation
+            });
+            if done {
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::FindAllDone(
+                        path,
+                        generation,
+                        Arc::new(find.occurrences().clone()),
+                    ),
+                    Target::Widget(tab_id),
+                );
             }
+        });
+    }
+
+    /// Takes the matches the worker found in the whole text, unless the find
+    /// or the text changed since it started.
+    pub fn find_all_done(&self, generation: u64, occurrences: &Selection) {
+        if self.find_generation.load(atomic::Ordering::Acquire) != generation {
+            return;
         }
+        self.find.borrow_mut().set_occurrences(occurrences.clone());
+        *self.find_progress.borrow_mut() = FindProgress::Ready;
+    }
 
-        let mut find = self.find.borrow_mut();
-        find.unset();
-        find.search_string = current_find.search_string.clone();
-        find.case_matching = current_find.case_matching.clone();
-        find.regex = current_find.regex.clone();
-        find.whole_words = current_find.whole_words;
-        *self.find_progress.borrow_mut() = FindProgress::Started;
+    /// How many matches the find has in the text, and whether all of the
+    /// text was searched.
+    pub fn find_count(&self, current_find: &Find) -> (usize, bool) {
+        self.reset_find(current_find);
+        (
+            self.find.borrow().occurrences().len(),
+            *self.find_progress.borrow() == FindProgress::Ready,
+        )
     }
 
     pub fn update_find(
@@ -803,30 +1001,8 @@ impl BufferNew {
         }
         let start_offset = self.offset_of_line(line);
         let end_offset = self.offset_of_line(line + 1);
-        let line_styles: Vec<(usize, usize, Style)> = self
-            .styles
-            .iter_chunks(start_offset..end_offset)
-            .filter_map(|(iv, style)| {
-                let start = iv.start();
-                let end = iv.end();
-                if start > end_offset {
-                    None
-                } else if end < start_offset {
-                    None
-                } else {
-                    Some((
-                        if start > start_offset {
-                            start - start_offset
-                        } else {
-                            0
-                        },
-                        end - start_offset,
-                        style.clone(),
-                    ))
-                }
-            })
-            .collect();
-        let line_styles = Arc::new(line_styles);
+        let line_styles =
+            Arc::new(line_styles(&self.styles, start_offset, end_offset));
         self.line_styles.borrow_mut()[line] = Some(line_styles.clone());
         line_styles
     }
@@ -882,9 +1058,10 @@ impl BufferNew {
         config: &Config,
     ) -> PietTextLayout {
         let styles = self.get_line_styles(line);
+        let (visible_content, invisible) = visible_line(line_content);
         let mut layout_builder = ctx
             .text()
-            .new_text_layout(line_content.to_string())
+            .new_text_layout(visible_content.to_string())
             .font(config.editor.font_family(), config.editor.font_size as f64)
             .text_color(
                 config
@@ -915,9 +1092,55 @@ impl BufferNew {
                 }
             }
         }
+
+        if let BufferContent::File(_) = &self.content {
+            let todo_color = config.get_color_unchecked(LapceTheme::EDITOR_TODO);
+            for (start, end, _) in self.todo_tokens_on_line(
+                line,
+                line_content,
+                &config.editor.todo_tokens,
+            ) {
+                layout_builder = layout_builder
+                    .range_attribute(
+                        start..end,
+                        TextAttribute::TextColor(todo_color.clone()),
+                    )
+                    .range_attribute(
+                        start..end,
+                        TextAttribute::Weight(FontWeight::BOLD),
+                    );
+            }
+        }
+
+        let invisible_color =
+            config.get_color_unchecked(LapceTheme::EDITOR_INVISIBLE);
+        for (start, end) in invisible {
+            layout_builder = layout_builder.range_attribute(
+                start..end,
+                TextAttribute::TextColor(invisible_color.clone()),
+            );
+        }
         layout_builder.build_with_bounds(bounds)
     }
 
+    /// The todo tokens on the line. When the buffer has syntax highlighting,
+    /// only the ones inside comments are returned.
+    pub fn todo_tokens_on_line(
+        &self,
+        line: usize,
+        line_content: &str,
+        tokens: &[String],
+    ) -> Vec<(usize, usize, String)> {
+        let found = find_todo_tokens(line_content, tokens);
+        if found.is_empty()
+            || self.language.is_none()
+            || self.styles.iter().next().is_none()
+        {
+            return found;
+        }
+        filter_comments(found, &self.get_line_styles(line))
+    }
+
     pub fn indent_on_line(&self, line: usize) -> String {
         let line_start_offset = self.rope.offset_of_line(line);
         let word_boundary =
@@ -978,6 +1201,8 @@ impl BufferNew {
     }
 
     pub fn offset_of_line_col(&self, line: usize, col: usize) -> usize {
+        // a line past the end, like from `main.rs:9999`, is the last one
+        let line = line.min(self.last_line());
         let tab_width = 8;
         let mut pos = 0;
         let mut offset = self.offset_of_line(line);
@@ -1611,6 +1836,23 @@ impl BufferNew {
         None
     }
 
+    /// The names of the opening and the closing tag of the element whose
+    /// tag name has the offset, from the syntax tree of JSX or the text of
+    /// markup.
+    pub fn tag_names(&self, offset: usize) -> Option<[(usize, usize); 2]> {
+        if self.language == Some(LapceLanguage::Javascript) {
+            return linked_tag::jsx_tag_names(self.syntax_tree.as_ref()?, offset);
+        }
+        let extension = match &self.content {
+            BufferContent::File(path) => path.extension()?.to_str()?.to_lowercase(),
+            BufferContent::Local(_) => return None,
+        };
+        if !linked_tag::MARKUP_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+        linked_tag::markup_tag_names(&self.rope.to_string(), offset)
+    }
+
     pub fn prev_code_boundary(&self, offset: usize) -> usize {
         WordCursor::new(&self.rope, offset).prev_code_boundary()
     }
@@ -1638,6 +1880,26 @@ impl BufferNew {
         self.history_changes.insert(history.to_string(), changes);
     }
 
+    /// The changes of the buffer from the head of the repository.
+    pub fn diff_hunks(&self) -> Vec<DiffHunk> {
+        self.history_changes
+            .get("head")
+            .map(|changes| diff_hunks(changes))
+            .unwrap_or_default()
+    }
+
+    /// The lines of the head the hunk replaces, if it's still one of the
+    /// changes of the buffer.
+    pub fn diff_hunk_old_text(&self, hunk: &DiffHunk) -> Option<String> {
+        if !self.diff_hunks().contains(hunk) {
+            return None;
+        }
+        let head = self.histories.get("head")?;
+        let start = head.offset_of_line(hunk.old_lines.start);
+        let end = head.offset_of_line(hunk.old_lines.end);
+        Some(head.slice_to_cow(start..end).to_string())
+    }
+
     pub fn update_styles(
         &mut self,
         rev: u64,
@@ -1780,7 +2042,15 @@ impl BufferNew {
         }
         self.rev += 1;
         self.atomic_rev.store(self.rev, atomic::Ordering::Release);
-        self.dirty = true;
+        self.dirty = !self.scratch;
+        if let BufferContent::File(path) = &self.content {
+            let event = if self.scratch {
+                UpdateEvent::SaveScratch(path.clone())
+            } else {
+                UpdateEvent::Backup(path.clone(), new_text.clone())
+            };
+            let _ = self.update_sender.send(event);
+        }
 
         let (iv, newlen) = delta.summary();
         let old_logical_end_line = self.rope.line_of_offset(iv.end) + 1;
@@ -1789,6 +2059,7 @@ impl BufferNew {
             proxy.update(self.id, &delta, self.rev);
         }
 
+        let old_rope = self.rope.clone();
         self.revs.push(new_rev);
         self.rope = new_text.clone();
         self.tombstones = new_tombstones;
@@ -1798,6 +2069,12 @@ impl BufferNew {
 
         let logical_start_line = self.rope.line_of_offset(iv.start);
         let new_logical_end_line = self.rope.line_of_offset(iv.start + newlen) + 1;
+        self.word_index.update(
+            &old_rope,
+            logical_start_line..old_logical_end_line,
+            &self.rope,
+            logical_start_line..new_logical_end_line,
+        );
         let old_hard_count = old_logical_end_line - logical_start_line;
         let new_hard_count = new_logical_end_line - logical_start_line;
 
@@ -1808,8 +2085,15 @@ impl BufferNew {
         };
         self.update_size(&inval_lines);
         self.update_line_styles(&delta, &inval_lines);
-        self.find.borrow_mut().unset();
-        *self.find_progress.borrow_mut() = FindProgress::Started;
+        // only the matches around the edit are searched again
+        self.find.borrow_mut().update_highlights(&self.rope, &delta);
+        let progress = self.find_progress.borrow().clone();
+        if let FindProgress::InProgress(searched) = progress {
+            *self.find_progress.borrow_mut() = FindProgress::InProgress(
+                searched.apply_delta(&delta, false, InsertDrift::Default),
+            );
+        }
+        self.find_all();
         self.notify_update();
     }
 
@@ -1817,6 +2101,10 @@ impl BufferNew {
         self.last_edit_type = EditType::Other;
     }
 
+    pub fn last_edit_type(&self) -> EditType {
+        self.last_edit_type
+    }
+
     pub fn edit_multiple(
         &mut self,
         ctx: &mut EventCtx,
@@ -1875,6 +2163,79 @@ impl BufferNew {
         self.edit_multiple(ctx, vec![(selection, content)], proxy, edit_type)
     }
 
+    pub fn can_undo(&self) -> bool {
+        self.cur_undo > 1
+    }
+
+    /// The undo groups of the buffer, for `UndoSnapshot::undo_history` to
+    /// walk back through away from the UI thread.
+    pub fn undo_snapshot(&self) -> UndoSnapshot {
+        UndoSnapshot {
+            rope: self.rope.clone(),
+            tombstones: self.tombstones.clone(),
+            deletes_from_union: self.deletes_from_union.clone(),
+            undone_groups: self.undone_groups.clone(),
+            revs: self.revs.clone(),
+            undos: self.undos.clone(),
+            live_undos: self.live_undos.clone(),
+            cur_undo: self.cur_undo,
+            restored: self.undo_history_restored,
+        }
+    }
+
+    /// Rebuilds the undo groups of an earlier session under the current text,
+    /// which has to be the one the history was saved for. The text doesn't
+    /// change, but the redo steps of this session are dropped.
+    pub fn restore_undo_history(&mut self, history: &UndoHistory) -> bool {
+        if history.steps.is_empty() || !history.matches(&self.rope) {
+            return false;
+        }
+        let mut text = self.rope.to_string();
+        let mut redos = Vec::new();
+        for step in history.steps.iter() {
+            let older = match step.apply(&text) {
+                Some(older) => older,
+                None => return false,
+            };
+            redos.push(step.inverse(&text));
+            text = older;
+        }
+
+        let syntax_tree = self.syntax_tree.clone();
+        self.reset_revs();
+        self.undos = BTreeSet::new();
+        self.push_rev(
+            0,
+            Delta::simple_edit(Interval::new(0, 0), Rope::from(text), 0),
+        );
+        for redo in redos.iter().rev() {
+            let undo_group = self.undo_group_id;
+            self.live_undos.push(undo_group);
+            self.cur_undo += 1;
+            self.undo_group_id += 1;
+            let delta = Delta::simple_edit(
+                Interval::new(redo.start, redo.end),
+                Rope::from(redo.text.as_str()),
+                self.rope.len(),
+            );
+            self.push_rev(undo_group, delta);
+        }
+        self.syntax_tree = syntax_tree;
+        self.last_edit_type = EditType::Other;
+        true
+    }
+
+    /// Adds a revision without telling anyone about the edit, for rebuilding
+    /// the history.
+    fn push_rev(&mut self, undo_group: usize, delta: RopeDelta) {
+        let (new_rev, new_text, new_tombstones, new_deletes_from_union) =
+            self.mk_new_rev(undo_group, delta);
+        self.revs.push(new_rev);
+        self.rope = new_text;
+        self.tombstones = new_tombstones;
+        self.deletes_from_union = new_deletes_from_union;
+    }
+
     pub fn do_undo(&mut self, proxy: Arc<LapceProxy>) -> Option<RopeDelta> {
         if self.cur_undo > 1 {
             self.cur_undo -= 1;
@@ -1927,119 +2288,242 @@ impl BufferNew {
         delta
     }
 
-    fn deletes_from_union_before_index(
+    fn compute_undo(&self, groups: &BTreeSet<usize>) -> (Revision, Subset) {
+        compute_undo(
+            &self.revs,
+            &self.deletes_from_union,
+            &self.undone_groups,
+            groups,
+        )
+    }
+}
+
+/// The undo groups of a buffer, taken off it to walk back through them.
+pub struct UndoSnapshot {
+    rope: Rope,
+    tombstones: Rope,
+    deletes_from_union: Subset,
+    undone_groups: BTreeSet<usize>,
+    revs: Vec<Revision>,
+    undos: BTreeSet<usize>,
+    live_undos: Vec<usize>,
+    cur_undo: usize,
+    /// Whether the undo groups already have the steps of earlier sessions.
+    pub restored: bool,
+}
+
+impl UndoSnapshot {
+    /// The steps back through the undo groups before the current text, the
+    /// latest first, to keep them across sessions. The steps of `earlier`
+    /// follow, if it was saved for the text the groups go back to. The walk
+    /// stops once the steps don't fit in `max_size` bytes.
+    pub fn undo_history(
         &self,
-        rev_index: usize,
-        invert_undos: bool,
-    ) -> Cow<Subset> {
-        let mut deletes_from_union = Cow::Borrowed(&self.deletes_from_union);
-        let mut undone_groups = Cow::Borrowed(&self.undone_groups);
-
-        // invert the changes to deletes_from_union starting in the present and working backwards
-        for rev in self.revs[rev_index..].iter().rev() {
-            deletes_from_union = match rev.edit {
-                Contents::Edit {
-                    ref inserts,
-                    ref deletes,
-                    ref undo_group,
-                    ..
-                } => {
-                    if undone_groups.contains(undo_group) {
-                        // no need to un-delete undone inserts since we'll just shrink them out
-                        Cow::Owned(deletes_from_union.transform_shrink(inserts))
+        earlier: Option<&UndoHistory>,
+        max_size: usize,
+    ) -> UndoHistory {
+        let mut steps = Vec::new();
+        let mut size = 0;
+        let mut undone = self.undos.clone();
+        let mut text = self.rope.clone();
+        let mut tombstones = self.tombstones.clone();
+        let mut deletes_from_union = self.deletes_from_union.clone();
+        for i in (1..self.cur_undo).rev() {
+            if steps.len() >= MAX_STEPS || size > max_size {
+                return UndoHistory::new(&self.rope, steps, max_size);
+            }
+            undone.insert(self.live_undos[i]);
+            let (_, older_deletes) = compute_undo(
+                &self.revs,
+                &self.deletes_from_union,
+                &self.undone_groups,
+                &undone,
+            );
+            let delta =
+                Delta::synthesize(&tombstones, &deletes_from_union, &older_deletes);
+            let older = delta.apply(&text);
+            tombstones = shuffle_tombstones(
+                &text,
+                &tombstones,
+                &deletes_from_union,
+                &older_deletes,
+            );
+            deletes_from_union = older_deletes;
+
+            let (iv, new_len) = delta.summary();
+            let step = UndoStep {
+                start: iv.start,
+                end: iv.end,
+                text: older
+                    .slice_to_cow(iv.start..iv.start + new_len)
+                    .into_owned(),
+            };
+            size += step.size();
+            steps.push(step);
+            text = older;
+        }
+        // the groups go back to the text this session started with
+        if let Some(earlier) = earlier.filter(|earlier| earlier.matches(&text)) {
+            steps.extend(earlier.steps.iter().cloned());
+        }
+        UndoHistory::new(&self.rope, steps, max_size)
+    }
+}
+
+/// The styles of the line between the offsets, relative to its start.
+pub fn line_styles(
+    styles: &Spans<Style>,
+    start_offset: usize,
+    end_offset: usize,
+) -> Vec<(usize, usize, Style)> {
+    styles
+        .iter_chunks(start_offset..end_offset)
+        .filter_map(|(iv, style)| {
+            let start = iv.start();
+            let end = iv.end();
+            if start > end_offset {
+                None
+            } else if end < start_offset {
+                None
+            } else {
+                Some((
+                    if start > start_offset {
+                        start - start_offset
                     } else {
-                        let un_deleted = deletes_from_union.subtract(deletes);
-                        Cow::Owned(un_deleted.transform_shrink(inserts))
-                    }
+                        0
+                    },
+                    end - start_offset,
+                    style.clone(),
+                ))
+            }
+        })
+        .collect()
+}
+
+fn deletes_from_union_before_index<'a>(
+    revs: &[Revision],
+    deletes_from_union: &'a Subset,
+    undone_groups: &'a BTreeSet<usize>,
+    rev_index: usize,
+    invert_undos: bool,
+) -> Cow<'a, Subset> {
+    let mut deletes_from_union = Cow::Borrowed(deletes_from_union);
+    let mut undone_groups = Cow::Borrowed(undone_groups);
+
+    // invert the changes to deletes_from_union starting in the present and working backwards
+    for rev in revs[rev_index..].iter().rev() {
+        deletes_from_union = match rev.edit {
+            Contents::Edit {
+                ref inserts,
+                ref deletes,
+                ref undo_group,
+                ..
+            } => {
+                if undone_groups.contains(undo_group) {
+                    // no need to un-delete undone inserts since we'll just shrink them out
+                    Cow::Owned(deletes_from_union.transform_shrink(inserts))
+                } else {
+                    let un_deleted = deletes_from_union.subtract(deletes);
+                    Cow::Owned(un_deleted.transform_shrink(inserts))
                 }
-                Contents::Undo {
-                    ref toggled_groups,
-                    ref deletes_bitxor,
-                } => {
-                    if invert_undos {
-                        let new_undone = undone_groups
-                            .symmetric_difference(toggled_groups)
-                            .cloned()
-                            .collect();
-                        undone_groups = Cow::Owned(new_undone);
-                        Cow::Owned(deletes_from_union.bitxor(deletes_bitxor))
-                    } else {
-                        deletes_from_union
-                    }
+            }
+            Contents::Undo {
+                ref toggled_groups,
+                ref deletes_bitxor,
+            } => {
+                if invert_undos {
+                    let new_undone = undone_groups
+                        .symmetric_difference(toggled_groups)
+                        .cloned()
+                        .collect();
+                    undone_groups = Cow::Owned(new_undone);
+                    Cow::Owned(deletes_from_union.bitxor(deletes_bitxor))
+                } else {
+                    deletes_from_union
                 }
             }
         }
-        deletes_from_union
     }
+    deletes_from_union
+}
 
-    fn find_first_undo_candidate_index(
-        &self,
-        toggled_groups: &BTreeSet<usize>,
-    ) -> usize {
-        // find the lowest toggled undo group number
-        if let Some(lowest_group) = toggled_groups.iter().cloned().next() {
-            for (i, rev) in self.revs.iter().enumerate().rev() {
-                if rev.max_undo_so_far < lowest_group {
-                    return i + 1; // +1 since we know the one we just found doesn't have it
-                }
+fn find_first_undo_candidate_index(
+    revs: &[Revision],
+    toggled_groups: &BTreeSet<usize>,
+) -> usize {
+    // find the lowest toggled undo group number
+    if let Some(lowest_group) = toggled_groups.iter().cloned().next() {
+        for (i, rev) in revs.iter().enumerate().rev() {
+            if rev.max_undo_so_far < lowest_group {
+                return i + 1; // +1 since we know the one we just found doesn't have it
             }
-            0
-        } else {
-            // no toggled groups, return past end
-            self.revs.len()
         }
+        0
+    } else {
+        // no toggled groups, return past end
+        revs.len()
     }
+}
 
-    fn compute_undo(&self, groups: &BTreeSet<usize>) -> (Revision, Subset) {
-        let toggled_groups = self
-            .undone_groups
-            .symmetric_difference(&groups)
-            .cloned()
-            .collect();
-        let first_candidate = self.find_first_undo_candidate_index(&toggled_groups);
-        // the `false` below: don't invert undos since our first_candidate is based on the current undo set, not past
-        let mut deletes_from_union = self
-            .deletes_from_union_before_index(first_candidate, false)
-            .into_owned();
-
-        for rev in &self.revs[first_candidate..] {
-            if let Contents::Edit {
-                ref undo_group,
-                ref inserts,
-                ref deletes,
-                ..
-            } = rev.edit
-            {
-                if groups.contains(undo_group) {
-                    if !inserts.is_empty() {
-                        deletes_from_union =
-                            deletes_from_union.transform_union(inserts);
-                    }
-                } else {
-                    if !inserts.is_empty() {
-                        deletes_from_union =
-                            deletes_from_union.transform_expand(inserts);
-                    }
-                    if !deletes.is_empty() {
-                        deletes_from_union = deletes_from_union.union(deletes);
-                    }
+/// The revision undoing `groups`, which become the undone groups instead of
+/// `undone_groups`, and the deletes from union after it.
+fn compute_undo(
+    revs: &[Revision],
+    current_deletes_from_union: &Subset,
+    undone_groups: &BTreeSet<usize>,
+    groups: &BTreeSet<usize>,
+) -> (Revision, Subset) {
+    let toggled_groups = undone_groups
+        .symmetric_difference(groups)
+        .cloned()
+        .collect();
+    let first_candidate = find_first_undo_candidate_index(revs, &toggled_groups);
+    // the `false` below: don't invert undos since our first_candidate is based on the current undo set, not past
+    let mut deletes_from_union = deletes_from_union_before_index(
+        revs,
+        current_deletes_from_union,
+        undone_groups,
+        first_candidate,
+        false,
+    )
+    .into_owned();
+
+    for rev in &revs[first_candidate..] {
+        if let Contents::Edit {
+            ref undo_group,
+            ref inserts,
+            ref deletes,
+            ..
+        } = rev.edit
+        {
+            if groups.contains(undo_group) {
+                if !inserts.is_empty() {
+                    deletes_from_union = deletes_from_union.transform_union(inserts);
+                }
+            } else {
+                if !inserts.is_empty() {
+                    deletes_from_union =
+                        deletes_from_union.transform_expand(inserts);
+                }
+                if !deletes.is_empty() {
+                    deletes_from_union = deletes_from_union.union(deletes);
                 }
             }
         }
+    }
 
-        let deletes_bitxor = self.deletes_from_union.bitxor(&deletes_from_union);
-        let max_undo_so_far = self.revs.last().unwrap().max_undo_so_far;
-        (
-            Revision {
-                max_undo_so_far,
-                edit: Contents::Undo {
-                    toggled_groups,
-                    deletes_bitxor,
-                },
+    let deletes_bitxor = current_deletes_from_union.bitxor(&deletes_from_union);
+    let max_undo_so_far = revs.last().unwrap().max_undo_so_far;
+    (
+        Revision {
+            max_undo_so_far,
+            edit: Contents::Undo {
+                toggled_groups,
+                deletes_bitxor,
             },
-            deletes_from_union,
-        )
-    }
+        },
+        deletes_from_union,
+    )
 }
 
 fn shuffle_tombstones(
@@ -2491,6 +2975,31 @@ pub fn matching_pair_direction(c: char) -> Option<bool> {
     })
 }
 
+/// The range of `old` to replace with the range of `new` to turn one into the
+/// other, leaving out the text both start and end with.
+pub fn changed_region(old: &str, new: &str) -> (Range<usize>, Range<usize>) {
+    let mut prefix = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old
+        .bytes()
+        .rev()
+        .zip(new.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) {
+        suffix -= 1;
+    }
+    (prefix..old.len() - suffix, prefix..new.len() - suffix)
+}
+
 pub fn matching_char(c: char) -> Option<char> {
     Some(match c {
         '{' => '}',
@@ -3116,3 +3625,78 @@ where
 //     }
 //     UnicodeWidthStr::width(s)
 // }
+
+#[cfg(test)]
+mod tests {
+    use druid::AppLauncher;
+
+    use super::*;
+
+    fn buffer(text: &str) -> BufferNew {
+        let mut buffer = BufferNew::new(
+            BufferContent::Local(LocalBufferKind::Empty),
+            Arc::new(unbounded().0),
+            WidgetId::next(),
+            AppLauncher::new().get_external_handle(),
+        );
+        buffer.load_content(text);
+        buffer
+    }
+
+    /// The history of a file that went from `older` to `newer` in one step.
+    fn history(newer: &str, older: &str) -> UndoHistory {
+        let step = UndoStep::between(newer, older);
+        UndoHistory::new(&Rope::from(newer), vec![step], usize::MAX)
+    }
+
+    #[test]
+    fn test_undo_snapshot_round_trip() {
+        let mut buffer = buffer("hello brave new world");
+        let mut saved = history("hello brave new world", "hello new world");
+        saved
+            .steps
+            .push(UndoStep::between("hello new world", "hello world"));
+        assert!(buffer.restore_undo_history(&saved));
+        buffer.undo_history_restored = true;
+
+        let kept = buffer.undo_snapshot().undo_history(None, usize::MAX);
+        assert!(kept.matches(&buffer.rope));
+        let mut text = buffer.rope.to_string();
+        for step in kept.steps.iter() {
+            text = step.apply(&text).unwrap();
+        }
+        assert_eq!(text, "hello world");
+    }
+
+    #[test]
+    fn test_undo_snapshot_keeps_earlier_history_after_edits() {
+        // this session went from "ab" to "abc", the last one from "a" to "ab"
+        let mut buffer = buffer("abc");
+        assert!(buffer.restore_undo_history(&history("abc", "ab")));
+        let earlier = history("ab", "a");
+
+        let kept = buffer.undo_snapshot().undo_history(Some(&earlier), 1024);
+        let mut text = "abc".to_string();
+        for step in kept.steps.iter() {
+            text = step.apply(&text).unwrap();
+        }
+        assert_eq!(kept.steps.len(), 2);
+        assert_eq!(text, "a");
+
+        // the history of a file changed outside of the editor is dropped
+        let other = history("xy", "x");
+        let kept = buffer.undo_snapshot().undo_history(Some(&other), 1024);
+        assert_eq!(kept.steps.len(), 1);
+    }
+
+    #[test]
+    fn test_undo_snapshot_stops_at_max_size() {
+        let mut buffer = buffer("abc");
+        let mut saved = history("abc", "ab");
+        saved.steps.push(UndoStep::between("ab", "a"));
+        assert!(buffer.restore_undo_history(&saved));
+        let step_size = saved.steps[0].size();
+        let kept = buffer.undo_snapshot().undo_history(None, step_size);
+        assert_eq!(kept.steps.len(), 1);
+    }
+}
@@ -1,31 +1,30 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 use druid::{
     BoxConstraints, Color, Command, Data, Env, Event, EventCtx, FontDescriptor,
     FontFamily, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect,
-    RenderContext, Size, Target, TextLayout, UpdateCtx, Widget,
-};
-use lsp_types::{
-    CodeActionDisabled, CodeActionOrCommand, DocumentChangeOperation,
-    DocumentChanges, OneOf, TextEdit, Url, WorkspaceEdit,
+    RenderContext, Size, Target, TextLayout, UpdateCtx, Widget, WidgetId,
 };
+use lsp_types::{CodeActionDisabled, CodeActionOrCommand};
 
 use crate::{
-    buffer::{BufferContent, EditType},
+    buffer::BufferContent,
     command::{CommandExecuted, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND},
     config::LapceTheme,
     data::{EditorContent, LapceMainSplitData, LapceTabData},
     keypress::{KeyPressData, KeyPressFocus},
-    movement::{Movement, Selection},
+    movement::Movement,
     proxy::LapceProxy,
     state::Mode,
     theme::OldLapceTheme,
+    workspace_edit::workspace_edits,
 };
 
 pub struct CodeAction {}
 
 #[derive(Clone, Data)]
 pub struct CodeActionData {
+    pub tab_id: WidgetId,
     pub main_split: LapceMainSplitData,
     pub proxy: Arc<LapceProxy>,
 }
@@ -127,41 +126,14 @@ impl CodeActionData {
                     CodeActionOrCommand::Command(cmd) => {}
                     CodeActionOrCommand::CodeAction(action) => {
                         if let Some(edit) = action.edit.as_ref() {
-                            if let Some(edits) = workspce_edits(edit) {
-                                if let Some(edits) =
-                                    edits.get(&Url::from_file_path(&path).unwrap())
-                                {
-                                    let path = path.clone();
-                                    let buffer = self
-                                        .main_split
-                                        .open_files
-                                        .get_mut(&path)
-                                        .unwrap();
-                                    let edits: Vec<(Selection, String)> = edits
-                                        .iter()
-                                        .map(|edit| {
-                                            let selection = Selection::region(
-                                                buffer.offset_of_position(
-                                                    &edit.range.start,
-                                                ),
-                                                buffer.offset_of_position(
-                                                    &edit.range.end,
-                                                ),
-                                            );
-                                            (selection, edit.new_text.clone())
-                                        })
-                                        .collect();
-                                    self.main_split.edit(
-                                        ctx,
-                                        &path,
-                                        edits
-                                            .iter()
-                                            .map(|(s, c)| (s, c.as_ref()))
-                                            .collect(),
-                                        EditType::Other,
-                                    );
-                                }
-                            }
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::PreviewWorkspaceEdit(
+                                    action.title.clone(),
+                                    workspace_edits(edit),
+                                ),
+                                Target::Widget(self.tab_id),
+                            ));
                         }
                     }
                 }
@@ -216,6 +188,7 @@ impl Widget<LapceTabData> for CodeAction {
                 let mut keypress = data.keypress.clone();
                 let mut_keypress = Arc::make_mut(&mut keypress);
                 let mut code_action_data = CodeActionData {
+                    tab_id: data.id,
                     main_split: data.main_split.clone(),
                     proxy: data.proxy.clone(),
                 };
@@ -383,45 +356,3 @@ impl Widget<LapceTabData> for CodeAction {
         }
     }
 }
-
-fn workspce_edits(edit: &WorkspaceEdit) -> Option<HashMap<Url, Vec<TextEdit>>> {
-    if let Some(changes) = edit.changes.as_ref() {
-        return Some(changes.clone());
-    }
-
-    let changes = edit.document_changes.as_ref()?;
-    let edits = match changes {
-        DocumentChanges::Edits(edits) => edits
-            .iter()
-            .map(|e| {
-                (
-                    e.text_document.uri.clone(),
-                    e.edits
-                        .iter()
-                        .map(|e| match e {
-                            OneOf::Left(e) => e.clone(),
-                            OneOf::Right(e) => e.text_edit.clone(),
-                        })
-                        .collect(),
-                )
-            })
-            .collect::<HashMap<Url, Vec<TextEdit>>>(),
-        DocumentChanges::Operations(ops) => ops
-            .iter()
-            .filter_map(|o| match o {
-                DocumentChangeOperation::Op(op) => None,
-                DocumentChangeOperation::Edit(e) => Some((
-                    e.text_document.uri.clone(),
-                    e.edits
-                        .iter()
-                        .map(|e| match e {
-                            OneOf::Left(e) => e.clone(),
-                            OneOf::Right(e) => e.text_edit.clone(),
-                        })
-                        .collect(),
-                )),
-            })
-            .collect::<HashMap<Url, Vec<TextEdit>>>(),
-    };
-    Some(edits)
-}
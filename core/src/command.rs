@@ -2,12 +2,24 @@ use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use alacritty_terminal::ansi::CursorShape;
 use anyhow::Result;
-use druid::{Point, Rect, Selector, Size, WidgetId};
+use druid::{
+    Command, EventCtx, ExtEventSink, Point, Rect, Selector, Size, WidgetId,
+};
 use indexmap::IndexMap;
 use lapce_proxy::{
+    buffer::SaveConflict,
     dispatch::{DiffInfo, FileDiff, FileNodeItem},
+    editorconfig,
     plugin::PluginDescription,
+    protocol::{
+        ProtocolInfo, CAPABILITY_ENVIRONMENT, CAPABILITY_GIT,
+        CAPABILITY_GIT_CHECKOUT, CAPABILITY_GIT_FILE, CAPABILITY_GIT_REMOTE,
+        CAPABILITY_GLOBAL_SEARCH, CAPABILITY_REPLACE, CAPABILITY_SEARCH_CONTEXT,
+        CAPABILITY_TASKS, CAPABILITY_TERMINAL, CAPABILITY_TRASH,
+        CAPABILITY_WATCHER_STATS,
+    },
     terminal::TermId,
+    watcher::FileChanges,
 };
 use lsp_types::{
     CodeActionResponse, CompletionItem, CompletionResponse, Location, Position,
@@ -21,14 +33,25 @@ use tree_sitter_highlight::Highlight;
 use xi_rope::{spans::Spans, Rope};
 
 use crate::{
+    breadcrumbs::DocumentSymbolNode,
     buffer::BufferId,
     buffer::{DiffLines, InvalLines, Style},
+    data::ClosedEditor,
     editor::{EditorLocation, EditorLocationNew, HighlightTextLayout},
+    format::Formatting,
+    gutter::DiffHunk,
+    indexing::IndexScope,
     menu::MenuItem,
-    movement::{LinePosition, Movement},
+    movement::{LinePosition, Movement, Selection},
     palette::{NewPaletteItem, PaletteType},
-    split::SplitMoveDirection,
+    profile::Profile,
+    proxy::ProxyStatus,
+    recovery::SessionRecovery,
+    search::PinnedSearch,
+    split::{CloseChoice, CloseRequest, SplitMoveDirection},
     state::LapceWorkspace,
+    terminal::{PasteMode, TerminalTask},
+    title::TitleMenu,
 };
 
 pub const LAPCE_NEW_COMMAND: Selector<LapceCommandNew> =
@@ -114,6 +137,14 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Open Keyboard Shortcuts")]
     OpenKeyboardShortcuts,
 
+    #[strum(serialize = "export_profile")]
+    #[strum(message = "Export Settings Profile")]
+    ExportProfile,
+
+    #[strum(serialize = "import_profile")]
+    #[strum(message = "Import Settings Profile")]
+    ImportProfile,
+
     #[strum(serialize = "close_tab")]
     #[strum(message = "Close Current Tab")]
     CloseTab,
@@ -130,6 +161,10 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Go To Previous Tab")]
     PreviousTab,
 
+    /// Goes to the tab at the index in the data, for the tab menu.
+    #[strum(serialize = "focus_tab")]
+    FocusTab,
+
     #[strum(serialize = "reload_window")]
     #[strum(message = "Reload Window")]
     ReloadWindow,
@@ -138,6 +173,54 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Connect to SSH Host")]
     ConnectSshHost,
 
+    /// Moves the file or the folder at the path in `data` to the trash.
+    #[strum(serialize = "delete_file")]
+    DeleteFile,
+
+    /// Opens the file, or reveals the folder, picked in the menu of a
+    /// breadcrumb.
+    #[strum(serialize = "breadcrumbs.open")]
+    OpenBreadcrumb,
+
+    #[strum(serialize = "recently_deleted")]
+    #[strum(message = "Recently Deleted Files")]
+    RecentlyDeleted,
+
+    /// Forgets the recent files, searches and palette picks of the
+    /// workspace, and deletes its scratches that aren't open.
+    #[strum(serialize = "clear_workspace_history")]
+    #[strum(message = "Clear Workspace History")]
+    ClearWorkspaceHistory,
+
+    /// Restores the deleted item with the `id` in `data`, or the latest one
+    /// deleted from its `path`.
+    #[strum(serialize = "restore_deleted")]
+    RestoreDeleted,
+
+    #[strum(serialize = "reconnect_workspace")]
+    #[strum(message = "Reconnect Workspace")]
+    ReconnectWorkspace,
+
+    /// Replaces the tab with an empty one, as a new window has.
+    #[strum(serialize = "close_workspace")]
+    #[strum(message = "Close Workspace")]
+    CloseWorkspace,
+
+    /// Opens the menu of the workspace box of the title bar, like clicking it.
+    #[strum(serialize = "title.show_workspace_menu")]
+    #[strum(message = "Title Bar: Show Workspace Menu")]
+    ShowWorkspaceMenu,
+
+    /// Does what clicking the branch of the title bar does.
+    #[strum(serialize = "title.show_branch_menu")]
+    #[strum(message = "Title Bar: Show Branch Menu")]
+    ShowBranchMenu,
+
+    /// Does what clicking the remote box of the title bar does.
+    #[strum(serialize = "title.show_remote_menu")]
+    #[strum(message = "Title Bar: Show Remote Menu")]
+    ShowRemoteMenu,
+
     #[strum(serialize = "palette.line")]
     PaletteLine,
 
@@ -154,9 +237,103 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "palette.workspace")]
     PaletteWorkspace,
 
+    #[strum(message = "Open Scratch Buffer")]
+    #[strum(serialize = "palette.scratch")]
+    PaletteScratch,
+
+    #[strum(serialize = "new_file")]
+    #[strum(message = "New File")]
+    NewFile,
+
+    #[strum(serialize = "new_scratch_buffer")]
+    #[strum(message = "New Scratch Buffer")]
+    NewScratchBuffer,
+
+    #[strum(serialize = "palette.todos")]
+    #[strum(message = "Show TODOs")]
+    PaletteTodos,
+
+    #[strum(serialize = "palette.command_history")]
+    #[strum(message = "Show Command History")]
+    PaletteCommandHistory,
+
+    #[strum(serialize = "palette.pane_files")]
+    #[strum(message = "Show Files of This Pane")]
+    PaletteThisPaneFiles,
+
+    #[strum(serialize = "show_environment")]
+    #[strum(message = "Show Environment")]
+    ShowEnvironment,
+
+    #[strum(serialize = "show_watcher_stats")]
+    #[strum(message = "Show File Watcher Statistics")]
+    ShowWatcherStats,
+
+    #[strum(serialize = "configure_indexed_folders")]
+    #[strum(message = "Configure Indexed Folders")]
+    ConfigureIndexedFolders,
+
+    #[strum(serialize = "close_other_editors")]
+    #[strum(message = "Close Other Editors")]
+    CloseOtherEditors,
+
+    #[strum(serialize = "close_all_editors")]
+    #[strum(message = "Close All Editors")]
+    CloseAllEditors,
+
+    #[strum(serialize = "reopen_closed_editor")]
+    #[strum(message = "Reopen Closed Editor")]
+    ReopenClosedEditor,
+
+    #[strum(serialize = "set_log_level")]
+    #[strum(message = "Set Log Level")]
+    SetLogLevel,
+
+    #[strum(serialize = "open_log_file")]
+    #[strum(message = "Open Log File")]
+    OpenLogFile,
+
+    #[strum(serialize = "open_log_view")]
+    #[strum(message = "Open Log View")]
+    OpenLogView,
+
+    #[strum(serialize = "cycle_log_view_level")]
+    #[strum(message = "Cycle Log View Level")]
+    CycleLogViewLevel,
+
+    #[strum(serialize = "report_issue")]
+    #[strum(message = "Report Issue")]
+    ReportIssue,
+
     #[strum(serialize = "source_control.checkout_branch")]
     CheckoutBranch,
 
+    #[strum(serialize = "source_control.branch_menu")]
+    #[strum(message = "Checkout Branch")]
+    BranchMenu,
+
+    /// Creates the branch named in `data` and checks it out, or asks for the
+    /// name without one.
+    #[strum(serialize = "source_control.create_branch")]
+    #[strum(message = "Create Branch")]
+    CreateBranch,
+
+    #[strum(serialize = "source_control.fetch")]
+    #[strum(message = "Git: Fetch")]
+    GitFetch,
+
+    #[strum(serialize = "source_control.pull")]
+    #[strum(message = "Git: Pull")]
+    GitPull,
+
+    #[strum(serialize = "source_control.push")]
+    #[strum(message = "Git: Push")]
+    GitPush,
+
+    #[strum(serialize = "toggle_fullscreen")]
+    #[strum(message = "Toggle Full Screen")]
+    ToggleFullScreen,
+
     #[strum(serialize = "toggle_maximized_panel")]
     ToggleMaximizedPanel,
 
@@ -184,17 +361,252 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "toggle_problem")]
     ToggleProblem,
 
+    #[strum(serialize = "problem.toggle_errors")]
+    #[strum(message = "Problems: Toggle Errors")]
+    ProblemToggleErrors,
+
+    #[strum(serialize = "problem.toggle_warnings")]
+    #[strum(message = "Problems: Toggle Warnings")]
+    ProblemToggleWarnings,
+
+    #[strum(serialize = "problem.toggle_information")]
+    #[strum(message = "Problems: Toggle Information")]
+    ProblemToggleInformation,
+
     #[strum(serialize = "toggle_search")]
     ToggleSearch,
 
+    #[strum(serialize = "search.toggle_case_sensitive")]
+    #[strum(message = "Search: Toggle Case Sensitive")]
+    SearchToggleCaseSensitive,
+
+    #[strum(serialize = "search.toggle_whole_word")]
+    #[strum(message = "Search: Toggle Whole Word")]
+    SearchToggleWholeWord,
+
+    #[strum(serialize = "search.toggle_regex")]
+    #[strum(message = "Search: Toggle Regex")]
+    SearchToggleRegex,
+
+    #[strum(serialize = "search.toggle_context")]
+    #[strum(message = "Search: Toggle Context Lines")]
+    SearchToggleContext,
+
+    #[strum(serialize = "search.toggle_pin")]
+    #[strum(message = "Search: Pin or Unpin the Query")]
+    SearchTogglePin,
+
+    /// Unpins the pinned query of the index in the data.
+    #[strum(serialize = "search.unpin")]
+    SearchUnpin,
+
+    #[strum(serialize = "search.copy_results")]
+    #[strum(message = "Copy Search Results")]
+    SearchCopyResults,
+
+    /// Copies the path in the data, relative to the workspace.
+    #[strum(serialize = "search.copy_path")]
+    SearchCopyPath,
+
+    /// Copies the line of the match of the path and the index in the data.
+    #[strum(serialize = "search.copy_line")]
+    SearchCopyLine,
+
+    #[strum(serialize = "search.toggle_preserve_case")]
+    #[strum(message = "Search: Toggle Preserve Case")]
+    SearchTogglePreserveCase,
+
+    #[strum(serialize = "search.replace_all")]
+    #[strum(message = "Search: Replace All")]
+    SearchReplaceAll,
+
+    #[strum(serialize = "search.refresh")]
+    #[strum(message = "Search: Refresh Changed Files")]
+    RefreshSearch,
+
+    #[strum(serialize = "search.rerun")]
+    #[strum(message = "Search: Run Again")]
+    SearchRerun,
+
+    #[strum(serialize = "search.clear_results")]
+    #[strum(message = "Search: Clear Results")]
+    SearchClearResults,
+
+    #[strum(serialize = "search.next_result")]
+    #[strum(message = "Search: Next Result")]
+    SearchResultNext,
+
+    #[strum(serialize = "search.previous_result")]
+    #[strum(message = "Search: Previous Result")]
+    SearchResultPrev,
+
+    #[strum(serialize = "search.open_result")]
+    #[strum(message = "Search: Open Result")]
+    SearchResultOpen,
+
+    #[strum(serialize = "search.history_previous")]
+    #[strum(message = "Search: Previous Search in History")]
+    SearchHistoryPrevious,
+
+    #[strum(serialize = "search.history_next")]
+    #[strum(message = "Search: Next Search in History")]
+    SearchHistoryNext,
+
+    #[strum(serialize = "search.collapse_all")]
+    #[strum(message = "Search: Collapse All Results")]
+    SearchCollapseAll,
+
+    #[strum(serialize = "search.expand_all")]
+    #[strum(message = "Search: Expand All Results")]
+    SearchExpandAll,
+
+    #[strum(serialize = "toggle_editors_panel")]
+    ToggleEditorsPanel,
+
+    #[strum(serialize = "toggle_rulers")]
+    #[strum(message = "Toggle Rulers")]
+    ToggleRulers,
+
+    #[strum(serialize = "move_editor_to_panel_area")]
+    #[strum(message = "Move Editor to Panel Area")]
+    MoveEditorToPanelArea,
+
+    #[strum(serialize = "move_editor_to_main_split")]
+    #[strum(message = "Move Editor to Main Split")]
+    MoveEditorToMainSplit,
+
     #[strum(serialize = "focus_editor")]
     FocusEditor,
 
     #[strum(serialize = "focus_terminal")]
     FocusTerminal,
 
+    #[strum(serialize = "terminal.next_tab")]
+    #[strum(message = "Terminal: Next Tab")]
+    TerminalTabNext,
+
+    #[strum(serialize = "terminal.previous_tab")]
+    #[strum(message = "Terminal: Previous Tab")]
+    TerminalTabPrev,
+
+    #[strum(serialize = "terminal.new_tab")]
+    #[strum(message = "Terminal: New Tab")]
+    TerminalTabNew,
+
+    /// Starts a terminal in the active tab of the terminal panel if it has
+    /// none, or else in a new tab.
+    #[strum(serialize = "terminal.new")]
+    #[strum(message = "Terminal: New Terminal")]
+    NewTerminal,
+
+    #[strum(serialize = "terminal.close_tab")]
+    #[strum(message = "Terminal: Close Tab")]
+    TerminalTabClose,
+
+    /// Runs the task of the settings named in `data` in a terminal, or asks
+    /// which one without a name.
+    #[strum(serialize = "run_task")]
+    #[strum(message = "Run Task")]
+    RunTask,
+
+    /// Opens a terminal in a new tab, in the folder in `data` or else in the
+    /// folder of the file of the active editor.
+    #[strum(serialize = "terminal.open_here")]
+    #[strum(message = "Open Terminal Here")]
+    OpenTerminalHere,
+
+    /// Names the active terminal after `data`, or asks for the name without
+    /// it.
+    #[strum(serialize = "terminal.rename")]
+    #[strum(message = "Terminal: Rename")]
+    TerminalRename,
+
+    /// Commits the staged changes, or all of them when none are staged,
+    /// with the message of the source control panel.
     #[strum(serialize = "source_control_commit")]
+    #[strum(message = "Source Control: Commit")]
     SourceControlCommit,
+
+    /// Stages the files whose paths are in `data`, or the selected changes
+    /// of the source control panel.
+    #[strum(serialize = "source_control.stage")]
+    #[strum(message = "Source Control: Stage Changes")]
+    SourceControlStage,
+
+    #[strum(serialize = "source_control.unstage")]
+    #[strum(message = "Source Control: Unstage Changes")]
+    SourceControlUnstage,
+
+    #[strum(serialize = "source_control.discard")]
+    #[strum(message = "Source Control: Discard Changes")]
+    SourceControlDiscard,
+}
+
+impl LapceWorkbenchCommand {
+    /// The proxy capability the command relies on, if any.
+    pub fn required_capability(&self) -> Option<&'static str> {
+        match self {
+            LapceWorkbenchCommand::ShowEnvironment => Some(CAPABILITY_ENVIRONMENT),
+            LapceWorkbenchCommand::ShowWatcherStats => {
+                Some(CAPABILITY_WATCHER_STATS)
+            }
+            LapceWorkbenchCommand::PaletteTodos
+            | LapceWorkbenchCommand::ToggleSearch
+            | LapceWorkbenchCommand::SearchToggleCaseSensitive
+            | LapceWorkbenchCommand::SearchToggleWholeWord
+            | LapceWorkbenchCommand::SearchToggleRegex
+            | LapceWorkbenchCommand::SearchTogglePin
+            | LapceWorkbenchCommand::SearchUnpin
+            | LapceWorkbenchCommand::SearchCopyResults
+            | LapceWorkbenchCommand::SearchCopyPath
+            | LapceWorkbenchCommand::SearchCopyLine
+            | LapceWorkbenchCommand::SearchTogglePreserveCase
+            | LapceWorkbenchCommand::SearchResultNext
+            | LapceWorkbenchCommand::SearchResultPrev
+            | LapceWorkbenchCommand::SearchResultOpen
+            | LapceWorkbenchCommand::SearchHistoryPrevious
+            | LapceWorkbenchCommand::SearchHistoryNext
+            | LapceWorkbenchCommand::SearchCollapseAll
+            | LapceWorkbenchCommand::SearchExpandAll
+            | LapceWorkbenchCommand::RefreshSearch
+            | LapceWorkbenchCommand::SearchRerun
+            | LapceWorkbenchCommand::SearchClearResults => {
+                Some(CAPABILITY_GLOBAL_SEARCH)
+            }
+            LapceWorkbenchCommand::SearchReplaceAll => Some(CAPABILITY_REPLACE),
+            LapceWorkbenchCommand::SearchToggleContext => {
+                Some(CAPABILITY_SEARCH_CONTEXT)
+            }
+            LapceWorkbenchCommand::ToggleTerminal
+            | LapceWorkbenchCommand::FocusTerminal
+            | LapceWorkbenchCommand::TerminalTabNext
+            | LapceWorkbenchCommand::TerminalTabPrev
+            | LapceWorkbenchCommand::TerminalTabNew
+            | LapceWorkbenchCommand::NewTerminal
+            | LapceWorkbenchCommand::TerminalTabClose
+            | LapceWorkbenchCommand::OpenTerminalHere
+            | LapceWorkbenchCommand::TerminalRename => Some(CAPABILITY_TERMINAL),
+            LapceWorkbenchCommand::RunTask => Some(CAPABILITY_TASKS),
+            LapceWorkbenchCommand::SourceControlStage
+            | LapceWorkbenchCommand::SourceControlUnstage
+            | LapceWorkbenchCommand::SourceControlDiscard => {
+                Some(CAPABILITY_GIT_FILE)
+            }
+            LapceWorkbenchCommand::CheckoutBranch
+            | LapceWorkbenchCommand::BranchMenu
+            | LapceWorkbenchCommand::CreateBranch => Some(CAPABILITY_GIT_CHECKOUT),
+            LapceWorkbenchCommand::GitFetch
+            | LapceWorkbenchCommand::GitPull
+            | LapceWorkbenchCommand::GitPush => Some(CAPABILITY_GIT_REMOTE),
+            LapceWorkbenchCommand::DeleteFile
+            | LapceWorkbenchCommand::RecentlyDeleted
+            | LapceWorkbenchCommand::RestoreDeleted => Some(CAPABILITY_TRASH),
+            LapceWorkbenchCommand::ToggleSourceControl
+            | LapceWorkbenchCommand::SourceControlCommit
+            | LapceWorkbenchCommand::ShowBranchMenu => Some(CAPABILITY_GIT),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Display, EnumString, EnumIter, Clone, PartialEq, Debug, EnumMessage)]
@@ -211,6 +623,13 @@ pub enum LapceCommand {
     CodeActionsCancel,
     #[strum(serialize = "palette.cancel")]
     PaletteCancel,
+    /// Copies the id of the command picked in the palette, to bind a key to.
+    #[strum(serialize = "palette.copy_command_id")]
+    PaletteCopyCommandId,
+    #[strum(serialize = "menu.cancel")]
+    MenuCancel,
+    #[strum(serialize = "workspace_edit.cancel")]
+    WorkspaceEditCancel,
     #[strum(serialize = "delete_backward")]
     DeleteBackward,
     #[strum(serialize = "delete_foreward")]
@@ -255,14 +674,39 @@ pub enum LapceCommand {
     ListNext,
     #[strum(serialize = "list.previous")]
     ListPrevious,
+    #[strum(serialize = "list.next_page")]
+    ListNextPage,
+    #[strum(serialize = "list.previous_page")]
+    ListPreviousPage,
     #[strum(serialize = "split_vertical")]
     SplitVertical,
+    #[strum(serialize = "split_vertical_empty")]
+    #[strum(message = "Split Right with Empty Editor")]
+    SplitVerticalEmpty,
     #[strum(serialize = "split_horizontal")]
     SplitHorizontal,
     #[strum(serialize = "split_close")]
     SplitClose,
+    #[strum(serialize = "clear_terminal")]
+    #[strum(message = "Terminal: Clear")]
+    ClearTerminal,
+    #[strum(serialize = "toggle_pin_editor")]
+    #[strum(message = "Toggle Pin Editor")]
+    TogglePinEditor,
+    #[strum(serialize = "copy_file_path")]
+    #[strum(message = "Copy File Path")]
+    CopyFilePath,
+    #[strum(serialize = "reveal_in_file_explorer")]
+    #[strum(message = "Reveal in File Explorer")]
+    RevealInFileExplorer,
+    #[strum(serialize = "toggle_line_numbers")]
+    #[strum(message = "Toggle Line Numbers")]
+    ToggleLineNumbers,
     #[strum(serialize = "split_exchange")]
     SplitExchange,
+    #[strum(serialize = "split_even_size")]
+    #[strum(message = "Split: Give the Views the Same Size")]
+    SplitEvenSize,
     #[strum(serialize = "split_right")]
     SplitRight,
     #[strum(serialize = "split_left")]
@@ -297,6 +741,9 @@ pub enum LapceCommand {
     InsertNewLine,
     #[strum(serialize = "insert_tab")]
     InsertTab,
+    #[strum(serialize = "expand_abbreviation")]
+    #[strum(message = "Expand Emmet Abbreviation")]
+    ExpandAbbreviation,
     #[strum(serialize = "word_backward")]
     WordBackward,
     #[strum(serialize = "word_foward")]
@@ -323,6 +770,46 @@ pub enum LapceCommand {
     ClipboardCopy,
     #[strum(serialize = "clipboard_paste")]
     ClipboardPaste,
+    #[strum(serialize = "paste_and_match_indentation")]
+    #[strum(message = "Paste and Match Indentation")]
+    PasteAndMatchIndentation,
+    #[strum(serialize = "paste_without_formatting")]
+    #[strum(message = "Paste without Formatting")]
+    PasteWithoutFormatting,
+    #[strum(serialize = "fix_pasted_text")]
+    #[strum(message = "Fix Characters in Pasted Text")]
+    FixPastedText,
+    #[strum(serialize = "keep_pasted_text")]
+    KeepPastedText,
+    #[strum(serialize = "restore_deleted_file")]
+    #[strum(message = "Restore the Deleted File")]
+    RestoreDeletedFile,
+    #[strum(serialize = "overwrite_file")]
+    #[strum(message = "Overwrite the File on Disk")]
+    OverwriteFile,
+    #[strum(serialize = "reload_from_disk")]
+    #[strum(message = "Reload from Disk")]
+    ReloadFromDisk,
+    #[strum(serialize = "ignore_disk_change")]
+    IgnoreDiskChange,
+    #[strum(serialize = "editor_zoom_in")]
+    #[strum(message = "Zoom In Editor")]
+    EditorZoomIn,
+    #[strum(serialize = "editor_zoom_out")]
+    #[strum(message = "Zoom Out Editor")]
+    EditorZoomOut,
+    #[strum(serialize = "editor_zoom_reset")]
+    #[strum(message = "Reset Editor Zoom")]
+    EditorZoomReset,
+    #[strum(serialize = "compare_with_disk")]
+    #[strum(message = "Compare with the File on Disk")]
+    CompareWithDisk,
+    #[strum(serialize = "narrow_to_selection")]
+    #[strum(message = "Narrow to Selection")]
+    NarrowToSelection,
+    #[strum(serialize = "widen_from_narrow")]
+    #[strum(message = "Widen from Narrow")]
+    WidenFromNarrow,
     #[strum(serialize = "undo")]
     Undo,
     #[strum(serialize = "redo")]
@@ -338,6 +825,13 @@ pub enum LapceCommand {
     JumpLocationBackward,
     #[strum(serialize = "jump_location_forward")]
     JumpLocationForward,
+    /// Shows the file the editor showed before the current one.
+    #[strum(serialize = "pane_back_file")]
+    #[strum(message = "Pane: Previous File")]
+    PaneBackFile,
+    #[strum(serialize = "pane_forward_file")]
+    #[strum(message = "Pane: Next File")]
+    PaneForwardFile,
     #[strum(serialize = "next_error")]
     NextError,
     #[strum(serialize = "previous_error")]
@@ -351,10 +845,19 @@ pub enum LapceCommand {
     FormatDocument,
     #[strum(serialize = "save")]
     Save,
+    #[strum(serialize = "save_as")]
+    #[strum(message = "Save As")]
+    SaveAs,
     #[strum(serialize = "show_code_actions")]
     ShowCodeActions,
+    #[strum(serialize = "show_line_diagnostics")]
+    #[strum(message = "Show Line Diagnostics")]
+    ShowLineDiagnostics,
     #[strum(serialize = "match_pairs")]
     MatchPairs,
+    #[strum(serialize = "select_matching_tag_pair")]
+    #[strum(message = "Select Matching Tag Pair")]
+    SelectMatchingTagPair,
     #[strum(serialize = "next_unmatched_right_bracket")]
     NextUnmatchedRightBracket,
     #[strum(serialize = "jump_to_next_snippet_placeholder")]
@@ -381,6 +884,46 @@ pub enum LapceCommand {
 }
 
 impl LapceCommand {
+    /// Whether the command changes the buffer, which read-only views ignore.
+    pub fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            LapceCommand::DeleteBackward
+                | LapceCommand::DeleteForeward
+                | LapceCommand::DeleteForewardAndInsert
+                | LapceCommand::DeleteVisual
+                | LapceCommand::DeleteOperator
+                | LapceCommand::DeleteWordBackward
+                | LapceCommand::DeleteToBeginningOfLine
+                | LapceCommand::InsertMode
+                | LapceCommand::InsertFirstNonBlank
+                | LapceCommand::ToggleComment
+                | LapceCommand::NewLineAbove
+                | LapceCommand::NewLineBelow
+                | LapceCommand::GetCompletion
+                | LapceCommand::InsertNewLine
+                | LapceCommand::InsertTab
+                | LapceCommand::ExpandAbbreviation
+                | LapceCommand::Append
+                | LapceCommand::AppendEndOfLine
+                | LapceCommand::Paste
+                | LapceCommand::ClipboardPaste
+                | LapceCommand::PasteAndMatchIndentation
+                | LapceCommand::PasteWithoutFormatting
+                | LapceCommand::FixPastedText
+                | LapceCommand::Undo
+                | LapceCommand::Redo
+                | LapceCommand::FormatDocument
+                | LapceCommand::Save
+                | LapceCommand::SaveAs
+                | LapceCommand::OverwriteFile
+                | LapceCommand::ReloadFromDisk
+                | LapceCommand::ShowCodeActions
+                | LapceCommand::JoinLines
+                | LapceCommand::Insert(_)
+        )
+    }
+
     pub fn move_command(&self, count: Option<usize>) -> Option<Movement> {
         match self {
             LapceCommand::Left => Some(Movement::Left),
@@ -425,11 +968,26 @@ pub enum EnsureVisiblePosition {
 
 #[derive(Debug)]
 pub enum LapceUICommand {
-    InitTerminalPanel(bool),
+    /// Starts a terminal in the split of a terminal panel tab if it has
+    /// none, focused or not, in the folder if there's one.
+    InitTerminalPanel(bool, Option<PathBuf>),
+    /// Adds a tab to the terminal panel, with a terminal in it whose shell
+    /// starts in the folder if there's one.
+    NewTerminalTab(Option<PathBuf>),
+    /// Adds a tab to the terminal panel, with the task running in it.
+    NewTerminalTaskTab(Arc<TerminalTask>),
+    /// Starts the task in the split of a terminal panel tab, or beside its
+    /// last terminal if it has one already.
+    InitTerminalTask(Arc<TerminalTask>),
+    /// Makes the tab of the index the active one in the terminal panel.
+    FocusTerminalTab(usize),
+    /// Closes the terminal panel tab of the split with its terminals.
+    CloseTerminalTab(WidgetId),
     ReloadConfig,
     LoadBuffer {
         path: PathBuf,
         content: String,
+        editorconfig: editorconfig::Properties,
         locations: Vec<(WidgetId, EditorLocationNew)>,
     },
     LoadBufferHead {
@@ -445,28 +1003,108 @@ pub enum LapceUICommand {
     },
     HideMenu,
     ShowMenu(Point, Arc<Vec<MenuItem>>),
+    /// Runs the item of the menu with the index, as if it was clicked.
+    RunMenuItem(usize),
+    /// Hides the menu without running any item.
+    CancelMenu,
+    /// Opens the menu of the part of the title bar, under it.
+    ShowTitleMenu(TitleMenu),
     UpdateSearch(String),
+    /// The text of the include field of the search panel changed.
+    UpdateSearchInclude(String),
+    /// The text of the replace field of the search panel changed.
+    UpdateSearchReplace(String),
+    GlobalSearchError(u64, String),
+    /// The results of a pinned query were counted, none if it failed.
+    PinnedSearchCount(PinnedSearch, Option<usize>),
+    /// The matches of the search with the id in the file were replaced.
+    SearchMatchesReplaced(u64, PathBuf),
+    /// The stale files of the search with the id were searched again, with
+    /// the matches of those that still have some and their lines of context.
+    SearchFilesRefreshed(
+        u64,
+        Vec<PathBuf>,
+        Arc<HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>>,
+        Arc<HashMap<PathBuf, Vec<(usize, String)>>>,
+    ),
+    /// The matches of a file and the lines of context around them, as the
+    /// search with the id finds them.
+    GlobalSearchResultAdded(
+        u64,
+        PathBuf,
+        Vec<(usize, (usize, usize), String)>,
+        Vec<(usize, String)>,
+    ),
+    /// The search with the id is done. Proxies that don't stream the matches
+    /// send them all here.
     GlobalSearchResult(
-        String,
+        u64,
         Arc<HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>>,
     ),
     SetWorkspace(LapceWorkspace),
+    CloseWorkspace,
+    /// The matches of the find in the whole file, for the search of its
+    /// buffer with the generation.
+    FindAllDone(PathBuf, u64, Arc<Selection>),
     SetTheme(String, bool),
     OpenFile(PathBuf),
+    RevealInFileExplorer(PathBuf),
+    ToggleLineNumbers,
+    NewScratchBuffer(String),
+    ShowEnvironment(String),
+    ShowWatcherStats(String),
+    UpdateProxyProtocol(ProtocolInfo),
+    UpdateProxyStatus(ProxyStatus),
+    UpdateLogView,
     OpenFileDiff(PathBuf, String),
     CancelCompletion(usize),
     ResolveCompletion(BufferId, u64, usize, CompletionItem),
     UpdateCompletion(usize, String, CompletionResponse),
+    /// The completion item of the request with its documentation resolved.
+    UpdateCompletionDoc(usize, CompletionItem),
     UpdateCodeActions(PathBuf, u64, usize, CodeActionResponse),
     CancelPalette,
+    /// How testing the connection to a new SSH host went.
+    SshTestResult(String, Result<(), String>),
     ShowCodeActions,
     CancelCodeActions,
+    /// Previews the workspace edit with the title, as the edits of each file.
+    PreviewWorkspaceEdit(String, Vec<(PathBuf, Vec<TextEdit>)>),
+    /// A file of the workspace edit with the id was read, or failed to be.
+    WorkspaceEditFileRead(u64, PathBuf, Result<String, String>),
+    ApplyWorkspaceEdit,
+    /// The proxy edited a file of the workspace edit with the id, or failed
+    /// to.
+    WorkspaceEditApplied(u64, PathBuf, Result<(), String>),
+    CancelWorkspaceEdit,
+    /// Opens the URL with the default application of the OS.
+    OpenUrl(String),
     Focus,
     FocusSourceControl,
     FocusEditor,
     RunPalette(Option<PaletteType>),
     RunPaletteReferences(Vec<EditorLocationNew>),
+    /// Asks how to import the profile.
+    RunPaletteProfileImport(Arc<Profile>),
+    /// Asks whether to export the secret-looking settings of the profile.
+    RunPaletteProfileExport(Arc<Profile>),
+    /// Applies the profile, replacing the current settings if true.
+    ImportProfile(Arc<Profile>, bool),
+    /// Asks where to save the profile, and saves it.
+    ExportProfile(Arc<Profile>),
     UpdatePaletteItems(String, Vec<NewPaletteItem>),
+    /// The size of the file the file palette is about to preview.
+    PreviewPaletteFile(PathBuf, u64),
+    /// The workspace has more files than `files.large-workspace-threshold`,
+    /// at least this many.
+    LargeWorkspace(usize),
+    SetIndexScope(IndexScope),
+    UpdateTodos(
+        String,
+        Arc<HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>>,
+    ),
+    /// The todo list of the run, scanned in the background.
+    UpdateTodoItems(String, Vec<NewPaletteItem>),
     FilterPaletteItems(String, String, Vec<NewPaletteItem>),
     UpdateExplorerItems(usize, PathBuf, Vec<FileNodeItem>),
     UpdateInstalledPlugins(HashMap<String, PluginDescription>),
@@ -479,22 +1117,59 @@ pub enum LapceUICommand {
     CloseTab,
     CloseTabId(WidgetId),
     FocusTabId(WidgetId),
+    FocusTabIndex(usize),
     SwapTab(usize),
     NewTab,
     NextTab,
     PreviousTab,
     FilterItems,
     ReloadWindow,
+    ToggleFullScreen,
+    WindowMinimize,
+    WindowMaximizeToggle,
+    WindowClose,
     CloseBuffers(Vec<BufferId>),
     RequestPaintRect(Rect),
     ApplyEdits(usize, u64, Vec<TextEdit>),
     ApplyEditsAndSave(usize, u64, Result<Value>),
-    DocumentFormat(PathBuf, u64, Result<Value>),
-    DocumentFormatAndSave(PathBuf, u64, Result<Value>),
+    /// The formatting of the file at the revision, kept to the region of
+    /// a narrowed editor if there's one.
+    DocumentFormat(
+        PathBuf,
+        u64,
+        Option<std::ops::Range<usize>>,
+        Result<Formatting>,
+    ),
+    DocumentFormatAndSave(
+        PathBuf,
+        u64,
+        Option<std::ops::Range<usize>>,
+        Result<Formatting>,
+    ),
     BufferSave(PathBuf, u64),
+    /// Saves the scratch, once its edits pause.
+    SaveScratch(PathBuf),
+    /// A save refused for a conflict, with the text on disk.
+    BufferSaveConflict(PathBuf, SaveConflict, Option<String>),
     UpdateSemanticTokens(BufferId, PathBuf, u64, Vec<(usize, usize, String)>),
+    /// The symbols of the file, at the revision of its buffer.
+    UpdateDocumentSymbols(PathBuf, u64, Arc<Vec<DocumentSymbolNode>>),
     UpdateHighlights(BufferId, u64, Vec<(usize, usize, Highlight)>),
+    /// The shell set the title of the terminal, empty when it reset it.
     UpdateTerminalTitle(TermId, String),
+    /// Names the terminal, or gives it back the title from the shell when
+    /// the name is empty.
+    TerminalRename(TermId, String),
+    /// A multi-line paste into the terminal, whose program doesn't take
+    /// bracketed pastes, to ask how to paste it.
+    TerminalPasteChoice(TermId, String),
+    /// Pastes the text waiting in the terminal the way picked, which the
+    /// next pastes into it use too.
+    TerminalPaste(TermId, PasteMode),
+    /// Restores what was picked of the session that didn't end cleanly.
+    RecoverSession(Arc<SessionRecovery>),
+    /// Starts fresh, keeping the recovery data aside.
+    DiscardRecovery(Arc<SessionRecovery>),
     UpdateStyle {
         id: BufferId,
         path: PathBuf,
@@ -514,6 +1189,10 @@ pub enum LapceUICommand {
         rev: u64,
         tree: Tree,
     },
+    /// Puts the lines of the head back in place of the change.
+    RevertDiffHunk(PathBuf, DiffHunk),
+    /// Copies the lines of the head the change replaced.
+    CopyDiffHunk(PathBuf, DiffHunk),
     UpdateHisotryChanges {
         id: BufferId,
         path: PathBuf,
@@ -527,6 +1206,16 @@ pub enum LapceUICommand {
     WorkDoneProgress(ProgressParams),
     UpdateDiffInfo(DiffInfo),
     ReloadBuffer(BufferId, u64, String),
+    /// An `.editorconfig` file above the buffer's file changed.
+    UpdateEditorconfig(BufferId, editorconfig::Properties),
+    /// The file of the buffer was deleted, or is back.
+    FileDeleted(BufferId, bool),
+    /// The file of the buffer changed on disk, with its new text.
+    FileChanged(BufferId, String),
+    /// A batch of the files of the workspace the watcher saw change.
+    FileChanges(FileChanges),
+    /// Reads the open folders of the file explorer again.
+    RefreshExplorer,
     EnsureVisible((Rect, (f64, f64), Option<EnsureVisiblePosition>)),
     EnsureRectVisible(Rect),
     EnsureCursorVisible(Option<EnsureVisiblePosition>),
@@ -536,20 +1225,53 @@ pub enum LapceUICommand {
     ScrollTo((f64, f64)),
     ForceScrollTo(f64, f64),
     CloseTerminal(TermId),
+    /// The process of the terminal exited, with its exit code if it was a
+    /// task.
+    TerminalExited(TermId, Option<i32>),
     SplitTerminal(bool, WidgetId),
+    /// Splits the terminal with a new one whose shell starts in the
+    /// directory, once the one of the terminal is known.
+    SplitTerminalIn(bool, WidgetId, Option<PathBuf>),
+    /// Splits the terminal with one running the task beside it.
+    SplitTerminalTask(WidgetId, Arc<TerminalTask>),
     SplitTerminalClose(TermId, WidgetId),
+    SplitTerminalCollapse(WidgetId),
     SplitAddEditor(WidgetId),
     SplitEditor(bool, WidgetId),
+    SplitEditorEmpty(WidgetId),
     SplitEditorMove(SplitMoveDirection, WidgetId),
     SplitEditorExchange(WidgetId),
     SplitEditorClose(WidgetId),
+    /// The answer to whether to save the editors being closed.
+    SplitEditorConfirmClose(CloseRequest, CloseChoice),
+    SplitEditorCloseOthers(WidgetId),
+    SplitEditorCloseAll,
+    /// Opens a closed editor again where it was.
+    SplitEditorReopen(ClosedEditor),
+    /// Splits the editor with a new one showing the location. It's the
+    /// preview editor of the split when the bool is set, and the editor
+    /// locations are opened to the side in otherwise.
+    SplitEditorTo(WidgetId, EditorLocationNew, bool),
+    /// Gives the children of the split the same size.
+    SplitEvenSize,
+    SplitEditorDragStart(WidgetId),
+    SplitEditorTransfer(WidgetId, WidgetId),
     Split(bool),
     SplitExchange,
     SplitClose,
     SplitMove(SplitMoveDirection),
+    SplitMoveOut(SplitMoveDirection),
     JumpToPosition(Option<WidgetId>, Position),
     JumpToLine(Option<WidgetId>, usize),
     JumpToLocation(Option<WidgetId>, EditorLocationNew),
+    /// Opens the location in the editor to the side of the one focused
+    /// last, splitting that editor the first time.
+    JumpToLocationToSide(EditorLocationNew),
+    /// Previews the location in the preview editor of the split, which it
+    /// keeps open as a normal editor when the bool is set.
+    PreviewLocation(EditorLocationNew, bool),
+    /// Shows the file at the index of the files the editor showed.
+    ShowShownFile(WidgetId, usize),
     TerminalJumpToLine(i32),
     GoToLocationNew(WidgetId, EditorLocationNew),
     GotoReference(WidgetId, usize, EditorLocationNew),
@@ -557,3 +1279,48 @@ pub enum LapceUICommand {
     PaletteReferences(usize, Vec<Location>),
     GotoLocation(Location),
 }
+
+/// What running a UI command needs of the context of the event it came in,
+/// so that the commands can also be run without a window, like the tests do
+/// with the `harness`.
+pub trait CommandCtx {
+    fn submit_command(&mut self, cmd: Command);
+    fn children_changed(&mut self);
+    fn request_layout(&mut self);
+    fn size(&self) -> Size;
+    fn get_external_handle(&self) -> ExtEventSink;
+    fn set_handled(&mut self);
+    /// Tells the window whether the mouse is on a part of the title bar
+    /// that drags it.
+    fn handle_titlebar(&mut self, val: bool);
+}
+
+impl CommandCtx for EventCtx<'_, '_> {
+    fn submit_command(&mut self, cmd: Command) {
+        EventCtx::submit_command(self, cmd);
+    }
+
+    fn children_changed(&mut self) {
+        EventCtx::children_changed(self);
+    }
+
+    fn request_layout(&mut self) {
+        EventCtx::request_layout(self);
+    }
+
+    fn size(&self) -> Size {
+        EventCtx::size(self)
+    }
+
+    fn get_external_handle(&self) -> ExtEventSink {
+        EventCtx::get_external_handle(self)
+    }
+
+    fn set_handled(&mut self) {
+        EventCtx::set_handled(self);
+    }
+
+    fn handle_titlebar(&mut self, val: bool) {
+        self.window().handle_titlebar(val);
+    }
+}
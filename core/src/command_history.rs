@@ -0,0 +1,161 @@
+use std::{collections::VecDeque, str::FromStr};
+
+use strum::EnumMessage;
+
+use crate::command::{
+    CommandTarget, LapceCommand, LapceCommandNew, LapceWorkbenchCommand,
+};
+
+/// How many commands the history keeps, dropping the oldest.
+const HISTORY_LIMIT: usize = 200;
+
+/// What a command is for, to tell the ones worth running again from those
+/// keys run all the time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandCategory {
+    /// Commands of the workbench, like toggling a panel or pinning a search.
+    Workbench,
+    /// Commands of the focused view listed in the palette, like formatting
+    /// the document or going to a definition.
+    View,
+    /// Moving the cursor, editing text and going through lists.
+    Input,
+    /// Commands that only serve the UI, like the history itself.
+    Internal,
+}
+
+/// The categories the history keeps.
+const RECORDED_CATEGORIES: &[CommandCategory] =
+    &[CommandCategory::Workbench, CommandCategory::View];
+
+impl CommandCategory {
+    pub fn of(command: &LapceCommandNew) -> Self {
+        match command.target {
+            CommandTarget::Workbench => {
+                match LapceWorkbenchCommand::from_str(&command.cmd) {
+                    Ok(LapceWorkbenchCommand::PaletteCommandHistory) | Err(_) => {
+                        CommandCategory::Internal
+                    }
+                    Ok(_) => CommandCategory::Workbench,
+                }
+            }
+            CommandTarget::Plugin(_) => CommandCategory::Workbench,
+            CommandTarget::Focus => match LapceCommand::from_str(&command.cmd) {
+                Ok(cmd) if cmd.get_message().is_some() => CommandCategory::View,
+                Ok(_) => CommandCategory::Input,
+                Err(_) => CommandCategory::Internal,
+            },
+        }
+    }
+
+    pub fn is_recorded(&self) -> bool {
+        RECORDED_CATEGORIES.contains(self)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CommandHistoryEntry {
+    /// The command as it ran, with its data.
+    pub command: LapceCommandNew,
+    /// When it ran, in seconds since the epoch.
+    pub time: i64,
+}
+
+/// The commands run in this session, in the categories worth running again.
+#[derive(Clone, Debug, Default)]
+pub struct CommandHistory {
+    entries: VecDeque<CommandHistoryEntry>,
+}
+
+impl CommandHistory {
+    /// Keeps the command if its category is recorded. Returns whether it
+    /// was kept.
+    pub fn record(&mut self, command: &LapceCommandNew, time: i64) -> bool {
+        if !CommandCategory::of(command).is_recorded() {
+            return false;
+        }
+        if self.entries.len() == HISTORY_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(CommandHistoryEntry {
+            command: command.clone(),
+            time,
+        });
+        true
+    }
+
+    /// The commands, the latest first.
+    pub fn entries(&self) -> impl Iterator<Item = &CommandHistoryEntry> {
+        self.entries.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn command(cmd: &str, target: CommandTarget) -> LapceCommandNew {
+        LapceCommandNew {
+            cmd: cmd.to_string(),
+            data: None,
+            palette_desc: None,
+            target,
+        }
+    }
+
+    #[test]
+    fn test_categories() {
+        let category = |cmd, target| CommandCategory::of(&command(cmd, target));
+        assert_eq!(
+            category("toggle_search", CommandTarget::Workbench),
+            CommandCategory::Workbench
+        );
+        assert_eq!(
+            category("format_document", CommandTarget::Focus),
+            CommandCategory::View
+        );
+        assert_eq!(
+            category("down", CommandTarget::Focus),
+            CommandCategory::Input
+        );
+        assert_eq!(
+            category("list.select", CommandTarget::Focus),
+            CommandCategory::Input
+        );
+        assert_eq!(
+            category("palette.command_history", CommandTarget::Workbench),
+            CommandCategory::Internal
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let mut history = CommandHistory::default();
+        assert!(!history.record(&command("down", CommandTarget::Focus), 1));
+        let mut unpin = command("search.unpin", CommandTarget::Workbench);
+        unpin.data = Some(json!(2));
+        assert!(history.record(&unpin, 2));
+        assert!(history.record(&command("format_document", CommandTarget::Focus), 3));
+        let entries: Vec<&CommandHistoryEntry> = history.entries().collect();
+        assert_eq!(entries[0].command.cmd, "format_document");
+        assert_eq!(entries[1].command.data, Some(json!(2)));
+        assert_eq!(entries[1].time, 2);
+
+        for i in 0..HISTORY_LIMIT {
+            history.record(
+                &command("toggle_search", CommandTarget::Workbench),
+                i as i64,
+            );
+        }
+        assert_eq!(history.len(), HISTORY_LIMIT);
+        assert!(history
+            .entries()
+            .all(|entry| entry.command.cmd == "toggle_search"));
+    }
+}
@@ -1,9 +1,17 @@
-use std::{cmp::Ordering, collections::HashMap, fmt::Display, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::Arc,
+};
 
 use anyhow::Error;
 use bit_vec::BitVec;
 use druid::{
-    piet::{Svg, Text, TextAttribute, TextLayoutBuilder},
+    piet::{
+        PietText, PietTextLayout, Svg, Text, TextAttribute, TextLayout as _,
+        TextLayoutBuilder,
+    },
     scroll_component::ScrollComponent,
     theme,
     widget::SvgData,
@@ -14,7 +22,9 @@ use druid::{
 };
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use itertools::Itertools;
-use lsp_types::{CompletionItem, CompletionItemKind, CompletionResponse, Position};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionResponse, Documentation, Position,
+};
 use regex::Regex;
 use std::str::FromStr;
 
@@ -30,12 +40,19 @@ use crate::{
     theme::OldLapceTheme,
 };
 
+const DOCUMENTATION_WIDTH: f64 = 400.0;
+const DOCUMENTATION_PADDING: f64 = 10.0;
+
 #[derive(Debug)]
 pub struct Snippet {
     elements: Vec<SnippetElement>,
 }
 
 impl Snippet {
+    pub fn new(elements: Vec<SnippetElement>) -> Self {
+        Snippet { elements }
+    }
+
     fn extract_elements(
         s: &str,
         pos: usize,
@@ -256,6 +273,17 @@ pub struct CompletionData {
     pub filtered_items: Arc<Vec<ScoredCompletionItem>>,
     pub matcher: Arc<SkimMatcherV2>,
     pub size: Size,
+    /// The items resolved for their documentation, by their labels.
+    pub resolved: im::HashMap<String, Arc<CompletionItem>>,
+    /// Whether the list was closed with Escape. It isn't opened again until
+    /// something is typed.
+    pub dismissed: bool,
+    /// The words of the open buffers completing the input, shown without
+    /// items from a language server, or with them if `merge_words`.
+    pub word_items: Arc<Vec<ScoredCompletionItem>>,
+    merge_words: bool,
+    /// The items of the language server for the input, with the words.
+    items: Arc<Vec<ScoredCompletionItem>>,
 }
 
 impl CompletionData {
@@ -274,6 +302,11 @@ impl CompletionData {
             matcher: Arc::new(SkimMatcherV2::default().ignore_case()),
             size: Size::new(400.0, 300.0),
             empty: Arc::new(Vec::new()),
+            resolved: im::HashMap::new(),
+            dismissed: false,
+            word_items: Arc::new(Vec::new()),
+            merge_words: false,
+            items: Arc::new(Vec::new()),
         }
     }
 
@@ -289,6 +322,15 @@ impl CompletionData {
         self.index = Movement::Up.update_index(self.index, self.len(), 1, true);
     }
 
+    pub fn next_page(&mut self, page: usize) {
+        self.index =
+            Movement::Down.update_index(self.index, self.len(), page, false);
+    }
+
+    pub fn previous_page(&mut self, page: usize) {
+        self.index = Movement::Up.update_index(self.index, self.len(), page, false);
+    }
+
     pub fn current_items(&self) -> &Arc<Vec<ScoredCompletionItem>> {
         if self.input == "" {
             self.all_items()
@@ -298,9 +340,52 @@ impl CompletionData {
     }
 
     pub fn all_items(&self) -> &Arc<Vec<ScoredCompletionItem>> {
-        self.input_items
+        &self.items
+    }
+
+    /// Puts the words with the items of the language server, after them, or
+    /// in their place when `merge` is off.
+    fn combine_items(&mut self) {
+        let items = self
+            .input_items
             .get(&self.input)
-            .unwrap_or_else(move || self.input_items.get("").unwrap_or(&self.empty))
+            .unwrap_or_else(|| self.input_items.get("").unwrap_or(&self.empty))
+            .clone();
+        self.items = if self.word_items.is_empty()
+            || (!self.merge_words && !items.is_empty())
+        {
+            items
+        } else if items.is_empty() {
+            self.word_items.clone()
+        } else {
+            let labels: HashSet<&str> =
+                items.iter().map(|i| i.item.label.as_str()).collect();
+            let mut merged = (*items).clone();
+            merged.extend(
+                self.word_items
+                    .iter()
+                    .filter(|w| !labels.contains(w.item.label.as_str()))
+                    .cloned(),
+            );
+            Arc::new(merged)
+        };
+    }
+
+    /// Sets the words completing the input, see `word_items`.
+    pub fn set_word_items(&mut self, items: Vec<CompletionItem>, merge: bool) {
+        self.word_items = Arc::new(
+            items
+                .into_iter()
+                .map(|item| ScoredCompletionItem {
+                    item,
+                    score: 0,
+                    index: 0,
+                    indices: Vec::new(),
+                })
+                .collect(),
+        );
+        self.merge_words = merge;
+        self.filter_items();
     }
 
     pub fn current_item(&self) -> &CompletionItem {
@@ -311,6 +396,83 @@ impl CompletionData {
         self.current_items()[self.index].item.label.as_str()
     }
 
+    /// The detail and the documentation of the selected item, from the
+    /// resolved one if it was.
+    pub fn current_documentation(&self) -> Option<(String, String)> {
+        if self.status == CompletionStatus::Inactive || self.len() == 0 {
+            return None;
+        }
+        let item = self.current_item();
+        let item = self
+            .resolved
+            .get(&item.label)
+            .map(|item| item.as_ref())
+            .unwrap_or(item);
+        let detail = item.detail.clone().unwrap_or_default();
+        let documentation = match item.documentation.as_ref() {
+            Some(Documentation::String(s)) => s.to_string(),
+            Some(Documentation::MarkupContent(content)) => content
+                .value
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("```"))
+                .join("\n"),
+            None => "".to_string(),
+        };
+        let documentation = documentation.trim().to_string();
+        if detail.is_empty() && documentation.is_empty() {
+            return None;
+        }
+        Some((detail, documentation))
+    }
+
+    /// Asks the language server for the documentation of the selected item,
+    /// if it came without it.
+    pub fn resolve_current(&self, proxy: Arc<LapceProxy>, event_sink: ExtEventSink) {
+        if self.status == CompletionStatus::Inactive || self.len() == 0 {
+            return;
+        }
+        let item = self.current_item();
+        if item.documentation.is_some()
+            || item.data.is_none()
+            || self.resolved.contains_key(&item.label)
+        {
+            return;
+        }
+        let request_id = self.request_id;
+        let widget_id = self.id;
+        proxy.completion_resolve(
+            self.buffer_id,
+            item.clone(),
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    if let Ok(item) = serde_json::from_value::<CompletionItem>(res) {
+                        event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateCompletionDoc(request_id, item),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Whether Enter accepts the selected item, with `accept_on_enter` being
+    /// "on", "off" or "smart": only if it changes what was typed.
+    pub fn accepts_enter(&self, accept_on_enter: &str) -> bool {
+        if self.status == CompletionStatus::Inactive || self.len() == 0 {
+            return false;
+        }
+        match accept_on_enter {
+            "off" => false,
+            "smart" => {
+                let item = self.current_item();
+                item.insert_text.as_ref().unwrap_or(&item.label) != &self.input
+            }
+            _ => true,
+        }
+    }
+
     pub fn request(
         &self,
         proxy: Arc<LapceProxy>,
@@ -353,6 +515,9 @@ impl CompletionData {
         self.status = CompletionStatus::Inactive;
         self.input = "".to_string();
         self.input_items.clear();
+        self.resolved.clear();
+        self.word_items = self.empty.clone();
+        self.items = self.empty.clone();
         self.index = 0;
     }
 
@@ -395,6 +560,7 @@ impl CompletionData {
     }
 
     pub fn filter_items(&mut self) {
+        self.combine_items();
         if self.input == "" {
             return;
         }
@@ -405,24 +571,27 @@ impl CompletionData {
             .filter_map(|i| {
                 let filter_text =
                     i.item.filter_text.as_ref().unwrap_or(&i.item.label);
-                let shift = i.item.label.match_indices(filter_text).next()?.0;
-                if let Some((score, mut indices)) =
-                    self.matcher.fuzzy_indices(filter_text, &self.input)
-                {
-                    if shift > 0 {
-                        indices = indices.iter().map(|i| i + shift).collect();
-                    }
-                    let mut item = i.clone();
-                    item.score = score;
-                    item.indices = indices;
-                    Some(item)
-                } else {
-                    None
-                }
+                let (score, indices) =
+                    self.matcher.fuzzy_indices(filter_text, &self.input)?;
+                // the highlighted characters are the ones of the label, which
+                // the filter text can differ from
+                let indices = match i.item.label.find(filter_text.as_str()) {
+                    Some(shift) => indices.iter().map(|i| i + shift).collect(),
+                    None => self
+                        .matcher
+                        .fuzzy_indices(&i.item.label, &self.input)
+                        .map(|(_, indices)| indices)
+                        .unwrap_or_default(),
+                };
+                let mut item = i.clone();
+                item.score = score;
+                item.indices = indices;
+                Some(item)
             })
             .collect();
-        items
-            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Less));
+        // stable, so the items matching as well keep their order, like the
+        // words after the items of the language server
+        items.sort_by(|a, b| b.score.cmp(&a.score));
         self.filtered_items = Arc::new(items);
     }
 }
@@ -435,6 +604,9 @@ pub struct CompletionContainer {
         LapceIdentityWrapper<LapceScrollNew<LapceTabData, CompletionNew>>,
     >,
     content_size: Size,
+    /// The detail and the documentation of the selected item, shown to the
+    /// side of the list.
+    documentation: Option<PietTextLayout>,
 }
 
 impl CompletionContainer {
@@ -448,9 +620,43 @@ impl CompletionContainer {
             completion: WidgetPod::new(completion),
             scroll_id: data.scroll_id,
             content_size: Size::ZERO,
+            documentation: None,
         }
     }
 
+    fn documentation_layout(
+        &self,
+        text: &mut PietText,
+        data: &LapceTabData,
+    ) -> Option<PietTextLayout> {
+        let (detail, documentation) = data.completion.current_documentation()?;
+        let content = if detail.is_empty() || documentation.is_empty() {
+            format!("{}{}", detail, documentation)
+        } else {
+            format!("{}\n\n{}", detail, documentation)
+        };
+        let layout = text
+            .new_text_layout(content)
+            .font(FontFamily::SYSTEM_UI, data.config.editor.font_size as f64)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                    .clone(),
+            )
+            .range_attribute(
+                0..detail.len(),
+                TextAttribute::TextColor(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                ),
+            )
+            .max_width(DOCUMENTATION_WIDTH - DOCUMENTATION_PADDING * 2.0)
+            .build()
+            .ok()?;
+        Some(layout)
+    }
+
     pub fn ensure_item_visble(
         &mut self,
         ctx: &mut UpdateCtx,
@@ -503,6 +709,18 @@ impl Widget<LapceTabData> for CompletionContainer {
                             input.to_owned(),
                             resp.to_owned(),
                         );
+                        data.completion.resolve_current(
+                            data.proxy.clone(),
+                            ctx.get_external_handle(),
+                        );
+                    }
+                    LapceUICommand::UpdateCompletionDoc(request_id, item) => {
+                        if data.completion.request_id == *request_id {
+                            let completion = Arc::make_mut(&mut data.completion);
+                            completion
+                                .resolved
+                                .insert(item.label.clone(), Arc::new(item.clone()));
+                        }
                     }
                     LapceUICommand::CancelCompletion(request_id) => {
                         if data.completion.request_id == *request_id {
@@ -584,7 +802,11 @@ impl Widget<LapceTabData> for CompletionContainer {
 
         if old_completion.index != completion.index {
             self.ensure_item_visble(ctx, data, env);
-            ctx.request_paint();
+            ctx.request_layout();
+        }
+
+        if !old_completion.resolved.same(&completion.resolved) {
+            ctx.request_layout();
         }
     }
 
@@ -595,12 +817,26 @@ impl Widget<LapceTabData> for CompletionContainer {
         data: &LapceTabData,
         env: &Env,
     ) -> Size {
-        let size = data.completion.size.clone();
+        let line_height = data.config.editor.line_height as f64;
+        let max_items = data.config.editor.completion_max_visible_items.max(1);
+        let size = Size::new(
+            data.completion.size.width,
+            (max_items as f64 * line_height).min(data.completion.size.height),
+        );
         let bc = BoxConstraints::new(Size::ZERO, size);
         self.content_size = self.completion.layout(ctx, &bc, data, env);
         self.completion.set_origin(ctx, data, env, Point::ZERO);
         ctx.set_paint_insets((10.0, 10.0, 10.0, 10.0));
-        size
+
+        self.documentation = self.documentation_layout(ctx.text(), data);
+        match self.documentation.as_ref() {
+            Some(layout) => {
+                let height = (layout.size().height + DOCUMENTATION_PADDING * 2.0)
+                    .min(data.completion.size.height);
+                Size::new(size.width + DOCUMENTATION_WIDTH, size.height.max(height))
+            }
+            None => size,
+        }
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
@@ -616,6 +852,33 @@ impl Widget<LapceTabData> for CompletionContainer {
                     .get_color_unchecked(LapceTheme::LAPCE_DROPDOWN_SHADOW),
             );
             self.completion.paint(ctx, data, env);
+
+            if let Some(layout) = self.documentation.as_ref() {
+                let height = (layout.size().height + DOCUMENTATION_PADDING * 2.0)
+                    .min(data.completion.size.height);
+                let rect = Size::new(DOCUMENTATION_WIDTH, height)
+                    .to_rect()
+                    .with_origin(Point::new(self.content_size.width, 0.0));
+                ctx.blurred_rect(
+                    rect,
+                    shadow_width,
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_DROPDOWN_SHADOW),
+                );
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::COMPLETION_BACKGROUND),
+                );
+                ctx.with_save(|ctx| {
+                    ctx.clip(rect);
+                    ctx.draw_text(
+                        layout,
+                        rect.origin()
+                            + (DOCUMENTATION_PADDING, DOCUMENTATION_PADDING),
+                    );
+                });
+            }
         }
     }
 }
@@ -891,4 +1154,96 @@ mod tests {
             parsed.tabs(0)
         );
     }
+
+    fn completion_data(
+        labels: &[(&str, Option<&str>)],
+        input: &str,
+    ) -> CompletionData {
+        let mut completion = CompletionData::new();
+        completion.status = CompletionStatus::Started;
+        let items = labels
+            .iter()
+            .map(|(label, filter_text)| ScoredCompletionItem {
+                item: CompletionItem {
+                    label: label.to_string(),
+                    filter_text: filter_text.map(|f| f.to_string()),
+                    ..Default::default()
+                },
+                score: 0,
+                index: 0,
+                indices: Vec::new(),
+            })
+            .collect();
+        completion
+            .input_items
+            .insert("".to_string(), Arc::new(items));
+        completion.update_input(input.to_string());
+        completion
+    }
+
+    #[test]
+    fn test_filter_highlights_label() {
+        let completion = completion_data(
+            &[
+                ("fn new() -> Self", Some("new")),
+                ("&mut self", Some("mut")),
+            ],
+            "nw",
+        );
+        let items = completion.current_items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].indices, vec![3, 5]);
+
+        let completion = completion_data(&[("&mut self", Some("mutable"))], "mt");
+        assert_eq!(completion.current_items()[0].indices, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_paging_and_enter() {
+        let labels: Vec<String> = (0..25).map(|i| format!("item{}", i)).collect();
+        let labels: Vec<(&str, Option<&str>)> =
+            labels.iter().map(|l| (l.as_str(), None)).collect();
+        let mut completion = completion_data(&labels, "");
+        completion.next_page(10);
+        assert_eq!(completion.index, 10);
+        completion.next_page(10);
+        completion.next_page(10);
+        assert_eq!(completion.index, 24);
+        completion.previous_page(10);
+        assert_eq!(completion.index, 14);
+
+        let completion = completion_data(&[("item", None)], "item");
+        assert!(completion.accepts_enter("on"));
+        assert!(!completion.accepts_enter("smart"));
+        assert!(!completion.accepts_enter("off"));
+    }
+
+    #[test]
+    fn test_word_items() {
+        let words = vec![
+            CompletionItem {
+                label: "items".to_string(),
+                ..Default::default()
+            },
+            CompletionItem {
+                label: "item_words".to_string(),
+                ..Default::default()
+            },
+        ];
+        let mut completion = completion_data(&[], "it");
+        completion.set_word_items(words.clone(), false);
+        assert_eq!(completion.len(), 2);
+
+        // the language server's items win unless they're merged
+        let mut completion = completion_data(&[("items", None)], "it");
+        completion.set_word_items(words.clone(), false);
+        assert_eq!(completion.len(), 1);
+        completion.set_word_items(words, true);
+        let labels: Vec<&str> = completion
+            .current_items()
+            .iter()
+            .map(|i| i.item.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["items", "item_words"]);
+    }
 }
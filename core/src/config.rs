@@ -1,4 +1,9 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
 use directories::ProjectDirs;
@@ -7,12 +12,14 @@ use druid::{
     theme, Color, Env, ExtEventSink, FontDescriptor, FontFamily, Key, Size, Target,
 };
 use hashbrown::HashMap;
+use lapce_proxy::format::FormatCommand;
 use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
     data::hex_to_color,
-    state::{LapceWorkspace, LapceWorkspaceType},
+    format::language_of,
+    state::{LapceWorkspace, LapceWorkspaceType, SshHost},
 };
 
 const default_settings: &'static str = include_str!("../../defaults/settings.toml");
@@ -21,6 +28,11 @@ const default_light_theme: &'static str =
 const default_dark_theme: &'static str =
     include_str!("../../defaults/dark-theme.toml");
 pub const LOGO: &'static str = include_str!("../../extra/images/logo.svg");
+/// How many SSH hosts the host picker remembers.
+const MAX_RECENT_SSH_HOSTS: usize = 10;
+/// `ui.animations`, for the widgets that only get the env, like the scroll
+/// bars.
+pub const UI_ANIMATIONS: Key<bool> = Key::new("lapce.ui.animations");
 
 pub struct LapceTheme {}
 
@@ -40,6 +52,10 @@ impl LapceTheme {
     pub const EDITOR_CARET: &'static str = "editor.caret";
     pub const EDITOR_SELECTION: &'static str = "editor.selection";
     pub const EDITOR_CURRENT_LINE: &'static str = "editor.current_line";
+    pub const EDITOR_TODO: &'static str = "editor.todo";
+    pub const EDITOR_INVISIBLE: &'static str = "editor.invisible";
+    pub const EDITOR_RULER: &'static str = "editor.ruler";
+    pub const EDITOR_LAST_RULER: &'static str = "editor.last_ruler";
 
     pub const SOURCE_CONTROL_ADDED: &'static str = "source_control.added";
     pub const SOURCE_CONTROL_REMOVED: &'static str = "source_control.removed";
@@ -88,6 +104,150 @@ pub struct LapceConfig {
     pub modal: bool,
     pub color_theme: String,
     pub icon_theme: String,
+    pub resolve_shell_env: bool,
+}
+
+/// The default log level, and the levels of specific modules, keyed by their
+/// path like `lapce_core::proxy`.
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct LogConfig {
+    pub level: String,
+    #[serde(default)]
+    pub modules: std::collections::HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct FilesConfig {
+    /// Patterns in gitignore syntax of the files hidden from the explorer,
+    /// the file palette and the searches.
+    pub exclude: Vec<String>,
+    /// Past this many files, opening a workspace asks what to index. 0 never
+    /// asks.
+    pub large_workspace_threshold: usize,
+    /// Files bigger than this many bytes aren't previewed by the file
+    /// palette. 0 previews any file.
+    pub large_file_threshold: u64,
+    /// For how many days the deleted files can be restored.
+    pub trash_retention_days: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct SearchConfig {
+    /// Whether saving a file with results of the workspace search finds its
+    /// matches again, rather than marking them stale.
+    pub auto_refresh: bool,
+    /// Whether the selection put in the search input when the search panel
+    /// opens is searched right away, rather than once it's edited or Enter
+    /// is pressed.
+    pub search_on_prefill: bool,
+    /// How many lines around each match the results show, once context is
+    /// toggled on in the search panel.
+    pub context_lines: usize,
+    /// Whether clicking a result previews it in an editor the next click
+    /// reuses, until the result is double clicked or edited. Off, a click
+    /// opens it in the active editor.
+    pub preview_results: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct StartupConfig {
+    /// What a new window opens: "last-session" the tabs it had when it was
+    /// closed, "last-workspace" only the workspace of its active tab, or
+    /// "empty" no workspace.
+    pub behavior: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct TasksConfig {
+    /// Where the terminal of a task opens: "panel-split" beside the active
+    /// terminal, "panel-tab" in a new terminal tab, or "editor-area" in a
+    /// new tab of the terminal panel maximized over the editors.
+    pub output_placement: String,
+}
+
+/// A command the "Run Task" palette runs in a terminal, from
+/// `[task.<name>]`, like `command = "cargo test"` for `[task.test]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskConfig {
+    pub command: String,
+    /// The folder it runs in, the workspace one if it's relative or not set.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Whether its terminal closes once it exits with 0, focusing the one
+    /// that was before it.
+    #[serde(default)]
+    pub auto_close: bool,
+}
+
+/// The command line tool formatting the files of a language, from
+/// `[format.<language>]`, like `command = "rustfmt"` for `[format.rust]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FormatterConfig {
+    pub command: String,
+    /// Its arguments, where `${file}` is the path of the file.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Whether the text goes through the standard input and output of the
+    /// command, rather than a temporary file whose path ends its arguments.
+    #[serde(default = "FormatterConfig::default_stdin")]
+    pub stdin: bool,
+    /// How many milliseconds the command gets before it's killed.
+    #[serde(default = "FormatterConfig::default_timeout")]
+    pub timeout: u64,
+    /// Whether the language server formats the files instead when it can.
+    #[serde(default = "FormatterConfig::default_prefer_lsp")]
+    pub prefer_lsp: bool,
+}
+
+impl FormatterConfig {
+    fn default_stdin() -> bool {
+        true
+    }
+
+    fn default_timeout() -> u64 {
+        5000
+    }
+
+    fn default_prefer_lsp() -> bool {
+        true
+    }
+
+    /// The command as the proxy runs it.
+    pub fn command(&self) -> FormatCommand {
+        FormatCommand {
+            command: self.command.clone(),
+            args: self.args.clone(),
+            stdin: self.stdin,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct UIConfig {
+    /// Whether the UI animates: the cursor fade, the fading scroll bars and
+    /// the spinner of a connecting remote. Off, they change at once.
+    pub animations: bool,
+    /// The width or height, along their split, editors don't shrink below.
+    /// A split that would make them smaller is refused.
+    pub editor_min_size: usize,
+    /// The same for terminals.
+    pub terminal_min_size: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct WindowConfig {
+    /// Whether the title bar and the activity bar are hidden in fullscreen.
+    pub fullscreen_hides_chrome: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -96,18 +256,123 @@ pub struct EditorConfig {
     pub font_family: String,
     pub font_size: usize,
     pub line_height: usize,
+    pub todo_tokens: Vec<String>,
+    pub sanitize_paste: String,
+    /// How the lines of the cursors stand out: "line" fills their background,
+    /// "gutter" highlights their number, "both" or "none".
+    pub current_line: String,
+    /// Whether the undo history of files is kept across sessions.
+    pub persistent_undo: bool,
+    /// How many bytes of undo history are kept per file.
+    pub persistent_undo_size: usize,
+    /// The columns to draw ruler lines at.
+    pub rulers: Vec<usize>,
+    /// Rulers replacing `rulers` for files with the extension, e.g.
+    /// `{ rs = [100], py = [79] }`.
+    #[serde(default)]
+    pub language_rulers: std::collections::HashMap<String, Vec<usize>>,
+    /// Whether the `.editorconfig` properties of a file win over the
+    /// settings they conflict with, or the other way around.
+    pub prefer_editorconfig: bool,
+    /// Whether Enter accepts the selected completion: "on", "off", or
+    /// "smart" for only when it changes what was typed.
+    pub accept_suggestion_on_enter: String,
+    /// Whether Tab accepts the selected completion.
+    pub accept_suggestion_on_tab: bool,
+    /// How many completion items are shown before the list scrolls.
+    pub completion_max_visible_items: usize,
+    /// Whether the words of the open files are completed: "fallback" for
+    /// when no language server gives any item, "merge" to add them to its
+    /// items, or "off".
+    pub word_completion: String,
+    /// The extensions of the files whose Emmet abbreviations expand to
+    /// HTML, by Tab or the `expand_abbreviation` command.
+    pub emmet_html: Vec<String>,
+    /// The extensions of the files whose Emmet abbreviations expand to CSS.
+    pub emmet_css: Vec<String>,
+    /// Whether renaming an HTML, XML or JSX tag renames its pair too.
+    pub linked_tag_editing: bool,
+    /// Whether a workspace edit of a single file, e.g. from a code action, is
+    /// previewed before it's applied, like the ones of several files.
+    pub preview_single_file_edits: bool,
+    /// Whether the path of the file and the symbols at the cursor show above
+    /// the text of the editors.
+    pub show_breadcrumbs: bool,
+    /// How the cursor of the focused editor blinks: "on", "off", or
+    /// "smooth" to fade it in and out.
+    pub cursor_blink: String,
+    /// How many milliseconds the cursor stays shown, and then hidden. It
+    /// doesn't blink while typing, until this long after the last key.
+    pub cursor_blink_interval: u64,
+    /// Set by the `toggle_rulers` command, and reset when the config is
+    /// reloaded.
+    #[serde(skip)]
+    pub rulers_hidden: bool,
 }
 
 impl EditorConfig {
     pub fn font_family(&self) -> FontFamily {
         FontFamily::new_unchecked(self.font_family.clone())
     }
+
+    pub fn highlight_current_line(&self) -> bool {
+        matches!(self.current_line.as_str(), "line" | "both")
+    }
+
+    pub fn highlight_current_line_number(&self) -> bool {
+        matches!(self.current_line.as_str(), "gutter" | "both")
+    }
+
+    /// The ruler columns for the file at `path`, whose `.editorconfig` sets
+    /// `max_line_length`.
+    pub fn rulers_for(
+        &self,
+        path: &Path,
+        max_line_length: Option<usize>,
+    ) -> Vec<usize> {
+        if self.rulers_hidden {
+            return Vec::new();
+        }
+        let rulers = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.language_rulers.get(ext))
+            .unwrap_or(&self.rulers);
+        match max_line_length {
+            Some(max) if self.prefer_editorconfig || rulers.is_empty() => {
+                vec![max]
+            }
+            _ => rulers.clone(),
+        }
+    }
+
+    /// How long each phase of the cursor blink lasts, none if it doesn't
+    /// blink.
+    pub fn cursor_blink_interval(&self) -> Option<Duration> {
+        if self.cursor_blink == "off" || self.cursor_blink_interval == 0 {
+            return None;
+        }
+        Some(Duration::from_millis(self.cursor_blink_interval))
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct Config {
     pub lapce: LapceConfig,
     pub editor: EditorConfig,
+    pub window: WindowConfig,
+    pub ui: UIConfig,
+    pub startup: StartupConfig,
+    pub files: FilesConfig,
+    pub search: SearchConfig,
+    pub tasks: TasksConfig,
+    pub log: LogConfig,
+    /// The formatter commands, by the language of the files they format.
+    #[serde(default)]
+    pub format: std::collections::HashMap<String, FormatterConfig>,
+    /// The tasks, by their name.
+    #[serde(default)]
+    pub task: std::collections::HashMap<String, TaskConfig>,
     #[serde(skip)]
     pub theme: HashMap<String, Color>,
     #[serde(skip)]
@@ -162,7 +427,7 @@ impl Config {
                         .merge(config::File::from(path.as_path()).required(false));
                 }
             }
-            crate::state::LapceWorkspaceType::RemoteSSH(_, _) => {}
+            crate::state::LapceWorkspaceType::RemoteSSH(_) => {}
         }
 
         let mut config: Config = settings.try_into()?;
@@ -177,6 +442,11 @@ impl Config {
         Ok(config)
     }
 
+    /// The formatter command of the language of the file at `path`.
+    pub fn formatter_for(&self, path: &Path) -> Option<&FormatterConfig> {
+        self.format.get(&language_of(path)?)
+    }
+
     pub fn settings_file() -> Option<PathBuf> {
         ProjectDirs::from("", "", "Lapce")
             .map(|d| d.config_dir().join("settings.toml"))
@@ -254,6 +524,19 @@ impl Config {
         text_layout.size()
     }
 
+    /// The config of an editor zoomed by `zoom`, with its font and lines
+    /// scaled. It's a copy, so one is only made for a zoomed editor.
+    pub fn zoomed(config: &Arc<Config>, zoom: f64) -> Arc<Config> {
+        if zoom == 1.0 {
+            return config.clone();
+        }
+        let scale = |size: usize| ((size as f64 * zoom).round() as usize).max(1);
+        let mut zoomed = (**config).clone();
+        zoomed.editor.font_size = scale(config.editor.font_size);
+        zoomed.editor.line_height = scale(config.editor.line_height);
+        Arc::new(zoomed)
+    }
+
     pub fn reload_env(&self, env: &mut Env) {
         env.set(theme::SCROLLBAR_RADIUS, 0.0);
         env.set(theme::SCROLLBAR_EDGE_WIDTH, 0.0);
@@ -317,8 +600,8 @@ impl Config {
                     "kind".to_string(),
                     toml::Value::String(match workspace.kind {
                         LapceWorkspaceType::Local => "local".to_string(),
-                        LapceWorkspaceType::RemoteSSH(user, host) => {
-                            format!("ssh://{}@{}", user, host)
+                        LapceWorkspaceType::RemoteSSH(host) => {
+                            format!("ssh://{}", host)
                         }
                     }),
                 );
@@ -360,10 +643,7 @@ impl Config {
                     let kind = value.get("kind")?.as_str()?;
                     let kind = match kind {
                         s if kind.starts_with("ssh://") => {
-                            let mut parts = s[6..].split("@");
-                            let user = parts.next()?.to_string();
-                            let host = parts.next()?.to_string();
-                            LapceWorkspaceType::RemoteSSH(user, host)
+                            LapceWorkspaceType::RemoteSSH(SshHost::parse(&s[6..])?)
                         }
                         _ => LapceWorkspaceType::Local,
                     };
@@ -382,6 +662,51 @@ impl Config {
         )
     }
 
+    /// The SSH hosts connected to recently, the latest first.
+    pub fn recent_ssh_hosts() -> Vec<SshHost> {
+        Self::recent_ssh_hosts_file()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| toml::from_str::<toml::Value>(&content).ok())
+            .and_then(|value| {
+                Some(
+                    value
+                        .get("hosts")?
+                        .as_array()?
+                        .iter()
+                        .filter_map(|host| SshHost::parse(host.as_str()?))
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Puts the host first in the recent SSH hosts.
+    pub fn add_recent_ssh_host(host: &SshHost) -> Option<()> {
+        let mut hosts = Self::recent_ssh_hosts();
+        hosts.retain(|h| h != host);
+        hosts.insert(0, host.clone());
+        hosts.truncate(MAX_RECENT_SSH_HOSTS);
+
+        let mut table = toml::value::Table::new();
+        table.insert(
+            "hosts".to_string(),
+            toml::Value::Array(
+                hosts
+                    .iter()
+                    .map(|host| toml::Value::String(host.to_string()))
+                    .collect(),
+            ),
+        );
+        let content = toml::to_string(&table).ok()?;
+        std::fs::write(Self::recent_ssh_hosts_file()?, content).ok()
+    }
+
+    fn recent_ssh_hosts_file() -> Option<PathBuf> {
+        let proj_dirs = ProjectDirs::from("", "", "Lapce")?;
+        std::fs::create_dir_all(proj_dirs.config_dir()).ok()?;
+        Some(proj_dirs.config_dir().join("ssh_hosts.toml"))
+    }
+
     pub fn recent_workspaces_file() -> Option<PathBuf> {
         let proj_dirs = ProjectDirs::from("", "", "Lapce")?;
         std::fs::create_dir_all(proj_dirs.config_dir());
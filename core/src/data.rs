@@ -1,19 +1,22 @@
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
     io::{BufReader, Read},
-    path::PathBuf,
+    ops::Range,
+    path::{Path, PathBuf},
     process::{self, Stdio},
     rc::Rc,
     str::FromStr,
     sync::{atomic::AtomicU64, Arc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
-use crossbeam_channel::{bounded, unbounded, Receiver, Sender, TryRecvError};
+use crossbeam_channel::{
+    bounded, unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError,
+};
 use crossbeam_utils::sync::WaitGroup;
 use directories::{ProjectDirs, UserDirs};
 use druid::{
@@ -26,7 +29,15 @@ use druid::{
 };
 use im::{self, hashmap};
 use itertools::Itertools;
-use lapce_proxy::{dispatch::FileDiff, plugin::PluginDescription, terminal::TermId};
+use lapce_proxy::{
+    buffer::SaveConflict,
+    dispatch::{FileDiff, GitFileCommand, GitRemoteCommand},
+    plugin::PluginDescription,
+    protocol::CAPABILITY_WORKSPACE_EDIT,
+    terminal::TermId,
+};
+use lapce_rpc::Callback;
+use log::LevelFilter;
 use lsp_types::{
     CodeActionOrCommand, CodeActionResponse, CompletionItem, CompletionResponse,
     CompletionTextEdit, Diagnostic, DiagnosticSeverity, GotoDefinitionResponse,
@@ -45,11 +56,12 @@ use xi_rope::{
 };
 
 use crate::{
+    breadcrumbs::BreadcrumbTarget,
     buffer::{
         get_word_property, has_unmatched_pair, matching_char,
         matching_pair_direction, previous_has_unmatched_pair, BufferContent,
-        BufferId, BufferNew, BufferState, BufferUpdate, EditType, LocalBufferKind,
-        Style, UpdateEvent, WordProperty,
+        BufferId, BufferNew, BufferState, BufferUpdate, EditType, HistoryDiff,
+        LocalBufferKind, Style, UpdateEvent, WordProperty,
     },
     command::{
         CommandTarget, EnsureVisiblePosition, LapceCommand, LapceCommandNew,
@@ -57,30 +69,66 @@ use crate::{
         LAPCE_UI_COMMAND,
     },
     completion::{CompletionData, CompletionStatus, Snippet},
-    config::{Config, ConfigWatcher, GetConfig, LapceTheme},
+    config::{Config, ConfigWatcher, GetConfig, LapceTheme, UI_ANIMATIONS},
     db::{LapceDb, WorkspaceInfo},
     editor::{EditorLocationNew, LapceEditorBufferData},
     explorer::FileExplorerData,
     find::Find,
+    focus::FocusHistory,
+    format::{text_edits, Formatting},
+    gutter::DiffHunk,
+    history::WorkspaceHistory,
     keypress::{KeyPressData, KeyPressFocus},
     language::{new_highlight_config, new_parser, LapceLanguage, SCOPES},
+    logging,
     menu::MenuData,
     movement::{
         Cursor, CursorMode, InsertDrift, LinePosition, Movement, SelRegion,
         Selection,
     },
+    narrow::{region_edit, NarrowRegion},
     palette::{PaletteData, PaletteType, PaletteViewData},
-    panel::PanelPosition,
+    panel::{EditorsPanelData, PanelPosition},
     plugin::PluginData,
     problem::ProblemData,
-    proxy::{LapceProxy, ProxyHandlerNew, TermEvent},
-    search::SearchData,
+    profile::Profile,
+    proxy::{LapceProxy, ProxyBackend, ProxyHandlerNew, ProxyStatus, TermEvent},
+    recovery::{self, SessionRecovery},
+    sanitize::PastedText,
+    scratch::{is_scratch, list_scratches, new_scratch},
+    search::{
+        find_context, find_matches, include_pattern, relative_path, search_prefill,
+        search_results, SearchData,
+    },
     source_control::{SourceControlData, SEARCH_BUFFER, SOURCE_CONTROL_BUFFER},
+    split::{SplitDirection, SplitMoveDirection},
     state::{LapceWorkspace, LapceWorkspaceType, Mode, VisualMode},
     svg::get_svg,
-    terminal::TerminalSplitData,
+    terminal::{TaskPlacement, TerminalSplitData, TerminalTask},
+    title::TitleMenu,
+    workspace_edit::{apply_edits, WorkspaceEditData},
 };
 
+/// How many of the recent log lines the log view shows.
+const LOG_VIEW_LINES: usize = 2000;
+
+/// How long the text has to stay the same before the unsaved text is backed
+/// up, and a scratch saved.
+const BACKUP_DELAY: Duration = Duration::from_secs(2);
+/// How long the text has to stay the same before it's diffed again with
+/// the head, so typing doesn't diff the whole file on every key.
+const HISTORY_DIFF_DELAY: Duration = Duration::from_millis(200);
+
+/// How much a step of zoom scales an editor, and how far it goes.
+const EDITOR_ZOOM_STEP: f64 = 0.1;
+const MIN_EDITOR_ZOOM: f64 = 0.5;
+const MAX_EDITOR_ZOOM: f64 = 3.0;
+
+/// How many closed editors can be reopened.
+const CLOSED_EDITORS_LEN: usize = 20;
+/// How many files an editor remembers showing.
+const SHOWN_FILES_LEN: usize = 30;
+
 #[derive(Clone, Data)]
 pub struct LapceData {
     pub windows: im::HashMap<WindowId, LapceWindowData>,
@@ -89,6 +137,10 @@ pub struct LapceData {
 
 impl LapceData {
     pub fn load(event_sink: ExtEventSink) -> Self {
+        let config = Config::load(&LapceWorkspace::default()).ok();
+        logging::init(&config.as_ref().map(|c| c.log.clone()).unwrap_or_default());
+        logging::set_event_sink(event_sink.clone());
+
         let mut windows = im::HashMap::new();
         let keypress = Arc::new(KeyPressData::new());
         let window = LapceWindowData::new(keypress.clone(), event_sink.clone());
@@ -109,6 +161,7 @@ impl LapceData {
     pub fn reload_env(&self, env: &mut Env) {
         env.set(theme::SCROLLBAR_WIDTH, 10.0);
         env.set(theme::SCROLLBAR_MAX_OPACITY, 0.7);
+        env.set(UI_ANIMATIONS, true);
     }
 
     fn load_plugin_descriptions() -> Result<Vec<PluginDescription>> {
@@ -145,6 +198,7 @@ pub struct LapceWindowData {
     pub db: Arc<LapceDb>,
     pub watcher: Arc<notify::RecommendedWatcher>,
     pub menu: Arc<MenuData>,
+    pub fullscreen: bool,
 }
 
 impl Data for LapceWindowData {
@@ -152,6 +206,7 @@ impl Data for LapceWindowData {
         self.active == other.active
             && self.tabs.same(&other.tabs)
             && self.menu.same(&other.menu)
+            && self.fullscreen == other.fullscreen
     }
 }
 
@@ -163,7 +218,26 @@ impl LapceWindowData {
         let mut active_tab_id = WidgetId::next();
         let mut active = 0;
 
-        if let Ok(info) = db.get_tabs_info() {
+        let config = Arc::new(
+            Config::load(&LapceWorkspace {
+                kind: LapceWorkspaceType::Local,
+                path: None,
+                last_open: 0,
+            })
+            .unwrap_or_default(),
+        );
+
+        // after a crash, what to restore is asked for instead
+        let recovery = if recovery::start_session() {
+            db.get_tabs_info().ok().and_then(|tabs| {
+                SessionRecovery::load(tabs, |w| db.get_workspace_info(w).ok())
+            })
+        } else {
+            None
+        };
+
+        if let Some(info) = db.get_tabs_info().ok().filter(|_| recovery.is_none()) {
+            let info = info.for_startup(&config.startup.behavior);
             for (i, workspace) in info.workspaces.iter().enumerate() {
                 let tab_id = WidgetId::next();
                 let tab = LapceTabData::new(
@@ -196,19 +270,20 @@ impl LapceWindowData {
             active_tab_id = tab_id;
         }
 
-        let config = Arc::new(
-            Config::load(&LapceWorkspace {
-                kind: LapceWorkspaceType::Local,
-                path: None,
-                last_open: 0,
-            })
-            .unwrap_or_default(),
-        );
         event_sink.submit_command(
             LAPCE_UI_COMMAND,
             LapceUICommand::Focus,
             Target::Widget(active_tab_id),
         );
+        if let Some(recovery) = recovery {
+            let tab = tabs.get_mut(&active_tab_id).unwrap();
+            Arc::make_mut(&mut tab.palette).recovery = Some(Arc::new(recovery));
+            event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::RunPalette(Some(PaletteType::Recovery)),
+                Target::Widget(tab.palette.widget_id),
+            );
+        }
 
         let mut watcher =
             notify::recommended_watcher(ConfigWatcher::new(event_sink.clone()))
@@ -232,8 +307,27 @@ impl LapceWindowData {
             db,
             watcher: Arc::new(watcher),
             menu: Arc::new(menu),
+            fullscreen: false,
         }
     }
+
+    /// Whether the title bar and the activity bars are hidden, which they
+    /// are in fullscreen unless the config keeps them.
+    pub fn chrome_hidden(&self) -> bool {
+        self.fullscreen && self.config.window.fullscreen_hides_chrome
+    }
+
+    /// Tells the tabs whether to hide their activity bar.
+    pub fn update_chrome(&mut self) {
+        let hidden = self.chrome_hidden();
+        for (_, tab) in self.tabs.iter_mut() {
+            tab.chrome_hidden = hidden;
+        }
+    }
+
+    pub fn reload_env(&self, env: &mut Env) {
+        env.set(UI_ANIMATIONS, self.config.ui.animations);
+    }
 }
 
 #[derive(Clone)]
@@ -250,6 +344,7 @@ pub enum PanelKind {
     Terminal,
     Search,
     Problem,
+    Editors,
 }
 
 impl PanelKind {
@@ -261,6 +356,7 @@ impl PanelKind {
             PanelKind::Terminal => "terminal.svg".to_string(),
             PanelKind::Search => "search.svg".to_string(),
             PanelKind::Problem => "error.svg".to_string(),
+            PanelKind::Editors => "split-horizontal.svg".to_string(),
         }
     }
 
@@ -323,7 +419,9 @@ pub struct LapceTabData {
     pub find: Arc<Find>,
     pub source_control: Arc<SourceControlData>,
     pub problem: Arc<ProblemData>,
+    pub editors_panel: Arc<EditorsPanelData>,
     pub search: Arc<SearchData>,
+    pub workspace_edit: Arc<WorkspaceEditData>,
     pub plugin: Arc<PluginData>,
     pub plugins: Arc<Vec<PluginDescription>>,
     pub installed_plugins: Arc<HashMap<String, PluginDescription>>,
@@ -342,7 +440,12 @@ pub struct LapceTabData {
     pub focus: WidgetId,
     pub focus_area: FocusArea,
     pub db: Arc<LapceDb>,
+    /// The recent files, searches and palette picks of the workspace.
+    pub history: Arc<WorkspaceHistory>,
     pub progresses: im::Vector<WorkProgress>,
+    /// Whether the window is in fullscreen with its chrome hidden.
+    pub chrome_hidden: bool,
+    pub proxy_status: ProxyStatus,
 }
 
 impl Data for LapceTabData {
@@ -365,8 +468,12 @@ impl Data for LapceTabData {
             && self.file_explorer.same(&other.file_explorer)
             && self.plugin.same(&other.plugin)
             && self.problem.same(&other.problem)
+            && self.editors_panel.same(&other.editors_panel)
             && self.search.same(&other.search)
+            && self.workspace_edit.same(&other.workspace_edit)
             && self.installed_plugins.same(&other.installed_plugins)
+            && self.chrome_hidden == other.chrome_hidden
+            && self.proxy_status == other.proxy_status
     }
 }
 
@@ -383,21 +490,75 @@ impl LapceTabData {
         db: Arc<LapceDb>,
         keypress: Arc<KeyPressData>,
         event_sink: ExtEventSink,
+    ) -> Self {
+        Self::new_with_backend(tab_id, workspace, db, keypress, event_sink, None)
+    }
+
+    /// Like `new`, with the requests to the proxy going to `backend` instead
+    /// of a proxy process when one is given, like a `MockProxy` in tests.
+    pub fn new_with_backend(
+        tab_id: WidgetId,
+        workspace: LapceWorkspace,
+        db: Arc<LapceDb>,
+        keypress: Arc<KeyPressData>,
+        event_sink: ExtEventSink,
+        backend: Option<Arc<dyn ProxyBackend>>,
     ) -> Self {
         let config = Arc::new(Config::load(&workspace).unwrap_or_default());
 
         let workspace_info = db.get_workspace_info(&workspace).ok();
+        let history = Arc::new(WorkspaceHistory::load(&workspace));
 
         let (update_sender, update_receiver) = unbounded();
         let update_sender = Arc::new(update_sender);
         let (term_sender, term_receiver) = unbounded();
-        let proxy = Arc::new(LapceProxy::new(
-            tab_id,
-            workspace.clone(),
-            term_sender.clone(),
-            event_sink.clone(),
-        ));
-        let palette = Arc::new(PaletteData::new(proxy.clone()));
+        let proxy = Arc::new(match backend {
+            Some(backend) => LapceProxy::with_backend(
+                tab_id,
+                backend,
+                term_sender.clone(),
+                event_sink.clone(),
+            ),
+            None => LapceProxy::new(
+                tab_id,
+                workspace.clone(),
+                term_sender.clone(),
+                event_sink.clone(),
+            ),
+        });
+        // before the explorer reads the workspace
+        proxy.set_file_excludes(&config.files.exclude);
+        let mut palette = PaletteData::new(proxy.clone());
+        palette.index_scope = workspace_info
+            .as_ref()
+            .and_then(|info| info.index_scope.clone());
+        let threshold = config.files.large_workspace_threshold;
+        if palette.index_scope.is_none() && threshold > 0 && workspace.path.is_some()
+        {
+            let event_sink = event_sink.clone();
+            proxy.count_files(
+                threshold,
+                Box::new(move |result| {
+                    if let Ok(res) = result {
+                        let complete = res
+                            .get("complete")
+                            .and_then(|c| c.as_bool())
+                            .unwrap_or(true);
+                        let count =
+                            res.get("count").and_then(|c| c.as_u64()).unwrap_or(0)
+                                as usize;
+                        if !complete {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::LargeWorkspace(count),
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                }),
+            );
+        }
+        let palette = Arc::new(palette);
         let completion = Arc::new(CompletionData::new());
         let source_control = Arc::new(SourceControlData::new());
         let plugin = Arc::new(PluginData::new());
@@ -407,10 +568,17 @@ impl LapceTabData {
             proxy.clone(),
             event_sink.clone(),
         ));
-        let search = Arc::new(SearchData::new());
+        let mut search = SearchData::new();
+        if let Some(info) = workspace_info.as_ref() {
+            search.options = info.search_options;
+            search.show_context = info.search_context;
+            search.pinned = Arc::new(info.pinned_searches.clone());
+        }
+        let search = Arc::new(search);
 
         let mut main_split = LapceMainSplitData::new(
             tab_id,
+            &workspace,
             workspace_info.as_ref(),
             palette.preview_editor,
             update_sender.clone(),
@@ -432,9 +600,24 @@ impl LapceTabData {
             &config,
             event_sink.clone(),
         );
+        main_split.add_editor(
+            search.include_editor_view_id,
+            None,
+            LocalBufferKind::SearchInclude,
+            &config,
+            event_sink.clone(),
+        );
+        main_split.add_editor(
+            search.replace_editor_view_id,
+            None,
+            LocalBufferKind::SearchReplace,
+            &config,
+            event_sink.clone(),
+        );
 
         let terminal = Arc::new(TerminalSplitData::new(proxy.clone()));
         let problem = Arc::new(ProblemData::new());
+        let editors_panel = Arc::new(EditorsPanelData::new());
 
         let mut panels = im::HashMap::new();
         panels.insert(
@@ -464,6 +647,7 @@ impl LapceTabData {
             }),
         );
         let focus = (*main_split.active).unwrap_or(*main_split.split_id);
+        let proxy_status = proxy.status();
         let mut tab = Self {
             id: tab_id,
             workspace: Arc::new(workspace),
@@ -473,7 +657,9 @@ impl LapceTabData {
             terminal,
             plugin,
             problem,
+            editors_panel,
             search,
+            workspace_edit: Arc::new(WorkspaceEditData::new(tab_id)),
             plugins: Arc::new(Vec::new()),
             installed_plugins: Arc::new(HashMap::new()),
             find: Arc::new(Find::new(0)),
@@ -500,7 +686,10 @@ impl LapceTabData {
             config,
             focus_area: FocusArea::Editor,
             db,
+            history,
             progresses: im::Vector::new(),
+            chrome_hidden: false,
+            proxy_status,
         };
         tab.start_update_process(event_sink);
         tab
@@ -509,14 +698,16 @@ impl LapceTabData {
     pub fn start_update_process(&mut self, event_sink: ExtEventSink) {
         if let Some(receiver) = self.update_receiver.take() {
             let tab_id = self.id;
+            let workspace = self.workspace.clone();
             let local_event_sink = event_sink.clone();
             thread::spawn(move || {
                 LapceTabData::buffer_update_process(
                     tab_id,
+                    workspace,
                     receiver,
                     local_event_sink,
                 );
-                println!("buffer update process stopped");
+                log::info!("buffer update process stopped");
             });
         }
 
@@ -535,7 +726,7 @@ impl LapceTabData {
                     workspace,
                     proxy,
                 );
-                println!("terminal update process stopped");
+                log::info!("terminal update process stopped");
             });
         }
 
@@ -543,7 +734,7 @@ impl LapceTabData {
             let widget_id = self.palette.widget_id;
             thread::spawn(move || {
                 PaletteViewData::update_process(receiver, widget_id, event_sink);
-                println!("palette update process stopped");
+                log::info!("palette update process stopped");
             });
         }
     }
@@ -570,8 +761,9 @@ impl LapceTabData {
             find: self.find.clone(),
             buffer,
             editor: editor.clone(),
-            config: self.config.clone(),
+            config: Config::zoomed(&self.config, editor.zoom),
             workspace: self.workspace.clone(),
+            db: self.db.clone(),
         }
     }
 
@@ -672,12 +864,13 @@ impl LapceTabData {
         tab_size: Size,
         config: &Config,
     ) -> Point {
-        let line_height = self.config.editor.line_height as f64;
         let editor = self.main_split.active_editor();
         let editor = match editor {
             Some(editor) => editor,
             None => return Point::ZERO,
         };
+        let zoomed = Config::zoomed(&self.config, editor.zoom);
+        let line_height = zoomed.editor.line_height as f64;
 
         match &editor.content {
             BufferContent::Local(_) => {
@@ -687,7 +880,7 @@ impl LapceTabData {
                 let buffer = self.main_split.open_files.get(path).unwrap();
                 let offset = editor.cursor.offset();
                 let (line, col) = buffer.offset_to_line_col(offset);
-                let width = config.editor_text_width(text, "W");
+                let width = zoomed.editor_text_width(text, "W");
                 let x = col as f64 * width;
                 let y = (line + 1) as f64 * line_height;
                 let origin = editor.window_origin - self.window_origin.to_vec2()
@@ -703,13 +896,13 @@ impl LapceTabData {
         tab_size: Size,
         config: &Config,
     ) -> Point {
-        let line_height = self.config.editor.line_height as f64;
-
         let editor = self.main_split.active_editor();
         let editor = match editor {
             Some(editor) => editor,
             None => return Point::ZERO,
         };
+        let zoomed = Config::zoomed(&self.config, editor.zoom);
+        let line_height = zoomed.editor.line_height as f64;
 
         match &editor.content {
             BufferContent::Local(_) => {
@@ -719,17 +912,18 @@ impl LapceTabData {
                 let buffer = self.main_split.open_files.get(path).unwrap();
                 let offset = self.completion.offset;
                 let (line, col) = buffer.offset_to_line_col(offset);
-                let width = config.editor_text_width(text, "W");
+                let width = zoomed.editor_text_width(text, "W");
                 let x = col as f64 * width - line_height - 5.0;
                 let y = (line + 1) as f64 * line_height;
                 let mut origin = editor.window_origin - self.window_origin.to_vec2()
                     + Vec2::new(x, y);
-                if origin.y + self.completion.size.height + 1.0 > tab_size.height {
-                    let height = self
-                        .completion
-                        .size
-                        .height
-                        .min(self.completion.len() as f64 * line_height);
+                let max_height = self.completion.size.height.min(
+                    config.editor.completion_max_visible_items.max(1) as f64
+                        * line_height,
+                );
+                if origin.y + max_height + 1.0 > tab_size.height {
+                    let height =
+                        max_height.min(self.completion.len() as f64 * line_height);
                     origin.y = editor.window_origin.y - self.window_origin.y
                         + line as f64 * line_height
                         - height;
@@ -756,7 +950,163 @@ impl LapceTabData {
             find: self.find.clone(),
             focus_area: self.focus_area.clone(),
             terminal: self.terminal.clone(),
+            history: self.history.clone(),
+            db: self.db.clone(),
+        }
+    }
+
+    /// Changes the history of the workspace and saves it.
+    pub fn update_history(&mut self, f: impl FnOnce(&mut WorkspaceHistory)) {
+        f(Arc::make_mut(&mut self.history));
+        if let Err(e) = self.db.save_history_async(&self.workspace, &self.history) {
+            log::error!("can't save the history: {}", e);
+        }
+    }
+
+    pub fn record_file(&mut self, path: &Path) {
+        self.update_history(|history| history.add_file(path));
+    }
+
+    /// Keeps the query in the search history, once one of its results is
+    /// opened.
+    pub fn record_search(&mut self) {
+        let query = self.search.query.clone();
+        self.update_history(|history| history.add_search(&query));
+    }
+
+    /// Checks out the branch, after creating it if `create`. Why git refused,
+    /// like uncommitted changes the checkout would overwrite, is shown in a
+    /// dialog.
+    fn checkout_branch(&self, branch: &str, create: bool) {
+        self.proxy.git_checkout(
+            branch,
+            create,
+            Box::new(move |result| {
+                if let Err(e) = result {
+                    show_request_error("Checkout failed", &e);
+                }
+            }),
+        );
+    }
+
+    /// Syncs the branch with its remote. The counts next to the branch are
+    /// updated by the proxy once it's done.
+    fn git_remote(&self, command: GitRemoteCommand) {
+        self.proxy.git_remote(
+            command,
+            Box::new(move |result| {
+                if let Err(e) = result {
+                    let title = match command {
+                        GitRemoteCommand::Fetch => "Fetch failed",
+                        GitRemoteCommand::Pull => "Pull failed",
+                        GitRemoteCommand::Push => "Push failed",
+                    };
+                    show_request_error(title, &e);
+                }
+            }),
+        );
+    }
+
+    /// Stages, unstages or discards the changes of the files whose paths
+    /// are in `data`, or of the selection of the source control panel.
+    /// Discarding asks first, as the changes are lost.
+    fn git_file(&self, command: GitFileCommand, data: Option<Value>) {
+        let paths =
+            data.and_then(|data| serde_json::from_value::<Vec<PathBuf>>(data).ok());
+        let diffs = self.source_control.diffs_for(command, paths.as_deref());
+        if diffs.is_empty() {
+            return;
+        }
+        let question = match diffs.as_slice() {
+            [diff] => format!(
+                "Discard the changes to {}? They can't be undone.",
+                diff.path()
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("")
+            ),
+            _ => format!(
+                "Discard the changes to {} files? They can't be undone.",
+                diffs.len()
+            ),
+        };
+        let proxy = self.proxy.clone();
+        let run = move || {
+            proxy.git_file(
+                command,
+                diffs,
+                Box::new(move |result| {
+                    if let Err(e) = result {
+                        let title = match command {
+                            GitFileCommand::Stage => "Stage failed",
+                            GitFileCommand::Unstage => "Unstage failed",
+                            GitFileCommand::Discard => "Discard failed",
+                        };
+                        show_request_error(title, &e);
+                    }
+                }),
+            )
+        };
+        if command != GitFileCommand::Discard {
+            run();
+            return;
         }
+        thread::spawn(move || {
+            if let tinyfiledialogs::YesNo::Yes = tinyfiledialogs::message_box_yes_no(
+                "Discard changes",
+                &question,
+                tinyfiledialogs::MessageBoxIcon::Warning,
+                tinyfiledialogs::YesNo::No,
+            ) {
+                run();
+            }
+        });
+    }
+
+    /// Moves the file or the folder to the trash, the one of the OS or the
+    /// staging folder of a remote workspace.
+    fn delete_path(&self, ctx: &mut EventCtx, path: &PathBuf) {
+        let tab_id = self.id;
+        let event_sink = ctx.get_external_handle();
+        self.proxy.delete_path(
+            path,
+            self.workspace.stages_deleted_files(),
+            Box::new(move |result| match result {
+                Ok(_) => {
+                    event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RefreshExplorer,
+                        Target::Widget(tab_id),
+                    );
+                }
+                Err(e) => show_request_error("Delete failed", &e),
+            }),
+        );
+    }
+
+    fn restore_deleted(
+        &self,
+        ctx: &mut EventCtx,
+        id: Option<&str>,
+        path: Option<&PathBuf>,
+    ) {
+        let tab_id = self.id;
+        let event_sink = ctx.get_external_handle();
+        self.proxy.restore_deleted(
+            id,
+            path,
+            self.workspace.stages_deleted_files(),
+            Box::new(move |result| match result {
+                Ok(_) => {
+                    event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RefreshExplorer,
+                        Target::Widget(tab_id),
+                    );
+                }
+                Err(e) => show_request_error("Restore failed", &e),
+            }),
+        );
     }
 
     pub fn run_workbench_command(
@@ -767,6 +1117,11 @@ impl LapceTabData {
         count: Option<usize>,
         env: &Env,
     ) {
+        if let Some(capability) = command.required_capability() {
+            if !self.proxy.has_capability(capability) {
+                return;
+            }
+        }
         match command {
             LapceWorkbenchCommand::OpenFolder => {
                 let event_sink = ctx.get_external_handle();
@@ -795,6 +1150,10 @@ impl LapceTabData {
                     }
                 });
             }
+            LapceWorkbenchCommand::ToggleRulers => {
+                let config = Arc::make_mut(&mut self.config);
+                config.editor.rulers_hidden = !config.editor.rulers_hidden;
+            }
             LapceWorkbenchCommand::EnableModal => {
                 let config = Arc::make_mut(&mut self.config);
                 config.lapce.modal = true;
@@ -832,6 +1191,7 @@ impl LapceTabData {
                             position: None,
                             scroll_offset: None,
                             hisotry: None,
+                            selection_end: None,
                         },
                         &self.config,
                     );
@@ -857,11 +1217,61 @@ impl LapceTabData {
                             position: None,
                             scroll_offset: None,
                             hisotry: None,
+                            selection_end: None,
                         },
                         &self.config,
                     );
                 }
             }
+            LapceWorkbenchCommand::ExportProfile => {
+                let profile = Arc::new(Profile::current(
+                    self.installed_plugins.keys().cloned().collect(),
+                ));
+                if profile.secrets().is_empty() {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ExportProfile(profile),
+                        Target::Widget(self.id),
+                    ));
+                } else {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPaletteProfileExport(profile),
+                        Target::Widget(self.palette.widget_id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::ImportProfile => {
+                let event_sink = ctx.get_external_handle();
+                let palette_id = self.palette.widget_id;
+                thread::spawn(move || {
+                    let dir = UserDirs::new()
+                        .and_then(|u| u.home_dir().to_str().map(|s| s.to_string()))
+                        .unwrap_or(".".to_string());
+                    let file = match tinyfiledialogs::open_file_dialog(
+                        "Import profile",
+                        &dir,
+                        Some((&["*.toml"], "Lapce profile")),
+                    ) {
+                        Some(file) => file,
+                        None => return,
+                    };
+                    match Profile::load(&PathBuf::from(&file)) {
+                        Ok(profile) => {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::RunPaletteProfileImport(Arc::new(
+                                    profile,
+                                )),
+                                Target::Widget(palette_id),
+                            );
+                        }
+                        Err(e) => {
+                            log::error!("can't read the profile {}: {}", file, e)
+                        }
+                    }
+                });
+            }
             LapceWorkbenchCommand::Palette => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -869,6 +1279,13 @@ impl LapceTabData {
                     Target::Widget(self.palette.widget_id),
                 ));
             }
+            LapceWorkbenchCommand::ConfigureIndexedFolders => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::IndexScope)),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
             LapceWorkbenchCommand::PaletteLine => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -897,79 +1314,370 @@ impl LapceTabData {
                     Target::Widget(self.palette.widget_id),
                 ));
             }
-            LapceWorkbenchCommand::NewTab => {
-                ctx.submit_command(Command::new(
-                    LAPCE_UI_COMMAND,
-                    LapceUICommand::NewTab,
-                    Target::Auto,
-                ));
-            }
-            LapceWorkbenchCommand::CloseTab => {
+            LapceWorkbenchCommand::PaletteScratch => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::CloseTab,
-                    Target::Auto,
+                    LapceUICommand::RunPalette(Some(PaletteType::Scratch)),
+                    Target::Widget(self.palette.widget_id),
                 ));
             }
-            LapceWorkbenchCommand::NextTab => {
+            LapceWorkbenchCommand::PaletteTodos => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::NextTab,
-                    Target::Auto,
+                    LapceUICommand::RunPalette(Some(PaletteType::Todo)),
+                    Target::Widget(self.palette.widget_id),
                 ));
             }
-            LapceWorkbenchCommand::PreviousTab => {
+            LapceWorkbenchCommand::PaletteCommandHistory => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::PreviousTab,
-                    Target::Auto,
+                    LapceUICommand::RunPalette(Some(PaletteType::CommandHistory)),
+                    Target::Widget(self.palette.widget_id),
                 ));
             }
-            LapceWorkbenchCommand::ReloadWindow => {
+            LapceWorkbenchCommand::PaletteThisPaneFiles => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::ReloadWindow,
-                    Target::Auto,
+                    LapceUICommand::RunPalette(Some(PaletteType::PaneFiles)),
+                    Target::Widget(self.palette.widget_id),
                 ));
             }
-            LapceWorkbenchCommand::ToggleTerminal => {
-                self.toggle_panel(ctx, PanelKind::Terminal);
+            LapceWorkbenchCommand::ShowEnvironment => {
+                let event_sink = ctx.get_external_handle();
+                let tab_id = self.id;
+                self.proxy.get_environment(Box::new(move |result| {
+                    if let Ok(report) = result {
+                        if let Ok(report) = serde_json::from_value::<String>(report)
+                        {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::ShowEnvironment(report),
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                }));
             }
-            LapceWorkbenchCommand::ToggleMaximizedPanel => {
-                if let Some(data) = data {
-                    if let Ok(kind) = serde_json::from_value::<PanelKind>(data) {
-                        for (_, panel) in self.panels.iter_mut() {
-                            if panel.widgets.contains(&kind) {
-                                if panel.active == kind {
-                                    let panel = Arc::make_mut(panel);
-                                    panel.maximized = !panel.maximized;
-                                }
-                                break;
-                            }
+            LapceWorkbenchCommand::ShowWatcherStats => {
+                let event_sink = ctx.get_external_handle();
+                let tab_id = self.id;
+                self.proxy.get_watcher_stats(Box::new(move |result| {
+                    if let Ok(report) = result {
+                        if let Ok(report) = serde_json::from_value::<String>(report)
+                        {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::ShowWatcherStats(report),
+                                Target::Widget(tab_id),
+                            );
                         }
                     }
-                } else {
-                    let panel = self.panels.get_mut(&self.panel_active).unwrap();
-                    let panel = Arc::make_mut(panel);
-                    panel.maximized = !panel.maximized;
+                }));
+            }
+            LapceWorkbenchCommand::SetLogLevel => {
+                // without a level, like from the palette, it cycles through
+                // the levels
+                let spec = data
+                    .as_ref()
+                    .and_then(|data| data.as_str())
+                    .map(|spec| spec.to_string())
+                    .unwrap_or_else(|| {
+                        logging::next_level(logging::level()).to_string()
+                    });
+                match logging::set_level(&spec) {
+                    Ok(()) => log::info!("log level set to {}", spec),
+                    Err(e) => log::warn!("{}", e),
                 }
             }
-            LapceWorkbenchCommand::FocusEditor => {
-                if let Some(active) = *self.main_split.active {
-                    ctx.submit_command(Command::new(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::Focus,
-                        Target::Widget(active),
-                    ));
+            LapceWorkbenchCommand::OpenLogFile => {
+                if let Some(path) = logging::log_file() {
+                    log::logger().flush();
+                    // nothing may have been logged yet to create it
+                    let created = path
+                        .parent()
+                        .map_or(Ok(()), std::fs::create_dir_all)
+                        .and_then(|_| {
+                            std::fs::OpenOptions::new()
+                                .create(true)
+                                .append(true)
+                                .open(&path)
+                        });
+                    if let Err(e) = created {
+                        log::error!("can't create the log file {:?}: {}", path, e);
+                        return;
+                    }
+
+                    let editor_view_id = self.main_split.active.clone();
+                    self.main_split.jump_to_location(
+                        ctx,
+                        *editor_view_id,
+                        EditorLocationNew {
+                            path,
+                            position: None,
+                            scroll_offset: None,
+                            hisotry: None,
+                            selection_end: None,
+                        },
+                        &self.config,
+                    );
                 }
             }
-            LapceWorkbenchCommand::FocusTerminal => {
-                ctx.submit_command(Command::new(
-                    LAPCE_UI_COMMAND,
-                    LapceUICommand::Focus,
+            LapceWorkbenchCommand::OpenLogView => {
+                self.main_split.open_log_view(ctx, &self.config);
+            }
+            LapceWorkbenchCommand::CycleLogViewLevel => {
+                self.main_split.log_view_level =
+                    Arc::new(logging::next_level(*self.main_split.log_view_level));
+                self.main_split.refresh_log_view(ctx);
+            }
+            LapceWorkbenchCommand::ReportIssue => {
+                let workspace = match &self.workspace.kind {
+                    LapceWorkspaceType::Local => "local",
+                    LapceWorkspaceType::RemoteSSH(_) => "remote ssh",
+                };
+                let proxy_protocol = self
+                    .proxy
+                    .protocol()
+                    .map(|p| p.version)
+                    .unwrap_or_else(|| "unknown".to_string());
+                let mut report = format!(
+                    "Lapce {}\nOS: {} {}\nWorkspace: {}\nProxy protocol: {}\n\n",
+                    env!("CARGO_PKG_VERSION"),
+                    std::env::consts::OS,
+                    std::env::consts::ARCH,
+                    workspace,
+                    proxy_protocol,
+                );
+                for line in logging::recent_lines(LevelFilter::Trace, 200) {
+                    report += &line;
+                    report += "\n";
+                }
+                Application::global().clipboard().put_string(report);
+                log::info!("copied the issue report to the clipboard");
+            }
+            LapceWorkbenchCommand::ClearWorkspaceHistory => {
+                self.update_history(|history| {
+                    *history = WorkspaceHistory::default()
+                });
+                for (path, _) in list_scratches(&self.workspace) {
+                    if self.main_split.open_files.contains_key(&path) {
+                        continue;
+                    }
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        log::error!("can't delete the scratch {:?}: {}", path, e);
+                    }
+                }
+                log::info!("cleared the history of {}", self.workspace);
+            }
+            LapceWorkbenchCommand::NewFile => {
+                self.main_split.new_file(ctx, &self.workspace, &self.config);
+            }
+            LapceWorkbenchCommand::NewScratchBuffer => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::ScratchLanguage)),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::CloseOtherEditors => {
+                if let Some(active) = *self.main_split.active {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SplitEditorCloseOthers(active),
+                        Target::Widget(*self.main_split.split_id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::CloseAllEditors => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEditorCloseAll,
+                    Target::Widget(*self.main_split.split_id),
+                ));
+            }
+            LapceWorkbenchCommand::ReopenClosedEditor => {
+                if let Some(closed) =
+                    Arc::make_mut(&mut self.main_split.closed_editors).pop()
+                {
+                    let split_id = closed.split_id;
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SplitEditorReopen(closed),
+                        Target::Widget(split_id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::NewTab => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::NewTab,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::CloseTab => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CloseTab,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::NextTab => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::NextTab,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::PreviousTab => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::PreviousTab,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::ReloadWindow => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ReloadWindow,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::ToggleFullScreen => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ToggleFullScreen,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::ToggleTerminal => {
+                self.toggle_panel(ctx, PanelKind::Terminal);
+            }
+            LapceWorkbenchCommand::ToggleMaximizedPanel => {
+                if let Some(data) = data {
+                    if let Ok(kind) = serde_json::from_value::<PanelKind>(data) {
+                        for (_, panel) in self.panels.iter_mut() {
+                            if panel.widgets.contains(&kind) {
+                                if panel.active == kind {
+                                    let panel = Arc::make_mut(panel);
+                                    panel.maximized = !panel.maximized;
+                                }
+                                break;
+                            }
+                        }
+                    }
+                } else {
+                    let panel = self.panels.get_mut(&self.panel_active).unwrap();
+                    let panel = Arc::make_mut(panel);
+                    panel.maximized = !panel.maximized;
+                }
+            }
+            LapceWorkbenchCommand::FocusEditor => {
+                if let Some(active) = self.main_split.last_focused_editor() {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::Focus,
+                        Target::Widget(active),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::FocusTerminal => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
                     Target::Widget(self.terminal.active),
                 ));
             }
+            LapceWorkbenchCommand::TerminalTabNext
+            | LapceWorkbenchCommand::TerminalTabPrev => {
+                let len = self.terminal.tabs.len();
+                let index =
+                    if matches!(command, LapceWorkbenchCommand::TerminalTabNext) {
+                        (self.terminal.active_tab + 1) % len
+                    } else {
+                        (self.terminal.active_tab + len - 1) % len
+                    };
+                self.show_panel(ctx, PanelKind::Terminal);
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::FocusTerminalTab(index),
+                    Target::Widget(self.terminal.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::TerminalTabNew => {
+                self.show_panel(ctx, PanelKind::Terminal);
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::NewTerminalTab(None),
+                    Target::Widget(self.terminal.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::NewTerminal => {
+                self.show_panel(ctx, PanelKind::Terminal);
+                let (command, target) =
+                    match self.terminal.tab_terminal(self.terminal.active_tab) {
+                        None => (
+                            LapceUICommand::InitTerminalPanel(true, None),
+                            self.terminal.split_id(),
+                        ),
+                        Some(_) => (
+                            LapceUICommand::NewTerminalTab(None),
+                            self.terminal.widget_id,
+                        ),
+                    };
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    command,
+                    Target::Widget(target),
+                ));
+            }
+            LapceWorkbenchCommand::TerminalTabClose => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CloseTerminalTab(self.terminal.split_id()),
+                    Target::Widget(self.terminal.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::OpenTerminalHere => {
+                let cwd = match data.as_ref().and_then(|d| d.as_str()) {
+                    Some(path) => Some(PathBuf::from(path)),
+                    None => self.active_file_folder(),
+                };
+                self.show_panel(ctx, PanelKind::Terminal);
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::NewTerminalTab(cwd),
+                    Target::Widget(self.terminal.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::RunTask => {
+                match data.as_ref().and_then(|d| d.as_str()) {
+                    Some(name) => self.run_task(ctx, name),
+                    None => ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPalette(Some(PaletteType::Task)),
+                        Target::Widget(self.palette.widget_id),
+                    )),
+                }
+            }
+            LapceWorkbenchCommand::TerminalRename => {
+                match data.as_ref().and_then(|d| d.as_str()) {
+                    Some(name) => ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::TerminalRename(
+                            self.terminal.active_term_id,
+                            name.to_string(),
+                        ),
+                        Target::Widget(self.id),
+                    )),
+                    None => ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPalette(Some(
+                            PaletteType::RenameTerminal,
+                        )),
+                        Target::Widget(self.palette.widget_id),
+                    )),
+                }
+            }
             LapceWorkbenchCommand::ToggleSourceControl => {
                 self.toggle_panel(ctx, PanelKind::SourceControl);
             }
@@ -980,11 +1688,171 @@ impl LapceTabData {
                 self.toggle_panel(ctx, PanelKind::FileExplorer);
             }
             LapceWorkbenchCommand::ToggleSearch => {
+                if self.focus_area != FocusArea::Panel(PanelKind::Search) {
+                    self.prefill_search();
+                }
                 self.toggle_panel(ctx, PanelKind::Search);
             }
             LapceWorkbenchCommand::ToggleProblem => {
                 self.toggle_panel(ctx, PanelKind::Problem);
             }
+            LapceWorkbenchCommand::SearchToggleCaseSensitive => {
+                let options = &mut Arc::make_mut(&mut self.search).options;
+                options.case_sensitive = !options.case_sensitive;
+                self.rerun_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchToggleWholeWord => {
+                let options = &mut Arc::make_mut(&mut self.search).options;
+                options.whole_word = !options.whole_word;
+                self.rerun_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchToggleRegex => {
+                let options = &mut Arc::make_mut(&mut self.search).options;
+                options.regex = !options.regex;
+                self.rerun_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchToggleContext => {
+                let search = Arc::make_mut(&mut self.search);
+                search.show_context = !search.show_context;
+                self.rerun_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchTogglePin => {
+                if Arc::make_mut(&mut self.search).toggle_pin() {
+                    self.refresh_pinned_searches(ctx);
+                    let _ = self.db.save_workspace_async(self);
+                }
+            }
+            LapceWorkbenchCommand::SearchUnpin => {
+                if let Some(index) = data.as_ref().and_then(|d| d.as_u64()) {
+                    Arc::make_mut(&mut self.search).unpin(index as usize);
+                    let _ = self.db.save_workspace_async(self);
+                }
+            }
+            LapceWorkbenchCommand::SearchTogglePreserveCase => {
+                Arc::make_mut(&mut self.search).toggle_preserve_case();
+            }
+            LapceWorkbenchCommand::SearchCopyResults => {
+                if !self.search.matches.is_empty() {
+                    let text =
+                        self.search.results_text(self.workspace.path.as_deref());
+                    Application::global().clipboard().put_string(text);
+                }
+            }
+            LapceWorkbenchCommand::SearchCopyPath => {
+                if let Some(path) = data.as_ref().and_then(|d| d.as_str()) {
+                    let path = relative_path(
+                        &PathBuf::from(path),
+                        self.workspace.path.as_deref(),
+                    );
+                    Application::global().clipboard().put_string(path);
+                }
+            }
+            LapceWorkbenchCommand::SearchCopyLine => {
+                let line = data
+                    .as_ref()
+                    .and_then(|d| {
+                        serde_json::from_value::<(PathBuf, usize)>(d.clone()).ok()
+                    })
+                    .and_then(|(path, index)| {
+                        let (_, _, line) =
+                            self.search.matches.get(&path)?.get(index)?;
+                        Some(line.trim().to_string())
+                    });
+                if let Some(line) = line {
+                    Application::global().clipboard().put_string(line);
+                }
+            }
+            LapceWorkbenchCommand::SearchReplaceAll => {
+                self.replace_all(ctx);
+            }
+            LapceWorkbenchCommand::RefreshSearch => {
+                self.refresh_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchRerun => {
+                self.rerun_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchClearResults => {
+                self.main_split
+                    .set_local_buffer_text(LocalBufferKind::Search, "");
+                self.rerun_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchResultNext => {
+                Arc::make_mut(&mut self.search).select_next();
+            }
+            LapceWorkbenchCommand::SearchResultPrev => {
+                Arc::make_mut(&mut self.search).select_previous();
+            }
+            LapceWorkbenchCommand::SearchResultOpen => {
+                // Enter searches the selection the input was opened with
+                let location = if self.search.prefilled.is_some() {
+                    Arc::make_mut(&mut self.search).prefilled = None;
+                    self.rerun_search(ctx);
+                    None
+                } else {
+                    self.search.selected.as_ref().and_then(|(path, index)| {
+                        self.search.match_location(path, *index)
+                    })
+                };
+                if let Some(location) = location {
+                    self.record_search();
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::JumpToLocation(None, location),
+                        Target::Widget(self.id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::SearchHistoryPrevious
+            | LapceWorkbenchCommand::SearchHistoryNext => {
+                let searches = &self.history.searches;
+                let current = searches.iter().position(|s| s == &self.search.query);
+                let index = match (command, current) {
+                    (LapceWorkbenchCommand::SearchHistoryPrevious, Some(i)) => {
+                        Some(i + 1)
+                    }
+                    (LapceWorkbenchCommand::SearchHistoryPrevious, None) => Some(0),
+                    (_, Some(i)) => i.checked_sub(1),
+                    (_, None) => None,
+                };
+                if let Some(pattern) = index.and_then(|i| searches.get(i)).cloned() {
+                    self.main_split
+                        .set_local_buffer_text(LocalBufferKind::Search, &pattern);
+                    self.rerun_search(ctx);
+                }
+            }
+            LapceWorkbenchCommand::ProblemToggleErrors => {
+                Arc::make_mut(&mut self.problem).toggle(DiagnosticSeverity::Error);
+            }
+            LapceWorkbenchCommand::ProblemToggleWarnings => {
+                Arc::make_mut(&mut self.problem).toggle(DiagnosticSeverity::Warning);
+            }
+            LapceWorkbenchCommand::ProblemToggleInformation => {
+                Arc::make_mut(&mut self.problem)
+                    .toggle(DiagnosticSeverity::Information);
+            }
+            LapceWorkbenchCommand::SearchCollapseAll => {
+                Arc::make_mut(&mut self.search).collapse_all();
+            }
+            LapceWorkbenchCommand::SearchExpandAll => {
+                Arc::make_mut(&mut self.search).expand_all();
+            }
+            LapceWorkbenchCommand::ToggleEditorsPanel => {
+                self.toggle_panel(ctx, PanelKind::Editors);
+            }
+            LapceWorkbenchCommand::MoveEditorToPanelArea => {
+                self.move_active_editor(
+                    ctx,
+                    *self.main_split.split_id,
+                    self.editors_panel.split_id,
+                );
+            }
+            LapceWorkbenchCommand::MoveEditorToMainSplit => {
+                self.move_active_editor(
+                    ctx,
+                    self.editors_panel.split_id,
+                    *self.main_split.split_id,
+                );
+            }
             LapceWorkbenchCommand::TogglePanel => {
                 if let Some(data) = data {
                     if let Ok(kind) = serde_json::from_value::<PanelKind>(data) {
@@ -1007,23 +1875,17 @@ impl LapceTabData {
                 }
             }
             LapceWorkbenchCommand::SourceControlCommit => {
-                let diffs: Vec<FileDiff> = self
-                    .source_control
-                    .file_diffs
-                    .iter()
-                    .filter_map(
-                        |(diff, checked)| {
-                            if *checked {
-                                Some(diff.clone())
-                            } else {
-                                None
-                            }
-                        },
-                    )
-                    .collect();
-                if diffs.len() == 0 {
+                if !self.source_control.has_changes() {
                     return;
                 }
+                // the staged changes are in the index already, or else all
+                // of them are committed
+                let diffs: Vec<FileDiff> =
+                    if self.source_control.staged_diffs.is_empty() {
+                        self.source_control.file_diffs.clone()
+                    } else {
+                        Vec::new()
+                    };
                 let buffer = self
                     .main_split
                     .local_buffers
@@ -1047,17 +1909,283 @@ impl LapceTabData {
                     Cursor::new(CursorMode::Insert(Selection::caret(0)), None)
                 };
             }
-            LapceWorkbenchCommand::CheckoutBranch => {}
+            LapceWorkbenchCommand::CheckoutBranch => {
+                if let Some(branch) = data.as_ref().and_then(|d| d.as_str()) {
+                    self.checkout_branch(branch, false);
+                }
+            }
+            LapceWorkbenchCommand::CreateBranch => {
+                match data.as_ref().and_then(|d| d.as_str()) {
+                    Some(branch) => self.checkout_branch(branch, true),
+                    None => ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPalette(Some(PaletteType::NewBranch)),
+                        Target::Widget(self.palette.widget_id),
+                    )),
+                }
+            }
+            LapceWorkbenchCommand::FocusTab => {
+                if let Some(index) = data.and_then(|d| d.as_u64()) {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::FocusTabIndex(index as usize),
+                        Target::Auto,
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::SourceControlStage => {
+                self.git_file(GitFileCommand::Stage, data);
+            }
+            LapceWorkbenchCommand::SourceControlUnstage => {
+                self.git_file(GitFileCommand::Unstage, data);
+            }
+            LapceWorkbenchCommand::SourceControlDiscard => {
+                self.git_file(GitFileCommand::Discard, data);
+            }
+            LapceWorkbenchCommand::GitFetch => {
+                self.git_remote(GitRemoteCommand::Fetch);
+            }
+            LapceWorkbenchCommand::GitPull => {
+                self.git_remote(GitRemoteCommand::Pull);
+            }
+            LapceWorkbenchCommand::GitPush => {
+                self.git_remote(GitRemoteCommand::Push);
+            }
+            LapceWorkbenchCommand::BranchMenu => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::Branch)),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
             LapceWorkbenchCommand::ConnectSshHost => {
+                // straight to the prompt when there's no host to pick
+                let palette_type = if Config::recent_ssh_hosts().is_empty() {
+                    PaletteType::NewSshHost
+                } else {
+                    PaletteType::SshHost
+                };
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::RunPalette(Some(PaletteType::SshHost)),
+                    LapceUICommand::RunPalette(Some(palette_type)),
                     Target::Widget(self.palette.widget_id),
                 ));
             }
+            LapceWorkbenchCommand::DeleteFile => {
+                if let Some(path) = data.as_ref().and_then(|d| d.as_str()) {
+                    self.delete_path(ctx, &PathBuf::from(path));
+                }
+            }
+            LapceWorkbenchCommand::OpenBreadcrumb => {
+                let target = data.and_then(|data| {
+                    serde_json::from_value::<BreadcrumbTarget>(data).ok()
+                });
+                if let Some(target) = target {
+                    let command = if target.path.is_dir() {
+                        LapceUICommand::RevealInFileExplorer(target.path)
+                    } else {
+                        LapceUICommand::JumpToLocation(
+                            None,
+                            EditorLocationNew {
+                                path: target.path,
+                                position: target.position,
+                                scroll_offset: None,
+                                hisotry: None,
+                                selection_end: None,
+                            },
+                        )
+                    };
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        command,
+                        Target::Widget(self.id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::RecentlyDeleted => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::Deleted)),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::RestoreDeleted => {
+                if let Some(data) = data {
+                    let id = data.get("id").and_then(|id| id.as_str());
+                    let path =
+                        data.get("path").and_then(|p| p.as_str()).map(PathBuf::from);
+                    self.restore_deleted(ctx, id, path.as_ref());
+                }
+            }
+            LapceWorkbenchCommand::ReconnectWorkspace => {
+                // the tab is opened again with a new proxy, and the editors
+                // it had are restored
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SetWorkspace((*self.workspace).clone()),
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::CloseWorkspace => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CloseWorkspace,
+                    Target::Auto,
+                ));
+            }
+            LapceWorkbenchCommand::ShowWorkspaceMenu => {
+                self.show_title_menu(ctx, TitleMenu::Workspace);
+            }
+            LapceWorkbenchCommand::ShowBranchMenu => {
+                self.show_title_menu(ctx, TitleMenu::Branch);
+            }
+            LapceWorkbenchCommand::ShowRemoteMenu => {
+                self.show_title_menu(ctx, TitleMenu::Remote);
+            }
+        }
+    }
+
+    /// Runs the task of the settings in a terminal placed as
+    /// `tasks.output-placement` says, showing the terminal panel.
+    fn run_task(&mut self, ctx: &mut EventCtx, name: &str) {
+        let config = match self.config.task.get(name) {
+            Some(config) => config,
+            None => return,
+        };
+        let panel = self
+            .panels
+            .values()
+            .find(|panel| panel.widgets.contains(&PanelKind::Terminal));
+        let task = Arc::new(TerminalTask {
+            name: name.to_string(),
+            command: config.command.clone(),
+            cwd: config.cwd.as_ref().map(|cwd| match &self.workspace.path {
+                Some(path) => path.join(cwd),
+                None => PathBuf::from(cwd),
+            }),
+            auto_close: config.auto_close,
+            placement: TaskPlacement::from_config(
+                &self.config.tasks.output_placement,
+            ),
+            previous_term_id: self
+                .terminal
+                .tab_terminal(self.terminal.active_tab)
+                .map(|terminal| terminal.term_id),
+            panel_shown: panel
+                .map(|panel| panel.is_shown() && panel.active == PanelKind::Terminal)
+                .unwrap_or(false),
+            panel_maximized: panel.map(|panel| panel.maximized).unwrap_or(false),
+        });
+
+        self.show_panel(ctx, PanelKind::Terminal);
+        let command = match task.placement {
+            TaskPlacement::PanelSplit => Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::InitTerminalTask(task.clone()),
+                Target::Widget(self.terminal.split_id()),
+            ),
+            TaskPlacement::PanelTab | TaskPlacement::EditorArea => Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::NewTerminalTaskTab(task.clone()),
+                Target::Widget(self.terminal.widget_id),
+            ),
+        };
+        ctx.submit_command(command);
+        if task.placement == TaskPlacement::EditorArea {
+            self.set_terminal_maximized(true);
+        }
+    }
+
+    fn set_terminal_maximized(&mut self, maximized: bool) {
+        for (_, panel) in self.panels.iter_mut() {
+            if panel.widgets.contains(&PanelKind::Terminal) {
+                Arc::make_mut(panel).maximized = maximized;
+                break;
+            }
         }
     }
 
+    /// Closes the terminal whose process exited. The terminal of a task stays
+    /// open with how it exited, unless it exited cleanly and closes itself:
+    /// then the focus and the panel go back to how they were before it.
+    pub fn terminal_exited(
+        &mut self,
+        ctx: &mut EventCtx,
+        term_id: TermId,
+        exit_code: Option<i32>,
+    ) {
+        let task = match self.terminal.terminals.get(&term_id) {
+            Some(terminal) => terminal.task.clone(),
+            None => return,
+        };
+        let task = match task {
+            Some(task) => task,
+            None => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CloseTerminal(term_id),
+                    Target::Widget(self.id),
+                ));
+                return;
+            }
+        };
+        if !(task.auto_close && exit_code == Some(0)) {
+            let terminal_panel = Arc::make_mut(&mut self.terminal);
+            if let Some(terminal) = terminal_panel.terminals.get_mut(&term_id) {
+                Arc::make_mut(terminal).title = task.exit_title(exit_code);
+            }
+            return;
+        }
+
+        let previous = task
+            .previous_term_id
+            .and_then(|term_id| self.terminal.terminals.get(&term_id))
+            .map(|terminal| (terminal.term_id, terminal.widget_id));
+        if let Some((previous_term_id, widget_id)) = previous {
+            Arc::make_mut(&mut self.terminal)
+                .set_active_terminal(previous_term_id, widget_id);
+        }
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::CloseTerminal(term_id),
+            Target::Widget(self.id),
+        ));
+        if task.placement == TaskPlacement::EditorArea && !task.panel_maximized {
+            self.set_terminal_maximized(false);
+        }
+        if !task.panel_shown {
+            self.hide_panel(ctx, PanelKind::Terminal);
+        } else if let Some((_, widget_id)) = previous {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(widget_id),
+            ));
+        }
+    }
+
+    /// The folder of the file of the active editor, none for scratch and
+    /// other unsaved buffers, whose terminals start at the workspace root.
+    /// In a remote workspace, it's the folder on the remote host.
+    fn active_file_folder(&self) -> Option<PathBuf> {
+        let editor = self.main_split.active_editor()?;
+        match &editor.content {
+            BufferContent::File(path) if !is_scratch(path) => {
+                path.parent().map(|p| p.to_path_buf())
+            }
+            _ => None,
+        }
+    }
+
+    /// Has the title bar of the window open the menu of its part.
+    fn show_title_menu(&self, ctx: &mut EventCtx, menu: TitleMenu) {
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ShowTitleMenu(menu),
+            Target::Auto,
+        ));
+    }
+
     pub fn run_command(
         &mut self,
         ctx: &mut EventCtx,
@@ -1065,6 +2193,7 @@ impl LapceTabData {
         count: Option<usize>,
         env: &Env,
     ) {
+        Arc::make_mut(&mut self.keypress).record_command(command);
         match command.target {
             CommandTarget::Workbench => {
                 if let Ok(cmd) = LapceWorkbenchCommand::from_str(&command.cmd) {
@@ -1094,96 +2223,753 @@ impl LapceTabData {
         workspace: Arc<LapceWorkspace>,
         proxy: Arc<LapceProxy>,
     ) {
-        let mut terminals = HashMap::new();
-        let mut last_redraw = std::time::Instant::now();
-        let mut last_event = None;
-        loop {
-            let (term_id, event) = if let Some((term_id, event)) = last_event.take()
-            {
-                (term_id, event)
-            } else {
-                match receiver.recv() {
-                    Ok((term_id, event)) => (term_id, event),
-                    Err(_) => return,
+        let mut terminals = HashMap::new();
+        let mut last_redraw = std::time::Instant::now();
+        let mut last_event = None;
+        loop {
+            let (term_id, event) = if let Some((term_id, event)) = last_event.take()
+            {
+                (term_id, event)
+            } else {
+                match receiver.recv() {
+                    Ok((term_id, event)) => (term_id, event),
+                    Err(_) => return,
+                }
+            };
+            match event {
+                TermEvent::CloseTerminal => {
+                    terminals.remove(&term_id);
+                }
+                TermEvent::NewTerminal(raw) => {
+                    terminals.insert(term_id, raw);
+                }
+                TermEvent::UpdateContent(content) => {
+                    if let Some(raw) = terminals.get_mut(&term_id) {
+                        raw.lock().update_content(&content);
+                        last_event = receiver.try_recv().ok();
+                        if last_event.is_some() {
+                            if last_redraw.elapsed().as_millis() > 10 {
+                                last_redraw = std::time::Instant::now();
+                                event_sink.submit_command(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::RequestPaint,
+                                    Target::Widget(tab_id),
+                                );
+                            }
+                        } else {
+                            last_redraw = std::time::Instant::now();
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::RequestPaint,
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves the focus from the editor split or a panel to the area next to
+    /// it in the direction, when the move can't be done inside the split.
+    pub fn split_move_out(
+        &mut self,
+        ctx: &mut EventCtx,
+        direction: &SplitMoveDirection,
+    ) {
+        let current = match &self.focus_area {
+            FocusArea::Editor => None,
+            FocusArea::Panel(kind) => match self.panel_position(*kind) {
+                Some(position) => Some(position),
+                None => return,
+            },
+            FocusArea::Palette => return,
+        };
+
+        // None stands for the editor split
+        let candidates = match (&current, direction) {
+            (None, SplitMoveDirection::Left) => {
+                vec![
+                    Some(PanelPosition::LeftTop),
+                    Some(PanelPosition::LeftBottom),
+                ]
+            }
+            (None, SplitMoveDirection::Right) => vec![
+                Some(PanelPosition::RightTop),
+                Some(PanelPosition::RightBottom),
+            ],
+            (None, SplitMoveDirection::Down) => vec![
+                Some(PanelPosition::BottomLeft),
+                Some(PanelPosition::BottomRight),
+            ],
+            (Some(PanelPosition::LeftTop), SplitMoveDirection::Down) => {
+                vec![Some(PanelPosition::LeftBottom)]
+            }
+            (Some(PanelPosition::LeftBottom), SplitMoveDirection::Up) => {
+                vec![Some(PanelPosition::LeftTop)]
+            }
+            (Some(PanelPosition::RightTop), SplitMoveDirection::Down) => {
+                vec![Some(PanelPosition::RightBottom)]
+            }
+            (Some(PanelPosition::RightBottom), SplitMoveDirection::Up) => {
+                vec![Some(PanelPosition::RightTop)]
+            }
+            (
+                Some(PanelPosition::LeftTop | PanelPosition::LeftBottom),
+                SplitMoveDirection::Right,
+            )
+            | (
+                Some(PanelPosition::RightTop | PanelPosition::RightBottom),
+                SplitMoveDirection::Left,
+            )
+            | (
+                Some(PanelPosition::BottomLeft | PanelPosition::BottomRight),
+                SplitMoveDirection::Up,
+            ) => vec![None],
+            (Some(PanelPosition::BottomLeft), SplitMoveDirection::Right) => vec![
+                Some(PanelPosition::BottomRight),
+                Some(PanelPosition::RightBottom),
+                Some(PanelPosition::RightTop),
+            ],
+            (Some(PanelPosition::BottomLeft), SplitMoveDirection::Left) => vec![
+                Some(PanelPosition::LeftBottom),
+                Some(PanelPosition::LeftTop),
+            ],
+            (Some(PanelPosition::BottomRight), SplitMoveDirection::Left) => vec![
+                Some(PanelPosition::BottomLeft),
+                Some(PanelPosition::LeftBottom),
+                Some(PanelPosition::LeftTop),
+            ],
+            (Some(PanelPosition::BottomRight), SplitMoveDirection::Right) => vec![
+                Some(PanelPosition::RightBottom),
+                Some(PanelPosition::RightTop),
+            ],
+            _ => vec![],
+        };
+
+        for candidate in candidates {
+            match candidate {
+                None => {
+                    let target = (*self.main_split.active)
+                        .unwrap_or(*self.main_split.split_id);
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::Focus,
+                        Target::Widget(target),
+                    ));
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::EnsureCursorVisible(None),
+                        Target::Widget(target),
+                    ));
+                    return;
+                }
+                Some(position) => {
+                    if let Some(panel) = self.panels.get(&position) {
+                        if panel.is_shown() {
+                            let kind = panel.active;
+                            self.show_panel(ctx, kind);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn hide_panel(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
+        for (_, panel) in self.panels.iter_mut() {
+            if panel.widgets.contains(&kind) {
+                let panel = Arc::make_mut(panel);
+                panel.shown = false;
+                break;
+            }
+        }
+        if let Some(active) = *self.main_split.active {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(active),
+            ));
+        }
+    }
+
+    pub fn show_panel(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
+        for (_, panel) in self.panels.iter_mut() {
+            for k in panel.widgets.clone() {
+                if k == kind {
+                    let panel = Arc::make_mut(panel);
+                    panel.shown = true;
+                    panel.active = k;
+                    if kind == PanelKind::Search {
+                        self.refresh_pinned_searches(ctx);
+                    }
+                    let focus_id = match kind {
+                        PanelKind::FileExplorer => self.file_explorer.widget_id,
+                        PanelKind::SourceControl => self.source_control.active,
+                        PanelKind::Plugin => self.plugin.widget_id,
+                        PanelKind::Terminal => self.terminal.widget_id,
+                        PanelKind::Search => self.search.active,
+                        PanelKind::Problem => self.problem.widget_id,
+                        PanelKind::Editors => self.editors_panel_focus(),
+                    };
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::Focus,
+                        Target::Widget(focus_id),
+                    ));
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Whether the search panel is the one shown in its place.
+    fn is_search_shown(&self) -> bool {
+        self.panels
+            .values()
+            .any(|panel| panel.is_shown() && panel.active == PanelKind::Search)
+    }
+
+    /// Counts the results of the pinned queries again if the files may have
+    /// changed, but only while they're seen: the others wait for the search
+    /// panel to be shown.
+    pub fn refresh_pinned_searches(&mut self, ctx: &mut EventCtx) {
+        if !self.search.pinned_stale
+            || self.search.pinned.is_empty()
+            || !self.is_search_shown()
+        {
+            return;
+        }
+        let tab_id = self.id;
+        let proxy = self.proxy.clone();
+        Arc::make_mut(&mut self.search).count_pinned(
+            &proxy,
+            tab_id,
+            ctx.get_external_handle(),
+        );
+    }
+
+    /// Notes that files changed, so the counts of the pinned queries may be
+    /// out of date.
+    pub fn files_changed(&mut self, ctx: &mut EventCtx) {
+        Arc::make_mut(&mut self.search).pinned_stale = true;
+        self.refresh_pinned_searches(ctx);
+    }
+
+    /// Puts the selection of the active editor in the search input, selected
+    /// so that typing replaces it, if it's on a single line. The input
+    /// searches it when its text changes, unless `search-on-prefill` is off.
+    fn prefill_search(&mut self) {
+        let editor = match (*self.main_split.active)
+            .and_then(|id| self.main_split.editors.get(&id))
+        {
+            Some(editor) => editor,
+            None => return,
+        };
+        if !matches!(editor.content, BufferContent::File(_)) {
+            return;
+        }
+        let buffer = self.main_split.editor_buffer(editor.view_id);
+        let text = match editor
+            .cursor
+            .selected_text(&buffer)
+            .and_then(|s| search_prefill(&s, self.search.options.regex))
+        {
+            Some(text) => text,
+            None => return,
+        };
+
+        Arc::make_mut(&mut self.search).prefilled =
+            if self.config.search.search_on_prefill {
+                None
+            } else {
+                Some(text.clone())
+            };
+        self.main_split
+            .set_local_buffer_text(LocalBufferKind::Search, &text);
+        let editor = self
+            .main_split
+            .editors
+            .get_mut(&self.search.editor_view_id)
+            .unwrap();
+        Arc::make_mut(editor).cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, text.len())), None);
+    }
+
+    /// Loads a pinned query with its options into the search panel. The
+    /// search input runs it when its text changes.
+    pub fn load_pinned_search(&mut self, index: usize) {
+        let pin = match self.search.pinned.get(index) {
+            Some(pin) => pin.clone(),
+            None => return,
+        };
+        let search = Arc::make_mut(&mut self.search);
+        search.options = pin.options;
+        // set first, so the include field doesn't run the search again
+        search.include = pin.include.clone();
+        self.main_split.set_local_buffer_text(
+            LocalBufferKind::SearchInclude,
+            &pin.include.join(", "),
+        );
+        self.main_split
+            .set_local_buffer_text(LocalBufferKind::Search, &pin.query);
+        let _ = self.db.save_workspace_async(self);
+    }
+
+    /// Replaces the matches of the finished search in all the files. Open
+    /// files are edited in their buffers, the others by the proxy, which
+    /// leaves alone the files changed since the search. The matches of each
+    /// file go once they're replaced.
+    fn replace_all(&mut self, ctx: &mut EventCtx) {
+        if self.search.is_searching() || self.search.replacer.is_none() {
+            return;
+        }
+        let search_id = self.search.search_id;
+        let paths: Vec<PathBuf> = self.search.matches.keys().cloned().collect();
+        for path in paths {
+            let lines = self.search.replaced_lines(&path);
+            if lines.is_empty() {
+                continue;
+            }
+            let open = self
+                .main_split
+                .open_files
+                .get(&path)
+                .map(|b| b.loaded)
+                .unwrap_or(false);
+            if open {
+                match self.main_split.replace_lines(ctx, &path, &lines) {
+                    Ok(()) => {
+                        Arc::make_mut(&mut self.search)
+                            .remove_matches(search_id, &path);
+                    }
+                    Err(e) => log::error!("can't replace in {:?}: {}", path, e),
+                }
+                continue;
+            }
+            let event_sink = ctx.get_external_handle();
+            let tab_id = self.id;
+            self.proxy.replace_lines(
+                path.clone(),
+                lines,
+                Box::new(move |result| match result {
+                    Ok(_) => {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::SearchMatchesReplaced(search_id, path),
+                            Target::Widget(tab_id),
+                        );
+                    }
+                    Err(e) => log::error!("{}", e),
+                }),
+            );
+        }
+    }
+
+    /// Shows the preview of a workspace edit, as the edits of each file. Open
+    /// files are previewed from their buffers, the others once the proxy read
+    /// them. An edit of a single file is applied right away, unless the
+    /// config wants it previewed too.
+    pub fn preview_workspace_edit(
+        &mut self,
+        ctx: &mut EventCtx,
+        title: &str,
+        edits: &[(PathBuf, Vec<TextEdit>)],
+    ) {
+        if edits.is_empty() {
+            return;
+        }
+        let workspace_edit = Arc::make_mut(&mut self.workspace_edit);
+        workspace_edit.set(title, edits.to_vec());
+        workspace_edit.apply_when_read =
+            edits.len() == 1 && !self.config.editor.preview_single_file_edits;
+        workspace_edit.shown = !workspace_edit.apply_when_read;
+        let id = workspace_edit.id;
+        for file in workspace_edit.files.iter_mut() {
+            let buffer = self
+                .main_split
+                .open_files
+                .get(&file.path)
+                .filter(|buffer| buffer.loaded);
+            if let Some(buffer) = buffer {
+                file.set_content(buffer.rope.to_string());
+                continue;
+            }
+            if !self.proxy.has_capability(CAPABILITY_WORKSPACE_EDIT) {
+                file.error =
+                    Some("the proxy can't edit files that aren't open".to_string());
+                continue;
+            }
+            let event_sink = ctx.get_external_handle();
+            let tab_id = self.id;
+            let path = file.path.clone();
+            self.proxy.read_file(
+                &file.path,
+                Box::new(move |result| {
+                    let result = result
+                        .map(|content| content.as_str().unwrap_or("").to_string())
+                        .map_err(|e| {
+                            e["message"].as_str().unwrap_or("").to_string()
+                        });
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::WorkspaceEditFileRead(id, path, result),
+                        Target::Widget(tab_id),
+                    );
+                }),
+            );
+        }
+        if workspace_edit.shown {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(workspace_edit.widget_id),
+            ));
+        }
+        if workspace_edit.apply_when_read && workspace_edit.is_read() {
+            self.apply_workspace_edit(ctx);
+        }
+    }
+
+    pub fn workspace_edit_file_read(
+        &mut self,
+        ctx: &mut EventCtx,
+        id: u64,
+        path: &PathBuf,
+        result: Result<String, String>,
+    ) {
+        if self.workspace_edit.id != id {
+            return;
+        }
+        let workspace_edit = Arc::make_mut(&mut self.workspace_edit);
+        if let Some(file) = workspace_edit.file_mut(path) {
+            match result {
+                Ok(content) => file.set_content(content),
+                Err(e) => {
+                    log::error!("can't read {:?}: {}", path, e);
+                    file.error = Some(e);
                 }
+            }
+        }
+        if workspace_edit.apply_when_read && workspace_edit.is_read() {
+            self.apply_workspace_edit(ctx);
+        }
+    }
+
+    /// Applies the files and hunks of the workspace edit that aren't left
+    /// out. Open files are edited in their buffers, in one undo step each,
+    /// the others by the proxy. A file that changed since it was previewed
+    /// isn't edited, and stays listed with the error.
+    pub fn apply_workspace_edit(&mut self, ctx: &mut EventCtx) {
+        if !self.workspace_edit.can_apply() {
+            self.close_workspace_edit_if_done(ctx);
+            return;
+        }
+        let workspace_edit = Arc::make_mut(&mut self.workspace_edit);
+        let id = workspace_edit.id;
+        for file in workspace_edit.files.iter_mut() {
+            if file.applying || file.error.is_some() {
+                continue;
+            }
+            let content = match file.content.as_ref() {
+                Some(content) => content,
+                None => continue,
             };
-            match event {
-                TermEvent::CloseTerminal => {
-                    terminals.remove(&term_id);
+            let edits = file.included_edits();
+            if edits.is_empty() {
+                continue;
+            }
+            let unchanged = self
+                .main_split
+                .open_files
+                .get(&file.path)
+                .filter(|buffer| buffer.loaded)
+                .map(|buffer| buffer.rope.to_string() == *content);
+            match unchanged {
+                Some(true) => {
+                    let edits: Vec<(Selection, &str)> = edits
+                        .iter()
+                        .map(|(start, end, text)| {
+                            (Selection::region(*start, *end), text.as_str())
+                        })
+                        .collect();
+                    self.main_split.edit(
+                        ctx,
+                        &file.path,
+                        edits.iter().map(|(s, text)| (s, *text)).collect(),
+                        EditType::Other,
+                    );
                 }
-                TermEvent::NewTerminal(raw) => {
-                    terminals.insert(term_id, raw);
+                Some(false) => {
+                    file.error =
+                        Some("changed since the edit was computed".to_string());
                 }
-                TermEvent::UpdateContent(content) => {
-                    if let Some(raw) = terminals.get_mut(&term_id) {
-                        raw.lock().update_content(&content);
-                        last_event = receiver.try_recv().ok();
-                        if last_event.is_some() {
-                            if last_redraw.elapsed().as_millis() > 10 {
-                                last_redraw = std::time::Instant::now();
-                                event_sink.submit_command(
-                                    LAPCE_UI_COMMAND,
-                                    LapceUICommand::RequestPaint,
-                                    Target::Widget(tab_id),
-                                );
-                            }
-                        } else {
-                            last_redraw = std::time::Instant::now();
-                            event_sink.submit_command(
+                None if self.proxy.has_capability(CAPABILITY_WORKSPACE_EDIT) => {
+                    file.applying = true;
+                    let event_sink = ctx.get_external_handle();
+                    let tab_id = self.id;
+                    let path = file.path.clone();
+                    self.proxy.replace_content(
+                        file.path.clone(),
+                        content.clone(),
+                        apply_edits(content, &edits),
+                        Box::new(move |result| {
+                            let result = result.map(|_| ()).map_err(|e| {
+                                e["message"].as_str().unwrap_or("").to_string()
+                            });
+                            let _ = event_sink.submit_command(
                                 LAPCE_UI_COMMAND,
-                                LapceUICommand::RequestPaint,
+                                LapceUICommand::WorkspaceEditApplied(
+                                    id, path, result,
+                                ),
                                 Target::Widget(tab_id),
                             );
-                        }
-                    }
+                        }),
+                    );
+                }
+                None => {
+                    file.error = Some(
+                        "the proxy can't edit files that aren't open".to_string(),
+                    );
                 }
             }
+            if let Some(e) = file.error.as_ref() {
+                log::error!("can't apply the edit to {:?}: {}", file.path, e);
+            }
         }
+        // what's left are the files being edited by the proxy, and the ones
+        // that couldn't be
+        workspace_edit
+            .files
+            .retain(|file| file.applying || file.error.is_some());
+        workspace_edit.active = 0;
+        self.close_workspace_edit_if_done(ctx);
     }
 
-    fn hide_panel(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
-        for (_, panel) in self.panels.iter_mut() {
-            if panel.widgets.contains(&kind) {
-                let panel = Arc::make_mut(panel);
-                panel.shown = false;
-                break;
+    pub fn workspace_edit_applied(
+        &mut self,
+        ctx: &mut EventCtx,
+        id: u64,
+        path: &PathBuf,
+        result: Result<(), String>,
+    ) {
+        if self.workspace_edit.id != id {
+            return;
+        }
+        let workspace_edit = Arc::make_mut(&mut self.workspace_edit);
+        match result {
+            Ok(()) => workspace_edit.files.retain(|file| &file.path != path),
+            Err(e) => {
+                log::error!("can't apply the edit to {:?}: {}", path, e);
+                if let Some(file) = workspace_edit.file_mut(path) {
+                    file.applying = false;
+                    file.error = Some(e);
+                }
             }
         }
-        if let Some(active) = *self.main_split.active {
+        workspace_edit.active = 0;
+        self.close_workspace_edit_if_done(ctx);
+    }
+
+    /// Closes the preview once all its files are edited. Errors are only
+    /// logged for edits applied without a preview.
+    fn close_workspace_edit_if_done(&mut self, ctx: &mut EventCtx) {
+        let workspace_edit = &self.workspace_edit;
+        let done = if workspace_edit.shown {
+            workspace_edit.files.is_empty()
+        } else {
+            workspace_edit.files.iter().all(|file| !file.applying)
+        };
+        if done {
+            self.close_workspace_edit(ctx);
+        }
+    }
+
+    pub fn close_workspace_edit(&mut self, ctx: &mut EventCtx) {
+        let workspace_edit = Arc::make_mut(&mut self.workspace_edit);
+        let shown = workspace_edit.shown;
+        workspace_edit.shown = false;
+        workspace_edit.apply_when_read = false;
+        workspace_edit.files.clear();
+        if shown {
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
                 LapceUICommand::Focus,
-                Target::Widget(active),
+                Target::Widget(self.focus),
             ));
         }
     }
 
-    fn show_panel(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
-        for (_, panel) in self.panels.iter_mut() {
-            for k in panel.widgets.clone() {
-                if k == kind {
-                    let panel = Arc::make_mut(panel);
-                    panel.shown = true;
-                    panel.active = k;
-                    let focus_id = match kind {
-                        PanelKind::FileExplorer => self.file_explorer.widget_id,
-                        PanelKind::SourceControl => self.source_control.active,
-                        PanelKind::Plugin => self.plugin.widget_id,
-                        PanelKind::Terminal => self.terminal.widget_id,
-                        PanelKind::Search => self.search.active,
-                        PanelKind::Problem => self.problem.widget_id,
-                    };
-                    ctx.submit_command(Command::new(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::Focus,
-                        Target::Widget(focus_id),
-                    ));
-                    break;
+    /// Searches again the files changed since their matches were found, and
+    /// puts their new matches in place of the old ones. Open files are
+    /// searched in their buffers, the others by the proxy.
+    pub fn refresh_search(&mut self, ctx: &mut EventCtx) {
+        if self.search.is_searching() || self.search.query.is_empty() {
+            return;
+        }
+        let stale = self.search.stale_files(&self.main_split.open_files);
+        if stale.is_empty() {
+            return;
+        }
+        let regex = match self.search.options.build_regex(&self.search.query) {
+            Ok(regex) => regex,
+            Err(_) => return,
+        };
+        let search_id = self.search.search_id;
+        let context = self.search.context_lines(&self.config, &self.proxy);
+        let mut unopened = Vec::new();
+        for path in stale {
+            match self.main_split.open_files.get(&path).filter(|b| b.loaded) {
+                Some(buffer) => {
+                    let text = buffer.rope.to_string();
+                    let matches = find_matches(&regex, &text);
+                    let lines = find_context(&text, &matches, context);
+                    let rev = buffer.rev;
+                    Arc::make_mut(&mut self.search)
+                        .refresh_file(search_id, &path, matches, lines, rev);
+                }
+                None => unopened.push(path),
+            }
+        }
+        let root = match self.workspace.path.as_ref() {
+            Some(root) => root,
+            None => return,
+        };
+        let (unopened, include): (Vec<PathBuf>, Vec<String>) = unopened
+            .into_iter()
+            .filter_map(|path| {
+                let pattern = include_pattern(root, &path)?;
+                Some((path, pattern))
+            })
+            .unzip();
+        if unopened.is_empty() {
+            return;
+        }
+        let event_sink = ctx.get_external_handle();
+        let tab_id = self.id;
+        self.proxy.global_search(
+            0,
+            self.search.query.clone(),
+            &self.search.options,
+            &include,
+            context,
+            Box::new(move |result| {
+                let results = result
+                    .ok()
+                    .and_then(|results| search_results(results, context));
+                match results {
+                    Some((matches, context)) => {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::SearchFilesRefreshed(
+                                search_id,
+                                unopened,
+                                Arc::new(matches),
+                                Arc::new(context),
+                            ),
+                            Target::Widget(tab_id),
+                        );
+                    }
+                    None => log::error!("can't refresh the search results"),
+                }
+            }),
+        );
+    }
+
+    /// Runs the workspace search again after its options changed, and saves
+    /// them with the workspace.
+    fn rerun_search(&mut self, ctx: &mut EventCtx) {
+        let pattern = self
+            .main_split
+            .local_buffers
+            .get(&LocalBufferKind::Search)
+            .unwrap()
+            .rope
+            .to_string();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::UpdateSearch(pattern),
+            Target::Widget(self.id),
+        ));
+        let _ = self.db.save_workspace_async(self);
+    }
+
+    /// The editor to focus when the editors panel is shown: the active one if
+    /// it's in the panel, or else any editor in it.
+    fn editors_panel_focus(&self) -> WidgetId {
+        let split_id = Some(self.editors_panel.split_id);
+        if let Some(active) = *self.main_split.active {
+            if let Some(editor) = self.main_split.editors.get(&active) {
+                if editor.split_id == split_id {
+                    return active;
+                }
+            }
+        }
+        self.main_split
+            .editors
+            .values()
+            .find(|editor| editor.split_id == split_id)
+            .map(|editor| editor.view_id)
+            .unwrap_or(self.editors_panel.split_id)
+    }
+
+    /// Lists and shows the editors panel in the bottom area, or takes it out
+    /// of the list once it has no editors left.
+    pub fn update_editors_panel(&mut self, has_editors: bool) {
+        let listed = self
+            .panel_position(PanelKind::Editors)
+            .unwrap_or(PanelPosition::BottomLeft);
+        let panel = match self.panels.get_mut(&listed) {
+            Some(panel) => Arc::make_mut(panel),
+            None => return,
+        };
+        if has_editors {
+            if !panel.widgets.contains(&PanelKind::Editors) {
+                panel.widgets.push(PanelKind::Editors);
+            }
+            panel.active = PanelKind::Editors;
+            panel.shown = true;
+        } else {
+            panel.widgets.retain(|kind| kind != &PanelKind::Editors);
+            if panel.active == PanelKind::Editors {
+                if let Some(kind) = panel.widgets.first() {
+                    panel.active = *kind;
                 }
             }
         }
     }
 
+    /// Moves the active editor to the other split, if it's in the `from` one.
+    fn move_active_editor(
+        &mut self,
+        ctx: &mut EventCtx,
+        from: WidgetId,
+        to: WidgetId,
+    ) {
+        let view_id = match *self.main_split.active {
+            Some(view_id) => view_id,
+            None => return,
+        };
+        let in_split = self
+            .main_split
+            .editors
+            .get(&view_id)
+            .map(|editor| editor.split_id == Some(from))
+            .unwrap_or(false);
+        if in_split {
+            if from == self.editors_panel.split_id {
+                // the panel split only gets events while its panel is shown
+                self.update_editors_panel(true);
+            }
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SplitEditorTransfer(view_id, to),
+                Target::Widget(from),
+            ));
+        }
+    }
+
     fn toggle_panel(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
         if self.focus_area == FocusArea::Panel(kind) {
             self.hide_panel(ctx, kind);
@@ -1194,54 +2980,117 @@ impl LapceTabData {
 
     pub fn buffer_update_process(
         tab_id: WidgetId,
+        workspace: Arc<LapceWorkspace>,
         receiver: Receiver<UpdateEvent>,
         event_sink: ExtEventSink,
     ) {
         let mut parsers = HashMap::new();
         let mut highlighter = Highlighter::new();
         let mut highlight_configs = HashMap::new();
+        // the backups and the saves of the scratches wait for the edits to
+        // pause, so that they're not written on each key
+        let mut backups: HashMap<PathBuf, Rope> = HashMap::new();
+        let mut scratches: HashSet<PathBuf> = HashSet::new();
+        let mut write_at: Option<Instant> = None;
+        // the diffs with the versions of the files wait for the edits to
+        // pause, keeping the latest text of each
+        let mut diffs: HashMap<(PathBuf, String), HistoryDiff> = HashMap::new();
+        let mut diff_at: Option<Instant> = None;
         loop {
-            match receiver.recv() {
-                Err(_) => return,
-                Ok(event) => {
-                    match event {
-                        UpdateEvent::Buffer(update) => {
-                            buffer_receive_update(
-                                update,
-                                &mut parsers,
-                                &mut highlighter,
-                                &mut highlight_configs,
-                                &event_sink,
-                                tab_id,
-                            );
-                        }
-                        UpdateEvent::SemanticTokens(update, tokens) => {
-                            let mut highlights =
-                                SpansBuilder::new(update.rope.len());
-                            for (start, end, hl) in tokens {
-                                highlights.add_span(
-                                    Interval::new(start, end),
-                                    Style {
-                                        fg_color: Some(hl.to_string()),
-                                    },
+            let event = match write_at.into_iter().chain(diff_at).min() {
+                Some(at) => match receiver
+                    .recv_timeout(at.saturating_duration_since(Instant::now()))
+                {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let now = Instant::now();
+                        if write_at.map(|at| at <= now).unwrap_or(false) {
+                            for (path, rope) in backups.drain() {
+                                if let Err(e) = recovery::write_backup(
+                                    &workspace,
+                                    &path,
+                                    &rope.to_string(),
+                                ) {
+                                    log::error!("can't back up {:?}: {}", path, e);
+                                }
+                            }
+                            for path in scratches.drain() {
+                                let _ = event_sink.submit_command(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::SaveScratch(path),
+                                    Target::Widget(tab_id),
                                 );
                             }
-                            let highlights = highlights.build();
-                            event_sink.submit_command(
-                                LAPCE_UI_COMMAND,
-                                LapceUICommand::UpdateStyle {
-                                    id: update.id,
-                                    path: update.path,
-                                    rev: update.rev,
-                                    highlights,
-                                    semantic_tokens: true,
-                                },
-                                Target::Widget(tab_id),
-                            );
+                            write_at = None;
                         }
-                    };
+                        if diff_at.map(|at| at <= now).unwrap_or(false) {
+                            for (_, diff) in diffs.drain() {
+                                diff.spawn(event_sink.clone(), tab_id);
+                            }
+                            diff_at = None;
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                },
+                None => match receiver.recv() {
+                    Ok(event) => event,
+                    Err(_) => return,
+                },
+            };
+            match event {
+                UpdateEvent::Buffer(update) => {
+                    buffer_receive_update(
+                        update,
+                        &mut parsers,
+                        &mut highlighter,
+                        &mut highlight_configs,
+                        &event_sink,
+                        tab_id,
+                    );
                 }
-            }
+                UpdateEvent::SemanticTokens(update, tokens) => {
+                    let mut highlights = SpansBuilder::new(update.rope.len());
+                    for (start, end, hl) in tokens {
+                        highlights.add_span(
+                            Interval::new(start, end),
+                            Style {
+                                fg_color: Some(hl.to_string()),
+                            },
+                        );
+                    }
+                    let highlights = highlights.build();
+                    event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::UpdateStyle {
+                            id: update.id,
+                            path: update.path,
+                            rev: update.rev,
+                            highlights,
+                            semantic_tokens: true,
+                        },
+                        Target::Widget(tab_id),
+                    );
+                }
+                UpdateEvent::Backup(path, rope) => {
+                    backups.insert(path, rope);
+                    write_at = Some(Instant::now() + BACKUP_DELAY);
+                }
+                UpdateEvent::SaveScratch(path) => {
+                    scratches.insert(path);
+                    write_at = Some(Instant::now() + BACKUP_DELAY);
+                }
+                UpdateEvent::HistoryDiff(diff) => {
+                    diffs.insert((diff.path.clone(), diff.history.clone()), diff);
+                    diff_at = Some(Instant::now() + HISTORY_DIFF_DELAY);
+                }
+                UpdateEvent::RemoveBackup(path) => {
+                    backups.remove(&path);
+                    if let Err(e) = recovery::remove_backup(&workspace, &path) {
+                        log::error!("can't remove the backup of {:?}: {}", path, e);
+                    }
+                }
+            };
         }
     }
 }
@@ -1334,13 +3183,33 @@ impl Register {
 //     SplitActive,
 // }
 
+/// An editor of a file that was closed, kept to reopen it where it was.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosedEditor {
+    pub path: PathBuf,
+    pub position: Position,
+    pub scroll_offset: Vec2,
+    /// The split it was in, and its index there.
+    pub split_id: WidgetId,
+    pub index: usize,
+}
+
 #[derive(Clone, Data, Lens)]
 pub struct LapceMainSplitData {
     pub tab_id: Arc<WidgetId>,
     pub split_id: Arc<WidgetId>,
     pub active: Arc<Option<WidgetId>>,
+    /// The editors that were focused, the latest last.
+    pub focus_history: Arc<FocusHistory>,
+    /// The editors that were closed, the latest last.
+    pub closed_editors: Arc<Vec<ClosedEditor>>,
+    /// The editor the panels open locations to the side in, reused until
+    /// it's closed.
+    pub side_editor: Arc<Option<WidgetId>>,
     pub editors: im::HashMap<WidgetId, Arc<LapceEditorData>>,
     pub editors_order: Arc<Vec<WidgetId>>,
+    pub editors_flex: Arc<Vec<f64>>,
+    pub split_direction: SplitDirection,
     pub open_files: im::HashMap<PathBuf, Arc<BufferNew>>,
     pub local_buffers: im::HashMap<LocalBufferKind, Arc<BufferNew>>,
     pub update_sender: Arc<Sender<UpdateEvent>>,
@@ -1352,6 +3221,10 @@ pub struct LapceMainSplitData {
     pub diagnostics: im::HashMap<PathBuf, Arc<Vec<EditorDiagnostic>>>,
     pub error_count: usize,
     pub warning_count: usize,
+    pub log_view_level: Arc<LevelFilter>,
+    /// The unsaved text of the files being restored, put in their buffers
+    /// once they're loaded.
+    pub pending_backups: im::HashMap<PathBuf, Arc<String>>,
 }
 
 impl LapceMainSplitData {
@@ -1385,6 +3258,155 @@ impl LapceMainSplitData {
         }
     }
 
+    /// Moves the editor to the top of the focus history.
+    pub fn push_focus_history(&mut self, view_id: WidgetId) {
+        Arc::make_mut(&mut self.focus_history).push(view_id);
+    }
+
+    /// Removes a closed editor from the focus history.
+    pub fn forget_focus(&mut self, view_id: WidgetId) {
+        if self.focus_history.contains(view_id) {
+            Arc::make_mut(&mut self.focus_history).forget(view_id);
+        }
+    }
+
+    /// Keeps the editor being closed, at `index` of its split, to reopen it
+    /// later. Editors not showing a file can't be reopened, their text is
+    /// gone with them.
+    pub fn remember_closed(&mut self, view_id: WidgetId, index: usize) {
+        let editor = match self.editors.get(&view_id) {
+            Some(editor) => editor,
+            None => return,
+        };
+        let (path, split_id) = match (&editor.content, editor.split_id) {
+            (BufferContent::File(path), Some(split_id)) => (path.clone(), split_id),
+            _ => return,
+        };
+        let position = self
+            .open_files
+            .get(&path)
+            .map(|buffer| buffer.offset_to_position(editor.cursor.offset()))
+            .unwrap_or_default();
+        let closed = ClosedEditor {
+            path,
+            position,
+            scroll_offset: editor.scroll_offset,
+            split_id,
+            index,
+        };
+        let closed_editors = Arc::make_mut(&mut self.closed_editors);
+        closed_editors.push(closed);
+        if closed_editors.len() > CLOSED_EDITORS_LEN {
+            closed_editors.remove(0);
+        }
+    }
+
+    /// The editor in a split that was focused the latest, other than
+    /// `except`.
+    pub fn previous_focus(&self, except: WidgetId) -> Option<WidgetId> {
+        self.focus_history
+            .latest(Some(except), |id| self.is_in_split(id))
+    }
+
+    /// The editor to go back to from the panels: the one focused the latest,
+    /// or that a panel opened a location in, rather than the active one the
+    /// panels may have changed.
+    pub fn last_focused_editor(&self) -> Option<WidgetId> {
+        self.focus_history
+            .latest(None, |id| self.is_in_split(id))
+            .or(*self.active)
+    }
+
+    /// Opens the location in the editor to the side of the one focused last.
+    /// The first time, that editor is split for it.
+    pub fn jump_to_location_to_side(
+        &mut self,
+        ctx: &mut EventCtx,
+        location: EditorLocationNew,
+        config: &Config,
+    ) {
+        if let Some(side) = self.side_editor.filter(|id| self.is_in_split(*id)) {
+            self.jump_to_location(ctx, Some(side), location, config);
+            return;
+        }
+        let from = self
+            .last_focused_editor()
+            .and_then(|id| self.editors.get(&id))
+            .and_then(|editor| Some((editor.view_id, editor.split_id?)));
+        match from {
+            Some((view_id, split_id)) => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEditorTo(view_id, location, false),
+                    Target::Widget(split_id),
+                ));
+            }
+            None => {
+                self.jump_to_location(ctx, None, location, config);
+            }
+        }
+    }
+
+    /// Previews the location in the preview editor of the split of the
+    /// editor focused last, next to that editor the first time. With `keep`,
+    /// the preview editor becomes a normal one and is focused.
+    pub fn preview_location(
+        &mut self,
+        ctx: &mut EventCtx,
+        location: EditorLocationNew,
+        keep: bool,
+        config: &Config,
+    ) {
+        if !config.search.preview_results {
+            self.jump_to_location(ctx, None, location, config);
+            return;
+        }
+        let from = match self
+            .last_focused_editor()
+            .and_then(|id| self.editors.get(&id))
+            .and_then(|editor| Some((editor.view_id, editor.split_id?)))
+        {
+            Some(from) => from,
+            None => {
+                self.jump_to_location(ctx, None, location, config);
+                return;
+            }
+        };
+        let preview = self
+            .editors
+            .values()
+            .find(|editor| editor.preview && editor.split_id == Some(from.1))
+            .map(|editor| editor.view_id);
+        match preview {
+            Some(view_id) => {
+                self.jump_to_location(ctx, Some(view_id), location, config);
+                if keep {
+                    Arc::make_mut(self.editors.get_mut(&view_id).unwrap()).preview =
+                        false;
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::Focus,
+                        Target::Widget(view_id),
+                    ));
+                }
+            }
+            None => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEditorTo(from.0, location, true),
+                    Target::Widget(from.1),
+                ));
+            }
+        }
+    }
+
+    fn is_in_split(&self, view_id: WidgetId) -> bool {
+        self.editors
+            .get(&view_id)
+            .map(|editor| editor.split_id.is_some())
+            .unwrap_or(false)
+    }
+
     // pub fn active_editor_mut(&mut self) -> &mut LapceEditorData {
     //     Arc::make_mut(self.editors.get_mut(&self.active).unwrap())
     // }
@@ -1397,7 +3419,30 @@ impl LapceMainSplitData {
                 self.local_buffers.get(kind).unwrap().clone()
             }
         };
-        buffer
+        buffer
+    }
+
+    /// Shows the file at `index` of the files the editor showed, where it
+    /// was left.
+    pub fn show_shown_file(
+        &mut self,
+        ctx: &mut EventCtx,
+        editor_view_id: WidgetId,
+        index: usize,
+        config: &Config,
+    ) {
+        if !self.editors.contains_key(&editor_view_id) {
+            return;
+        }
+        let buffer = self.editor_buffer(editor_view_id);
+        let editor = Arc::make_mut(self.editors.get_mut(&editor_view_id).unwrap());
+        let location = match editor.shown_files.get(index) {
+            Some(location) => location.clone(),
+            None => return,
+        };
+        editor.save_shown_file(&buffer);
+        editor.current_shown = index;
+        self.go_to_location(ctx, Some(editor_view_id), location, config);
     }
 
     pub fn document_format(
@@ -1405,40 +3450,59 @@ impl LapceMainSplitData {
         ctx: &mut EventCtx,
         path: &PathBuf,
         rev: u64,
-        result: &Result<Value>,
+        region: &Option<Range<usize>>,
+        result: &Result<Formatting>,
     ) {
         let buffer = self.open_files.get(path).unwrap();
         if buffer.rev != rev {
             return;
         }
 
-        if let Ok(res) = result {
-            let edits: Result<Vec<TextEdit>, serde_json::Error> =
-                serde_json::from_value(res.clone());
-            if let Ok(edits) = edits {
-                if edits.len() > 0 {
-                    let buffer = self.open_files.get_mut(path).unwrap();
-
-                    let edits: Vec<(Selection, String)> = edits
-                        .iter()
-                        .map(|edit| {
-                            let selection = Selection::region(
-                                buffer.offset_of_position(&edit.range.start),
-                                buffer.offset_of_position(&edit.range.end),
-                            );
-                            (selection, edit.new_text.clone())
-                        })
-                        .collect();
-
-                    self.edit(
-                        ctx,
-                        &path,
-                        edits.iter().map(|(s, c)| (s, c.as_ref())).collect(),
-                        EditType::Other,
-                    );
-                }
+        let edits: Vec<(usize, usize, String)> = match result {
+            Ok(Formatting::Edits(value)) => {
+                let edits: Vec<TextEdit> =
+                    match serde_json::from_value(value.clone()) {
+                        Ok(edits) => edits,
+                        Err(_) => return,
+                    };
+                edits
+                    .iter()
+                    .map(|edit| {
+                        (
+                            buffer.offset_of_position(&edit.range.start),
+                            buffer.offset_of_position(&edit.range.end),
+                            edit.new_text.clone(),
+                        )
+                    })
+                    .collect()
             }
+            // only the lines the formatter changed are edited
+            Ok(Formatting::Text(text)) => text_edits(&buffer.rope.to_string(), text)
+                .into_iter()
+                .map(|(range, text)| (range.start, range.end, text))
+                .collect(),
+            Err(_) => return,
+        };
+
+        // a narrowed editor only formats its region
+        let edits: Vec<(Selection, String)> = edits
+            .into_iter()
+            .filter(|(start, end, _)| match region {
+                Some(region) => region.start <= *start && *end <= region.end,
+                None => true,
+            })
+            .map(|(start, end, text)| (Selection::region(start, end), text))
+            .collect();
+        if edits.is_empty() {
+            return;
         }
+
+        self.edit(
+            ctx,
+            &path,
+            edits.iter().map(|(s, c)| (s, c.as_ref())).collect(),
+            EditType::Other,
+        );
     }
 
     pub fn document_format_and_save(
@@ -1446,28 +3510,81 @@ impl LapceMainSplitData {
         ctx: &mut EventCtx,
         path: &PathBuf,
         rev: u64,
-        result: &Result<Value>,
+        region: &Option<Range<usize>>,
+        result: &Result<Formatting>,
     ) {
-        self.document_format(ctx, path, rev, result);
+        self.document_format(ctx, path, rev, region, result);
+        self.save_buffer(ctx, path);
+    }
+
+    /// Saves the buffer of the file as it is, after the edits its
+    /// `.editorconfig` asks for.
+    pub fn save_buffer(&mut self, ctx: &mut EventCtx, path: &PathBuf) {
+        self.apply_editorconfig(ctx, path);
 
         let buffer = self.open_files.get(path).unwrap();
         let rev = buffer.rev;
         let buffer_id = buffer.id;
         let event_sink = ctx.get_external_handle();
         let path = path.clone();
-        self.proxy.save(
-            rev,
-            buffer_id,
-            Box::new(move |result| {
-                if let Ok(r) = result {
-                    event_sink.submit_command(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::BufferSave(path, rev),
-                        Target::Auto,
-                    );
-                }
-            }),
+        self.proxy
+            .save(rev, buffer_id, save_callback(path, rev, event_sink));
+    }
+
+    /// Trims the trailing whitespace and inserts the final newline that the
+    /// `.editorconfig` properties of the file ask for before it's saved.
+    fn apply_editorconfig(&mut self, ctx: &mut EventCtx, path: &PathBuf) {
+        let buffer = self.open_files.get(path).unwrap();
+        let edits: Vec<(Selection, String)> = buffer
+            .editorconfig
+            .save_edits(&buffer.rope)
+            .into_iter()
+            .map(|(start, end, text)| (Selection::region(start, end), text))
+            .collect();
+        if !edits.is_empty() {
+            self.edit(
+                ctx,
+                path,
+                edits.iter().map(|(s, c)| (s, c.as_ref())).collect(),
+                EditType::Other,
+            );
+        }
+    }
+
+    /// Replaces lines of an open file in one undo step, as (line number from
+    /// 1, text, replacement). Nothing is replaced when a line isn't the text
+    /// anymore.
+    pub fn replace_lines(
+        &mut self,
+        ctx: &mut EventCtx,
+        path: &PathBuf,
+        lines: &[(usize, String, String)],
+    ) -> Result<(), String> {
+        let buffer = self
+            .open_files
+            .get(path)
+            .ok_or_else(|| "the file isn't open".to_string())?;
+        let mut edits = Vec::new();
+        for (line_number, old, new) in lines {
+            let line = line_number - 1;
+            if line > buffer.last_line()
+                || buffer
+                    .line_content(line)
+                    .trim_end_matches(|c| c == '\n' || c == '\r')
+                    != old
+            {
+                return Err(format!("line {} changed", line_number));
+            }
+            let start = buffer.offset_of_line(line);
+            edits.push((Selection::region(start, start + old.len()), new.clone()));
+        }
+        self.edit(
+            ctx,
+            path,
+            edits.iter().map(|(s, c)| (s, c.as_ref())).collect(),
+            EditType::Other,
         );
+        Ok(())
     }
 
     fn initiate_diagnositcs_offset(&mut self, path: &PathBuf) {
@@ -1558,6 +3675,134 @@ impl LapceMainSplitData {
         Some(delta)
     }
 
+    /// Keeps the narrowed editors in sync with the files they're narrowed
+    /// from, after the edits of either, and drops the local buffers of the
+    /// narrowed editors that were closed.
+    pub fn sync_narrowed(&mut self, ctx: &mut EventCtx) {
+        let narrowed: Vec<WidgetId> = self
+            .editors
+            .iter()
+            .filter(|(_, editor)| editor.narrow.is_some())
+            .map(|(view_id, _)| *view_id)
+            .collect();
+        for view_id in narrowed {
+            self.sync_narrow(ctx, view_id);
+        }
+
+        let closed: Vec<LocalBufferKind> = self
+            .local_buffers
+            .keys()
+            .filter(|kind| {
+                matches!(kind, LocalBufferKind::Narrow(_))
+                    && !self.editors.values().any(|editor| {
+                        matches!(&editor.content, BufferContent::Local(k) if k == *kind)
+                    })
+            })
+            .cloned()
+            .collect();
+        for kind in closed {
+            self.local_buffers.remove(&kind);
+        }
+    }
+
+    fn sync_narrow(&mut self, ctx: &mut EventCtx, view_id: WidgetId) {
+        let editor = self.editors.get(&view_id).unwrap();
+        let mut narrow = match editor.narrow.as_ref() {
+            Some(narrow) => (**narrow).clone(),
+            None => return,
+        };
+        let kind = LocalBufferKind::Narrow(view_id.to_raw());
+        let (file, local) = match (
+            self.open_files.get(&narrow.path),
+            self.local_buffers.get(&kind),
+        ) {
+            (Some(file), Some(local)) => (file.clone(), local.clone()),
+            _ => return,
+        };
+
+        let mut changed = false;
+        let file_changed = file.rev != narrow.file_rev;
+        if file_changed {
+            narrow.follow(&file.rope);
+            narrow.file_rev = file.rev;
+            changed = true;
+        }
+        if local.rev != narrow.local_rev {
+            // edited in the narrowed editor, so the file gets the same edit
+            let region = file.slice_to_cow(narrow.start..narrow.end).to_string();
+            if let Some((range, text)) =
+                region_edit(&region, &local.rope.to_string())
+            {
+                let selection = Selection::region(
+                    narrow.start + range.start,
+                    narrow.start + range.end,
+                );
+                let edit_type = match local.last_edit_type() {
+                    EditType::Undo | EditType::Redo => EditType::Other,
+                    edit_type => edit_type,
+                };
+                self.edit(
+                    ctx,
+                    &narrow.path,
+                    vec![(&selection, text.as_str())],
+                    edit_type,
+                );
+            }
+            let file = self.open_files.get(&narrow.path).unwrap();
+            narrow.end = narrow.start + local.len();
+            narrow.text = file.rope.clone();
+            narrow.file_rev = file.rev;
+            narrow.local_rev = local.rev;
+            changed = true;
+        } else if file_changed {
+            // edited elsewhere, or reloaded
+            let region = file.slice_to_cow(narrow.start..narrow.end).to_string();
+            if let Some((range, text)) =
+                region_edit(&local.rope.to_string(), &region)
+            {
+                let proxy = self.proxy.clone();
+                let local =
+                    Arc::make_mut(self.local_buffers.get_mut(&kind).unwrap());
+                let delta = local.edit(
+                    ctx,
+                    &Selection::region(range.start, range.end),
+                    &text,
+                    proxy,
+                    EditType::Other,
+                );
+                narrow.local_rev = local.rev;
+                Arc::make_mut(self.editors.get_mut(&view_id).unwrap())
+                    .cursor
+                    .apply_delta(&delta);
+            }
+        }
+
+        // the local buffer shows the highlights of the file
+        let file = self.open_files.get(&narrow.path).unwrap();
+        let styles_changed = narrow
+            .styles
+            .as_ref()
+            .map(|styles| !Arc::ptr_eq(styles, &file.styles))
+            .unwrap_or(true);
+        if styles_changed && file.styles.len() == file.len() {
+            narrow.styles = Some(file.styles.clone());
+            let styles = file.styles.subseq(narrow.start..narrow.end);
+            let local = Arc::make_mut(self.local_buffers.get_mut(&kind).unwrap());
+            local.update_styles(local.rev, styles, false);
+            changed = true;
+        }
+        let local = self.local_buffers.get(&kind).unwrap();
+        if local.dirty != file.dirty {
+            let dirty = file.dirty;
+            Arc::make_mut(self.local_buffers.get_mut(&kind).unwrap()).dirty = dirty;
+        }
+
+        if changed {
+            Arc::make_mut(self.editors.get_mut(&view_id).unwrap()).narrow =
+                Some(Arc::new(narrow));
+        }
+    }
+
     fn get_editor_or_new(
         &mut self,
         ctx: &mut EventCtx,
@@ -1605,6 +3850,7 @@ impl LapceMainSplitData {
                     position: Some(position),
                     scroll_offset: None,
                     hisotry: None,
+                    selection_end: None,
                 };
                 self.jump_to_location(ctx, editor_view_id, location, config);
             }
@@ -1625,6 +3871,10 @@ impl LapceMainSplitData {
         let editor = self.get_editor_or_new(ctx, Some(editor_view_id), config);
         editor.save_jump_location(&buffer);
         self.go_to_location(ctx, Some(editor_view_id), location, config);
+        // going back from the panel that jumped ends up there
+        if self.is_in_split(editor_view_id) {
+            self.push_focus_history(editor_view_id);
+        }
         editor_view_id
     }
 
@@ -1643,6 +3893,10 @@ impl LapceMainSplitData {
             BufferContent::Local(_) => true,
         };
         let path = location.path.clone();
+        if new_buffer && editor_view_id != *self.palette_preview_editor {
+            let editor = self.get_editor_or_new(ctx, Some(editor_view_id), config);
+            editor.show_file(&buffer, &path);
+        }
         let buffer_exists = self.open_files.contains_key(&path);
         if !buffer_exists {
             let buffer = Arc::new(BufferNew::new(
@@ -1679,13 +3933,33 @@ impl LapceMainSplitData {
                 }
             }
 
+            let selection_end = location
+                .selection_end
+                .as_ref()
+                .filter(|_| location.position.is_some())
+                .map(|end| buffer.offset_of_position(end))
+                .filter(|end| *end > offset);
+
             let editor = self.get_editor_or_new(ctx, Some(editor_view_id), config);
             editor.content = BufferContent::File(path.clone());
             editor.compare = location.hisotry.clone();
-            editor.cursor = if config.lapce.modal {
-                Cursor::new(CursorMode::Normal(offset), None)
-            } else {
-                Cursor::new(CursorMode::Insert(Selection::caret(offset)), None)
+            editor.cursor = match (config.lapce.modal, selection_end) {
+                (true, Some(end)) => Cursor::new(
+                    CursorMode::Visual {
+                        start: offset,
+                        end: buffer.prev_grapheme_offset(end, 1, offset),
+                        mode: VisualMode::Normal,
+                    },
+                    None,
+                ),
+                (true, None) => Cursor::new(CursorMode::Normal(offset), None),
+                (false, Some(end)) => Cursor::new(
+                    CursorMode::Insert(Selection::region(offset, end)),
+                    None,
+                ),
+                (false, None) => {
+                    Cursor::new(CursorMode::Insert(Selection::caret(offset)), None)
+                }
             };
 
             if let Some(scroll_offset) = scroll_offset {
@@ -1737,6 +4011,7 @@ impl LapceMainSplitData {
 impl LapceMainSplitData {
     pub fn new(
         tab_id: WidgetId,
+        workspace: &LapceWorkspace,
         workspace_info: Option<&WorkspaceInfo>,
         palette_preview_editor: WidgetId,
         update_sender: Arc<Sender<UpdateEvent>>,
@@ -1749,17 +4024,66 @@ impl LapceMainSplitData {
         let mut open_files = im::HashMap::new();
         let mut editors = im::HashMap::new();
         let mut editors_order = Vec::new();
+        let mut editors_flex = Vec::new();
+        let mut split_direction = SplitDirection::Vertical;
 
         let mut active = None;
         if let Some(info) = workspace_info {
+            split_direction = info.split.direction;
             let mut positions = HashMap::new();
-            for (i, e) in info.editors.iter().enumerate() {
-                let editor = LapceEditorData::new(
+            for (i, child) in info.split.children.iter().enumerate() {
+                let e = &child.editor;
+                // files removed since the layout was saved are dropped, the
+                // proxy can't open them anyway
+                match &e.content {
+                    BufferContent::File(path) => {
+                        if workspace.kind == LapceWorkspaceType::Local
+                            && !path.exists()
+                        {
+                            continue;
+                        }
+                    }
+                    BufferContent::Local(_) => continue,
+                }
+                let mut editor = LapceEditorData::new(
                     None,
                     Some(*split_id),
                     e.content.clone(),
                     config,
                 );
+                editor.current_shown = e.current_shown;
+                for (index, file) in e.shown_files.iter().enumerate() {
+                    if workspace.kind == LapceWorkspaceType::Local
+                        && !file.path.exists()
+                    {
+                        if index < e.current_shown {
+                            editor.current_shown -= 1;
+                        }
+                        continue;
+                    }
+                    editor.shown_files.push(EditorLocationNew {
+                        path: file.path.clone(),
+                        position: file.position,
+                        scroll_offset: file
+                            .scroll_offset
+                            .map(|(x, y)| Vec2::new(x, y)),
+                        hisotry: None,
+                        selection_end: None,
+                    });
+                }
+                // saved before editors kept the files they showed
+                if editor.shown_files.is_empty() {
+                    if let BufferContent::File(path) = &e.content {
+                        editor.shown_files.push(EditorLocationNew {
+                            path: path.clone(),
+                            position: None,
+                            scroll_offset: None,
+                            hisotry: None,
+                            selection_end: None,
+                        });
+                        editor.current_shown = 0;
+                    }
+                }
                 if info.active_editor == i {
                     active = Some(editor.view_id);
                 }
@@ -1779,6 +4103,7 @@ impl LapceMainSplitData {
                                     e.scroll_offset.1,
                                 )),
                                 hisotry: None,
+                                selection_end: None,
                             },
                         ));
 
@@ -1795,8 +4120,12 @@ impl LapceMainSplitData {
                     BufferContent::Local(_) => {}
                 }
                 editors_order.push(editor.view_id);
+                editors_flex.push(child.flex);
                 editors.insert(editor.view_id, Arc::new(editor));
             }
+            if active.is_none() {
+                active = editors_order.first().cloned();
+            }
             for (path, locations) in positions.into_iter() {
                 open_files.get(&path).unwrap().retrieve_file(
                     tab_id,
@@ -1847,15 +4176,31 @@ impl LapceMainSplitData {
                 event_sink.clone(),
             )),
         );
+        let mut log_buffer = BufferNew::new(
+            BufferContent::Local(LocalBufferKind::Log),
+            update_sender.clone(),
+            tab_id,
+            event_sink.clone(),
+        )
+        .set_local();
+        log_buffer.load_content(
+            &logging::recent_lines(LevelFilter::Info, LOG_VIEW_LINES).join("\n"),
+        );
+        local_buffers.insert(LocalBufferKind::Log, Arc::new(log_buffer));
 
         Self {
             tab_id: Arc::new(tab_id),
             split_id,
             editors,
             editors_order: Arc::new(editors_order),
+            editors_flex: Arc::new(editors_flex),
+            split_direction,
             open_files,
             local_buffers,
             active: Arc::new(active),
+            focus_history: Arc::new(FocusHistory::new(active)),
+            closed_editors: Arc::new(Vec::new()),
+            side_editor: Arc::new(None),
             update_sender,
             register: Arc::new(Register::default()),
             proxy,
@@ -1865,6 +4210,193 @@ impl LapceMainSplitData {
             diagnostics: im::HashMap::new(),
             error_count: 0,
             warning_count: 0,
+            log_view_level: Arc::new(LevelFilter::Info),
+            pending_backups: im::HashMap::new(),
+        }
+    }
+
+    /// Focuses the log view, opening it in the split first if there's none.
+    pub fn open_log_view(&mut self, ctx: &mut EventCtx, config: &Config) {
+        self.refresh_log_view(ctx);
+        let existing = self
+            .editors
+            .values()
+            .find(|e| e.content == BufferContent::Local(LocalBufferKind::Log))
+            .map(|e| e.view_id);
+        match existing {
+            Some(view_id) => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(view_id),
+                ));
+            }
+            None => {
+                let editor = LapceEditorData::new(
+                    None,
+                    Some(*self.split_id),
+                    BufferContent::Local(LocalBufferKind::Log),
+                    config,
+                );
+                let view_id = editor.view_id;
+                self.editors.insert(view_id, Arc::new(editor));
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitAddEditor(view_id),
+                    Target::Widget(*self.split_id),
+                ));
+            }
+        }
+    }
+
+    /// Opens an untitled buffer, a plain text scratch that "Save As" turns
+    /// into a file, in the active editor if it's empty, else in a new editor
+    /// next to it.
+    pub fn new_file(
+        &mut self,
+        ctx: &mut EventCtx,
+        workspace: &LapceWorkspace,
+        config: &Config,
+    ) {
+        if workspace.kind != LapceWorkspaceType::Local {
+            self.new_proxy_file(ctx, "txt");
+            return;
+        }
+        let path = match new_scratch(workspace, "txt") {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!("create new file error {}", e);
+                return;
+            }
+        };
+        let active = (*self.active).and_then(|id| self.editors.get(&id));
+        let view_id = match active {
+            Some(editor)
+                if editor.content
+                    == BufferContent::Local(LocalBufferKind::Empty) =>
+            {
+                editor.view_id
+            }
+            _ => {
+                let split_id = active
+                    .and_then(|editor| editor.split_id)
+                    .unwrap_or(*self.split_id);
+                let editor = LapceEditorData::new(
+                    None,
+                    Some(split_id),
+                    BufferContent::Local(LocalBufferKind::Empty),
+                    config,
+                );
+                let view_id = editor.view_id;
+                self.editors.insert(view_id, Arc::new(editor));
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitAddEditor(view_id),
+                    Target::Widget(split_id),
+                ));
+                view_id
+            }
+        };
+        let view_id = self.jump_to_location(
+            ctx,
+            Some(view_id),
+            EditorLocationNew {
+                path,
+                position: None,
+                scroll_offset: None,
+                hisotry: None,
+                selection_end: None,
+            },
+            config,
+        );
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(view_id),
+        ));
+    }
+
+    /// Opens a new empty temporary file of the proxy. It stands for a scratch
+    /// in a remote workspace, as the scratches are kept on this machine,
+    /// where the proxy can't open them.
+    pub fn new_proxy_file(&self, ctx: &mut EventCtx, extension: &str) {
+        let event_sink = ctx.get_external_handle();
+        self.proxy.write_temp_file(
+            "",
+            extension,
+            Box::new(move |result| match result {
+                Ok(value) => match serde_json::from_value::<PathBuf>(value) {
+                    Ok(path) => {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::OpenFile(path),
+                            Target::Auto,
+                        );
+                    }
+                    Err(e) => log::error!("invalid path of the new file: {}", e),
+                },
+                Err(e) => show_request_error("Can't create the file", &e),
+            }),
+        );
+    }
+
+    /// Replaces the text of a local buffer, like the search input, with the
+    /// cursors of its editors at the end.
+    pub fn set_local_buffer_text(&mut self, kind: LocalBufferKind, text: &str) {
+        let buffer = Arc::make_mut(self.local_buffers.get_mut(&kind).unwrap());
+        buffer.load_content(text);
+        let offset = buffer.len();
+        let content = BufferContent::Local(kind);
+        for editor in self.editors.values_mut() {
+            if editor.content == content {
+                Arc::make_mut(editor).cursor =
+                    Cursor::new(CursorMode::Insert(Selection::caret(offset)), None);
+            }
+        }
+    }
+
+    /// Reloads the log view with the recent lines that pass its level. The
+    /// views with the cursor on the last line follow the new lines.
+    pub fn refresh_log_view(&mut self, ctx: &mut EventCtx) {
+        let lines = logging::recent_lines(*self.log_view_level, LOG_VIEW_LINES);
+        let buffer = Arc::make_mut(
+            self.local_buffers.get_mut(&LocalBufferKind::Log).unwrap(),
+        );
+        let tailing: Vec<WidgetId> = self
+            .editors
+            .values()
+            .filter(|e| e.content == BufferContent::Local(LocalBufferKind::Log))
+            .filter(|e| {
+                buffer.line_of_offset(e.cursor.offset()) >= buffer.last_line()
+            })
+            .map(|e| e.view_id)
+            .collect();
+        buffer.load_content(&lines.join("\n"));
+
+        let len = buffer.len();
+        let last_line_offset = buffer.offset_of_line(buffer.last_line());
+        for editor in self.editors.values_mut() {
+            if editor.content != BufferContent::Local(LocalBufferKind::Log) {
+                continue;
+            }
+            let editor = Arc::make_mut(editor);
+            let offset = if tailing.contains(&editor.view_id) {
+                last_line_offset
+            } else {
+                editor.cursor.offset().min(len)
+            };
+            editor.cursor = if editor.cursor.is_insert() {
+                Cursor::new(CursorMode::Insert(Selection::caret(offset)), None)
+            } else {
+                Cursor::new(CursorMode::Normal(offset), None)
+            };
+            if tailing.contains(&editor.view_id) {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::EnsureCursorVisible(None),
+                    Target::Widget(editor.view_id),
+                ));
+            }
         }
     }
 
@@ -1928,11 +4460,41 @@ pub struct LapceEditorData {
     pub size: Rc<RefCell<Size>>,
     pub window_origin: Point,
     pub snippet: Option<Vec<(usize, (usize, usize))>>,
+    /// The names of the tag pair being renamed together, as they were after
+    /// the last edit.
+    pub linked_tag: Option<[(usize, usize); 2]>,
     pub locations: Vec<EditorLocationNew>,
     pub current_location: usize,
+    /// The files the editor showed, the latest last, with where the cursor
+    /// and the scroll were when it moved on from each. Unlike `locations`,
+    /// jumps within a file don't add to it.
+    pub shown_files: Vec<EditorLocationNew>,
+    /// The index of the file shown in `shown_files`.
+    pub current_shown: usize,
     pub last_movement: Movement,
     pub last_inline_find: Option<(InlineFindDirection, String)>,
     pub inline_find: Option<InlineFindDirection>,
+    pub pinned: bool,
+    /// Whether it previews a search result, and the next result clicked
+    /// replaces its file. Double clicking the result or editing it keeps
+    /// it open.
+    pub preview: bool,
+    pub pasted: Option<PastedText>,
+    /// Where the editor was in the main split before it was moved to the
+    /// editors panel, so that moving it back puts it in the same place.
+    pub main_split_index: Option<usize>,
+    /// The line whose diagnostics are listed in a popup, after hovering its
+    /// gutter marker or running `ShowLineDiagnostics`.
+    pub line_diagnostics: Option<usize>,
+    /// The change from the head whose popup was opened by clicking its
+    /// marker in the gutter.
+    pub diff_hunk: Option<DiffHunk>,
+    /// How much the font and lines of the editor are scaled, 1.0 when it's
+    /// not zoomed.
+    pub zoom: f64,
+    /// The region of the file the editor is narrowed to, whose text it shows
+    /// in a local buffer.
+    pub narrow: Option<Arc<NarrowRegion>>,
 }
 
 impl LapceEditorData {
@@ -1956,14 +4518,41 @@ impl LapceEditorData {
             compare: None,
             window_origin: Point::ZERO,
             snippet: None,
+            linked_tag: None,
             locations: vec![],
             current_location: 0,
+            shown_files: vec![],
+            current_shown: 0,
             last_movement: Movement::Left,
             inline_find: None,
             last_inline_find: None,
+            pinned: false,
+            preview: false,
+            pasted: None,
+            main_split_index: None,
+            line_diagnostics: None,
+            diff_hunk: None,
+            zoom: 1.0,
+            narrow: None,
+        }
+    }
+
+    /// The file the editor shows, or the one it's narrowed from.
+    pub fn file_path(&self) -> Option<&PathBuf> {
+        match &self.content {
+            BufferContent::File(path) => Some(path),
+            BufferContent::Local(_) => self.narrow.as_ref().map(|n| &n.path),
         }
     }
 
+    /// Zooms the editor in, or out for negative steps.
+    pub fn zoom_by(&mut self, steps: i32) {
+        let zoom = self.zoom + steps as f64 * EDITOR_ZOOM_STEP;
+        self.zoom = ((zoom * 10.0).round() / 10.0)
+            .max(MIN_EDITOR_ZOOM)
+            .min(MAX_EDITOR_ZOOM);
+    }
+
     pub fn add_snippet_placeholders(
         &mut self,
         new_placeholders: Vec<(usize, (usize, usize))>,
@@ -1991,6 +4580,73 @@ impl LapceEditorData {
         placeholders.extend_from_slice(&v[1..]);
     }
 
+    /// Adds the file at `path` to the files shown, as the latest, when the
+    /// editor moves on to it from the file of `buffer`. Moving to the file
+    /// already current, as `cycle_shown_files` does, keeps the order.
+    pub fn show_file(&mut self, buffer: &BufferNew, path: &Path) {
+        if self
+            .shown_files
+            .get(self.current_shown)
+            .map(|f| f.path.as_path())
+            == Some(path)
+        {
+            return;
+        }
+        self.save_shown_file(buffer);
+        self.shown_files.retain(|f| f.path != path);
+        self.shown_files.push(EditorLocationNew {
+            path: path.to_path_buf(),
+            position: None,
+            scroll_offset: None,
+            hisotry: None,
+            selection_end: None,
+        });
+        if self.shown_files.len() > SHOWN_FILES_LEN {
+            self.shown_files.remove(0);
+        }
+        self.current_shown = self.shown_files.len() - 1;
+    }
+
+    /// Keeps where the cursor and the scroll are in the file of `buffer`,
+    /// to be back there when it's shown again.
+    pub fn save_shown_file(&mut self, buffer: &BufferNew) {
+        if let BufferContent::File(path) = &buffer.content {
+            let position = buffer.offset_to_position(self.cursor.offset());
+            let scroll_offset = self.scroll_offset;
+            if let Some(file) = self.shown_files.iter_mut().find(|f| &f.path == path)
+            {
+                file.position = Some(position);
+                file.scroll_offset = Some(scroll_offset);
+            }
+        }
+    }
+
+    /// Makes the file shown before the current one, or after it with
+    /// `forward`, the current one and returns it. The files that don't
+    /// `exist` anymore are dropped on the way.
+    pub fn cycle_shown_files(
+        &mut self,
+        forward: bool,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Option<EditorLocationNew> {
+        loop {
+            let next = if forward {
+                self.current_shown + 1
+            } else {
+                self.current_shown.checked_sub(1)?
+            };
+            let file = self.shown_files.get(next)?;
+            if exists(&file.path) {
+                self.current_shown = next;
+                return Some(file.clone());
+            }
+            self.shown_files.remove(next);
+            if !forward {
+                self.current_shown -= 1;
+            }
+        }
+    }
+
     pub fn save_jump_location(&mut self, buffer: &BufferNew) {
         if let BufferContent::File(path) = &buffer.content {
             let location = EditorLocationNew {
@@ -1998,6 +4654,7 @@ impl LapceEditorData {
                 position: Some(buffer.offset_to_position(self.cursor.offset())),
                 scroll_offset: Some(self.scroll_offset.clone()),
                 hisotry: None,
+                selection_end: None,
             };
             self.locations.push(location);
             self.current_location = self.locations.len();
@@ -2345,6 +5002,7 @@ impl LapceEditorViewData {
                 position: Some(position),
                 scroll_offset: None,
                 hisotry: None,
+                selection_end: None,
             };
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
@@ -2776,6 +5434,50 @@ impl LapceEditorViewData {
     }
 }
 
+/// Marks the buffer saved at `rev`, or shows why the save was refused.
+pub fn save_callback(
+    path: PathBuf,
+    rev: u64,
+    event_sink: ExtEventSink,
+) -> Box<dyn Callback> {
+    Box::new(move |result: Result<Value, Value>| match result {
+        Ok(_) => {
+            event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::BufferSave(path, rev),
+                Target::Auto,
+            );
+        }
+        Err(e) => {
+            if let Some((conflict, disk)) = SaveConflict::from_error(&e) {
+                event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::BufferSaveConflict(path, conflict, disk),
+                    Target::Auto,
+                );
+            }
+        }
+    })
+}
+
+/// Tells about the error of a request to the proxy in a dialog.
+pub(crate) fn show_request_error(title: &'static str, error: &Value) {
+    let message = error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .unwrap_or("")
+        .to_string();
+    log::error!("{}: {}", title, message);
+    // away from the thread of the proxy's responses
+    thread::spawn(move || {
+        tinyfiledialogs::message_box_ok(
+            title,
+            &message,
+            tinyfiledialogs::MessageBoxIcon::Error,
+        );
+    });
+}
+
 fn next_in_file_errors_offset(
     position: Position,
     path: &PathBuf,
@@ -2822,6 +5524,7 @@ fn process_get_references(
                     position: Some(location.range.start.clone()),
                     scroll_offset: None,
                     hisotry: None,
+                    selection_end: None,
                 },
             ),
             Target::Auto,
@@ -1,22 +1,33 @@
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
 use directories::ProjectDirs;
 use druid::Vec2;
 use lsp_types::Position;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    buffer::BufferContent,
+    buffer::{BufferContent, UndoSnapshot},
     data::{EditorContent, LapceData, LapceTabData, LapceWindowData},
+    history::WorkspaceHistory,
+    indexing::IndexScope,
     movement::Cursor,
+    search::{PinnedSearch, SearchOptions},
+    split::SplitDirection,
     state::LapceWorkspace,
+    undo_history::UndoHistory,
 };
 
 pub enum SaveEvent {
     Workspace(LapceWorkspace, WorkspaceInfo),
     Tabs(TabsInfo),
+    History(LapceWorkspace, WorkspaceHistory),
+    /// The undo groups of the open files, to keep at most the given size of
+    /// undo steps of each.
+    UndoHistories(Vec<(PathBuf, UndoSnapshot)>, usize),
+    /// Answers once what was sent to save before it is written.
+    Flush(Sender<()>),
 }
 
 #[derive(Clone)]
@@ -25,23 +36,124 @@ pub struct LapceDb {
     save_tx: Sender<SaveEvent>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "SavedWorkspaceInfo")]
 pub struct WorkspaceInfo {
     pub active_editor: usize,
-    pub editors: Vec<EditorInfo>,
+    pub split: SplitInfo,
+    pub search_options: SearchOptions,
+    pub pinned_searches: Vec<PinnedSearch>,
+    /// Whether the search results show lines of context.
+    pub search_context: bool,
+    /// What the file palette indexes, once it was picked.
+    pub index_scope: Option<IndexScope>,
+    /// How many terminals were open, to open them again after a crash.
+    pub terminals: usize,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// A `WorkspaceInfo` as any version saved it. Before the split layout was
+/// kept, the editors were a flat list side by side.
+#[derive(Deserialize)]
+struct SavedWorkspaceInfo {
+    active_editor: usize,
+    split: Option<SplitInfo>,
+    #[serde(default)]
+    editors: Vec<EditorInfo>,
+    #[serde(default)]
+    search_options: SearchOptions,
+    #[serde(default)]
+    pinned_searches: Vec<PinnedSearch>,
+    #[serde(default)]
+    search_context: bool,
+    #[serde(default)]
+    index_scope: Option<IndexScope>,
+    #[serde(default)]
+    terminals: usize,
+}
+
+impl From<SavedWorkspaceInfo> for WorkspaceInfo {
+    fn from(info: SavedWorkspaceInfo) -> Self {
+        let editors = info.editors;
+        let split = info.split.unwrap_or_else(|| SplitInfo {
+            direction: SplitDirection::Vertical,
+            children: editors
+                .into_iter()
+                .map(|editor| SplitChildInfo { flex: 1.0, editor })
+                .collect(),
+        });
+        Self {
+            active_editor: info.active_editor,
+            split,
+            search_options: info.search_options,
+            pinned_searches: info.pinned_searches,
+            search_context: info.search_context,
+            index_scope: info.index_scope,
+            terminals: info.terminals,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SplitInfo {
+    pub direction: SplitDirection,
+    pub children: Vec<SplitChildInfo>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SplitChildInfo {
+    pub flex: f64,
+    pub editor: EditorInfo,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TabsInfo {
     pub active_tab: usize,
     pub workspaces: Vec<LapceWorkspace>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl TabsInfo {
+    /// The tabs a new window opens with the `startup.behavior` of the
+    /// config.
+    pub fn for_startup(self, behavior: &str) -> TabsInfo {
+        match behavior {
+            "empty" => TabsInfo {
+                active_tab: 0,
+                workspaces: Vec::new(),
+            },
+            "last-workspace" => {
+                let workspace = self
+                    .workspaces
+                    .get(self.active_tab)
+                    .or_else(|| self.workspaces.first())
+                    .cloned();
+                TabsInfo {
+                    active_tab: 0,
+                    workspaces: workspace.into_iter().collect(),
+                }
+            }
+            _ => self,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EditorInfo {
     pub content: BufferContent,
     pub scroll_offset: (f64, f64),
     pub position: Option<Position>,
+    /// The files the editor showed, the latest last, and the index of the
+    /// current one.
+    #[serde(default)]
+    pub shown_files: Vec<ShownFileInfo>,
+    #[serde(default)]
+    pub current_shown: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShownFileInfo {
+    pub path: PathBuf,
+    pub scroll_offset: Option<(f64, f64)>,
+    pub position: Option<Position>,
 }
 
 impl LapceDb {
@@ -63,12 +175,39 @@ impl LapceDb {
                     SaveEvent::Tabs(info) => {
                         local_db.insert_tabs(&info);
                     }
+                    SaveEvent::History(workspace, history) => {
+                        if let Err(e) = history.save(&workspace) {
+                            log::error!("can't save the history: {}", e);
+                        }
+                    }
+                    SaveEvent::UndoHistories(snapshots, max_size) => {
+                        if let Err(e) =
+                            local_db.insert_undo_histories(&snapshots, max_size)
+                        {
+                            log::error!("can't save the undo histories: {}", e);
+                        }
+                    }
+                    SaveEvent::Flush(done) => {
+                        let _ = done.send(());
+                    }
                 }
             }
         });
         Ok(db)
     }
 
+    /// A db in `dir` that drops what it's asked to save in the background, so
+    /// that tests don't write the history of the config dir.
+    #[cfg(test)]
+    pub fn for_tests(dir: &std::path::Path) -> Self {
+        let (save_tx, save_rx) = unbounded();
+        std::thread::spawn(move || save_rx.iter().for_each(drop));
+        Self {
+            path: dir.join("lapce.db"),
+            save_tx,
+        }
+    }
+
     pub fn get_db(&self) -> Result<sled::Db> {
         let db = sled::Config::default()
             .path(&self.path)
@@ -121,7 +260,7 @@ impl LapceDb {
         Ok(())
     }
 
-    fn insert_workspace(
+    pub fn insert_workspace(
         &self,
         workspace: &LapceWorkspace,
         info: &WorkspaceInfo,
@@ -145,32 +284,67 @@ impl LapceDb {
             .ok_or(anyhow!("no workspace"))?;
 
         let mut active_editor = 0;
-        let editors = data
+        let children: Vec<SplitChildInfo> = data
             .main_split
             .editors_order
             .iter()
+            .zip(data.main_split.editors_flex.iter())
+            .filter_map(|(view_id, flex)| {
+                let editor = data.main_split.editors.get(view_id)?;
+                // editors without a file, like unsaved buffers, aren't restored
+                let path = match &editor.content {
+                    BufferContent::File(path) => path,
+                    BufferContent::Local(_) => return None,
+                };
+                let buffer = data.main_split.open_files.get(path)?;
+                Some((
+                    *view_id,
+                    SplitChildInfo {
+                        flex: *flex,
+                        editor: EditorInfo {
+                            content: editor.content.clone(),
+                            scroll_offset: (
+                                editor.scroll_offset.x,
+                                editor.scroll_offset.y,
+                            ),
+                            position: Some(
+                                buffer.offset_to_position(editor.cursor.offset()),
+                            ),
+                            shown_files: editor
+                                .shown_files
+                                .iter()
+                                .map(|file| ShownFileInfo {
+                                    path: file.path.clone(),
+                                    scroll_offset: file
+                                        .scroll_offset
+                                        .map(|offset| (offset.x, offset.y)),
+                                    position: file.position,
+                                })
+                                .collect(),
+                            current_shown: editor.current_shown,
+                        },
+                    },
+                ))
+            })
             .enumerate()
-            .map(|(i, view_id)| {
-                if *data.main_split.active == Some(*view_id) {
+            .map(|(i, (view_id, child))| {
+                if *data.main_split.active == Some(view_id) {
                     active_editor = i;
                 }
-                let editor = data.main_split.editors.get(view_id).unwrap();
-                EditorInfo {
-                    content: editor.content.clone(),
-                    scroll_offset: (editor.scroll_offset.x, editor.scroll_offset.y),
-                    position: if let BufferContent::File(path) = &editor.content {
-                        let buffer =
-                            data.main_split.open_files.get(path).unwrap().clone();
-                        Some(buffer.offset_to_position(editor.cursor.offset()))
-                    } else {
-                        None
-                    },
-                }
+                child
             })
             .collect();
         let workspace_info = WorkspaceInfo {
-            editors,
             active_editor,
+            split: SplitInfo {
+                direction: data.main_split.split_direction,
+                children,
+            },
+            search_options: data.search.options,
+            pinned_searches: (*data.search.pinned).clone(),
+            search_context: data.search.show_context,
+            index_scope: data.palette.index_scope.clone(),
+            terminals: data.terminal.terminals.len(),
         };
         Ok(((*data.workspace).clone(), workspace_info))
     }
@@ -179,9 +353,69 @@ impl LapceDb {
         let (workspace, workspace_info) = self.workspace_info(data)?;
 
         self.insert_workspace(&workspace, &workspace_info)?;
+        self.save_undo_histories(data)?;
+        Ok(())
+    }
+
+    /// Keeps the undo history of the open files for the next session, when
+    /// `editor.persistent-undo` is on. The undo groups are walked in the
+    /// background.
+    fn save_undo_histories(&self, data: &LapceTabData) -> Result<()> {
+        if !data.config.editor.persistent_undo {
+            return Ok(());
+        }
+        let snapshots = data
+            .main_split
+            .open_files
+            .iter()
+            .filter(|(_, buffer)| buffer.loaded)
+            .map(|(path, buffer)| (path.clone(), buffer.undo_snapshot()))
+            .collect();
+        self.save_tx.send(SaveEvent::UndoHistories(
+            snapshots,
+            data.config.editor.persistent_undo_size,
+        ))?;
         Ok(())
     }
 
+    fn insert_undo_histories(
+        &self,
+        snapshots: &[(PathBuf, UndoSnapshot)],
+        max_size: usize,
+    ) -> Result<()> {
+        let db = self.get_db()?;
+        for (path, snapshot) in snapshots {
+            // the history of earlier sessions goes after this one's
+            let earlier = if snapshot.restored {
+                None
+            } else {
+                self.get_undo_history(path).ok()
+            };
+            let history = snapshot.undo_history(earlier.as_ref(), max_size);
+            db.insert(undo_history_key(path), serde_json::to_vec(&history)?)?;
+        }
+        db.flush()?;
+        Ok(())
+    }
+
+    /// Waits for what was sent to save in the background to be written, like
+    /// before the app exits.
+    pub fn wait_for_saves(&self) {
+        let (done_tx, done_rx) = bounded(1);
+        if self.save_tx.send(SaveEvent::Flush(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+    }
+
+    pub fn get_undo_history(&self, path: &PathBuf) -> Result<UndoHistory> {
+        let db = self.get_db()?;
+        let history = db
+            .get(undo_history_key(path))?
+            .ok_or(anyhow!("can't find undo history"))?;
+        let history = serde_json::from_slice(&history)?;
+        Ok(history)
+    }
+
     pub fn save_workspace_async(&self, data: &LapceTabData) -> Result<()> {
         let (workspace, workspace_info) = self.workspace_info(data)?;
 
@@ -190,6 +424,18 @@ impl LapceDb {
         Ok(())
     }
 
+    /// Saves the history in the order of the changes, which one thread
+    /// writing them keeps.
+    pub fn save_history_async(
+        &self,
+        workspace: &LapceWorkspace,
+        history: &WorkspaceHistory,
+    ) -> Result<()> {
+        self.save_tx
+            .send(SaveEvent::History(workspace.clone(), history.clone()))?;
+        Ok(())
+    }
+
     pub fn get_tabs_info(&self) -> Result<TabsInfo> {
         let db = self.get_db()?;
         let tabs = db.get(b"tabs")?.ok_or(anyhow!("can't find tabs info"))?;
@@ -220,3 +466,75 @@ impl LapceDb {
         Ok(())
     }
 }
+
+fn undo_history_key(path: &PathBuf) -> String {
+    format!("undo_history:{}", path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_info_of_old_layout() {
+        let info: WorkspaceInfo = serde_json::from_str(
+            r#"{
+                "active_editor": 1,
+                "editors": [
+                    {
+                        "content": {"File": "/w/a.rs"},
+                        "scroll_offset": [0.0, 10.0],
+                        "position": null
+                    },
+                    {
+                        "content": {"File": "/w/b.rs"},
+                        "scroll_offset": [0.0, 0.0],
+                        "position": {"line": 2, "character": 3}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(info.active_editor, 1);
+        assert_eq!(info.split.direction, SplitDirection::Vertical);
+        let contents: Vec<&BufferContent> = info
+            .split
+            .children
+            .iter()
+            .map(|child| &child.editor.content)
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                &BufferContent::File(PathBuf::from("/w/a.rs")),
+                &BufferContent::File(PathBuf::from("/w/b.rs")),
+            ]
+        );
+        assert_eq!(info.split.children[0].editor.scroll_offset, (0.0, 10.0));
+        assert_eq!(info.terminals, 0);
+    }
+
+    #[test]
+    fn test_workspace_info_round_trip() {
+        let mut info: WorkspaceInfo =
+            serde_json::from_str(r#"{"active_editor": 0, "editors": []}"#).unwrap();
+        info.split.direction = SplitDirection::Horizontal;
+        info.split.children.push(SplitChildInfo {
+            flex: 2.0,
+            editor: EditorInfo {
+                content: BufferContent::File(PathBuf::from("/w/a.rs")),
+                scroll_offset: (0.0, 0.0),
+                position: None,
+                shown_files: Vec::new(),
+                current_shown: 0,
+            },
+        });
+        info.terminals = 2;
+        let info: WorkspaceInfo =
+            serde_json::from_str(&serde_json::to_string(&info).unwrap()).unwrap();
+        assert_eq!(info.split.direction, SplitDirection::Horizontal);
+        assert_eq!(info.split.children.len(), 1);
+        assert_eq!(info.split.children[0].flex, 2.0);
+        assert_eq!(info.terminals, 2);
+    }
+}
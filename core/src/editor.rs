@@ -1,5 +1,7 @@
+use crate::breadcrumbs::LapceEditorBreadcrumbs;
 use crate::buffer::{
-    has_unmatched_pair, BufferContent, DiffLines, EditType, LocalBufferKind,
+    changed_region, has_unmatched_pair, BufferContent, DiffLines, EditType,
+    LocalBufferKind,
 };
 use crate::command::{
     CommandExecuted, CommandTarget, LapceCommandNew, LapceWorkbenchCommand,
@@ -8,21 +10,29 @@ use crate::command::{
 use crate::completion::{CompletionData, CompletionStatus, Snippet};
 use crate::config::{Config, LapceTheme, LOGO};
 use crate::data::{
-    EditorContent, EditorDiagnostic, FocusArea, InlineFindDirection,
-    LapceEditorData, LapceMainSplitData, LapceTabData, PanelData, PanelKind,
-    RegisterData,
+    save_callback, show_request_error, EditorContent, EditorDiagnostic, FocusArea,
+    InlineFindDirection, LapceEditorData, LapceMainSplitData, LapceTabData,
+    PanelData, PanelKind, RegisterData,
 };
+use crate::db::LapceDb;
 use crate::find::Find;
+use crate::format::Formatting;
+use crate::gutter::{
+    diagnostic_severity, diagnostic_source, hunk_at_line, line_diagnostics,
+    DiffHunk, DiffHunkKind, GutterMarker, GutterMarkers,
+};
 use crate::keypress::{KeyMap, KeyPress, KeyPressFocus};
 use crate::menu::MenuItem;
-use crate::movement::InsertDrift;
+use crate::movement::{transform_line, InsertDrift};
+use crate::narrow::{line_region, NarrowRegion};
 use crate::panel::PanelPosition;
 use crate::proxy::LapceProxy;
+use crate::sanitize::{sanitize, PastedText};
 use crate::scroll::LapceIdentityWrapper;
 use crate::signature::SignatureState;
 use crate::source_control::SourceControlData;
 use crate::split::LapceSplitNew;
-use crate::state::LapceWorkspace;
+use crate::state::{LapceWorkspace, LapceWorkspaceType};
 use crate::svg::{file_svg_new, get_svg, logo_svg};
 use crate::tab::LapceIcon;
 use crate::theme::OldLapceTheme;
@@ -44,22 +54,24 @@ use crate::{
         EnsureVisiblePosition, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND,
     },
     completion::ScoredCompletionItem,
+    emmet, linked_tag,
     movement::{ColPosition, LinePosition, Movement, SelRegion, Selection},
     scroll::LapceScroll,
-    split::SplitMoveDirection,
+    split::{EmptyEditorHints, SplitMoveDirection},
     state::Mode,
     state::VisualMode,
+    word_index::{self, WordIndex},
 };
 use anyhow::{anyhow, Result};
 use crossbeam_channel::{self, bounded};
-use druid::kurbo::BezPath;
+use druid::kurbo::{BezPath, Circle};
 use druid::{
     kurbo::Line, piet::PietText, theme, widget::Flex, widget::IdentityWrapper,
     widget::Padding, widget::Scroll, widget::SvgData, Affine, BoxConstraints, Color,
     Command, Data, Env, Event, EventCtx, FontDescriptor, FontFamily, Insets,
     KeyEvent, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect,
-    RenderContext, Size, Target, TextLayout, UpdateCtx, Vec2, Widget, WidgetExt,
-    WidgetId, WidgetPod, WindowId,
+    RenderContext, Size, Target, TextLayout, TimerToken, UpdateCtx, Vec2, Widget,
+    WidgetExt, WidgetId, WidgetPod, WindowId,
 };
 use druid::{
     menu, Application, ExtEventSink, FileDialogOptions, Menu, Modifiers, MouseEvent,
@@ -69,7 +81,12 @@ use druid::{
         PietTextLayout, Text, TextAttribute, TextLayout as TextLayoutTrait,
         TextLayoutBuilder,
     },
-    FontWeight,
+    FontStyle, FontWeight,
+};
+use lapce_proxy::buffer::SaveConflict;
+use lapce_proxy::format::FormatCommand;
+use lapce_proxy::protocol::{
+    CAPABILITY_FORMAT_COMMAND, CAPABILITY_TRASH, CAPABILITY_WRITE_FILE,
 };
 use lsp_types::CompletionTextEdit;
 use lsp_types::{
@@ -77,15 +94,18 @@ use lsp_types::{
     Diagnostic, DiagnosticSeverity, DocumentChanges, GotoDefinitionResponse,
     Location, Position, SignatureHelp, TextEdit, Url, WorkspaceEdit,
 };
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::ops::Range;
 use std::thread;
 use std::{cmp::Ordering, iter::Iterator, path::PathBuf};
 use std::{collections::HashMap, sync::Arc};
-use std::{str::FromStr, time::Duration};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
 use strum::EnumMessage;
 use unicode_width::UnicodeWidthStr;
-use xi_rope::{Interval, RopeDelta, Transformer};
+use xi_rope::{multiset::CountMatcher, Interval, Rope, RopeDelta, Transformer};
 
 pub struct LapceUI {}
 
@@ -139,6 +159,8 @@ pub struct EditorLocationNew {
     pub position: Option<Position>,
     pub scroll_offset: Option<Vec2>,
     pub hisotry: Option<String>,
+    /// Where to select up to from `position`.
+    pub selection_end: Option<Position>,
 }
 
 #[derive(Clone, Debug)]
@@ -221,6 +243,7 @@ pub struct LapceEditorBufferData {
     pub find: Arc<Find>,
     pub proxy: Arc<LapceProxy>,
     pub config: Arc<Config>,
+    pub db: Arc<LapceDb>,
 }
 
 impl LapceEditorBufferData {
@@ -228,6 +251,54 @@ impl LapceEditorBufferData {
         Arc::make_mut(&mut self.buffer)
     }
 
+    /// Whether the editor is the search input or the commit message box of
+    /// a panel.
+    fn is_panel_input(&self) -> bool {
+        matches!(
+            self.editor.content,
+            BufferContent::Local(
+                LocalBufferKind::Search
+                    | LocalBufferKind::SearchInclude
+                    | LocalBufferKind::SearchReplace
+                    | LocalBufferKind::SourceControl
+            )
+        )
+    }
+
+    /// Makes a preview editor a normal one, once its file is edited.
+    fn keep_preview(&mut self) {
+        if self.editor.preview {
+            Arc::make_mut(&mut self.editor).preview = false;
+        }
+    }
+
+    /// The log view and the palette's preview can't be edited.
+    fn is_read_only(&self) -> bool {
+        self.editor.content == BufferContent::Local(LocalBufferKind::Log)
+            || self.view_id == *self.main_split.palette_preview_editor
+    }
+
+    /// Brings back the undo steps of earlier sessions once the ones of this
+    /// session run out, so that opening a file doesn't pay for them.
+    fn restore_undo_history(&mut self) {
+        if self.buffer.can_undo()
+            || self.buffer.undo_history_restored
+            || !self.config.editor.persistent_undo
+        {
+            return;
+        }
+        let path = match &self.buffer.content {
+            BufferContent::File(path) => path.clone(),
+            BufferContent::Local(_) => return,
+        };
+        let history = self.db.get_undo_history(&path);
+        let buffer = self.buffer_mut();
+        buffer.undo_history_restored = true;
+        if let Ok(history) = history {
+            buffer.restore_undo_history(&history);
+        }
+    }
+
     fn sync_buffer_position(&mut self, scroll_offset: Vec2) {
         let cursor_offset = self.editor.cursor.offset();
         if self.buffer.cursor_offset != cursor_offset
@@ -303,7 +374,11 @@ impl LapceEditorBufferData {
                 }
             }
             BufferContent::Local(kind) => match kind {
-                LocalBufferKind::Search => Size::new(editor_size.width, line_height),
+                LocalBufferKind::Search
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchReplace => {
+                    Size::new(editor_size.width, line_height)
+                }
                 LocalBufferKind::SourceControl => {
                     for (pos, panels) in panels.iter() {
                         for panel_kind in panels.widgets.iter() {
@@ -333,6 +408,11 @@ impl LapceEditorBufferData {
                     Size::ZERO
                 }
                 LocalBufferKind::Empty => Size::ZERO,
+                LocalBufferKind::Log | LocalBufferKind::Narrow(_) => Size::new(
+                    (width * self.buffer.max_len as f64).max(editor_size.width),
+                    line_height * self.buffer.num_lines as f64 + editor_size.height
+                        - line_height,
+                ),
             },
         }
     }
@@ -434,11 +514,36 @@ impl LapceEditorBufferData {
         }
     }
 
-    fn inactive_apply_delta(&mut self, delta: &RopeDelta) {
+    /// Moves the cursors of the other editors of the buffer through `delta`,
+    /// made to `old_rope`, and keeps the lines at their top in view when
+    /// lines were added or removed above.
+    fn inactive_apply_delta(
+        &mut self,
+        ctx: &mut EventCtx,
+        old_rope: &Rope,
+        delta: &RopeDelta,
+    ) {
+        let line_height = self.config.editor.line_height as f64;
         for (view_id, editor) in self.main_split.editors.iter_mut() {
             if view_id != &self.editor.view_id {
                 if self.buffer.content == editor.content {
-                    Arc::make_mut(editor).cursor.apply_delta(delta);
+                    let editor = Arc::make_mut(editor);
+                    editor.cursor.apply_delta(delta);
+
+                    let offset = editor.scroll_offset;
+                    let line = (offset.y / line_height).floor() as usize;
+                    let new_line =
+                        transform_line(old_rope, &self.buffer.rope, delta, line);
+                    if new_line != line {
+                        let y =
+                            offset.y + (new_line as f64 - line as f64) * line_height;
+                        editor.scroll_offset = Vec2::new(offset.x, y);
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::ForceScrollTo(offset.x, y),
+                            Target::Widget(*view_id),
+                        ));
+                    }
                 }
             }
         }
@@ -559,6 +664,72 @@ impl LapceEditorBufferData {
         completion.cancel();
     }
 
+    /// Expands the Emmet abbreviation before the cursor, placing it on the
+    /// first tabstop. Returns false if there's none, for Tab to indent.
+    fn expand_abbreviation(&mut self, ctx: &mut EventCtx, strict: bool) -> bool {
+        let extension = match &self.buffer.content {
+            BufferContent::File(path) => path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_lowercase(),
+            BufferContent::Local(_) => return false,
+        };
+        let syntax = if self.config.editor.emmet_html.contains(&extension) {
+            emmet::Syntax::Html
+        } else if self.config.editor.emmet_css.contains(&extension) {
+            emmet::Syntax::Css
+        } else {
+            return false;
+        };
+        let selection = self.editor.cursor.edit_selection(&self.buffer);
+        if selection.regions().len() != 1 || !selection.is_caret() {
+            return false;
+        }
+
+        let offset = self.editor.cursor.offset();
+        let line = self.buffer.line_of_offset(offset);
+        let line_start = self.buffer.offset_of_line(line);
+        let text = self.buffer.slice_to_cow(line_start..offset).to_string();
+        let start = match emmet::extract_abbreviation(&text, syntax) {
+            Some(start) => start,
+            None => return false,
+        };
+        let indent = self
+            .buffer
+            .editorconfig
+            .indent_unit()
+            .unwrap_or_else(|| "\t".to_string());
+        let snippet = match emmet::expand(
+            &text[start..],
+            syntax,
+            &indent,
+            &self.buffer.indent_on_line(line),
+            strict,
+        ) {
+            Some(snippet) => snippet,
+            None => return false,
+        };
+
+        let start = line_start + start;
+        self.edit(
+            ctx,
+            &Selection::region(start, offset),
+            &snippet.text(),
+            None,
+            true,
+            EditType::InsertChars,
+        );
+        let snippet_tabs = snippet.tabs(start);
+        let (_, (tab_start, tab_end)) = snippet_tabs[0];
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(tab_start, tab_end, None));
+        self.set_cursor(Cursor::new(CursorMode::Insert(selection), None));
+        Arc::make_mut(&mut self.editor).add_snippet_placeholders(snippet_tabs);
+        self.cancel_completion();
+        true
+    }
+
     fn update_completion(&mut self, ctx: &mut EventCtx) {
         if self.get_mode() != Mode::Insert {
             return;
@@ -569,6 +740,9 @@ impl LapceEditorBufferData {
         if self.buffer.local {
             return;
         }
+        if self.completion.dismissed {
+            return;
+        }
         let offset = self.editor.cursor.offset();
         let start_offset = self.buffer.prev_code_boundary(offset);
         let end_offset = self.buffer.next_code_boundary(offset);
@@ -583,6 +757,8 @@ impl LapceEditorBufferData {
                 .slice_to_cow(start_offset - 1..start_offset)
                 .to_string()
         };
+        let words = self.word_completion_items(&input);
+        let merge_words = self.config.editor.word_completion == "merge";
         let completion = Arc::make_mut(&mut self.completion);
         if input == "" && char != "." && char != ":" {
             completion.cancel();
@@ -594,6 +770,7 @@ impl LapceEditorBufferData {
             && completion.buffer_id == self.buffer.id
         {
             completion.update_input(input.clone());
+            completion.set_word_items(words, merge_words);
 
             if !completion.input_items.contains_key("") {
                 let event_sink = ctx.get_external_handle();
@@ -621,6 +798,8 @@ impl LapceEditorBufferData {
                 );
             }
 
+            completion
+                .resolve_current(self.proxy.clone(), ctx.get_external_handle());
             return;
         }
 
@@ -630,6 +809,7 @@ impl LapceEditorBufferData {
         completion.status = CompletionStatus::Started;
         completion.input_items.clear();
         completion.request_id += 1;
+        completion.set_word_items(words, merge_words);
         let event_sink = ctx.get_external_handle();
         completion.request(
             self.proxy.clone(),
@@ -653,6 +833,32 @@ impl LapceEditorBufferData {
         }
     }
 
+    /// The words of the buffer and of the other open buffers of its language
+    /// that complete `input`, unless the word completion is off.
+    fn word_completion_items(&self, input: &str) -> Vec<CompletionItem> {
+        if input.is_empty() || self.config.editor.word_completion == "off" {
+            return Vec::new();
+        }
+        let others: Vec<&WordIndex> = self
+            .main_split
+            .open_files
+            .values()
+            .filter(|buffer| {
+                buffer.id != self.buffer.id
+                    && buffer.language == self.buffer.language
+            })
+            .map(|buffer| &buffer.word_index)
+            .collect();
+        let line = self.buffer.line_of_offset(self.editor.cursor.offset());
+        word_index::completion_items(
+            input,
+            &self.buffer.rope,
+            line,
+            &self.buffer.word_index,
+            &others,
+        )
+    }
+
     fn cursor_region(&self, text: &mut PietText, config: &Config) -> Rect {
         let offset = self.editor.cursor.offset();
         let (line, col) = self.buffer.offset_to_line_col(offset);
@@ -682,7 +888,7 @@ impl LapceEditorBufferData {
         let second_half = self.buffer.slice_to_cow(offset..line_end).to_string();
 
         let indent = if has_unmatched_pair(&first_half) {
-            format!("{}    ", line_indent)
+            format!("{}{}", line_indent, self.buffer.indent_unit())
         } else {
             let next_line_indent = self.buffer.indent_on_line(line + 1);
             if next_line_indent.len() > line_indent.len() {
@@ -693,7 +899,7 @@ impl LapceEditorBufferData {
         };
 
         let selection = Selection::caret(offset);
-        let content = format!("{}{}", "\n", indent);
+        let content = format!("{}{}", self.buffer.line_ending(), indent);
 
         let (selection, _) = self.edit(
             ctx,
@@ -713,7 +919,11 @@ impl LapceEditorBufferData {
                     if pair_start {
                         if let Some(c) = matching_char(c) {
                             if second_half.trim().starts_with(&c.to_string()) {
-                                let content = format!("{}{}", "\n", line_indent);
+                                let content = format!(
+                                    "{}{}",
+                                    self.buffer.line_ending(),
+                                    line_indent
+                                );
                                 self.edit(
                                     ctx,
                                     &selection,
@@ -744,7 +954,7 @@ impl LapceEditorBufferData {
         }
     }
 
-    fn paste(&mut self, ctx: &mut EventCtx, data: &RegisterData) {
+    fn paste(&mut self, ctx: &mut EventCtx, data: &RegisterData) -> RopeDelta {
         match data.mode {
             VisualMode::Normal => {
                 Arc::make_mut(&mut self.editor).snippet = None;
@@ -759,7 +969,7 @@ impl LapceEditorBufferData {
                     }
                 };
                 let after = !data.content.contains("\n");
-                let (selection, _) = self.edit(
+                let (selection, delta) = self.edit(
                     ctx,
                     &selection,
                     &data.content,
@@ -790,6 +1000,7 @@ impl LapceEditorBufferData {
                         }
                     }
                 }
+                delta
             }
             VisualMode::Linewise | VisualMode::Blockwise => {
                 let (selection, content) = match &self.editor.cursor.mode {
@@ -812,7 +1023,7 @@ impl LapceEditorBufferData {
                         (selection, data)
                     }
                 };
-                let (selection, _) = self.edit(
+                let (selection, delta) = self.edit(
                     ctx,
                     &selection,
                     &content,
@@ -843,6 +1054,426 @@ impl LapceEditorBufferData {
                         ));
                     }
                 }
+                delta
+            }
+        }
+    }
+
+    /// Inserts the clipboard content verbatim at every cursor, or re-indented
+    /// to the line of each cursor when `match_indent` is set. When there are
+    /// as many clipboard lines as cursors, each cursor gets its own line.
+    fn paste_clipboard(
+        &mut self,
+        ctx: &mut EventCtx,
+        content: &str,
+        match_indent: bool,
+    ) -> Option<RopeDelta> {
+        Arc::make_mut(&mut self.editor).snippet = None;
+        let selection = match self.editor.cursor.mode {
+            CursorMode::Normal(offset) => {
+                let line_end = self.buffer.offset_line_end(offset, true);
+                Selection::caret((offset + 1).min(line_end))
+            }
+            CursorMode::Insert { .. } | CursorMode::Visual { .. } => {
+                self.editor.cursor.edit_selection(&self.buffer)
+            }
+        };
+
+        let regions = selection.regions();
+        let lines: Vec<&str> = content.lines().collect();
+        let edits: Vec<(Selection, String)> = regions
+            .iter()
+            .enumerate()
+            .map(|(i, region)| {
+                let content = if regions.len() > 1 && lines.len() == regions.len() {
+                    lines[i]
+                } else {
+                    content
+                };
+                let content = if match_indent {
+                    let line = self.buffer.line_of_offset(region.min());
+                    reindent(content, &self.buffer.indent_on_line(line))
+                } else {
+                    content.to_string()
+                };
+                (Selection::region(region.min(), region.max()), content)
+            })
+            .collect();
+        if edits.is_empty() {
+            return None;
+        }
+
+        let additional_edit: Vec<(&Selection, &str)> = edits[1..]
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect();
+        let (_, delta) = self.edit(
+            ctx,
+            &edits[0].0,
+            &edits[0].1,
+            Some(additional_edit),
+            true,
+            EditType::Other,
+        );
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        self.set_cursor_after_change(selection);
+        Some(delta)
+    }
+
+    /// Applies `editor.sanitize-paste` to clipboard text about to be pasted
+    /// into a file. Returns the text to paste, and how many characters should
+    /// be offered for fixing afterwards.
+    fn sanitize_clipboard(&self, content: String) -> (String, usize) {
+        if !matches!(self.buffer.content, BufferContent::File(_)) {
+            return (content, 0);
+        }
+        match self.config.editor.sanitize_paste.as_str() {
+            "off" => (content, 0),
+            "always" => (sanitize(&content).0, 0),
+            _ => {
+                let (_, count) = sanitize(&content);
+                (content, count)
+            }
+        }
+    }
+
+    /// Remembers where the text was pasted, so that the editor can offer to
+    /// fix the characters in it.
+    fn set_pasted(&mut self, count: usize, delta: &RopeDelta) {
+        let pasted = if count > 0 {
+            let (ins, del) = delta.clone().factor();
+            let ins = ins.transform_shrink(&del);
+            let regions = ins
+                .inserted_subset()
+                .range_iter(CountMatcher::NonZero)
+                .collect();
+            Some(PastedText {
+                rev: self.buffer.rev,
+                regions,
+                count,
+            })
+        } else {
+            None
+        };
+        Arc::make_mut(&mut self.editor).pasted = pasted;
+    }
+
+    /// Saves the text of the editor over the file on disk, after a save
+    /// was refused for a conflict.
+    fn overwrite_file(&mut self, ctx: &mut EventCtx) {
+        let path = match &self.buffer.content {
+            BufferContent::File(path) => path.clone(),
+            BufferContent::Local(_) => return,
+        };
+        let proxy = self.proxy.clone();
+        let buffer_id = self.buffer.id;
+        let rev = self.buffer.rev;
+        let event_sink = ctx.get_external_handle();
+        self.proxy.sync_buffer(
+            buffer_id,
+            rev,
+            self.buffer.rope.to_string(),
+            Box::new(move |result| {
+                if result.is_ok() {
+                    proxy.save(rev, buffer_id, save_callback(path, rev, event_sink));
+                }
+            }),
+        );
+    }
+
+    /// Replaces the text of the editor with the file on disk, as an edit
+    /// that can be undone. Only what changed is replaced, so that the
+    /// cursor stays where it was when it's out of it.
+    fn reload_from_disk(&mut self, ctx: &mut EventCtx) {
+        let disk = match self.buffer.histories.get("disk") {
+            Some(disk) => disk.to_string(),
+            None => return,
+        };
+        self.initiate_diagnositcs_offset();
+        let proxy = self.proxy.clone();
+        let (old, new) = changed_region(&self.buffer.rope.to_string(), &disk);
+        let selection = Selection::region(old.start, old.end);
+        let old_rope = self.buffer.rope.clone();
+        let delta = self.buffer_mut().edit_multiple(
+            ctx,
+            vec![(&selection, &disk[new])],
+            proxy,
+            EditType::Other,
+        );
+        self.inactive_apply_delta(ctx, &old_rope, &delta);
+        self.update_diagnositcs_offset(&delta);
+        let editor = Arc::make_mut(&mut self.editor);
+        editor.cursor.apply_delta(&delta);
+        if editor.compare.as_deref() == Some("disk") {
+            editor.compare = None;
+        }
+
+        let buffer = self.buffer_mut();
+        buffer.dirty = false;
+        buffer.clear_save_conflict();
+        buffer.clear_backup();
+        // the proxy refuses the edit if its text had moved on
+        self.proxy.sync_buffer(
+            self.buffer.id,
+            self.buffer.rev,
+            disk,
+            Box::new(|_| {}),
+        );
+    }
+
+    /// Keeps the text of the editor over the file that changed on disk. It
+    /// counts as unsaved, and the next save overwrites the file.
+    fn ignore_disk_change(&mut self) {
+        let content = self.buffer.rope.to_string();
+        self.proxy.sync_buffer(
+            self.buffer.id,
+            self.buffer.rev,
+            content,
+            Box::new(|_| {}),
+        );
+        let editor = Arc::make_mut(&mut self.editor);
+        if editor.compare.as_deref() == Some("disk") {
+            editor.compare = None;
+        }
+        let buffer = self.buffer_mut();
+        buffer.dirty = true;
+        buffer.clear_save_conflict();
+    }
+
+    /// Narrows the editor to the lines of the selection, which it then shows
+    /// in a local buffer of their own.
+    fn narrow_to_selection(&mut self, ctx: &mut EventCtx) {
+        let path = match &self.buffer.content {
+            BufferContent::File(path) => path.clone(),
+            BufferContent::Local(_) => return,
+        };
+        let selection = self.editor.cursor.edit_selection(&self.buffer);
+        if selection.is_caret() {
+            return;
+        }
+        let (start, end) = line_region(
+            &self.buffer.rope,
+            selection.min_offset(),
+            selection.max_offset(),
+        );
+        let kind = LocalBufferKind::Narrow(self.view_id.to_raw());
+        let mut local = BufferNew::new(
+            BufferContent::Local(kind.clone()),
+            self.main_split.update_sender.clone(),
+            *self.main_split.tab_id,
+            ctx.get_external_handle(),
+        )
+        .set_local();
+        local.load_content(&self.buffer.slice_to_cow(start..end));
+        local.language = self.buffer.language;
+        local.editorconfig = self.buffer.editorconfig.clone();
+        local.dirty = self.buffer.dirty;
+        let mut narrow = NarrowRegion::new(path, start, end, &self.buffer);
+        narrow.local_rev = local.rev;
+        self.main_split
+            .local_buffers
+            .insert(kind.clone(), Arc::new(local));
+
+        // the lines in view stay where they were
+        let line_height = self.config.editor.line_height as f64;
+        let hidden = self.buffer.line_of_offset(start) as f64 * line_height;
+        let editor = Arc::make_mut(&mut self.editor);
+        editor.cursor = editor.cursor.map_offsets(|offset| narrow.to_local(offset));
+        editor.content = BufferContent::Local(kind);
+        editor.narrow = Some(Arc::new(narrow));
+        editor.compare = None;
+        editor.snippet = None;
+        editor.linked_tag = None;
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ForceScrollTo(
+                editor.scroll_offset.x,
+                (editor.scroll_offset.y - hidden).max(0.0),
+            ),
+            Target::Widget(self.view_id),
+        ));
+    }
+
+    /// Shows the whole file again in a narrowed editor. Its edits are
+    /// already in the file.
+    fn widen_from_narrow(&mut self, ctx: &mut EventCtx) {
+        let narrow = match self.editor.narrow.clone() {
+            Some(narrow) => narrow,
+            None => return,
+        };
+        let file = match self.main_split.open_files.get(&narrow.path) {
+            Some(file) => file.clone(),
+            None => return,
+        };
+        self.main_split
+            .local_buffers
+            .remove(&LocalBufferKind::Narrow(self.view_id.to_raw()));
+
+        let line_height = self.config.editor.line_height as f64;
+        let hidden = file.line_of_offset(narrow.start) as f64 * line_height;
+        let editor = Arc::make_mut(&mut self.editor);
+        editor.cursor = editor.cursor.map_offsets(|offset| narrow.to_file(offset));
+        editor.content = BufferContent::File(narrow.path.clone());
+        editor.narrow = None;
+        editor.snippet = None;
+        editor.linked_tag = None;
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ForceScrollTo(
+                editor.scroll_offset.x,
+                editor.scroll_offset.y + hidden,
+            ),
+            Target::Widget(self.view_id),
+        ));
+    }
+
+    /// The file to format or save, and the region of it a narrowed editor
+    /// shows.
+    fn file_to_format(
+        &self,
+    ) -> Option<(PathBuf, Arc<BufferNew>, Option<Range<usize>>)> {
+        match (&self.buffer.content, self.editor.narrow.as_ref()) {
+            (BufferContent::File(path), _) => {
+                Some((path.clone(), self.buffer.clone(), None))
+            }
+            (BufferContent::Local(_), Some(narrow)) => {
+                let file = self.main_split.open_files.get(&narrow.path)?;
+                Some((
+                    narrow.path.clone(),
+                    file.clone(),
+                    Some(narrow.start..narrow.end),
+                ))
+            }
+            (BufferContent::Local(_), None) => None,
+        }
+    }
+
+    /// Formats the file by its language server, or by the command of its
+    /// language in the `format` config, then saves it if `save`. The
+    /// language server goes first, and the command is the fallback, unless
+    /// the config prefers the command.
+    fn format_file(&self, ctx: &mut EventCtx, save: bool) {
+        let (path, file, region) = match self.file_to_format() {
+            Some(file) => file,
+            None => return,
+        };
+        let formatter = self
+            .config
+            .formatter_for(&path)
+            .filter(|_| self.proxy.has_capability(CAPABILITY_FORMAT_COMMAND))
+            .map(|formatter| (formatter.command(), formatter.prefer_lsp));
+        let content = formatter.as_ref().map(|_| file.rope.to_string());
+        let proxy = self.proxy.clone();
+        let buffer_id = file.id;
+        let rev = file.rev;
+        let event_sink = ctx.get_external_handle();
+        thread::spawn(move || {
+            let lsp = || {
+                let (sender, receiver) = bounded(1);
+                proxy.get_document_formatting(
+                    buffer_id,
+                    Box::new(move |result| {
+                        sender.send(result);
+                    }),
+                );
+                receiver
+                    .recv_timeout(Duration::from_secs(1))
+                    .map_or_else(
+                        |e| Err(anyhow!("{}", e)),
+                        |v| v.map_err(|e| anyhow!("{:?}", e)),
+                    )
+                    .and_then(|v| match v {
+                        Value::Null => Err(anyhow!("no document formatting")),
+                        v => Ok(Formatting::Edits(v)),
+                    })
+            };
+            let command = |format: FormatCommand| {
+                let timeout = Duration::from_millis(format.timeout + 1000);
+                let (sender, receiver) = bounded(1);
+                proxy.format_with_command(
+                    &format,
+                    &path,
+                    content.clone().unwrap_or_default(),
+                    Box::new(move |result| {
+                        sender.send(result);
+                    }),
+                );
+                let result = receiver
+                    .recv_timeout(timeout)
+                    .map_err(|e| json!({ "message": e.to_string() }))
+                    .and_then(|result| result);
+                match result {
+                    Ok(value) => value["output"]
+                        .as_str()
+                        .map(|output| Formatting::Text(output.to_string()))
+                        .ok_or_else(|| anyhow!("no output")),
+                    Err(e) => {
+                        show_request_error("Formatting failed", &e);
+                        Err(anyhow!("{:?}", e))
+                    }
+                }
+            };
+            let result = match formatter {
+                Some((format, false)) => command(format),
+                Some((format, true)) => lsp().or_else(|_| command(format)),
+                None => lsp(),
+            };
+            let command = if save {
+                LapceUICommand::DocumentFormatAndSave(path, rev, region, result)
+            } else {
+                LapceUICommand::DocumentFormat(path, rev, region, result)
+            };
+            event_sink.submit_command(LAPCE_UI_COMMAND, command, Target::Auto);
+        });
+    }
+
+    fn fix_pasted_text(&mut self, ctx: &mut EventCtx) {
+        let pasted = match Arc::make_mut(&mut self.editor).pasted.take() {
+            Some(pasted) => pasted,
+            None => return,
+        };
+        if pasted.rev != self.buffer.rev {
+            return;
+        }
+        let edits: Vec<(Selection, String)> = pasted
+            .regions
+            .iter()
+            .map(|(start, end)| {
+                let content = self.buffer.slice_to_cow(*start..*end);
+                (Selection::region(*start, *end), sanitize(&content).0)
+            })
+            .collect();
+        if edits.is_empty() {
+            return;
+        }
+
+        // Not going through `edit`, which would put the replaced text into
+        // the delete register in normal mode.
+        self.initiate_diagnositcs_offset();
+        let selection = self.editor.cursor.edit_selection(&self.buffer);
+        let proxy = self.proxy.clone();
+        let old_rope = self.buffer.rope.clone();
+        let delta = self.buffer_mut().edit_multiple(
+            ctx,
+            edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect(),
+            proxy,
+            EditType::Other,
+        );
+        self.inactive_apply_delta(ctx, &old_rope, &delta);
+        self.update_diagnositcs_offset(&delta);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        match self.editor.cursor.mode {
+            CursorMode::Insert(_) => {
+                self.set_cursor(Cursor::new(CursorMode::Insert(selection), None));
+            }
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                self.set_cursor(Cursor::new(
+                    CursorMode::Normal(selection.min_offset()),
+                    None,
+                ));
             }
         }
     }
@@ -949,17 +1580,33 @@ impl LapceEditorBufferData {
 
         self.initiate_diagnositcs_offset();
 
+        let linked_tag = self.linked_tag_edit(selection, c);
         let proxy = self.proxy.clone();
+        let old_rope = self.buffer.rope.clone();
         let buffer = self.buffer_mut();
-        let delta = if let Some(additional_edit) = additional_edit {
+        let delta = if additional_edit.is_some() || linked_tag.is_some() {
             let mut edits = vec![(selection, c)];
-            edits.extend_from_slice(&additional_edit);
+            if let Some((mirror, _)) = linked_tag.as_ref() {
+                edits.push((mirror, c));
+            }
+            if let Some(additional_edit) = additional_edit {
+                edits.extend_from_slice(&additional_edit);
+            }
             buffer.edit_multiple(ctx, edits, proxy, edit_type)
         } else {
             buffer.edit(ctx, &selection, c, proxy, edit_type)
         };
-        self.inactive_apply_delta(&delta);
+        self.inactive_apply_delta(ctx, &old_rope, &delta);
         let selection = selection.apply_delta(&delta, after, InsertDrift::Default);
+        Arc::make_mut(&mut self.editor).linked_tag = linked_tag.map(|(_, names)| {
+            let mut transformer = Transformer::new(&delta);
+            names.map(|(start, end)| {
+                (
+                    transformer.transform(start, false),
+                    transformer.transform(end, true),
+                )
+            })
+        });
         if let Some(snippet) = self.editor.snippet.clone() {
             let mut transformer = Transformer::new(&delta);
             Arc::make_mut(&mut self.editor).snippet = Some(
@@ -983,17 +1630,51 @@ impl LapceEditorBufferData {
         (selection, delta)
     }
 
+    /// When the edit is of the name of a tag, the same edit in the name of
+    /// its pair, with the names before the edit. The pair found for the
+    /// first edit is kept while the names are the same.
+    fn linked_tag_edit(
+        &self,
+        selection: &Selection,
+        c: &str,
+    ) -> Option<(Selection, [(usize, usize); 2])> {
+        if !self.config.editor.linked_tag_editing
+            || !linked_tag::is_tag_name(c)
+            || selection.regions().len() != 1
+        {
+            return None;
+        }
+        let region = selection.regions()[0];
+        let (start, end) = (region.min(), region.max());
+        let within = |names: &[(usize, usize); 2]| {
+            names.iter().position(|(s, e)| *s <= start && end <= *e)
+        };
+        let same_names = |names: &[(usize, usize); 2]| {
+            names.iter().all(|(_, e)| *e <= self.buffer.len())
+                && self.buffer.slice_to_cow(names[0].0..names[0].1)
+                    == self.buffer.slice_to_cow(names[1].0..names[1].1)
+        };
+
+        let names = self
+            .editor
+            .linked_tag
+            .filter(|names| within(names).is_some() && same_names(names))
+            .or_else(|| self.buffer.tag_names(start).filter(same_names))?;
+        let i = within(&names)?;
+        let mirror = |offset: usize| names[1 - i].0 + offset - names[i].0;
+        Some((Selection::region(mirror(start), mirror(end)), names))
+    }
+
     fn next_diff(&mut self, ctx: &mut EventCtx, env: &Env) {
         if let BufferContent::File(buffer_path) = &self.buffer.content {
-            if self.source_control.file_diffs.len() == 0 {
+            if !self.source_control.has_changes() {
                 return;
             }
             let mut diff_files: Vec<(PathBuf, Vec<Position>)> = self
                 .source_control
-                .file_diffs
-                .iter()
-                .map(|(diff, _)| {
-                    let path = diff.path();
+                .changed_paths()
+                .into_iter()
+                .map(|path| {
                     let mut positions = Vec::new();
                     if let Some(buffer) = self.main_split.open_files.get(path) {
                         if let Some(changes) = buffer.history_changes.get("head") {
@@ -1051,6 +1732,7 @@ impl LapceEditorBufferData {
                 position: Some(position),
                 scroll_offset: None,
                 hisotry: Some("head".to_string()),
+                selection_end: None,
             };
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
@@ -1106,6 +1788,7 @@ impl LapceEditorBufferData {
                 position: Some(position),
                 scroll_offset: None,
                 hisotry: None,
+                selection_end: None,
             };
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
@@ -1161,6 +1844,24 @@ impl LapceEditorBufferData {
         None
     }
 
+    /// Shows the file this editor showed before the current one, or after
+    /// it, where it was left. Files removed since are skipped.
+    fn cycle_shown_files(&mut self, ctx: &mut EventCtx, forward: bool) {
+        // the files of a remote workspace can't be checked from here
+        let local = self.workspace.kind == LapceWorkspaceType::Local;
+        let editor = Arc::make_mut(&mut self.editor);
+        editor.save_shown_file(&self.buffer);
+        if let Some(location) =
+            editor.cycle_shown_files(forward, |path| !local || path.exists())
+        {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::GoToLocationNew(editor.view_id, location),
+                Target::Auto,
+            ));
+        }
+    }
+
     fn page_move(&mut self, ctx: &mut EventCtx, down: bool, env: &Env) {
         let line_height = self.config.editor.line_height as f64;
         let lines =
@@ -1575,6 +2276,8 @@ impl LapceEditorBufferData {
             let num_lines = (ctx.size().height / line_height).floor() as usize;
             let last_line = self.buffer.last_line();
             let current_line = self.editor.cursor.current_line(&self.buffer);
+            let current_lines = self.editor.cursor.current_lines(&self.buffer);
+            let is_active = *self.main_split.active == Some(self.view_id);
             let width = self.config.editor_text_width(ctx.text(), "W");
             for line in start_line..start_line + num_lines + 1 {
                 if line > last_line {
@@ -1600,154 +2303,189 @@ impl LapceEditorBufferData {
                 let pos = Point::new(x, y);
                 let content = content.to_string();
 
-                let text_layout = ctx
+                let highlighted = current_lines.contains(&line)
+                    && self.config.editor.highlight_current_line_number();
+                let mut text_layout = ctx
                     .text()
                     .new_text_layout(content)
                     .font(
                         self.config.editor.font_family(),
                         self.config.editor.font_size as f64,
                     )
-                    .text_color(if line == current_line {
+                    .text_color(if !highlighted {
+                        self.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone()
+                    } else if is_active {
                         self.config
                             .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
                             .clone()
                     } else {
                         self.config
-                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
                             .clone()
-                    })
-                    .build()
-                    .unwrap();
+                            .with_alpha(0.6)
+                    });
+                if highlighted {
+                    text_layout = text_layout.default_attribute(FontWeight::BOLD);
+                }
+                let text_layout = text_layout.build().unwrap();
                 ctx.draw_text(&text_layout, pos);
             }
 
-            if let Some(changes) = self.buffer.history_changes.get("head") {
-                let mut line = 0;
-                let mut last_change = None;
-                for change in changes.iter() {
-                    let len = match change {
-                        DiffLines::Left(range) => 0,
-                        DiffLines::Skip(left, right) => right.len(),
-                        DiffLines::Both(left, right) => right.len(),
-                        DiffLines::Right(range) => range.len(),
-                    };
-                    line += len;
-                    if line < start_line {
-                        last_change = Some(change.clone());
-                        continue;
-                    }
+            let (bar_x, _) = Self::diff_bar_x(gutter_width, width);
+            for hunk in self.buffer.diff_hunks() {
+                if hunk.lines.end < start_line {
+                    continue;
+                }
+                if hunk.lines.start > end_line + 1 {
+                    break;
+                }
+                let color = self
+                    .config
+                    .get_color_unchecked(hunk.kind.color())
+                    .clone()
+                    .with_alpha(0.8);
+                let y = hunk.lines.start as f64 * line_height - scroll_offset.y;
+                if hunk.kind == DiffHunkKind::Removed {
+                    // a triangle pointing between the lines around the ones
+                    // removed
+                    let size = 4.0;
+                    let mut path = BezPath::new();
+                    path.move_to(Point::new(bar_x, y - size));
+                    path.line_to(Point::new(bar_x + size, y));
+                    path.line_to(Point::new(bar_x, y + size));
+                    path.close_path();
+                    ctx.fill(path, &color);
+                } else {
+                    let rect = Size::new(3.0, hunk.lines.len() as f64 * line_height)
+                        .to_rect()
+                        .with_origin(Point::new(bar_x, y));
+                    ctx.fill(rect, &color);
+                }
+            }
 
-                    let mut modified = false;
-                    let color = match change {
-                        DiffLines::Left(range) => {
-                            Some(self.config.get_color_unchecked(
-                                LapceTheme::SOURCE_CONTROL_REMOVED,
-                            ))
-                        }
-                        DiffLines::Right(range) => {
-                            if let Some(last_change) = last_change.as_ref() {
-                                match last_change {
-                                    DiffLines::Left(l) => {
-                                        modified = true;
-                                    }
-                                    _ => (),
-                                }
-                            }
-                            if modified {
-                                Some(self.config.get_color_unchecked(
-                                    LapceTheme::SOURCE_CONTROL_MODIFIED,
-                                ))
-                            } else {
-                                Some(self.config.get_color_unchecked(
-                                    LapceTheme::SOURCE_CONTROL_ADDED,
-                                ))
-                            }
-                        }
-                        _ => None,
-                    };
-
-                    if let Some(color) = color {
-                        let removed_height = 10.0;
-                        let size = Size::new(
-                            3.0,
-                            if len == 0 {
-                                removed_height
-                            } else {
-                                line_height * len as f64
-                            },
-                        );
-                        let x = gutter_width + width;
-                        let mut y =
-                            (line - len) as f64 * line_height - scroll_offset.y;
-                        if len == 0 {
-                            y -= removed_height / 2.0;
-                        }
-                        if modified {
-                            let rect = Size::new(3.0, removed_height)
-                                .to_rect()
-                                .with_origin(Point::new(
-                                    x,
-                                    y - removed_height / 2.0,
-                                ));
-                            ctx.fill(
-                                rect,
-                                self.config.get_color_unchecked(
-                                    LapceTheme::EDITOR_BACKGROUND,
-                                ),
-                            );
-                        }
-                        let rect = size.to_rect().with_origin(Point::new(x, y));
-                        ctx.fill(rect, &color.clone().with_alpha(0.8));
-                    }
+            self.paint_gutter_markers(
+                ctx,
+                gutter_width,
+                start_line,
+                start_line + num_lines,
+            );
+        });
+    }
 
-                    if line > end_line {
-                        break;
-                    }
-                    last_change = Some(change.clone());
+    /// The markers of the gutter's marker column between the lines.
+    fn gutter_markers(&self, start_line: usize, end_line: usize) -> GutterMarkers {
+        let mut markers = GutterMarkers::default();
+        if let Some(diagnostics) = self.diagnostics() {
+            markers.add_diagnostics(diagnostics, start_line, end_line);
+        }
+        if *self.main_split.active == Some(self.view_id) {
+            if let Some(actions) = self.current_code_actions() {
+                if actions.len() > 0 {
+                    let offset = self.editor.cursor.offset();
+                    let (line, _) = self.buffer.offset_to_line_col(offset);
+                    markers.add(line, GutterMarker::CodeActions);
                 }
             }
+        }
+        markers
+    }
 
-            if *self.main_split.active == Some(self.view_id) {
-                self.paint_code_actions_hint(ctx, gutter_width);
-            }
-        });
+    /// Where the marker column of the gutter is, horizontally.
+    fn gutter_marker_x(gutter_width: f64, char_width: f64) -> (f64, f64) {
+        let x = gutter_width + char_width + 3.0;
+        (x, x + 16.0)
     }
 
-    fn paint_code_actions_hint(&self, ctx: &mut PaintCtx, gutter_width: f64) {
-        if let Some(actions) = self.current_code_actions() {
-            if actions.len() > 0 {
-                let line_height = self.config.editor.line_height as f64;
-                let offset = self.editor.cursor.offset();
-                let (line, _) = self.buffer.offset_to_line_col(offset);
-                let svg = get_svg("lightbulb.svg").unwrap();
-                let width = 16.0;
-                let height = 16.0;
-                let char_width = self.config.editor_text_width(ctx.text(), "W");
-                let rect =
-                    Size::new(width, height).to_rect().with_origin(Point::new(
-                        gutter_width + char_width + 3.0,
-                        (line_height - height) / 2.0 + line_height * line as f64
-                            - self.editor.scroll_offset.y,
-                    ));
-                ctx.draw_svg(
-                    &svg,
-                    rect,
-                    Some(self.config.get_color_unchecked(LapceTheme::LAPCE_WARN)),
-                );
-            }
+    /// Where the bars of the changes from the head are, horizontally.
+    fn diff_bar_x(gutter_width: f64, char_width: f64) -> (f64, f64) {
+        let x = gutter_width + char_width;
+        (x, x + 3.0)
+    }
+
+    /// The change from the head whose bar, or triangle, is at `pos` in the
+    /// gutter.
+    fn hovered_hunk(
+        &self,
+        text: &mut PietText,
+        pos: Point,
+        gutter_width: f64,
+    ) -> Option<DiffHunk> {
+        if self.editor.compare.is_some() {
+            return None;
+        }
+        let char_width = self.config.editor_text_width(text, "W");
+        let (x0, x1) = Self::diff_bar_x(gutter_width, char_width);
+        if pos.x < x0 - 3.0 || pos.x > x1 || pos.y < 0.0 {
+            return None;
+        }
+        let line_height = self.config.editor.line_height as f64;
+        let line =
+            ((pos.y + self.editor.scroll_offset.y) / line_height).floor() as usize;
+        hunk_at_line(&self.buffer.diff_hunks(), line).cloned()
+    }
+
+    fn paint_gutter_markers(
+        &self,
+        ctx: &mut PaintCtx,
+        gutter_width: f64,
+        start_line: usize,
+        end_line: usize,
+    ) {
+        let line_height = self.config.editor.line_height as f64;
+        let width = 16.0;
+        let height = 16.0;
+        let char_width = self.config.editor_text_width(ctx.text(), "W");
+        let (x, _) = Self::gutter_marker_x(gutter_width, char_width);
+        for (line, marker) in self.gutter_markers(start_line, end_line).iter() {
+            let svg = get_svg(marker.icon()).unwrap();
+            let rect = Size::new(width, height).to_rect().with_origin(Point::new(
+                x,
+                (line_height - height) / 2.0 + line_height * *line as f64
+                    - self.editor.scroll_offset.y,
+            ));
+            ctx.draw_svg(
+                &svg,
+                rect,
+                Some(self.config.get_color_unchecked(marker.color())),
+            );
+        }
+    }
+
+    /// The line with diagnostics whose gutter marker is under the mouse.
+    fn hovered_marker_line(
+        &self,
+        text: &mut PietText,
+        pos: Point,
+        gutter_width: f64,
+    ) -> Option<usize> {
+        let char_width = self.config.editor_text_width(text, "W");
+        let (x0, x1) = Self::gutter_marker_x(gutter_width, char_width);
+        if pos.x < x0 || pos.x > x1 || pos.y < 0.0 {
+            return None;
+        }
+        let line_height = self.config.editor.line_height as f64;
+        let line =
+            ((pos.y + self.editor.scroll_offset.y) / line_height).floor() as usize;
+        let diagnostics = self.diagnostics()?;
+        if line_diagnostics(diagnostics, line).is_empty() {
+            return None;
         }
+        Some(line)
     }
 
     fn paint_content(
         &self,
         ctx: &mut PaintCtx,
         is_focused: bool,
+        caret_alpha: f64,
         placeholder: Option<&String>,
         config: &Config,
-    ) {
+    ) -> Vec<(Rect, Command)> {
         let line_height = self.config.editor.line_height as f64;
         if self.editor.compare.is_none() {
-            self.paint_cursor(ctx, is_focused, placeholder, config);
+            self.paint_cursor(ctx, is_focused, caret_alpha, placeholder, config);
             self.paint_find(ctx);
         }
         let self_size = ctx.size();
@@ -1768,6 +2506,8 @@ impl LapceEditorBufferData {
         let char_width = text_layout.size().width;
         let y_shift = (line_height - text_layout.size().height) / 2.0;
 
+        self.paint_rulers(ctx, char_width);
+
         if let Some(compare) = self.editor.compare.as_ref() {
             if let Some(changes) = self.buffer.history_changes.get(compare) {
                 let cursor_line =
@@ -1968,7 +2708,7 @@ impl LapceEditorBufferData {
                     }
                 }
             }
-            return;
+            return Vec::new();
         }
 
         let cursor_offset = self.editor.cursor.offset();
@@ -2009,7 +2749,8 @@ impl LapceEditorBufferData {
         }
 
         self.paint_snippet(ctx);
-        self.paint_diagnostics(ctx);
+        let mut links = self.paint_diagnostics(ctx);
+        links.extend(self.paint_diff_hunk(ctx));
         if self.buffer.len() == 0 {
             if let Some(placeholder) = placeholder {
                 let text_layout = ctx
@@ -2026,6 +2767,7 @@ impl LapceEditorBufferData {
                 ctx.draw_text(&text_layout, Point::new(0.0, y_shift));
             }
         }
+        links
     }
 
     fn paint_cursor_on_line(
@@ -2112,18 +2854,22 @@ impl LapceEditorBufferData {
                         let caret_actual_line =
                             self.buffer.line_of_offset(region.end());
                         if caret_actual_line == actual_line {
-                            let size = ctx.size();
-                            ctx.fill(
-                                Rect::ZERO
-                                    .with_origin(Point::new(
-                                        0.0,
-                                        line as f64 * line_height,
-                                    ))
-                                    .with_size(Size::new(size.width, line_height)),
-                                self.config.get_color_unchecked(
-                                    LapceTheme::EDITOR_CURRENT_LINE,
-                                ),
-                            );
+                            if let Some(color) = self.current_line_color(is_focused)
+                            {
+                                let size = ctx.size();
+                                ctx.fill(
+                                    Rect::ZERO
+                                        .with_origin(Point::new(
+                                            0.0,
+                                            line as f64 * line_height,
+                                        ))
+                                        .with_size(Size::new(
+                                            size.width,
+                                            line_height,
+                                        )),
+                                    &color,
+                                );
+                            }
                         }
                     }
                 }
@@ -2151,14 +2897,18 @@ impl LapceEditorBufferData {
         if cursor_line == actual_line {
             match &self.editor.cursor.mode {
                 CursorMode::Normal(_) => {
-                    let size = ctx.size();
-                    ctx.fill(
-                        Rect::ZERO
-                            .with_origin(Point::new(0.0, line as f64 * line_height))
-                            .with_size(Size::new(size.width, line_height)),
-                        self.config
-                            .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
-                    );
+                    if let Some(color) = self.current_line_color(is_focused) {
+                        let size = ctx.size();
+                        ctx.fill(
+                            Rect::ZERO
+                                .with_origin(Point::new(
+                                    0.0,
+                                    line as f64 * line_height,
+                                ))
+                                .with_size(Size::new(size.width, line_height)),
+                            &color,
+                        );
+                    }
                 }
                 _ => (),
             }
@@ -2186,14 +2936,20 @@ impl LapceEditorBufferData {
         }
     }
 
+    /// Paints the cursors, with the caret at `caret_alpha` of its color as
+    /// it blinks.
     fn paint_cursor(
         &self,
         ctx: &mut PaintCtx,
         is_focused: bool,
+        caret_alpha: f64,
         placeholder: Option<&String>,
         config: &Config,
     ) {
         let line_height = self.config.editor.line_height as f64;
+        let caret = self.config.get_color_unchecked(LapceTheme::EDITOR_CARET);
+        let caret = Some(caret.clone().with_alpha(caret.as_rgba().3 * caret_alpha))
+            .filter(|_| is_focused && caret_alpha > 0.0);
         let start_line =
             (self.editor.scroll_offset.y / line_height).floor() as usize;
         let end_line = ((self.editor.size.borrow().height
@@ -2206,7 +2962,7 @@ impl LapceEditorBufferData {
                 let line = self.buffer.line_of_offset(*offset);
                 self.paint_cursor_line(ctx, line, is_focused, placeholder);
 
-                if is_focused {
+                if let Some(caret) = caret.as_ref() {
                     let (x0, x1) = self.editor.cursor.current_char(
                         ctx.text(),
                         &self.buffer,
@@ -2217,7 +2973,7 @@ impl LapceEditorBufferData {
                         Rect::ZERO
                             .with_origin(Point::new(x0, line as f64 * line_height))
                             .with_size(Size::new(char_width, line_height)),
-                        self.config.get_color_unchecked(LapceTheme::EDITOR_CARET),
+                        caret,
                     );
                 }
             }
@@ -2282,7 +3038,7 @@ impl LapceEditorBufferData {
                         );
                     }
 
-                    if is_focused {
+                    if let Some(caret) = caret.as_ref() {
                         let line = self.buffer.line_of_offset(*end);
 
                         let (x0, x1) = self.editor.cursor.current_char(
@@ -2298,8 +3054,7 @@ impl LapceEditorBufferData {
                                     line as f64 * line_height,
                                 ))
                                 .with_size(Size::new(char_width, line_height)),
-                            self.config
-                                .get_color_unchecked(LapceTheme::EDITOR_CARET),
+                            caret,
                         );
                     }
                 }
@@ -2316,11 +3071,15 @@ impl LapceEditorBufferData {
                 let start = self.buffer.offset_of_line(start_line);
                 let end = self.buffer.offset_of_line(end_line + 1);
                 let regions = selection.regions_in_range(start, end);
-                for region in regions {
-                    if region.start() == region.end() {
-                        let line = self.buffer.line_of_offset(region.start());
+                // the current lines go first, so that the selections on them
+                // are painted over their background
+                for line in self.editor.cursor.current_lines(&self.buffer) {
+                    if line >= start_line && line <= end_line {
                         self.paint_cursor_line(ctx, line, is_focused, placeholder);
-                    } else {
+                    }
+                }
+                for region in regions {
+                    if !region.is_caret() {
                         let start = region.start();
                         let end = region.end();
                         let paint_start_line = start_line;
@@ -2366,7 +3125,7 @@ impl LapceEditorBufferData {
                 }
 
                 for region in regions {
-                    if is_focused {
+                    if let Some(caret) = caret.as_ref() {
                         let (line, col) =
                             self.buffer.offset_to_line_col(region.end());
                         let x = col as f64 * width;
@@ -2376,8 +3135,7 @@ impl LapceEditorBufferData {
                                 Point::new(x, y),
                                 Point::new(x, y + line_height),
                             ),
-                            self.config
-                                .get_color_unchecked(LapceTheme::EDITOR_CARET),
+                            caret,
                             2.0,
                         )
                     }
@@ -2386,6 +3144,41 @@ impl LapceEditorBufferData {
         }
     }
 
+    /// The rulers are laid out in content coordinates, so they follow the
+    /// horizontal scroll and the font size without extra bookkeeping.
+    fn paint_rulers(&self, ctx: &mut PaintCtx, char_width: f64) {
+        let path = match &self.buffer.content {
+            BufferContent::File(path) => path,
+            BufferContent::Local(_) => return,
+        };
+        let rulers = self
+            .config
+            .editor
+            .rulers_for(path, self.buffer.editorconfig.max_line_length);
+        let rect = ctx.region().bounding_box();
+        let color = self.config.get_color_unchecked(LapceTheme::EDITOR_RULER);
+        let last_color = self
+            .config
+            .get_color(LapceTheme::EDITOR_LAST_RULER)
+            .unwrap_or(color);
+        for (i, col) in rulers.iter().enumerate() {
+            let x = (*col as f64 * char_width).round() + 0.5;
+            if x < rect.x0 || x > rect.x1 {
+                continue;
+            }
+            let color = if i + 1 == rulers.len() && rulers.len() > 1 {
+                last_color
+            } else {
+                color
+            };
+            ctx.stroke(
+                Line::new(Point::new(x, rect.y0), Point::new(x, rect.y1)),
+                color,
+                1.0,
+            );
+        }
+    }
+
     fn paint_cursor_line(
         &self,
         ctx: &mut PaintCtx,
@@ -2396,17 +3189,37 @@ impl LapceEditorBufferData {
         if !is_focused && self.buffer.len() == 0 && placeholder.is_some() {
             return;
         }
+        let color = match self.current_line_color(is_focused) {
+            Some(color) => color,
+            None => return,
+        };
         let line_height = self.config.editor.line_height as f64;
         let size = ctx.size();
         ctx.fill(
             Rect::ZERO
                 .with_origin(Point::new(0.0, line as f64 * line_height))
                 .with_size(Size::new(size.width, line_height)),
-            self.config
-                .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+            &color,
         );
     }
 
+    /// The background of the current lines, fainter when the editor isn't
+    /// focused, or none when the config turns it off.
+    fn current_line_color(&self, is_focused: bool) -> Option<Color> {
+        if !self.config.editor.highlight_current_line() {
+            return None;
+        }
+        let color = self
+            .config
+            .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE)
+            .clone();
+        if is_focused {
+            Some(color)
+        } else {
+            Some(color.with_alpha(0.5))
+        }
+    }
+
     fn paint_find(&self, ctx: &mut PaintCtx) {
         let line_height = self.config.editor.line_height as f64;
         let start_line =
@@ -2505,7 +3318,9 @@ impl LapceEditorBufferData {
         }
     }
 
-    fn paint_diagnostics(&self, ctx: &mut PaintCtx) {
+    /// Paints the squiggles, and the popup listing the diagnostics of a line.
+    /// Returns the links in the popup, for clicks.
+    fn paint_diagnostics(&self, ctx: &mut PaintCtx) -> Vec<(Rect, Command)> {
         let line_height = self.config.editor.line_height as f64;
         let start_line =
             (self.editor.scroll_offset.y / line_height).floor() as usize;
@@ -2530,7 +3345,7 @@ impl LapceEditorBufferData {
                         self.buffer.offset_of_position(&start)
                     };
                     if start_offset == cursor_offset {
-                        current = Some(diagnostic.clone());
+                        current = Some(start.line as usize);
                     }
                     for line in start.line as usize..end.line as usize + 1 {
                         if line < start_line {
@@ -2579,108 +3394,307 @@ impl LapceEditorBufferData {
             }
         }
 
-        if let Some(diagnostic) = current {
-            if self.editor.cursor.is_normal() {
+        let line = match self.editor.line_diagnostics {
+            Some(line) => line,
+            None if self.editor.cursor.is_normal() => match current {
+                Some(line) => line,
+                None => return Vec::new(),
+            },
+            None => return Vec::new(),
+        };
+        let diagnostics = match self.diagnostics() {
+            Some(diagnostics) => line_diagnostics(diagnostics, line),
+            None => return Vec::new(),
+        };
+        if diagnostics.is_empty() {
+            return Vec::new();
+        }
+
+        // every row of the popup, with its icon and where a click on it goes,
+        // placed relative to the top of the popup
+        let mut rows = Vec::new();
+        let max_width = self.editor.size.borrow().width - 40.0;
+        let mut y = 10.0;
+        for (i, diagnostic) in diagnostics.iter().enumerate() {
+            if i > 0 {
+                y += 10.0;
+            }
+            let text_layout = ctx
+                .text()
+                .new_text_layout(diagnostic.diagnositc.message.clone())
+                .font(FontFamily::SYSTEM_UI, 14.0)
+                .text_color(
+                    self.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .max_width(max_width)
+                .build()
+                .unwrap();
+            let height = text_layout.size().height;
+            let marker = GutterMarker::Diagnostic(diagnostic_severity(diagnostic));
+            rows.push((text_layout, 20.0, y, Some(marker), None));
+            y += height;
+
+            let source = diagnostic_source(diagnostic);
+            if !source.is_empty() {
+                let href = diagnostic
+                    .diagnositc
+                    .code_description
+                    .as_ref()
+                    .map(|d| d.href.to_string());
+                let mut text_layout = ctx
+                    .text()
+                    .new_text_layout(source)
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(
+                        self.config
+                            .get_color_unchecked(if href.is_some() {
+                                LapceTheme::EDITOR_FOCUS
+                            } else {
+                                LapceTheme::EDITOR_DIM
+                            })
+                            .clone(),
+                    )
+                    .max_width(max_width);
+                if href.is_some() {
+                    text_layout = text_layout
+                        .default_attribute(TextAttribute::Underline(true));
+                }
+                let text_layout = text_layout.build().unwrap();
+                let command = href.map(|href| {
+                    Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::OpenUrl(href),
+                        Target::Widget(*self.main_split.tab_id),
+                    )
+                });
+                y += 5.0;
+                let height = text_layout.size().height;
+                rows.push((text_layout, 20.0, y, None, command));
+                y += height;
+            }
+
+            for related in diagnostic
+                .diagnositc
+                .related_information
+                .as_ref()
+                .unwrap_or(&Vec::new())
+            {
+                let text = format!(
+                    "{}[{}:{}]: {}",
+                    PathBuf::from(related.location.uri.path())
+                        .file_name()
+                        .and_then(|f| f.to_str())
+                        .unwrap_or(""),
+                    related.location.range.start.line + 1,
+                    related.location.range.start.character + 1,
+                    related.message
+                );
                 let text_layout = ctx
                     .text()
-                    .new_text_layout(diagnostic.diagnositc.message.clone())
-                    .font(FontFamily::SYSTEM_UI, 14.0)
+                    .new_text_layout(text)
+                    .font(FontFamily::SYSTEM_UI, 13.0)
                     .text_color(
                         self.config
-                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
                             .clone(),
                     )
-                    .max_width(self.editor.size.borrow().width - 20.0)
+                    .max_width(max_width - 20.0)
                     .build()
                     .unwrap();
-                let text_size = text_layout.size();
-                let mut text_height = text_size.height;
-
-                let related = diagnostic
-                    .diagnositc
-                    .related_information
-                    .map(|related| {
-                        related
-                            .iter()
-                            .map(|i| {
-                                let text_layout = ctx
-                                    .text()
-                                    .new_text_layout(i.message.clone())
-                                    .font(FontFamily::SYSTEM_UI, 14.0)
-                                    .text_color(
-                                        self.config
-                                            .get_color_unchecked(
-                                                LapceTheme::EDITOR_FOREGROUND,
-                                            )
-                                            .clone(),
-                                    )
-                                    .max_width(
-                                        self.editor.size.borrow().width - 20.0,
-                                    )
-                                    .build()
-                                    .unwrap();
-                                text_height += 10.0 + text_layout.size().height;
-                                text_layout
-                            })
-                            .collect::<Vec<PietTextLayout>>()
-                    })
-                    .unwrap_or(Vec::new());
+                let command = related.location.uri.to_file_path().ok().map(|path| {
+                    Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::JumpToLocation(
+                            None,
+                            EditorLocationNew {
+                                path,
+                                position: Some(related.location.range.start),
+                                scroll_offset: None,
+                                hisotry: None,
+                                selection_end: None,
+                            },
+                        ),
+                        Target::Widget(*self.main_split.tab_id),
+                    )
+                });
+                y += 5.0;
+                let height = text_layout.size().height;
+                rows.push((text_layout, 40.0, y, None, command));
+                y += height;
+            }
+        }
 
-                let start = diagnostic.diagnositc.range.start;
-                let rect = Rect::ZERO
-                    .with_origin(Point::new(
-                        0.0,
-                        (start.line + 1) as f64 * line_height,
-                    ))
-                    .with_size(Size::new(
-                        self.editor.size.borrow().width,
-                        text_height + 20.0,
-                    ));
-                ctx.fill(
+        let origin =
+            Point::new(self.editor.scroll_offset.x, (line + 1) as f64 * line_height);
+        let rect = Rect::ZERO
+            .with_origin(origin)
+            .with_size(Size::new(self.editor.size.borrow().width, y + 10.0));
+        ctx.fill(
+            rect,
+            self.config
+                .get_color_unchecked(LapceTheme::EDITOR_SELECTION),
+        );
+        let marker = GutterMarker::Diagnostic(diagnostic_severity(diagnostics[0]));
+        ctx.stroke(rect, self.config.get_color_unchecked(marker.color()), 1.0);
+
+        let mut links = Vec::new();
+        for (text_layout, x, y, marker, command) in rows {
+            let pos = origin + Vec2::new(10.0 + x, y);
+            if let Some(marker) = marker {
+                let svg = get_svg(marker.icon()).unwrap();
+                let size = 16.0;
+                let first_line =
+                    text_layout.line_metric(0).map(|m| m.height).unwrap_or(size);
+                let rect = Size::new(size, size).to_rect().with_origin(Point::new(
+                    origin.x + 10.0,
+                    pos.y + (first_line - size) / 2.0,
+                ));
+                ctx.draw_svg(
+                    &svg,
                     rect,
-                    self.config
-                        .get_color_unchecked(LapceTheme::EDITOR_SELECTION),
+                    Some(self.config.get_color_unchecked(marker.color())),
                 );
+            }
+            if let Some(command) = command {
+                links.push((text_layout.size().to_rect().with_origin(pos), command));
+            }
+            ctx.draw_text(&text_layout, pos);
+        }
+        links
+    }
 
-                let severity = diagnostic
-                    .diagnositc
-                    .severity
-                    .as_ref()
-                    .unwrap_or(&DiagnosticSeverity::Information);
-                let color = match severity {
-                    DiagnosticSeverity::Error => {
-                        self.config.get_color_unchecked(LapceTheme::LAPCE_ERROR)
-                    }
-                    DiagnosticSeverity::Warning => {
-                        self.config.get_color_unchecked(LapceTheme::LAPCE_WARN)
-                    }
-                    _ => self.config.get_color_unchecked(LapceTheme::LAPCE_WARN),
-                };
-                ctx.stroke(rect, color, 1.0);
-                ctx.draw_text(
-                    &text_layout,
-                    Point::new(
-                        10.0 + self.editor.scroll_offset.x,
-                        (start.line + 1) as f64 * line_height + 10.0,
-                    ),
-                );
-                let mut text_height = text_size.height;
-
-                for text in related {
-                    text_height += 10.0;
-                    ctx.draw_text(
-                        &text,
-                        Point::new(
-                            10.0 + self.editor.scroll_offset.x,
-                            (start.line + 1) as f64 * line_height
-                                + 10.0
-                                + text_height,
-                        ),
-                    );
-                    text_height += text.size().height;
-                }
+    /// Paints the popup of the change from the head opened from the gutter,
+    /// with the lines of the head it replaces. Returns its links, for clicks.
+    fn paint_diff_hunk(&self, ctx: &mut PaintCtx) -> Vec<(Rect, Command)> {
+        let hunk = match self.editor.diff_hunk.as_ref() {
+            Some(hunk) => hunk,
+            None => return Vec::new(),
+        };
+        let path = match &self.buffer.content {
+            BufferContent::File(path) => path,
+            BufferContent::Local(_) => return Vec::new(),
+        };
+        // gone once the text changed it
+        let old_text = match self.buffer.diff_hunk_old_text(hunk) {
+            Some(old_text) => old_text,
+            None => return Vec::new(),
+        };
+        let line_height = self.config.editor.line_height as f64;
+
+        // the texts of the popup, placed relative to its top
+        let mut rows = Vec::new();
+        let mut y = 10.0;
+        let old_lines: Vec<&str> = old_text.lines().collect();
+        for line in old_lines.iter().take(DIFF_HUNK_POPUP_LINES) {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(line.replace('\t', "    "))
+                .font(
+                    self.config.editor.font_family(),
+                    self.config.editor.font_size as f64,
+                )
+                .text_color(
+                    self.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_y = y + (line_height - text_layout.size().height) / 2.0;
+            rows.push((text_layout, Point::new(0.0, text_y), None));
+            y += line_height;
+        }
+        if old_lines.len() > DIFF_HUNK_POPUP_LINES {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(format!(
+                    "… {} more lines",
+                    old_lines.len() - DIFF_HUNK_POPUP_LINES
+                ))
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    self.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let height = text_layout.size().height;
+            rows.push((text_layout, Point::new(0.0, y), None));
+            y += height;
+        }
+        if !old_lines.is_empty() {
+            y += 10.0;
+        }
+
+        let mut actions = vec![(
+            "Revert Hunk",
+            LapceUICommand::RevertDiffHunk(path.clone(), hunk.clone()),
+        )];
+        // added lines have nothing of the head to copy
+        if !old_lines.is_empty() {
+            actions.push((
+                "Copy",
+                LapceUICommand::CopyDiffHunk(path.clone(), hunk.clone()),
+            ));
+        }
+        let mut x = 0.0;
+        let mut height: f64 = 0.0;
+        for (label, command) in actions {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(label)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    self.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOCUS)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let size = text_layout.size();
+            let command = Command::new(
+                LAPCE_UI_COMMAND,
+                command,
+                Target::Widget(*self.main_split.tab_id),
+            );
+            rows.push((text_layout, Point::new(x, y), Some(command)));
+            x += size.width + 20.0;
+            height = height.max(size.height);
+        }
+        y += height;
+
+        let line = match hunk.kind {
+            DiffHunkKind::Removed => hunk.lines.start,
+            DiffHunkKind::Added | DiffHunkKind::Modified => hunk.lines.end,
+        };
+        let origin =
+            Point::new(self.editor.scroll_offset.x, line as f64 * line_height);
+        let rect = Rect::ZERO
+            .with_origin(origin)
+            .with_size(Size::new(self.editor.size.borrow().width, y + 10.0));
+        ctx.fill(
+            rect,
+            self.config
+                .get_color_unchecked(LapceTheme::EDITOR_SELECTION),
+        );
+        ctx.stroke(
+            rect,
+            self.config.get_color_unchecked(hunk.kind.color()),
+            1.0,
+        );
+
+        let mut links = Vec::new();
+        for (text_layout, pos, command) in rows {
+            let pos = origin + Vec2::new(10.0 + pos.x, pos.y);
+            if let Some(command) = command {
+                links.push((text_layout.size().to_rect().with_origin(pos), command));
             }
+            ctx.draw_text(&text_layout, pos);
         }
+        links
     }
 }
 
@@ -2704,14 +3718,31 @@ impl KeyPressFocus for LapceEditorBufferData {
                 self.editor.content
                     == BufferContent::Local(LocalBufferKind::SourceControl)
             }
-            "search_focus" => {
-                self.editor.content == BufferContent::Local(LocalBufferKind::Search)
-            }
+            "search_focus" => matches!(
+                self.editor.content,
+                BufferContent::Local(
+                    LocalBufferKind::Search
+                        | LocalBufferKind::SearchInclude
+                        | LocalBufferKind::SearchReplace
+                )
+            ),
             "in_snippet" => self.editor.snippet.is_some(),
             "list_focus" => {
                 self.completion.status != CompletionStatus::Inactive
                     && self.completion.len() > 0
             }
+            "completion_ignores_enter" => {
+                self.completion.status != CompletionStatus::Inactive
+                    && self.completion.len() > 0
+                    && !self.completion.accepts_enter(
+                        &self.config.editor.accept_suggestion_on_enter,
+                    )
+            }
+            "completion_accepts_tab" => {
+                self.config.editor.accept_suggestion_on_tab
+                    && self.completion.status != CompletionStatus::Inactive
+                    && self.completion.len() > 0
+            }
             _ => false,
         }
     }
@@ -2723,6 +3754,12 @@ impl KeyPressFocus for LapceEditorBufferData {
         count: Option<usize>,
         env: &Env,
     ) -> CommandExecuted {
+        if self.is_read_only() && cmd.is_edit() {
+            return CommandExecuted::Yes;
+        }
+        if cmd.is_edit() {
+            self.keep_preview();
+        }
         if let Some(movement) = cmd.move_command(count) {
             self.do_move(&movement, count.unwrap_or(1));
             if let Some(snippet) = self.editor.snippet.as_ref() {
@@ -2801,6 +3838,15 @@ impl KeyPressFocus for LapceEditorBufferData {
                     }
                 }
             }
+            LapceCommand::SplitEvenSize => {
+                if let Some(split_id) = self.editor.split_id {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SplitEvenSize,
+                        Target::Widget(split_id),
+                    ));
+                }
+            }
             LapceCommand::SplitVertical => {
                 if let Some(split_id) = self.editor.split_id.clone() {
                     if let BufferContent::File(_) = &self.editor.content {
@@ -2812,22 +3858,76 @@ impl KeyPressFocus for LapceEditorBufferData {
                     }
                 }
             }
+            LapceCommand::SplitVerticalEmpty => {
+                if let Some(split_id) = self.editor.split_id.clone() {
+                    match &self.editor.content {
+                        BufferContent::File(_)
+                        | BufferContent::Local(LocalBufferKind::Empty) => {
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::SplitEditorEmpty(
+                                    self.editor.view_id,
+                                ),
+                                Target::Widget(split_id),
+                            ));
+                        }
+                        BufferContent::Local(_) => {}
+                    }
+                }
+            }
             LapceCommand::SplitClose => {
                 if let Some(split_id) = self.editor.split_id.clone() {
-                    if let BufferContent::File(_) = &self.editor.content {
-                        ctx.submit_command(Command::new(
-                            LAPCE_UI_COMMAND,
-                            LapceUICommand::SplitEditorClose(self.editor.view_id),
-                            Target::Widget(split_id),
-                        ));
+                    match &self.editor.content {
+                        BufferContent::File(_)
+                        | BufferContent::Local(LocalBufferKind::Empty)
+                        | BufferContent::Local(LocalBufferKind::Log) => {
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::SplitEditorClose(
+                                    self.editor.view_id,
+                                ),
+                                Target::Widget(split_id),
+                            ));
+                        }
+                        BufferContent::Local(_) => {}
                     }
                 }
             }
+            LapceCommand::TogglePinEditor => {
+                let editor = Arc::make_mut(&mut self.editor);
+                editor.pinned = !editor.pinned;
+            }
+            LapceCommand::CopyFilePath => {
+                if let BufferContent::File(path) = &self.buffer.content {
+                    if let Some(path) = path.to_str() {
+                        Application::global().clipboard().put_string(path);
+                    }
+                }
+            }
+            LapceCommand::RevealInFileExplorer => {
+                if let BufferContent::File(path) = &self.buffer.content {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RevealInFileExplorer(path.clone()),
+                        Target::Auto,
+                    ));
+                }
+            }
+            LapceCommand::ToggleLineNumbers => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ToggleLineNumbers,
+                    Target::Widget(self.view_id),
+                ));
+            }
             LapceCommand::Undo => {
+                self.restore_undo_history();
                 self.initiate_diagnositcs_offset();
                 let proxy = self.proxy.clone();
+                let old_rope = self.buffer.rope.clone();
                 let buffer = self.buffer_mut();
                 if let Some(delta) = buffer.do_undo(proxy) {
+                    self.inactive_apply_delta(ctx, &old_rope, &delta);
                     self.jump_to_nearest_delta(&delta);
                     self.update_diagnositcs_offset(&delta);
                 }
@@ -2835,8 +3935,10 @@ impl KeyPressFocus for LapceEditorBufferData {
             LapceCommand::Redo => {
                 self.initiate_diagnositcs_offset();
                 let proxy = self.proxy.clone();
+                let old_rope = self.buffer.rope.clone();
                 let buffer = self.buffer_mut();
                 if let Some(delta) = buffer.do_redo(proxy) {
+                    self.inactive_apply_delta(ctx, &old_rope, &delta);
                     self.jump_to_nearest_delta(&delta);
                     self.update_diagnositcs_offset(&delta);
                 }
@@ -3003,17 +4105,85 @@ impl KeyPressFocus for LapceEditorBufferData {
             }
             LapceCommand::ClipboardPaste => {
                 if let Some(s) = Application::global().clipboard().get_string() {
+                    let (content, count) = self.sanitize_clipboard(s);
                     let data = RegisterData {
-                        content: s.to_string(),
+                        content,
                         mode: VisualMode::Normal,
                     };
-                    self.paste(ctx, &data);
+                    let delta = self.paste(ctx, &data);
+                    self.set_pasted(count, &delta);
+                }
+            }
+            LapceCommand::PasteAndMatchIndentation => {
+                if let Some(s) = Application::global().clipboard().get_string() {
+                    let (content, count) = self.sanitize_clipboard(s);
+                    if let Some(delta) = self.paste_clipboard(ctx, &content, true) {
+                        self.set_pasted(count, &delta);
+                    }
+                }
+            }
+            LapceCommand::PasteWithoutFormatting => {
+                if let Some(s) = Application::global().clipboard().get_string() {
+                    self.paste_clipboard(ctx, &s, false);
                 }
             }
             LapceCommand::Paste => {
                 let data = self.main_split.register.unamed.clone();
                 self.paste(ctx, &data);
             }
+            LapceCommand::FixPastedText => {
+                self.fix_pasted_text(ctx);
+            }
+            LapceCommand::KeepPastedText => {
+                Arc::make_mut(&mut self.editor).pasted = None;
+            }
+            LapceCommand::OverwriteFile => {
+                self.overwrite_file(ctx);
+            }
+            LapceCommand::ReloadFromDisk => {
+                self.reload_from_disk(ctx);
+            }
+            LapceCommand::IgnoreDiskChange => {
+                self.ignore_disk_change();
+            }
+            LapceCommand::EditorZoomIn => {
+                Arc::make_mut(&mut self.editor).zoom_by(1);
+            }
+            LapceCommand::EditorZoomOut => {
+                Arc::make_mut(&mut self.editor).zoom_by(-1);
+            }
+            LapceCommand::EditorZoomReset => {
+                Arc::make_mut(&mut self.editor).zoom = 1.0;
+            }
+            LapceCommand::NarrowToSelection => {
+                self.narrow_to_selection(ctx);
+            }
+            LapceCommand::WidenFromNarrow => {
+                self.widen_from_narrow(ctx);
+            }
+            LapceCommand::RestoreDeletedFile => {
+                if let BufferContent::File(path) = &self.buffer.content {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::RestoreDeleted.to_string(),
+                            palette_desc: None,
+                            data: Some(json!({ "path": path })),
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            LapceCommand::CompareWithDisk => {
+                if self.buffer.histories.contains_key("disk") {
+                    let editor = Arc::make_mut(&mut self.editor);
+                    editor.compare = match editor.compare.as_deref() {
+                        Some("disk") => None,
+                        _ => Some("disk".to_string()),
+                    };
+                }
+            }
             LapceCommand::DeleteWordBackward => {
                 let selection = match self.editor.cursor.mode {
                     CursorMode::Normal(_) | CursorMode::Visual { .. } => {
@@ -3108,11 +4278,19 @@ impl KeyPressFocus for LapceEditorBufferData {
                 self.update_completion(ctx);
             }
             LapceCommand::InsertTab => {
+                if self.expand_abbreviation(ctx, true) {
+                    return CommandExecuted::Yes;
+                }
                 let selection = self.editor.cursor.edit_selection(&self.buffer);
+                let indent = self
+                    .buffer
+                    .editorconfig
+                    .indent_unit()
+                    .unwrap_or_else(|| "\t".to_string());
                 let (selection, _) = self.edit(
                     ctx,
                     &selection,
-                    "\t",
+                    &indent,
                     None,
                     true,
                     EditType::InsertChars,
@@ -3120,6 +4298,22 @@ impl KeyPressFocus for LapceEditorBufferData {
                 self.set_cursor(Cursor::new(CursorMode::Insert(selection), None));
                 self.update_completion(ctx);
             }
+            LapceCommand::ExpandAbbreviation => {
+                self.expand_abbreviation(ctx, false);
+            }
+            LapceCommand::SelectMatchingTagPair => {
+                let offset = self.editor.cursor.offset();
+                if let Some(names) = self.buffer.tag_names(offset) {
+                    let mut selection = Selection::new();
+                    for (start, end) in names {
+                        selection.add_region(SelRegion::new(start, end, None));
+                    }
+                    self.set_cursor(Cursor::new(
+                        CursorMode::Insert(selection),
+                        None,
+                    ));
+                }
+            }
             LapceCommand::InsertNewLine => {
                 let selection = self.editor.cursor.edit_selection(&self.buffer);
                 if selection.regions().len() > 1 {
@@ -3174,6 +4368,12 @@ impl KeyPressFocus for LapceEditorBufferData {
             LapceCommand::JumpLocationForward => {
                 self.jump_location_forward(ctx, env);
             }
+            LapceCommand::PaneBackFile => {
+                self.cycle_shown_files(ctx, false);
+            }
+            LapceCommand::PaneForwardFile => {
+                self.cycle_shown_files(ctx, true);
+            }
             LapceCommand::NextError => {
                 self.next_error(ctx, env);
             }
@@ -3185,10 +4385,28 @@ impl KeyPressFocus for LapceEditorBufferData {
             LapceCommand::ListNext => {
                 let completion = Arc::make_mut(&mut self.completion);
                 completion.next();
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
             }
             LapceCommand::ListPrevious => {
                 let completion = Arc::make_mut(&mut self.completion);
                 completion.previous();
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
+            }
+            LapceCommand::ListNextPage => {
+                let page = self.config.editor.completion_max_visible_items.max(1);
+                let completion = Arc::make_mut(&mut self.completion);
+                completion.next_page(page);
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
+            }
+            LapceCommand::ListPreviousPage => {
+                let page = self.config.editor.completion_max_visible_items.max(1);
+                let completion = Arc::make_mut(&mut self.completion);
+                completion.previous_page(page);
+                completion
+                    .resolve_current(self.proxy.clone(), ctx.get_external_handle());
             }
             LapceCommand::JumpToNextSnippetPlaceholder => {
                 if let Some(snippet) = self.editor.snippet.as_ref() {
@@ -3296,6 +4514,34 @@ impl KeyPressFocus for LapceEditorBufferData {
                 }
             }
             LapceCommand::NormalMode => {
+                // with nothing to close or no mode to leave, Escape goes from
+                // the inputs of the panels back to the editors
+                if self.is_panel_input()
+                    && self.editor.line_diagnostics.is_none()
+                    && self.completion.status == CompletionStatus::Inactive
+                    && (!self.config.lapce.modal
+                        || matches!(self.editor.cursor.mode, CursorMode::Normal(_)))
+                {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::FocusEditor,
+                        Target::Auto,
+                    ));
+                    return CommandExecuted::Yes;
+                }
+                if self.editor.line_diagnostics.is_some() {
+                    Arc::make_mut(&mut self.editor).line_diagnostics = None;
+                }
+                if self.editor.diff_hunk.is_some() {
+                    Arc::make_mut(&mut self.editor).diff_hunk = None;
+                }
+                if self.completion.status != CompletionStatus::Inactive {
+                    // closed until something is typed, not reopened by the
+                    // next deletion
+                    let completion = Arc::make_mut(&mut self.completion);
+                    completion.cancel();
+                    completion.dismissed = true;
+                }
                 if !self.config.lapce.modal {
                     return CommandExecuted::Yes;
                 }
@@ -3388,6 +4634,7 @@ impl KeyPressFocus for LapceEditorBufferData {
                                                     ),
                                                     scroll_offset: None,
                                                     hisotry: None,
+                                                    selection_end: None,
                                                 },
                                             ),
                                             Target::Auto,
@@ -3418,6 +4665,20 @@ impl KeyPressFocus for LapceEditorBufferData {
                     println!("source control cancel");
                 }
             }
+            LapceCommand::ShowLineDiagnostics => {
+                let line = self.editor.cursor.current_line(&self.buffer);
+                let has_diagnostics = self
+                    .diagnostics()
+                    .map(|d| !line_diagnostics(d, line).is_empty())
+                    .unwrap_or(false);
+                let editor = Arc::make_mut(&mut self.editor);
+                editor.line_diagnostics =
+                    if has_diagnostics && editor.line_diagnostics != Some(line) {
+                        Some(line)
+                    } else {
+                        None
+                    };
+            }
             LapceCommand::ShowCodeActions => {
                 if let Some(actions) = self.current_code_actions() {
                     if actions.len() > 0 {
@@ -3487,66 +4748,78 @@ impl KeyPressFocus for LapceEditorBufferData {
                 }
             }
             LapceCommand::FormatDocument => {
-                if let BufferContent::File(path) = &self.buffer.content {
-                    let path = path.clone();
-                    let proxy = self.proxy.clone();
-                    let buffer_id = self.buffer.id;
-                    let rev = self.buffer.rev;
-                    let event_sink = ctx.get_external_handle();
-                    let (sender, receiver) = bounded(1);
-                    thread::spawn(move || {
-                        proxy.get_document_formatting(
-                            buffer_id,
-                            Box::new(move |result| {
-                                sender.send(result);
-                            }),
-                        );
-
-                        let result = receiver
-                            .recv_timeout(Duration::from_secs(1))
-                            .map_or_else(
-                                |e| Err(anyhow!("{}", e)),
-                                |v| v.map_err(|e| anyhow!("{:?}", e)),
-                            );
-                        event_sink.submit_command(
-                            LAPCE_UI_COMMAND,
-                            LapceUICommand::DocumentFormat(path, rev, result),
-                            Target::Auto,
-                        );
-                    });
-                }
+                self.format_file(ctx, false);
             }
             LapceCommand::Save => {
                 if !self.buffer.dirty {
                     return CommandExecuted::Yes;
                 }
 
+                // a narrowed editor saves the whole file
+                self.format_file(ctx, true);
+            }
+            LapceCommand::SaveAs => {
                 if let BufferContent::File(path) = &self.buffer.content {
                     let path = path.clone();
+                    let scratch = self.buffer.scratch;
+                    let rope = self.buffer.rope.clone();
                     let proxy = self.proxy.clone();
-                    let buffer_id = self.buffer.id;
-                    let rev = self.buffer.rev;
+                    // an older proxy can't write the file, but the one of a
+                    // local workspace is on this machine
+                    let write_locally = self.workspace.kind
+                        == LapceWorkspaceType::Local
+                        && !proxy.has_capability(CAPABILITY_WRITE_FILE);
                     let event_sink = ctx.get_external_handle();
-                    let (sender, receiver) = bounded(1);
                     thread::spawn(move || {
-                        proxy.get_document_formatting(
-                            buffer_id,
-                            Box::new(move |result| {
-                                sender.send(result);
-                            }),
-                        );
-
-                        let result = receiver
-                            .recv_timeout(Duration::from_secs(1))
-                            .map_or_else(
-                                |e| Err(anyhow!("{}", e)),
-                                |v| v.map_err(|e| anyhow!("{:?}", e)),
+                        let default = path
+                            .file_name()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or("")
+                            .to_string();
+                        let new_path = match tinyfiledialogs::save_file_dialog(
+                            "Save As", &default,
+                        ) {
+                            Some(new_path) => PathBuf::from(new_path),
+                            None => return,
+                        };
+                        let opened = new_path.clone();
+                        let done = move |result: Result<Value, Value>| {
+                            if let Err(e) = result {
+                                show_request_error("Save As failed", &e);
+                                return;
+                            }
+                            if scratch {
+                                if let Err(e) = std::fs::remove_file(&path) {
+                                    log::error!(
+                                        "can't delete the scratch {:?}: {}",
+                                        path,
+                                        e
+                                    );
+                                }
+                            }
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::OpenFile(opened),
+                                Target::Auto,
                             );
-                        event_sink.submit_command(
-                            LAPCE_UI_COMMAND,
-                            LapceUICommand::DocumentFormatAndSave(path, rev, result),
-                            Target::Auto,
-                        );
+                        };
+                        if write_locally {
+                            done(
+                                std::fs::write(&new_path, rope.to_string())
+                                    .map(|_| Value::Null)
+                                    .map_err(
+                                        |e| json!({ "message": e.to_string() }),
+                                    ),
+                            );
+                        } else {
+                            // the file is written where the workspace is,
+                            // which is on another machine for a remote one
+                            proxy.write_file(
+                                &new_path,
+                                &rope.to_string(),
+                                Box::new(done),
+                            );
+                        }
                     });
                 }
             }
@@ -3556,7 +4829,11 @@ impl KeyPressFocus for LapceEditorBufferData {
     }
 
     fn receive_char(&mut self, ctx: &mut EventCtx, c: &str) {
+        if self.get_mode() == Mode::Insert && self.is_read_only() {
+            return;
+        }
         if self.get_mode() == Mode::Insert {
+            self.keep_preview();
             let mut selection = self.editor.cursor.edit_selection(&self.buffer);
             let cursor_char =
                 self.buffer.char_at_offset(selection.get_cursor_offset());
@@ -3628,6 +4905,9 @@ impl KeyPressFocus for LapceEditorBufferData {
                     }
                 }
             }
+            if self.completion.dismissed {
+                Arc::make_mut(&mut self.completion).dismissed = false;
+            }
             self.update_completion(ctx);
         } else {
             if let Some(direction) = self.editor.inline_find.clone() {
@@ -3640,10 +4920,27 @@ impl KeyPressFocus for LapceEditorBufferData {
     }
 }
 
+/// How long the zoom of an editor shows once it changed.
+const ZOOM_SHOWN_FOR: Duration = Duration::from_millis(1500);
+
+const BANNER_HEIGHT: f64 = 25.0;
+/// How many lines of the head the popup of a change shows at most.
+const DIFF_HUNK_POPUP_LINES: usize = 10;
+/// How often a smoothly blinking cursor is repainted while it fades.
+const CURSOR_FADE_FRAME: Duration = Duration::from_millis(16);
+
 pub struct LapceEditorView {
     pub view_id: WidgetId,
     pub header: WidgetPod<LapceTabData, LapceEditorHeader>,
+    breadcrumbs: WidgetPod<LapceTabData, LapceEditorBreadcrumbs>,
     pub editor: WidgetPod<LapceTabData, LapceEditorContainer>,
+    hints: EmptyEditorHints,
+    banner_actions: Vec<(Rect, LapceCommand)>,
+    /// Shows the zoom of the editor while it's running, after it changed.
+    zoom_timer: TimerToken,
+    /// The lines hidden above and below the region of a narrowed editor,
+    /// which each take a banner.
+    narrow_hidden: (usize, usize),
 }
 
 impl LapceEditorView {
@@ -3653,12 +4950,242 @@ impl LapceEditorView {
         Self {
             view_id: data.view_id,
             header: WidgetPod::new(header),
+            breadcrumbs: WidgetPod::new(LapceEditorBreadcrumbs::new(data.view_id)),
             editor: WidgetPod::new(editor),
+            hints: EmptyEditorHints::new(),
+            banner_actions: Vec::new(),
+            zoom_timer: TimerToken::INVALID,
+            narrow_hidden: (0, 0),
+        }
+    }
+
+    /// Keeps the same lines in view once the editor is zoomed, and shows
+    /// the zoom for a moment.
+    fn zoom_changed(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &LapceTabData,
+        old_zoom: f64,
+    ) {
+        let old_line_height =
+            Config::zoomed(&data.config, old_zoom).editor.line_height as f64;
+        let editor_data = data.editor_view_content(self.view_id);
+        let scale = editor_data.config.editor.line_height as f64 / old_line_height;
+        let editor_size = editor_data.editor.size.borrow().clone();
+        let size =
+            editor_data.get_size(ctx.text(), editor_size, data.panels.clone());
+        let scroll = self.editor.widget_mut().editor.widget_mut().inner_mut();
+        scroll.set_child_size(size);
+        let offset = scroll.offset();
+        scroll.force_scroll_to(Point::new(offset.x * scale, offset.y * scale));
+        self.zoom_timer = ctx.request_timer(ZOOM_SHOWN_FOR);
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    fn paint_zoom(&self, ctx: &mut PaintCtx, data: &LapceTabData) {
+        let zoom = data.main_split.editors.get(&self.view_id).unwrap().zoom;
+        let text_layout = ctx
+            .text()
+            .new_text_layout(format!("Zoom {}%", (zoom * 100.0).round()))
+            .font(FontFamily::SYSTEM_UI, 13.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        let text_size = text_layout.size();
+        let origin = Point::new(
+            ctx.size().width - text_size.width - 20.0,
+            self.editor.layout_rect().y0 + 10.0,
+        );
+        let rect = text_size.to_rect().with_origin(origin).inflate(8.0, 4.0);
+        ctx.fill(
+            rect,
+            data.config
+                .get_color_unchecked(LapceTheme::PANEL_BACKGROUND),
+        );
+        ctx.draw_text(&text_layout, origin);
+    }
+
+    /// The one line banner at the bottom of the editor, telling about a file
+    /// that was deleted or changed on disk or a save that was refused, or
+    /// offering to fix the characters of the last paste.
+    fn paint_banner(&mut self, ctx: &mut PaintCtx, data: &LapceTabData) {
+        self.banner_actions.clear();
+        let editor_data = data.editor_view_content(self.view_id);
+        let (message, actions) = if editor_data.buffer.deleted {
+            // restoring takes the trash of the proxy
+            let actions = if data.proxy.has_capability(CAPABILITY_TRASH) {
+                vec![("Restore", LapceCommand::RestoreDeletedFile)]
+            } else {
+                Vec::new()
+            };
+            ("The file was deleted".to_string(), actions)
+        } else if editor_data.buffer.changed_on_disk && !editor_data.buffer.dirty {
+            (
+                "The file changed on disk \u{2014}".to_string(),
+                vec![
+                    ("Reload", LapceCommand::ReloadFromDisk),
+                    ("Ignore", LapceCommand::IgnoreDiskChange),
+                ],
+            )
+        } else if let Some(conflict) =
+            editor_data.buffer.save_conflict.or_else(|| {
+                // edited since, so reloading would drop the edits
+                Some(SaveConflict::ChangedOnDisk)
+                    .filter(|_| editor_data.buffer.changed_on_disk)
+            })
+        {
+            let message = match conflict {
+                SaveConflict::ChangedOnDisk => {
+                    "The file changed on disk since it was last saved \u{2014}"
+                }
+                SaveConflict::StaleRevision => {
+                    "The file was reloaded from disk while being edited \u{2014}"
+                }
+            };
+            (
+                message.to_string(),
+                vec![
+                    ("Overwrite", LapceCommand::OverwriteFile),
+                    ("Reload", LapceCommand::ReloadFromDisk),
+                    ("Compare", LapceCommand::CompareWithDisk),
+                ],
+            )
+        } else if let Some(pasted) = editor_data
+            .editor
+            .pasted
+            .as_ref()
+            .filter(|pasted| pasted.rev == editor_data.buffer.rev)
+        {
+            (
+                format!(
+                    "Pasted text contained {} invisible/typographic character{} \u{2014}",
+                    pasted.count,
+                    if pasted.count == 1 { "" } else { "s" }
+                ),
+                vec![
+                    ("Fix", LapceCommand::FixPastedText),
+                    ("Keep", LapceCommand::KeepPastedText),
+                ],
+            )
+        } else {
+            return;
+        };
+        let y = self.editor.layout_rect().y1 - BANNER_HEIGHT;
+        self.paint_banner_line(ctx, data, y, &message, actions);
+    }
+
+    /// The lines of the file a narrowed editor hides above and below its
+    /// region.
+    fn hidden_by_narrow(&self, data: &LapceTabData) -> (usize, usize) {
+        let editor = data.main_split.editors.get(&self.view_id).unwrap();
+        let narrow = match editor.narrow.as_ref() {
+            Some(narrow) => narrow,
+            None => return (0, 0),
+        };
+        match data.main_split.open_files.get(&narrow.path) {
+            Some(file) => (
+                file.line_of_offset(narrow.start),
+                file.last_line() - file.line_of_offset(narrow.end),
+            ),
+            None => (0, 0),
+        }
+    }
+
+    /// The banners of a narrowed editor, telling how many lines of the file
+    /// are hidden above and below its region.
+    fn paint_narrow_banners(&mut self, ctx: &mut PaintCtx, data: &LapceTabData) {
+        let (above, below) = self.narrow_hidden;
+        let hidden = |lines: usize, place: &str| {
+            format!(
+                "{} line{} hidden {} \u{2014}",
+                lines,
+                if lines == 1 { "" } else { "s" },
+                place
+            )
+        };
+        if above > 0 {
+            let y = self.editor.layout_rect().y0 - BANNER_HEIGHT;
+            self.paint_banner_line(
+                ctx,
+                data,
+                y,
+                &hidden(above, "above"),
+                vec![("Widen", LapceCommand::WidenFromNarrow)],
+            );
+        }
+        if below > 0 {
+            let y = self.editor.layout_rect().y1;
+            self.paint_banner_line(
+                ctx,
+                data,
+                y,
+                &hidden(below, "below"),
+                vec![("Widen", LapceCommand::WidenFromNarrow)],
+            );
+        }
+    }
+
+    fn paint_banner_line(
+        &mut self,
+        ctx: &mut PaintCtx,
+        data: &LapceTabData,
+        y: f64,
+        message: &str,
+        actions: Vec<(&str, LapceCommand)>,
+    ) {
+        let size = ctx.size();
+        let height = BANNER_HEIGHT;
+        let rect = Size::new(size.width, height)
+            .to_rect()
+            .with_origin(Point::new(0.0, y));
+        ctx.fill(
+            rect,
+            data.config
+                .get_color_unchecked(LapceTheme::PANEL_BACKGROUND),
+        );
+
+        let mut texts = vec![(message, None)];
+        for (i, (text, command)) in actions.into_iter().enumerate() {
+            if i > 0 {
+                texts.push(("|", None));
+            }
+            texts.push((text, Some(command)));
+        }
+        let mut x = 10.0;
+        for (text, command) in texts {
+            let color = if command.is_some() {
+                LapceTheme::EDITOR_FOCUS
+            } else {
+                LapceTheme::EDITOR_FOREGROUND
+            };
+            let text_layout = ctx
+                .text()
+                .new_text_layout(text.to_string())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(data.config.get_color_unchecked(color).clone())
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            let origin = Point::new(x, rect.y0 + (height - text_size.height) / 2.0);
+            ctx.draw_text(&text_layout, origin);
+            let text_rect = Size::new(text_size.width, height)
+                .to_rect()
+                .with_origin(Point::new(x, rect.y0));
+            if let Some(command) = command {
+                self.banner_actions.push((text_rect, command));
+            }
+            x += text_size.width + 8.0;
         }
     }
 
     pub fn hide_header(mut self) -> Self {
         self.header.widget_mut().display = false;
+        self.breadcrumbs.widget_mut().display = false;
         self
     }
 
@@ -3693,18 +5220,30 @@ impl LapceEditorView {
             BufferContent::File(_) => {
                 data.focus_area = FocusArea::Editor;
                 data.main_split.active = Arc::new(Some(self.view_id));
+                data.main_split.push_focus_history(self.view_id);
             }
             BufferContent::Local(kind) => match kind {
-                LocalBufferKind::Search => {
+                LocalBufferKind::Search
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchReplace => {
                     data.focus_area = FocusArea::Panel(PanelKind::Search);
                 }
                 LocalBufferKind::SourceControl => {
                     data.focus_area = FocusArea::Panel(PanelKind::SourceControl);
                     Arc::make_mut(&mut data.source_control).active = self.view_id;
                 }
-                LocalBufferKind::Empty => {}
+                LocalBufferKind::Empty
+                | LocalBufferKind::Log
+                | LocalBufferKind::Narrow(_) => {
+                    data.focus_area = FocusArea::Editor;
+                    data.main_split.active = Arc::new(Some(self.view_id));
+                    data.main_split.push_focus_history(self.view_id);
+                }
             },
         }
+        if editor.split_id == Some(data.editors_panel.split_id) {
+            data.focus_area = FocusArea::Panel(PanelKind::Editors);
+        }
     }
 
     pub fn handle_lapce_ui_command(
@@ -3731,6 +5270,11 @@ impl LapceEditorView {
             LapceUICommand::EnsureRectVisible(rect) => {
                 self.ensure_rect_visible(ctx, data, *rect, env);
             }
+            LapceUICommand::ToggleLineNumbers => {
+                let container = self.editor.widget_mut();
+                container.display_gutter = !container.display_gutter;
+                ctx.request_layout();
+            }
             LapceUICommand::ResolveCompletion(buffer_id, rev, offset, item) => {
                 if data.buffer.id != *buffer_id {
                     return;
@@ -3919,11 +5463,41 @@ impl Widget<LapceTabData> for LapceEditorView {
         env: &Env,
     ) {
         let editor = data.main_split.editors.get(&self.view_id).unwrap().clone();
+        let is_empty =
+            editor.content == BufferContent::Local(LocalBufferKind::Empty);
 
         match event {
+            Event::Timer(token) if *token == self.zoom_timer => {
+                self.zoom_timer = TimerToken::INVALID;
+                ctx.request_paint();
+                ctx.set_handled();
+                return;
+            }
+            Event::MouseMove(mouse_event) if is_empty => {
+                self.hints.mouse_move(ctx, mouse_event.pos);
+            }
             Event::MouseDown(mouse_event) => match mouse_event.button {
                 druid::MouseButton::Left => {
                     self.request_focus(ctx, data, true);
+                    if is_empty {
+                        self.hints.mouse_down(ctx, mouse_event);
+                    }
+                    for (rect, command) in self.banner_actions.iter() {
+                        if rect.contains(mouse_event.pos) {
+                            ctx.submit_command(Command::new(
+                                LAPCE_NEW_COMMAND,
+                                LapceCommandNew {
+                                    cmd: command.to_string(),
+                                    data: None,
+                                    palette_desc: None,
+                                    target: CommandTarget::Focus,
+                                },
+                                Target::Widget(self.view_id),
+                            ));
+                            ctx.set_handled();
+                            return;
+                        }
+                    }
                 }
                 druid::MouseButton::Right => {
                     self.request_focus(ctx, data, false);
@@ -3990,8 +5564,13 @@ impl Widget<LapceTabData> for LapceEditorView {
             _ => (),
         }
         data.update_from_editor_buffer_data(editor_data, &editor, &buffer);
+        let zoom = data.main_split.editors.get(&self.view_id).unwrap().zoom;
+        if zoom != editor.zoom {
+            self.zoom_changed(ctx, data, editor.zoom);
+        }
 
         self.header.event(ctx, event, data, env);
+        self.breadcrumbs.event(ctx, event, data, env);
         self.editor.event(ctx, event, data, env);
 
         let offset = self.editor.widget().editor.widget().inner().offset();
@@ -4016,6 +5595,7 @@ impl Widget<LapceTabData> for LapceEditorView {
             _ => (),
         }
         self.header.lifecycle(ctx, event, data, env);
+        self.breadcrumbs.lifecycle(ctx, event, data, env);
         self.editor.lifecycle(ctx, event, data, env);
     }
 
@@ -4026,6 +5606,11 @@ impl Widget<LapceTabData> for LapceEditorView {
         data: &LapceTabData,
         env: &Env,
     ) {
+        self.hints.update(ctx, old_data, data);
+        self.breadcrumbs.update(ctx, data, env);
+        if self.hidden_by_narrow(data) != self.narrow_hidden {
+            ctx.request_layout();
+        }
         if old_data.config.lapce.modal != data.config.lapce.modal {
             if !data.config.lapce.modal {
                 ctx.submit_command(Command::new(
@@ -4075,57 +5660,61 @@ impl Widget<LapceTabData> for LapceEditorView {
         if data.buffer.dirty != old_data.buffer.dirty {
             ctx.request_paint();
         }
+        if data.editor.pinned != old_data.editor.pinned {
+            ctx.request_layout();
+        }
+        if data.editor.preview != old_data.editor.preview {
+            ctx.request_paint();
+        }
+        if data.editor.pasted != old_data.editor.pasted {
+            ctx.request_paint();
+        }
         if data.editor.cursor != old_data.editor.cursor {
             ctx.request_paint();
         }
+        if data.editor.line_diagnostics != old_data.editor.line_diagnostics {
+            ctx.request_paint();
+        }
+        if data.editor.diff_hunk != old_data.editor.diff_hunk {
+            ctx.request_paint();
+        }
+        if data.editor.zoom != old_data.editor.zoom {
+            ctx.request_layout();
+        }
 
         if let BufferContent::Local(kind) = &data.buffer.content {
-            if let LocalBufferKind::Search = kind {
-                if !data.buffer.rope.ptr_eq(&old_data.buffer.rope) {
-                    let pattern = data.buffer.rope.to_string();
-                    let tab_id = (*data.main_split.tab_id).clone();
-                    ctx.request_layout();
-                    ctx.submit_command(Command::new(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::UpdateSearch(pattern.clone()),
-                        Target::Widget(tab_id),
-                    ));
-                    if pattern == "" {
+            if !data.buffer.rope.ptr_eq(&old_data.buffer.rope) {
+                let tab_id = (*data.main_split.tab_id).clone();
+                match kind {
+                    LocalBufferKind::Search => {
+                        let pattern = data.buffer.rope.to_string();
+                        ctx.request_layout();
+                        // the tab runs the search, with the options it keeps
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateSearch(pattern),
+                            Target::Widget(tab_id),
+                        ));
+                    }
+                    LocalBufferKind::SearchInclude => {
                         ctx.submit_command(Command::new(
                             LAPCE_UI_COMMAND,
-                            LapceUICommand::GlobalSearchResult(
-                                pattern.clone(),
-                                Arc::new(HashMap::new()),
+                            LapceUICommand::UpdateSearchInclude(
+                                data.buffer.rope.to_string(),
+                            ),
+                            Target::Widget(tab_id),
+                        ));
+                    }
+                    LocalBufferKind::SearchReplace => {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateSearchReplace(
+                                data.buffer.rope.to_string(),
                             ),
                             Target::Widget(tab_id),
                         ));
-                    } else {
-                        let event_sink = ctx.get_external_handle();
-                        data.proxy.global_search(
-                            data.buffer.rope.to_string(),
-                            Box::new(move |result| {
-                                if let Ok(matches) = result {
-                                    if let Ok(matches) = serde_json::from_value::<
-                                        HashMap<
-                                            PathBuf,
-                                            Vec<(usize, (usize, usize), String)>,
-                                        >,
-                                    >(
-                                        matches
-                                    ) {
-                                        event_sink.submit_command(
-                                            LAPCE_UI_COMMAND,
-                                            LapceUICommand::GlobalSearchResult(
-                                                pattern,
-                                                Arc::new(matches),
-                                            ),
-                                            Target::Widget(tab_id),
-                                        );
-                                    }
-                                }
-                            }),
-                        )
                     }
+                    _ => {}
                 }
             }
         }
@@ -4161,24 +5750,41 @@ impl Widget<LapceTabData> for LapceEditorView {
         env: &Env,
     ) -> Size {
         let self_size = bc.max();
+        if data.main_split.editors.get(&self.view_id).unwrap().content
+            == BufferContent::Local(LocalBufferKind::Empty)
+        {
+            self.hints.layout(ctx, self_size, data);
+        }
         let header_size = self.header.layout(ctx, bc, data, env);
         self.header.set_origin(ctx, data, env, Point::ZERO);
-        let editor_size = if self_size.height > header_size.height {
+        let breadcrumbs_size = self.breadcrumbs.layout(ctx, bc, data, env);
+        self.breadcrumbs.set_origin(
+            ctx,
+            data,
+            env,
+            Point::new(0.0, header_size.height),
+        );
+        // hidden breadcrumbs take no height
+        let mut top = header_size.height + breadcrumbs_size.height;
+        let mut bottom = 0.0;
+        self.narrow_hidden = self.hidden_by_narrow(data);
+        if self.narrow_hidden.0 > 0 {
+            top += BANNER_HEIGHT;
+        }
+        if self.narrow_hidden.1 > 0 {
+            bottom += BANNER_HEIGHT;
+        }
+        let editor_size = if self_size.height > top + bottom {
             let editor_size =
-                Size::new(self_size.width, self_size.height - header_size.height);
+                Size::new(self_size.width, self_size.height - top - bottom);
             let editor_bc = BoxConstraints::new(Size::ZERO, editor_size);
             let size = self.editor.layout(ctx, &editor_bc, data, env);
-            self.editor.set_origin(
-                ctx,
-                data,
-                env,
-                Point::new(0.0, header_size.height),
-            );
+            self.editor.set_origin(ctx, data, env, Point::new(0.0, top));
             size
         } else {
             Size::ZERO
         };
-        Size::new(editor_size.width, editor_size.height + header_size.height)
+        Size::new(editor_size.width, editor_size.height + top + bottom)
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
@@ -4191,11 +5797,23 @@ impl Widget<LapceTabData> for LapceEditorView {
             );
         }
         let start = std::time::SystemTime::now();
-        self.editor.paint(ctx, data, env);
+        if data.main_split.editors.get(&self.view_id).unwrap().content
+            == BufferContent::Local(LocalBufferKind::Empty)
+        {
+            self.hints.paint(ctx, data);
+        } else {
+            self.editor.paint(ctx, data, env);
+            self.paint_banner(ctx, data);
+            self.paint_narrow_banners(ctx, data);
+            if self.zoom_timer != TimerToken::INVALID {
+                self.paint_zoom(ctx, data);
+            }
+        }
         let end = std::time::SystemTime::now();
         let duration = end.duration_since(start).unwrap().as_micros();
         // println!("editor paint took {}", duration);
         self.header.paint(ctx, data, env);
+        self.breadcrumbs.paint(ctx, data, env);
     }
 }
 
@@ -4539,6 +6157,7 @@ pub struct LapceEditorHeader {
     height: f64,
     icon_size: f64,
     icons: Vec<LapceIcon>,
+    more_rect: Rect,
     svg_padding: f64,
 }
 
@@ -4554,6 +6173,7 @@ impl LapceEditorHeader {
             icon_size: 24.0,
             svg_padding: 4.0,
             icons: Vec::new(),
+            more_rect: Rect::ZERO,
         }
     }
 
@@ -4564,15 +6184,20 @@ impl LapceEditorHeader {
         let mut icons = Vec::new();
         let x =
             self_size.width - ((icons.len() + 1) as f64) * (gap + self.icon_size);
+        let (icon, cmd) = if data.editor.pinned {
+            ("pin.svg", LapceCommand::TogglePinEditor)
+        } else {
+            ("close.svg", LapceCommand::SplitClose)
+        };
         let icon = LapceIcon {
-            icon: "close.svg".to_string(),
+            icon: icon.to_string(),
             rect: Size::new(self.icon_size, self.icon_size)
                 .to_rect()
                 .with_origin(Point::new(x, gap)),
             command: Command::new(
                 LAPCE_NEW_COMMAND,
                 LapceCommandNew {
-                    cmd: LapceCommand::SplitClose.to_string(),
+                    cmd: cmd.to_string(),
                     data: None,
                     palette_desc: None,
                     target: CommandTarget::Focus,
@@ -4605,15 +6230,84 @@ impl LapceEditorHeader {
         icons
     }
 
-    pub fn mouse_down(&self, ctx: &mut EventCtx, mouse_event: &MouseEvent) {
+    fn more_menu_items(&self, data: &LapceTabData) -> Vec<MenuItem> {
+        let editor = data.main_split.editors.get(&self.view_id).unwrap();
+        let mut commands = vec![LapceCommand::TogglePinEditor];
+        if let BufferContent::File(_) = &editor.content {
+            commands.push(LapceCommand::CopyFilePath);
+            if data.workspace.path.is_some() {
+                commands.push(LapceCommand::RevealInFileExplorer);
+            }
+        }
+        commands.push(LapceCommand::ToggleLineNumbers);
+        commands
+            .into_iter()
+            .map(|cmd| MenuItem {
+                text: if cmd == LapceCommand::TogglePinEditor {
+                    if editor.pinned {
+                        "Unpin Editor".to_string()
+                    } else {
+                        "Pin Editor".to_string()
+                    }
+                } else {
+                    cmd.get_message().unwrap().to_string()
+                },
+                command: LapceCommandNew {
+                    cmd: cmd.to_string(),
+                    data: None,
+                    palette_desc: None,
+                    target: CommandTarget::Focus,
+                },
+            })
+            .collect()
+    }
+
+    pub fn mouse_down(
+        &self,
+        ctx: &mut EventCtx,
+        mouse_event: &MouseEvent,
+        data: &LapceTabData,
+    ) {
+        if self.more_rect.contains(mouse_event.pos) {
+            let point =
+                ctx.to_window(Point::new(self.more_rect.x0, self.more_rect.y1));
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowMenu(
+                    point,
+                    Arc::new(self.more_menu_items(data)),
+                ),
+                Target::Auto,
+            ));
+            return;
+        }
         for icon in self.icons.iter() {
             if icon.rect.contains(mouse_event.pos) {
                 ctx.submit_command(icon.command.clone());
+                return;
+            }
+        }
+        // the rest of the header is the handle to drag the editor around
+        if mouse_event.button.is_left() {
+            if let Some(split_id) = data
+                .main_split
+                .editors
+                .get(&self.view_id)
+                .and_then(|editor| editor.split_id)
+            {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEditorDragStart(self.view_id),
+                    Target::Widget(split_id),
+                ));
             }
         }
     }
 
     pub fn icon_hit_test(&self, mouse_event: &MouseEvent) -> bool {
+        if self.more_rect.contains(mouse_event.pos) {
+            return true;
+        }
         for icon in self.icons.iter() {
             if icon.rect.contains(mouse_event.pos) {
                 return true;
@@ -4622,6 +6316,28 @@ impl LapceEditorHeader {
         false
     }
 
+    fn paint_icon(
+        &self,
+        ctx: &mut PaintCtx,
+        rect: Rect,
+        icon: &str,
+        config: &Config,
+    ) {
+        if rect.contains(self.mouse_pos) {
+            ctx.fill(
+                &rect,
+                config.get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+            );
+        }
+        if let Some(svg) = get_svg(icon) {
+            ctx.draw_svg(
+                &svg,
+                rect.inflate(-self.svg_padding, -self.svg_padding),
+                Some(config.get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)),
+            );
+        }
+    }
+
     pub fn paint_buffer(&self, ctx: &mut PaintCtx, data: &LapceEditorBufferData) {
         let shadow_width = 5.0;
         let rect = ctx.size().to_rect();
@@ -4639,11 +6355,18 @@ impl LapceEditorHeader {
 
         let mut clip_rect = ctx.size().to_rect();
         if self.view_is_hot {
-            if let Some(icon) = self.icons.iter().rev().next().as_ref() {
+            clip_rect.x1 = self.more_rect.x0;
+        } else if data.buffer.dirty || data.editor.pinned {
+            if let Some(icon) = self.icons.first() {
                 clip_rect.x1 = icon.rect.x0;
             }
         }
-        if let BufferContent::File(path) = &data.buffer.content {
+        // a narrowed editor shows the file it's narrowed from
+        let path = match &data.buffer.content {
+            BufferContent::File(path) => Some(path),
+            BufferContent::Local(_) => data.editor.narrow.as_ref().map(|n| &n.path),
+        };
+        if let Some(path) = path {
             ctx.with_save(|ctx| {
                 ctx.clip(clip_rect);
                 let mut path = path.clone();
@@ -4667,10 +6390,20 @@ impl LapceEditorHeader {
                 if let Some(compare) = data.editor.compare.as_ref() {
                     file_name += " (Working tree)";
                 }
+                if data.editor.narrow.is_some() {
+                    file_name += " (Narrowed)";
+                }
+                // the name of a previewed file is in italics
+                let style = if data.editor.preview {
+                    FontStyle::Italic
+                } else {
+                    FontStyle::Regular
+                };
                 let text_layout = ctx
                     .text()
                     .new_text_layout(file_name)
                     .font(FontFamily::SYSTEM_UI, 13.0)
+                    .default_attribute(TextAttribute::Style(style))
                     .text_color(
                         data.config
                             .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
@@ -4709,27 +6442,60 @@ impl LapceEditorHeader {
                 }
             });
         }
+        if data.buffer.content == BufferContent::Local(LocalBufferKind::Log) {
+            ctx.with_save(|ctx| {
+                ctx.clip(clip_rect);
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout("Log")
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                ctx.draw_text(&text_layout, Point::new(10.0, 7.0));
+                let x = text_layout.size().width;
+
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout(format!(
+                        "{} and above",
+                        data.main_split.log_view_level
+                    ))
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                ctx.draw_text(&text_layout, Point::new(10.0 + x + 5.0, 7.0));
+            });
+        }
 
-        if self.view_is_hot {
-            for icon in self.icons.iter() {
-                if icon.rect.contains(self.mouse_pos) {
+        for (i, icon) in self.icons.iter().enumerate() {
+            if i == 0 && !data.editor.pinned {
+                // the close button is a dot for dirty buffers until hovered
+                let hovered = self.view_is_hot && icon.rect.contains(self.mouse_pos);
+                if data.buffer.dirty && !hovered {
                     ctx.fill(
-                        &icon.rect,
+                        Circle::new(icon.rect.center(), 4.0),
                         data.config
-                            .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
-                    );
-                }
-                if let Some(svg) = get_svg(&icon.icon) {
-                    ctx.draw_svg(
-                        &svg,
-                        icon.rect.inflate(-self.svg_padding, -self.svg_padding),
-                        Some(
-                            data.config
-                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
-                        ),
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
                     );
+                    continue;
                 }
             }
+            if self.view_is_hot || (i == 0 && data.editor.pinned) {
+                self.paint_icon(ctx, icon.rect, &icon.icon, &data.config);
+            }
+        }
+        if self.view_is_hot {
+            self.paint_icon(ctx, self.more_rect, "ellipsis.svg", &data.config);
         }
     }
 }
@@ -4742,6 +6508,9 @@ impl Widget<LapceTabData> for LapceEditorHeader {
         data: &mut LapceTabData,
         env: &Env,
     ) {
+        if !self.display {
+            return;
+        }
         match event {
             Event::MouseMove(mouse_event) => {
                 self.mouse_pos = mouse_event.pos;
@@ -4754,7 +6523,7 @@ impl Widget<LapceTabData> for LapceEditorHeader {
                 }
             }
             Event::MouseDown(mouse_event) => {
-                self.mouse_down(ctx, mouse_event);
+                self.mouse_down(ctx, mouse_event, data);
             }
             _ => {}
         }
@@ -4789,6 +6558,12 @@ impl Widget<LapceTabData> for LapceEditorHeader {
         if self.display {
             let size = Size::new(bc.max().width, self.height);
             self.icons = self.get_icons(size, data);
+            let gap = (self.height - self.icon_size) / 2.0;
+            let x = size.width
+                - ((self.icons.len() + 1) as f64) * (gap + self.icon_size);
+            self.more_rect = Size::new(self.icon_size, self.icon_size)
+                .to_rect()
+                .with_origin(Point::new(x, gap));
             let cross_size = 20.0;
             let padding = (size.height - cross_size) / 2.0;
             let origin = Point::new(size.width - padding - cross_size, padding);
@@ -4812,6 +6587,8 @@ impl Widget<LapceTabData> for LapceEditorHeader {
 pub struct LapceEditorGutter {
     view_id: WidgetId,
     width: f64,
+    /// The line whose diagnostics popup was opened by hovering its marker.
+    hovered_line: Option<usize>,
 }
 
 impl LapceEditorGutter {
@@ -4819,6 +6596,7 @@ impl LapceEditorGutter {
         Self {
             view_id,
             width: 0.0,
+            hovered_line: None,
         }
     }
 }
@@ -4831,6 +6609,52 @@ impl Widget<LapceTabData> for LapceEditorGutter {
         data: &mut LapceTabData,
         env: &Env,
     ) {
+        if let Event::MouseDown(mouse_event) = event {
+            let hunk = data.editor_view_content(self.view_id).hovered_hunk(
+                ctx.text(),
+                mouse_event.pos,
+                self.width,
+            );
+            if let Some(hunk) = hunk {
+                let editor = data.main_split.editors.get_mut(&self.view_id).unwrap();
+                let editor = Arc::make_mut(editor);
+                editor.diff_hunk = if editor.diff_hunk.as_ref() == Some(&hunk) {
+                    None
+                } else {
+                    Some(hunk)
+                };
+                ctx.set_handled();
+            }
+        }
+        if let Event::MouseMove(mouse_event) = event {
+            let (line, on_hunk) = if ctx.is_hot() {
+                let data = data.editor_view_content(self.view_id);
+                (
+                    data.hovered_marker_line(
+                        ctx.text(),
+                        mouse_event.pos,
+                        self.width,
+                    ),
+                    data.hovered_hunk(ctx.text(), mouse_event.pos, self.width)
+                        .is_some(),
+                )
+            } else {
+                (None, false)
+            };
+            if line.is_some() || on_hunk {
+                ctx.set_cursor(&druid::Cursor::Pointer);
+            } else {
+                ctx.clear_cursor();
+            }
+            if line != self.hovered_line {
+                let editor = data.main_split.editors.get_mut(&self.view_id).unwrap();
+                // leaving a marker only closes the popup it opened
+                if line.is_some() || editor.line_diagnostics == self.hovered_line {
+                    Arc::make_mut(editor).line_diagnostics = line;
+                }
+                self.hovered_line = line;
+            }
+        }
     }
 
     fn lifecycle(
@@ -4905,6 +6729,13 @@ pub struct LapceEditor {
     view_id: WidgetId,
     placeholder: Option<String>,
     commands: Vec<(LapceCommandNew, PietTextLayout, Rect, PietTextLayout)>,
+    /// The links in the diagnostics popup, as of the last paint.
+    diagnostic_links: Vec<(Rect, Command)>,
+    /// The timer of the next change of the blinking cursor, only requested
+    /// while the editor is focused.
+    blink_timer: TimerToken,
+    /// When the cursor last moved, which its blink starts from.
+    blink_start: Instant,
 }
 
 impl LapceEditor {
@@ -4913,6 +6744,78 @@ impl LapceEditor {
             view_id,
             placeholder: None,
             commands: vec![],
+            diagnostic_links: Vec::new(),
+            blink_timer: TimerToken::INVALID,
+            blink_start: Instant::now(),
+        }
+    }
+
+    /// How opaque the cursor is at this point of its blink. It stays shown
+    /// for a whole interval after it moved, so it doesn't blink while typing.
+    fn caret_alpha(&self, config: &Config) -> f64 {
+        let interval = match config.editor.cursor_blink_interval() {
+            Some(interval) => interval.as_secs_f64(),
+            None => return 1.0,
+        };
+        let elapsed = self.blink_start.elapsed().as_secs_f64();
+        if elapsed < interval {
+            return 1.0;
+        }
+        if config.editor.cursor_blink == "smooth" && config.ui.animations {
+            let t = (elapsed - interval) / interval;
+            0.5 + 0.5 * (t * std::f64::consts::PI).cos()
+        } else if (elapsed / interval) as u64 % 2 == 0 {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Requests the timer of the next change of the cursor, at the end of
+    /// the current phase so the blink doesn't drift, or for the next frame
+    /// of a fade. Unfocused editors don't request any.
+    fn schedule_blink<F>(&mut self, request_timer: F, data: &LapceTabData)
+    where
+        F: FnOnce(Duration) -> TimerToken,
+    {
+        let config = &data.config;
+        let interval = match config.editor.cursor_blink_interval() {
+            Some(interval) if data.focus == self.view_id => interval,
+            _ => {
+                self.blink_timer = TimerToken::INVALID;
+                return;
+            }
+        };
+        let elapsed = self.blink_start.elapsed();
+        let deadline = if elapsed < interval {
+            interval - elapsed
+        } else if config.editor.cursor_blink == "smooth" && config.ui.animations {
+            CURSOR_FADE_FRAME
+        } else {
+            let interval = interval.as_millis().max(1);
+            Duration::from_millis((interval - elapsed.as_millis() % interval) as u64)
+        };
+        self.blink_timer = request_timer(deadline);
+    }
+
+    /// Repaints the lines of the cursors, rather than the whole editor, for
+    /// each change of the blink.
+    fn request_caret_paint(&self, ctx: &mut EventCtx, data: &LapceTabData) {
+        let editor = match data.main_split.editors.get(&self.view_id) {
+            Some(editor) => editor,
+            None => return,
+        };
+        let buffer = data.main_split.editor_buffer(self.view_id);
+        let line_height =
+            Config::zoomed(&data.config, editor.zoom).editor.line_height as f64;
+        let width = ctx.size().width;
+        for line in editor.cursor.current_lines(&buffer) {
+            ctx.request_paint_rect(Rect::new(
+                0.0,
+                line as f64 * line_height,
+                width,
+                (line + 1) as f64 * line_height,
+            ));
         }
     }
 }
@@ -4925,6 +6828,39 @@ impl Widget<LapceTabData> for LapceEditor {
         data: &mut LapceTabData,
         env: &Env,
     ) {
+        if let Event::Timer(token) = event {
+            if *token == self.blink_timer {
+                self.request_caret_paint(ctx, data);
+                self.schedule_blink(|d| ctx.request_timer(d), data);
+                ctx.set_handled();
+                return;
+            }
+        }
+        if let Event::MouseMove(mouse_event) | Event::MouseDown(mouse_event) = event
+        {
+            let link = self
+                .diagnostic_links
+                .iter()
+                .find(|(rect, _)| rect.contains(mouse_event.pos))
+                .filter(|_| !ctx.is_active());
+            if let Some((_, command)) = link {
+                ctx.set_cursor(&druid::Cursor::Pointer);
+                if let Event::MouseDown(_) = event {
+                    ctx.submit_command(command.clone());
+                    let editor =
+                        data.main_split.editors.get_mut(&self.view_id).unwrap();
+                    if editor.diff_hunk.is_some() {
+                        Arc::make_mut(editor).diff_hunk = None;
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+        }
+        let config = Config::zoomed(
+            &data.config,
+            data.main_split.editors.get(&self.view_id).unwrap().zoom,
+        );
         let buffer = data.main_split.editor_buffer(self.view_id);
         let editor = data.main_split.editors.get_mut(&self.view_id).unwrap();
         match event {
@@ -4935,7 +6871,7 @@ impl Widget<LapceTabData> for LapceEditor {
                         ctx.text(),
                         mouse_event.pos,
                         editor.cursor.get_mode(),
-                        &data.config,
+                        &config,
                         editor.compare.clone(),
                     );
                     let editor = Arc::make_mut(editor);
@@ -4993,10 +6929,11 @@ impl Widget<LapceTabData> for LapceEditor {
                     ctx.text(),
                     mouse_event.pos,
                     editor.cursor.get_mode(),
-                    &data.config,
+                    &config,
                     editor.compare.clone(),
                 );
                 let editor = Arc::make_mut(editor);
+                editor.diff_hunk = None;
                 match editor.cursor.mode.clone() {
                     CursorMode::Normal(offset) => {
                         if mouse_event.mods.shift() {
@@ -5065,6 +7002,32 @@ impl Widget<LapceTabData> for LapceEditor {
                                     target: CommandTarget::Focus,
                                 },
                             },
+                            MenuItem {
+                                text: LapceCommand::PasteAndMatchIndentation
+                                    .get_message()
+                                    .unwrap()
+                                    .to_string(),
+                                command: LapceCommandNew {
+                                    cmd: LapceCommand::PasteAndMatchIndentation
+                                        .to_string(),
+                                    palette_desc: None,
+                                    data: None,
+                                    target: CommandTarget::Focus,
+                                },
+                            },
+                            MenuItem {
+                                text: LapceCommand::PasteWithoutFormatting
+                                    .get_message()
+                                    .unwrap()
+                                    .to_string(),
+                                command: LapceCommandNew {
+                                    cmd: LapceCommand::PasteWithoutFormatting
+                                        .to_string(),
+                                    palette_desc: None,
+                                    data: None,
+                                    target: CommandTarget::Focus,
+                                },
+                            },
                             MenuItem {
                                 text: "Command Palette".to_string(),
                                 command: LapceCommandNew {
@@ -5120,6 +7083,21 @@ impl Widget<LapceTabData> for LapceEditor {
         data: &LapceTabData,
         env: &Env,
     ) {
+        let focused = data.focus == self.view_id;
+        let moved = match (
+            old_data.main_split.editors.get(&self.view_id),
+            data.main_split.editors.get(&self.view_id),
+        ) {
+            (Some(old_editor), Some(editor)) => old_editor.cursor != editor.cursor,
+            _ => false,
+        };
+        if moved || (focused && old_data.focus != self.view_id) {
+            self.blink_start = Instant::now();
+        }
+        if focused && self.blink_timer == TimerToken::INVALID {
+            self.schedule_blink(|d| ctx.request_timer(d), data);
+        }
+
         // let buffer = &data.buffer;
         // let old_buffer = &old_data.buffer;
 
@@ -5188,8 +7166,15 @@ impl Widget<LapceTabData> for LapceEditor {
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
         let is_focused = data.focus == self.view_id;
+        let caret_alpha = self.caret_alpha(&data.config);
         let data = data.editor_view_content(self.view_id);
-        data.paint_content(ctx, is_focused, self.placeholder.as_ref(), &data.config);
+        self.diagnostic_links = data.paint_content(
+            ctx,
+            is_focused,
+            caret_alpha,
+            self.placeholder.as_ref(),
+            &data.config,
+        );
     }
 }
 
@@ -5328,6 +7313,52 @@ fn str_matching_pair(c: &str) -> Option<char> {
     None
 }
 
+/// Strips the leading whitespace the lines of `content` have in common, and
+/// indents every line but the first one with `indent`. A first line without
+/// indentation was usually copied from the middle of a line, so it doesn't
+/// count towards the common whitespace.
+fn reindent(content: &str, indent: &str) -> String {
+    let common = content
+        .lines()
+        .enumerate()
+        .filter(|(i, l)| {
+            l.trim() != "" && (*i > 0 || l.starts_with(char::is_whitespace))
+        })
+        .map(|(_, l)| &l[..l.len() - l.trim_start().len()])
+        .fold(None, |common: Option<&str>, ws| match common {
+            None => Some(ws),
+            Some(common) => {
+                let len = common
+                    .chars()
+                    .zip(ws.chars())
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a.len_utf8())
+                    .sum::<usize>();
+                Some(&common[..len])
+            }
+        })
+        .unwrap_or("");
+
+    content
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            if line.trim() == "" {
+                return line
+                    .trim_start_matches(|c| c == ' ' || c == '\t')
+                    .to_string();
+            }
+            let line = line.strip_prefix(common).unwrap_or(line);
+            if i == 0 {
+                line.to_string()
+            } else {
+                format!("{}{}", indent, line)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 fn process_get_references(
     editor_view_id: WidgetId,
     offset: usize,
@@ -5351,6 +7382,7 @@ fn process_get_references(
                     position: Some(location.range.start.clone()),
                     scroll_offset: None,
                     hisotry: None,
+                    selection_end: None,
                 },
             ),
             Target::Auto,
@@ -5386,3 +7418,44 @@ fn paint_wave_line(
     }
     ctx.stroke(path, color, 1.4);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reindent_spaces() {
+        assert_eq!(
+            reindent("    if a {\n        b();\n    }", "\t"),
+            "if a {\n\t    b();\n\t}"
+        );
+    }
+
+    #[test]
+    fn test_reindent_tabs() {
+        assert_eq!(
+            reindent("\t\tfoo\n\t\tbar\n\t\t\tbaz", "    "),
+            "foo\n    bar\n    \tbaz"
+        );
+        // tabs and spaces have nothing in common
+        assert_eq!(reindent("\tfoo\n    bar", ""), "\tfoo\n    bar");
+    }
+
+    #[test]
+    fn test_reindent_first_line() {
+        // copied from the middle of a line
+        assert_eq!(
+            reindent("foo()\n        .bar()\n        .baz()", "    "),
+            "foo()\n    .bar()\n    .baz()"
+        );
+        assert_eq!(reindent("\n    a\n    b", "  "), "\n  a\n  b");
+    }
+
+    #[test]
+    fn test_reindent_below_zero() {
+        // lines less indented than the first one aren't cut into
+        assert_eq!(reindent("        x\n    y", "  "), "    x\n  y");
+        assert_eq!(reindent("a\n   \n  b", "\t"), "a\n\n\tb");
+        assert_eq!(reindent("", "\t"), "");
+    }
+}
@@ -0,0 +1,1003 @@
+use crate::completion::{Snippet, SnippetElement};
+
+/// What an abbreviation expands to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    Html,
+    Css,
+}
+
+const HTML_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "address",
+    "area",
+    "article",
+    "aside",
+    "audio",
+    "b",
+    "base",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "canvas",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "data",
+    "datalist",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "i",
+    "iframe",
+    "img",
+    "input",
+    "ins",
+    "kbd",
+    "label",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "map",
+    "mark",
+    "menu",
+    "meta",
+    "meter",
+    "nav",
+    "noscript",
+    "object",
+    "ol",
+    "optgroup",
+    "option",
+    "output",
+    "p",
+    "param",
+    "picture",
+    "pre",
+    "progress",
+    "q",
+    "s",
+    "samp",
+    "script",
+    "section",
+    "select",
+    "slot",
+    "small",
+    "source",
+    "span",
+    "strong",
+    "style",
+    "sub",
+    "summary",
+    "sup",
+    "svg",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "title",
+    "tr",
+    "track",
+    "u",
+    "ul",
+    "var",
+    "video",
+    "wbr",
+];
+
+/// The elements without a closing tag.
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta",
+    "param", "source", "track", "wbr",
+];
+
+/// The elements kept on the line of their siblings.
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "button", "cite", "code", "data", "dfn",
+    "em", "i", "img", "input", "kbd", "label", "mark", "q", "s", "samp", "select",
+    "small", "span", "strong", "sub", "sup", "textarea", "time", "u", "var",
+];
+
+/// The attributes an element gets without being asked for, empty ones being
+/// tabstops.
+const DEFAULT_ATTRIBUTES: &[(&str, &[(&str, &str)])] = &[
+    ("a", &[("href", "")]),
+    ("form", &[("action", "")]),
+    ("iframe", &[("src", "")]),
+    ("img", &[("src", ""), ("alt", "")]),
+    ("input", &[("type", "text")]),
+    ("label", &[("for", "")]),
+    ("link", &[("rel", "stylesheet"), ("href", "")]),
+    ("source", &[("src", "")]),
+];
+
+const CSS_PROPERTIES: &[(&str, &str)] = &[
+    ("ai", "align-items"),
+    ("as", "align-self"),
+    ("b", "bottom"),
+    ("bd", "border"),
+    ("bdb", "border-bottom"),
+    ("bdc", "border-color"),
+    ("bdl", "border-left"),
+    ("bdr", "border-right"),
+    ("bdrs", "border-radius"),
+    ("bdt", "border-top"),
+    ("bg", "background"),
+    ("bgc", "background-color"),
+    ("bgi", "background-image"),
+    ("br", "border-radius"),
+    ("bxsh", "box-shadow"),
+    ("bxz", "box-sizing"),
+    ("c", "color"),
+    ("cnt", "content"),
+    ("cur", "cursor"),
+    ("d", "display"),
+    ("fl", "float"),
+    ("ff", "font-family"),
+    ("fs", "font-style"),
+    ("fw", "font-weight"),
+    ("fx", "flex"),
+    ("fxd", "flex-direction"),
+    ("fxg", "flex-grow"),
+    ("fxs", "flex-shrink"),
+    ("fxw", "flex-wrap"),
+    ("fz", "font-size"),
+    ("g", "gap"),
+    ("gtc", "grid-template-columns"),
+    ("gtr", "grid-template-rows"),
+    ("h", "height"),
+    ("jc", "justify-content"),
+    ("l", "left"),
+    ("lh", "line-height"),
+    ("m", "margin"),
+    ("mb", "margin-bottom"),
+    ("mah", "max-height"),
+    ("maw", "max-width"),
+    ("mih", "min-height"),
+    ("miw", "min-width"),
+    ("ml", "margin-left"),
+    ("mr", "margin-right"),
+    ("mt", "margin-top"),
+    ("op", "opacity"),
+    ("ord", "order"),
+    ("ov", "overflow"),
+    ("p", "padding"),
+    ("pb", "padding-bottom"),
+    ("pl", "padding-left"),
+    ("pos", "position"),
+    ("pr", "padding-right"),
+    ("pt", "padding-top"),
+    ("r", "right"),
+    ("t", "top"),
+    ("ta", "text-align"),
+    ("td", "text-decoration"),
+    ("tt", "text-transform"),
+    ("trf", "transform"),
+    ("trs", "transition"),
+    ("v", "visibility"),
+    ("va", "vertical-align"),
+    ("w", "width"),
+    ("whs", "white-space"),
+    ("z", "z-index"),
+];
+
+/// The abbreviations of a property with its value.
+const CSS_KEYWORDS: &[(&str, &str)] = &[
+    ("aic", "align-items: center"),
+    ("bdn", "border: none"),
+    ("bxzbb", "box-sizing: border-box"),
+    ("curp", "cursor: pointer"),
+    ("db", "display: block"),
+    ("df", "display: flex"),
+    ("dg", "display: grid"),
+    ("di", "display: inline"),
+    ("dib", "display: inline-block"),
+    ("dn", "display: none"),
+    ("fll", "float: left"),
+    ("flr", "float: right"),
+    ("fsi", "font-style: italic"),
+    ("fwb", "font-weight: bold"),
+    ("fwn", "font-weight: normal"),
+    ("fxdc", "flex-direction: column"),
+    ("fxdr", "flex-direction: row"),
+    ("fxww", "flex-wrap: wrap"),
+    ("jcc", "justify-content: center"),
+    ("jcsb", "justify-content: space-between"),
+    ("ovh", "overflow: hidden"),
+    ("ova", "overflow: auto"),
+    ("posa", "position: absolute"),
+    ("posf", "position: fixed"),
+    ("posr", "position: relative"),
+    ("poss", "position: sticky"),
+    ("tac", "text-align: center"),
+    ("tal", "text-align: left"),
+    ("tar", "text-align: right"),
+    ("tdn", "text-decoration: none"),
+    ("tdu", "text-decoration: underline"),
+    ("ttu", "text-transform: uppercase"),
+    ("vh", "visibility: hidden"),
+];
+
+/// The properties whose numbers don't get a unit.
+const UNITLESS_PROPERTIES: &[&str] = &[
+    "flex",
+    "flex-grow",
+    "flex-shrink",
+    "font-weight",
+    "line-height",
+    "opacity",
+    "order",
+    "z-index",
+];
+
+/// Where the abbreviation ending the text before the cursor starts. The
+/// spaces in `{}` and `[]` are part of it.
+pub fn extract_abbreviation(line: &str, syntax: Syntax) -> Option<usize> {
+    let start = match syntax {
+        Syntax::Html => {
+            let mut depth = 0usize;
+            let mut start = 0;
+            for (i, c) in line.char_indices().rev() {
+                match c {
+                    '}' | ']' | ')' => depth += 1,
+                    '{' | '[' => depth = depth.saturating_sub(1),
+                    '(' if depth > 0 => depth -= 1,
+                    _ if depth > 0 => {}
+                    '<' => {
+                        // the abbreviation is after the tag the line has
+                        start = line[i..].find('>').map(|e| i + e + 1)?;
+                        break;
+                    }
+                    c if c.is_whitespace() || c == '"' || c == '\'' => {
+                        start = i + c.len_utf8();
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            start
+        }
+        Syntax::Css => line
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace() || ";{}:".contains(*c))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0),
+    };
+    if start < line.len() {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+/// Expands the abbreviation to a snippet, with tabstops where there's
+/// something to fill in and at the end. The lines after the first start
+/// with `line_indent`, and nest by `indent`. If `strict`, only the
+/// abbreviations that can't be regular text are expanded, for Tab to still
+/// indent.
+pub fn expand(
+    abbreviation: &str,
+    syntax: Syntax,
+    indent: &str,
+    line_indent: &str,
+    strict: bool,
+) -> Option<Snippet> {
+    let mut output = Output::default();
+    match syntax {
+        Syntax::Html => {
+            if abbreviation == "!" {
+                return Some(boilerplate(indent, line_indent));
+            }
+            let mut parser = Parser {
+                chars: abbreviation.chars().collect(),
+                pos: 0,
+            };
+            let nodes = parser.parse_list(0)?;
+            if parser.pos < parser.chars.len() {
+                return None;
+            }
+            if strict && !nodes.iter().all(|node| node.is_known()) {
+                return None;
+            }
+            let elements = resolve(&nodes, None, (0, 1));
+            output.elements(&elements, indent, line_indent, 0);
+        }
+        Syntax::Css => {
+            let (property, value) = expand_css(abbreviation)?;
+            output.text(&property);
+            output.text(": ");
+            match value {
+                Some(value) => output.text(&value),
+                None => output.tabstop(),
+            }
+            output.text(";");
+        }
+    }
+    output.elements.push(SnippetElement::Tabstop(0));
+    Some(Snippet::new(output.elements))
+}
+
+fn boilerplate(indent: &str, line_indent: &str) -> Snippet {
+    let nl = |depth: usize| format!("\n{}{}", line_indent, indent.repeat(depth));
+    let mut output = Output::default();
+    output.text(&format!(
+        "<!DOCTYPE html>{}<html lang=\"en\">{}<head>{}<meta charset=\"UTF-8\">{}\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\
+         {}<title>",
+        nl(0),
+        nl(0),
+        nl(1),
+        nl(1),
+        nl(1)
+    ));
+    output.placeholder("Document");
+    output.text(&format!("</title>{}</head>{}<body>{}", nl(0), nl(0), nl(1)));
+    output.tabstop();
+    output.text(&format!("{}</body>{}</html>", nl(0), nl(0)));
+    output.elements.push(SnippetElement::Tabstop(0));
+    Snippet::new(output.elements)
+}
+
+/// An element of the abbreviation, or a group of them in parentheses.
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, String)>,
+    text: Option<String>,
+    repeat: Option<usize>,
+    group: bool,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Whether all the names are of HTML elements, or of custom ones with a
+    /// `-`, or numbered.
+    fn is_known(&self) -> bool {
+        let name = self.name.as_str();
+        (name.is_empty()
+            || HTML_TAGS.contains(&name)
+            || name.contains('-')
+            || name.contains('$'))
+            && self.children.iter().all(|child| child.is_known())
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// The siblings up to the end, a `)`, or a `^` climbing out of the
+    /// list, with the children they were given by `>`.
+    fn parse_list(&mut self, depth: usize) -> Option<Vec<Node>> {
+        self.parse_list_climbing(depth).map(|(nodes, _)| nodes)
+    }
+
+    /// Like `parse_list`, also returning how many levels a `^` still has to
+    /// climb from the caller.
+    fn parse_list_climbing(&mut self, depth: usize) -> Option<(Vec<Node>, usize)> {
+        let mut nodes = vec![self.parse_item()?];
+        loop {
+            match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    nodes.push(self.parse_item()?);
+                }
+                Some('>') => {
+                    self.pos += 1;
+                    let (children, climb) = self.parse_list_climbing(depth + 1)?;
+                    nodes.last_mut()?.children.extend(children);
+                    if climb > 1 && depth > 0 {
+                        return Some((nodes, climb - 1));
+                    }
+                    if climb > 0 {
+                        nodes.push(self.parse_item()?);
+                    }
+                }
+                Some('^') => {
+                    let mut climb = 0;
+                    while self.peek() == Some('^') {
+                        self.pos += 1;
+                        climb += 1;
+                    }
+                    if depth == 0 {
+                        nodes.push(self.parse_item()?);
+                    } else {
+                        return Some((nodes, climb));
+                    }
+                }
+                _ => return Some((nodes, 0)),
+            }
+        }
+    }
+
+    fn parse_item(&mut self) -> Option<Node> {
+        let mut node = if self.peek() == Some('(') {
+            self.pos += 1;
+            let children = self.parse_list(0)?;
+            if self.peek() != Some(')') {
+                return None;
+            }
+            self.pos += 1;
+            Node {
+                group: true,
+                children,
+                ..Default::default()
+            }
+        } else {
+            let name = self.parse_name();
+            let mut node = Node {
+                name,
+                ..Default::default()
+            };
+            loop {
+                match self.peek() {
+                    Some('#') => {
+                        self.pos += 1;
+                        let id = self.parse_name();
+                        if id.is_empty() {
+                            return None;
+                        }
+                        node.id = Some(id);
+                    }
+                    Some('.') => {
+                        self.pos += 1;
+                        let class = self.parse_name();
+                        if class.is_empty() {
+                            return None;
+                        }
+                        node.classes.push(class);
+                    }
+                    Some('[') => {
+                        self.pos += 1;
+                        node.attributes.extend(self.parse_attributes()?);
+                    }
+                    Some('{') => {
+                        self.pos += 1;
+                        node.text = Some(self.parse_text()?);
+                    }
+                    _ => break,
+                }
+            }
+            if node.name.is_empty()
+                && node.id.is_none()
+                && node.classes.is_empty()
+                && node.attributes.is_empty()
+                && node.text.is_none()
+            {
+                return None;
+            }
+            node
+        };
+        if self.peek() == Some('*') {
+            self.pos += 1;
+            let start = self.pos;
+            while self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+                self.pos += 1;
+            }
+            let repeat: String = self.chars[start..self.pos].iter().collect();
+            node.repeat = Some(repeat.parse().ok().filter(|n| *n > 0)?);
+        }
+        Some(node)
+    }
+
+    fn parse_name(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .map(|c| c.is_alphanumeric() || "-_:$@!".contains(c))
+            .unwrap_or(false)
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// The attributes up to the `]`, like `href title="a b" disabled`.
+    fn parse_attributes(&mut self) -> Option<Vec<(String, String)>> {
+        let mut attributes = Vec::new();
+        loop {
+            while self.peek()?.is_whitespace() {
+                self.pos += 1;
+            }
+            if self.peek()? == ']' {
+                self.pos += 1;
+                return Some(attributes);
+            }
+            let start = self.pos;
+            while !matches!(self.peek()?, '=' | ']') && !self.peek()?.is_whitespace()
+            {
+                self.pos += 1;
+            }
+            let name: String = self.chars[start..self.pos].iter().collect();
+            let mut value = String::new();
+            if self.peek()? == '=' {
+                self.pos += 1;
+                match self.peek()? {
+                    quote @ ('"' | '\'') => {
+                        self.pos += 1;
+                        while self.peek()? != quote {
+                            value.push(self.peek()?);
+                            self.pos += 1;
+                        }
+                        self.pos += 1;
+                    }
+                    _ => {
+                        while self.peek()? != ']' && !self.peek()?.is_whitespace() {
+                            value.push(self.peek()?);
+                            self.pos += 1;
+                        }
+                    }
+                }
+            }
+            attributes.push((name, value));
+        }
+    }
+
+    /// The text up to the `}` closing the one before it.
+    fn parse_text(&mut self) -> Option<String> {
+        let mut depth = 0;
+        let mut text = String::new();
+        loop {
+            let c = self.peek()?;
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(text),
+                '}' => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+        }
+    }
+}
+
+/// An element to write out, with its repeats and numbers resolved. Without
+/// a tag, it's only its text.
+#[derive(Debug)]
+struct Element {
+    tag: Option<String>,
+    attributes: Vec<(String, String)>,
+    text: Option<String>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn is_inline(&self) -> bool {
+        self.tag
+            .as_ref()
+            .map(|tag| INLINE_TAGS.contains(&tag.as_str()))
+            .unwrap_or(true)
+    }
+}
+
+/// Repeats the nodes, numbering them, and fills in the tags left implicit
+/// from their parent. `number` is the index of the repeat the nodes are in,
+/// out of how many.
+fn resolve(
+    nodes: &[Node],
+    parent: Option<&str>,
+    number: (usize, usize),
+) -> Vec<Element> {
+    let mut elements = Vec::new();
+    for node in nodes {
+        let repeat = node.repeat.unwrap_or(1);
+        for i in 0..repeat {
+            let number = if node.repeat.is_some() {
+                (i, repeat)
+            } else {
+                number
+            };
+            if node.group {
+                elements.extend(resolve(&node.children, parent, number));
+                continue;
+            }
+
+            let tag = if !node.name.is_empty() {
+                Some(numbered(&node.name, number))
+            } else if node.id.is_none()
+                && node.classes.is_empty()
+                && node.attributes.is_empty()
+            {
+                None
+            } else {
+                Some(implicit_tag(parent).to_string())
+            };
+            let mut attributes: Vec<(String, String)> = tag
+                .as_ref()
+                .and_then(|tag| {
+                    DEFAULT_ATTRIBUTES.iter().find(|(t, _)| t == tag).map(
+                        |(_, attributes)| {
+                            attributes
+                                .iter()
+                                .map(|(n, v)| (n.to_string(), v.to_string()))
+                                .collect()
+                        },
+                    )
+                })
+                .unwrap_or_default();
+            let mut set = |name: &str, value: String| match attributes
+                .iter_mut()
+                .find(|(n, _)| n == name)
+            {
+                Some((_, v)) => *v = value,
+                None => attributes.push((name.to_string(), value)),
+            };
+            if let Some(id) = node.id.as_ref() {
+                set("id", numbered(id, number));
+            }
+            if !node.classes.is_empty() {
+                let classes: Vec<String> = node
+                    .classes
+                    .iter()
+                    .map(|class| numbered(class, number))
+                    .collect();
+                set("class", classes.join(" "));
+            }
+            for (name, value) in node.attributes.iter() {
+                set(&numbered(name, number), numbered(value, number));
+            }
+
+            elements.push(Element {
+                children: resolve(&node.children, tag.as_deref(), number),
+                tag,
+                attributes,
+                text: node.text.as_ref().map(|text| numbered(text, number)),
+            });
+        }
+    }
+    elements
+}
+
+/// The tag of an element given only a class or an id.
+fn implicit_tag(parent: Option<&str>) -> &'static str {
+    match parent {
+        Some("ul") | Some("ol") | Some("menu") => "li",
+        Some("table") | Some("thead") | Some("tbody") | Some("tfoot") => "tr",
+        Some("tr") => "td",
+        Some("select") | Some("optgroup") => "option",
+        Some(parent) if INLINE_TAGS.contains(&parent) => "span",
+        _ => "div",
+    }
+}
+
+/// Replaces the runs of `$` with the number, padded with zeros to their
+/// length. `$@-` counts down and `$@3` starts at 3.
+fn numbered(s: &str, (index, total): (usize, usize)) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '$' {
+            result.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let mut width = 0;
+        while i < chars.len() && chars[i] == '$' {
+            width += 1;
+            i += 1;
+        }
+        let mut reverse = false;
+        let mut start = 1;
+        if i < chars.len() && chars[i] == '@' {
+            i += 1;
+            if i < chars.len() && chars[i] == '-' {
+                reverse = true;
+                i += 1;
+            }
+            let digits_start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[digits_start..i].iter().collect();
+            start = digits.parse().unwrap_or(1);
+        }
+        let n = if reverse {
+            start + total - 1 - index
+        } else {
+            start + index
+        };
+        result.push_str(&format!("{:0width$}", n, width = width));
+    }
+    result
+}
+
+#[derive(Default)]
+struct Output {
+    elements: Vec<SnippetElement>,
+    tabs: usize,
+}
+
+impl Output {
+    fn text(&mut self, text: &str) {
+        if let Some(SnippetElement::Text(last)) = self.elements.last_mut() {
+            last.push_str(text);
+        } else if !text.is_empty() {
+            self.elements.push(SnippetElement::Text(text.to_string()));
+        }
+    }
+
+    fn tabstop(&mut self) {
+        self.tabs += 1;
+        self.elements.push(SnippetElement::Tabstop(self.tabs));
+    }
+
+    fn placeholder(&mut self, text: &str) {
+        self.tabs += 1;
+        self.elements.push(SnippetElement::PlaceHolder(
+            self.tabs,
+            vec![SnippetElement::Text(text.to_string())],
+        ));
+    }
+
+    fn newline(&mut self, indent: &str, line_indent: &str, depth: usize) {
+        self.text(&format!("\n{}{}", line_indent, indent.repeat(depth)));
+    }
+
+    /// The siblings, on lines of their own unless they're all inline.
+    fn elements(
+        &mut self,
+        elements: &[Element],
+        indent: &str,
+        line_indent: &str,
+        depth: usize,
+    ) {
+        let block = elements.iter().any(|e| !e.is_inline());
+        for (i, element) in elements.iter().enumerate() {
+            if block && i > 0 {
+                self.newline(indent, line_indent, depth);
+            }
+            self.element(element, indent, line_indent, depth);
+        }
+    }
+
+    fn element(
+        &mut self,
+        element: &Element,
+        indent: &str,
+        line_indent: &str,
+        depth: usize,
+    ) {
+        let tag = match element.tag.as_ref() {
+            Some(tag) => tag,
+            None => {
+                self.text(element.text.as_deref().unwrap_or(""));
+                return;
+            }
+        };
+        self.text(&format!("<{}", tag));
+        for (name, value) in element.attributes.iter() {
+            self.text(&format!(" {}=\"", name));
+            if value.is_empty() {
+                self.tabstop();
+            } else {
+                self.text(value);
+            }
+            self.text("\"");
+        }
+        self.text(">");
+        if VOID_TAGS.contains(&tag.as_str()) {
+            return;
+        }
+
+        if let Some(text) = element.text.as_ref() {
+            self.text(text);
+        }
+        if element.children.iter().any(|e| !e.is_inline()) {
+            for child in element.children.iter() {
+                self.newline(indent, line_indent, depth + 1);
+                self.element(child, indent, line_indent, depth + 1);
+            }
+            self.newline(indent, line_indent, depth);
+        } else if !element.children.is_empty() {
+            self.elements(&element.children, indent, line_indent, depth + 1);
+        } else if element.text.is_none() {
+            self.tabstop();
+        }
+        self.text(&format!("</{}>", tag));
+    }
+}
+
+/// The property and the value of a CSS abbreviation like `m10-auto` or
+/// `bgc#f`, without a value if it's to be filled in.
+fn expand_css(abbreviation: &str) -> Option<(String, Option<String>)> {
+    let (abbreviation, important) = match abbreviation.strip_suffix('!') {
+        Some(abbreviation) => (abbreviation, " !important"),
+        None => (abbreviation, ""),
+    };
+    if let Some((_, keyword)) = CSS_KEYWORDS.iter().find(|(a, _)| *a == abbreviation)
+    {
+        let (property, value) = keyword.split_once(": ")?;
+        return Some((
+            property.to_string(),
+            Some(format!("{}{}", value, important)),
+        ));
+    }
+
+    let split = abbreviation
+        .find(|c: char| !c.is_ascii_lowercase())
+        .unwrap_or(abbreviation.len());
+    let (name, value) = abbreviation.split_at(split);
+    let property = CSS_PROPERTIES.iter().find(|(a, _)| *a == name)?.1;
+    if value.is_empty() {
+        return Some((property.to_string(), None));
+    }
+    let values = css_values(value, UNITLESS_PROPERTIES.contains(&property))?;
+    Some((
+        property.to_string(),
+        Some(format!("{}{}", values.join(" "), important)),
+    ))
+}
+
+/// The values separated by `-`, a `-` right after another one being a
+/// minus: `10-auto` or `0--5e`.
+fn css_values(s: &str, unitless: bool) -> Option<Vec<String>> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !values.is_empty() {
+            if chars[i] != '-' {
+                return None;
+            }
+            i += 1;
+        }
+        let start = i;
+        if chars.get(i) == Some(&'#') {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_hexdigit() {
+                i += 1;
+            }
+            let hex: String = chars[start + 1..i].iter().collect::<String>();
+            let hex = hex.to_lowercase();
+            values.push(match hex.len() {
+                1 => format!("#{}", hex.repeat(3)),
+                2 => format!("#{}", hex.repeat(3)),
+                3 | 6 => format!("#{}", hex),
+                _ => return None,
+            });
+            continue;
+        }
+        if chars.get(i) == Some(&'-') {
+            i += 1;
+        }
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+            i += 1;
+        }
+        let number: String = chars[start..i].iter().collect();
+        let unit_start = i;
+        while i < chars.len() && (chars[i].is_ascii_alphabetic() || chars[i] == '%')
+        {
+            i += 1;
+        }
+        let unit: String = chars[unit_start..i].iter().collect();
+        if number.parse::<f64>().is_err() {
+            // a keyword like `auto`
+            if number.is_empty() && !unit.is_empty() {
+                values.push(unit);
+                continue;
+            }
+            return None;
+        }
+        let unit = match unit.as_str() {
+            "" if unitless || number.parse::<f64>().ok()? == 0.0 => "",
+            "" => "px",
+            "p" => "%",
+            "e" => "em",
+            "r" => "rem",
+            "x" => "ex",
+            unit => unit,
+        };
+        values.push(format!("{}{}", number, unit));
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_html(abbreviation: &str) -> String {
+        expand(abbreviation, Syntax::Html, "  ", "", false)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_expand_html() {
+        assert_eq!(
+            expand_html("ul>li.item$*3"),
+            "<ul>\n  <li class=\"item1\">$1</li>\n  <li class=\"item2\">$2</li>\n  \
+             <li class=\"item3\">$3</li>\n</ul>$0"
+        );
+        assert_eq!(
+            expand_html("div#main>p{hi}+a[title=\"x y\"]"),
+            "<div id=\"main\">\n  <p>hi</p>\n  <a href=\"$1\" title=\"x y\">$2</a>\n</div>$0"
+        );
+        assert_eq!(
+            expand_html("div>p>span^h2"),
+            "<div>\n  <p><span>$1</span></p>\n  <h2>$2</h2>\n</div>$0"
+        );
+        assert_eq!(
+            expand_html("(dt+dd)*2"),
+            "<dt>$1</dt>\n<dd>$2</dd>\n<dt>$3</dt>\n<dd>$4</dd>$0"
+        );
+        assert_eq!(expand_html("img"), "<img src=\"$1\" alt=\"$2\">$0");
+        assert_eq!(expand_html("h$$@-*2"), "<h02>$1</h02>\n<h01>$2</h01>$0");
+        assert!(expand("div>", Syntax::Html, "  ", "", false).is_none());
+        assert!(expand("hello", Syntax::Html, "  ", "", true).is_none());
+        assert!(expand("hello", Syntax::Html, "  ", "", false).is_some());
+    }
+
+    #[test]
+    fn test_expand_css() {
+        let css =
+            |a: &str| expand(a, Syntax::Css, "", "", true).map(|s| s.to_string());
+        assert_eq!(css("m10-auto").as_deref(), Some("margin: 10px auto;$0"));
+        assert_eq!(css("w100p").as_deref(), Some("width: 100%;$0"));
+        assert_eq!(css("m0--5e").as_deref(), Some("margin: 0 -5em;$0"));
+        assert_eq!(css("lh1.5").as_deref(), Some("line-height: 1.5;$0"));
+        assert_eq!(css("bgc#f").as_deref(), Some("background-color: #fff;$0"));
+        assert_eq!(css("dn!").as_deref(), Some("display: none !important;$0"));
+        assert_eq!(css("pos").as_deref(), Some("position: $1;$0"));
+        assert_eq!(css("xyz"), None);
+    }
+
+    #[test]
+    fn test_extract_abbreviation() {
+        fn extract(line: &str, syntax: Syntax) -> Option<&str> {
+            extract_abbreviation(line, syntax).map(|start| &line[start..])
+        }
+        let html = |line| extract(line, Syntax::Html);
+        assert_eq!(html("  ul>li*2"), Some("ul>li*2"));
+        assert_eq!(html("<p>a{some text}"), Some("a{some text}"));
+        assert_eq!(html("x a[title='a b']"), Some("a[title='a b']"));
+        assert_eq!(html("text "), None);
+        let css = |line| extract(line, Syntax::Css);
+        assert_eq!(css("  a { m10"), Some("m10"));
+        assert_eq!(css("color:"), None);
+    }
+}
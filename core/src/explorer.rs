@@ -6,6 +6,7 @@ use std::{str::FromStr, sync::Arc};
 
 use druid::ExtEventSink;
 use druid::{
+    kurbo::Line,
     piet::{Text, TextLayout as PietTextLayout, TextLayoutBuilder},
     theme,
     widget::{CrossAxisAlignment, Flex, FlexParams, Label, Scroll, SvgData},
@@ -18,9 +19,13 @@ use druid::{
 use include_dir::{include_dir, Dir};
 use lapce_proxy::dispatch::FileNodeItem;
 use parking_lot::Mutex;
+use serde_json::json;
+use strum::EnumMessage;
 
+use crate::command::{CommandTarget, LapceCommandNew, LapceWorkbenchCommand};
 use crate::config::{Config, LapceTheme};
 use crate::data::{LapceData, LapceTabData};
+use crate::menu::MenuItem;
 use crate::proxy::LapceProxy;
 use crate::scroll::LapceScrollNew;
 use crate::state::{LapceWorkspace, LapceWorkspaceType};
@@ -54,6 +59,10 @@ pub struct FileExplorerData {
     pub workspace: Option<FileNodeItem>,
     index: usize,
     count: usize,
+    /// The items hidden by `files.exclude`, by the folder they're in.
+    excluded: HashMap<PathBuf, Vec<FileNodeItem>>,
+    /// Whether the excluded items are shown in the tree for now.
+    pub show_excluded: bool,
 }
 
 impl FileExplorerData {
@@ -73,6 +82,7 @@ impl FileExplorerData {
                 open: false,
                 children: HashMap::new(),
                 children_open_count: 0,
+                excluded: false,
             });
             let index = 0;
             let path = path.clone();
@@ -109,9 +119,154 @@ impl FileExplorerData {
                 open: false,
                 children: HashMap::new(),
                 children_open_count: 0,
+                excluded: false,
             }),
             index: 0,
             count: 0,
+            excluded: HashMap::new(),
+            show_excluded: false,
+        }
+    }
+
+    /// Sets the items read in the folder at `path`. The folders that were
+    /// already read under it are kept as they were, and the excluded items
+    /// are put aside unless they're shown.
+    pub fn update_children(&mut self, path: &PathBuf, items: Vec<FileNodeItem>) {
+        let (excluded, items): (Vec<FileNodeItem>, Vec<FileNodeItem>) =
+            items.into_iter().partition(|item| item.excluded);
+        let show_excluded = self.show_excluded;
+        if let Some(node) = self.get_node_mut(path) {
+            let mut old_children = std::mem::take(&mut node.children);
+            let shown = if show_excluded { &excluded[..] } else { &[] };
+            node.children = items
+                .iter()
+                .chain(shown.iter())
+                .map(|item| {
+                    let child = match old_children.remove(&item.path_buf) {
+                        Some(mut old) if old.is_dir == item.is_dir => {
+                            old.excluded = item.excluded;
+                            old
+                        }
+                        _ => item.clone(),
+                    };
+                    (item.path_buf.clone(), child)
+                })
+                .collect();
+            // a folder read again, like on a refresh, stays as it was
+            if !node.read {
+                node.open = true;
+            }
+            node.read = true;
+        }
+        if excluded.is_empty() {
+            self.excluded.remove(path);
+        } else {
+            self.excluded.insert(path.clone(), excluded);
+        }
+        if let Some(paths) = self.node_tree(path) {
+            for path in paths.iter() {
+                self.update_node_count(path);
+            }
+        }
+    }
+
+    /// How many excluded items are hidden in the folders of the tree.
+    pub fn excluded_count(&self) -> usize {
+        self.excluded
+            .iter()
+            .filter(|(dir, _)| self.get_node(dir).is_some())
+            .map(|(_, items)| items.len())
+            .sum()
+    }
+
+    /// Shows the excluded items in the tree, or hides them again.
+    pub fn toggle_excluded(&mut self) {
+        self.show_excluded = !self.show_excluded;
+        let mut dirs: Vec<PathBuf> = self.excluded.keys().cloned().collect();
+        // the folders are shown before what's in them
+        dirs.sort_by_key(|dir| dir.components().count());
+        for dir in dirs {
+            let items = self.excluded.get(&dir).cloned().unwrap_or_default();
+            let show_excluded = self.show_excluded;
+            let node = match self.get_node_mut(&dir) {
+                Some(node) => node,
+                None => continue,
+            };
+            for item in items {
+                if show_excluded {
+                    node.children.insert(item.path_buf.clone(), item);
+                } else {
+                    node.children.remove(&item.path_buf);
+                }
+            }
+            if let Some(paths) = self.node_tree(&dir) {
+                for path in paths.iter() {
+                    self.update_node_count(path);
+                }
+            }
+        }
+    }
+
+    /// Reads the folders of the tree again, like after the exclude patterns
+    /// changed.
+    pub fn refresh(
+        &self,
+        tab_id: WidgetId,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
+    ) {
+        let mut dirs = Vec::new();
+        if let Some(workspace) = self.workspace.as_ref() {
+            read_dirs(workspace, &mut dirs);
+        }
+        self.read_dirs_again(dirs, tab_id, proxy, event_sink);
+    }
+
+    /// Reads the folders again, like after files were created or removed in
+    /// them, if they're read in the tree.
+    pub fn refresh_dirs(
+        &self,
+        dirs: Vec<PathBuf>,
+        tab_id: WidgetId,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
+    ) {
+        let dirs = dirs
+            .into_iter()
+            .filter(|dir| {
+                self.get_node(dir)
+                    .map(|node| node.is_dir && node.read)
+                    .unwrap_or(false)
+            })
+            .collect();
+        self.read_dirs_again(dirs, tab_id, proxy, event_sink);
+    }
+
+    fn read_dirs_again(
+        &self,
+        dirs: Vec<PathBuf>,
+        tab_id: WidgetId,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
+    ) {
+        for dir in dirs {
+            let event_sink = event_sink.clone();
+            proxy.read_dir(
+                &dir.clone(),
+                Box::new(move |result| {
+                    if let Ok(res) = result {
+                        let resp: Result<Vec<FileNodeItem>, serde_json::Error> =
+                            serde_json::from_value(res);
+                        if let Ok(items) = resp {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::UpdateExplorerItems(0, dir, items),
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                }),
+            );
         }
     }
 
@@ -146,6 +301,22 @@ impl FileExplorerData {
         node
     }
 
+    pub fn get_node(&self, path: &PathBuf) -> Option<&FileNodeItem> {
+        let mut node = self.workspace.as_ref()?;
+        if &node.path_buf == path {
+            return Some(node);
+        }
+        let root = node.path_buf.clone();
+        let path = path.strip_prefix(&root).ok()?;
+        for path in path.ancestors().collect::<Vec<&Path>>().iter().rev() {
+            if path.to_str()? == "" {
+                continue;
+            }
+            node = node.children.get(&root.join(path))?;
+        }
+        Some(node)
+    }
+
     pub fn get_node_mut(&mut self, path: &PathBuf) -> Option<&mut FileNodeItem> {
         let mut node = self.workspace.as_mut()?;
         if &node.path_buf == path {
@@ -162,6 +333,59 @@ impl FileExplorerData {
         Some(node)
     }
 
+    /// Opens all the folders leading to the path, reading the ones that
+    /// haven't been read yet.
+    pub fn reveal(
+        &mut self,
+        path: &PathBuf,
+        tab_id: WidgetId,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
+    ) {
+        let paths = match self.node_tree(path) {
+            Some(paths) => paths,
+            None => return,
+        };
+        let mut unread = false;
+        for dir in paths.iter().rev() {
+            if dir == path {
+                continue;
+            }
+            if !unread {
+                if let Some(node) = self.get_node_mut(dir) {
+                    if node.read {
+                        node.open = true;
+                        continue;
+                    }
+                }
+            }
+            // once a folder isn't read, the ones below it don't exist yet,
+            // so they are all read in order
+            unread = true;
+            let dir = dir.clone();
+            let event_sink = event_sink.clone();
+            proxy.read_dir(
+                &dir.clone(),
+                Box::new(move |result| {
+                    if let Ok(res) = result {
+                        let resp: Result<Vec<FileNodeItem>, serde_json::Error> =
+                            serde_json::from_value(res);
+                        if let Ok(items) = resp {
+                            event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::UpdateExplorerItems(0, dir, items),
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                }),
+            );
+        }
+        for dir in paths.iter() {
+            self.update_node_count(dir);
+        }
+    }
+
     fn paint_item(
         &self,
         ctx: &mut PaintCtx,
@@ -243,7 +467,11 @@ impl FileExplorerData {
                 .font(FontFamily::SYSTEM_UI, 13.0)
                 .text_color(
                     config
-                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .get_color_unchecked(if item.excluded {
+                            LapceTheme::EDITOR_DIM
+                        } else {
+                            LapceTheme::EDITOR_FOREGROUND
+                        })
                         .clone(),
                 )
                 .build()
@@ -320,6 +548,16 @@ fn node_children(node: &FileNodeItem) -> Vec<&FileNodeItem> {
     children
 }
 
+/// The folders under `node` that were read, itself included.
+fn read_dirs(node: &FileNodeItem, dirs: &mut Vec<PathBuf>) {
+    if node.is_dir && node.read {
+        dirs.push(node.path_buf.clone());
+        for child in node.children.values() {
+            read_dirs(child, dirs);
+        }
+    }
+}
+
 // fn get_item_count(item: &FileNodeItem) -> usize {
 //     let mut count = 1;
 //     if item.open {
@@ -357,6 +595,8 @@ fn get_item_children<'a>(
 pub struct FileExplorer {
     widget_id: WidgetId,
     file_list: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+    /// The footer telling about the excluded items, when there are some.
+    footer: Option<Rect>,
 }
 
 impl FileExplorer {
@@ -365,10 +605,23 @@ impl FileExplorer {
         Self {
             widget_id: data.widget_id,
             file_list: WidgetPod::new(file_list.boxed()),
+            footer: None,
         }
     }
 }
 
+/// What the footer says, if anything is excluded in the tree.
+fn excluded_footer(file_explorer: &FileExplorerData) -> Option<String> {
+    let count = file_explorer.excluded_count();
+    if file_explorer.show_excluded {
+        Some(format!("Hide excluded items ({})", count))
+    } else if count > 0 {
+        Some(format!("Excluded items hidden ({})", count))
+    } else {
+        None
+    }
+}
+
 impl Widget<LapceTabData> for FileExplorer {
     fn id(&self) -> Option<WidgetId> {
         Some(self.widget_id)
@@ -381,6 +634,25 @@ impl Widget<LapceTabData> for FileExplorer {
         data: &mut LapceTabData,
         env: &Env,
     ) {
+        if let Some(footer) = self.footer {
+            match event {
+                Event::MouseMove(mouse_event)
+                    if footer.contains(mouse_event.pos) =>
+                {
+                    ctx.set_cursor(&Cursor::Pointer);
+                    return;
+                }
+                Event::MouseDown(mouse_event)
+                    if footer.contains(mouse_event.pos) =>
+                {
+                    Arc::make_mut(&mut data.file_explorer).toggle_excluded();
+                    ctx.request_layout();
+                    ctx.set_handled();
+                    return;
+                }
+                _ => {}
+            }
+        }
         self.file_list.event(ctx, event, data, env);
     }
 
@@ -401,6 +673,12 @@ impl Widget<LapceTabData> for FileExplorer {
         data: &LapceTabData,
         env: &Env,
     ) {
+        if !Arc::ptr_eq(&old_data.file_explorer, &data.file_explorer)
+            && excluded_footer(&old_data.file_explorer)
+                != excluded_footer(&data.file_explorer)
+        {
+            ctx.request_layout();
+        }
         self.file_list.update(ctx, data, env);
     }
 
@@ -413,7 +691,25 @@ impl Widget<LapceTabData> for FileExplorer {
     ) -> Size {
         let self_size = bc.max();
         let line_height = data.config.editor.line_height as f64;
-        self.file_list.layout(ctx, bc, data, env);
+        let footer_height = if excluded_footer(&data.file_explorer).is_some() {
+            line_height
+        } else {
+            0.0
+        };
+        self.footer = if footer_height > 0.0 {
+            Some(
+                Size::new(self_size.width, footer_height)
+                    .to_rect()
+                    .with_origin(Point::new(0.0, self_size.height - footer_height)),
+            )
+        } else {
+            None
+        };
+        let list_bc = BoxConstraints::tight(Size::new(
+            self_size.width,
+            (self_size.height - footer_height).max(0.0),
+        ));
+        self.file_list.layout(ctx, &list_bc, data, env);
         self.file_list
             .set_origin(ctx, data, env, Point::new(0.0, 0.0));
         self_size
@@ -422,6 +718,37 @@ impl Widget<LapceTabData> for FileExplorer {
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
         self.file_list.paint(ctx, data, env);
 
+        if let (Some(footer), Some(text)) =
+            (self.footer, excluded_footer(&data.file_explorer))
+        {
+            ctx.fill(
+                footer,
+                data.config
+                    .get_color_unchecked(LapceTheme::PANEL_BACKGROUND),
+            );
+            ctx.stroke(
+                Line::new(
+                    Point::new(footer.x0, footer.y0 + 0.5),
+                    Point::new(footer.x1, footer.y0 + 0.5),
+                ),
+                data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER),
+                1.0,
+            );
+            let text_layout = ctx
+                .text()
+                .new_text_layout(text)
+                .font(FontFamily::SYSTEM_UI, 12.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let y = footer.y0 + (footer.height() - text_layout.size().height) / 2.0;
+            ctx.draw_text(&text_layout, Point::new(10.0, y));
+        }
+
         //  let line_height = data.config.editor.line_height as f64;
 
         //  let shadow_width = 5.0;
@@ -500,6 +827,60 @@ impl Widget<LapceTabData> for FileExplorerFileList {
                     }
                 }
             }
+            Event::MouseDown(mouse_event) if mouse_event.button.is_right() => {
+                let line_height = data.config.editor.line_height as f64;
+                let index =
+                    ((mouse_event.pos.y + line_height) / line_height) as usize;
+                let mut menu_items = Vec::new();
+                if let Some(node) =
+                    Arc::make_mut(&mut data.file_explorer).get_node_by_index(index)
+                {
+                    if node.is_dir {
+                        menu_items.push(MenuItem {
+                            text: LapceWorkbenchCommand::OpenTerminalHere
+                                .get_message()
+                                .unwrap()
+                                .to_string(),
+                            command: LapceCommandNew {
+                                cmd: LapceWorkbenchCommand::OpenTerminalHere
+                                    .to_string(),
+                                palette_desc: None,
+                                data: Some(json!(node.path_buf)),
+                                target: CommandTarget::Workbench,
+                            },
+                        });
+                    }
+                    if Some(&node.path_buf) != data.workspace.path.as_ref() {
+                        menu_items.push(MenuItem {
+                            text: "Delete".to_string(),
+                            command: LapceCommandNew {
+                                cmd: LapceWorkbenchCommand::DeleteFile.to_string(),
+                                palette_desc: None,
+                                data: Some(json!(node.path_buf)),
+                                target: CommandTarget::Workbench,
+                            },
+                        });
+                    }
+                }
+                menu_items.push(MenuItem {
+                    text: "Recently Deleted".to_string(),
+                    command: LapceCommandNew {
+                        cmd: LapceWorkbenchCommand::RecentlyDeleted.to_string(),
+                        palette_desc: None,
+                        data: None,
+                        target: CommandTarget::Workbench,
+                    },
+                });
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowMenu(
+                        ctx.to_window(mouse_event.pos),
+                        Arc::new(menu_items),
+                    ),
+                    Target::Auto,
+                ));
+                ctx.set_handled();
+            }
             Event::MouseDown(mouse_event) => {
                 let line_height = data.config.editor.line_height as f64;
                 let file_explorer = Arc::make_mut(&mut data.file_explorer);
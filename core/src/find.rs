@@ -14,6 +14,10 @@ use crate::{
 
 const REGEX_SIZE_LIMIT: usize = 1000000;
 
+/// How many bytes `find_all` searches between checks of whether it's still
+/// wanted.
+const FIND_ALL_CHUNK: usize = 1 << 20;
+
 /// Indicates what changed in the find state.
 #[derive(PartialEq, Debug, Clone)]
 pub enum FindProgress {
@@ -96,6 +100,11 @@ impl Find {
         &self.occurrences
     }
 
+    pub fn set_occurrences(&mut self, occurrences: Selection) {
+        self.occurrences = occurrences;
+        self.hls_dirty = true;
+    }
+
     pub fn hls_dirty(&self) -> bool {
         self.hls_dirty
     }
@@ -110,6 +119,98 @@ impl Find {
             && is_multiline_regex(self.search_string.as_ref().unwrap())
     }
 
+    /// Whether the other find has the same query and options.
+    pub fn same_query(&self, other: &Find) -> bool {
+        self.search_string == other.search_string
+            && self.case_matching == other.case_matching
+            && self.regex.as_ref().map(|r| r.as_str())
+                == other.regex.as_ref().map(|r| r.as_str())
+            && self.whole_words == other.whole_words
+    }
+
+    /// Whether the matches of this query are all among the ones of
+    /// `previous`, which is the case when the query was only typed further.
+    /// Its occurrences can then be found with `refine_occurrences`.
+    pub fn refines(&self, previous: &Find) -> bool {
+        let (query, previous_query) =
+            match (&self.search_string, &previous.search_string) {
+                (Some(query), Some(previous_query)) => (query, previous_query),
+                _ => return false,
+            };
+        // a longer whole word doesn't contain the shorter one as a word
+        self.regex.is_none()
+            && previous.regex.is_none()
+            && !self.whole_words
+            && !previous.whole_words
+            && self.case_matching == previous.case_matching
+            && query.is_ascii()
+            && !previous_query.is_empty()
+            && query.len() > previous_query.len()
+            && query.starts_with(previous_query.as_str())
+    }
+
+    /// Keeps the occurrences of the previous query which the current one,
+    /// refining it, matches too. The text searched for the previous query
+    /// counts as searched for this one.
+    pub fn refine_occurrences(&mut self, text: &Rope) {
+        let query = match self.search_string.as_ref() {
+            Some(query) => query.clone(),
+            None => return,
+        };
+        let previous = std::mem::replace(&mut self.occurrences, Selection::new());
+        for region in previous.regions() {
+            let start = region.min();
+            let end = start + query.len();
+            if end > text.len()
+                || text.at_or_next_codepoint_boundary(end) != Some(end)
+            {
+                continue;
+            }
+            let matched = text.slice_to_cow(start..end);
+            let is_match = match self.case_matching {
+                CaseMatching::Exact => matched == query.as_str(),
+                CaseMatching::CaseInsensitive => {
+                    matched.eq_ignore_ascii_case(&query)
+                }
+            };
+            if is_match {
+                self.occurrences
+                    .add_region(SelRegion::new(start, end, None));
+            }
+        }
+        self.hls_dirty = true;
+    }
+
+    /// Finds the occurrences in the whole text, a chunk of lines at a time,
+    /// giving up as soon as `cancelled` says so. Returns whether it got to
+    /// the end.
+    pub fn find_all(&mut self, text: &Rope, cancelled: impl Fn() -> bool) -> bool {
+        self.occurrences = Selection::new();
+        let search_string = match self.search_string.as_ref() {
+            Some(search_string) => search_string,
+            None => return true,
+        };
+        // the matches can go across the chunks
+        if LinesMetric::next(search_string, 0).is_some() || self.is_multiline_regex()
+        {
+            self.update_find(text, 0, text.len(), false);
+            return true;
+        }
+
+        let mut start = 0;
+        while start < text.len() {
+            if cancelled() {
+                return false;
+            }
+            let mut cursor =
+                Cursor::new(text, min(start + FIND_ALL_CHUNK, text.len()));
+            let end = cursor.next::<LinesMetric>().unwrap_or(text.len());
+            self.update_find(text, start, end, false);
+            start = end;
+        }
+        true
+    }
+
     /// Unsets the search and removes all highlights from the view.
     pub fn unset(&mut self) {
         self.search_string = None;
@@ -527,3 +628,149 @@ impl Find {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn find(query: &str, case_sensitive: bool) -> Find {
+        let mut find = Find::new(0);
+        find.set_find(query, case_sensitive, false, false);
+        find
+    }
+
+    fn starts(find: &Find) -> Vec<usize> {
+        find.occurrences()
+            .regions()
+            .iter()
+            .map(|r| r.min())
+            .collect()
+    }
+
+    #[test]
+    fn test_refines() {
+        assert!(find("foob", false).refines(&find("foo", false)));
+        assert!(!find("foo", false).refines(&find("foo", false)));
+        assert!(!find("fob", false).refines(&find("foo", false)));
+        assert!(!find("foob", true).refines(&find("foo", false)));
+        assert!(!find("foob", false).refines(&Find::new(0)));
+
+        let mut regex = Find::new(0);
+        regex.set_find("foob", false, true, false);
+        assert!(!regex.refines(&find("foo", false)));
+        let mut words = Find::new(0);
+        words.set_find("foob", false, false, true);
+        assert!(!words.refines(&find("foo", false)));
+    }
+
+    #[test]
+    fn test_refine_occurrences() {
+        let text = Rope::from("Foo foobar fOOBAZ\nfoo\nFOOB");
+        let mut typed = find("foo", false);
+        typed.find_all(&text, || false);
+        assert_eq!(starts(&typed), vec![0, 4, 11, 18, 22]);
+
+        let mut refined = find("foob", false);
+        refined.occurrences = typed.occurrences.clone();
+        refined.refine_occurrences(&text);
+        let mut scanned = find("foob", false);
+        scanned.find_all(&text, || false);
+        assert_eq!(starts(&refined), starts(&scanned));
+        assert_eq!(starts(&refined), vec![4, 11, 22]);
+
+        let mut exact = find("foob", true);
+        exact.occurrences = typed.occurrences.clone();
+        exact.refine_occurrences(&text);
+        assert_eq!(starts(&exact), vec![4]);
+    }
+
+    #[test]
+    fn test_find_all_cancelled() {
+        let text = Rope::from("foo\n".repeat(FIND_ALL_CHUNK));
+        let mut find = find("foo", false);
+        assert!(!find.find_all(&text, || true));
+        assert!(find.find_all(&text, || false));
+        assert_eq!(find.occurrences().len(), FIND_ALL_CHUNK);
+    }
+
+    #[test]
+    fn test_update_highlights_after_edit() {
+        let mut text = Rope::from("foo bar\nbar foo\nfoo\n");
+        let mut find = find("foo", false);
+        find.find_all(&text, || false);
+
+        let delta = RopeDelta::simple_edit(
+            Interval::new(8, 11),
+            Rope::from("foo"),
+            text.len(),
+        );
+        text = delta.apply(&text);
+        find.update_highlights(&text, &delta);
+
+        let mut scanned = self::find("foo", false);
+        scanned.find_all(&text, || false);
+        assert_eq!(starts(&find), starts(&scanned));
+    }
+
+    /// The work of a keystroke in the find input on a 50MB file: refining
+    /// the matches of the query so far, and searching the visible lines.
+    /// Run with `cargo test --release -- --ignored find_latency`.
+    #[test]
+    #[ignore]
+    fn bench_find_latency() {
+        let mut content = String::new();
+        let mut i = 0;
+        while content.len() < 50 * 1024 * 1024 {
+            content.push_str(&format!(
+                "let item_{} = compute(value_{}, {});\n",
+                i,
+                i,
+                i % 97
+            ));
+            i += 1;
+        }
+        let text = Rope::from(content);
+        let visible_end = text.offset_of_line(60);
+
+        let mut previous = find("item_1", false);
+        previous.find_all(&text, || false);
+
+        let mut worst = Duration::ZERO;
+        for query in ["item_12", "item_123", "item_1234"] {
+            let started = Instant::now();
+            let mut typed = find(query, false);
+            assert!(typed.refines(&previous));
+            typed.occurrences = previous.occurrences.clone();
+            typed.refine_occurrences(&text);
+            typed.update_find(&text, 0, visible_end, true);
+            let elapsed = started.elapsed();
+            println!(
+                "{}: {} matches in {:?}",
+                query,
+                typed.occurrences().len(),
+                elapsed
+            );
+            worst = worst.max(elapsed);
+            previous = typed;
+        }
+
+        let started = Instant::now();
+        let mut typed = find("compute(value_99", false);
+        typed.update_find(&text, 0, visible_end, true);
+        let visible = started.elapsed();
+        println!("visible lines searched in {:?}", visible);
+
+        let started = Instant::now();
+        typed.find_all(&text, || false);
+        println!(
+            "whole file searched in {:?}, {} matches",
+            started.elapsed(),
+            typed.occurrences().len()
+        );
+
+        assert!(worst < Duration::from_millis(100));
+        assert!(visible < Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,98 @@
+use druid::WidgetId;
+
+/// How many editors the history remembers.
+const FOCUS_HISTORY_LEN: usize = 50;
+
+/// The editors of the splits that were focused, or that a panel opened a
+/// location in, the latest last. Focusing a panel leaves it alone, so going
+/// from one panel to another and back to the editors ends up in the editor
+/// worked in last, whatever the panels did to the active one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FocusHistory {
+    editors: Vec<WidgetId>,
+}
+
+impl FocusHistory {
+    pub fn new(active: Option<WidgetId>) -> Self {
+        Self {
+            editors: active.into_iter().collect(),
+        }
+    }
+
+    /// Moves the editor to the top of the history.
+    pub fn push(&mut self, view_id: WidgetId) {
+        self.editors.retain(|id| *id != view_id);
+        self.editors.push(view_id);
+        if self.editors.len() > FOCUS_HISTORY_LEN {
+            self.editors.remove(0);
+        }
+    }
+
+    /// Removes a closed editor.
+    pub fn forget(&mut self, view_id: WidgetId) {
+        self.editors.retain(|id| *id != view_id);
+    }
+
+    pub fn contains(&self, view_id: WidgetId) -> bool {
+        self.editors.contains(&view_id)
+    }
+
+    /// The latest editor, other than `except`, that `usable` accepts.
+    pub fn latest(
+        &self,
+        except: Option<WidgetId>,
+        usable: impl Fn(WidgetId) -> bool,
+    ) -> Option<WidgetId> {
+        self.editors
+            .iter()
+            .rev()
+            .copied()
+            .find(|id| Some(*id) != except && usable(*id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_to_the_editor_of_a_search_result() {
+        let (result, before_search) = (WidgetId::next(), WidgetId::next());
+        let mut history = FocusHistory::new(Some(result));
+        // the editor worked in before searching
+        history.push(before_search);
+        assert_eq!(history.latest(None, |_| true), Some(before_search));
+        // focusing the search input and the results pushes nothing, and
+        // clicking a result opens it in the other editor
+        history.push(result);
+        assert_eq!(history.latest(None, |_| true), Some(result));
+    }
+
+    #[test]
+    fn test_closed_and_unusable_editors_are_skipped() {
+        let (a, b, c) = (WidgetId::next(), WidgetId::next(), WidgetId::next());
+        let mut history = FocusHistory::default();
+        history.push(a);
+        history.push(b);
+        history.push(c);
+        history.push(a);
+        assert_eq!(history.latest(None, |_| true), Some(a));
+        assert_eq!(history.latest(Some(a), |_| true), Some(c));
+        assert_eq!(history.latest(None, |id| id != a), Some(c));
+
+        history.forget(c);
+        assert!(!history.contains(c));
+        assert_eq!(history.latest(Some(a), |_| true), Some(b));
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = FocusHistory::default();
+        let first = WidgetId::next();
+        history.push(first);
+        for _ in 0..FOCUS_HISTORY_LEN {
+            history.push(WidgetId::next());
+        }
+        assert!(!history.contains(first));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{ops::Range, path::Path};
+
+use serde_json::Value;
+
+use crate::buffer::changed_region;
+
+/// What formats a document: the edits of its language server, or the whole
+/// text a formatter command printed.
+#[derive(Clone, Debug)]
+pub enum Formatting {
+    Edits(Value),
+    Text(String),
+}
+
+/// The name of the language of the file at `path`, which picks its
+/// `[format.<language>]` config. Files of other languages go by their
+/// extension.
+pub fn language_of(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    let language = match extension {
+        "rs" => "rust",
+        "go" => "go",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "py" => "python",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "md" => "markdown",
+        "yml" | "yaml" => "yaml",
+        "htm" | "html" => "html",
+        "sh" | "bash" => "shell",
+        "rb" => "ruby",
+        _ => extension,
+    };
+    Some(language.to_string())
+}
+
+/// The edits turning `old` into `new`, as the ranges of `old` to replace
+/// and their replacements. Only the lines that differ are edited, and only
+/// the part of them that does, so the cursors and the folds of the rest of
+/// the text stay put.
+pub fn text_edits(old: &str, new: &str) -> Vec<(Range<usize>, String)> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let mut edits = Vec::new();
+    let mut offset = 0;
+    // the start of the lines being replaced, and their replacement
+    let mut hunk: Option<(usize, String)> = None;
+    let mut flush = |hunk: &mut Option<(usize, String)>, end: usize| {
+        if let Some((start, text)) = hunk.take() {
+            let (old_range, new_range) = changed_region(&old[start..end], &text);
+            edits.push((
+                start + old_range.start..start + old_range.end,
+                text[new_range].to_string(),
+            ));
+        }
+    };
+    for line in diff::slice(&old_lines, &new_lines) {
+        match line {
+            diff::Result::Both(line, _) => {
+                flush(&mut hunk, offset);
+                offset += line.len();
+            }
+            diff::Result::Left(line) => {
+                hunk.get_or_insert_with(|| (offset, String::new()));
+                offset += line.len();
+            }
+            diff::Result::Right(line) => {
+                hunk.get_or_insert_with(|| (offset, String::new()))
+                    .1
+                    .push_str(line);
+            }
+        }
+    }
+    flush(&mut hunk, offset);
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, edits: &[(Range<usize>, String)]) -> String {
+        let mut text = old.to_string();
+        for (range, new) in edits.iter().rev() {
+            text.replace_range(range.clone(), new);
+        }
+        text
+    }
+
+    #[test]
+    fn test_text_edits() {
+        let old = "fn a(){\nlet x=1;\n}\n\nfn b() {}\n";
+        let new = "fn a() {\n    let x = 1;\n}\n\nfn b() {}\n";
+        let edits = text_edits(old, new);
+        assert_eq!(apply(old, &edits), new);
+        // the unchanged function isn't touched
+        assert!(edits.iter().all(|(range, _)| range.end <= 19));
+
+        assert!(text_edits(old, old).is_empty());
+        assert_eq!(
+            text_edits("a\nb\n", "a\nc\n"),
+            vec![(2..3, "c".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_text_edits_of_added_and_removed_lines() {
+        for (old, new) in [
+            ("a\nb\n", "a\nb\nc\n"),
+            ("a\nb\nc\n", "a\nc\n"),
+            ("", "a\n"),
+            ("a", "a\n"),
+            ("a\r\nb\r\n", "a\r\nb\r\n\r\n"),
+        ] {
+            assert_eq!(apply(old, &text_edits(old, new)), new);
+        }
+    }
+
+    #[test]
+    fn test_language_of() {
+        assert_eq!(language_of(Path::new("/w/a.rs")), Some("rust".to_string()));
+        assert_eq!(
+            language_of(Path::new("a.tsx")),
+            Some("typescript".to_string())
+        );
+        assert_eq!(language_of(Path::new("a.zig")), Some("zig".to_string()));
+        assert_eq!(language_of(Path::new("Makefile")), None);
+    }
+}
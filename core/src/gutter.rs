@@ -0,0 +1,312 @@
+use std::{collections::BTreeMap, ops};
+
+use lsp_types::{DiagnosticSeverity, NumberOrString};
+
+use crate::{buffer::DiffLines, config::LapceTheme, data::EditorDiagnostic};
+
+/// A marker in the marker column of the editor gutter. A line shows a single
+/// marker, the one that sorts first, so a new kind of marker only needs its
+/// place in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GutterMarker {
+    CodeActions,
+    Diagnostic(DiagnosticSeverity),
+}
+
+impl GutterMarker {
+    pub fn icon(&self) -> &'static str {
+        match self {
+            GutterMarker::CodeActions => "lightbulb.svg",
+            GutterMarker::Diagnostic(DiagnosticSeverity::Error) => "error.svg",
+            GutterMarker::Diagnostic(DiagnosticSeverity::Warning) => "warning.svg",
+            GutterMarker::Diagnostic(_) => "info.svg",
+        }
+    }
+
+    pub fn color(&self) -> &'static str {
+        match self {
+            GutterMarker::CodeActions => LapceTheme::LAPCE_WARN,
+            GutterMarker::Diagnostic(DiagnosticSeverity::Error) => {
+                LapceTheme::LAPCE_ERROR
+            }
+            GutterMarker::Diagnostic(DiagnosticSeverity::Warning) => {
+                LapceTheme::LAPCE_WARN
+            }
+            GutterMarker::Diagnostic(_) => LapceTheme::EDITOR_DIM,
+        }
+    }
+}
+
+/// The markers of the lines in view.
+#[derive(Default)]
+pub struct GutterMarkers {
+    markers: BTreeMap<usize, GutterMarker>,
+}
+
+impl GutterMarkers {
+    pub fn add(&mut self, line: usize, marker: GutterMarker) {
+        let current = self.markers.entry(line).or_insert(marker);
+        if marker < *current {
+            *current = marker;
+        }
+    }
+
+    /// Adds the most severe diagnostic starting on each line between
+    /// `start_line` and `end_line`.
+    pub fn add_diagnostics(
+        &mut self,
+        diagnostics: &[EditorDiagnostic],
+        start_line: usize,
+        end_line: usize,
+    ) {
+        for diagnostic in diagnostics {
+            let line = diagnostic.diagnositc.range.start.line as usize;
+            if line >= start_line && line <= end_line {
+                self.add(
+                    line,
+                    GutterMarker::Diagnostic(diagnostic_severity(diagnostic)),
+                );
+            }
+        }
+    }
+
+    pub fn get(&self, line: usize) -> Option<GutterMarker> {
+        self.markers.get(&line).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&usize, &GutterMarker)> {
+        self.markers.iter()
+    }
+}
+
+/// The severity the diagnostic is shown with.
+pub fn diagnostic_severity(diagnostic: &EditorDiagnostic) -> DiagnosticSeverity {
+    diagnostic
+        .diagnositc
+        .severity
+        .unwrap_or(DiagnosticSeverity::Information)
+}
+
+/// The diagnostics starting on `line`, the most severe first.
+pub fn line_diagnostics(
+    diagnostics: &[EditorDiagnostic],
+    line: usize,
+) -> Vec<&EditorDiagnostic> {
+    let mut diagnostics: Vec<&EditorDiagnostic> = diagnostics
+        .iter()
+        .filter(|d| d.diagnositc.range.start.line as usize == line)
+        .collect();
+    diagnostics.sort_by_key(|d| diagnostic_severity(d));
+    diagnostics
+}
+
+/// The source and the code of the diagnostic, like "rustc E0308".
+pub fn diagnostic_source(diagnostic: &EditorDiagnostic) -> String {
+    let code = diagnostic.diagnositc.code.as_ref().map(|code| match code {
+        NumberOrString::Number(n) => n.to_string(),
+        NumberOrString::String(s) => s.clone(),
+    });
+    match (diagnostic.diagnositc.source.as_ref(), code) {
+        (Some(source), Some(code)) => format!("{} {}", source, code),
+        (Some(source), None) => source.clone(),
+        (None, Some(code)) => code,
+        (None, None) => "".to_string(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffHunkKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl DiffHunkKind {
+    pub fn color(&self) -> &'static str {
+        match self {
+            DiffHunkKind::Added => LapceTheme::SOURCE_CONTROL_ADDED,
+            DiffHunkKind::Modified => LapceTheme::SOURCE_CONTROL_MODIFIED,
+            DiffHunkKind::Removed => LapceTheme::SOURCE_CONTROL_REMOVED,
+        }
+    }
+}
+
+/// A change of the file from the head: the lines of the buffer it spans,
+/// none when lines were removed before `lines.start`, and the lines of the
+/// head they replace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub kind: DiffHunkKind,
+    pub lines: ops::Range<usize>,
+    pub old_lines: ops::Range<usize>,
+}
+
+/// The hunks of the changes between the head, on the left, and the buffer.
+pub fn diff_hunks(changes: &[DiffLines]) -> Vec<DiffHunk> {
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    // where the next lines start, in the head and in the buffer
+    let (mut old_line, mut line) = (0, 0);
+    for change in changes {
+        match change {
+            DiffLines::Left(old) => {
+                hunks.push(DiffHunk {
+                    kind: DiffHunkKind::Removed,
+                    lines: line..line,
+                    old_lines: old.clone(),
+                });
+                old_line = old.end;
+            }
+            DiffLines::Right(new) => {
+                match hunks.last_mut() {
+                    // lines replacing the ones just removed
+                    Some(hunk)
+                        if hunk.kind == DiffHunkKind::Removed
+                            && hunk.lines.start == new.start
+                            && hunk.old_lines.end == old_line =>
+                    {
+                        hunk.kind = DiffHunkKind::Modified;
+                        hunk.lines = new.clone();
+                    }
+                    _ => hunks.push(DiffHunk {
+                        kind: DiffHunkKind::Added,
+                        lines: new.clone(),
+                        old_lines: old_line..old_line,
+                    }),
+                }
+                line = new.end;
+            }
+            DiffLines::Both(old, new) | DiffLines::Skip(old, new) => {
+                old_line = old.end;
+                line = new.end;
+            }
+        }
+    }
+    hunks
+}
+
+/// The hunk whose marker is on `line`. The marker of removed lines sits
+/// between the lines around them.
+pub fn hunk_at_line(hunks: &[DiffHunk], line: usize) -> Option<&DiffHunk> {
+    hunks
+        .iter()
+        .find(|hunk| hunk.lines.contains(&line))
+        .or_else(|| {
+            hunks.iter().find(|hunk| {
+                hunk.kind == DiffHunkKind::Removed
+                    && (hunk.lines.start == line || hunk.lines.start == line + 1)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{Diagnostic, Position, Range};
+
+    use super::*;
+
+    fn diagnostic(
+        line: u32,
+        severity: Option<DiagnosticSeverity>,
+        message: &str,
+    ) -> EditorDiagnostic {
+        let mut diagnostic = Diagnostic::new_simple(
+            Range::new(Position::new(line, 0), Position::new(line, 1)),
+            message.to_string(),
+        );
+        diagnostic.severity = severity;
+        EditorDiagnostic {
+            range: None,
+            diagnositc: diagnostic,
+        }
+    }
+
+    #[test]
+    fn test_marker_precedence() {
+        let diagnostics = vec![
+            diagnostic(1, Some(DiagnosticSeverity::Warning), "a"),
+            diagnostic(1, Some(DiagnosticSeverity::Error), "b"),
+            diagnostic(1, Some(DiagnosticSeverity::Hint), "c"),
+            diagnostic(2, None, "d"),
+            diagnostic(3, Some(DiagnosticSeverity::Warning), "e"),
+            diagnostic(9, Some(DiagnosticSeverity::Error), "f"),
+        ];
+        let mut markers = GutterMarkers::default();
+        markers.add_diagnostics(&diagnostics, 0, 5);
+        markers.add(3, GutterMarker::CodeActions);
+        assert_eq!(
+            markers.get(1),
+            Some(GutterMarker::Diagnostic(DiagnosticSeverity::Error))
+        );
+        assert_eq!(
+            markers.get(2),
+            Some(GutterMarker::Diagnostic(DiagnosticSeverity::Information))
+        );
+        assert_eq!(markers.get(3), Some(GutterMarker::CodeActions));
+        assert_eq!(markers.get(9), None);
+        assert_eq!(markers.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_line_diagnostics() {
+        let diagnostics = vec![
+            diagnostic(1, Some(DiagnosticSeverity::Hint), "a"),
+            diagnostic(1, Some(DiagnosticSeverity::Error), "b"),
+            diagnostic(2, Some(DiagnosticSeverity::Error), "c"),
+            diagnostic(1, Some(DiagnosticSeverity::Warning), "d"),
+            diagnostic(1, Some(DiagnosticSeverity::Error), "e"),
+        ];
+        let messages: Vec<&str> = line_diagnostics(&diagnostics, 1)
+            .iter()
+            .map(|d| d.diagnositc.message.as_str())
+            .collect();
+        assert_eq!(messages, vec!["b", "e", "d", "a"]);
+        assert!(line_diagnostics(&diagnostics, 0).is_empty());
+    }
+
+    #[test]
+    fn test_diagnostic_source() {
+        let mut d = diagnostic(0, None, "a");
+        assert_eq!(diagnostic_source(&d), "");
+        d.diagnositc.source = Some("rustc".to_string());
+        assert_eq!(diagnostic_source(&d), "rustc");
+        d.diagnositc.code = Some(NumberOrString::String("E0308".to_string()));
+        assert_eq!(diagnostic_source(&d), "rustc E0308");
+        d.diagnositc.source = None;
+        d.diagnositc.code = Some(NumberOrString::Number(7));
+        assert_eq!(diagnostic_source(&d), "7");
+    }
+
+    #[test]
+    fn test_diff_hunks() {
+        // the head "a b c d e f" became "a x c d g e"
+        let changes = vec![
+            DiffLines::Both(0..1, 0..1),
+            DiffLines::Left(1..2),
+            DiffLines::Right(1..2),
+            DiffLines::Both(2..4, 2..4),
+            DiffLines::Right(4..5),
+            DiffLines::Both(4..5, 5..6),
+            DiffLines::Left(5..6),
+        ];
+        let hunk = |kind, lines, old_lines| DiffHunk {
+            kind,
+            lines,
+            old_lines,
+        };
+        let hunks = diff_hunks(&changes);
+        assert_eq!(
+            hunks,
+            vec![
+                hunk(DiffHunkKind::Modified, 1..2, 1..2),
+                hunk(DiffHunkKind::Added, 4..5, 4..4),
+                hunk(DiffHunkKind::Removed, 6..6, 5..6),
+            ]
+        );
+
+        assert_eq!(hunk_at_line(&hunks, 1), Some(&hunks[0]));
+        assert_eq!(hunk_at_line(&hunks, 4), Some(&hunks[1]));
+        assert_eq!(hunk_at_line(&hunks, 5), Some(&hunks[2]));
+        assert_eq!(hunk_at_line(&hunks, 3), None);
+        assert!(diff_hunks(&[DiffLines::Skip(0..10, 0..10)]).is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! Runs the commands of the widgets on the data of a tab, without a window.
+//! The tab is built over a `MockProxy` of a temporary folder, and what the
+//! widgets ask of the context of the event is recorded, so that tests can
+//! check both the data and the commands that were submitted. Mouse and key
+//! events are sent to the widgets that implement `HarnessWidget`.
+
+use std::{fs, path::PathBuf, sync::Arc};
+
+use druid::{
+    AppLauncher, Command, Event, ExtEventSink, KbKey, KeyEvent, Modifiers,
+    MouseButton, MouseButtons, MouseEvent, Point, Size, Target, Vec2, WidgetExt,
+    WidgetId,
+};
+use tempfile::TempDir;
+
+use crate::{
+    buffer::{BufferContent, BufferNew},
+    command::{CommandCtx, LapceUICommand, LAPCE_UI_COMMAND},
+    data::{LapceEditorData, LapceTabData, LapceWindowData, PanelKind},
+    db::LapceDb,
+    editor::LapceEditorView,
+    keypress::KeyPressData,
+    menu::MenuData,
+    mock_proxy::MockProxy,
+    proxy::ProxyBackend,
+    split::LapceSplitNew,
+    state::LapceWorkspace,
+};
+
+/// Stands for druid's `EventCtx`, keeping what the widgets ask of it.
+pub struct RecordingCtx {
+    pub commands: Vec<Command>,
+    pub children_changed: bool,
+    pub layout_requested: bool,
+    pub handled: bool,
+    /// What the widget last told of the title bar under the mouse.
+    pub titlebar: Option<bool>,
+    pub size: Size,
+    event_sink: ExtEventSink,
+}
+
+impl CommandCtx for RecordingCtx {
+    fn submit_command(&mut self, cmd: Command) {
+        self.commands.push(cmd);
+    }
+
+    fn children_changed(&mut self) {
+        self.children_changed = true;
+    }
+
+    fn request_layout(&mut self) {
+        self.layout_requested = true;
+    }
+
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn get_external_handle(&self) -> ExtEventSink {
+        self.event_sink.clone()
+    }
+
+    fn set_handled(&mut self) {
+        self.handled = true;
+    }
+
+    fn handle_titlebar(&mut self, val: bool) {
+        self.titlebar = Some(val);
+    }
+}
+
+/// A widget that handles the events the harness sends it, the way its
+/// `event` does with druid's context.
+pub trait HarnessWidget<T> {
+    fn event(&mut self, ctx: &mut RecordingCtx, event: &Event, data: &mut T);
+}
+
+/// A press of the left button at the point, `count` being 2 for a double
+/// click.
+pub fn mouse_down(pos: Point, count: u8, mods: Modifiers) -> Event {
+    Event::MouseDown(MouseEvent {
+        pos,
+        window_pos: pos,
+        buttons: MouseButtons::new(),
+        mods,
+        count,
+        focus: false,
+        button: MouseButton::Left,
+        wheel_delta: Vec2::ZERO,
+    })
+}
+
+/// A press of the key.
+pub fn key_down(key: KbKey, mods: Modifiers) -> Event {
+    Event::KeyDown(KeyEvent {
+        key,
+        mods,
+        ..KeyEvent::default()
+    })
+}
+
+pub struct Harness {
+    pub data: LapceTabData,
+    pub ctx: RecordingCtx,
+    root: TempDir,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        let backend: Arc<dyn ProxyBackend> =
+            Arc::new(MockProxy::new(root.path().to_path_buf()));
+        let event_sink = AppLauncher::new().get_external_handle();
+        let data = LapceTabData::new_with_backend(
+            WidgetId::next(),
+            LapceWorkspace::default(),
+            Arc::new(LapceDb::for_tests(root.path())),
+            Arc::new(KeyPressData::new()),
+            event_sink.clone(),
+            Some(backend),
+        );
+        Self {
+            data,
+            ctx: RecordingCtx {
+                commands: Vec::new(),
+                children_changed: false,
+                layout_requested: false,
+                handled: false,
+                titlebar: None,
+                size: Size::new(1200.0, 800.0),
+                event_sink,
+            },
+            root,
+        }
+    }
+
+    /// Writes a file in the folder of the mock proxy, and returns its path.
+    pub fn file(&self, name: &str, text: &str) -> PathBuf {
+        let path = self.root.path().join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    /// The main split of the tab with an editor of each content, the first
+    /// one active, as the tab builds it from a saved layout.
+    pub fn main_split(&mut self, contents: &[BufferContent]) -> LapceSplitNew {
+        let split_id = *self.data.main_split.split_id;
+        let (split, order) = self.split(split_id, contents);
+        self.data.main_split.active = Arc::new(order.first().cloned());
+        self.data.main_split.editors_flex = Arc::new(vec![1.0; order.len()]);
+        self.data.main_split.editors_order = Arc::new(order);
+        split
+    }
+
+    /// The split of the editors panel with an editor of each content, the
+    /// panel listing it as when editors were moved there.
+    pub fn editors_panel(&mut self, contents: &[BufferContent]) -> LapceSplitNew {
+        let split_id = self.data.editors_panel.split_id;
+        let (split, _) = self.split(split_id, contents);
+        self.data.update_editors_panel(true);
+        split
+    }
+
+    /// Whether a panel lists the editors panel.
+    pub fn editors_panel_listed(&self) -> bool {
+        self.data
+            .panels
+            .values()
+            .any(|panel| panel.widgets.contains(&PanelKind::Editors))
+    }
+
+    /// A split with an editor of each content, and the editors in order.
+    fn split(
+        &mut self,
+        split_id: WidgetId,
+        contents: &[BufferContent],
+    ) -> (LapceSplitNew, Vec<WidgetId>) {
+        let mut split = LapceSplitNew::new(split_id);
+        let mut order = Vec::new();
+        for content in contents {
+            let editor = LapceEditorData::new(
+                None,
+                Some(split_id),
+                content.clone(),
+                &self.data.config,
+            );
+            if let BufferContent::File(path) = content {
+                if !self.data.main_split.open_files.contains_key(path) {
+                    let buffer = BufferNew::new(
+                        content.clone(),
+                        self.data.update_sender.clone(),
+                        self.data.id,
+                        self.ctx.event_sink.clone(),
+                    );
+                    self.data
+                        .main_split
+                        .open_files
+                        .insert(path.clone(), Arc::new(buffer));
+                }
+            }
+            split = split.with_flex_child(
+                LapceEditorView::new(&editor).boxed(),
+                Some(editor.view_id),
+                1.0,
+            );
+            order.push(editor.view_id);
+            self.data
+                .main_split
+                .editors
+                .insert(editor.view_id, Arc::new(editor));
+        }
+        (split, order)
+    }
+
+    /// A window with the tab as its only one.
+    pub fn window(&self) -> LapceWindowData {
+        let mut tabs = im::HashMap::new();
+        tabs.insert(self.data.id, self.data.clone());
+        LapceWindowData {
+            tabs,
+            tabs_order: Arc::new(vec![self.data.id]),
+            active: 0,
+            active_id: self.data.id,
+            keypress: self.data.keypress.clone(),
+            config: self.data.config.clone(),
+            plugins: Arc::new(Vec::new()),
+            db: self.data.db.clone(),
+            watcher: Arc::new(
+                notify::recommended_watcher(|_: notify::Result<notify::Event>| {})
+                    .unwrap(),
+            ),
+            menu: Arc::new(MenuData::new()),
+            fullscreen: false,
+        }
+    }
+
+    /// Sends the event to the widget, which handles it on the data of the
+    /// tab.
+    pub fn send<W: HarnessWidget<LapceTabData>>(
+        &mut self,
+        widget: &mut W,
+        event: &Event,
+    ) {
+        widget.event(&mut self.ctx, event, &mut self.data);
+    }
+
+    /// Sends the event to a widget of the window.
+    pub fn send_window<W: HarnessWidget<LapceWindowData>>(
+        &mut self,
+        widget: &mut W,
+        window: &mut LapceWindowData,
+        event: &Event,
+    ) {
+        widget.event(&mut self.ctx, event, window);
+    }
+
+    /// Runs the command on the split, as its `event` would.
+    pub fn run(&mut self, split: &mut LapceSplitNew, command: LapceUICommand) {
+        assert!(split.run_editor_command(&mut self.ctx, &mut self.data, &command));
+    }
+
+    /// The UI commands submitted so far, with their targets.
+    pub fn ui_commands(&self) -> Vec<(&LapceUICommand, Target)> {
+        self.ctx
+            .commands
+            .iter()
+            .filter_map(|cmd| Some((cmd.get(LAPCE_UI_COMMAND)?, cmd.target())))
+            .collect()
+    }
+
+    /// The widget the last `Focus` submitted goes to.
+    pub fn focused(&self) -> Option<Target> {
+        self.ui_commands()
+            .into_iter()
+            .rev()
+            .find(|(cmd, _)| matches!(cmd, LapceUICommand::Focus))
+            .map(|(_, target)| target)
+    }
+
+    /// Forgets the commands submitted so far, and what else the widgets
+    /// asked of the context.
+    pub fn clear(&mut self) {
+        self.ctx.commands.clear();
+        self.ctx.children_changed = false;
+        self.ctx.layout_requested = false;
+        self.ctx.handled = false;
+        self.ctx.titlebar = None;
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::state::LapceWorkspace;
+
+/// How many recent files and searches a history keeps.
+const MAX_RECENT: usize = 50;
+
+/// How often and when last an item of the palette was picked.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frecency {
+    pub count: u32,
+    /// In seconds since the epoch.
+    pub last: i64,
+}
+
+impl Frecency {
+    /// The picks, weighted by how recent the last one is.
+    pub fn score(&self, now: i64) -> i64 {
+        let age = now - self.last;
+        let weight = match age {
+            a if a < 4 * 60 * 60 => 4,
+            a if a < 24 * 60 * 60 => 2,
+            a if a < 7 * 24 * 60 * 60 => 1,
+            _ => 0,
+        };
+        self.count.min(25) as i64 * weight
+    }
+}
+
+/// The recent files, searches and palette picks of a workspace, kept apart
+/// from the other workspaces'. Without a workspace, the global history is
+/// used.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceHistory {
+    /// The files opened, the latest first.
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    /// The searches whose results were opened, the latest first.
+    #[serde(default)]
+    pub searches: Vec<String>,
+    /// The palette picks, by the palette and the text of the item.
+    #[serde(default)]
+    pub picks: HashMap<String, Frecency>,
+}
+
+impl WorkspaceHistory {
+    pub fn add_file(&mut self, path: &Path) {
+        self.files.retain(|p| p != path);
+        self.files.insert(0, path.to_path_buf());
+        self.files.truncate(MAX_RECENT);
+    }
+
+    pub fn add_search(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            return;
+        }
+        self.searches.retain(|s| s != pattern);
+        self.searches.insert(0, pattern.to_string());
+        self.searches.truncate(MAX_RECENT);
+    }
+
+    pub fn add_pick(&mut self, key: &str, now: i64) {
+        let pick = self.picks.entry(key.to_string()).or_insert(Frecency {
+            count: 0,
+            last: now,
+        });
+        pick.count += 1;
+        pick.last = now;
+    }
+
+    /// How much to rank an item of the palette up, from how often it was
+    /// picked and, for a file, how recently it was opened.
+    pub fn boost(&self, key: &str, path: Option<&Path>, now: i64) -> i64 {
+        let pick = self.picks.get(key).map(|p| p.score(now)).unwrap_or(0);
+        let recent = path
+            .and_then(|path| self.files.iter().position(|p| p == path))
+            .map(|i| (MAX_RECENT - i) as i64)
+            .unwrap_or(0);
+        pick + recent
+    }
+
+    /// Moves the files under `root` out of the global history into this one.
+    /// The searches and picks can't be told apart, so they stay global.
+    fn migrate_from(&mut self, global: &mut WorkspaceHistory, root: &Path) {
+        let (files, rest) = global
+            .files
+            .drain(..)
+            .partition(|path: &PathBuf| path.starts_with(root));
+        global.files = rest;
+        self.files = files;
+    }
+
+    /// Loads the history of the workspace. The first time, the files of
+    /// the workspace in the global history move to it.
+    pub fn load(workspace: &LapceWorkspace) -> Self {
+        let path = match history_file(workspace) {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        if path.exists() {
+            return read_history(&path).unwrap_or_else(|e| {
+                log::error!("can't read the history {:?}: {}", path, e);
+                Self::default()
+            });
+        }
+
+        let mut history = Self::default();
+        if let Some(root) = workspace.path.as_ref() {
+            let global = LapceWorkspace::default();
+            let mut global_history = Self::load(&global);
+            history.migrate_from(&mut global_history, root);
+            if let Err(e) = global_history.save(&global) {
+                log::error!("can't save the global history: {}", e);
+            }
+            if let Err(e) = history.save(workspace) {
+                log::error!("can't save the history of {}: {}", workspace, e);
+            }
+        }
+        history
+    }
+
+    pub fn save(&self, workspace: &LapceWorkspace) -> Result<()> {
+        let path =
+            history_file(workspace).ok_or(anyhow!("can't find config dir"))?;
+        write_atomic(&path, serde_json::to_string(self)?.as_bytes())
+    }
+}
+
+fn read_history(path: &Path) -> Result<WorkspaceHistory> {
+    let content = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// The file of the history of the workspace, or of the global history
+/// without one.
+fn history_file(workspace: &LapceWorkspace) -> Option<PathBuf> {
+    let proj_dirs = ProjectDirs::from("", "", "Lapce")?;
+    let dir = proj_dirs.config_dir().join("history");
+    let name = match workspace.path {
+        Some(_) => workspace.file_name(),
+        None => "global".to_string(),
+    };
+    Some(dir.join(format!("{}.json", name)))
+}
+
+/// Writes the file through a temporary one renamed over it, so that it's
+/// never left half written.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let dir = path.parent().ok_or(anyhow!("no parent dir"))?;
+    fs::create_dir_all(dir)?;
+    let file_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .ok_or(anyhow!("no file name"))?;
+    let tmp = dir.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+    let result = (|| -> Result<()> {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent() {
+        let mut history = WorkspaceHistory::default();
+        history.add_file(Path::new("/w/a"));
+        history.add_file(Path::new("/w/b"));
+        history.add_file(Path::new("/w/a"));
+        assert_eq!(
+            history.files,
+            vec![PathBuf::from("/w/a"), PathBuf::from("/w/b")]
+        );
+
+        history.add_search("");
+        for i in 0..MAX_RECENT + 1 {
+            history.add_search(&i.to_string());
+        }
+        assert_eq!(history.searches.len(), MAX_RECENT);
+        assert_eq!(history.searches[0], MAX_RECENT.to_string());
+    }
+
+    #[test]
+    fn test_boost() {
+        let day = 24 * 60 * 60;
+        let mut history = WorkspaceHistory::default();
+        history.add_pick("Command:Format Document", 0);
+        history.add_pick("Command:Format Document", 10);
+        assert_eq!(history.boost("Command:Format Document", None, 20), 8);
+        assert_eq!(history.boost("Command:Format Document", None, 2 * day), 2);
+        assert_eq!(history.boost("Command:Format Document", None, 30 * day), 0);
+        assert_eq!(history.boost("Command:Save", None, 20), 0);
+
+        history.add_file(Path::new("/w/a"));
+        history.add_file(Path::new("/w/b"));
+        assert_eq!(
+            history.boost("File:a", Some(Path::new("/w/a")), 0),
+            MAX_RECENT as i64 - 1
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut global = WorkspaceHistory::default();
+        global.add_file(Path::new("/work/a"));
+        global.add_file(Path::new("/home/b"));
+        global.add_file(Path::new("/work/c"));
+        global.add_search("secret");
+        let mut history = WorkspaceHistory::default();
+        history.migrate_from(&mut global, Path::new("/work"));
+        assert_eq!(
+            history.files,
+            vec![PathBuf::from("/work/c"), PathBuf::from("/work/a")]
+        );
+        assert_eq!(global.files, vec![PathBuf::from("/home/b")]);
+        assert!(history.searches.is_empty());
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let path = dir.join("history.json");
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+/// Which files of the workspace the file palette lists. Workspaces past
+/// `files.large-workspace-threshold` files ask for it when they're opened.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexScope {
+    /// Every file that isn't excluded.
+    All,
+    /// The files in these top-level folders, and the ones at the top level.
+    Folders(Vec<String>),
+    /// No index: the palette searches the files as the query is typed.
+    None,
+}
+
+impl Default for IndexScope {
+    fn default() -> Self {
+        IndexScope::All
+    }
+}
+
+impl IndexScope {
+    /// The top-level folders to index, or `None` for all of them.
+    pub fn folders(&self) -> Option<Vec<String>> {
+        match self {
+            IndexScope::Folders(folders) => Some(folders.clone()),
+            IndexScope::All | IndexScope::None => None,
+        }
+    }
+
+    pub fn is_indexed(&self, folder: &str) -> bool {
+        match self {
+            IndexScope::All => true,
+            IndexScope::Folders(folders) => folders.iter().any(|f| f == folder),
+            IndexScope::None => false,
+        }
+    }
+
+    /// Adds the folder to the indexed ones, or removes it. `folders` are all
+    /// the top-level folders, which `All` stands for.
+    pub fn toggle_folder(&mut self, folder: &str, folders: &[String]) {
+        let mut indexed: Vec<String> = match self {
+            IndexScope::All => folders.to_vec(),
+            IndexScope::Folders(indexed) => indexed.clone(),
+            IndexScope::None => Vec::new(),
+        };
+        if let Some(i) = indexed.iter().position(|f| f == folder) {
+            indexed.remove(i);
+        } else {
+            indexed.push(folder.to_string());
+            indexed.sort();
+        }
+        *self = IndexScope::Folders(indexed);
+    }
+
+    /// What the status bar says about a partial index, to tell why a file
+    /// isn't in the palette.
+    pub fn status(&self) -> Option<String> {
+        match self {
+            IndexScope::All => None,
+            IndexScope::Folders(folders) => Some(format!(
+                "Indexing {} folder{}",
+                folders.len(),
+                if folders.len() == 1 { "" } else { "s" }
+            )),
+            IndexScope::None => Some("Indexing off".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folders() -> Vec<String> {
+        vec!["docs".to_string(), "src".to_string(), "target".to_string()]
+    }
+
+    #[test]
+    fn test_toggle_folder_from_all() {
+        let mut scope = IndexScope::All;
+        scope.toggle_folder("target", &folders());
+        assert_eq!(
+            scope,
+            IndexScope::Folders(vec!["docs".to_string(), "src".to_string()])
+        );
+        assert!(!scope.is_indexed("target"));
+        scope.toggle_folder("target", &folders());
+        assert!(scope.is_indexed("target"));
+    }
+
+    #[test]
+    fn test_toggle_folder_from_none() {
+        let mut scope = IndexScope::None;
+        scope.toggle_folder("src", &folders());
+        assert_eq!(scope, IndexScope::Folders(vec!["src".to_string()]));
+        assert_eq!(scope.status(), Some("Indexing 1 folder".to_string()));
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(IndexScope::All.status(), None);
+        assert_eq!(IndexScope::None.status(), Some("Indexing off".to_string()));
+    }
+}
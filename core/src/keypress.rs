@@ -16,6 +16,7 @@ use crate::command::{
     lapce_internal_commands, CommandExecuted, CommandTarget, LapceCommandNew,
     LAPCE_NEW_COMMAND,
 };
+use crate::command_history::CommandHistory;
 use crate::data::LapceTabData;
 use crate::{
     command::LapceCommand,
@@ -88,6 +89,8 @@ pub struct KeyPressData {
     pending_keypress: Vec<KeyPress>,
     pub keymaps: Arc<IndexMap<Vec<KeyPress>, Vec<KeyMap>>>,
     pub commands: Arc<IndexMap<String, LapceCommandNew>>,
+    /// The commands run in this session.
+    pub history: Arc<CommandHistory>,
     count: Option<usize>,
 }
 
@@ -97,10 +100,20 @@ impl KeyPressData {
             pending_keypress: Vec::new(),
             keymaps: Arc::new(Self::get_keymaps().unwrap_or(IndexMap::new())),
             commands: Arc::new(lapce_internal_commands()),
+            history: Arc::new(CommandHistory::default()),
             count: None,
         }
     }
 
+    /// Keeps the command in the history, if it's worth running again.
+    pub fn record_command(&mut self, command: &LapceCommandNew) {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Arc::make_mut(&mut self.history).record(command, time);
+    }
+
     pub fn update_keymaps(&mut self) {
         if let Ok(new_keymaps) = Self::get_keymaps() {
             self.keymaps = Arc::new(new_keymaps);
@@ -108,24 +121,30 @@ impl KeyPressData {
     }
 
     fn run_command<T: KeyPressFocus>(
-        &self,
+        &mut self,
         ctx: &mut EventCtx,
         command: &str,
         count: Option<usize>,
         focus: &mut T,
         env: &Env,
     ) -> CommandExecuted {
-        if let Some(cmd) = self.commands.get(command) {
+        if let Some(cmd) = self.commands.get(command).cloned() {
             if let CommandTarget::Focus = cmd.target {
-                if let Ok(cmd) = LapceCommand::from_str(command) {
-                    focus.run_command(ctx, &cmd, count, env)
+                if let Ok(lapce_cmd) = LapceCommand::from_str(command) {
+                    let executed = focus.run_command(ctx, &lapce_cmd, count, env);
+                    // the workbench commands are kept by the tab, which runs
+                    // them
+                    if executed == CommandExecuted::Yes {
+                        self.record_command(&cmd);
+                    }
+                    executed
                 } else {
                     CommandExecuted::No
                 }
             } else {
                 ctx.submit_command(Command::new(
                     LAPCE_NEW_COMMAND,
-                    cmd.clone(),
+                    cmd,
                     Target::Auto,
                 ));
                 CommandExecuted::Yes
@@ -434,6 +453,8 @@ impl KeyPressData {
                 "tab" => druid::keyboard_types::Key::Tab,
                 "enter" => druid::keyboard_types::Key::Enter,
                 "delete" => druid::keyboard_types::Key::Delete,
+                "pageup" => druid::keyboard_types::Key::PageUp,
+                "pagedown" => druid::keyboard_types::Key::PageDown,
                 "del" => druid::keyboard_types::Key::Delete,
                 _ => druid::keyboard_types::Key::Character(
                     parts[parts.len() - 1].to_string(),
@@ -1,27 +1,47 @@
 pub mod activity;
 pub mod app;
+pub mod breadcrumbs;
 pub mod buffer;
 pub mod code_action;
 pub mod command;
+pub mod command_history;
 pub mod completion;
 pub mod config;
 pub mod container;
 mod data;
 pub mod db;
 pub mod editor;
+pub mod emmet;
 pub mod explorer;
 pub mod find;
+pub mod focus;
+pub mod format;
+pub mod gutter;
+#[cfg(test)]
+mod harness;
+pub mod history;
+pub mod indexing;
 pub mod keypress;
 pub mod language;
+pub mod linked_tag;
+pub mod location;
+pub mod logging;
 pub mod lsp;
 pub mod menu;
+pub mod mock_proxy;
 pub mod movement;
+pub mod narrow;
 pub mod outline;
 pub mod palette;
 pub mod panel;
 pub mod plugin;
 pub mod problem;
+pub mod profile;
 pub mod proxy;
+pub mod recovery;
+pub mod replace;
+pub mod sanitize;
+pub mod scratch;
 pub mod scroll;
 pub mod search;
 pub mod signature;
@@ -33,5 +53,9 @@ pub mod svg;
 mod tab;
 pub mod terminal;
 pub mod theme;
+pub mod todo;
 pub mod title;
+pub mod undo_history;
 pub mod window;
+pub mod word_index;
+pub mod workspace_edit;
@@ -0,0 +1,209 @@
+use tree_sitter::{Node, Tree};
+
+/// The extensions of the markup files, whose tags are paired by scanning
+/// the text, there's no syntax tree for them.
+pub const MARKUP_EXTENSIONS: &[&str] =
+    &["html", "htm", "xhtml", "xml", "svg", "vue", "svelte"];
+
+/// Whether the text can be part of a tag name.
+pub fn is_tag_name(text: &str) -> bool {
+    text.chars().all(is_name_char)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || "-_:.".contains(c)
+}
+
+/// The names of the opening and the closing tag of the JSX element whose
+/// tag name has the offset, as (start, end) offsets.
+pub fn jsx_tag_names(tree: &Tree, offset: usize) -> Option<[(usize, usize); 2]> {
+    let mut node = tree.root_node().descendant_for_byte_range(offset, offset)?;
+    while !matches!(node.kind(), "jsx_opening_element" | "jsx_closing_element") {
+        if node.kind() == "jsx_element" {
+            return None;
+        }
+        node = node.parent()?;
+    }
+    let element = node.parent()?;
+    let name = |field: &str| -> Option<(usize, usize)> {
+        let name: Node = element
+            .child_by_field_name(field)?
+            .child_by_field_name("name")?;
+        Some((name.start_byte(), name.end_byte()))
+    };
+    let names = [name("open_tag")?, name("close_tag")?];
+    if names
+        .iter()
+        .any(|(start, end)| *start <= offset && offset <= *end)
+    {
+        Some(names)
+    } else {
+        None
+    }
+}
+
+/// The names of the opening and the closing tag of the markup element
+/// whose tag name has the offset, as (start, end) offsets. The tags in
+/// comments and the `<` in attribute values don't count.
+pub fn markup_tag_names(text: &str, offset: usize) -> Option<[(usize, usize); 2]> {
+    let tags = tags(text);
+    let index = tags
+        .iter()
+        .position(|tag| tag.name.0 <= offset && offset <= tag.name.1)?;
+    let tag = &tags[index];
+    let name = &text[tag.name.0..tag.name.1];
+    if tag.self_closing || name.is_empty() {
+        return None;
+    }
+    let same_name = |other: &&Tag| &text[other.name.0..other.name.1] == name;
+
+    let mut depth = 0;
+    let pair = if tag.closing {
+        tags[..index].iter().rev().filter(same_name).find(|other| {
+            if other.self_closing {
+                return false;
+            }
+            if other.closing {
+                depth += 1;
+                return false;
+            }
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+            false
+        })?
+    } else {
+        tags[index + 1..].iter().filter(same_name).find(|other| {
+            if other.self_closing {
+                return false;
+            }
+            if !other.closing {
+                depth += 1;
+                return false;
+            }
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+            false
+        })?
+    };
+    if tag.closing {
+        Some([pair.name, tag.name])
+    } else {
+        Some([tag.name, pair.name])
+    }
+}
+
+struct Tag {
+    name: (usize, usize),
+    closing: bool,
+    self_closing: bool,
+}
+
+/// The tags of the markup, skipping the comments, the declarations, and the
+/// content of scripts and styles.
+fn tags(text: &str) -> Vec<Tag> {
+    let bytes = text.as_bytes();
+    let find = |from: usize, pattern: &str| {
+        text[from..]
+            .find(pattern)
+            .map(|i| from + i + pattern.len())
+            .unwrap_or(text.len())
+    };
+    let mut tags = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'<' {
+            i += 1;
+            continue;
+        }
+        let rest = &text[i..];
+        if rest.starts_with("<!--") {
+            i = find(i, "-->");
+            continue;
+        }
+        if rest.starts_with("<![CDATA[") {
+            i = find(i, "]]>");
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            i = find(i, ">");
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let start = if closing { i + 2 } else { i + 1 };
+        let end = text[start..]
+            .find(|c: char| !is_name_char(c))
+            .map(|k| start + k)
+            .unwrap_or(text.len());
+        if end == start && !closing {
+            i += 1;
+            continue;
+        }
+
+        // the rest of the tag, where quoted values may have a `>`
+        let mut j = end;
+        let mut quote = None;
+        while j < bytes.len() {
+            match (quote, bytes[j]) {
+                (None, b'"') | (None, b'\'') => quote = Some(bytes[j]),
+                (Some(q), c) if q == c => quote = None,
+                (None, b'>') => break,
+                _ => {}
+            }
+            j += 1;
+        }
+        let self_closing = !closing && j > end && bytes[j - 1] == b'/';
+        tags.push(Tag {
+            name: (start, end),
+            closing,
+            self_closing,
+        });
+        i = j + 1;
+
+        let name = text[start..end].to_lowercase();
+        if !closing && !self_closing && (name == "script" || name == "style") {
+            i = text[i.min(text.len())..]
+                .to_lowercase()
+                .find(&format!("</{}", name))
+                .map(|k| i + k)
+                .unwrap_or(text.len());
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(text: &str, offset: usize) -> Option<[&str; 2]> {
+        markup_tag_names(text, offset)
+            .map(|[a, b]| [&text[a.0..a.1], &text[b.0..b.1]])
+    }
+
+    #[test]
+    fn test_markup_tag_names() {
+        let text = "<div a=\"x>y\"><div></div><!-- <div> --><br/></div>";
+        let [open, close] = markup_tag_names(text, 2).unwrap();
+        assert_eq!(open, (1, 4));
+        assert_eq!(close, (text.len() - 4, text.len() - 1));
+        // from the closing tag, over the nested one
+        assert_eq!(markup_tag_names(text, text.len() - 2).unwrap()[0], (1, 4));
+        assert_eq!(names(text, 15), Some(["div", "div"]));
+        assert_eq!(markup_tag_names(text, 15).unwrap()[1], (20, 23));
+        // in the comment, the self closing tag and an attribute
+        assert_eq!(names(text, 32), None);
+        assert_eq!(names(text, 41), None);
+        assert_eq!(names(text, 7), None);
+    }
+
+    #[test]
+    fn test_markup_script() {
+        let text = "<p><script>if (a <p) {}</script></p>";
+        assert_eq!(markup_tag_names(text, 1).unwrap()[1], (34, 35));
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use lsp_types::Position;
+
+use crate::editor::EditorLocationNew;
+
+/// Splits the `:line` or `:line:column` at the end of `input`, like
+/// `src/main.rs:120:5`, off its path. They count from 1 as compilers print
+/// them, and a trailing `:` without a number is left out.
+pub fn split_position(input: &str) -> (&str, Option<Position>) {
+    let input = input.trim_end_matches(':');
+    let number = |s: &str| {
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            s.parse::<u32>().ok()
+        } else {
+            None
+        }
+    };
+    let position = |line: u32, col: u32| {
+        Position::new(line.saturating_sub(1), col.saturating_sub(1))
+    };
+
+    // a drive letter, as in `C:\foo.rs`, is never followed by a number
+    let (rest, last) = match input.rsplit_once(':') {
+        Some((rest, last)) => match number(last) {
+            Some(last) => (rest, last),
+            None => return (input, None),
+        },
+        None => return (input, None),
+    };
+    if let Some((path, line)) = rest.rsplit_once(':') {
+        if let Some(line) = number(line) {
+            return (path, Some(position(line, last)));
+        }
+    }
+    (rest, Some(position(last, 1)))
+}
+
+/// The location of a file given on the command line, relative to `cwd`,
+/// with the position at its end if any.
+pub fn location_of_arg(arg: &str, cwd: &Path) -> Option<EditorLocationNew> {
+    let (path, position) = split_position(arg);
+    if path.is_empty() {
+        return None;
+    }
+    let path = cwd.join(PathBuf::from(path));
+    if path.is_dir() {
+        return None;
+    }
+    Some(EditorLocationNew {
+        path,
+        position,
+        scroll_offset: None,
+        hisotry: None,
+        selection_end: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_position() {
+        assert_eq!(
+            split_position("src/main.rs:120:5"),
+            ("src/main.rs", Some(Position::new(119, 4)))
+        );
+        assert_eq!(
+            split_position("src/main.rs:120"),
+            ("src/main.rs", Some(Position::new(119, 0)))
+        );
+        assert_eq!(split_position("src/main.rs"), ("src/main.rs", None));
+        assert_eq!(split_position("src/main.rs:"), ("src/main.rs", None));
+        assert_eq!(
+            split_position("src/main.rs:7:"),
+            ("src/main.rs", Some(Position::new(6, 0)))
+        );
+        assert_eq!(split_position("main.rs:a"), ("main.rs:a", None));
+    }
+
+    #[test]
+    fn test_split_position_of_drive_letters() {
+        assert_eq!(split_position("C:\\foo.rs"), ("C:\\foo.rs", None));
+        assert_eq!(
+            split_position("C:\\foo.rs:10"),
+            ("C:\\foo.rs", Some(Position::new(9, 0)))
+        );
+        assert_eq!(
+            split_position("C:\\foo.rs:10:2"),
+            ("C:\\foo.rs", Some(Position::new(9, 1)))
+        );
+    }
+}
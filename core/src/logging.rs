@@ -0,0 +1,312 @@
+use std::{
+    backtrace::Backtrace,
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    panic,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use directories::ProjectDirs;
+use druid::{ExtEventSink, Target};
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::Mutex;
+
+use crate::{
+    command::{LapceUICommand, LAPCE_UI_COMMAND},
+    config::LogConfig,
+};
+
+/// The log file is rotated once it grows past this size.
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// How many rotated files are kept next to the current one.
+const ROTATED_FILES: usize = 3;
+/// How many lines are kept in memory for the log view and issue reports.
+const RECENT_LINES: usize = 5000;
+
+lazy_static! {
+    static ref LOGGER: LapceLogger = LapceLogger::new();
+}
+
+#[derive(Clone, Debug)]
+struct LogLine {
+    level: Level,
+    text: String,
+}
+
+#[derive(Clone, Debug)]
+struct LogLevels {
+    default: LevelFilter,
+    modules: HashMap<String, LevelFilter>,
+}
+
+impl LogLevels {
+    /// The level of the most specific configured module the target is in.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module.as_str()
+                    || target.starts_with(&format!("{}::", module))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .values()
+            .copied()
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+struct LapceLogger {
+    levels: Mutex<LogLevels>,
+    file: Mutex<Option<File>>,
+    recent: Mutex<VecDeque<LogLine>>,
+    event_sink: Mutex<Option<ExtEventSink>>,
+    /// Whether the UI was told about lines it hasn't fetched yet, so that a
+    /// burst of lines only sends one command.
+    notified: AtomicBool,
+}
+
+impl LapceLogger {
+    fn new() -> Self {
+        Self {
+            levels: Mutex::new(LogLevels {
+                default: LevelFilter::Info,
+                modules: HashMap::new(),
+            }),
+            file: Mutex::new(None),
+            recent: Mutex::new(VecDeque::new()),
+            event_sink: Mutex::new(None),
+            notified: AtomicBool::new(false),
+        }
+    }
+
+    fn write(&self, text: &str) {
+        let mut file = self.file.lock();
+        let full = file
+            .as_ref()
+            .and_then(|f| f.metadata().ok())
+            .map(|m| m.len() > MAX_FILE_SIZE)
+            .unwrap_or(false);
+        if full {
+            *file = None;
+            rotate_log_files();
+        }
+        if file.is_none() {
+            *file = open_log_file();
+        }
+        if let Some(file) = file.as_mut() {
+            let _ = writeln!(file, "{}", text);
+        }
+    }
+}
+
+impl Log for LapceLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.lock().level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let text = format!(
+            "{} {:<5} {}: {}",
+            timestamp(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        self.write(&text);
+        {
+            let mut recent = self.recent.lock();
+            if recent.len() >= RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(LogLine {
+                level: record.level(),
+                text,
+            });
+        }
+
+        // cloned out of the lock, in case submitting logs something itself
+        let event_sink = self.event_sink.lock().clone();
+        if let Some(event_sink) = event_sink {
+            if !self.notified.swap(true, Ordering::SeqCst) {
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::UpdateLogView,
+                    Target::Auto,
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Installs the logger and the panic hook, then applies the config. Only the
+/// first call installs them.
+pub fn init(config: &LogConfig) {
+    if log::set_logger(&*LOGGER).is_ok() {
+        install_panic_hook();
+    }
+    configure(config);
+}
+
+/// Applies the levels from the config, replacing the ones set at runtime.
+pub fn configure(config: &LogConfig) {
+    let mut levels = LOGGER.levels.lock();
+    levels.default = config.level.parse().unwrap_or(LevelFilter::Info);
+    levels.modules = config
+        .modules
+        .iter()
+        .filter_map(|(module, level)| Some((module.clone(), level.parse().ok()?)))
+        .collect();
+    log::set_max_level(levels.max());
+}
+
+/// Changes a level at runtime, given as `<level>` for the default one or as
+/// `<module>=<level>`.
+pub fn set_level(spec: &str) -> Result<()> {
+    let (module, level) = match spec.split_once('=') {
+        Some((module, level)) => (Some(module.trim()), level.trim()),
+        None => (None, spec.trim()),
+    };
+    let level: LevelFilter = level
+        .parse()
+        .map_err(|_| anyhow!("unknown log level {}", level))?;
+    let mut levels = LOGGER.levels.lock();
+    match module {
+        Some(module) => {
+            levels.modules.insert(module.to_string(), level);
+        }
+        None => levels.default = level,
+    }
+    log::set_max_level(levels.max());
+    Ok(())
+}
+
+pub fn level() -> LevelFilter {
+    LOGGER.levels.lock().default
+}
+
+/// The next more verbose level, wrapping around to errors only after trace.
+pub fn next_level(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::Off | LevelFilter::Trace => LevelFilter::Error,
+        LevelFilter::Error => LevelFilter::Warn,
+        LevelFilter::Warn => LevelFilter::Info,
+        LevelFilter::Info => LevelFilter::Debug,
+        LevelFilter::Debug => LevelFilter::Trace,
+    }
+}
+
+/// Where new lines are announced with `UpdateLogView`.
+pub fn set_event_sink(event_sink: ExtEventSink) {
+    *LOGGER.event_sink.lock() = Some(event_sink);
+}
+
+/// The last `count` lines at the level or above. Fetching them re-arms the
+/// `UpdateLogView` notification.
+pub fn recent_lines(level: LevelFilter, count: usize) -> Vec<String> {
+    LOGGER.notified.store(false, Ordering::SeqCst);
+    let recent = LOGGER.recent.lock();
+    let mut lines: Vec<String> = recent
+        .iter()
+        .rev()
+        .filter(|line| line.level <= level)
+        .take(count)
+        .map(|line| line.text.clone())
+        .collect();
+    lines.reverse();
+    lines
+}
+
+pub fn log_file() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "Lapce")
+        .map(|d| d.data_local_dir().join("logs").join("lapce.log"))
+}
+
+fn open_log_file() -> Option<File> {
+    let path = log_file()?;
+    fs::create_dir_all(path.parent()?).ok()?;
+    OpenOptions::new().create(true).append(true).open(path).ok()
+}
+
+fn rotate_log_files() {
+    let path = match log_file() {
+        Some(path) => path,
+        None => return,
+    };
+    let rotated = |i: usize| path.with_extension(format!("log.{}", i));
+    for i in (1..ROTATED_FILES).rev() {
+        let _ = fs::rename(rotated(i), rotated(i + 1));
+    }
+    let _ = fs::rename(&path, rotated(1));
+}
+
+/// Logs panics with their backtrace before the default hook runs, so that
+/// they end up in the log file even when the app goes down.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        log::error!(
+            target: "panic",
+            "thread '{}' {}\n{}",
+            thread.name().unwrap_or("<unnamed>"),
+            info,
+            Backtrace::force_capture()
+        );
+        log::logger().flush();
+        default_hook(info);
+    }));
+}
+
+/// The current UTC time, precise to the millisecond.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        now.subsec_millis()
+    )
+}
+
+/// The date of a number of days since the unix epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
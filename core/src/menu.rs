@@ -15,6 +15,7 @@ use crate::{
     config::LapceTheme,
     data::{LapceTabData, LapceWindowData},
     keypress::KeyPressFocus,
+    movement::Movement,
     state::Mode,
 };
 
@@ -54,6 +55,36 @@ impl KeyPressFocus for MenuData {
         env: &Env,
     ) -> CommandExecuted {
         match command {
+            LapceCommand::Down | LapceCommand::ListNext => {
+                self.active = Movement::Down.update_index(
+                    self.active,
+                    self.items.len(),
+                    1,
+                    true,
+                );
+            }
+            LapceCommand::Up | LapceCommand::ListPrevious => {
+                self.active = Movement::Up.update_index(
+                    self.active,
+                    self.items.len(),
+                    1,
+                    true,
+                );
+            }
+            LapceCommand::ListSelect => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunMenuItem(self.active),
+                    Target::Widget(self.widget_id),
+                ));
+            }
+            LapceCommand::MenuCancel => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CancelMenu,
+                    Target::Widget(self.widget_id),
+                ));
+            }
             _ => return CommandExecuted::No,
         }
         CommandExecuted::Yes
@@ -113,6 +144,12 @@ impl Menu {
         ctx.set_handled();
         let line_height = data.config.editor.line_height as f64;
         let n = (mouse_event.pos.y / line_height).floor() as usize;
+        self.run_item(ctx, data, n);
+    }
+
+    /// Runs the command of the item `n` in the active tab, which takes the
+    /// focus back and so hides the menu.
+    fn run_item(&self, ctx: &mut EventCtx, data: &LapceWindowData, n: usize) {
         if let Some(item) = data.menu.items.get(n) {
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
@@ -151,12 +188,38 @@ impl Widget<LapceWindowData> for Menu {
                     self.mouse_down(ctx, mouse_event, data);
                 }
             }
+            Event::KeyDown(key_event) => {
+                if data.menu.shown {
+                    let mut keypress = data.keypress.clone();
+                    Arc::make_mut(&mut keypress).key_down(
+                        ctx,
+                        key_event,
+                        Arc::make_mut(&mut data.menu),
+                        env,
+                    );
+                    data.keypress = keypress;
+                    ctx.set_handled();
+                }
+            }
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
                 match command {
                     LapceUICommand::Focus => {
                         self.request_focus(ctx);
                     }
+                    LapceUICommand::RunMenuItem(n) => {
+                        self.run_item(ctx, data, *n);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::CancelMenu => {
+                        // the menu hides once it loses the focus
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::Focus,
+                            Target::Widget(data.active_id),
+                        ));
+                        ctx.set_handled();
+                    }
                     _ => (),
                 }
             }
@@ -196,11 +259,11 @@ impl Widget<LapceWindowData> for Menu {
             ctx.request_layout();
         }
 
-        if !old_data.menu.shown != data.menu.shown {
+        if old_data.menu.shown != data.menu.shown {
             ctx.request_paint();
         }
 
-        if !old_data.menu.active != data.menu.active {
+        if old_data.menu.active != data.menu.active {
             ctx.request_paint();
         }
     }
@@ -0,0 +1,550 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::unbounded;
+use lapce_proxy::buffer::{Buffer, BufferId, SaveConflict};
+use lapce_proxy::dispatch::{
+    FileNodeItem, NewBufferResponse, Notification, Request,
+};
+use lapce_proxy::editorconfig::Properties;
+use lapce_proxy::format::run_format_command;
+use lapce_proxy::save::{replace_content, write_file};
+use lapce_rpc::Callback;
+use parking_lot::Mutex;
+use regex::RegexBuilder;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use xi_rope::RopeDelta;
+
+use crate::{proxy::ProxyBackend, search::find_context};
+
+/// An in-memory stand-in for the proxy, for tests and for running without
+/// one. Files are read from `root`, and any request can be given scripted
+/// responses, made to fail or delayed. Buffers are the ones of the proxy, so
+/// their revisions and saves behave the same.
+///
+/// Requests and notifications are decoded into the `Request` and
+/// `Notification` of the proxy, and one the proxy couldn't decode panics, so
+/// a test sending a wrong method or params fails.
+pub struct MockProxy {
+    root: PathBuf,
+    buffers: Mutex<HashMap<BufferId, Buffer>>,
+    latency: Mutex<Duration>,
+    scripted: Mutex<HashMap<String, VecDeque<Result<Value, Value>>>>,
+    failing: Mutex<HashMap<String, String>>,
+    sent: Mutex<Vec<(String, Value)>>,
+}
+
+impl MockProxy {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            buffers: Mutex::new(HashMap::new()),
+            latency: Mutex::new(Duration::ZERO),
+            scripted: Mutex::new(HashMap::new()),
+            failing: Mutex::new(HashMap::new()),
+            sent: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Delays every response by `latency`. Without latency the callbacks run
+    /// before the request returns.
+    pub fn set_latency(&self, latency: Duration) {
+        *self.latency.lock() = latency;
+    }
+
+    /// Queues the response to the next request of `method`, ahead of what
+    /// the mock would answer on its own.
+    pub fn script(&self, method: &str, result: Result<Value, Value>) {
+        self.scripted
+            .lock()
+            .entry(method.to_string())
+            .or_default()
+            .push_back(result);
+    }
+
+    /// Fails every request of `method` with `message` until `recover`.
+    pub fn fail(&self, method: &str, message: &str) {
+        self.failing
+            .lock()
+            .insert(method.to_string(), message.to_string());
+    }
+
+    pub fn recover(&self, method: &str) {
+        self.failing.lock().remove(method);
+    }
+
+    /// Reloads the buffer from disk, as the proxies before 1.15 did when the
+    /// file changed while it had no unsaved edits.
+    pub fn reload_buffer(&self, buffer_id: usize) {
+        if let Some(buffer) = self.buffers.lock().get_mut(&BufferId(buffer_id)) {
+            buffer.reload();
+        }
+    }
+
+    /// The requests and notifications sent so far, as (method, params).
+    pub fn sent(&self) -> Vec<(String, Value)> {
+        self.sent.lock().clone()
+    }
+
+    fn response(&self, method: &str, request: Request) -> Result<Value, Value> {
+        if let Some(message) = self.failing.lock().get(method) {
+            return Err(error(message));
+        }
+        if let Some(result) = self
+            .scripted
+            .lock()
+            .get_mut(method)
+            .and_then(|queue| queue.pop_front())
+        {
+            return result;
+        }
+        match request {
+            Request::NewBuffer { buffer_id, path } => {
+                let content =
+                    fs::read_to_string(&path).map_err(|e| error(&e.to_string()))?;
+                let editorconfig = Properties::resolve(&path);
+                let buffer = Buffer::new(buffer_id, path, unbounded().0);
+                self.buffers.lock().insert(buffer_id, buffer);
+                Ok(json!(NewBufferResponse {
+                    content,
+                    editorconfig,
+                }))
+            }
+            Request::ReadDir { path } => {
+                let items: Vec<FileNodeItem> = fs::read_dir(&path)
+                    .map_err(|e| error(&e.to_string()))?
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| FileNodeItem {
+                        path_buf: entry.path(),
+                        is_dir: entry.path().is_dir(),
+                        read: false,
+                        open: false,
+                        children: HashMap::new(),
+                        children_open_count: 0,
+                        excluded: false,
+                    })
+                    .collect();
+                Ok(json!(items))
+            }
+            Request::GetFileSize { path } => {
+                let metadata =
+                    fs::metadata(&path).map_err(|e| error(&e.to_string()))?;
+                Ok(json!(metadata.len()))
+            }
+            Request::GetFiles { .. } => Ok(json!(files(&self.root))),
+            Request::ReadFile { path } => {
+                let content =
+                    fs::read_to_string(&path).map_err(|e| error(&e.to_string()))?;
+                Ok(json!(content))
+            }
+            Request::WriteTempFile { content, extension } => {
+                let path = std::env::temp_dir().join(format!(
+                    "lapce-mock-{}.{}",
+                    Uuid::new_v4(),
+                    extension
+                ));
+                fs::write(&path, content).map_err(|e| error(&e.to_string()))?;
+                Ok(json!(path))
+            }
+            Request::ReplaceContent { path, old, new } => {
+                replace_content(&path, &old, &new)
+                    .map_err(|e| error(&e.to_string()))?;
+                Ok(json!({}))
+            }
+            Request::WriteFile { path, content } => {
+                write_file(&path, &content).map_err(|e| error(&e.to_string()))?;
+                Ok(json!({}))
+            }
+            Request::FormatWithCommand {
+                format,
+                path,
+                content,
+            } => {
+                let output =
+                    run_format_command(&format, &path, &content, Some(&self.root))
+                        .map_err(|e| error(&e.to_string()))?;
+                Ok(json!({ "output": output }))
+            }
+            Request::GlobalSearch {
+                pattern,
+                case_sensitive,
+                whole_word,
+                regex,
+                context,
+                ..
+            } => self.global_search(
+                &pattern,
+                case_sensitive,
+                whole_word,
+                regex,
+                context,
+            ),
+            Request::Save { rev, buffer_id } => self.save(buffer_id, rev),
+            Request::SyncBuffer {
+                buffer_id,
+                rev,
+                content,
+            } => {
+                let mut buffers = self.buffers.lock();
+                let buffer = buffers
+                    .get_mut(&buffer_id)
+                    .ok_or_else(|| error("no such buffer"))?;
+                buffer.sync(rev, &content);
+                Ok(json!({}))
+            }
+            _ => Err(error(&format!(
+                "{} isn't supported by the mock proxy",
+                method
+            ))),
+        }
+    }
+
+    fn save(&self, buffer_id: BufferId, rev: u64) -> Result<Value, Value> {
+        let mut buffers = self.buffers.lock();
+        let buffer = match buffers.get_mut(&buffer_id) {
+            Some(buffer) => buffer,
+            None => return Ok(json!({})),
+        };
+        match buffer.save(rev) {
+            Ok(()) => Ok(json!({})),
+            Err(e) => match e.downcast_ref::<SaveConflict>() {
+                Some(conflict) => Err(conflict.to_error(buffer.disk_content())),
+                None => Err(error(&e.to_string())),
+            },
+        }
+    }
+
+    fn update(&self, buffer_id: BufferId, delta: &RopeDelta, rev: u64) {
+        if let Some(buffer) = self.buffers.lock().get_mut(&buffer_id) {
+            buffer.update(delta, rev);
+        }
+    }
+
+    fn global_search(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        whole_word: bool,
+        is_regex: bool,
+        context: usize,
+    ) -> Result<Value, Value> {
+        let pattern = if is_regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        let pattern = if whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|e| error(&format!("Invalid regex: {}", e)))?;
+
+        let mut matches = HashMap::new();
+        let mut contexts = HashMap::new();
+        for path in files(&self.root) {
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let line_matches: Vec<(usize, (usize, usize), String)> = content
+                .lines()
+                .enumerate()
+                .filter_map(|(i, line)| {
+                    let m = regex.find(line)?;
+                    Some((i + 1, (m.start(), m.end()), format!("{}\n", line)))
+                })
+                .collect();
+            if !line_matches.is_empty() {
+                if context > 0 {
+                    contexts.insert(
+                        path.clone(),
+                        find_context(&content, &line_matches, context),
+                    );
+                }
+                matches.insert(path, line_matches);
+            }
+        }
+        if context > 0 {
+            return Ok(json!({ "matches": matches, "context": contexts }));
+        }
+        Ok(json!(matches))
+    }
+}
+
+impl ProxyBackend for MockProxy {
+    fn send_request(&self, method: &str, params: &Value, f: Box<dyn Callback>) {
+        self.sent.lock().push((method.to_string(), params.clone()));
+        let request: Request = decode(method, params);
+        let result = self.response(method, request);
+        let latency = *self.latency.lock();
+        if latency.is_zero() {
+            f.call(result);
+        } else {
+            thread::spawn(move || {
+                thread::sleep(latency);
+                f.call(result);
+            });
+        }
+    }
+
+    fn send_notification(&self, method: &str, params: &Value) {
+        self.sent.lock().push((method.to_string(), params.clone()));
+        if let Notification::Update {
+            buffer_id,
+            delta,
+            rev,
+        } = decode(method, params)
+        {
+            self.update(buffer_id, &delta, rev);
+        }
+    }
+}
+
+/// The request or notification the proxy would decode from the method and
+/// params it's sent.
+fn decode<T: serde::de::DeserializeOwned>(method: &str, params: &Value) -> T {
+    serde_json::from_value(json!({ "method": method, "params": params }))
+        .unwrap_or_else(|e| {
+            panic!("the proxy can't decode {} with {}: {}", method, params, e)
+        })
+}
+
+/// An error shaped like the ones the proxy responds with.
+fn error(message: &str) -> Value {
+    json!({
+        "code": 0,
+        "message": message,
+    })
+}
+
+/// The files under `dir`, recursively.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(self::files(&path));
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// The mock over a temporary folder with an `a.txt`, which is removed
+    /// when the `TempDir` is dropped.
+    fn mock() -> (TempDir, MockProxy) {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "hello\nHello world\n").unwrap();
+        let mock = MockProxy::new(root.path().to_path_buf());
+        (root, mock)
+    }
+
+    fn request(
+        mock: &MockProxy,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Value> {
+        let result = Arc::new(Mutex::new(None));
+        let local_result = result.clone();
+        mock.send_request(
+            method,
+            &params,
+            Box::new(move |r| {
+                *local_result.lock() = Some(r);
+            }),
+        );
+        let result = result.lock().take().unwrap();
+        result
+    }
+
+    #[test]
+    fn test_new_buffer_reads_file() {
+        let (_root, mock) = mock();
+        let path = mock.root().join("a.txt");
+        let result =
+            request(&mock, "new_buffer", json!({ "buffer_id": 1, "path": path }));
+        assert_eq!(result.unwrap()["content"], "hello\nHello world\n");
+    }
+
+    #[test]
+    fn test_global_search() {
+        let (_root, mock) = mock();
+        let result = request(
+            &mock,
+            "global_search",
+            json!({ "pattern": "hello", "case_sensitive": true }),
+        )
+        .unwrap();
+        let matches: HashMap<PathBuf, Vec<(usize, (usize, usize), String)>> =
+            serde_json::from_value(result).unwrap();
+        let a = &matches[&mock.root().join("a.txt")];
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].0, 1);
+        assert_eq!(a[0].1, (0, 5));
+    }
+
+    #[test]
+    fn test_global_search_with_context() {
+        let (_root, mock) = mock();
+        let result = request(
+            &mock,
+            "global_search",
+            json!({ "pattern": "hello", "case_sensitive": true, "context": 1 }),
+        )
+        .unwrap();
+        let (matches, context) = crate::search::search_results(result, 1).unwrap();
+        let path = mock.root().join("a.txt");
+        assert_eq!(matches[&path].len(), 1);
+        assert_eq!(context[&path], vec![(2, "Hello world\n".to_string())]);
+    }
+
+    #[test]
+    fn test_scripted_and_failing_requests() {
+        let (_root, mock) = mock();
+        mock.script("get_files", Ok(json!(["x"])));
+        let result = request(&mock, "get_files", json!({ "path": "path" }));
+        assert_eq!(result, Ok(json!(["x"])));
+
+        mock.fail("save", "disk full");
+        let result = save(&mock, 1, 0);
+        assert_eq!(result.unwrap_err()["message"], "disk full");
+        mock.recover("save");
+        assert!(save(&mock, 1, 0).is_ok());
+
+        assert_eq!(mock.sent().len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "the proxy can't decode save")]
+    fn test_request_the_proxy_cant_decode() {
+        let (_root, mock) = mock();
+        // the proxy takes the revision as a number
+        let _ = request(&mock, "save", json!({ "buffer_id": 1, "rev": "1" }));
+    }
+
+    #[test]
+    #[should_panic(expected = "the proxy can't decode buffer_save")]
+    fn test_unknown_method() {
+        let (_root, mock) = mock();
+        let _ = request(&mock, "buffer_save", json!({ "buffer_id": 1 }));
+    }
+
+    fn open(mock: &MockProxy, buffer_id: usize) -> PathBuf {
+        let path = mock.root().join("a.txt");
+        request(
+            mock,
+            "new_buffer",
+            json!({ "buffer_id": buffer_id, "path": path }),
+        )
+        .unwrap();
+        path
+    }
+
+    /// Inserts `text` at the start of the buffer, `len` long.
+    fn insert(mock: &MockProxy, buffer_id: usize, text: &str, len: usize, rev: u64) {
+        let delta = xi_rope::Delta::simple_edit(
+            xi_rope::Interval::new(0, 0),
+            xi_rope::Rope::from(text),
+            len,
+        );
+        mock.send_notification(
+            "update",
+            &json!({ "buffer_id": buffer_id, "delta": delta, "rev": rev }),
+        );
+    }
+
+    fn save(mock: &MockProxy, buffer_id: usize, rev: u64) -> Result<Value, Value> {
+        request(mock, "save", json!({ "buffer_id": buffer_id, "rev": rev }))
+    }
+
+    #[test]
+    fn test_save_refuses_other_revision() {
+        let (_root, mock) = mock();
+        let path = open(&mock, 1);
+        insert(&mock, 1, "> ", 18, 1);
+        let error = save(&mock, 1, 0).unwrap_err();
+        assert_eq!(
+            SaveConflict::from_error(&error).map(|(c, _)| c),
+            Some(SaveConflict::StaleRevision)
+        );
+        assert!(save(&mock, 1, 1).is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "> hello\nHello world\n");
+    }
+
+    #[test]
+    fn test_save_after_edit_refused_for_reload() {
+        let (_root, mock) = mock();
+        let path = open(&mock, 1);
+        fs::write(&path, "theirs\n").unwrap();
+        // the file watcher reloads the buffer while an edit is on its way
+        mock.reload_buffer(1);
+        insert(&mock, 1, "mine ", 18, 1);
+        let error = save(&mock, 1, 1).unwrap_err();
+        let (conflict, disk) = SaveConflict::from_error(&error).unwrap();
+        assert_eq!(conflict, SaveConflict::StaleRevision);
+        assert_eq!(disk.as_deref(), Some("theirs\n"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theirs\n");
+
+        // overwriting gives the proxy the text of the UI first
+        let content = "mine hello\nHello world\n";
+        request(
+            &mock,
+            "sync_buffer",
+            json!({ "buffer_id": 1, "rev": 1, "content": content }),
+        )
+        .unwrap();
+        assert!(save(&mock, 1, 1).is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_save_refuses_file_changed_on_disk() {
+        let (_root, mock) = mock();
+        let path = open(&mock, 1);
+        insert(&mock, 1, "mine ", 18, 1);
+        // changed outside while there were unsaved edits, so not reloaded
+        fs::write(&path, "theirs\n").unwrap();
+        let error = save(&mock, 1, 1).unwrap_err();
+        let (conflict, disk) = SaveConflict::from_error(&error).unwrap();
+        assert_eq!(conflict, SaveConflict::ChangedOnDisk);
+        assert_eq!(disk.as_deref(), Some("theirs\n"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theirs\n");
+
+        // reloading takes the text on disk, after which saves go through
+        request(
+            &mock,
+            "sync_buffer",
+            json!({ "buffer_id": 1, "rev": 2, "content": "theirs\n" }),
+        )
+        .unwrap();
+        insert(&mock, 1, "both ", 7, 3);
+        assert!(save(&mock, 1, 3).is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "both theirs\n");
+    }
+}
@@ -1,6 +1,6 @@
 use druid::{piet::PietText, Env, Point, Rect, Size};
 use serde::{Deserialize, Serialize};
-use xi_rope::{RopeDelta, Transformer};
+use xi_rope::{Rope, RopeDelta, Transformer};
 
 use crate::{
     buffer::BufferNew,
@@ -84,6 +84,21 @@ impl Cursor {
         buffer.line_of_offset(self.offset())
     }
 
+    /// The lines of every cursor, which is more than one with multiple
+    /// cursors. Sorted, without duplicates.
+    pub fn current_lines(&self, buffer: &BufferNew) -> Vec<usize> {
+        let mut lines = match &self.mode {
+            CursorMode::Insert(selection) => selection
+                .regions()
+                .iter()
+                .map(|region| buffer.line_of_offset(region.end()))
+                .collect(),
+            _ => vec![self.current_line(buffer)],
+        };
+        lines.dedup();
+        lines
+    }
+
     pub fn current_char(
         &self,
         text: &mut PietText,
@@ -194,6 +209,25 @@ impl Cursor {
         RegisterData { content, mode }
     }
 
+    /// The text of the selection, none without a selection or with several.
+    pub fn selected_text(&self, buffer: &BufferNew) -> Option<String> {
+        match &self.mode {
+            CursorMode::Normal(_) => None,
+            CursorMode::Visual {
+                mode: VisualMode::Normal,
+                ..
+            } => Some(self.yank(buffer).content),
+            CursorMode::Visual { .. } => None,
+            CursorMode::Insert(selection) => {
+                let regions = selection.regions();
+                if regions.len() != 1 || regions[0].is_caret() {
+                    return None;
+                }
+                Some(self.yank(buffer).content)
+            }
+        }
+    }
+
     pub fn edit_selection(&self, buffer: &BufferNew) -> Selection {
         match &self.mode {
             CursorMode::Insert(selection) => selection.clone(),
@@ -271,6 +305,30 @@ impl Cursor {
         }
         self.horiz = None;
     }
+
+    /// The cursor with each of its offsets moved by `f`.
+    pub fn map_offsets(&self, f: impl Fn(usize) -> usize) -> Cursor {
+        let mode = match &self.mode {
+            CursorMode::Normal(offset) => CursorMode::Normal(f(*offset)),
+            CursorMode::Visual { start, end, mode } => CursorMode::Visual {
+                start: f(*start),
+                end: f(*end),
+                mode: *mode,
+            },
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    new_selection.add_region(SelRegion::new(
+                        f(region.start()),
+                        f(region.end()),
+                        region.horiz().cloned(),
+                    ));
+                }
+                CursorMode::Insert(new_selection)
+            }
+        };
+        Cursor::new(mode, self.horiz.clone())
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
@@ -723,3 +781,107 @@ fn format_index(index: i64, len: usize, recursive: bool) -> usize {
         }
     }
 }
+
+/// Where `line` of `old` is in `new`, the text after `delta`, so a view
+/// showing it at its top can keep doing so when lines are added or removed
+/// above.
+pub fn transform_line(
+    old: &Rope,
+    new: &Rope,
+    delta: &RopeDelta,
+    line: usize,
+) -> usize {
+    if line > old.line_of_offset(old.len()) {
+        return line;
+    }
+    let offset = old.offset_of_line(line);
+    let offset = Transformer::new(delta).transform(offset, false);
+    new.line_of_offset(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::{Delta, Interval};
+
+    use super::*;
+
+    fn edit(len: usize, start: usize, end: usize, text: &str) -> RopeDelta {
+        Delta::simple_edit(Interval::new(start, end), Rope::from(text), len)
+    }
+
+    fn normal(offset: usize, delta: &RopeDelta) -> CursorMode {
+        let mut cursor = Cursor::new(CursorMode::Normal(offset), None);
+        cursor.apply_delta(delta);
+        cursor.mode
+    }
+
+    #[test]
+    fn test_cursor_apply_delta_before() {
+        // "hello world", with the cursor on the "w"
+        assert_eq!(normal(6, &edit(11, 2, 2, "ab")), CursorMode::Normal(8));
+        assert_eq!(normal(6, &edit(11, 0, 2, "")), CursorMode::Normal(4));
+        assert_eq!(normal(6, &edit(11, 0, 5, "hi")), CursorMode::Normal(3));
+    }
+
+    #[test]
+    fn test_cursor_apply_delta_inside() {
+        assert_eq!(normal(6, &edit(11, 6, 6, "ab")), CursorMode::Normal(8));
+        assert_eq!(normal(6, &edit(11, 4, 8, "")), CursorMode::Normal(4));
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(2, 8)), None);
+        cursor.apply_delta(&edit(11, 4, 4, "ab"));
+        assert_eq!(cursor.mode, CursorMode::Insert(Selection::region(2, 10)));
+        cursor.apply_delta(&edit(13, 4, 6, ""));
+        assert_eq!(cursor.mode, CursorMode::Insert(Selection::region(2, 8)));
+    }
+
+    #[test]
+    fn test_cursor_apply_delta_after() {
+        assert_eq!(normal(6, &edit(11, 8, 8, "ab")), CursorMode::Normal(6));
+        assert_eq!(normal(6, &edit(11, 8, 11, "")), CursorMode::Normal(6));
+
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 2,
+                end: 8,
+                mode: VisualMode::Normal,
+            },
+            None,
+        );
+        cursor.apply_delta(&edit(11, 9, 9, "ab"));
+        assert_eq!(
+            cursor.mode,
+            CursorMode::Visual {
+                start: 2,
+                end: 8,
+                mode: VisualMode::Normal,
+            }
+        );
+        cursor.apply_delta(&edit(13, 0, 3, ""));
+        assert_eq!(
+            cursor.mode,
+            CursorMode::Visual {
+                start: 0,
+                end: 5,
+                mode: VisualMode::Normal,
+            }
+        );
+    }
+
+    #[test]
+    fn test_transform_line() {
+        let old = Rope::from("a\nb\nc\nd\n");
+        let check = |start: usize, end: usize, text: &str| {
+            let delta = edit(old.len(), start, end, text);
+            let new = delta.apply(&old);
+            transform_line(&old, &new, &delta, 2)
+        };
+        assert_eq!(check(0, 0, "x\ny\n"), 4);
+        assert_eq!(check(0, 2, ""), 1);
+        assert_eq!(check(6, 6, "x\n"), 2);
+        // an edit at the start of the line shows at the top
+        assert_eq!(check(4, 4, "x\n"), 2);
+        assert_eq!(check(0, 0, ""), 2);
+    }
+}
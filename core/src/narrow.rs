@@ -0,0 +1,177 @@
+use std::{ops::Range, path::PathBuf, sync::Arc};
+
+use xi_rope::{spans::Spans, Rope};
+
+use crate::buffer::{changed_region, BufferNew, Style};
+
+/// The region of a file an editor is narrowed to. The editor shows a local
+/// buffer with the text of the region, and its edits are made to the file as
+/// well, so the language servers only ever see the whole file.
+#[derive(Clone, Debug)]
+pub struct NarrowRegion {
+    pub path: PathBuf,
+    /// The offsets of the region in the file.
+    pub start: usize,
+    pub end: usize,
+    /// The text of the file when it was last in sync with the region.
+    pub text: Rope,
+    /// The revision of the file when it was last in sync with the region.
+    pub file_rev: u64,
+    /// The revision of the local buffer when it was last in sync.
+    pub local_rev: u64,
+    /// The styles of the file the local buffer last got its styles from,
+    /// none until it got them.
+    pub styles: Option<Arc<Spans<Style>>>,
+}
+
+impl NarrowRegion {
+    pub fn new(path: PathBuf, start: usize, end: usize, file: &BufferNew) -> Self {
+        Self {
+            path,
+            start,
+            end,
+            text: file.rope.clone(),
+            file_rev: file.rev,
+            local_rev: 0,
+            styles: None,
+        }
+    }
+
+    /// The offset in the local buffer of an offset of the file, kept in
+    /// the region.
+    pub fn to_local(&self, offset: usize) -> usize {
+        offset.max(self.start).min(self.end) - self.start
+    }
+
+    /// The offset in the file of an offset of the local buffer.
+    pub fn to_file(&self, offset: usize) -> usize {
+        self.start + offset.min(self.end - self.start)
+    }
+
+    pub fn contains(&self, range: &Range<usize>) -> bool {
+        self.start <= range.start && range.end <= self.end
+    }
+
+    /// Moves the region along with the change of the file to `text`. A
+    /// change around the edges of the region grows it to take the change in.
+    pub fn follow(&mut self, text: &Rope) {
+        let (old, new) = changed_region(&self.text.to_string(), &text.to_string());
+        // an insertion right at an edge goes in the region
+        let touches = |offset: usize| old.is_empty() && old.start == offset;
+        if old.end <= self.start && !touches(self.start) {
+            self.start = self.start - old.end + new.end;
+            self.end = self.end - old.end + new.end;
+        } else if old.start < self.end || touches(self.end) {
+            self.start = self.start.min(old.start);
+            self.end = if old.end <= self.end {
+                self.end - old.end + new.end
+            } else {
+                new.end
+            };
+        }
+        self.text = text.clone();
+    }
+}
+
+/// The whole lines of `text` from `start` to `end`, without the line ending
+/// of the last one. A selection of whole lines ends at the start of the next
+/// line, which is left out.
+pub fn line_region(text: &Rope, start: usize, end: usize) -> (usize, usize) {
+    let first = text.line_of_offset(start);
+    let mut last = text.line_of_offset(end);
+    if end > start && last > first && text.offset_of_line(last) == end {
+        last -= 1;
+    }
+    let start = text.offset_of_line(first);
+    let mut end = text.offset_of_line(last + 1);
+    let ending = text.slice_to_cow(end.saturating_sub(2).max(start)..end);
+    if ending.ends_with("\r\n") {
+        end -= 2;
+    } else if ending.ends_with('\n') {
+        end -= 1;
+    }
+    (start, end)
+}
+
+/// The range of `old` to replace, and the text to replace it with, to turn
+/// it into `new`.
+pub fn region_edit(old: &str, new: &str) -> Option<(Range<usize>, String)> {
+    if old == new {
+        return None;
+    }
+    let (old_range, new_range) = changed_region(old, new);
+    Some((old_range, new[new_range].to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(text: &str, start: usize, end: usize) -> NarrowRegion {
+        NarrowRegion {
+            path: PathBuf::from("/w/a.rs"),
+            start,
+            end,
+            text: Rope::from(text),
+            file_rev: 0,
+            local_rev: 0,
+            styles: None,
+        }
+    }
+
+    #[test]
+    fn test_line_region() {
+        let text = Rope::from("one\ntwo\nthree\nfour");
+        assert_eq!(line_region(&text, 5, 6), (4, 7));
+        assert_eq!(line_region(&text, 1, 10), (0, 13));
+        // the selection of a whole line
+        assert_eq!(line_region(&text, 4, 8), (4, 7));
+        assert_eq!(line_region(&text, 15, 17), (14, 18));
+
+        let text = Rope::from("one\r\ntwo\r\n");
+        assert_eq!(line_region(&text, 0, 2), (0, 3));
+        assert_eq!(line_region(&text, 5, 10), (5, 8));
+    }
+
+    #[test]
+    fn test_region_edit() {
+        assert_eq!(region_edit("fn a() {}", "fn a() {}"), None);
+        assert_eq!(
+            region_edit("fn a() {}", "fn ab() {}"),
+            Some((4..4, "b".to_string()))
+        );
+        assert_eq!(
+            region_edit("let x = 1;", "let = 1;"),
+            Some((4..6, "".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_follow() {
+        let text = "aaa\nbbb\nccc\n";
+
+        // a change above moves the region
+        let mut narrow = region(text, 4, 7);
+        narrow.follow(&Rope::from("a\nbbb\nccc\n"));
+        assert_eq!((narrow.start, narrow.end), (2, 5));
+        assert_eq!(narrow.to_local(0), 0);
+        assert_eq!(narrow.to_file(3), 5);
+
+        // a change below doesn't
+        let mut narrow = region(text, 4, 7);
+        narrow.follow(&Rope::from("aaa\nbbb\ncccc\n"));
+        assert_eq!((narrow.start, narrow.end), (4, 7));
+
+        // a change in the region grows or shrinks it
+        let mut narrow = region(text, 4, 7);
+        narrow.follow(&Rope::from("aaa\nbXbb\nccc\n"));
+        assert_eq!((narrow.start, narrow.end), (4, 8));
+
+        // a change over its edge takes it in
+        let mut narrow = region(text, 4, 7);
+        narrow.follow(&Rope::from("aaXbb\nccc\n"));
+        assert_eq!((narrow.start, narrow.end), (2, 5));
+        assert!(narrow.contains(&(2..5)));
+        assert!(!narrow.contains(&(1..3)));
+    }
+}
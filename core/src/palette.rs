@@ -9,8 +9,8 @@ use druid::{
     widget::FillStrat,
     widget::IdentityWrapper,
     widget::SvgData,
-    Affine, Command, ExtEventSink, FontFamily, FontWeight, Insets, KeyEvent, Lens,
-    Target, Vec2, WidgetId, WindowId,
+    Affine, Application, Command, ExtEventSink, FontFamily, FontWeight, Insets,
+    KeyEvent, Lens, Target, TimerToken, Vec2, WidgetId, WindowId,
 };
 use druid::{
     piet::{Text, TextLayout as PietTextLayout, TextLayoutBuilder},
@@ -22,46 +22,66 @@ use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use fzyr::{has_match, locate, Score};
 use itertools::Itertools;
+use lapce_proxy::dispatch::FileNodeItem;
+use lapce_proxy::protocol::CAPABILITY_TEMP_FILE;
 use lapce_proxy::terminal::TermId;
+use lapce_proxy::trash::DeletedItem;
 use lsp_types::{DocumentSymbolResponse, Location, Position, Range, SymbolKind};
 use serde_json::{self, json, Value};
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, DirEntry};
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::process::Child;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use std::{cmp::Ordering, mem::size_of_val};
 use strum::{EnumMessage, IntoEnumIterator};
 use usvg;
 use uuid::Uuid;
 
 use crate::{
-    buffer::BufferContent,
+    buffer::{str_col, BufferContent, LocalBufferKind},
     command::LAPCE_COMMAND,
     command::LAPCE_UI_COMMAND,
     command::{CommandExecuted, CommandTarget, LapceCommand, LAPCE_NEW_COMMAND},
-    command::{LapceCommandNew, LapceUICommand},
+    command::{LapceCommandNew, LapceUICommand, LapceWorkbenchCommand},
     config::{Config, LapceTheme},
     data::{
         EditorContent, FocusArea, LapceEditorData, LapceEditorViewData,
         LapceMainSplitData, LapceTabData, PanelKind,
     },
+    db::LapceDb,
     editor::{EditorLocationNew, LapceEditorContainer, LapceEditorView},
     find::Find,
+    history::WorkspaceHistory,
+    indexing::IndexScope,
     keypress::{KeyPressData, KeyPressFocus},
+    location::split_position,
     movement::Movement,
-    proxy::LapceProxy,
+    profile::Profile,
+    proxy::{test_ssh_connection, LapceProxy},
+    recovery::SessionRecovery,
+    scratch::{list_scratches, SCRATCH_LANGUAGES},
     scroll::{LapceIdentityWrapper, LapceScroll, LapceScrollNew},
+    search::SearchOptions,
     state::LapceFocus,
     state::LapceWorkspace,
     state::LapceWorkspaceType,
     state::Mode,
+    state::SshHost,
     svg::{file_svg_new, symbol_svg_new},
-    terminal::TerminalSplitData,
+    terminal::{PasteMode, TerminalSplitData},
     theme::OldLapceTheme,
+    todo::{find_todo_tokens, todo_pattern, TodoBuffer},
 };
 
+/// How long the highlighted item has to stay so to be previewed, so that
+/// holding an arrow key doesn't load every file on the way.
+const PREVIEW_DELAY: Duration = Duration::from_millis(100);
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum PaletteType {
     File,
@@ -72,7 +92,32 @@ pub enum PaletteType {
     Command,
     Reference,
     Theme,
+    /// The recent SSH hosts, then the entry to connect to a new one.
     SshHost,
+    /// Asks for the `user@host:port` to connect to.
+    NewSshHost,
+    Scratch,
+    ScratchLanguage,
+    Todo,
+    IndexScope,
+    Profile,
+    Branch,
+    /// Asks for the name of a branch to create.
+    NewBranch,
+    /// Asks for the name of the active terminal.
+    RenameTerminal,
+    /// The recently deleted files, to restore.
+    Deleted,
+    /// The commands run in this session, the latest first.
+    CommandHistory,
+    /// The files the active editor showed, the latest first.
+    PaneFiles,
+    /// The ways to paste multi-line text into the active terminal.
+    TerminalPaste,
+    /// The tasks of the settings, to run one.
+    Task,
+    /// What to restore of the session that didn't end cleanly.
+    Recovery,
 }
 
 impl PaletteType {
@@ -87,15 +132,32 @@ impl PaletteType {
             PaletteType::Reference => "".to_string(),
             PaletteType::Theme => "".to_string(),
             PaletteType::SshHost => "".to_string(),
+            PaletteType::NewSshHost => "".to_string(),
+            PaletteType::Scratch => "".to_string(),
+            PaletteType::ScratchLanguage => "".to_string(),
+            PaletteType::IndexScope => "".to_string(),
+            PaletteType::Profile => "".to_string(),
+            PaletteType::Branch => "".to_string(),
+            PaletteType::NewBranch => "".to_string(),
+            PaletteType::RenameTerminal => "".to_string(),
+            PaletteType::Deleted => "".to_string(),
+            PaletteType::CommandHistory => "".to_string(),
+            PaletteType::PaneFiles => "".to_string(),
+            PaletteType::TerminalPaste => "".to_string(),
+            PaletteType::Task => "".to_string(),
+            PaletteType::Recovery => "".to_string(),
+            PaletteType::Todo => "".to_string(),
         }
     }
 
     fn has_preview(&self) -> bool {
         match &self {
-            PaletteType::Line
+            PaletteType::File
+            | PaletteType::Line
             | PaletteType::DocumentSymbol
             | PaletteType::GlobalSearch
-            | PaletteType::Reference => true,
+            | PaletteType::Reference
+            | PaletteType::Todo => true,
             _ => false,
         }
     }
@@ -129,7 +191,52 @@ pub enum PaletteItemContent {
     ReferenceLocation(PathBuf, EditorLocationNew),
     Workspace(LapceWorkspace),
     Command(LapceCommandNew),
+    /// A command from the history, with its description and a hint of its
+    /// id, data and when it ran.
+    HistoryCommand {
+        command: LapceCommandNew,
+        text: String,
+        hint: String,
+    },
     Theme(String),
+    Scratch(PathBuf, String),
+    ScratchLanguage(String, String),
+    /// A way to paste the text waiting in the terminal.
+    TerminalPaste(TermId, PasteMode),
+    /// An item of the session to recover by its index, with its
+    /// description and whether it's restored. Picking it toggles it.
+    RecoveryItem(usize, String, bool),
+    /// Restores the items picked if true, else starts fresh.
+    RecoveryAction(bool, String),
+    /// A scope for the file index, with its description.
+    IndexScope(IndexScope, String),
+    /// A top-level folder, and whether it's indexed. Picking it toggles it.
+    IndexFolder(String, bool),
+    /// Applies the profile, replacing the current settings if true, else
+    /// merging into them.
+    ImportProfile(Arc<Profile>, bool, String),
+    ExportProfile(Arc<Profile>, String),
+    /// A line about the profile, like a setting it changes.
+    ProfileNote(String),
+    SshHost(SshHost),
+    NewSshHost,
+    /// A branch, and whether it's the current one.
+    Branch(String, bool),
+    /// A task by its name, with its command.
+    Task(String, String),
+    CreateBranch,
+    /// A deleted item, with its path in the workspace and how long ago it
+    /// was deleted.
+    Deleted(DeletedItem, PathBuf, String),
+    /// A file the editor showed, by its index there, with its path in the
+    /// workspace.
+    ShownFile(WidgetId, usize, PathBuf),
+    Todo {
+        path: PathBuf,
+        location: EditorLocationNew,
+        token: String,
+        text: String,
+    },
 }
 
 impl PaletteItemContent {
@@ -190,6 +297,18 @@ impl PaletteItemContent {
                     Target::Auto,
                 ));
             }
+            PaletteItemContent::Todo { location, .. } => {
+                let editor_id = if preview {
+                    Some(preview_editor_id)
+                } else {
+                    None
+                };
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::JumpToLocation(editor_id, location.clone()),
+                    Target::Auto,
+                ));
+            }
             PaletteItemContent::Workspace(workspace) => {
                 if !preview {
                     ctx.submit_command(Command::new(
@@ -206,7 +325,142 @@ impl PaletteItemContent {
                     Target::Auto,
                 ));
             }
-            PaletteItemContent::Command(command) => {
+            PaletteItemContent::Scratch(path, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::OpenFile(path.clone()),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::ScratchLanguage(_, extension) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::NewScratchBuffer(extension.to_string()),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::TerminalPaste(term_id, mode) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::TerminalPaste(*term_id, *mode),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::IndexScope(scope, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SetIndexScope(scope.clone()),
+                        Target::Auto,
+                    ));
+                }
+            }
+            // toggled by the palette itself, which stays open
+            PaletteItemContent::IndexFolder(_, _) => {}
+            PaletteItemContent::RecoveryItem(_, _, _) => {}
+            // the palette has the recovery to run
+            PaletteItemContent::RecoveryAction(_, _) => {}
+            PaletteItemContent::ImportProfile(profile, replace, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ImportProfile(profile.clone(), *replace),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::ExportProfile(profile, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ExportProfile(profile.clone()),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::ProfileNote(_) => {}
+            PaletteItemContent::Branch(branch, current) => {
+                if !preview && !current {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::CheckoutBranch.to_string(),
+                            palette_desc: None,
+                            data: Some(json!(branch)),
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::Task(name, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::RunTask.to_string(),
+                            palette_desc: None,
+                            data: Some(json!(name)),
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::CreateBranch => {
+                if !preview {
+                    return Some(PaletteType::NewBranch);
+                }
+            }
+            PaletteItemContent::SshHost(host) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SetWorkspace(LapceWorkspace {
+                            kind: LapceWorkspaceType::RemoteSSH(host.clone()),
+                            path: None,
+                            last_open: 0,
+                        }),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::NewSshHost => {
+                if !preview {
+                    return Some(PaletteType::NewSshHost);
+                }
+            }
+            PaletteItemContent::ShownFile(view_id, index, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowShownFile(*view_id, *index),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::Deleted(item, _, _) => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::RestoreDeleted.to_string(),
+                            palette_desc: None,
+                            data: Some(json!({ "id": item.id })),
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::Command(command)
+            | PaletteItemContent::HistoryCommand { command, .. } => {
                 if !preview {
                     ctx.submit_command(Command::new(
                         LAPCE_NEW_COMMAND,
@@ -280,8 +534,8 @@ impl PaletteItemContent {
                 let text = w.path.as_ref().unwrap().to_str().unwrap();
                 let text = match &w.kind {
                     LapceWorkspaceType::Local => text.to_string(),
-                    LapceWorkspaceType::RemoteSSH(user, host) => {
-                        format!("[{}@{}] {}", user, host, text)
+                    LapceWorkspaceType::RemoteSSH(host) => {
+                        format!("[{}] {}", host, text)
                     }
                 };
                 (None, text, indices.to_vec(), "".to_string(), vec![])
@@ -297,6 +551,37 @@ impl PaletteItemContent {
                 "".to_string(),
                 vec![],
             ),
+            PaletteItemContent::HistoryCommand { text, hint, .. } => {
+                let text_indices = indices
+                    .iter()
+                    .filter_map(|i| {
+                        let i = *i;
+                        if i < text.len() {
+                            Some(i)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                let hint_indices = indices
+                    .iter()
+                    .filter_map(|i| {
+                        let i = *i;
+                        if i > text.len() {
+                            Some(i - text.len() - 1)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                (
+                    None,
+                    text.to_string(),
+                    text_indices,
+                    hint.to_string(),
+                    hint_indices,
+                )
+            }
             PaletteItemContent::Theme(theme) => (
                 None,
                 theme.to_string(),
@@ -311,6 +596,182 @@ impl PaletteItemContent {
                 "".to_string(),
                 vec![],
             ),
+            PaletteItemContent::Scratch(path, preview) => {
+                let file_name = path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("")
+                    .to_string();
+                let text_indices = indices
+                    .iter()
+                    .filter_map(|i| {
+                        let i = *i;
+                        if i < file_name.len() {
+                            Some(i)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                let hint_indices = indices
+                    .iter()
+                    .filter_map(|i| {
+                        let i = *i;
+                        if i > file_name.len() {
+                            Some(i - file_name.len() - 1)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                (
+                    Some(file_svg_new(path)),
+                    file_name,
+                    text_indices,
+                    preview.to_string(),
+                    hint_indices,
+                )
+            }
+            PaletteItemContent::ScratchLanguage(name, _) => (
+                None,
+                name.to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::IndexScope(_, description) => (
+                None,
+                description.to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::TerminalPaste(_, mode) => (
+                None,
+                mode.description().to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::RecoveryItem(_, description, restored) => (
+                None,
+                description.to_string(),
+                indices.to_vec(),
+                if *restored { "restore" } else { "skip" }.to_string(),
+                vec![],
+            ),
+            PaletteItemContent::RecoveryAction(_, description) => (
+                None,
+                description.to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::IndexFolder(name, indexed) => (
+                Some(get_svg("default_folder.svg").unwrap()),
+                name.to_string(),
+                indices.to_vec(),
+                if *indexed { "indexed" } else { "not indexed" }.to_string(),
+                vec![],
+            ),
+            PaletteItemContent::ImportProfile(_, _, description)
+            | PaletteItemContent::ExportProfile(_, description)
+            | PaletteItemContent::ProfileNote(description) => (
+                None,
+                description.to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::Branch(branch, current) => (
+                Some(get_svg("git-icon.svg").unwrap()),
+                branch.to_string(),
+                indices.to_vec(),
+                if *current { "current" } else { "" }.to_string(),
+                vec![],
+            ),
+            PaletteItemContent::Task(name, command) => (
+                Some(get_svg("terminal.svg").unwrap()),
+                name.to_string(),
+                indices.to_vec(),
+                command.to_string(),
+                vec![],
+            ),
+            PaletteItemContent::CreateBranch => (
+                None,
+                "Create new branch…".to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::SshHost(host) => (
+                Some(get_svg("remote.svg").unwrap()),
+                host.to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::NewSshHost => (
+                None,
+                "New connection…".to_string(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::ShownFile(_, _, path) => {
+                file_paint_items(path, indices)
+            }
+            PaletteItemContent::Deleted(_, path, ago) => {
+                let (svg, text, text_indices, hint, hint_indices) =
+                    file_paint_items(path, indices);
+                let hint = if hint.is_empty() {
+                    ago.to_string()
+                } else {
+                    format!("{} \u{2014} {}", hint, ago)
+                };
+                (svg, text, text_indices, hint, hint_indices)
+            }
+            PaletteItemContent::Todo {
+                path,
+                location,
+                text,
+                ..
+            } => {
+                let hint = format!(
+                    "{}:{}",
+                    path.to_str().unwrap_or(""),
+                    location.position.map(|p| p.line + 1).unwrap_or(1)
+                );
+                let text_indices = indices
+                    .iter()
+                    .filter_map(|i| {
+                        let i = *i;
+                        if i < text.len() {
+                            Some(i)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                let hint_indices = indices
+                    .iter()
+                    .filter_map(|i| {
+                        let i = *i;
+                        if i > text.len() {
+                            Some(i - text.len() - 1)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                (
+                    Some(file_svg_new(path)),
+                    text.to_string(),
+                    text_indices,
+                    hint,
+                    hint_indices,
+                )
+            }
         };
 
         if let Some(svg) = svg.as_ref() {
@@ -405,6 +866,8 @@ pub struct PaletteViewData {
     pub config: Arc<Config>,
     pub focus_area: FocusArea,
     pub terminal: Arc<TerminalSplitData>,
+    pub history: Arc<WorkspaceHistory>,
+    pub db: Arc<LapceDb>,
 }
 
 impl Lens<LapceTabData, PaletteViewData> for PaletteViewLens {
@@ -429,6 +892,7 @@ impl Lens<LapceTabData, PaletteViewData> for PaletteViewLens {
         data.keypress = palette_view.keypress.clone();
         data.main_split = palette_view.main_split.clone();
         data.find = palette_view.find.clone();
+        data.history = palette_view.history.clone();
         result
     }
 }
@@ -449,6 +913,26 @@ pub struct PaletteData {
     items: Vec<NewPaletteItem>,
     filtered_items: Vec<NewPaletteItem>,
     pub preview_editor: WidgetId,
+    /// What the file list indexes, `None` until it was picked for the
+    /// workspace, which indexes everything.
+    pub index_scope: Option<IndexScope>,
+    /// How many files the workspace has at least, when it was found to be
+    /// large.
+    pub large_workspace: Option<usize>,
+    preview_timer: TimerToken,
+    /// Why the input can't be used, shown next to it.
+    pub input_error: Option<String>,
+    /// The connection being tested before switching to a new SSH host.
+    ssh_test: Option<SshTest>,
+    /// The session to recover, until what to restore is picked.
+    pub recovery: Option<Arc<SessionRecovery>>,
+}
+
+#[derive(Clone)]
+struct SshTest {
+    id: String,
+    host: SshHost,
+    process: Arc<parking_lot::Mutex<Child>>,
 }
 
 impl KeyPressFocus for PaletteViewData {
@@ -490,6 +974,17 @@ impl KeyPressFocus for PaletteViewData {
             LapceCommand::ListSelect => {
                 self.select(ctx);
             }
+            LapceCommand::PaletteCopyCommandId => {
+                let cmd = match self.palette.get_item().map(|item| &item.content) {
+                    Some(PaletteItemContent::Command(command))
+                    | Some(PaletteItemContent::HistoryCommand { command, .. }) => {
+                        command.cmd.clone()
+                    }
+                    _ => return CommandExecuted::No,
+                };
+                Application::global().clipboard().put_string(cmd);
+                self.cancel(ctx);
+            }
             _ => return CommandExecuted::No,
         }
         CommandExecuted::Yes
@@ -524,6 +1019,19 @@ impl PaletteData {
             items: Vec::new(),
             filtered_items: Vec::new(),
             preview_editor,
+            index_scope: None,
+            large_workspace: None,
+            preview_timer: TimerToken::INVALID,
+            input_error: None,
+            ssh_test: None,
+            recovery: None,
+        }
+    }
+
+    /// Gives up on the connection test, if one is running.
+    fn stop_ssh_test(&mut self) {
+        if let Some(test) = self.ssh_test.take() {
+            let _ = test.process.lock().kill();
         }
     }
 
@@ -541,24 +1049,69 @@ impl PaletteData {
 
     pub fn preview(&self, ctx: &mut EventCtx) {
         if let Some(item) = self.get_item() {
-            item.content.select(ctx, true, self.preview_editor);
+            if let PaletteItemContent::File(_, full_path) = &item.content {
+                self.preview_file(ctx, full_path);
+            } else {
+                item.content.select(ctx, true, self.preview_editor);
+            }
         }
     }
 
+    /// Opens the file in the preview editor once its size is known, as large
+    /// ones aren't worth loading for a glance.
+    fn preview_file(&self, ctx: &mut EventCtx, path: &PathBuf) {
+        let event_sink = ctx.get_external_handle();
+        let widget_id = self.widget_id;
+        let file = path.clone();
+        self.proxy.get_file_size(
+            path,
+            Box::new(move |result| {
+                if let Ok(size) = result {
+                    event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::PreviewPaletteFile(
+                            file,
+                            size.as_u64().unwrap_or(0),
+                        ),
+                        Target::Widget(widget_id),
+                    );
+                }
+            }),
+        );
+    }
+
+    /// Previews the highlighted item after `PREVIEW_DELAY`, unless it changes
+    /// by then.
+    fn preview_later(&mut self, ctx: &mut EventCtx) {
+        self.preview_timer = ctx.request_timer(PREVIEW_DELAY);
+    }
+
     pub fn get_item(&self) -> Option<&NewPaletteItem> {
-        let items = self.current_items();
-        if items.is_empty() {
-            return None;
-        }
-        Some(&items[self.index])
+        self.current_items().get(self.index)
     }
 
     pub fn get_input(&self) -> &str {
         match &self.palette_type {
-            PaletteType::File => &self.input,
+            // the files are matched without the `:line:column` typed after
+            PaletteType::File => split_position(&self.input).0,
             PaletteType::Reference => &self.input,
             PaletteType::Theme => &self.input,
             PaletteType::SshHost => &self.input,
+            PaletteType::NewSshHost => &self.input,
+            PaletteType::Scratch => &self.input,
+            PaletteType::ScratchLanguage => &self.input,
+            PaletteType::Todo => &self.input,
+            PaletteType::IndexScope => &self.input,
+            PaletteType::Profile => &self.input,
+            PaletteType::Branch => &self.input,
+            PaletteType::NewBranch => &self.input,
+            PaletteType::RenameTerminal => &self.input,
+            PaletteType::Deleted => &self.input,
+            PaletteType::CommandHistory => &self.input,
+            PaletteType::PaneFiles => &self.input,
+            PaletteType::TerminalPaste => &self.input,
+            PaletteType::Task => &self.input,
+            PaletteType::Recovery => &self.input,
             PaletteType::Line => &self.input[1..],
             PaletteType::DocumentSymbol => &self.input[1..],
             PaletteType::Workspace => &self.input[1..],
@@ -566,11 +1119,179 @@ impl PaletteData {
             PaletteType::GlobalSearch => &self.input[1..],
         }
     }
+
+    pub fn get_todos(&self, ctx: &mut EventCtx, tokens: &[String]) {
+        let pattern = match todo_pattern(tokens) {
+            Some(pattern) => pattern,
+            None => return,
+        };
+        let run_id = self.run_id.clone();
+        let widget_id = self.widget_id;
+        let event_sink = ctx.get_external_handle();
+        // todos are searched in one go, beside any search of the search panel
+        let options = SearchOptions {
+            case_sensitive: true,
+            whole_word: false,
+            regex: true,
+        };
+        self.proxy.global_search(
+            0,
+            pattern,
+            &options,
+            &[],
+            0,
+            Box::new(move |result| {
+                if let Ok(matches) = result {
+                    if let Ok(matches) = serde_json::from_value::<
+                        HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>,
+                    >(matches)
+                    {
+                        event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateTodos(run_id, Arc::new(matches)),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Searches the todos again if the todo list is the one being shown.
+    pub fn refresh_todos(&self, ctx: &mut EventCtx, tokens: &[String]) {
+        if self.status == PaletteStatus::Started
+            && self.palette_type == PaletteType::Todo
+        {
+            self.get_todos(ctx, tokens);
+        }
+    }
+}
+
+fn profile_items(contents: Vec<PaletteItemContent>) -> Vec<NewPaletteItem> {
+    contents
+        .into_iter()
+        .map(|content| {
+            let filter_text = match &content {
+                PaletteItemContent::ImportProfile(_, _, text)
+                | PaletteItemContent::ExportProfile(_, text)
+                | PaletteItemContent::ProfileNote(text) => text.to_string(),
+                _ => "".to_string(),
+            };
+            NewPaletteItem {
+                content,
+                filter_text,
+                score: 0,
+                indices: vec![],
+            }
+        })
+        .collect()
+}
+
+/// The open files of the workspace to scan for todos, with their unsaved
+/// edits and their highlights.
+fn todo_buffers(data: &LapceTabData) -> Vec<TodoBuffer> {
+    data.main_split
+        .open_files
+        .iter()
+        .filter(|(_, buffer)| buffer.loaded && !buffer.scratch)
+        .filter(|(path, _)| {
+            data.workspace
+                .path
+                .as_ref()
+                .map(|workspace_path| path.starts_with(workspace_path))
+                .unwrap_or(true)
+        })
+        .map(|(path, buffer)| TodoBuffer {
+            path: path.clone(),
+            rope: buffer.rope.clone(),
+            styles: buffer
+                .language
+                .filter(|_| buffer.styles.iter().next().is_some())
+                .map(|_| buffer.styles.clone()),
+        })
+        .collect()
+}
+
+/// Builds the todo list from the workspace search results. Open buffers are
+/// scanned directly instead, so unsaved edits show up and the tokens can be
+/// restricted to comments.
+fn todo_items(
+    matches: &HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>,
+    open_files: &HashSet<PathBuf>,
+    buffers: &[TodoBuffer],
+    tokens: &[String],
+    workspace_path: Option<&PathBuf>,
+) -> Vec<NewPaletteItem> {
+    let mut todos = Vec::new();
+    for (path, line_matches) in matches.iter() {
+        if open_files.contains(path) {
+            continue;
+        }
+        for (line, _, content) in line_matches {
+            for (start, _, token) in find_todo_tokens(content, tokens) {
+                todos.push((
+                    path.clone(),
+                    line.saturating_sub(1),
+                    str_col(&content[..start]),
+                    token,
+                    content.trim().to_string(),
+                ));
+            }
+        }
+    }
+    for buffer in buffers {
+        for (line, start, token, content) in buffer.todos(tokens) {
+            todos.push((
+                buffer.path.clone(),
+                line,
+                str_col(&content[..start]),
+                token,
+                content.trim().to_string(),
+            ));
+        }
+    }
+    todos.sort_by(|a, b| (&a.0, a.1, a.2).cmp(&(&b.0, b.1, b.2)));
+
+    todos
+        .into_iter()
+        .map(|(full_path, line, col, token, text)| {
+            let mut path = full_path.clone();
+            if let Some(workspace_path) = workspace_path {
+                path = path
+                    .strip_prefix(workspace_path)
+                    .unwrap_or(&full_path)
+                    .to_path_buf();
+            }
+            let filter_text = format!("{} {}", text, path.to_str().unwrap_or(""));
+            NewPaletteItem {
+                content: PaletteItemContent::Todo {
+                    path,
+                    location: EditorLocationNew {
+                        path: full_path,
+                        position: Some(Position {
+                            line: line as u32,
+                            character: col as u32,
+                        }),
+                        scroll_offset: None,
+                        hisotry: None,
+                        selection_end: None,
+                    },
+                    token,
+                    text,
+                },
+                filter_text,
+                score: 0,
+                indices: vec![],
+            }
+        })
+        .collect()
 }
 
 impl PaletteViewData {
     fn cancel(&mut self, ctx: &mut EventCtx) {
         let palette = Arc::make_mut(&mut self.palette);
+        palette.stop_ssh_test();
+        palette.input_error = None;
         palette.status = PaletteStatus::Inactive;
         palette.input = "".to_string();
         palette.cursor = 0;
@@ -578,6 +1299,14 @@ impl PaletteViewData {
         palette.palette_type = PaletteType::File;
         palette.items.clear();
         palette.filtered_items.clear();
+        // closing the recovery without a pick starts fresh
+        if let Some(recovery) = palette.recovery.take() {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::DiscardRecovery(recovery),
+                Target::Auto,
+            ));
+        }
         if ctx.is_focused() {
             ctx.resign_focus();
         }
@@ -655,7 +1384,10 @@ impl PaletteViewData {
                 self.get_workspaces(ctx);
             }
             &PaletteType::Reference => {}
-            &PaletteType::SshHost => {}
+            &PaletteType::SshHost => {
+                self.get_ssh_hosts(ctx);
+            }
+            &PaletteType::NewSshHost => {}
             &PaletteType::GlobalSearch => {
                 self.get_global_search(ctx);
             }
@@ -666,6 +1398,43 @@ impl PaletteViewData {
                 let config = self.config.clone();
                 self.get_themes(ctx, &config);
             }
+            &PaletteType::Scratch => {
+                self.get_scratches(ctx);
+            }
+            &PaletteType::ScratchLanguage => {
+                self.get_scratch_languages(ctx);
+            }
+            &PaletteType::Todo => {
+                let tokens = self.config.editor.todo_tokens.clone();
+                self.palette.get_todos(ctx, &tokens);
+            }
+            &PaletteType::IndexScope => {
+                self.get_index_scopes(ctx);
+            }
+            &PaletteType::Profile => {}
+            &PaletteType::Branch => {
+                self.get_branches(ctx);
+            }
+            &PaletteType::NewBranch => {}
+            &PaletteType::RenameTerminal => {}
+            &PaletteType::Deleted => {
+                self.get_deleted(ctx);
+            }
+            &PaletteType::CommandHistory => {
+                self.get_command_history(ctx);
+            }
+            &PaletteType::PaneFiles => {
+                self.get_shown_files(ctx);
+            }
+            &PaletteType::TerminalPaste => {
+                self.get_terminal_paste_modes(ctx);
+            }
+            &PaletteType::Task => {
+                self.get_tasks();
+            }
+            &PaletteType::Recovery => {
+                self.get_recovery_items(ctx);
+            }
         }
     }
 
@@ -691,6 +1460,21 @@ impl PaletteViewData {
             &PaletteType::Reference => 0,
             &PaletteType::Theme => 0,
             &PaletteType::SshHost => 0,
+            &PaletteType::NewSshHost => 0,
+            &PaletteType::Scratch => 0,
+            &PaletteType::ScratchLanguage => 0,
+            &PaletteType::Todo => 0,
+            &PaletteType::IndexScope => 0,
+            &PaletteType::Profile => 0,
+            &PaletteType::Branch => 0,
+            &PaletteType::NewBranch => 0,
+            &PaletteType::RenameTerminal => 0,
+            &PaletteType::Deleted => 0,
+            &PaletteType::CommandHistory => 0,
+            &PaletteType::PaneFiles => 0,
+            &PaletteType::TerminalPaste => 0,
+            &PaletteType::Task => 0,
+            &PaletteType::Recovery => 0,
             &PaletteType::Line => 1,
             &PaletteType::DocumentSymbol => 1,
             &PaletteType::Workspace => 1,
@@ -712,14 +1496,14 @@ impl PaletteViewData {
         let palette = Arc::make_mut(&mut self.palette);
         palette.index =
             Movement::Down.update_index(palette.index, palette.len(), 1, true);
-        palette.preview(ctx);
+        palette.preview_later(ctx);
     }
 
     pub fn previous(&mut self, ctx: &mut EventCtx) {
         let palette = Arc::make_mut(&mut self.palette);
         palette.index =
             Movement::Up.update_index(palette.index, palette.len(), 1, true);
-        palette.preview(ctx);
+        palette.preview_later(ctx);
     }
 
     pub fn select(&mut self, ctx: &mut EventCtx) {
@@ -731,26 +1515,105 @@ impl PaletteViewData {
                 false,
             );
         }
-        if self.palette.palette_type == PaletteType::SshHost {
-            let input = self.palette.get_input();
-            let splits = input.split("@").collect::<Vec<&str>>();
-            let mut splits = splits.iter().rev();
-            let host = splits.next().unwrap().to_string();
-            let user = splits
-                .next()
-                .map(|s| s.to_string())
-                .unwrap_or("root".to_string());
+        if self.palette.palette_type == PaletteType::NewSshHost {
+            self.test_ssh_host(ctx);
+            return;
+        }
+        if self.palette.palette_type == PaletteType::NewBranch {
+            let branch = self.palette.get_input().trim().to_string();
+            if branch != "" {
+                ctx.submit_command(Command::new(
+                    LAPCE_NEW_COMMAND,
+                    LapceCommandNew {
+                        cmd: LapceWorkbenchCommand::CreateBranch.to_string(),
+                        palette_desc: None,
+                        data: Some(json!(branch)),
+                        target: CommandTarget::Workbench,
+                    },
+                    Target::Auto,
+                ));
+            }
+            self.cancel(ctx);
+            return;
+        }
+        if self.palette.palette_type == PaletteType::RenameTerminal {
+            // an empty name gives the terminal back the title from the shell
+            let name = self.palette.get_input().trim().to_string();
             ctx.submit_command(Command::new(
-                LAPCE_UI_COMMAND,
-                LapceUICommand::SetWorkspace(LapceWorkspace {
-                    kind: LapceWorkspaceType::RemoteSSH(user, host),
-                    path: None,
-                    last_open: 0,
-                }),
+                LAPCE_NEW_COMMAND,
+                LapceCommandNew {
+                    cmd: LapceWorkbenchCommand::TerminalRename.to_string(),
+                    palette_desc: None,
+                    data: Some(json!(name)),
+                    target: CommandTarget::Workbench,
+                },
                 Target::Auto,
             ));
+            self.cancel(ctx);
             return;
         }
+        if let Some(PaletteItemContent::IndexFolder(folder, _)) =
+            self.palette.get_item().map(|item| item.content.clone())
+        {
+            self.toggle_index_folder(ctx, &folder);
+            return;
+        }
+        if let Some(PaletteItemContent::ProfileNote(_)) =
+            self.palette.get_item().map(|item| &item.content)
+        {
+            return;
+        }
+        match self.palette.get_item().map(|item| item.content.clone()) {
+            Some(PaletteItemContent::RecoveryItem(index, _, _)) => {
+                self.toggle_recovery_item(index);
+                return;
+            }
+            Some(PaletteItemContent::RecoveryAction(restore, _)) => {
+                let palette = Arc::make_mut(&mut self.palette);
+                if let Some(recovery) = palette.recovery.take() {
+                    let command = if restore {
+                        LapceUICommand::RecoverSession(recovery)
+                    } else {
+                        LapceUICommand::DiscardRecovery(recovery)
+                    };
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        command,
+                        Target::Auto,
+                    ));
+                }
+                self.cancel(ctx);
+                return;
+            }
+            _ => (),
+        }
+        self.record_pick();
+        if self.palette.palette_type == PaletteType::File {
+            if let (
+                Some(PaletteItemContent::File(_, full_path)),
+                (_, Some(position)),
+            ) = (
+                self.palette.get_item().map(|item| &item.content),
+                split_position(&self.palette.input),
+            ) {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::JumpToLocation(
+                        None,
+                        EditorLocationNew {
+                            path: full_path.clone(),
+                            position: Some(position),
+                            scroll_offset: None,
+                            hisotry: None,
+                            selection_end: None,
+                        },
+                    ),
+                    Target::Auto,
+                ));
+                self.cancel(ctx);
+                return;
+            }
+        }
         let palette = Arc::make_mut(&mut self.palette);
         if let Some(item) = palette.get_item() {
             if let Some(palette_type) =
@@ -765,14 +1628,104 @@ impl PaletteViewData {
         }
     }
 
+    /// Keeps the item picked in the history, to rank it up next time.
+    fn record_pick(&mut self) {
+        let key = match self.palette.get_item().and_then(|item| {
+            history_key(&self.palette.palette_type, &item.filter_text)
+        }) {
+            Some(key) => key,
+            None => return,
+        };
+        Arc::make_mut(&mut self.history).add_pick(&key, now());
+        if let Err(e) = self.db.save_history_async(&self.workspace, &self.history) {
+            log::error!("can't save the history: {}", e);
+        }
+    }
+
+    /// Checks the host typed in and tries to log in to it, so that a
+    /// mistake can be fixed in place instead of after the switch.
+    fn test_ssh_host(&mut self, ctx: &mut EventCtx) {
+        let palette = Arc::make_mut(&mut self.palette);
+        if palette.ssh_test.is_some() {
+            return;
+        }
+        let host = match SshHost::validate(palette.get_input()) {
+            Ok(host) => host,
+            Err(e) => {
+                palette.input_error = Some(e);
+                return;
+            }
+        };
+        palette.input_error = None;
+
+        let id = Uuid::new_v4().to_string();
+        let event_sink = ctx.get_external_handle();
+        let widget_id = palette.widget_id;
+        let result_id = id.clone();
+        match test_ssh_connection(&host, move |result| {
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SshTestResult(result_id, result),
+                Target::Widget(widget_id),
+            );
+        }) {
+            Ok(process) => {
+                palette.ssh_test = Some(SshTest { id, host, process });
+            }
+            Err(e) => {
+                palette.input_error = Some(format!("Can't run ssh: {}", e));
+            }
+        }
+    }
+
+    /// Switches to the host whose test passed, or shows why it failed.
+    fn finish_ssh_test(
+        &mut self,
+        ctx: &mut EventCtx,
+        id: &str,
+        result: &Result<(), String>,
+    ) {
+        let palette = Arc::make_mut(&mut self.palette);
+        if palette.ssh_test.as_ref().map(|test| test.id.as_str()) != Some(id) {
+            return;
+        }
+        let test = palette.ssh_test.take().unwrap();
+        match result {
+            Ok(()) => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SetWorkspace(LapceWorkspace {
+                        kind: LapceWorkspaceType::RemoteSSH(test.host),
+                        path: None,
+                        last_open: 0,
+                    }),
+                    Target::Auto,
+                ));
+                self.cancel(ctx);
+            }
+            Err(e) => {
+                palette.input_error = Some(e.to_string());
+            }
+        }
+    }
+
     fn update_palette(&mut self, ctx: &mut EventCtx) {
         let palette = Arc::make_mut(&mut self.palette);
         palette.index = 0;
+        palette.stop_ssh_test();
+        palette.input_error = None;
         let palette_type = self.get_palette_type();
         if self.palette.palette_type != palette_type {
             self.run(ctx, Some(palette_type));
             return;
         }
+        if palette_type == PaletteType::File
+            && self.palette.index_scope == Some(IndexScope::None)
+        {
+            // without an index, the files are searched for each query
+            self.get_files(ctx);
+            return;
+        }
         if self.palette.get_input() != "" {
             self.palette.sender.send((
                 self.palette.run_id.clone(),
@@ -786,7 +1739,23 @@ impl PaletteViewData {
 
     fn get_palette_type(&self) -> PaletteType {
         match self.palette.palette_type {
-            PaletteType::Reference | PaletteType::SshHost => {
+            PaletteType::Reference
+            | PaletteType::SshHost
+            | PaletteType::NewSshHost
+            | PaletteType::Scratch
+            | PaletteType::ScratchLanguage
+            | PaletteType::Todo
+            | PaletteType::IndexScope
+            | PaletteType::Profile
+            | PaletteType::Branch
+            | PaletteType::NewBranch
+            | PaletteType::RenameTerminal
+            | PaletteType::Deleted
+            | PaletteType::CommandHistory
+            | PaletteType::PaneFiles
+            | PaletteType::TerminalPaste
+            | PaletteType::Task
+            | PaletteType::Recovery => {
                 return self.palette.palette_type.clone();
             }
             _ => (),
@@ -803,49 +1772,233 @@ impl PaletteViewData {
         }
     }
 
-    fn get_files(&self, ctx: &mut EventCtx) {
-        let run_id = self.palette.run_id.clone();
-        let widget_id = self.palette.widget_id;
-        let workspace = self.workspace.clone();
+    fn get_files(&self, ctx: &mut EventCtx) {
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let workspace = self.workspace.clone();
+        let event_sink = ctx.get_external_handle();
+        let scope = self.palette.index_scope.clone().unwrap_or_default();
+        let (query, limit) = if scope == IndexScope::None {
+            let query = self.palette.get_input().to_string();
+            if query == "" {
+                return;
+            }
+            (Some(query), Some(500))
+        } else {
+            (None, None)
+        };
+        let folders = scope.folders();
+        self.palette.proxy.get_files(
+            folders,
+            query,
+            limit,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<PathBuf>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let items: Vec<NewPaletteItem> = resp
+                            .iter()
+                            .enumerate()
+                            .map(|(index, path)| {
+                                let full_path = path.clone();
+                                let mut path = path.clone();
+                                if let Some(workspace_path) = workspace.path.as_ref()
+                                {
+                                    path = path
+                                        .strip_prefix(workspace_path)
+                                        .unwrap_or(&full_path)
+                                        .to_path_buf();
+                                }
+                                let filter_text =
+                                    path.to_str().unwrap_or("").to_string();
+                                NewPaletteItem {
+                                    content: PaletteItemContent::File(
+                                        path.to_owned(),
+                                        full_path,
+                                    ),
+                                    filter_text,
+                                    score: 0,
+                                    indices: Vec::new(),
+                                }
+                            })
+                            .collect();
+                        event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Asks whether to merge the profile into the `current` one or to
+    /// replace it, listing what each changes.
+    pub fn run_profile_import(
+        &mut self,
+        ctx: &mut EventCtx,
+        profile: Arc<Profile>,
+        current: &Profile,
+    ) {
+        self.run(ctx, Some(PaletteType::Profile));
+        let merged = current.changes(&profile.apply_to(current, false));
+        let replaced = current.changes(&profile.apply_to(current, true));
+        let mut items = vec![
+            PaletteItemContent::ImportProfile(
+                profile.clone(),
+                false,
+                format!("Merge into my settings ({} changes)", merged.len()),
+            ),
+            PaletteItemContent::ImportProfile(
+                profile.clone(),
+                true,
+                format!("Replace my settings ({} changes)", replaced.len()),
+            ),
+        ];
+        for change in replaced.iter() {
+            if merged.contains(change) {
+                items.push(PaletteItemContent::ProfileNote(change.to_string()));
+            } else {
+                items.push(PaletteItemContent::ProfileNote(format!(
+                    "{} (replace only)",
+                    change
+                )));
+            }
+        }
+        for change in merged.iter() {
+            if !replaced.contains(change) {
+                items.push(PaletteItemContent::ProfileNote(format!(
+                    "{} (merge only)",
+                    change
+                )));
+            }
+        }
+        Arc::make_mut(&mut self.palette).items = profile_items(items);
+    }
+
+    /// Asks whether to export the settings of the profile that look like
+    /// secrets, listing them. They're left out by default.
+    pub fn run_profile_export(&mut self, ctx: &mut EventCtx, profile: Arc<Profile>) {
+        self.run(ctx, Some(PaletteType::Profile));
+        let secrets = profile.secrets();
+        let mut items = vec![
+            PaletteItemContent::ExportProfile(
+                Arc::new(profile.without_secrets()),
+                format!(
+                    "Export without the {} secret-looking settings",
+                    secrets.len()
+                ),
+            ),
+            PaletteItemContent::ExportProfile(
+                profile.clone(),
+                "Export with the secret-looking settings".to_string(),
+            ),
+        ];
+        items.extend(
+            secrets.iter().map(|key| {
+                PaletteItemContent::ProfileNote(format!("secret: {}", key))
+            }),
+        );
+        Arc::make_mut(&mut self.palette).items = profile_items(items);
+    }
+
+    fn get_index_scopes(&mut self, ctx: &mut EventCtx) {
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = vec![
+            (IndexScope::All, "Index all files"),
+            (IndexScope::None, "Don't index, search files as you type"),
+        ]
+        .into_iter()
+        .map(|(scope, description)| NewPaletteItem {
+            content: PaletteItemContent::IndexScope(scope, description.to_string()),
+            filter_text: description.to_string(),
+            score: 0,
+            indices: vec![],
+        })
+        .collect();
+
+        let workspace_path = match self.workspace.path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let scope = palette.index_scope.clone().unwrap_or_default();
+        let mut items = palette.items.clone();
+        let run_id = palette.run_id.clone();
+        let widget_id = palette.widget_id;
         let event_sink = ctx.get_external_handle();
-        self.palette.proxy.get_files(Box::new(move |result| {
-            if let Ok(res) = result {
-                let resp: Result<Vec<PathBuf>, serde_json::Error> =
-                    serde_json::from_value(res);
-                if let Ok(resp) = resp {
-                    let items: Vec<NewPaletteItem> = resp
-                        .iter()
-                        .enumerate()
-                        .map(|(index, path)| {
-                            let full_path = path.clone();
-                            let mut path = path.clone();
-                            if let Some(workspace_path) = workspace.path.as_ref() {
-                                path = path
-                                    .strip_prefix(workspace_path)
-                                    .unwrap_or(&full_path)
-                                    .to_path_buf();
-                            }
-                            let filter_text =
-                                path.to_str().unwrap_or("").to_string();
+        palette.proxy.read_dir(
+            &workspace_path,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<FileNodeItem>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(nodes) = resp {
+                        let mut folders: Vec<String> = nodes
+                            .iter()
+                            .filter(|node| node.is_dir && !node.excluded)
+                            .filter_map(|node| {
+                                node.path_buf
+                                    .file_name()
+                                    .and_then(|name| name.to_str())
+                                    .map(|name| name.to_string())
+                            })
+                            .collect();
+                        folders.sort();
+                        items.extend(folders.into_iter().map(|folder| {
                             NewPaletteItem {
-                                content: PaletteItemContent::File(
-                                    path.to_owned(),
-                                    full_path,
+                                content: PaletteItemContent::IndexFolder(
+                                    folder.clone(),
+                                    scope.is_indexed(&folder),
                                 ),
-                                filter_text,
+                                filter_text: folder,
                                 score: 0,
-                                indices: Vec::new(),
+                                indices: vec![],
                             }
-                        })
-                        .collect();
-                    event_sink.submit_command(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::UpdatePaletteItems(run_id, items),
-                        Target::Widget(widget_id),
-                    );
+                        }));
+                        event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
                 }
+            }),
+        );
+    }
+
+    /// Adds the top-level folder to the index or takes it out, keeping the
+    /// palette open to pick more of them.
+    fn toggle_index_folder(&mut self, ctx: &mut EventCtx, folder: &str) {
+        let palette = Arc::make_mut(&mut self.palette);
+        let folders: Vec<String> = palette
+            .items
+            .iter()
+            .filter_map(|item| match &item.content {
+                PaletteItemContent::IndexFolder(folder, _) => Some(folder.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut scope = palette.index_scope.clone().unwrap_or_default();
+        scope.toggle_folder(folder, &folders);
+        for item in palette
+            .items
+            .iter_mut()
+            .chain(palette.filtered_items.iter_mut())
+        {
+            if let PaletteItemContent::IndexFolder(folder, indexed) =
+                &mut item.content
+            {
+                *indexed = scope.is_indexed(folder);
             }
-        }));
+        }
+        palette.index_scope = Some(scope.clone());
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::SetIndexScope(scope),
+            Target::Auto,
+        ));
     }
 
     fn get_workspaces(&mut self, ctx: &mut EventCtx) {
@@ -863,8 +2016,8 @@ impl PaletteViewData {
                     .unwrap();
                 let filter_text = match &w.kind {
                     LapceWorkspaceType::Local => text.to_string(),
-                    LapceWorkspaceType::RemoteSSH(user, host) => {
-                        format!("[{}@{}] {}", user, host, text)
+                    LapceWorkspaceType::RemoteSSH(host) => {
+                        format!("[{}] {}", host, text)
                     }
                 };
                 NewPaletteItem {
@@ -877,6 +2030,127 @@ impl PaletteViewData {
             .collect();
     }
 
+    /// The recent SSH hosts, the latest first, then the entry to connect to
+    /// a new one.
+    fn get_ssh_hosts(&mut self, ctx: &mut EventCtx) {
+        let mut items: Vec<NewPaletteItem> = Config::recent_ssh_hosts()
+            .into_iter()
+            .map(|host| NewPaletteItem {
+                filter_text: host.to_string(),
+                content: PaletteItemContent::SshHost(host),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+        items.push(NewPaletteItem {
+            content: PaletteItemContent::NewSshHost,
+            filter_text: "New connection…".to_string(),
+            score: 0,
+            indices: vec![],
+        });
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = items;
+    }
+
+    /// The current branch, then the entry to create one, then the others.
+    fn get_branches(&mut self, ctx: &mut EventCtx) {
+        let mut items: Vec<NewPaletteItem> = self
+            .source_control
+            .branch_items()
+            .into_iter()
+            .map(|(branch, current)| NewPaletteItem {
+                filter_text: branch.clone(),
+                content: PaletteItemContent::Branch(branch, current),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+        let index = items
+            .iter()
+            .take_while(|item| {
+                matches!(item.content, PaletteItemContent::Branch(_, true))
+            })
+            .count();
+        items.insert(
+            index,
+            NewPaletteItem {
+                content: PaletteItemContent::CreateBranch,
+                filter_text: "Create new branch…".to_string(),
+                score: 0,
+                indices: vec![],
+            },
+        );
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = items;
+    }
+
+    /// The tasks of the settings, by their name.
+    fn get_tasks(&mut self) {
+        let items = self
+            .config
+            .task
+            .iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(name, task)| NewPaletteItem {
+                filter_text: name.clone(),
+                content: PaletteItemContent::Task(
+                    name.clone(),
+                    task.command.clone(),
+                ),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = items;
+    }
+
+    /// The items deleted from the workspace within the retention window,
+    /// the latest first.
+    fn get_deleted(&self, ctx: &mut EventCtx) {
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let workspace = self.workspace.clone();
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.list_deleted(
+            workspace.stages_deleted_files(),
+            self.config.files.trash_retention_days,
+            Box::new(move |result| {
+                let deleted: Vec<DeletedItem> = match result
+                    .ok()
+                    .and_then(|res| serde_json::from_value(res).ok())
+                {
+                    Some(deleted) => deleted,
+                    None => return,
+                };
+                let now = now();
+                let items: Vec<NewPaletteItem> = deleted
+                    .into_iter()
+                    .map(|item| {
+                        let path = workspace
+                            .path
+                            .as_ref()
+                            .and_then(|w| item.path.strip_prefix(w).ok())
+                            .unwrap_or(&item.path)
+                            .to_path_buf();
+                        let ago = time_ago(now - item.time);
+                        NewPaletteItem {
+                            filter_text: path.to_str().unwrap_or("").to_string(),
+                            content: PaletteItemContent::Deleted(item, path, ago),
+                            score: 0,
+                            indices: vec![],
+                        }
+                    })
+                    .collect();
+                event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::UpdatePaletteItems(run_id, items),
+                    Target::Widget(widget_id),
+                );
+            }),
+        );
+    }
+
     fn get_themes(&mut self, ctx: &mut EventCtx, config: &Config) {
         let palette = Arc::make_mut(&mut self.palette);
         palette.items = config
@@ -891,12 +2165,55 @@ impl PaletteViewData {
             .collect();
     }
 
+    fn get_scratches(&mut self, ctx: &mut EventCtx) {
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = list_scratches(&self.workspace)
+            .into_iter()
+            .map(|(path, preview)| {
+                let file_name =
+                    path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+                let filter_text = format!("{} {}", file_name, preview);
+                NewPaletteItem {
+                    content: PaletteItemContent::Scratch(path, preview),
+                    filter_text,
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+    }
+
+    fn get_scratch_languages(&mut self, ctx: &mut EventCtx) {
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = SCRATCH_LANGUAGES
+            .iter()
+            .map(|(name, extension)| NewPaletteItem {
+                content: PaletteItemContent::ScratchLanguage(
+                    name.to_string(),
+                    extension.to_string(),
+                ),
+                filter_text: name.to_string(),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
+    }
+
     fn get_commands(&mut self, ctx: &mut EventCtx) {
+        let proxy = self.palette.proxy.clone();
         let palette = Arc::make_mut(&mut self.palette);
         palette.items = self
             .keypress
             .commands
             .iter()
+            .filter(|(_, c)| {
+                // hide what an older remote proxy can't do
+                LapceWorkbenchCommand::from_str(&c.cmd)
+                    .ok()
+                    .and_then(|cmd| cmd.required_capability())
+                    .map(|capability| proxy.has_capability(capability))
+                    .unwrap_or(true)
+            })
             .filter_map(|(_, c)| {
                 c.palette_desc.as_ref().map(|m| NewPaletteItem {
                     content: PaletteItemContent::Command(c.clone()),
@@ -906,6 +2223,166 @@ impl PaletteViewData {
                 })
             })
             .collect();
+        boost_items(&mut palette.items, &PaletteType::Command, &self.history);
+    }
+
+    fn get_command_history(&mut self, ctx: &mut EventCtx) {
+        let now = now();
+        let keypress = self.keypress.clone();
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = keypress
+            .history
+            .entries()
+            .map(|entry| {
+                let command = &entry.command;
+                let text = command
+                    .palette_desc
+                    .clone()
+                    .or_else(|| {
+                        keypress
+                            .commands
+                            .get(&command.cmd)
+                            .and_then(|c| c.palette_desc.clone())
+                    })
+                    .unwrap_or_else(|| command.cmd.clone());
+                let mut hint = command.cmd.clone();
+                if let Some(data) = command.data.as_ref() {
+                    hint = format!("{} {}", hint, data);
+                }
+                let hint =
+                    format!("{} \u{2014} {}", hint, time_ago(now - entry.time));
+                NewPaletteItem {
+                    filter_text: format!("{} {}", text, command.cmd),
+                    content: PaletteItemContent::HistoryCommand {
+                        command: command.clone(),
+                        text,
+                        hint,
+                    },
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+    }
+
+    /// The files the active editor showed but the current one, the latest
+    /// first. The ones removed since aren't listed.
+    fn get_shown_files(&mut self, ctx: &mut EventCtx) {
+        let editor = match self.main_split.active_editor() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let local = self.workspace.kind == LapceWorkspaceType::Local;
+        let items: Vec<NewPaletteItem> = editor
+            .shown_files
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(i, file)| {
+                *i != editor.current_shown && (!local || file.path.exists())
+            })
+            .map(|(i, file)| {
+                let path = self
+                    .workspace
+                    .path
+                    .as_ref()
+                    .and_then(|w| file.path.strip_prefix(w).ok())
+                    .unwrap_or(&file.path)
+                    .to_path_buf();
+                NewPaletteItem {
+                    filter_text: path.to_str().unwrap_or("").to_string(),
+                    content: PaletteItemContent::ShownFile(editor.view_id, i, path),
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+        Arc::make_mut(&mut self.palette).items = items;
+    }
+
+    fn get_recovery_items(&mut self, ctx: &mut EventCtx) {
+        let palette = Arc::make_mut(&mut self.palette);
+        let recovery = match palette.recovery.as_ref() {
+            Some(recovery) => recovery.clone(),
+            None => return,
+        };
+        let mut items: Vec<NewPaletteItem> = recovery
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, (item, restored))| {
+                let description = recovery.description(item);
+                NewPaletteItem {
+                    content: PaletteItemContent::RecoveryItem(
+                        i,
+                        description.clone(),
+                        *restored,
+                    ),
+                    filter_text: description,
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+        items.extend(
+            [(true, "Restore selected"), (false, "Start fresh")]
+                .iter()
+                .map(|(restore, description)| NewPaletteItem {
+                    content: PaletteItemContent::RecoveryAction(
+                        *restore,
+                        description.to_string(),
+                    ),
+                    filter_text: description.to_string(),
+                    score: 0,
+                    indices: vec![],
+                }),
+        );
+        palette.items = items;
+    }
+
+    /// Restores the item of the recovery or skips it, keeping the palette
+    /// open to pick more of them.
+    fn toggle_recovery_item(&mut self, index: usize) {
+        let palette = Arc::make_mut(&mut self.palette);
+        let recovery = match palette.recovery.as_mut() {
+            Some(recovery) => Arc::make_mut(recovery),
+            None => return,
+        };
+        recovery.toggle(index);
+        let restored = recovery
+            .items
+            .get(index)
+            .map(|(_, restored)| *restored)
+            .unwrap_or(false);
+        for item in palette
+            .items
+            .iter_mut()
+            .chain(palette.filtered_items.iter_mut())
+        {
+            if let PaletteItemContent::RecoveryItem(i, _, r) = &mut item.content {
+                if *i == index {
+                    *r = restored;
+                }
+            }
+        }
+    }
+
+    fn get_terminal_paste_modes(&mut self, ctx: &mut EventCtx) {
+        let term_id = self.terminal.active_term_id;
+        let mut modes = vec![PasteMode::AsIs, PasteMode::Joined];
+        if self.palette.proxy.has_capability(CAPABILITY_TEMP_FILE) {
+            modes.push(PasteMode::TempFile);
+        }
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = modes
+            .into_iter()
+            .map(|mode| NewPaletteItem {
+                content: PaletteItemContent::TerminalPaste(term_id, mode),
+                filter_text: mode.description().to_string(),
+                score: 0,
+                indices: vec![],
+            })
+            .collect();
     }
 
     fn get_lines(&mut self, ctx: &mut EventCtx) {
@@ -1121,7 +2598,8 @@ impl PaletteViewData {
                     matcher.fuzzy_indices(&i.filter_text, input)
                 {
                     let mut item = i.clone();
-                    item.score = score;
+                    // on top of how the item was ranked up
+                    item.score = score + i.score;
                     item.indices = indices;
                     Some(item)
                 } else {
@@ -1191,6 +2669,11 @@ impl Widget<LapceTabData> for NewPalette {
                 data.workspace = palette_data.workspace.clone();
                 data.main_split = palette_data.main_split.clone();
                 data.find = palette_data.find.clone();
+                data.history = palette_data.history.clone();
+                ctx.set_handled();
+            }
+            Event::Timer(token) if *token == data.palette.preview_timer => {
+                data.palette.preview(ctx);
                 ctx.set_handled();
             }
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
@@ -1215,15 +2698,49 @@ impl Widget<LapceTabData> for NewPalette {
                         data.workspace = palette_data.workspace.clone();
                         data.main_split = palette_data.main_split.clone();
                     }
+                    LapceUICommand::RunPaletteProfileImport(profile) => {
+                        ctx.request_focus();
+                        let current = Profile::current(
+                            data.installed_plugins.keys().cloned().collect(),
+                        );
+                        let mut palette_data = data.palette_view_data();
+                        palette_data.run_profile_import(
+                            ctx,
+                            profile.clone(),
+                            &current,
+                        );
+                        data.palette = palette_data.palette.clone();
+                        data.main_split = palette_data.main_split.clone();
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::RunPaletteProfileExport(profile) => {
+                        ctx.request_focus();
+                        let mut palette_data = data.palette_view_data();
+                        palette_data.run_profile_export(ctx, profile.clone());
+                        data.palette = palette_data.palette.clone();
+                        data.main_split = palette_data.main_split.clone();
+                        ctx.set_handled();
+                    }
                     LapceUICommand::CancelPalette => {
                         let mut palette_data = data.palette_view_data();
                         palette_data.cancel(ctx);
                         data.palette = palette_data.palette.clone();
                     }
+                    LapceUICommand::SshTestResult(id, result) => {
+                        let mut palette_data = data.palette_view_data();
+                        palette_data.finish_ssh_test(ctx, id, result);
+                        data.palette = palette_data.palette.clone();
+                        ctx.set_handled();
+                    }
                     LapceUICommand::UpdatePaletteItems(run_id, items) => {
                         let palette = Arc::make_mut(&mut data.palette);
                         if &palette.run_id == run_id {
                             palette.items = items.to_owned();
+                            boost_items(
+                                &mut palette.items,
+                                &palette.palette_type,
+                                &data.history,
+                            );
                             palette.preview(ctx);
                             if palette.get_input() != "" {
                                 palette.sender.send((
@@ -1234,6 +2751,95 @@ impl Widget<LapceTabData> for NewPalette {
                             }
                         }
                     }
+                    LapceUICommand::PreviewPaletteFile(path, size) => {
+                        let current = match data.palette.get_item() {
+                            Some(NewPaletteItem {
+                                content: PaletteItemContent::File(_, full_path),
+                                ..
+                            }) => full_path == path,
+                            _ => false,
+                        };
+                        if current {
+                            let threshold = data.config.files.large_file_threshold;
+                            if threshold > 0 && *size > threshold {
+                                let preview_editor = Arc::make_mut(
+                                    data.main_split
+                                        .editors
+                                        .get_mut(&data.palette.preview_editor)
+                                        .unwrap(),
+                                );
+                                preview_editor.content =
+                                    BufferContent::Local(LocalBufferKind::Empty);
+                            } else {
+                                // not a jump, so it stays out of the history
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::GoToLocationNew(
+                                        data.palette.preview_editor,
+                                        EditorLocationNew {
+                                            path: path.clone(),
+                                            position: None,
+                                            scroll_offset: None,
+                                            hisotry: None,
+                                            selection_end: None,
+                                        },
+                                    ),
+                                    Target::Auto,
+                                ));
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateTodos(run_id, matches) => {
+                        if &data.palette.run_id == run_id {
+                            // the lines of the open files are scanned in the
+                            // background
+                            let run_id = run_id.clone();
+                            let matches = matches.clone();
+                            let open_files = data
+                                .main_split
+                                .open_files
+                                .iter()
+                                .filter(|(_, buffer)| buffer.loaded)
+                                .map(|(path, _)| path.clone())
+                                .collect::<HashSet<PathBuf>>();
+                            let buffers = todo_buffers(data);
+                            let tokens = data.config.editor.todo_tokens.clone();
+                            let workspace_path = data.workspace.path.clone();
+                            let event_sink = ctx.get_external_handle();
+                            let widget_id = data.palette.widget_id;
+                            rayon::spawn(move || {
+                                let items = todo_items(
+                                    &matches,
+                                    &open_files,
+                                    &buffers,
+                                    &tokens,
+                                    workspace_path.as_ref(),
+                                );
+                                let _ = event_sink.submit_command(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::UpdateTodoItems(run_id, items),
+                                    Target::Widget(widget_id),
+                                );
+                            });
+                        }
+                    }
+                    LapceUICommand::UpdateTodoItems(run_id, items) => {
+                        if &data.palette.run_id == run_id {
+                            let items = items.clone();
+                            let palette = Arc::make_mut(&mut data.palette);
+                            palette.items = items;
+                            if palette.get_input() != "" {
+                                palette.sender.send((
+                                    palette.run_id.clone(),
+                                    palette.get_input().to_string(),
+                                    palette.items.clone(),
+                                ));
+                            } else {
+                                palette.preview(ctx);
+                            }
+                        }
+                    }
                     LapceUICommand::FilterPaletteItems(
                         run_id,
                         input,
@@ -1521,11 +3127,14 @@ pub struct PaletteContent {
     max_items: usize,
 }
 
-pub struct NewPaletteInput {}
+pub struct NewPaletteInput {
+    /// Where the button to stop the connection test was painted.
+    cancel_rect: Option<Rect>,
+}
 
 impl NewPaletteInput {
     pub fn new() -> Self {
-        Self {}
+        Self { cancel_rect: None }
     }
 }
 
@@ -1537,6 +3146,32 @@ impl Widget<PaletteViewData> for NewPaletteInput {
         data: &mut PaletteViewData,
         env: &Env,
     ) {
+        match event {
+            Event::MouseMove(mouse_event) => {
+                if self
+                    .cancel_rect
+                    .map(|rect| rect.contains(mouse_event.pos))
+                    .unwrap_or(false)
+                {
+                    ctx.set_cursor(&druid::Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+            }
+            Event::MouseDown(mouse_event) => {
+                if data.palette.ssh_test.is_some()
+                    && self
+                        .cancel_rect
+                        .map(|rect| rect.contains(mouse_event.pos))
+                        .unwrap_or(false)
+                {
+                    // the input stays, so it can be changed and tried again
+                    Arc::make_mut(&mut data.palette).stop_ssh_test();
+                    ctx.set_handled();
+                }
+            }
+            _ => {}
+        }
     }
 
     fn lifecycle(
@@ -1590,6 +3225,117 @@ impl Widget<PaletteViewData> for NewPaletteInput {
             1.0,
         );
         ctx.draw_text(&text_layout, Point::new(0.0, 0.0));
+
+        self.cancel_rect = None;
+        if data.palette.ssh_test.is_some() {
+            let cancel_layout = ctx
+                .text()
+                .new_text_layout("Cancel")
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOCUS)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let cancel_size = cancel_layout.size();
+            let cancel_origin =
+                Point::new(ctx.size().width - cancel_size.width, 1.0);
+            ctx.draw_text(&cancel_layout, cancel_origin);
+            self.cancel_rect =
+                Some(Rect::from_origin_size(cancel_origin, cancel_size));
+
+            let text_layout = ctx
+                .text()
+                .new_text_layout("Testing connection…")
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let width = text_layout.size().width + cancel_size.width + 10.0;
+            ctx.draw_text(&text_layout, Point::new(ctx.size().width - width, 1.0));
+            return;
+        }
+        if let Some(error) = data.palette.input_error.as_ref() {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(error.to_string())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_ERROR)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let width = text_layout.size().width;
+            ctx.draw_text(&text_layout, Point::new(ctx.size().width - width, 1.0));
+            return;
+        }
+
+        let info = match data.palette.palette_type {
+            PaletteType::Todo => data
+                .config
+                .editor
+                .todo_tokens
+                .iter()
+                .filter_map(|token| {
+                    let count = data
+                        .palette
+                        .items
+                        .iter()
+                        .filter(|item| match &item.content {
+                            PaletteItemContent::Todo { token: t, .. } => t == token,
+                            _ => false,
+                        })
+                        .count();
+                    if count > 0 {
+                        Some(format!("{} {}", token, count))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("  "),
+            PaletteType::TerminalPaste => {
+                let lines = data
+                    .terminal
+                    .terminals
+                    .get(&data.terminal.active_term_id)
+                    .and_then(|t| t.pending_paste.as_ref())
+                    .map(|text| text.lines().count())
+                    .unwrap_or(0);
+                format!("{} lines, the program doesn't take bracketed paste", lines)
+            }
+            PaletteType::Recovery => {
+                "Lapce didn't exit cleanly, pick what to restore".to_string()
+            }
+            PaletteType::IndexScope => match data.palette.large_workspace {
+                Some(count) => format!("{}+ files, pick what to index", count),
+                None => "Pick what to index".to_string(),
+            },
+            _ => return,
+        };
+        if info != "" {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(info)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let width = text_layout.size().width;
+            ctx.draw_text(&text_layout, Point::new(ctx.size().width - width, 1.0));
+        }
     }
 }
 
@@ -1768,6 +3514,58 @@ impl PaletteContent {
     }
 }
 
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// The key of an item in the history of palette picks, for the palettes
+/// ranking their items by it.
+fn history_key(palette_type: &PaletteType, filter_text: &str) -> Option<String> {
+    match palette_type {
+        PaletteType::File | PaletteType::Command => {
+            Some(format!("{:?}:{}", palette_type, filter_text))
+        }
+        _ => None,
+    }
+}
+
+/// Ranks up the items picked often, and the files opened recently.
+fn boost_items(
+    items: &mut Vec<NewPaletteItem>,
+    palette_type: &PaletteType,
+    history: &WorkspaceHistory,
+) {
+    let now = now();
+    let mut boosted = false;
+    for item in items.iter_mut() {
+        if let Some(key) = history_key(palette_type, &item.filter_text) {
+            let path = match &item.content {
+                PaletteItemContent::File(_, full_path) => Some(full_path.as_path()),
+                _ => None,
+            };
+            item.score = history.boost(&key, path, now);
+            boosted = true;
+        }
+    }
+    if boosted {
+        items.sort_by(|a, b| b.score.cmp(&a.score));
+    }
+}
+
+/// How long ago something was, for `secs` seconds.
+fn time_ago(secs: i64) -> String {
+    let (n, unit) = match secs.max(0) {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s => (s / (24 * 60 * 60), "day"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
 fn file_paint_items(
     path: &PathBuf,
     indices: &[usize],
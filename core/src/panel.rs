@@ -1,13 +1,14 @@
 use std::{collections::HashMap, sync::Arc};
 
 use druid::{
+    kurbo::Line,
     piet::{Text, TextLayout, TextLayoutBuilder},
-    BoxConstraints, Command, Env, Event, EventCtx, FontFamily, LayoutCtx, LifeCycle,
-    LifeCycleCtx, MouseEvent, PaintCtx, Point, RenderContext, Size, Target,
-    UpdateCtx, Widget, WidgetExt, WidgetId, WidgetPod, WindowId,
+    BoxConstraints, Command, Data, Env, Event, EventCtx, FontFamily, LayoutCtx,
+    LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point, Rect, RenderContext, Size,
+    Target, UpdateCtx, Widget, WidgetExt, WidgetId, WidgetPod, WindowId,
 };
 use parking_lot::Mutex;
-use serde_json::json;
+use serde_json::{json, Value};
 
 use crate::{
     command::{
@@ -18,7 +19,10 @@ use crate::{
     data::{LapceTabData, PanelKind},
     explorer::FileExplorerState,
     outline::OutlineState,
+    problem::problem_counts,
     scroll::LapceScrollNew,
+    search::{self, stale_changed},
+    source_control,
     split::{LapceSplitNew, SplitDirection},
     svg::get_svg,
     tab::LapceIcon,
@@ -298,6 +302,168 @@ pub enum PanelHeaderKind {
     Widget(Box<dyn Widget<LapceTabData>>),
 }
 
+/// How wide the inputs of a `PanelInputList` are when it's on their left.
+const INPUT_LIST_WIDTH: f64 = 300.0;
+
+/// Inputs stacked above a list, like the ones of the search panel. At the
+/// bottom, where the panel is short and wide, the inputs go on the left of
+/// the list instead.
+pub struct PanelInputList {
+    kind: PanelKind,
+    position: Option<PanelPosition>,
+    inputs_height: f64,
+    inputs: WidgetPod<LapceTabData, LapceSplitNew>,
+    list: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+}
+
+impl PanelInputList {
+    /// `inputs` are the input widgets with their heights, in the panel of
+    /// `kind`.
+    pub fn new(
+        kind: PanelKind,
+        split_id: WidgetId,
+        inputs: Vec<(Box<dyn Widget<LapceTabData>>, f64)>,
+        list: Box<dyn Widget<LapceTabData>>,
+    ) -> Self {
+        let inputs_height = inputs.iter().map(|(_, height)| height).sum();
+        let mut split = LapceSplitNew::new(split_id).horizontal();
+        for (input, height) in inputs {
+            split = split.with_child(input, None, height);
+        }
+        Self {
+            kind,
+            position: None,
+            inputs_height,
+            inputs: WidgetPod::new(split),
+            list: WidgetPod::new(list),
+        }
+    }
+
+    fn at_bottom(&self) -> bool {
+        matches!(
+            self.position,
+            Some(PanelPosition::BottomLeft) | Some(PanelPosition::BottomRight)
+        )
+    }
+}
+
+impl Widget<LapceTabData> for PanelInputList {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        self.inputs.event(ctx, event, data, env);
+        self.list.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.position = data.panel_position(self.kind);
+        }
+        self.inputs.lifecycle(ctx, event, data, env);
+        self.list.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        let position = data.panel_position(self.kind);
+        if position != self.position {
+            self.position = position;
+            ctx.request_layout();
+        }
+        self.inputs.update(ctx, data, env);
+        self.list.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let self_size = bc.max();
+        let (inputs_size, list_origin) = if self.at_bottom() {
+            let width = INPUT_LIST_WIDTH.min(self_size.width / 2.0);
+            (Size::new(width, self_size.height), Point::new(width, 0.0))
+        } else {
+            let height = self.inputs_height.min(self_size.height);
+            (Size::new(self_size.width, height), Point::new(0.0, height))
+        };
+        self.inputs
+            .layout(ctx, &BoxConstraints::tight(inputs_size), data, env);
+        self.inputs.set_origin(ctx, data, env, Point::ZERO);
+
+        let list_size = Size::new(
+            self_size.width - list_origin.x,
+            self_size.height - list_origin.y,
+        );
+        self.list
+            .layout(ctx, &BoxConstraints::tight(list_size), data, env);
+        self.list.set_origin(ctx, data, env, list_origin);
+
+        self_size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        self.inputs.paint(ctx, data, env);
+        self.list.paint(ctx, data, env);
+
+        let rect = self.list.layout_rect();
+        let line = if self.at_bottom() {
+            Line::new(Point::new(rect.x0, 0.0), Point::new(rect.x0, rect.y1))
+        } else {
+            Line::new(Point::new(0.0, rect.y0), Point::new(rect.x1, rect.y0))
+        };
+        ctx.stroke(
+            line,
+            data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER),
+            1.0,
+        );
+    }
+}
+
+/// The bottom panel that editors can be moved into from the main split. Its
+/// split starts out empty and the panel is only listed while it has editors.
+pub struct EditorsPanelData {
+    pub widget_id: WidgetId,
+    pub split_id: WidgetId,
+}
+
+impl EditorsPanelData {
+    pub fn new() -> Self {
+        Self {
+            widget_id: WidgetId::next(),
+            split_id: WidgetId::next(),
+        }
+    }
+
+    pub fn new_panel(&self) -> LapcePanel {
+        LapcePanel::new(
+            PanelKind::Editors,
+            self.widget_id,
+            self.split_id,
+            SplitDirection::Vertical,
+            PanelHeaderKind::Simple("Editors".to_string()),
+            vec![],
+        )
+    }
+}
+
 pub struct PanelSection {
     widget_id: WidgetId,
     header: Option<WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>>,
@@ -496,9 +662,65 @@ impl Widget<LapceTabData> for PanelSectionHeader {
     }
 }
 
+/// A button on the right of a panel's header, running a workbench command
+/// when clicked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PanelHeaderAction {
+    pub icon: String,
+    pub tooltip: String,
+    pub command: LapceWorkbenchCommand,
+    pub data: Option<Value>,
+}
+
+impl PanelHeaderAction {
+    pub fn new(icon: &str, tooltip: &str, command: LapceWorkbenchCommand) -> Self {
+        Self {
+            icon: icon.to_string(),
+            tooltip: tooltip.to_string(),
+            command,
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    fn icon(&self, rect: Rect, tab_id: WidgetId) -> LapceIcon {
+        LapceIcon {
+            icon: self.icon.clone(),
+            rect,
+            command: Command::new(
+                LAPCE_NEW_COMMAND,
+                LapceCommandNew {
+                    cmd: self.command.to_string(),
+                    data: self.data.clone(),
+                    palette_desc: None,
+                    target: CommandTarget::Workbench,
+                },
+                Target::Widget(tab_id),
+            ),
+        }
+    }
+}
+
+/// The buttons a panel puts in its header, besides closing and maximizing
+/// it, the rightmost first.
+fn panel_header_actions(
+    kind: PanelKind,
+    data: &LapceTabData,
+) -> Vec<PanelHeaderAction> {
+    match kind {
+        PanelKind::Search => search::header_actions(data),
+        PanelKind::SourceControl => source_control::header_actions(data),
+        _ => Vec::new(),
+    }
+}
+
 pub struct PanelMainHeader {
     text: String,
-    icons: Vec<LapceIcon>,
+    icons: Vec<(LapceIcon, String)>,
     panel_widget_id: WidgetId,
     kind: PanelKind,
     mouse_pos: Point,
@@ -519,69 +741,53 @@ impl PanelMainHeader {
         let icon_size = 24.0;
         let gap = (self_size.height - icon_size) / 2.0;
 
-        let mut icons = Vec::new();
-        let x = self_size.width - ((icons.len() + 1) as f64) * (gap + icon_size);
-        let icon = LapceIcon {
-            icon: "close.svg".to_string(),
-            rect: Size::new(icon_size, icon_size)
-                .to_rect()
-                .with_origin(Point::new(x, gap)),
-            command: Command::new(
-                LAPCE_NEW_COMMAND,
-                LapceCommandNew {
-                    cmd: LapceWorkbenchCommand::HidePanel.to_string(),
-                    data: Some(json!(self.kind)),
-                    palette_desc: None,
-                    target: CommandTarget::Workbench,
-                },
-                Target::Widget(data.id),
-            ),
-        };
-        icons.push(icon);
-
+        let mut actions = vec![PanelHeaderAction::new(
+            "close.svg",
+            "Close",
+            LapceWorkbenchCommand::HidePanel,
+        )
+        .with_data(json!(self.kind))];
         let position = data.panel_position(self.kind);
-        if let Some(position) = position {
-            if position == PanelPosition::BottomLeft
-                || position == PanelPosition::BottomRight
-            {
-                let mut icon_svg = "chevron-up.svg";
-                for (_, panel) in data.panels.iter() {
-                    if panel.widgets.contains(&self.kind) {
-                        if panel.maximized {
-                            icon_svg = "chevron-down.svg";
-                        }
-                        break;
-                    }
-                }
-
-                let x =
-                    self_size.width - ((icons.len() + 1) as f64) * (gap + icon_size);
-                let icon = LapceIcon {
-                    icon: icon_svg.to_string(),
-                    rect: Size::new(icon_size, icon_size)
-                        .to_rect()
-                        .with_origin(Point::new(x, gap)),
-                    command: Command::new(
-                        LAPCE_NEW_COMMAND,
-                        LapceCommandNew {
-                            cmd: LapceWorkbenchCommand::ToggleMaximizedPanel
-                                .to_string(),
-                            data: Some(json!(self.kind)),
-                            palette_desc: None,
-                            target: CommandTarget::Workbench,
-                        },
-                        Target::Widget(data.id),
-                    ),
-                };
-                icons.push(icon);
-            }
+        if position == Some(PanelPosition::BottomLeft)
+            || position == Some(PanelPosition::BottomRight)
+        {
+            let maximized = data
+                .panels
+                .values()
+                .find(|panel| panel.widgets.contains(&self.kind))
+                .map(|panel| panel.maximized)
+                .unwrap_or(false);
+            let (icon, tooltip) = if maximized {
+                ("chevron-down.svg", "Restore")
+            } else {
+                ("chevron-up.svg", "Maximize")
+            };
+            actions.push(
+                PanelHeaderAction::new(
+                    icon,
+                    tooltip,
+                    LapceWorkbenchCommand::ToggleMaximizedPanel,
+                )
+                .with_data(json!(self.kind)),
+            );
         }
+        actions.extend(panel_header_actions(self.kind, data));
 
-        self.icons = icons;
+        self.icons = actions
+            .into_iter()
+            .enumerate()
+            .map(|(i, action)| {
+                let x = self_size.width - ((i + 1) as f64) * (gap + icon_size);
+                let rect = Size::new(icon_size, icon_size)
+                    .to_rect()
+                    .with_origin(Point::new(x, gap));
+                (action.icon(rect, data.id), action.tooltip)
+            })
+            .collect();
     }
 
     fn mouse_down(&self, ctx: &mut EventCtx, mouse_event: &MouseEvent) {
-        for icon in self.icons.iter() {
+        for (icon, _) in self.icons.iter() {
             if icon.rect.contains(mouse_event.pos) {
                 ctx.submit_command(icon.command.clone());
             }
@@ -589,12 +795,40 @@ impl PanelMainHeader {
     }
 
     fn icon_hit_test(&self, mouse_event: &MouseEvent) -> bool {
-        for icon in self.icons.iter() {
-            if icon.rect.contains(mouse_event.pos) {
-                return true;
-            }
-        }
-        false
+        self.icons
+            .iter()
+            .any(|(icon, _)| icon.rect.contains(mouse_event.pos))
+    }
+
+    /// Paints what the hovered button does on the left of the buttons, over
+    /// the title if it's long.
+    fn paint_tooltip(&self, ctx: &mut PaintCtx, data: &LapceTabData, tooltip: &str) {
+        let right = self
+            .icons
+            .iter()
+            .map(|(icon, _)| icon.rect.x0)
+            .fold(ctx.size().width, f64::min);
+        let text_layout = ctx
+            .text()
+            .new_text_layout(tooltip.to_string())
+            .font(FontFamily::SYSTEM_UI, data.config.editor.font_size as f64)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        let padding = 5.0;
+        let size = text_layout.size();
+        let y = (ctx.size().height - size.height) / 2.0;
+        let x = (right - padding - size.width).max(padding);
+        ctx.fill(
+            Rect::new(x - padding, 0.0, right, ctx.size().height),
+            data.config
+                .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
+        );
+        ctx.draw_text(&text_layout, Point::new(x, y));
     }
 }
 
@@ -631,6 +865,9 @@ impl Widget<LapceTabData> for PanelMainHeader {
         data: &LapceTabData,
         env: &Env,
     ) {
+        if let LifeCycle::HotChanged(_) = event {
+            ctx.request_paint();
+        }
     }
 
     fn update(
@@ -640,6 +877,28 @@ impl Widget<LapceTabData> for PanelMainHeader {
         data: &LapceTabData,
         env: &Env,
     ) {
+        // the search icons depend on the query and the results
+        if self.kind == PanelKind::Search
+            && (old_data.search.query != data.search.query
+                || !old_data.search.matches.same(&data.search.matches)
+                || !old_data.search.collapsed.same(&data.search.collapsed)
+                || stale_changed(old_data, data))
+        {
+            ctx.request_layout();
+        }
+        if self.kind == PanelKind::SourceControl
+            && old_data.source_control.has_changes()
+                != data.source_control.has_changes()
+        {
+            ctx.request_layout();
+        }
+        if self.kind == PanelKind::Problem
+            && (old_data.main_split.error_count != data.main_split.error_count
+                || old_data.main_split.warning_count
+                    != data.main_split.warning_count)
+        {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -687,14 +946,35 @@ impl Widget<LapceTabData> for PanelMainHeader {
             let y = (height - text_layout.size().height) / 2.0;
             ctx.draw_text(&text_layout, Point::new(10.0, y));
 
+            if self.kind == PanelKind::Problem {
+                let x = 10.0 + text_layout.size().width + 10.0;
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout(problem_counts(
+                        data.main_split.error_count,
+                        data.main_split.warning_count,
+                    ))
+                    .font(FontFamily::SYSTEM_UI, data.config.editor.font_size as f64)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                let y = (height - text_layout.size().height) / 2.0;
+                ctx.draw_text(&text_layout, Point::new(x, y));
+            }
+
             let icon_padding = 4.0;
-            for icon in self.icons.iter() {
-                if icon.rect.contains(self.mouse_pos) {
+            for (icon, tooltip) in self.icons.iter() {
+                if ctx.is_hot() && icon.rect.contains(self.mouse_pos) {
                     ctx.fill(
                         &icon.rect,
                         data.config
                             .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
                     );
+                    self.paint_tooltip(ctx, data, tooltip);
                 }
                 if let Some(svg) = get_svg(&icon.icon) {
                     ctx.draw_svg(
@@ -22,11 +22,16 @@ use crate::{
     svg::{file_svg_new, get_svg},
 };
 
+#[derive(Clone)]
 pub struct ProblemData {
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
     pub error_widget_id: WidgetId,
     pub warning_widget_id: WidgetId,
+    pub information_widget_id: WidgetId,
+    pub show_errors: bool,
+    pub show_warnings: bool,
+    pub show_information: bool,
 }
 
 impl ProblemData {
@@ -36,6 +41,27 @@ impl ProblemData {
             split_id: WidgetId::next(),
             error_widget_id: WidgetId::next(),
             warning_widget_id: WidgetId::next(),
+            information_widget_id: WidgetId::next(),
+            show_errors: true,
+            show_warnings: true,
+            show_information: true,
+        }
+    }
+
+    /// Whether diagnostics listed under `severity`'s section are shown.
+    pub fn shows(&self, severity: DiagnosticSeverity) -> bool {
+        match severity {
+            DiagnosticSeverity::Error => self.show_errors,
+            DiagnosticSeverity::Warning => self.show_warnings,
+            _ => self.show_information,
+        }
+    }
+
+    pub fn toggle(&mut self, severity: DiagnosticSeverity) {
+        match severity {
+            DiagnosticSeverity::Error => self.show_errors = !self.show_errors,
+            DiagnosticSeverity::Warning => self.show_warnings = !self.show_warnings,
+            _ => self.show_information = !self.show_information,
         }
     }
 
@@ -59,11 +85,48 @@ impl ProblemData {
                     ProblemContent::new(DiagnosticSeverity::Warning).boxed(),
                     None,
                 ),
+                (
+                    self.information_widget_id,
+                    PanelHeaderKind::Simple("Information".to_string()),
+                    ProblemContent::new(DiagnosticSeverity::Information).boxed(),
+                    None,
+                ),
             ],
         )
     }
 }
 
+/// The error and warning counts shown in the panel header.
+pub fn problem_counts(errors: usize, warnings: usize) -> String {
+    format!(
+        "{} error{}, {} warning{}",
+        errors,
+        if errors == 1 { "" } else { "s" },
+        warnings,
+        if warnings == 1 { "" } else { "s" },
+    )
+}
+
+/// The section a diagnostic is listed under; hints and diagnostics
+/// without a severity go with the information ones.
+fn section_severity(severity: Option<DiagnosticSeverity>) -> DiagnosticSeverity {
+    match severity {
+        Some(DiagnosticSeverity::Error) => DiagnosticSeverity::Error,
+        Some(DiagnosticSeverity::Warning) => DiagnosticSeverity::Warning,
+        _ => DiagnosticSeverity::Information,
+    }
+}
+
+/// Where a click on the diagnostic jumps to.
+fn diagnostic_position(d: &EditorDiagnostic) -> lsp_types::Position {
+    d.range
+        .map(|(line, col)| lsp_types::Position {
+            line: line as u32,
+            character: col as u32,
+        })
+        .unwrap_or(d.diagnositc.range.start.clone())
+}
+
 pub struct ProblemContent {
     severity: DiagnosticSeverity,
     mouse_pos: Point,
@@ -81,6 +144,9 @@ impl ProblemContent {
         &self,
         data: &'a LapceTabData,
     ) -> Vec<(&'a PathBuf, Vec<&'a EditorDiagnostic>)> {
+        if !data.problem.shows(self.severity) {
+            return Vec::new();
+        }
         let items: Vec<(&PathBuf, Vec<&EditorDiagnostic>)> = data
             .main_split
             .diagnostics
@@ -88,7 +154,9 @@ impl ProblemContent {
             .filter_map(|(path, diagnostic)| {
                 let diagnostics: Vec<&EditorDiagnostic> = diagnostic
                     .iter()
-                    .filter(|d| d.diagnositc.severity == Some(self.severity))
+                    .filter(|d| {
+                        section_severity(d.diagnositc.severity) == self.severity
+                    })
                     .collect();
                 if diagnostics.len() > 0 {
                     Some((path, diagnostics))
@@ -138,16 +206,10 @@ impl ProblemContent {
                             None,
                             EditorLocationNew {
                                 path: path.clone(),
-                                position: Some(
-                                    d.range
-                                        .map(|(line, col)| lsp_types::Position {
-                                            line: line as u32,
-                                            character: col as u32,
-                                        })
-                                        .unwrap_or(d.diagnositc.range.start.clone()),
-                                ),
+                                position: Some(diagnostic_position(d)),
                                 scroll_offset: None,
                                 hisotry: None,
+                                selection_end: None,
                             },
                         ),
                         Target::Widget(data.id),
@@ -177,6 +239,7 @@ impl ProblemContent {
                                     ),
                                     scroll_offset: None,
                                     hisotry: None,
+                                    selection_end: None,
                                 },
                             ),
                             Target::Widget(data.id),
@@ -231,6 +294,8 @@ impl Widget<LapceTabData> for ProblemContent {
             .main_split
             .diagnostics
             .same(&old_data.main_split.diagnostics)
+            || data.problem.shows(self.severity)
+                != old_data.problem.shows(self.severity)
         {
             ctx.request_layout();
         }
@@ -379,7 +444,10 @@ impl Widget<LapceTabData> for ProblemContent {
                 if i >= min {
                     let svg = match self.severity {
                         DiagnosticSeverity::Error => get_svg("error.svg").unwrap(),
-                        _ => get_svg("warning.svg").unwrap(),
+                        DiagnosticSeverity::Warning => {
+                            get_svg("warning.svg").unwrap()
+                        }
+                        _ => get_svg("lightbulb.svg").unwrap(),
                     };
                     let rect = Size::new(line_height, line_height)
                         .to_rect()
@@ -413,6 +481,32 @@ impl Widget<LapceTabData> for ProblemContent {
                                 + (line_height - text_layout.size().height) / 2.0,
                         ),
                     );
+
+                    let position = diagnostic_position(d);
+                    let x = 2.0 * line_height + text_layout.size().width + 5.0;
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(format!(
+                            "[{}:{}]",
+                            position.line + 1,
+                            position.character + 1,
+                        ))
+                        .font(FontFamily::SYSTEM_UI, 13.0)
+                        .text_color(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                                .clone(),
+                        )
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(
+                        &text_layout,
+                        Point::new(
+                            x,
+                            line_height * i as f64
+                                + (line_height - text_layout.size().height) / 2.0,
+                        ),
+                    );
                 }
 
                 for related in d
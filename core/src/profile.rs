@@ -0,0 +1,374 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use anyhow::{anyhow, Result};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use toml::value::Table;
+
+/// The setup of a user, to move it between machines or share it with a team:
+/// the user settings, the keymaps and the installed plugins.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub settings: Table,
+    /// The content of `keymaps.toml`.
+    #[serde(default)]
+    pub keymaps: Table,
+    /// The names of the installed plugins.
+    #[serde(default)]
+    pub plugins: Vec<String>,
+}
+
+/// What applying a profile changes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProfileChange {
+    /// A setting by its dotted key, with its value before and after.
+    Setting {
+        key: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Keymaps {
+        added: usize,
+        removed: usize,
+    },
+    /// A plugin to install.
+    Plugin(String),
+}
+
+impl fmt::Display for ProfileChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileChange::Setting { key, old, new } => match (old, new) {
+                (Some(old), Some(new)) => write!(f, "~ {}: {} → {}", key, old, new),
+                (None, Some(new)) => write!(f, "+ {}: {}", key, new),
+                (Some(old), None) => write!(f, "- {}: {}", key, old),
+                (None, None) => write!(f, "{}", key),
+            },
+            ProfileChange::Keymaps { added, removed } => {
+                write!(f, "~ keymaps: {} added, {} removed", added, removed)
+            }
+            ProfileChange::Plugin(name) => write!(f, "+ plugin {}", name),
+        }
+    }
+}
+
+impl Profile {
+    /// The profile of this machine, with the given installed plugins.
+    pub fn current(plugins: Vec<String>) -> Profile {
+        let mut plugins = plugins;
+        plugins.sort();
+        Profile {
+            settings: read_table("settings.toml"),
+            keymaps: read_table("keymaps.toml"),
+            plugins,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Profile> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The dotted keys of the settings that look like they hold secrets, like
+    /// a token in the environment of a tool.
+    pub fn secrets(&self) -> Vec<String> {
+        flatten(&self.settings)
+            .into_iter()
+            .filter(|(key, value)| is_secret(key, value))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// The profile without the settings that look like secrets, which is
+    /// what's exported unless asked otherwise.
+    pub fn without_secrets(&self) -> Profile {
+        let mut profile = self.clone();
+        for key in self.secrets() {
+            remove_key(&mut profile.settings, &key);
+        }
+        profile
+    }
+
+    /// What `current` becomes with this profile applied: merged into it,
+    /// the profile winning where both have a setting, or replacing it. The
+    /// plugins are only ever installed, not removed.
+    pub fn apply_to(&self, current: &Profile, replace: bool) -> Profile {
+        let (settings, keymaps) = if replace {
+            (self.settings.clone(), self.keymaps.clone())
+        } else {
+            let mut settings = current.settings.clone();
+            merge_table(&mut settings, &self.settings);
+            let mut list = keymap_list(&current.keymaps);
+            for keymap in keymap_list(&self.keymaps) {
+                if !list.contains(&keymap) {
+                    list.push(keymap);
+                }
+            }
+            let mut keymaps = current.keymaps.clone();
+            if !list.is_empty() {
+                keymaps.insert("keymaps".to_string(), toml::Value::Array(list));
+            }
+            (settings, keymaps)
+        };
+
+        let mut plugins = current.plugins.clone();
+        for plugin in self.plugins.iter() {
+            if !plugins.contains(plugin) {
+                plugins.push(plugin.clone());
+            }
+        }
+        plugins.sort();
+
+        Profile {
+            settings,
+            keymaps,
+            plugins,
+        }
+    }
+
+    /// What changes from `self` to `target`.
+    pub fn changes(&self, target: &Profile) -> Vec<ProfileChange> {
+        let mut changes = Vec::new();
+
+        let old = flatten(&self.settings);
+        let new = flatten(&target.settings);
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let (old, new) = (old.get(key), new.get(key));
+            if old != new {
+                changes.push(ProfileChange::Setting {
+                    key: key.to_string(),
+                    old: old.map(|v| v.to_string()),
+                    new: new.map(|v| v.to_string()),
+                });
+            }
+        }
+
+        let old = keymap_list(&self.keymaps);
+        let new = keymap_list(&target.keymaps);
+        let added = new.iter().filter(|k| !old.contains(k)).count();
+        let removed = old.iter().filter(|k| !new.contains(k)).count();
+        if added > 0 || removed > 0 {
+            changes.push(ProfileChange::Keymaps { added, removed });
+        }
+
+        for plugin in target.plugins.iter() {
+            if !self.plugins.contains(plugin) {
+                changes.push(ProfileChange::Plugin(plugin.clone()));
+            }
+        }
+        changes
+    }
+
+    /// Writes the settings and the keymaps of the profile to the user's
+    /// files. The plugins are for the caller to install.
+    pub fn write(&self) -> Result<()> {
+        let dir = ProjectDirs::from("", "", "Lapce")
+            .ok_or_else(|| anyhow!("no config directory"))?;
+        let dir = dir.config_dir();
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("settings.toml"), toml::to_string(&self.settings)?)?;
+        std::fs::write(dir.join("keymaps.toml"), toml::to_string(&self.keymaps)?)?;
+        Ok(())
+    }
+}
+
+fn read_table(name: &str) -> Table {
+    ProjectDirs::from("", "", "Lapce")
+        .and_then(|dir| std::fs::read_to_string(dir.config_dir().join(name)).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn keymap_list(keymaps: &Table) -> Vec<toml::Value> {
+    keymaps
+        .get("keymaps")
+        .and_then(|k| k.as_array())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// The values of the table that aren't tables, by their dotted keys.
+fn flatten(table: &Table) -> BTreeMap<String, toml::Value> {
+    fn flatten_into(
+        prefix: &str,
+        table: &Table,
+        values: &mut BTreeMap<String, toml::Value>,
+    ) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                toml::Value::Table(table) => flatten_into(&key, table, values),
+                _ => {
+                    values.insert(key, value.clone());
+                }
+            }
+        }
+    }
+
+    let mut values = BTreeMap::new();
+    flatten_into("", table, &mut values);
+    values
+}
+
+fn merge_table(table: &mut Table, other: &Table) {
+    for (key, value) in other {
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(other)) => {
+                merge_table(table, other);
+            }
+            _ => {
+                table.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn remove_key(table: &mut Table, key: &str) {
+    match key.split_once('.') {
+        Some((first, rest)) => {
+            if let Some(toml::Value::Table(table)) = table.get_mut(first) {
+                remove_key(table, rest);
+            }
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+/// Whether the setting looks like a secret: a string under a name like
+/// `GITHUB_TOKEN` or `api-key`.
+fn is_secret(key: &str, value: &toml::Value) -> bool {
+    if !value.is_str() {
+        return false;
+    }
+    let name = key
+        .rsplit('.')
+        .next()
+        .unwrap_or(key)
+        .to_lowercase()
+        .replace('-', "_");
+    [
+        "token",
+        "secret",
+        "password",
+        "passwd",
+        "api_key",
+        "apikey",
+        "access_key",
+        "private_key",
+        "credential",
+    ]
+    .iter()
+    .any(|word| name.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(settings: &str, keymaps: &str, plugins: &[&str]) -> Profile {
+        Profile {
+            settings: toml::from_str(settings).unwrap(),
+            keymaps: toml::from_str(keymaps).unwrap(),
+            plugins: plugins.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_secrets() {
+        let profile = profile(
+            r#"
+            [editor]
+            todo-tokens = ["TODO"]
+            font-size = 14
+            [terminal.env]
+            GITHUB_TOKEN = "ghp_123"
+            EDITOR = "vim"
+            "#,
+            "",
+            &[],
+        );
+        assert_eq!(profile.secrets(), vec!["terminal.env.GITHUB_TOKEN"]);
+        let exported = profile.without_secrets();
+        assert!(exported.secrets().is_empty());
+        assert_eq!(
+            flatten(&exported.settings).get("terminal.env.EDITOR"),
+            Some(&toml::Value::String("vim".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let current = profile(
+            "[editor]\nfont-size = 13\nline-height = 25\n",
+            "[[keymaps]]\nkey = \"ctrl+a\"\ncommand = \"a\"\n",
+            &["rust"],
+        );
+        let imported = profile(
+            "[editor]\nfont-size = 14\n[lapce]\nmodal = true\n",
+            "[[keymaps]]\nkey = \"ctrl+b\"\ncommand = \"b\"\n",
+            &["go", "rust"],
+        );
+        let target = imported.apply_to(&current, false);
+        assert_eq!(
+            current.changes(&target),
+            vec![
+                ProfileChange::Setting {
+                    key: "editor.font-size".to_string(),
+                    old: Some("13".to_string()),
+                    new: Some("14".to_string()),
+                },
+                ProfileChange::Setting {
+                    key: "lapce.modal".to_string(),
+                    old: None,
+                    new: Some("true".to_string()),
+                },
+                ProfileChange::Keymaps {
+                    added: 1,
+                    removed: 0
+                },
+                ProfileChange::Plugin("go".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let current = profile(
+            "[editor]\nfont-size = 13\nline-height = 25\n",
+            "[[keymaps]]\nkey = \"ctrl+a\"\ncommand = \"a\"\n",
+            &["rust"],
+        );
+        let imported = profile("[editor]\nfont-size = 13\n", "", &[]);
+        let target = imported.apply_to(&current, true);
+        assert_eq!(
+            current.changes(&target),
+            vec![
+                ProfileChange::Setting {
+                    key: "editor.line-height".to_string(),
+                    old: Some("25".to_string()),
+                    new: None,
+                },
+                ProfileChange::Keymaps {
+                    added: 0,
+                    removed: 1
+                },
+            ]
+        );
+        assert_eq!(target.plugins, vec!["rust".to_string()]);
+    }
+}
@@ -1,20 +1,37 @@
 use std::collections::HashMap;
-use std::io::{BufReader, Stdin, Stdout};
+use std::io::{BufReader, Read, Stdin, Stdout};
 use std::process::{Command, Stdio};
 use std::thread;
-use std::{path::PathBuf, process::Child, sync::Arc};
+use std::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    process::Child,
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Result};
 use crossbeam_channel::Receiver;
 use crossbeam_channel::Sender;
-use druid::{ExtEventSink, WidgetId};
+use druid::{Data, ExtEventSink, WidgetId};
 use druid::{Target, WindowId};
 use flate2::read::GzDecoder;
+use lapce_proxy::buffer::SaveConflict;
 use lapce_proxy::dispatch::FileDiff;
-use lapce_proxy::dispatch::{DiffInfo, Dispatcher};
+use lapce_proxy::dispatch::{
+    DiffInfo, Dispatcher, GitFileCommand, GitRemoteCommand,
+};
 use lapce_proxy::dispatch::{FileNodeItem, NewBufferResponse};
+use lapce_proxy::editorconfig;
+use lapce_proxy::format::FormatCommand;
 use lapce_proxy::plugin::PluginDescription;
+use lapce_proxy::protocol::{
+    ProtocolInfo, CAPABILITY_COUNT_FILES, CAPABILITY_FILE_SIZE,
+    CAPABILITY_GIT_CHECKOUT, CAPABILITY_GIT_FILE, CAPABILITY_GIT_REMOTE,
+    CAPABILITY_SEARCH_STREAM, CAPABILITY_SYNC_BUFFER, CAPABILITY_TRASH,
+    CAPABILITY_WRITE_FILE,
+};
 use lapce_proxy::terminal::TermId;
+use lapce_proxy::watcher::FileChanges;
 use lapce_rpc::RpcHandler;
 use lapce_rpc::{stdio_transport, Callback};
 use lapce_rpc::{ControlFlow, Handler};
@@ -30,28 +47,69 @@ use serde_json::Value;
 use xi_rope::RopeDelta;
 
 use crate::command::LapceUICommand;
+use crate::search::SearchOptions;
 use crate::state::LapceWorkspace;
 use crate::state::LapceWorkspaceType;
+use crate::state::{ssh_error_message, SshHost};
 use crate::terminal::RawTerminal;
 use crate::{buffer::BufferId, command::LAPCE_UI_COMMAND};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Shares one connection between the ssh and scp calls to a host.
+const SSH_ARGS: &[&str] = &[
+    "-o",
+    "ControlMaster=auto",
+    "-o",
+    "ControlPath=~/.ssh/cm-%r@%h:%p",
+    "-o",
+    "ControlPersist=30m",
+];
+
 pub enum TermEvent {
     NewTerminal(Arc<Mutex<RawTerminal>>),
     UpdateContent(String),
     CloseTerminal,
 }
 
+/// Where the requests of the UI go: the proxy, or a stand-in for it like
+/// `MockProxy`. The method and params are the ones of the `Request` and
+/// `Notification` of the proxy.
+pub trait ProxyBackend: Send + Sync {
+    fn send_request(&self, method: &str, params: &Value, f: Box<dyn Callback>);
+    fn send_notification(&self, method: &str, params: &Value);
+}
+
+impl ProxyBackend for RpcHandler {
+    fn send_request(&self, method: &str, params: &Value, f: Box<dyn Callback>) {
+        self.send_rpc_request_async(method, params, f);
+    }
+
+    fn send_notification(&self, method: &str, params: &Value) {
+        self.send_rpc_notification(method, params);
+    }
+}
+
+/// Whether the proxy can be reached, which matters for remote workspaces.
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum ProxyStatus {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
 #[derive(Clone)]
 pub struct LapceProxy {
     pub tab_id: WidgetId,
     rpc: RpcHandler,
+    backend: Arc<dyn ProxyBackend>,
     proxy_receiver: Arc<Receiver<Value>>,
     core_sender: Arc<Sender<Value>>,
     core_receiver: Arc<Receiver<Value>>,
     term_tx: Sender<(TermId, TermEvent)>,
     event_sink: ExtEventSink,
+    protocol: Arc<Mutex<Option<ProtocolInfo>>>,
+    status: Arc<Mutex<ProxyStatus>>,
 }
 
 impl Handler for LapceProxy {
@@ -85,6 +143,37 @@ impl Handler for LapceProxy {
                     Target::Widget(self.tab_id),
                 );
             }
+            Notification::EditorconfigChanged {
+                buffer_id,
+                editorconfig,
+            } => {
+                self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::UpdateEditorconfig(buffer_id, editorconfig),
+                    Target::Widget(self.tab_id),
+                );
+            }
+            Notification::FileChanged { buffer_id, content } => {
+                self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::FileChanged(buffer_id, content),
+                    Target::Widget(self.tab_id),
+                );
+            }
+            Notification::FileChanges { changes } => {
+                self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::FileChanges(changes),
+                    Target::Widget(self.tab_id),
+                );
+            }
+            Notification::FileDeleted { buffer_id, deleted } => {
+                self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::FileDeleted(buffer_id, deleted),
+                    Target::Widget(self.tab_id),
+                );
+            }
             Notification::PublishDiagnostics { diagnostics } => {
                 self.event_sink.submit_command(
                     LAPCE_UI_COMMAND,
@@ -119,11 +208,25 @@ impl Handler for LapceProxy {
                 self.term_tx
                     .send((term_id, TermEvent::UpdateContent(content)));
             }
-            Notification::CloseTerminal { term_id } => {
+            Notification::CloseTerminal { term_id, exit_code } => {
                 self.term_tx.send((term_id, TermEvent::CloseTerminal));
                 self.event_sink.submit_command(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::CloseTerminal(term_id),
+                    LapceUICommand::TerminalExited(term_id, exit_code),
+                    Target::Widget(self.tab_id),
+                );
+            }
+            Notification::GlobalSearchResult {
+                search_id,
+                path,
+                matches,
+                context,
+            } => {
+                self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::GlobalSearchResultAdded(
+                        search_id, path, matches, context,
+                    ),
                     Target::Widget(self.tab_id),
                 );
             }
@@ -149,22 +252,52 @@ impl LapceProxy {
         let rpc = RpcHandler::new(proxy_sender);
         let proxy = Self {
             tab_id,
+            backend: Arc::new(rpc.clone()),
             rpc,
             proxy_receiver: Arc::new(proxy_receiver),
             core_sender: Arc::new(core_sender),
             core_receiver: Arc::new(core_receiver),
             term_tx,
             event_sink,
+            protocol: Arc::new(Mutex::new(None)),
+            status: Arc::new(Mutex::new(ProxyStatus::Connecting)),
         };
 
         let local_proxy = proxy.clone();
         thread::spawn(move || {
-            local_proxy.start(workspace);
+            if let Err(e) = local_proxy.start(workspace) {
+                log::error!("can't start the proxy: {}", e);
+                local_proxy.set_status(ProxyStatus::Disconnected);
+            }
         });
 
         proxy
     }
 
+    /// A proxy whose requests go to `backend` instead of a proxy process. It
+    /// reports the capabilities of this build, without a handshake.
+    pub fn with_backend(
+        tab_id: WidgetId,
+        backend: Arc<dyn ProxyBackend>,
+        term_tx: Sender<(TermId, TermEvent)>,
+        event_sink: ExtEventSink,
+    ) -> Self {
+        let (proxy_sender, proxy_receiver) = crossbeam_channel::unbounded();
+        let (core_sender, core_receiver) = crossbeam_channel::unbounded();
+        Self {
+            tab_id,
+            rpc: RpcHandler::new(proxy_sender),
+            backend,
+            proxy_receiver: Arc::new(proxy_receiver),
+            core_sender: Arc::new(core_sender),
+            core_receiver: Arc::new(core_receiver),
+            term_tx,
+            event_sink,
+            protocol: Arc::new(Mutex::new(Some(ProtocolInfo::current()))),
+            status: Arc::new(Mutex::new(ProxyStatus::Connected)),
+        }
+    }
+
     fn start(&self, workspace: LapceWorkspace) -> Result<()> {
         if let Some(path) = workspace.path.as_ref() {
             self.initialize(path.clone());
@@ -176,26 +309,27 @@ impl LapceProxy {
                 thread::spawn(move || {
                     let dispatcher = Dispatcher::new(core_sender);
                     dispatcher.mainloop(proxy_reciever);
-                    println!("proxy dispatcher stopped")
+                    log::info!("proxy dispatcher stopped")
                 });
+                self.handshake();
 
                 let mut proxy = self.clone();
                 let mut handler = self.clone();
                 let core_receiver = (*self.core_receiver).clone();
                 proxy.rpc.mainloop(core_receiver, &mut handler);
             }
-            LapceWorkspaceType::RemoteSSH(user, host) => {
-                let ssh_args = &[
-                    "-o",
-                    "ControlMaster=auto",
-                    "-o",
-                    "ControlPath=~/.ssh/cm-%r@%h:%p",
-                    "-o",
-                    "ControlPersist=30m",
-                ];
+            LapceWorkspaceType::RemoteSSH(host) => {
+                let user_host = host.user_host();
+                // ssh takes the port with -p and scp with -P
+                let port_args = |flag: &str| {
+                    host.port
+                        .map(|port| vec![flag.to_string(), port.to_string()])
+                        .unwrap_or_default()
+                };
                 let cmd = Command::new("ssh")
-                    .arg(format!("{}@{}", user, host))
-                    .args(ssh_args)
+                    .arg(&user_host)
+                    .args(SSH_ARGS)
+                    .args(port_args("-p"))
                     .arg("test")
                     .arg("-e")
                     .arg(format!("~/.lapce/lapce-proxy-{}", VERSION))
@@ -213,23 +347,26 @@ impl LapceProxy {
                         .expect("failed to copy content");
 
                     Command::new("ssh")
-                        .arg(format!("{}@{}", user, host))
-                        .args(ssh_args)
+                        .arg(&user_host)
+                        .args(SSH_ARGS)
+                        .args(port_args("-p"))
                         .arg("mkdir")
                         .arg("~/.lapce/")
                         .output()
                         .unwrap();
 
                     Command::new("scp")
-                        .args(ssh_args)
+                        .args(SSH_ARGS)
+                        .args(port_args("-P"))
                         .arg(&local_path)
-                        .arg(format!("{user}@{host}:~/.lapce/lapce-proxy-{VERSION}"))
+                        .arg(format!("{user_host}:~/.lapce/lapce-proxy-{VERSION}"))
                         .output()
                         .unwrap();
 
                     Command::new("ssh")
-                        .arg(format!("{}@{}", user, host))
-                        .args(ssh_args)
+                        .arg(&user_host)
+                        .args(SSH_ARGS)
+                        .args(port_args("-p"))
                         .arg("chmod")
                         .arg("+x")
                         .arg(format!("~/.lapce/lapce-proxy-{}", VERSION))
@@ -238,8 +375,9 @@ impl LapceProxy {
                 }
 
                 let mut child = Command::new("ssh")
-                    .arg(format!("{}@{}", user, host))
-                    .args(ssh_args)
+                    .arg(&user_host)
+                    .args(SSH_ARGS)
+                    .args(port_args("-p"))
                     .arg(format!("~/.lapce/lapce-proxy-{}", VERSION))
                     .stdin(Stdio::piped())
                     .stdout(Stdio::piped())
@@ -247,9 +385,18 @@ impl LapceProxy {
                 let stdin = child.stdin.take().unwrap();
                 let stdout = BufReader::new(child.stdout.take().unwrap());
 
+                // the connection is gone when ssh exits
+                let proxy = self.clone();
+                thread::spawn(move || {
+                    let _ = child.wait();
+                    log::info!("ssh exited");
+                    proxy.set_status(ProxyStatus::Disconnected);
+                });
+
                 let proxy_reciever = (*self.proxy_receiver).clone();
                 let core_sender = (*self.core_sender).clone();
                 stdio_transport(stdin, proxy_reciever, stdout, core_sender);
+                self.handshake();
 
                 let mut handler = self.clone();
                 let mut proxy = self.clone();
@@ -257,12 +404,101 @@ impl LapceProxy {
                 proxy.rpc.mainloop(core_receiver, &mut handler);
             }
         }
-        println!("proxy stopped");
+        log::info!("proxy stopped");
         Ok(())
     }
 
+    /// Exchanges the protocol versions and capabilities with the proxy. A
+    /// proxy that doesn't answer in time predates the handshake.
+    fn handshake(&self) {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        self.backend.send_request(
+            "handshake",
+            &json!(ProtocolInfo::current()),
+            Box::new(move |result| {
+                let _ = sender.send(result);
+            }),
+        );
+
+        let protocol = self.protocol.clone();
+        let event_sink = self.event_sink.clone();
+        let tab_id = self.tab_id;
+        let proxy = self.clone();
+        thread::spawn(move || {
+            let info = match receiver.recv_timeout(Duration::from_secs(10)) {
+                Ok(Ok(value)) => serde_json::from_value::<ProtocolInfo>(value)
+                    .unwrap_or_else(|_| ProtocolInfo::legacy()),
+                _ => ProtocolInfo::legacy(),
+            };
+            log::info!("proxy protocol {}", info.version);
+            *protocol.lock() = Some(info.clone());
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::UpdateProxyProtocol(info),
+                Target::Widget(tab_id),
+            );
+            proxy.set_status(ProxyStatus::Connected);
+        });
+    }
+
+    pub fn status(&self) -> ProxyStatus {
+        *self.status.lock()
+    }
+
+    /// A proxy that's disconnected stays so, even if a handshake that was
+    /// waiting gives up after it.
+    fn set_status(&self, status: ProxyStatus) {
+        {
+            let mut current = self.status.lock();
+            if *current == status
+                || (*current == ProxyStatus::Disconnected
+                    && status == ProxyStatus::Connected)
+            {
+                return;
+            }
+            *current = status;
+        }
+        let _ = self.event_sink.submit_command(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::UpdateProxyStatus(status),
+            Target::Widget(self.tab_id),
+        );
+    }
+
+    /// What the proxy reported in the handshake, once it's done.
+    pub fn protocol(&self) -> Option<ProtocolInfo> {
+        self.protocol.lock().clone()
+    }
+
+    /// Until the handshake is done the proxy is assumed to be the same build.
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.protocol
+            .lock()
+            .as_ref()
+            .map(|info| info.has_capability(capability))
+            .unwrap_or(true)
+    }
+
+    /// Sends the request the capability stands for. An older proxy without
+    /// it couldn't decode the request, so the callback gets an error instead.
+    fn send_request_if(
+        &self,
+        capability: &str,
+        method: &str,
+        params: &Value,
+        f: Box<dyn Callback>,
+    ) {
+        if self.has_capability(capability) {
+            self.backend.send_request(method, params, f);
+        } else {
+            f.call(Err(json!({
+                "message": format!("The proxy doesn't support {}", method),
+            })));
+        }
+    }
+
     pub fn initialize(&self, workspace: PathBuf) {
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "initialize",
             &json!({
                 "workspace": workspace,
@@ -271,7 +507,7 @@ impl LapceProxy {
     }
 
     pub fn terminal_close(&self, term_id: TermId) {
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "terminal_close",
             &json!({
                 "term_id": term_id,
@@ -280,7 +516,7 @@ impl LapceProxy {
     }
 
     pub fn terminal_resize(&self, term_id: TermId, width: usize, height: usize) {
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "terminal_resize",
             &json!({
                 "term_id": term_id,
@@ -291,7 +527,7 @@ impl LapceProxy {
     }
 
     pub fn terminal_write(&self, term_id: TermId, content: &str) {
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "terminal_write",
             &json!({
                 "term_id": term_id,
@@ -304,20 +540,22 @@ impl LapceProxy {
         &self,
         term_id: TermId,
         cwd: Option<PathBuf>,
+        command: Option<String>,
         raw: Arc<Mutex<RawTerminal>>,
     ) {
         self.term_tx.send((term_id, TermEvent::NewTerminal(raw)));
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "new_terminal",
             &json!({
                 "term_id": term_id,
                 "cwd": cwd,
+                "command": command,
             }),
         )
     }
 
     pub fn git_commit(&self, message: &str, diffs: Vec<FileDiff>) {
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "git_commit",
             &json!({
                 "message": message,
@@ -327,8 +565,8 @@ impl LapceProxy {
     }
 
     pub fn install_plugin(&self, plugin: &PluginDescription) {
-        self.rpc
-            .send_rpc_notification("install_plugin", &json!({ "plugin": plugin }));
+        self.backend
+            .send_notification("install_plugin", &json!({ "plugin": plugin }));
     }
 
     pub fn get_buffer_head(
@@ -337,28 +575,162 @@ impl LapceProxy {
         path: PathBuf,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "buffer_head",
             &json!({ "buffer_id": buffer_id, "path": path, }),
             f,
         );
     }
 
-    pub fn global_search(&self, pattern: String, f: Box<dyn Callback>) {
-        self.rpc.send_rpc_request_async(
+    pub fn global_search(
+        &self,
+        search_id: u64,
+        pattern: String,
+        options: &SearchOptions,
+        include: &[String],
+        context: usize,
+        f: Box<dyn Callback>,
+    ) {
+        // without the matches sent file by file, they come in the response
+        let search_id = if self.has_capability(CAPABILITY_SEARCH_STREAM) {
+            search_id
+        } else {
+            0
+        };
+        self.backend.send_request(
             "global_search",
-            &json!({ "pattern": pattern }),
+            &json!({
+                "pattern": pattern,
+                "search_id": search_id,
+                "case_sensitive": options.case_sensitive,
+                "whole_word": options.whole_word,
+                "regex": options.regex,
+                "include": include,
+                "context": context,
+            }),
+            f,
+        );
+    }
+
+    pub fn cancel_global_search(&self) {
+        if self.has_capability(CAPABILITY_SEARCH_STREAM) {
+            self.backend
+                .send_notification("cancel_global_search", &json!({}));
+        }
+    }
+
+    /// Sets the `files.exclude` patterns the proxy hides files with.
+    pub fn set_file_excludes(&self, patterns: &[String]) {
+        self.backend.send_notification(
+            "set_file_excludes",
+            &json!({
+                "patterns": patterns,
+            }),
+        );
+    }
+
+    /// The working directory of the shell of the terminal, null when the
+    /// proxy can't tell it.
+    pub fn terminal_cwd(&self, term_id: TermId, f: Box<dyn Callback>) {
+        self.backend.send_request(
+            "terminal_cwd",
+            &json!({
+                "term_id": term_id,
+            }),
+            f,
+        );
+    }
+
+    /// Replaces lines of a file that isn't open, as (line number from 1, text,
+    /// replacement).
+    pub fn replace_lines(
+        &self,
+        path: PathBuf,
+        lines: Vec<(usize, String, String)>,
+        f: Box<dyn Callback>,
+    ) {
+        self.backend.send_request(
+            "replace_lines",
+            &json!({
+                "path": path,
+                "lines": lines,
+            }),
+            f,
+        );
+    }
+
+    /// The text of a file that isn't open.
+    pub fn read_file(&self, path: &PathBuf, f: Box<dyn Callback>) {
+        self.backend
+            .send_request("read_file", &json!({ "path": path }), f);
+    }
+
+    /// Replaces the text of a file that isn't open, if it's still `old`.
+    pub fn replace_content(
+        &self,
+        path: PathBuf,
+        old: String,
+        new: String,
+        f: Box<dyn Callback>,
+    ) {
+        self.backend.send_request(
+            "replace_content",
+            &json!({
+                "path": path,
+                "old": old,
+                "new": new,
+            }),
             f,
         );
     }
 
+    /// Writes the text to the file, creating it if it doesn't exist.
+    pub fn write_file(&self, path: &PathBuf, content: &str, f: Box<dyn Callback>) {
+        self.send_request_if(
+            CAPABILITY_WRITE_FILE,
+            "write_file",
+            &json!({
+                "path": path,
+                "content": content,
+            }),
+            f,
+        );
+    }
+
+    /// Writes the content to a new temporary file of the proxy, answering
+    /// its path.
+    pub fn write_temp_file(
+        &self,
+        content: &str,
+        extension: &str,
+        f: Box<dyn Callback>,
+    ) {
+        self.backend.send_request(
+            "write_temp_file",
+            &json!({
+                "content": content,
+                "extension": extension,
+            }),
+            f,
+        );
+    }
+
+    pub fn get_environment(&self, f: Box<dyn Callback>) {
+        self.backend.send_request("get_environment", &json!({}), f);
+    }
+
+    pub fn get_watcher_stats(&self, f: Box<dyn Callback>) {
+        self.backend
+            .send_request("get_watcher_stats", &json!({}), f);
+    }
+
     pub fn new_buffer(
         &self,
         buffer_id: BufferId,
         path: PathBuf,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "new_buffer",
             &json!({ "buffer_id": buffer_id, "path": path }),
             f,
@@ -366,7 +738,7 @@ impl LapceProxy {
     }
 
     pub fn update(&self, buffer_id: BufferId, delta: &RopeDelta, rev: u64) {
-        self.rpc.send_rpc_notification(
+        self.backend.send_notification(
             "update",
             &json!({
                 "buffer_id": buffer_id,
@@ -377,7 +749,7 @@ impl LapceProxy {
     }
 
     pub fn save(&self, rev: u64, buffer_id: BufferId, f: Box<dyn Callback>) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "save",
             &json!({
                 "rev": rev,
@@ -387,6 +759,27 @@ impl LapceProxy {
         );
     }
 
+    /// Gives the proxy the text of the buffer at `rev`, to settle a save
+    /// conflict.
+    pub fn sync_buffer(
+        &self,
+        buffer_id: BufferId,
+        rev: u64,
+        content: String,
+        f: Box<dyn Callback>,
+    ) {
+        self.send_request_if(
+            CAPABILITY_SYNC_BUFFER,
+            "sync_buffer",
+            &json!({
+                "buffer_id": buffer_id,
+                "rev": rev,
+                "content": content,
+            }),
+            f,
+        );
+    }
+
     pub fn get_completion(
         &self,
         request_id: usize,
@@ -394,7 +787,7 @@ impl LapceProxy {
         position: Position,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_completion",
             &json!({
                 "request_id": request_id,
@@ -411,7 +804,7 @@ impl LapceProxy {
         completion_item: CompletionItem,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "completion_resolve",
             &json!({
                 "buffer_id": buffer_id,
@@ -427,7 +820,7 @@ impl LapceProxy {
         position: Position,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_signature",
             &json!({
                 "buffer_id": buffer_id,
@@ -443,7 +836,7 @@ impl LapceProxy {
         position: Position,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_references",
             &json!({
                 "buffer_id": buffer_id,
@@ -453,18 +846,138 @@ impl LapceProxy {
         );
     }
 
-    pub fn get_files(&self, f: Box<dyn Callback>) {
-        self.rpc.send_rpc_request_async(
+    /// The files of the workspace, in the top-level `folders` if given, and
+    /// matching `query` up to `limit` if given.
+    pub fn get_files(
+        &self,
+        folders: Option<Vec<String>>,
+        query: Option<String>,
+        limit: Option<usize>,
+        f: Box<dyn Callback>,
+    ) {
+        self.backend.send_request(
             "get_files",
             &json!({
                 "path": "path",
+                "folders": folders,
+                "query": query,
+                "limit": limit,
+            }),
+            f,
+        );
+    }
+
+    /// Counts the files of the workspace, up to `limit`.
+    pub fn count_files(&self, limit: usize, f: Box<dyn Callback>) {
+        self.send_request_if(
+            CAPABILITY_COUNT_FILES,
+            "count_files",
+            &json!({ "limit": limit }),
+            f,
+        );
+    }
+
+    pub fn get_file_size(&self, path: &PathBuf, f: Box<dyn Callback>) {
+        self.send_request_if(
+            CAPABILITY_FILE_SIZE,
+            "get_file_size",
+            &json!({ "path": path }),
+            f,
+        );
+    }
+
+    pub fn git_checkout(&self, branch: &str, create: bool, f: Box<dyn Callback>) {
+        self.send_request_if(
+            CAPABILITY_GIT_CHECKOUT,
+            "git_checkout",
+            &json!({
+                "branch": branch,
+                "create": create,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_remote(&self, command: GitRemoteCommand, f: Box<dyn Callback>) {
+        self.send_request_if(
+            CAPABILITY_GIT_REMOTE,
+            "git_remote",
+            &json!({
+                "command": command,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_file(
+        &self,
+        command: GitFileCommand,
+        diffs: Vec<FileDiff>,
+        f: Box<dyn Callback>,
+    ) {
+        self.send_request_if(
+            CAPABILITY_GIT_FILE,
+            "git_file",
+            &json!({
+                "command": command,
+                "diffs": diffs,
+            }),
+            f,
+        );
+    }
+
+    pub fn delete_path(&self, path: &PathBuf, staging: bool, f: Box<dyn Callback>) {
+        self.send_request_if(
+            CAPABILITY_TRASH,
+            "delete_path",
+            &json!({
+                "path": path,
+                "staging": staging,
+            }),
+            f,
+        );
+    }
+
+    pub fn list_deleted(
+        &self,
+        staging: bool,
+        retention_days: u64,
+        f: Box<dyn Callback>,
+    ) {
+        self.send_request_if(
+            CAPABILITY_TRASH,
+            "list_deleted",
+            &json!({
+                "staging": staging,
+                "retention_days": retention_days,
+            }),
+            f,
+        );
+    }
+
+    /// Restores the deleted item with the `id`, or the latest one deleted
+    /// from `path`.
+    pub fn restore_deleted(
+        &self,
+        id: Option<&str>,
+        path: Option<&PathBuf>,
+        staging: bool,
+        f: Box<dyn Callback>,
+    ) {
+        self.send_request_if(
+            CAPABILITY_TRASH,
+            "restore_deleted",
+            &json!({
+                "id": id,
+                "path": path,
+                "staging": staging,
             }),
             f,
         );
     }
 
     pub fn read_dir(&self, path: &PathBuf, f: Box<dyn Callback>) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "read_dir",
             &json!({
                 "path": path,
@@ -480,7 +993,7 @@ impl LapceProxy {
         position: Position,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_definition",
             &json!({
                 "request_id": request_id,
@@ -492,7 +1005,7 @@ impl LapceProxy {
     }
 
     pub fn get_document_symbols(&self, buffer_id: BufferId, f: Box<dyn Callback>) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_document_symbols",
             &json!({
                 "buffer_id": buffer_id,
@@ -507,7 +1020,7 @@ impl LapceProxy {
         position: Position,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_code_actions",
             &json!({
                 "buffer_id": buffer_id,
@@ -522,7 +1035,7 @@ impl LapceProxy {
         buffer_id: BufferId,
         f: Box<dyn Callback>,
     ) {
-        self.rpc.send_rpc_request_async(
+        self.backend.send_request(
             "get_document_formatting",
             &json!({
                 "buffer_id": buffer_id,
@@ -531,8 +1044,28 @@ impl LapceProxy {
         );
     }
 
+    /// Formats the text of the file at `path` with a command line tool,
+    /// which runs where the workspace is.
+    pub fn format_with_command(
+        &self,
+        format: &FormatCommand,
+        path: &Path,
+        content: String,
+        f: Box<dyn Callback>,
+    ) {
+        self.backend.send_request(
+            "format_with_command",
+            &json!({
+                "format": format,
+                "path": path,
+                "content": content,
+            }),
+            f,
+        );
+    }
+
     pub fn stop(&self) {
-        self.rpc.send_rpc_notification("shutdown", &json!({}));
+        self.backend.send_notification("shutdown", &json!({}));
         self.core_sender.send(json!({
             "method": "shutdown",
             "params": {},
@@ -540,6 +1073,58 @@ impl LapceProxy {
     }
 }
 
+/// Checks that `ssh` can log in to the host without asking anything, and
+/// calls `f` with the reason when it can't. The returned process can be
+/// killed to give up on the test.
+pub fn test_ssh_connection(
+    host: &SshHost,
+    f: impl FnOnce(Result<(), String>) + Send + 'static,
+) -> std::io::Result<Arc<Mutex<Child>>> {
+    let mut cmd = Command::new("ssh");
+    cmd.arg(host.user_host()).args(SSH_ARGS).args([
+        "-o",
+        "BatchMode=yes",
+        "-o",
+        "ConnectTimeout=10",
+    ]);
+    if let Some(port) = host.port {
+        cmd.arg("-p").arg(port.to_string());
+    }
+    let child = cmd
+        .arg("exit")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let child = Arc::new(Mutex::new(child));
+
+    let process = child.clone();
+    thread::spawn(move || loop {
+        // polled so that the lock is free for a kill in between
+        let status = process.lock().try_wait();
+        match status {
+            Ok(Some(status)) if status.success() => {
+                f(Ok(()));
+                return;
+            }
+            Ok(Some(_)) => {
+                let mut stderr = String::new();
+                if let Some(mut err) = process.lock().stderr.take() {
+                    let _ = err.read_to_string(&mut stderr);
+                }
+                f(Err(ssh_error_message(&stderr)));
+                return;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(100)),
+            Err(e) => {
+                f(Err(e.to_string()));
+                return;
+            }
+        }
+    });
+    Ok(child)
+}
+
 // #[derive(Clone)]
 // pub struct LapceProxy {
 //     peer: Arc<Mutex<Option<RpcPeer>>>,
@@ -1024,6 +1609,24 @@ pub enum Notification {
         new_content: String,
         rev: u64,
     },
+    EditorconfigChanged {
+        buffer_id: BufferId,
+        editorconfig: editorconfig::Properties,
+    },
+    /// The file of the buffer was deleted, or is back.
+    FileDeleted {
+        buffer_id: BufferId,
+        deleted: bool,
+    },
+    /// The file of the buffer changed on disk, with its new text.
+    FileChanged {
+        buffer_id: BufferId,
+        content: String,
+    },
+    /// The files of the workspace that changed, in batches.
+    FileChanges {
+        changes: FileChanges,
+    },
     PublishDiagnostics {
         diagnostics: PublishDiagnosticsParams,
     },
@@ -1046,8 +1649,20 @@ pub enum Notification {
         term_id: TermId,
         content: String,
     },
+    /// The process of the terminal exited, with its exit code when it's a
+    /// task's.
     CloseTerminal {
         term_id: TermId,
+        #[serde(default)]
+        exit_code: Option<i32>,
+    },
+    GlobalSearchResult {
+        search_id: u64,
+        path: PathBuf,
+        matches: Vec<(usize, (usize, usize), String)>,
+        /// The lines around the matches, as (line number from 1, line).
+        #[serde(default)]
+        context: Vec<(usize, String)>,
     },
 }
 
@@ -0,0 +1,625 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use directories::ProjectDirs;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buffer::BufferContent,
+    db::{TabsInfo, WorkspaceInfo},
+    history::write_atomic,
+    state::{fnv_hash, LapceWorkspace},
+};
+
+/// The lock of the running session, which a clean exit removes.
+const LOCK_FILE: &str = "session.lock";
+/// The text of the files with unsaved changes, kept as they're edited.
+const BACKUP_DIR: &str = "backups";
+/// The recovery data of the sessions that weren't restored, or were.
+const ARCHIVE_DIR: &str = "recovery";
+
+lazy_static! {
+    /// The backups this process wrote, which the end of its session drops.
+    /// The others are left to the instances that wrote them.
+    static ref WRITTEN_BACKUPS: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionLock {
+    pid: u32,
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let proj_dirs = ProjectDirs::from("", "", "Lapce")?;
+    Some(proj_dirs.config_dir().to_path_buf())
+}
+
+/// Takes the lock of the session. Returns whether the last session didn't
+/// end cleanly: its lock is left and its process is gone.
+pub fn start_session() -> bool {
+    let dir = match data_dir() {
+        Some(dir) => dir,
+        None => return false,
+    };
+    start_session_in(&dir, std::process::id(), process_alive).unwrap_or_else(|e| {
+        log::error!("can't lock the session: {}", e);
+        false
+    })
+}
+
+/// Releases the lock of the session, and drops the backups it wrote, which
+/// are only kept for a crash.
+pub fn end_session() {
+    if let Some(dir) = data_dir() {
+        end_session_in(&dir, std::process::id());
+    }
+}
+
+fn start_session_in(
+    dir: &Path,
+    pid: u32,
+    alive: impl Fn(u32) -> bool,
+) -> Result<bool> {
+    let path = dir.join(LOCK_FILE);
+    let unclean = match fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<SessionLock>(&content) {
+            // another instance runs, and owns the session
+            Ok(lock) if lock.pid != pid && alive(lock.pid) => return Ok(false),
+            _ => true,
+        },
+        Err(_) => false,
+    };
+    write_atomic(
+        &path,
+        serde_json::to_string(&SessionLock { pid })?.as_bytes(),
+    )?;
+    Ok(unclean)
+}
+
+fn end_session_in(dir: &Path, pid: u32) {
+    let backups = dir.join(BACKUP_DIR);
+    WRITTEN_BACKUPS.lock().retain(|file| {
+        if !file.starts_with(&backups) {
+            return true;
+        }
+        let _ = fs::remove_file(file);
+        false
+    });
+    // only once no other instance has backups left in it
+    let _ = fs::remove_dir(&backups);
+
+    let path = dir.join(LOCK_FILE);
+    let owned = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<SessionLock>(&content).ok())
+        .map(|lock| lock.pid == pid)
+        .unwrap_or(false);
+    if owned {
+        let _ = fs::remove_file(&path);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Where it can't be told, the process is taken to run, so that a running
+/// instance is never taken for a crashed one.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .arg("-0")
+        .arg(pid.to_string())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true)
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout).contains(&pid.to_string())
+        })
+        .unwrap_or(true)
+}
+
+/// The text of a file with unsaved changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// The backup of the file of the workspace, named by a hash which is the
+/// same in every build, so that the next one finds it.
+fn backup_file(dir: &Path, workspace: &LapceWorkspace, path: &Path) -> PathBuf {
+    let key = format!("{}\0{:?}", workspace.file_name(), path);
+    let hash = fnv_hash([key.as_bytes()]);
+    dir.join(BACKUP_DIR).join(format!("{:016x}.json", hash))
+}
+
+pub fn write_backup(
+    workspace: &LapceWorkspace,
+    path: &Path,
+    content: &str,
+) -> Result<()> {
+    let dir = data_dir().ok_or(anyhow!("can't find config dir"))?;
+    write_backup_in(&dir, workspace, path, content)
+}
+
+/// Drops the backup of the file, once its changes are saved.
+pub fn remove_backup(workspace: &LapceWorkspace, path: &Path) -> Result<()> {
+    let dir = data_dir().ok_or(anyhow!("can't find config dir"))?;
+    remove_backup_in(&dir, workspace, path)
+}
+
+fn write_backup_in(
+    dir: &Path,
+    workspace: &LapceWorkspace,
+    path: &Path,
+    content: &str,
+) -> Result<()> {
+    let backup = Backup {
+        path: path.to_path_buf(),
+        content: content.to_string(),
+    };
+    let file = backup_file(dir, workspace, path);
+    write_atomic(&file, serde_json::to_string(&backup)?.as_bytes())?;
+    WRITTEN_BACKUPS.lock().insert(file);
+    Ok(())
+}
+
+fn remove_backup_in(
+    dir: &Path,
+    workspace: &LapceWorkspace,
+    path: &Path,
+) -> Result<()> {
+    let file = backup_file(dir, workspace, path);
+    WRITTEN_BACKUPS.lock().remove(&file);
+    if file.exists() {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+fn load_backups_in(dir: &Path) -> Vec<Backup> {
+    let entries = match fs::read_dir(dir.join(BACKUP_DIR)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut backups: Vec<Backup> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.path().extension().and_then(|e| e.to_str()) == Some("json")
+        })
+        .filter_map(|entry| {
+            let content = fs::read_to_string(entry.path()).ok()?;
+            serde_json::from_str(&content).ok()
+        })
+        .collect();
+    backups.sort_by(|a, b| a.path.cmp(&b.path));
+    backups
+}
+
+/// Something the last session had, which can be restored or skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecoveryItem {
+    /// The tab of the workspace, given by its index, with its split layout.
+    Workspace(usize),
+    /// A file the split of the workspace had open.
+    File(usize, PathBuf),
+    /// How many terminals the workspace had.
+    Terminals(usize, usize),
+    /// The unsaved changes of a file, by the index of its backup.
+    Backup(usize),
+}
+
+/// What the last session had when it didn't end cleanly, and which of it to
+/// restore.
+#[derive(Clone, Debug)]
+pub struct SessionRecovery {
+    pub tabs: TabsInfo,
+    /// The layout of each workspace of `tabs`, where it was saved.
+    pub infos: Vec<Option<WorkspaceInfo>>,
+    pub backups: Vec<Backup>,
+    /// The items, and whether each is restored.
+    pub items: Vec<(RecoveryItem, bool)>,
+}
+
+#[derive(Serialize)]
+struct ArchivedSession<'a> {
+    tabs: &'a TabsInfo,
+    infos: &'a [Option<WorkspaceInfo>],
+}
+
+impl SessionRecovery {
+    /// The recovery of the session, `None` when it had nothing to restore.
+    pub fn new(
+        tabs: TabsInfo,
+        infos: Vec<Option<WorkspaceInfo>>,
+        backups: Vec<Backup>,
+    ) -> Option<Self> {
+        let mut items = Vec::new();
+        for (i, info) in infos.iter().enumerate() {
+            // a window without a folder has nothing to restore
+            if tabs.workspaces[i].path.is_none() {
+                continue;
+            }
+            items.push((RecoveryItem::Workspace(i), true));
+            let info = match info {
+                Some(info) => info,
+                None => continue,
+            };
+            for child in info.split.children.iter() {
+                if let BufferContent::File(path) = &child.editor.content {
+                    items.push((RecoveryItem::File(i, path.clone()), true));
+                }
+            }
+            if info.terminals > 0 {
+                items.push((RecoveryItem::Terminals(i, info.terminals), true));
+            }
+        }
+        for i in 0..backups.len() {
+            items.push((RecoveryItem::Backup(i), true));
+        }
+        if items.is_empty() {
+            return None;
+        }
+        Some(Self {
+            tabs,
+            infos,
+            backups,
+            items,
+        })
+    }
+
+    /// Reads what the last session saved and its backups.
+    pub fn load(
+        tabs: TabsInfo,
+        info: impl Fn(&LapceWorkspace) -> Option<WorkspaceInfo>,
+    ) -> Option<Self> {
+        let dir = data_dir()?;
+        let infos = tabs.workspaces.iter().map(|w| info(w)).collect();
+        Self::new(tabs, infos, load_backups_in(&dir))
+    }
+
+    pub fn description(&self, item: &RecoveryItem) -> String {
+        match item {
+            RecoveryItem::Workspace(i) => {
+                let path = self.tabs.workspaces[*i]
+                    .path
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default();
+                format!("Window layout of {}", path)
+            }
+            RecoveryItem::File(i, path) => {
+                let path = self.tabs.workspaces[*i]
+                    .path
+                    .as_ref()
+                    .and_then(|root| path.strip_prefix(root).ok())
+                    .unwrap_or(path);
+                format!("    Reopen {}", path.display())
+            }
+            RecoveryItem::Terminals(_, 1) => "    Reopen 1 terminal".to_string(),
+            RecoveryItem::Terminals(_, n) => format!("    Reopen {} terminals", n),
+            RecoveryItem::Backup(i) => {
+                format!("Unsaved changes to {}", self.backups[*i].path.display())
+            }
+        }
+    }
+
+    fn is_restored(&self, item: &RecoveryItem) -> bool {
+        self.items
+            .iter()
+            .any(|(restored, checked)| *checked && restored == item)
+    }
+
+    /// Restores the item, or skips it.
+    pub fn toggle(&mut self, index: usize) {
+        if let Some((_, checked)) = self.items.get_mut(index) {
+            *checked = !*checked;
+        }
+    }
+
+    /// The workspaces to open, with their layout without the files skipped,
+    /// how many terminals to open, and whether it was the active tab.
+    pub fn restored_workspaces(
+        &self,
+    ) -> Vec<(LapceWorkspace, Option<WorkspaceInfo>, usize, bool)> {
+        self.tabs
+            .workspaces
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_restored(&RecoveryItem::Workspace(*i)))
+            .map(|(i, workspace)| {
+                let info = self.infos[i].clone().map(|info| {
+                    without_files(info, |path| {
+                        !self.is_restored(&RecoveryItem::File(i, path.to_path_buf()))
+                    })
+                });
+                let terminals = info
+                    .as_ref()
+                    .map(|info| info.terminals)
+                    .filter(|n| self.is_restored(&RecoveryItem::Terminals(i, *n)))
+                    .unwrap_or(0);
+                (
+                    workspace.clone(),
+                    info,
+                    terminals,
+                    i == self.tabs.active_tab,
+                )
+            })
+            .collect()
+    }
+
+    pub fn restored_backups(&self) -> Vec<&Backup> {
+        self.backups
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_restored(&RecoveryItem::Backup(*i)))
+            .map(|(_, backup)| backup)
+            .collect()
+    }
+
+    /// Moves the recovery data out of the way, to a folder of the data
+    /// directory where it can still be found. Returns the folder.
+    pub fn archive(&self) -> Result<PathBuf> {
+        let dir = data_dir().ok_or(anyhow!("can't find config dir"))?;
+        self.archive_in(&dir)
+    }
+
+    fn archive_in(&self, dir: &Path) -> Result<PathBuf> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let archive = dir.join(ARCHIVE_DIR).join(secs.to_string());
+        let session = ArchivedSession {
+            tabs: &self.tabs,
+            infos: &self.infos,
+        };
+        write_atomic(
+            &archive.join("session.json"),
+            serde_json::to_string_pretty(&session)?.as_bytes(),
+        )?;
+        let backups = dir.join(BACKUP_DIR);
+        if backups.exists() {
+            fs::rename(&backups, archive.join(BACKUP_DIR))?;
+        }
+        Ok(archive)
+    }
+}
+
+/// The layout without the editors of the files `skip` is true for.
+fn without_files(
+    mut info: WorkspaceInfo,
+    skip: impl Fn(&Path) -> bool,
+) -> WorkspaceInfo {
+    let active = info
+        .split
+        .children
+        .get(info.active_editor)
+        .map(|c| c.editor.content.clone());
+    info.split
+        .children
+        .retain(|child| match &child.editor.content {
+            BufferContent::File(path) => !skip(path),
+            BufferContent::Local(_) => true,
+        });
+    info.active_editor = info
+        .split
+        .children
+        .iter()
+        .position(|child| Some(&child.editor.content) == active.as_ref())
+        .unwrap_or(0);
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::{EditorInfo, SplitChildInfo, SplitInfo},
+        split::SplitDirection,
+        state::LapceWorkspaceType,
+    };
+
+    fn workspace(path: &str) -> LapceWorkspace {
+        LapceWorkspace {
+            kind: LapceWorkspaceType::Local,
+            path: Some(PathBuf::from(path)),
+            last_open: 0,
+        }
+    }
+
+    fn info(
+        files: &[&str],
+        active_editor: usize,
+        terminals: usize,
+    ) -> WorkspaceInfo {
+        WorkspaceInfo {
+            active_editor,
+            split: SplitInfo {
+                direction: SplitDirection::Vertical,
+                children: files
+                    .iter()
+                    .map(|file| SplitChildInfo {
+                        flex: 1.0,
+                        editor: EditorInfo {
+                            content: BufferContent::File(PathBuf::from(file)),
+                            scroll_offset: (0.0, 0.0),
+                            position: None,
+                            shown_files: Vec::new(),
+                            current_shown: 0,
+                        },
+                    })
+                    .collect(),
+            },
+            search_options: Default::default(),
+            pinned_searches: Vec::new(),
+            search_context: false,
+            index_scope: None,
+            terminals,
+        }
+    }
+
+    #[test]
+    fn test_session_lock() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let alive = |pid| pid == 2;
+        assert!(!start_session_in(&dir, 2, alive).unwrap());
+        // the session of a running instance isn't taken over
+        assert!(!start_session_in(&dir, 1, alive).unwrap());
+        end_session_in(&dir, 1);
+        assert!(dir.join(LOCK_FILE).exists());
+
+        // the process of the session is gone
+        assert!(start_session_in(&dir, 3, |_| false).unwrap());
+        write_backup_in(&dir, &workspace("/w"), Path::new("/w/a"), "a").unwrap();
+        end_session_in(&dir, 3);
+        assert!(!dir.join(LOCK_FILE).exists());
+        assert!(load_backups_in(&dir).is_empty());
+        assert!(!start_session_in(&dir, 4, alive).unwrap());
+    }
+
+    #[test]
+    fn test_end_session_keeps_other_backups() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        // a backup another instance wrote
+        let other = Backup {
+            path: PathBuf::from("/v/b"),
+            content: "b".to_string(),
+        };
+        write_atomic(
+            &dir.join(BACKUP_DIR).join("other.json"),
+            serde_json::to_string(&other).unwrap().as_bytes(),
+        )
+        .unwrap();
+        start_session_in(&dir, 1, |_| false).unwrap();
+        write_backup_in(&dir, &workspace("/w"), Path::new("/w/a"), "a").unwrap();
+        end_session_in(&dir, 1);
+        assert_eq!(load_backups_in(&dir), vec![other]);
+    }
+
+    #[test]
+    fn test_backups() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let w = workspace("/w");
+        write_backup_in(&dir, &w, Path::new("/w/b"), "one").unwrap();
+        write_backup_in(&dir, &w, Path::new("/w/a"), "two").unwrap();
+        write_backup_in(&dir, &w, Path::new("/w/b"), "three").unwrap();
+        remove_backup_in(&dir, &w, Path::new("/w/c")).unwrap();
+        assert_eq!(
+            load_backups_in(&dir),
+            vec![
+                Backup {
+                    path: PathBuf::from("/w/a"),
+                    content: "two".to_string(),
+                },
+                Backup {
+                    path: PathBuf::from("/w/b"),
+                    content: "three".to_string(),
+                },
+            ]
+        );
+        remove_backup_in(&dir, &w, Path::new("/w/a")).unwrap();
+        assert_eq!(load_backups_in(&dir).len(), 1);
+
+        // the same file in another workspace has its own backup
+        let v = workspace("/v");
+        write_backup_in(&dir, &v, Path::new("/w/b"), "four").unwrap();
+        assert_eq!(load_backups_in(&dir).len(), 2);
+        assert_ne!(
+            backup_file(&dir, &w, Path::new("/w/b")),
+            backup_file(&dir, &v, Path::new("/w/b"))
+        );
+    }
+
+    #[test]
+    fn test_restore() {
+        let tabs = TabsInfo {
+            active_tab: 1,
+            workspaces: vec![workspace("/w"), workspace("/v")],
+        };
+        let infos =
+            vec![Some(info(&["/w/huge_file.sql", "/w/main.rs"], 1, 2)), None];
+        let backups = vec![Backup {
+            path: PathBuf::from("/w/main.rs"),
+            content: "fn main() {}".to_string(),
+        }];
+        let mut recovery = SessionRecovery::new(tabs, infos, backups).unwrap();
+        let items: Vec<String> = recovery
+            .items
+            .iter()
+            .map(|(item, _)| recovery.description(item))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                "Window layout of /w",
+                "    Reopen huge_file.sql",
+                "    Reopen main.rs",
+                "    Reopen 2 terminals",
+                "Window layout of /v",
+                "Unsaved changes to /w/main.rs",
+            ]
+        );
+
+        recovery.toggle(1);
+        recovery.toggle(3);
+        recovery.toggle(4);
+        let workspaces = recovery.restored_workspaces();
+        assert_eq!(workspaces.len(), 1);
+        let (workspace, info, terminals, active) = &workspaces[0];
+        assert_eq!(workspace.path, Some(PathBuf::from("/w")));
+        let info = info.as_ref().unwrap();
+        assert_eq!(info.split.children.len(), 1);
+        assert_eq!(
+            info.split.children[0].editor.content,
+            BufferContent::File(PathBuf::from("/w/main.rs"))
+        );
+        assert_eq!(info.active_editor, 0);
+        assert_eq!(*terminals, 0);
+        assert!(!active);
+        assert_eq!(recovery.restored_backups().len(), 1);
+
+        let empty = TabsInfo {
+            active_tab: 0,
+            workspaces: Vec::new(),
+        };
+        assert!(SessionRecovery::new(empty, Vec::new(), Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_archive() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        write_backup_in(&dir, &workspace("/w"), Path::new("/w/a"), "a").unwrap();
+        let tabs = TabsInfo {
+            active_tab: 0,
+            workspaces: vec![workspace("/w")],
+        };
+        let recovery =
+            SessionRecovery::new(tabs, vec![None], load_backups_in(&dir)).unwrap();
+        let archive = recovery.archive_in(&dir).unwrap();
+        assert!(load_backups_in(&dir).is_empty());
+        assert_eq!(load_backups_in(&archive).len(), 1);
+        assert!(archive.join("session.json").exists());
+    }
+}
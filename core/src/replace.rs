@@ -0,0 +1,339 @@
+use regex::{Captures, Regex};
+
+use crate::search::SearchOptions;
+
+/// How a part of the replacement changes the case of what follows it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CaseChange {
+    /// `\u`, the next character in upper case.
+    UpperNext,
+    /// `\l`, the next character in lower case.
+    LowerNext,
+    /// `\U`, in upper case until `\E`.
+    Upper,
+    /// `\L`, in lower case until `\E`.
+    Lower,
+    /// `\E`
+    End,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Group {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Group(Group),
+    Case(CaseChange),
+}
+
+/// What the matches of a search are replaced with. With regex search on, the
+/// replacement takes `$1` and `${name}` for the groups of the match, `\n` and
+/// `\t`, and `\u`, `\l`, `\U` and `\L` up to `\E` to change the case. Without
+/// it, the replacement is taken as it is.
+#[derive(Clone, Debug)]
+pub struct Replacer {
+    regex: Regex,
+    pieces: Vec<Piece>,
+    /// Whether the replacement takes the case of the match: lower case,
+    /// title case or upper case.
+    preserve_case: bool,
+}
+
+impl Replacer {
+    /// The replacer of the matches of `query`, or why the query or the
+    /// replacement is invalid.
+    pub fn new(
+        query: &str,
+        options: &SearchOptions,
+        replacement: &str,
+        preserve_case: bool,
+    ) -> Result<Self, String> {
+        let regex = options.build_regex(query)?;
+        let pieces = if options.regex {
+            parse(replacement, &regex)?
+        } else {
+            vec![Piece::Text(replacement.to_string())]
+        };
+        Ok(Self {
+            regex,
+            pieces,
+            preserve_case,
+        })
+    }
+
+    /// The replacement of the match from `start` to `end` of the line, in
+    /// bytes.
+    pub fn replacement(&self, line: &str, start: usize, end: usize) -> String {
+        let captures = self
+            .regex
+            .captures_iter(line)
+            .find(|c| c.get(0).map(|m| m.start()) == Some(start));
+        let matched = line.get(start..end).unwrap_or("");
+        self.replace_match(captures.as_ref(), matched)
+    }
+
+    /// The line with all the matches in it replaced.
+    pub fn replace_line(&self, line: &str) -> String {
+        self.regex
+            .replace_all(line, |captures: &Captures| {
+                self.replace_match(Some(captures), &captures[0])
+            })
+            .into_owned()
+    }
+
+    fn replace_match(&self, captures: Option<&Captures>, matched: &str) -> String {
+        let replacement = expand(&self.pieces, captures, matched);
+        if self.preserve_case {
+            preserve_case(matched, &replacement)
+        } else {
+            replacement
+        }
+    }
+}
+
+/// The pieces of the replacement, checking that the groups it refers to are
+/// in the regex.
+fn parse(replacement: &str, regex: &Regex) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = replacement.chars().peekable();
+    let push = |pieces: &mut Vec<Piece>, text: &mut String, piece: Piece| {
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(text)));
+        }
+        pieces.push(piece);
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => match chars.peek().copied() {
+                Some('$') => {
+                    chars.next();
+                    text.push('$');
+                }
+                Some('{') => {
+                    chars.next();
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(format!(
+                                    "Invalid replacement: unclosed ${{{}",
+                                    name
+                                ))
+                            }
+                        }
+                    }
+                    let group = match name.parse::<usize>() {
+                        Ok(index) => Group::Index(index),
+                        Err(_) => Group::Name(name),
+                    };
+                    check_group(&group, regex)?;
+                    push(&mut pieces, &mut text, Piece::Group(group));
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                        digits.push(*c);
+                        chars.next();
+                    }
+                    let group = Group::Index(digits.parse().unwrap_or(0));
+                    check_group(&group, regex)?;
+                    push(&mut pieces, &mut text, Piece::Group(group));
+                }
+                _ => text.push('$'),
+            },
+            '\\' => {
+                let case = match chars.peek().copied() {
+                    Some('n') => {
+                        text.push('\n');
+                        None
+                    }
+                    Some('t') => {
+                        text.push('\t');
+                        None
+                    }
+                    Some('\\') => {
+                        text.push('\\');
+                        None
+                    }
+                    Some('u') => Some(CaseChange::UpperNext),
+                    Some('l') => Some(CaseChange::LowerNext),
+                    Some('U') => Some(CaseChange::Upper),
+                    Some('L') => Some(CaseChange::Lower),
+                    Some('E') => Some(CaseChange::End),
+                    _ => {
+                        text.push('\\');
+                        continue;
+                    }
+                };
+                chars.next();
+                if let Some(case) = case {
+                    push(&mut pieces, &mut text, Piece::Case(case));
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+fn check_group(group: &Group, regex: &Regex) -> Result<(), String> {
+    match group {
+        Group::Index(index) if *index >= regex.captures_len() => {
+            Err(format!("Invalid replacement: there's no group {}", index))
+        }
+        Group::Name(name) if !regex.capture_names().any(|n| n == Some(name)) => Err(
+            format!("Invalid replacement: there's no group named {}", name),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn expand(pieces: &[Piece], captures: Option<&Captures>, matched: &str) -> String {
+    let mut result = String::new();
+    let mut next = None;
+    let mut until_end = None;
+    for piece in pieces {
+        let text = match piece {
+            Piece::Text(text) => text.as_str(),
+            Piece::Group(group) => {
+                let m = match (group, captures) {
+                    (Group::Index(0), None) => Some(matched),
+                    (Group::Index(index), Some(captures)) => {
+                        captures.get(*index).map(|m| m.as_str())
+                    }
+                    (Group::Name(name), Some(captures)) => {
+                        captures.name(name).map(|m| m.as_str())
+                    }
+                    _ => None,
+                };
+                m.unwrap_or("")
+            }
+            Piece::Case(case) => {
+                match case {
+                    CaseChange::UpperNext | CaseChange::LowerNext => {
+                        next = Some(*case)
+                    }
+                    CaseChange::Upper | CaseChange::Lower => until_end = Some(*case),
+                    CaseChange::End => until_end = None,
+                }
+                continue;
+            }
+        };
+        let mut chars = text.chars();
+        if let Some(case) = next.filter(|_| !text.is_empty()) {
+            let first = chars.next().unwrap();
+            if case == CaseChange::UpperNext {
+                result.extend(first.to_uppercase());
+            } else {
+                result.extend(first.to_lowercase());
+            }
+            next = None;
+        }
+        let rest = chars.as_str();
+        match until_end {
+            Some(CaseChange::Upper) => result.push_str(&rest.to_uppercase()),
+            Some(CaseChange::Lower) => result.push_str(&rest.to_lowercase()),
+            _ => result.push_str(rest),
+        }
+    }
+    result
+}
+
+/// The replacement in the case of the match, when it's all lower case, all
+/// upper case or starts with an upper case letter. Otherwise it's left as
+/// it is.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let letters = || matched.chars().filter(|c| c.is_alphabetic());
+    if letters().next().is_none() {
+        return replacement.to_string();
+    }
+    if letters().all(|c| c.is_lowercase()) {
+        return replacement.to_lowercase();
+    }
+    if letters().all(|c| c.is_uppercase()) && letters().count() > 1 {
+        return replacement.to_uppercase();
+    }
+    if matched.starts_with(|c: char| c.is_uppercase()) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex_options() -> SearchOptions {
+        SearchOptions {
+            case_sensitive: true,
+            whole_word: false,
+            regex: true,
+        }
+    }
+
+    #[test]
+    fn test_groups_and_case() {
+        let replacer = Replacer::new(
+            r"(\w+)_(?P<b>\w+)",
+            &regex_options(),
+            r"\u$1\u${b}",
+            false,
+        )
+        .unwrap();
+        assert_eq!(replacer.replace_line("let foo_bar = 1;"), "let FooBar = 1;");
+        let replacer =
+            Replacer::new(r"(\w+)_(\w+)", &regex_options(), r"\U$1\E-$2$$\t", false)
+                .unwrap();
+        assert_eq!(replacer.replace_line("foo_bar"), "FOO-bar$\t");
+    }
+
+    #[test]
+    fn test_replacement_of_each_match() {
+        let replacer =
+            Replacer::new(r"a(\d)", &regex_options(), "b${1}0", false).unwrap();
+        let line = "a1 a2";
+        assert_eq!(replacer.replacement(line, 3, 5), "b20");
+        assert_eq!(replacer.replace_line(line), "b10 b20");
+    }
+
+    #[test]
+    fn test_invalid_groups() {
+        let options = regex_options();
+        assert!(Replacer::new(r"(a)", &options, "$2", false)
+            .unwrap_err()
+            .contains("no group 2"));
+        assert!(Replacer::new(r"(a)", &options, "${x}", false)
+            .unwrap_err()
+            .contains("no group named x"));
+        assert!(Replacer::new(r"(a)", &options, "${1", false).is_err());
+        // without regex, the replacement is plain text
+        let replacer =
+            Replacer::new("a.", &SearchOptions::default(), "$2\\n", false).unwrap();
+        assert_eq!(replacer.replace_line("a.ba.A."), "$2\\nb$2\\n$2\\n");
+    }
+
+    #[test]
+    fn test_preserve_case() {
+        assert_eq!(preserve_case("foo", "BarBaz"), "barbaz");
+        assert_eq!(preserve_case("FOO", "barBaz"), "BARBAZ");
+        assert_eq!(preserve_case("Foo", "barBaz"), "BarBaz");
+        assert_eq!(preserve_case("fOO", "barBaz"), "barBaz");
+        let replacer =
+            Replacer::new("foo", &SearchOptions::default(), "bar", true).unwrap();
+        assert_eq!(replacer.replace_line("foo Foo FOO"), "bar Bar BAR");
+    }
+}
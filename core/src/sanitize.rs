@@ -0,0 +1,190 @@
+use std::borrow::Cow;
+
+/// The text of a paste that contained characters `sanitize` would change,
+/// kept until the user decides whether to fix them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PastedText {
+    /// The buffer revision right after the paste. The pasted regions are only
+    /// valid as long as the buffer stays at this revision.
+    pub rev: u64,
+    pub regions: Vec<(usize, usize)>,
+    pub count: usize,
+}
+
+/// What a character that is invisible, or easily confused with an ascii one,
+/// should be replaced with in source code. An empty string strips it.
+fn replacement(c: char) -> Option<&'static str> {
+    let replacement = match c {
+        '\t' | '\n' | '\r' => return None,
+        '\u{a0}'
+        | '\u{2000}'..='\u{200a}'
+        | '\u{202f}'
+        | '\u{205f}'
+        | '\u{3000}' => " ",
+        '\u{ad}' | '\u{200b}'..='\u{200f}' | '\u{2060}' | '\u{feff}' => "",
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' => "\"",
+        '\u{2010}' | '\u{2011}' | '\u{2212}' => "-",
+        '\u{2028}' | '\u{2029}' => "\n",
+        c if c.is_control() => "",
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+/// Skips the terminal escape sequence starting at the ESC character.
+fn skip_escape(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    match chars.next() {
+        // CSI: parameters and intermediates, up to the final byte.
+        Some('[') => {
+            while let Some(c) = chars.next() {
+                if ('\u{40}'..='\u{7e}').contains(&c) {
+                    break;
+                }
+            }
+        }
+        // OSC, DCS and friends: up to BEL or ST.
+        Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => {
+            while let Some(c) = chars.next() {
+                if c == '\u{7}' {
+                    break;
+                }
+                if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                    chars.next();
+                    break;
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Strips terminal escape sequences and invisible characters from the text,
+/// and converts typographic quotes and spaces to their ascii counterparts.
+/// Returns the new text and how many sequences or characters were changed.
+pub fn sanitize(text: &str) -> (String, usize) {
+    let mut result = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            skip_escape(&mut chars);
+            count += 1;
+        } else if let Some(replacement) = replacement(c) {
+            result.push_str(replacement);
+            count += 1;
+        } else {
+            result.push(c);
+        }
+    }
+    (result, count)
+}
+
+/// Replaces the invisible characters of the line with a visible glyph of the
+/// same utf8 length, so that the byte offsets into the line stay the same.
+/// The replaced ranges are returned as well, for highlighting.
+pub fn visible_line(line: &str) -> (Cow<str>, Vec<(usize, usize)>) {
+    let mut ranges = Vec::new();
+    let mut visible: Option<String> = None;
+    for (i, c) in line.char_indices() {
+        let replacement = match replacement(c) {
+            Some(replacement) => replacement,
+            None => continue,
+        };
+        let end = i + c.len_utf8();
+        ranges.push((i, end));
+        if !replacement.trim().is_empty() {
+            // The typographic quotes and dashes are visible already.
+            continue;
+        }
+        let glyph = match c.len_utf8() {
+            1 => '?',
+            2 => '\u{b7}',
+            3 => '\u{2219}',
+            _ => continue,
+        };
+        let visible = visible.get_or_insert_with(|| line.to_string());
+        let mut buf = [0; 4];
+        visible.replace_range(i..end, glyph.encode_utf8(&mut buf));
+    }
+    match visible {
+        Some(visible) => (Cow::Owned(visible), ranges),
+        None => (Cow::Borrowed(line), ranges),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replacement() {
+        assert_eq!(replacement('\t'), None);
+        assert_eq!(replacement('\n'), None);
+        assert_eq!(replacement('é'), None);
+        assert_eq!(replacement('\u{a0}'), Some(" "));
+        assert_eq!(replacement('\u{feff}'), Some(""));
+        assert_eq!(replacement('\u{2019}'), Some("'"));
+        assert_eq!(replacement('\u{201c}'), Some("\""));
+        assert_eq!(replacement('\u{2212}'), Some("-"));
+        assert_eq!(replacement('\u{2029}'), Some("\n"));
+        assert_eq!(replacement('\u{0}'), Some(""));
+    }
+
+    #[test]
+    fn test_sanitize_escape_sequences() {
+        assert_eq!(sanitize("\u{1b}[1;31mred\u{1b}[0m"), ("red".to_string(), 2));
+        // OSC ended by BEL, and by ST
+        assert_eq!(sanitize("\u{1b}]0;title\u{7}text"), ("text".to_string(), 1));
+        assert_eq!(
+            sanitize("\u{1b}]8;;http://a.b\u{1b}\\link\u{1b}]8;;\u{1b}\\"),
+            ("link".to_string(), 2)
+        );
+        // an unterminated sequence runs to the end
+        assert_eq!(sanitize("a\u{1b}[12"), ("a".to_string(), 1));
+        assert_eq!(sanitize("a\u{1b}]0;title"), ("a".to_string(), 1));
+    }
+
+    #[test]
+    fn test_sanitize_lone_escape() {
+        assert_eq!(sanitize("a\u{1b}"), ("a".to_string(), 1));
+        // a two character sequence, like ESC 7
+        assert_eq!(sanitize("\u{1b}7ab"), ("ab".to_string(), 1));
+    }
+
+    #[test]
+    fn test_sanitize_multi_byte() {
+        assert_eq!(
+            sanitize("\u{201c}héllo\u{201d}\u{a0}naïve\u{200b}!"),
+            ("\"héllo\" naïve!".to_string(), 4)
+        );
+        assert_eq!(
+            sanitize("fn main() {\n\tlet a = 1;\r\n}"),
+            ("fn main() {\n\tlet a = 1;\r\n}".to_string(), 0)
+        );
+        assert_eq!(sanitize("a\u{7}b"), ("ab".to_string(), 1));
+    }
+
+    #[test]
+    fn test_visible_line() {
+        let (line, ranges) = visible_line("abc");
+        assert!(matches!(line, Cow::Borrowed("abc")));
+        assert!(ranges.is_empty());
+
+        // each glyph has the utf8 length of the character it stands for
+        let (line, ranges) = visible_line("a\u{7}b");
+        assert_eq!(line, "a?b");
+        assert_eq!(ranges, vec![(1, 2)]);
+        let (line, ranges) = visible_line("é\u{a0}x");
+        assert_eq!(line, "é\u{b7}x");
+        assert_eq!(ranges, vec![(2, 4)]);
+        let (line, ranges) = visible_line("a\u{200b}b");
+        assert_eq!(line, "a\u{2219}b");
+        assert_eq!(ranges, vec![(1, 4)]);
+
+        // the quotes are visible already, but still highlighted
+        let (line, ranges) = visible_line("\u{201c}x\u{201d}");
+        assert!(matches!(line, Cow::Borrowed(_)));
+        assert_eq!(ranges, vec![(0, 3), (4, 7)]);
+    }
+}
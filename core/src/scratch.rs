@@ -0,0 +1,86 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use directories::ProjectDirs;
+
+use crate::state::LapceWorkspace;
+
+pub const SCRATCH_LANGUAGES: &[(&str, &str)] = &[
+    ("Plain Text", "txt"),
+    ("Rust", "rs"),
+    ("JavaScript", "js"),
+    ("Go", "go"),
+];
+
+fn scratch_root() -> Option<PathBuf> {
+    let proj_dirs = ProjectDirs::from("", "", "Lapce")?;
+    Some(proj_dirs.config_dir().join("scratches"))
+}
+
+/// The scratches of a workspace live in the config dir rather than in the
+/// project tree, in a folder named after the workspace. They're on this
+/// machine, so only the proxy of a local workspace can open them.
+pub fn scratch_dir(workspace: &LapceWorkspace) -> Option<PathBuf> {
+    Some(scratch_root()?.join(workspace.file_name()))
+}
+
+pub fn is_scratch(path: &Path) -> bool {
+    scratch_root()
+        .map(|root| path.starts_with(root))
+        .unwrap_or(false)
+}
+
+pub fn new_scratch(workspace: &LapceWorkspace, extension: &str) -> Result<PathBuf> {
+    let dir = scratch_dir(workspace).ok_or(anyhow!("can't find config dir"))?;
+    fs::create_dir_all(&dir)?;
+    let mut i = 1;
+    loop {
+        let path = dir.join(format!("scratch-{}.{}", i, extension));
+        if !path.exists() {
+            fs::File::create(&path)?;
+            return Ok(path);
+        }
+        i += 1;
+    }
+}
+
+/// Returns the scratches of the workspace with the first non empty line of
+/// each, most recently modified first.
+pub fn list_scratches(workspace: &LapceWorkspace) -> Vec<(PathBuf, String)> {
+    let dir = match scratch_dir(workspace) {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut scratches: Vec<(SystemTime, PathBuf, String)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let preview = fs::File::open(&path)
+                .ok()
+                .and_then(|f| {
+                    BufReader::new(f)
+                        .lines()
+                        .filter_map(|l| l.ok())
+                        .find(|l| l.trim() != "")
+                })
+                .map(|l| l.trim().to_string())
+                .unwrap_or("".to_string());
+            (modified, path, preview)
+        })
+        .collect();
+    scratches.sort_by(|a, b| b.0.cmp(&a.0));
+    scratches.into_iter().map(|(_, p, l)| (p, l)).collect()
+}
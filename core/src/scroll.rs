@@ -12,7 +12,7 @@ use druid::{
 
 use crate::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
-    config::{Config, GetConfig, LapceTheme},
+    config::{Config, GetConfig, LapceTheme, UI_ANIMATIONS},
 };
 
 /// Represents the size and position of a rectangular "viewport" into a larger area.
@@ -885,8 +885,13 @@ impl ScrollComponent {
                     }
                 }
                 Event::Timer(id) if *id == self.timer_id => {
-                    // Schedule scroll bars animation
-                    ctx.request_anim_frame();
+                    if env.get(UI_ANIMATIONS) {
+                        // Schedule scroll bars animation
+                        ctx.request_anim_frame();
+                    } else {
+                        self.opacity = 0.0;
+                        ctx.request_paint();
+                    }
                     self.timer_id = TimerToken::INVALID;
                     ctx.set_handled();
                 }
@@ -1609,10 +1614,15 @@ impl ScrollComponentNew {
                     }
                 }
                 Event::Timer(id) if *id == self.timer_id => {
-                    // Schedule scroll bars animation
                     self.timer_id = TimerToken::INVALID;
-                    self.fade_interval_id =
-                        ctx.request_timer(Duration::from_millis(20));
+                    if env.get(UI_ANIMATIONS) {
+                        // Schedule scroll bars animation
+                        self.fade_interval_id =
+                            ctx.request_timer(Duration::from_millis(20));
+                    } else {
+                        self.opacity = 0.0;
+                        ctx.request_paint();
+                    }
                     ctx.set_handled();
                 }
                 Event::Timer(id) if *id == self.fade_interval_id => {
@@ -1,31 +1,133 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
-    buffer::{BufferNew, UpdateEvent},
-    command::{LapceUICommand, LAPCE_UI_COMMAND},
+    buffer::{str_col, BufferNew, UpdateEvent},
+    command::{
+        CommandCtx, CommandTarget, LapceCommandNew, LapceUICommand,
+        LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+    },
     config::{Config, LapceTheme},
     data::{EditorContent, PanelKind},
     editor::{EditorLocationNew, LapceEditorView},
+    menu::MenuItem,
+    proxy::LapceProxy,
+    replace::Replacer,
     scroll::LapceScrollNew,
     split::SplitDirection,
-    svg::file_svg_new,
+    svg::{file_svg_new, get_svg},
 };
 use crossbeam_channel::Sender;
 use druid::{
+    kurbo::Line,
     piet::{Text, TextAttribute, TextLayout as PietTextLayout, TextLayoutBuilder},
     theme,
     widget::{CrossAxisAlignment, Flex, FlexParams, Label, Scroll, SvgData},
     Affine, BoxConstraints, Color, Command, Cursor, Data, Env, Event, EventCtx,
-    FontFamily, FontWeight, LayoutCtx, LifeCycle, LifeCycleCtx, MouseEvent,
-    PaintCtx, Point, Rect, RenderContext, Size, Target, TextLayout, UpdateCtx, Vec2,
-    Widget, WidgetExt, WidgetId, WidgetPod, WindowId,
+    ExtEventSink, FontFamily, FontWeight, LayoutCtx, LifeCycle, LifeCycleCtx,
+    MouseEvent, PaintCtx, Point, Rect, RenderContext, Size, Target, TextLayout,
+    UpdateCtx, Vec2, Widget, WidgetExt, WidgetId, WidgetPod, WindowId,
 };
+use lapce_proxy::protocol::CAPABILITY_SEARCH_CONTEXT;
+use lapce_proxy::watcher::FileChanges;
 
 use crate::{
     data::{LapceEditorData, LapceTabData},
-    panel::{LapcePanel, PanelHeaderKind},
-    split::LapceSplitNew,
+    panel::{LapcePanel, PanelHeaderAction, PanelHeaderKind, PanelInputList},
 };
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+impl SearchOptions {
+    /// The regex that finds `query` with these options, or why it's invalid.
+    pub fn build_regex(&self, query: &str) -> Result<Regex, String> {
+        let pattern = if self.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let pattern = if self.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map_err(|e| format!("Invalid regex: {}", e))
+    }
+}
+
+/// A query kept at the top of the panel with the options it was searched
+/// with, whose results are counted again as the files change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PinnedSearch {
+    pub query: String,
+    #[serde(default)]
+    pub options: SearchOptions,
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// How many results it had when it was last counted.
+    #[serde(skip)]
+    pub count: Option<usize>,
+}
+
+impl PinnedSearch {
+    /// Whether it searches the same as `other`, whatever their counts.
+    pub fn same_search(&self, other: &PinnedSearch) -> bool {
+        self.query == other.query
+            && self.options == other.options
+            && self.include == other.include
+    }
+}
+
+/// What a row of the results shows.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchRow {
+    /// The header of the pinned queries, which folds them.
+    PinnedHeader,
+    /// A pinned query, by its index.
+    Pinned(usize),
+    Summary,
+    File(PathBuf),
+    /// A match, by its index in the matches of the file.
+    Match(PathBuf, usize),
+    /// A line of context around the matches, by its line number from 1.
+    Context(PathBuf, usize),
+}
+
+/// A line shown under its file in the results.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResultLine {
+    /// A match, by its index in the matches of the file.
+    Match(usize),
+    /// A line of context, by its line number from 1.
+    Context(usize),
+}
+
+/// Where the latest search is, with the number of matches it found.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchState {
+    /// No search, or it failed.
+    Idle,
+    Searching {
+        found: usize,
+    },
+    Done {
+        found: usize,
+    },
+}
 
 #[derive(Clone)]
 pub struct SearchData {
@@ -33,7 +135,56 @@ pub struct SearchData {
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
     pub editor_view_id: WidgetId,
+    /// The editor of the include field, under the search input.
+    pub include_editor_view_id: WidgetId,
+    /// The editor of the replace field, between the search input and the
+    /// include field.
+    pub replace_editor_view_id: WidgetId,
     pub matches: Arc<HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>>,
+    /// The lines around the matches of each file by their line number, when
+    /// the search was run with context. Matches close together share them.
+    pub context: Arc<HashMap<PathBuf, BTreeMap<usize, String>>>,
+    /// Whether the search finds lines of context around the matches.
+    pub show_context: bool,
+    /// The files whose matches are folded away in the results.
+    pub collapsed: Arc<HashSet<PathBuf>>,
+    /// The pattern the matches were found for, empty before any search.
+    pub query: String,
+    pub options: SearchOptions,
+    /// The patterns of the only files to search, from the include field.
+    /// They find files even when `files.exclude` hides them.
+    pub include: Vec<String>,
+    /// Why the search failed, like an invalid regex.
+    pub error: Option<String>,
+    /// The match picked with the keyboard, by its file and its index in the
+    /// matches of the file.
+    pub selected: Option<(PathBuf, usize)>,
+    /// The id of the latest search. Results of earlier ones are dropped.
+    pub search_id: u64,
+    pub state: SearchState,
+    pub pinned: Arc<Vec<PinnedSearch>>,
+    /// Whether the pinned queries are folded under their header.
+    pub pinned_collapsed: bool,
+    /// Whether the files may have changed since the pinned queries were
+    /// counted.
+    pub pinned_stale: bool,
+    /// The text of the replace field.
+    pub replace: String,
+    /// Whether replacements take the case of what they replace.
+    pub preserve_case: bool,
+    /// What replaces the matches of the query, none while there's no query
+    /// or the replacement is invalid.
+    pub replacer: Option<Replacer>,
+    /// Why the replacement is invalid, like a group the regex doesn't have.
+    pub replace_error: Option<String>,
+    /// The revision of the buffer each file had when its matches were found,
+    /// 0 if it wasn't open. Files edited since then are stale.
+    pub revisions: Arc<HashMap<PathBuf, u64>>,
+    /// The files saved or reloaded since their matches were found.
+    pub stale: Arc<HashSet<PathBuf>>,
+    /// The selection put in the search input while `search-on-prefill` is
+    /// off, which isn't searched until it's edited or Enter is pressed.
+    pub prefilled: Option<String>,
 }
 
 impl SearchData {
@@ -44,30 +195,864 @@ impl SearchData {
             widget_id: WidgetId::next(),
             split_id: WidgetId::next(),
             editor_view_id,
+            include_editor_view_id: WidgetId::next(),
+            replace_editor_view_id: WidgetId::next(),
             matches: Arc::new(HashMap::new()),
+            context: Arc::new(HashMap::new()),
+            show_context: false,
+            collapsed: Arc::new(HashSet::new()),
+            query: "".to_string(),
+            options: SearchOptions::default(),
+            include: Vec::new(),
+            error: None,
+            selected: None,
+            search_id: 0,
+            state: SearchState::Idle,
+            pinned: Arc::new(Vec::new()),
+            pinned_collapsed: false,
+            pinned_stale: true,
+            replace: "".to_string(),
+            preserve_case: false,
+            replacer: None,
+            replace_error: None,
+            revisions: Arc::new(HashMap::new()),
+            stale: Arc::new(HashSet::new()),
+            prefilled: None,
+        }
+    }
+
+    /// Clears the results for a new search of `pattern`, and returns its id.
+    pub fn start(&mut self, pattern: String) -> u64 {
+        self.search_id += 1;
+        self.matches = Arc::new(HashMap::new());
+        self.context = Arc::new(HashMap::new());
+        self.revisions = Arc::new(HashMap::new());
+        self.stale = Arc::new(HashSet::new());
+        self.query = pattern;
+        self.error = None;
+        self.state = SearchState::Searching { found: 0 };
+        self.update_selection();
+        self.update_replacer();
+        self.search_id
+    }
+
+    /// Clears the results without starting another search, so that the one
+    /// running is ignored.
+    pub fn clear(&mut self) {
+        self.search_id += 1;
+        self.matches = Arc::new(HashMap::new());
+        self.context = Arc::new(HashMap::new());
+        self.revisions = Arc::new(HashMap::new());
+        self.stale = Arc::new(HashSet::new());
+        self.query = "".to_string();
+        self.error = None;
+        self.state = SearchState::Idle;
+        self.update_selection();
+        self.update_replacer();
+    }
+
+    /// Whether the latest search is still finding matches.
+    pub fn is_searching(&self) -> bool {
+        matches!(self.state, SearchState::Searching { .. })
+    }
+
+    /// Counts the matches again after they changed outside of the search.
+    fn update_found(&mut self) {
+        let count = self.matches.values().map(|m| m.len()).sum();
+        match &mut self.state {
+            SearchState::Idle => {}
+            SearchState::Searching { found } | SearchState::Done { found } => {
+                *found = count
+            }
         }
     }
 
+    /// What the status bar tells of the search, like "Searching… 3 matches"
+    /// while it runs, none without any.
+    pub fn status(&self) -> Option<String> {
+        let (found, searching) = match self.state {
+            SearchState::Idle => return None,
+            SearchState::Searching { found } => (found, true),
+            SearchState::Done { found } => (found, false),
+        };
+        let matches =
+            format!("{} {}", found, if found == 1 { "match" } else { "matches" });
+        if searching {
+            Some(format!("Searching… {}", matches))
+        } else {
+            Some(matches)
+        }
+    }
+
+    /// Adds the matches the search found in a file, with the lines of
+    /// context around them.
+    pub fn add_matches(
+        &mut self,
+        search_id: u64,
+        path: PathBuf,
+        matches: Vec<(usize, (usize, usize), String)>,
+        context: Vec<(usize, String)>,
+    ) {
+        if search_id != self.search_id || !self.is_searching() {
+            return;
+        }
+        if !context.is_empty() {
+            Arc::make_mut(&mut self.context)
+                .insert(path.clone(), context.into_iter().collect());
+        }
+        let added = matches.len();
+        let replaced = Arc::make_mut(&mut self.matches)
+            .insert(path, matches)
+            .map(|m| m.len())
+            .unwrap_or(0);
+        if let SearchState::Searching { found } = &mut self.state {
+            *found = *found + added - replaced;
+        }
+    }
+
+    /// Ends the search, with the matches of proxies that don't stream them.
+    pub fn finish(
+        &mut self,
+        search_id: u64,
+        matches: &HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>,
+    ) {
+        if search_id != self.search_id || !self.is_searching() {
+            return;
+        }
+        if !matches.is_empty() {
+            Arc::make_mut(&mut self.matches)
+                .extend(matches.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        self.state = SearchState::Done { found: 0 };
+        self.update_found();
+        self.update_selection();
+    }
+
+    pub fn fail(&mut self, search_id: u64, message: String) {
+        if search_id != self.search_id || !self.is_searching() {
+            return;
+        }
+        self.matches = Arc::new(HashMap::new());
+        self.context = Arc::new(HashMap::new());
+        self.error = Some(message);
+        self.state = SearchState::Idle;
+        self.update_selection();
+    }
+
+    /// How many lines of context the search finds around each match: none
+    /// unless it's toggled on and the proxy can find them.
+    pub fn context_lines(&self, config: &Config, proxy: &LapceProxy) -> usize {
+        if self.show_context && proxy.has_capability(CAPABILITY_SEARCH_CONTEXT) {
+            config.search.context_lines
+        } else {
+            0
+        }
+    }
+
+    /// Runs the search started with `start`, with the current options and
+    /// `context` lines around the matches. The matches come back to the tab
+    /// file by file as `GlobalSearchResultAdded`, then the end as
+    /// `GlobalSearchResult`, or as `GlobalSearchError` when the proxy refused
+    /// the pattern.
+    pub fn global_search(
+        &self,
+        search_id: u64,
+        pattern: String,
+        context: usize,
+        proxy: &LapceProxy,
+        tab_id: WidgetId,
+        event_sink: ExtEventSink,
+    ) {
+        proxy.global_search(
+            search_id,
+            pattern,
+            &self.options,
+            &self.include,
+            context,
+            Box::new(move |result| match result {
+                Ok(results) => {
+                    if let Some((matches, _)) = search_results(results, context) {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::GlobalSearchResult(
+                                search_id,
+                                Arc::new(matches),
+                            ),
+                            Target::Widget(tab_id),
+                        );
+                    }
+                }
+                Err(e) => {
+                    let message = e
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("Search failed")
+                        .to_string();
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::GlobalSearchError(search_id, message),
+                        Target::Widget(tab_id),
+                    );
+                }
+            }),
+        );
+    }
+
+    /// The search shown in the results, as it would be pinned.
+    fn current_search(&self) -> PinnedSearch {
+        let count = if self.is_searching() || self.error.is_some() {
+            None
+        } else {
+            Some(self.matches.values().map(|m| m.len()).sum())
+        };
+        PinnedSearch {
+            query: self.query.clone(),
+            options: self.options,
+            include: self.include.clone(),
+            count,
+        }
+    }
+
+    /// Whether the search shown in the results is pinned.
+    pub fn is_pinned(&self) -> bool {
+        let current = self.current_search();
+        self.pinned.iter().any(|pin| pin.same_search(&current))
+    }
+
+    /// Pins the search shown in the results, or unpins it if it was. Returns
+    /// whether the pins changed.
+    pub fn toggle_pin(&mut self) -> bool {
+        if self.query.is_empty() {
+            return false;
+        }
+        let current = self.current_search();
+        let pinned = Arc::make_mut(&mut self.pinned);
+        match pinned.iter().position(|pin| pin.same_search(&current)) {
+            Some(index) => {
+                pinned.remove(index);
+            }
+            None => {
+                if current.count.is_none() {
+                    self.pinned_stale = true;
+                }
+                pinned.push(current);
+            }
+        }
+        true
+    }
+
+    pub fn unpin(&mut self, index: usize) {
+        if index < self.pinned.len() {
+            Arc::make_mut(&mut self.pinned).remove(index);
+        }
+    }
+
+    /// Sets the count of the pinned query that searches the same as `pin`,
+    /// if it's still pinned.
+    pub fn set_pinned_count(&mut self, pin: &PinnedSearch, count: Option<usize>) {
+        if let Some(index) = self.pinned.iter().position(|p| p.same_search(pin)) {
+            Arc::make_mut(&mut self.pinned)[index].count = count;
+        }
+    }
+
+    /// Counts the results of the pinned queries again. They don't take the
+    /// place of the search in the results: with no search id, the proxy
+    /// neither cancels it nor streams their matches.
+    pub fn count_pinned(
+        &mut self,
+        proxy: &LapceProxy,
+        tab_id: WidgetId,
+        event_sink: ExtEventSink,
+    ) {
+        self.pinned_stale = false;
+        for pin in self.pinned.iter() {
+            let pin = pin.clone();
+            let event_sink = event_sink.clone();
+            proxy.global_search(
+                0,
+                pin.query.clone(),
+                &pin.options,
+                &pin.include,
+                0,
+                Box::new(move |result| {
+                    let count = result
+                        .ok()
+                        .and_then(|matches| {
+                            serde_json::from_value::<
+                                HashMap<PathBuf, Vec<serde_json::Value>>,
+                            >(matches)
+                            .ok()
+                        })
+                        .map(|matches| matches.values().map(|m| m.len()).sum());
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::PinnedSearchCount(pin, count),
+                        Target::Widget(tab_id),
+                    );
+                }),
+            );
+        }
+    }
+
+    /// The rows of the pinned queries: their header, and one per query
+    /// unless they're folded.
+    pub fn pinned_rows(&self) -> usize {
+        if self.pinned.is_empty() {
+            0
+        } else if self.pinned_collapsed {
+            1
+        } else {
+            self.pinned.len() + 1
+        }
+    }
+
+    /// The rows above the file groups.
+    pub fn header_rows(&self) -> usize {
+        self.pinned_rows() + self.summary_rows()
+    }
+
+    /// Sets the include patterns from the text of the include field, where
+    /// they're separated by commas. Returns whether they changed.
+    pub fn set_include(&mut self, text: &str) -> bool {
+        let include: Vec<String> = text
+            .split(',')
+            .map(|pattern| pattern.trim())
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| pattern.to_string())
+            .collect();
+        if include == self.include {
+            return false;
+        }
+        self.include = include;
+        true
+    }
+
+    /// Sets the text of the replace field. Returns whether it changed.
+    pub fn set_replace(&mut self, text: &str) -> bool {
+        if text == self.replace {
+            return false;
+        }
+        self.replace = text.to_string();
+        self.update_replacer();
+        true
+    }
+
+    pub fn toggle_preserve_case(&mut self) {
+        self.preserve_case = !self.preserve_case;
+        self.update_replacer();
+    }
+
+    /// Makes the replacer again after the query, its options or the
+    /// replacement changed, so an invalid replacement shows before it's
+    /// applied.
+    pub fn update_replacer(&mut self) {
+        let replacer = if self.query.is_empty() {
+            Ok(None)
+        } else {
+            Replacer::new(
+                &self.query,
+                &self.options,
+                &self.replace,
+                self.preserve_case,
+            )
+            .map(Some)
+        };
+        match replacer {
+            Ok(replacer) => {
+                self.replacer = replacer;
+                self.replace_error = None;
+            }
+            Err(e) => {
+                self.replacer = None;
+                self.replace_error = Some(e);
+            }
+        }
+    }
+
+    /// What the match from `start` to `end` of the line is replaced with,
+    /// shown in its row. There's none while the replace field is empty.
+    pub fn replacement(
+        &self,
+        line: &str,
+        start: usize,
+        end: usize,
+    ) -> Option<String> {
+        if self.replace.is_empty() {
+            return None;
+        }
+        Some(self.replacer.as_ref()?.replacement(line, start, end))
+    }
+
+    /// The lines of the file with their matches replaced, as (line number
+    /// from 1, text, replacement), leaving out those that stay the same.
+    pub fn replaced_lines(&self, path: &PathBuf) -> Vec<(usize, String, String)> {
+        let (replacer, matches) =
+            match (self.replacer.as_ref(), self.matches.get(path)) {
+                (Some(replacer), Some(matches)) => (replacer, matches),
+                _ => return Vec::new(),
+            };
+        matches
+            .iter()
+            .filter_map(|(line_number, _, line)| {
+                let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+                let new = replacer.replace_line(line);
+                if new == line {
+                    None
+                } else {
+                    Some((*line_number, line.to_string(), new))
+                }
+            })
+            .collect()
+    }
+
+    /// Drops the matches of the file once they're replaced.
+    pub fn remove_matches(&mut self, search_id: u64, path: &PathBuf) {
+        if search_id != self.search_id || !self.matches.contains_key(path) {
+            return;
+        }
+        Arc::make_mut(&mut self.matches).remove(path);
+        if self.context.contains_key(path) {
+            Arc::make_mut(&mut self.context).remove(path);
+        }
+        Arc::make_mut(&mut self.revisions).remove(path);
+        Arc::make_mut(&mut self.stale).remove(path);
+        self.update_found();
+        self.update_selection();
+    }
+
+    /// Keeps the revision of the buffer the matches of the file were found
+    /// in, `None` if it isn't open, unless it's already kept.
+    pub fn record_revision(&mut self, path: &PathBuf, rev: Option<u64>) {
+        if !self.matches.contains_key(path) || self.revisions.contains_key(path) {
+            return;
+        }
+        Arc::make_mut(&mut self.revisions).insert(path.clone(), rev.unwrap_or(0));
+    }
+
+    /// Keeps the revision of the buffer of the file the matches were found
+    /// for. The proxy searches the files on disk, so the matches of a buffer
+    /// with unsaved edits are stale already.
+    pub fn record_buffer(&mut self, path: &PathBuf, buffer: Option<&BufferNew>) {
+        self.record_revision(path, buffer.map(|b| b.rev));
+        if buffer.map(|b| b.dirty).unwrap_or(false) {
+            self.mark_stale(path);
+        }
+    }
+
+    /// Marks the matches of the file stale after it was saved or reloaded.
+    /// Returns whether it had any.
+    pub fn mark_stale(&mut self, path: &PathBuf) -> bool {
+        if !self.matches.contains_key(path) {
+            return false;
+        }
+        if !self.stale.contains(path) {
+            Arc::make_mut(&mut self.stale).insert(path.clone());
+        }
+        true
+    }
+
+    /// Marks the matches of the files the watcher saw change stale, all of
+    /// them after a bulk change. Returns whether any were.
+    pub fn mark_changed(&mut self, changes: &FileChanges) -> bool {
+        if changes.bulk {
+            let paths: Vec<PathBuf> = self.matches.keys().cloned().collect();
+            for path in paths.iter() {
+                self.mark_stale(path);
+            }
+            return !paths.is_empty();
+        }
+        let mut marked = false;
+        for path in changes.paths() {
+            marked |= self.mark_stale(path);
+        }
+        marked
+    }
+
+    /// Whether the matches of the file may be out of date: it was saved or
+    /// reloaded, or its buffer, at revision `rev` if it's open, was edited
+    /// since they were found.
+    pub fn is_stale(&self, path: &PathBuf, rev: Option<u64>) -> bool {
+        if self.stale.contains(path) {
+            return true;
+        }
+        match rev {
+            Some(rev) => {
+                self.matches.contains_key(path)
+                    && rev != self.revisions.get(path).copied().unwrap_or(0)
+            }
+            None => false,
+        }
+    }
+
+    /// The files whose matches may be out of date.
+    pub fn stale_files(
+        &self,
+        open_files: &im::HashMap<PathBuf, Arc<BufferNew>>,
+    ) -> Vec<PathBuf> {
+        self.matches
+            .keys()
+            .filter(|path| self.is_stale(path, open_files.get(*path).map(|b| b.rev)))
+            .cloned()
+            .collect()
+    }
+
+    /// Puts the matches the file has now and their context in place of the
+    /// old ones, found in its buffer at revision `rev`, or 0 when it was
+    /// searched on disk. The file goes when it has no matches left, and it
+    /// stays collapsed if it was.
+    pub fn refresh_file(
+        &mut self,
+        search_id: u64,
+        path: &PathBuf,
+        matches: Vec<(usize, (usize, usize), String)>,
+        context: Vec<(usize, String)>,
+        rev: u64,
+    ) {
+        if search_id != self.search_id || !self.matches.contains_key(path) {
+            return;
+        }
+        if context.is_empty() {
+            if self.context.contains_key(path) {
+                Arc::make_mut(&mut self.context).remove(path);
+            }
+        } else {
+            Arc::make_mut(&mut self.context)
+                .insert(path.clone(), context.into_iter().collect());
+        }
+        if matches.is_empty() {
+            Arc::make_mut(&mut self.matches).remove(path);
+            Arc::make_mut(&mut self.revisions).remove(path);
+        } else {
+            Arc::make_mut(&mut self.matches).insert(path.clone(), matches);
+            Arc::make_mut(&mut self.revisions).insert(path.clone(), rev);
+        }
+        if self.stale.contains(path) {
+            Arc::make_mut(&mut self.stale).remove(path);
+        }
+        self.update_found();
+        self.update_selection();
+    }
+
+    /// The rows above the file groups: the summary, once a search was run.
+    pub fn summary_rows(&self) -> usize {
+        if self.query.is_empty() {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Like "128 results in 23 files", or "No results", or the error. While
+    /// searching, it says so after what was found so far.
+    pub fn summary(&self) -> String {
+        if let Some(error) = self.error.as_ref().or(self.replace_error.as_ref()) {
+            return error.clone();
+        }
+        let results = self.matches.values().map(|m| m.len()).sum::<usize>();
+        if results == 0 {
+            return if self.is_searching() {
+                "Searching…".to_string()
+            } else {
+                "No results".to_string()
+            };
+        }
+        let files = self.matches.len();
+        let summary = format!(
+            "{} result{} in {} file{}",
+            results,
+            if results == 1 { "" } else { "s" },
+            files,
+            if files == 1 { "" } else { "s" },
+        );
+        if self.is_searching() {
+            format!("{}, searching…", summary)
+        } else {
+            summary
+        }
+    }
+
+    /// How many rows a file takes in the results: its own, plus one per
+    /// match and per line of context unless it's collapsed.
+    pub fn group_rows(&self, path: &PathBuf, matches: usize) -> usize {
+        if self.collapsed.contains(path) {
+            1
+        } else {
+            matches + self.context.get(path).map(|c| c.len()).unwrap_or(0) + 1
+        }
+    }
+
+    /// The lines shown under the file, in order: its matches, and the lines
+    /// of context between them. Matches whose context overlaps share one
+    /// block, with no line shown twice.
+    pub fn result_lines(&self, path: &PathBuf) -> Vec<ResultLine> {
+        let matches = match self.matches.get(path) {
+            Some(matches) => matches,
+            None => return Vec::new(),
+        };
+        let mut context = match self.context.get(path) {
+            Some(context) => context.keys().peekable(),
+            None => return (0..matches.len()).map(ResultLine::Match).collect(),
+        };
+        let mut lines = Vec::new();
+        for (index, (line_number, _, _)) in matches.iter().enumerate() {
+            while let Some(line) = context.next_if(|line| *line < line_number) {
+                lines.push(ResultLine::Context(*line));
+            }
+            lines.push(ResultLine::Match(index));
+        }
+        lines.extend(context.map(|line| ResultLine::Context(*line)));
+        lines
+    }
+
+    /// What the row `n` of the results shows, if there's anything there.
+    pub fn row(&self, n: usize) -> Option<SearchRow> {
+        let pinned_rows = self.pinned_rows();
+        if n < pinned_rows {
+            return Some(if n == 0 {
+                SearchRow::PinnedHeader
+            } else {
+                SearchRow::Pinned(n - 1)
+            });
+        }
+        if n < self.header_rows() {
+            return Some(SearchRow::Summary);
+        }
+        let mut i = self.header_rows();
+        for (path, matches) in self.matches.iter() {
+            let rows = self.group_rows(path, matches.len());
+            if i + rows <= n {
+                i += rows;
+                continue;
+            }
+            if n == i {
+                return Some(SearchRow::File(path.clone()));
+            }
+            return Some(match self.result_lines(path).get(n - i - 1)? {
+                ResultLine::Match(index) => SearchRow::Match(path.clone(), *index),
+                ResultLine::Context(line) => SearchRow::Context(path.clone(), *line),
+            });
+        }
+        None
+    }
+
+    /// The rows of the matches that aren't folded away, as (row, path, index).
+    fn match_rows(&self) -> Vec<(usize, PathBuf, usize)> {
+        let mut rows = Vec::new();
+        let mut i = self.header_rows();
+        for (path, matches) in self.matches.iter() {
+            if !self.collapsed.contains(path) {
+                for (row, line) in self.result_lines(path).into_iter().enumerate() {
+                    if let ResultLine::Match(index) = line {
+                        rows.push((i + row + 1, path.clone(), index));
+                    }
+                }
+            }
+            i += self.group_rows(path, matches.len());
+        }
+        rows
+    }
+
+    /// The row of the selected match, unless it's folded away.
+    pub fn selected_row(&self) -> Option<usize> {
+        let (path, index) = self.selected.as_ref()?;
+        self.match_rows()
+            .into_iter()
+            .find(|(_, p, i)| p == path && i == index)
+            .map(|(row, _, _)| row)
+    }
+
+    /// Moves the selection to the next match, or to the first one if there
+    /// was none. It stays on the last match rather than wrapping around.
+    pub fn select_next(&mut self) {
+        self.move_selection(true);
+    }
+
+    pub fn select_previous(&mut self) {
+        self.move_selection(false);
+    }
+
+    fn move_selection(&mut self, forward: bool) {
+        let rows = self.match_rows();
+        if rows.is_empty() {
+            return;
+        }
+        let current = self.selected.as_ref().and_then(|(path, index)| {
+            rows.iter().position(|(_, p, i)| p == path && i == index)
+        });
+        let new = match current {
+            Some(current) if forward => (current + 1).min(rows.len() - 1),
+            Some(current) => current.saturating_sub(1),
+            None if forward => 0,
+            None => rows.len() - 1,
+        };
+        let (_, path, index) = &rows[new];
+        self.selected = Some((path.clone(), *index));
+    }
+
+    /// Keeps the selection on a match after the matches changed, or drops it
+    /// when its file has none left.
+    pub fn update_selection(&mut self) {
+        self.selected = match self.selected.take() {
+            Some((path, index)) => match self.matches.get(&path) {
+                Some(matches) if !matches.is_empty() => {
+                    Some((path, index.min(matches.len() - 1)))
+                }
+                _ => None,
+            },
+            None => None,
+        };
+    }
+
+    /// Where clicking the match takes the editor: onto the match, with it
+    /// selected.
+    pub fn match_location(
+        &self,
+        path: &PathBuf,
+        index: usize,
+    ) -> Option<EditorLocationNew> {
+        let (line_number, (start, end), line) =
+            self.matches.get(path)?.get(index)?;
+        let line_number = *line_number as u32 - 1;
+        // the proxy reports the match in bytes, the editor takes columns
+        let column =
+            |offset: usize| line.get(..offset).map(str_col).unwrap_or(0) as u32;
+        Some(EditorLocationNew {
+            path: path.clone(),
+            position: Some(lsp_types::Position {
+                line: line_number,
+                character: column(*start),
+            }),
+            scroll_offset: None,
+            hisotry: None,
+            selection_end: Some(lsp_types::Position {
+                line: line_number,
+                character: column(*end),
+            }),
+        })
+    }
+
+    /// Where clicking a line of context takes the editor: to the start of
+    /// the line, numbered from 1.
+    pub fn context_location(
+        &self,
+        path: &PathBuf,
+        line_number: usize,
+    ) -> EditorLocationNew {
+        EditorLocationNew {
+            path: path.clone(),
+            position: Some(lsp_types::Position {
+                line: line_number.saturating_sub(1) as u32,
+                character: 0,
+            }),
+            scroll_offset: None,
+            hisotry: None,
+            selection_end: None,
+        }
+    }
+
+    /// The match as "line:col: text", with the line and the column counted
+    /// from 1.
+    pub fn match_text(&self, path: &PathBuf, index: usize) -> Option<String> {
+        let (line_number, (start, _), line) = self.matches.get(path)?.get(index)?;
+        let column = line.get(..*start).map(str_col).unwrap_or(0) + 1;
+        Some(format!("{}:{}: {}", line_number, column, line.trim()))
+    }
+
+    /// The results as text: the path of each file, relative to the
+    /// workspace, then its matches indented, in the order they're shown.
+    /// Lines of context read "line- text", as grep writes them.
+    pub fn results_text(&self, workspace: Option<&Path>) -> String {
+        let mut text = String::new();
+        for path in self.matches.keys() {
+            text.push_str(&relative_path(path, workspace));
+            text.push('\n');
+            for line in self.result_lines(path) {
+                let line = match line {
+                    ResultLine::Match(index) => self.match_text(path, index),
+                    ResultLine::Context(line_number) => {
+                        self.context.get(path).and_then(|context| {
+                            let line = context.get(&line_number)?;
+                            Some(format!("{}- {}", line_number, line.trim()))
+                        })
+                    }
+                };
+                if let Some(line) = line {
+                    text.push_str("    ");
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+
+    pub fn toggle_collapsed(&mut self, path: &PathBuf) {
+        let collapsed = Arc::make_mut(&mut self.collapsed);
+        if !collapsed.remove(path) {
+            collapsed.insert(path.clone());
+        }
+    }
+
+    pub fn collapse_all(&mut self) {
+        self.collapsed = Arc::new(self.matches.keys().cloned().collect());
+    }
+
+    pub fn expand_all(&mut self) {
+        self.collapsed = Arc::new(HashSet::new());
+    }
+
+    pub fn all_collapsed(&self) -> bool {
+        self.matches
+            .keys()
+            .all(|path| self.collapsed.contains(path))
+    }
+
     pub fn new_panel(&self, data: &LapceTabData) -> LapcePanel {
         let editor_data = data
             .main_split
             .editors
             .get(&data.search.editor_view_id)
             .unwrap();
-        let input = LapceEditorView::new(editor_data)
+        let input = SearchInput::new(
+            LapceEditorView::new(editor_data)
+                .hide_header()
+                .hide_gutter()
+                .padding(10.0)
+                .boxed(),
+        );
+        let replace_editor_data = data
+            .main_split
+            .editors
+            .get(&data.search.replace_editor_view_id)
+            .unwrap();
+        let replace = SearchInput::new_replace(
+            LapceEditorView::new(replace_editor_data)
+                .hide_header()
+                .hide_gutter()
+                .set_placeholder("Replace".to_string())
+                .padding(10.0)
+                .boxed(),
+        );
+        let include_editor_data = data
+            .main_split
+            .editors
+            .get(&data.search.include_editor_view_id)
+            .unwrap();
+        let include = LapceEditorView::new(include_editor_data)
             .hide_header()
             .hide_gutter()
+            .set_placeholder("Files to include, e.g. src/**/*.rs".to_string())
             .padding(10.0);
-        let split = LapceSplitNew::new(self.split_id)
-            .horizontal()
-            .with_child(input.boxed(), None, 45.0)
-            .with_flex_child(
-                LapceScrollNew::new(SearchContent::new().boxed())
-                    .vertical()
-                    .boxed(),
-                None,
-                1.0,
-            );
+        let split = PanelInputList::new(
+            PanelKind::Search,
+            self.split_id,
+            vec![
+                (input.boxed(), 45.0),
+                (replace.boxed(), 45.0),
+                (include.boxed(), 45.0),
+            ],
+            SearchResults::new().boxed(),
+        );
         LapcePanel::new(
             PanelKind::Search,
             self.widget_id,
@@ -79,6 +1064,335 @@ impl SearchData {
     }
 }
 
+/// The search input, with the toggles of the search options and the pin on
+/// its right, or the replace input with the preserve case toggle and the
+/// button that replaces all the matches.
+pub struct SearchInput {
+    input: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+    cmds: Vec<LapceWorkbenchCommand>,
+    /// Whether it's the replace input, outlined when the replacement is
+    /// invalid rather than the query.
+    replace: bool,
+    toggles: Vec<(Rect, LapceWorkbenchCommand)>,
+    mouse_pos: Point,
+}
+
+impl SearchInput {
+    pub fn new(input: Box<dyn Widget<LapceTabData>>) -> Self {
+        Self {
+            input: WidgetPod::new(input),
+            cmds: vec![
+                LapceWorkbenchCommand::SearchToggleCaseSensitive,
+                LapceWorkbenchCommand::SearchToggleWholeWord,
+                LapceWorkbenchCommand::SearchToggleRegex,
+                LapceWorkbenchCommand::SearchToggleContext,
+                LapceWorkbenchCommand::SearchTogglePin,
+            ],
+            replace: false,
+            toggles: Vec::new(),
+            mouse_pos: Point::ZERO,
+        }
+    }
+
+    pub fn new_replace(input: Box<dyn Widget<LapceTabData>>) -> Self {
+        Self {
+            input: WidgetPod::new(input),
+            cmds: vec![
+                LapceWorkbenchCommand::SearchTogglePreserveCase,
+                LapceWorkbenchCommand::SearchReplaceAll,
+            ],
+            replace: true,
+            toggles: Vec::new(),
+            mouse_pos: Point::ZERO,
+        }
+    }
+
+    fn has_error(&self, data: &LapceTabData) -> bool {
+        if self.replace {
+            data.search.error.is_none() && data.search.replace_error.is_some()
+        } else {
+            data.search.error.is_some()
+        }
+    }
+
+    fn toggle_at(&self, pos: Point) -> Option<&LapceWorkbenchCommand> {
+        self.toggles
+            .iter()
+            .find(|(rect, _)| rect.contains(pos))
+            .map(|(_, cmd)| cmd)
+    }
+}
+
+impl Widget<LapceTabData> for SearchInput {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        match event {
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = mouse_event.pos;
+                if self.toggle_at(mouse_event.pos).is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                    ctx.request_paint();
+                    return;
+                }
+                ctx.clear_cursor();
+            }
+            Event::MouseDown(mouse_event) => {
+                if let Some(cmd) = self.toggle_at(mouse_event.pos) {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: cmd.to_string(),
+                            data: None,
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Widget(data.id),
+                    ));
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        self.input.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.input.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if old_data.search.options != data.search.options
+            || old_data.search.error != data.search.error
+            || !old_data.search.pinned.same(&data.search.pinned)
+            || old_data.search.query != data.search.query
+            || old_data.search.preserve_case != data.search.preserve_case
+            || old_data.search.replace_error != data.search.replace_error
+            || old_data.search.show_context != data.search.show_context
+        {
+            ctx.request_paint();
+        }
+        self.input.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let toggle_size = 22.0;
+        let gap = 4.0;
+        // the inputs are as wide, so that their text lines up
+        let toggles_width = (toggle_size + gap) * 5.0 + 10.0;
+
+        let input_bc = BoxConstraints::new(
+            Size::new(0.0, bc.min().height),
+            Size::new((bc.max().width - toggles_width).max(0.0), bc.max().height),
+        );
+        let input_size = self.input.layout(ctx, &input_bc, data, env);
+        self.input.set_origin(ctx, data, env, Point::ZERO);
+
+        let y = (input_size.height - toggle_size) / 2.0;
+        self.toggles = self
+            .cmds
+            .iter()
+            .enumerate()
+            .map(|(i, cmd)| {
+                let x = input_size.width + (toggle_size + gap) * i as f64;
+                (
+                    Size::new(toggle_size, toggle_size)
+                        .to_rect()
+                        .with_origin(Point::new(x, y)),
+                    cmd.clone(),
+                )
+            })
+            .collect();
+        Size::new(bc.max().width, input_size.height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        self.input.paint(ctx, data, env);
+        if self.has_error(data) {
+            ctx.stroke(
+                self.input.layout_rect().inflate(-5.0, -5.0),
+                data.config.get_color_unchecked(LapceTheme::LAPCE_ERROR),
+                1.0,
+            );
+        }
+
+        let options = data.search.options;
+        let pinned = data.search.is_pinned();
+        for (rect, cmd) in self.toggles.iter() {
+            let (label, on) = match cmd {
+                LapceWorkbenchCommand::SearchToggleCaseSensitive => {
+                    ("Aa", options.case_sensitive)
+                }
+                LapceWorkbenchCommand::SearchToggleWholeWord => {
+                    ("ab", options.whole_word)
+                }
+                LapceWorkbenchCommand::SearchToggleContext => {
+                    ("±", data.search.show_context)
+                }
+                LapceWorkbenchCommand::SearchTogglePin => {
+                    (if pinned { "★" } else { "☆" }, pinned)
+                }
+                LapceWorkbenchCommand::SearchTogglePreserveCase => {
+                    ("AB", data.search.preserve_case)
+                }
+                LapceWorkbenchCommand::SearchReplaceAll => ("All", false),
+                _ => (".*", options.regex),
+            };
+            if on || (ctx.is_hot() && rect.contains(self.mouse_pos)) {
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+                );
+            }
+            if on {
+                ctx.stroke(
+                    rect,
+                    data.config.get_color_unchecked(LapceTheme::EDITOR_FOCUS),
+                    1.0,
+                );
+            }
+            let color = data.config.get_color_unchecked(if on {
+                LapceTheme::EDITOR_FOREGROUND
+            } else {
+                LapceTheme::EDITOR_DIM
+            });
+            let text_layout = ctx
+                .text()
+                .new_text_layout(label)
+                .font(FontFamily::SYSTEM_UI, 12.0)
+                .text_color(color.clone())
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            let origin = Point::new(
+                rect.x0 + (rect.width() - text_size.width) / 2.0,
+                rect.y0 + (rect.height() - text_size.height) / 2.0,
+            );
+            ctx.draw_text(&text_layout, origin);
+            if let LapceWorkbenchCommand::SearchToggleWholeWord = cmd {
+                let y = origin.y + text_size.height;
+                ctx.stroke(
+                    Line::new(
+                        Point::new(origin.x, y),
+                        Point::new(origin.x + text_size.width, y),
+                    ),
+                    color,
+                    1.0,
+                );
+            }
+        }
+    }
+}
+
+/// The results under the input, kept scrolled to the selected match.
+pub struct SearchResults {
+    content: WidgetPod<LapceTabData, LapceScrollNew<LapceTabData, SearchContent>>,
+}
+
+impl SearchResults {
+    pub fn new() -> Self {
+        Self {
+            content: WidgetPod::new(
+                LapceScrollNew::new(SearchContent::new()).vertical(),
+            ),
+        }
+    }
+
+    fn ensure_selected_visible(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        let row = match data.search.selected_row() {
+            Some(row) => row,
+            None => return,
+        };
+        let line_height = data.config.editor.line_height as f64;
+        let rect = Size::new(ctx.size().width, line_height)
+            .to_rect()
+            .with_origin(Point::new(0.0, row as f64 * line_height));
+        if self.content.widget_mut().scroll_to_visible(rect, env) {
+            ctx.request_paint();
+        }
+    }
+}
+
+impl Widget<LapceTabData> for SearchResults {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        self.content.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.content.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if old_data.search.selected != data.search.selected {
+            self.ensure_selected_visible(ctx, data, env);
+        }
+        self.content.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let size = self.content.layout(ctx, bc, data, env);
+        self.content.set_origin(ctx, data, env, Point::ZERO);
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        self.content.paint(ctx, data, env);
+    }
+}
+
 pub struct SearchContent {
     mouse_pos: Point,
 }
@@ -90,7 +1404,53 @@ impl SearchContent {
         }
     }
 
-    fn mouse_down(
+    fn mouse_down<C: CommandCtx>(
+        &self,
+        ctx: &mut C,
+        mouse_event: &MouseEvent,
+        data: &mut LapceTabData,
+    ) {
+        let line_height = data.config.editor.line_height as f64;
+        let n = (mouse_event.pos.y / line_height).floor() as usize;
+
+        match data.search.row(n) {
+            Some(SearchRow::File(path)) => {
+                Arc::make_mut(&mut data.search).toggle_collapsed(&path);
+            }
+            Some(SearchRow::Match(path, index)) => {
+                if let Some(location) = data.search.match_location(&path, index) {
+                    data.record_search();
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        jump_command(location, mouse_event),
+                        Target::Widget(data.id),
+                    ));
+                }
+                Arc::make_mut(&mut data.search).selected = Some((path, index));
+            }
+            Some(SearchRow::Context(path, line_number)) => {
+                data.record_search();
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    jump_command(
+                        data.search.context_location(&path, line_number),
+                        mouse_event,
+                    ),
+                    Target::Widget(data.id),
+                ));
+            }
+            Some(SearchRow::PinnedHeader) => {
+                let search = Arc::make_mut(&mut data.search);
+                search.pinned_collapsed = !search.pinned_collapsed;
+            }
+            Some(SearchRow::Pinned(index)) => {
+                data.load_pinned_search(index);
+            }
+            Some(SearchRow::Summary) | None => {}
+        }
+    }
+
+    fn right_mouse_down(
         &self,
         ctx: &mut EventCtx,
         mouse_event: &MouseEvent,
@@ -98,37 +1458,163 @@ impl SearchContent {
     ) {
         let line_height = data.config.editor.line_height as f64;
         let n = (mouse_event.pos.y / line_height).floor() as usize;
+        let item = |text: &str, cmd: LapceWorkbenchCommand, data| MenuItem {
+            text: text.to_string(),
+            command: LapceCommandNew {
+                cmd: cmd.to_string(),
+                palette_desc: None,
+                data,
+                target: CommandTarget::Workbench,
+            },
+        };
+        let copy_results = item(
+            "Copy Search Results",
+            LapceWorkbenchCommand::SearchCopyResults,
+            None,
+        );
+        let menu_items = match data.search.row(n) {
+            Some(SearchRow::Pinned(index)) => vec![item(
+                "Unpin",
+                LapceWorkbenchCommand::SearchUnpin,
+                Some(json!(index)),
+            )],
+            Some(SearchRow::File(path)) => vec![
+                item(
+                    "Copy Path",
+                    LapceWorkbenchCommand::SearchCopyPath,
+                    Some(json!(path)),
+                ),
+                copy_results,
+            ],
+            Some(SearchRow::Match(path, index)) => vec![
+                item(
+                    "Copy Line",
+                    LapceWorkbenchCommand::SearchCopyLine,
+                    Some(json!([path, index])),
+                ),
+                copy_results,
+            ],
+            Some(SearchRow::Summary) | Some(SearchRow::Context(_, _))
+                if !data.search.matches.is_empty() =>
+            {
+                vec![copy_results]
+            }
+            _ => Vec::new(),
+        };
+        if !menu_items.is_empty() {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowMenu(
+                    ctx.to_window(mouse_event.pos),
+                    Arc::new(menu_items),
+                ),
+                Target::Auto,
+            ));
+            ctx.set_handled();
+        }
+    }
+
+    /// The header of the pinned queries, and the queries with their counts
+    /// unless they're folded.
+    fn paint_pinned(&self, ctx: &mut PaintCtx, data: &LapceTabData) {
+        let line_height = data.config.editor.line_height as f64;
+        let padding = (line_height - 14.0) / 2.0;
+        let size = ctx.size();
+        let foreground = data
+            .config
+            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+            .clone();
+        let dim_color = data.config.get_color_unchecked(LapceTheme::EDITOR_DIM);
+
+        let icon_name = if data.search.pinned_collapsed {
+            "chevron-right.svg"
+        } else {
+            "chevron-down.svg"
+        };
+        let rect = Size::new(line_height, line_height)
+            .to_rect()
+            .inflate(-padding, -padding);
+        ctx.draw_svg(&get_svg(icon_name).unwrap(), rect, Some(&foreground));
+        let text_layout = ctx
+            .text()
+            .new_text_layout("Pinned")
+            .font(FontFamily::SYSTEM_UI, 13.0)
+            .text_color(foreground.clone())
+            .build()
+            .unwrap();
+        ctx.draw_text(
+            &text_layout,
+            Point::new(line_height, (line_height - text_layout.size().height) / 2.0),
+        );
+        if data.search.pinned_collapsed {
+            return;
+        }
+
+        for (i, pin) in data.search.pinned.iter().enumerate() {
+            let y = line_height * (i + 1) as f64;
+            let count = match pin.count {
+                Some(count) => count.to_string(),
+                None => "…".to_string(),
+            };
+            let count_layout = ctx
+                .text()
+                .new_text_layout(count)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(dim_color.clone())
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &count_layout,
+                Point::new(
+                    size.width - count_layout.size().width - padding,
+                    y + (line_height - count_layout.size().height) / 2.0,
+                ),
+            );
+
+            let text_layout = ctx
+                .text()
+                .new_text_layout(pin.query.clone())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(foreground.clone())
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    line_height,
+                    y + (line_height - text_layout.size().height) / 2.0,
+                ),
+            );
 
-        let mut i = 0;
-        for (path, matches) in data.search.matches.iter() {
-            if matches.len() + 1 + i < n {
-                i += matches.len() + 1;
-                continue;
+            let mut details = Vec::new();
+            if pin.options.case_sensitive {
+                details.push("Aa".to_string());
             }
-
-            for (line_number, (start, end), line) in matches {
-                i += 1;
-                if i == n {
-                    ctx.submit_command(Command::new(
-                        LAPCE_UI_COMMAND,
-                        LapceUICommand::JumpToLocation(
-                            None,
-                            EditorLocationNew {
-                                path: path.clone(),
-                                position: Some(lsp_types::Position {
-                                    line: *line_number as u32 - 1,
-                                    character: 0,
-                                }),
-                                scroll_offset: None,
-                                hisotry: None,
-                            },
-                        ),
-                        Target::Widget(data.id),
-                    ));
-                    return;
-                }
+            if pin.options.whole_word {
+                details.push("ab".to_string());
+            }
+            if pin.options.regex {
+                details.push(".*".to_string());
+            }
+            if !pin.include.is_empty() {
+                details.push(pin.include.join(", "));
+            }
+            if !details.is_empty() {
+                let details_layout = ctx
+                    .text()
+                    .new_text_layout(details.join(" "))
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(dim_color.clone())
+                    .build()
+                    .unwrap();
+                ctx.draw_text(
+                    &details_layout,
+                    Point::new(
+                        line_height + text_layout.size().width + 5.0,
+                        y + (line_height - details_layout.size().height) / 2.0,
+                    ),
+                );
             }
-            i += 1;
         }
     }
 }
@@ -147,6 +1633,9 @@ impl Widget<LapceTabData> for SearchContent {
                 ctx.set_cursor(&Cursor::Pointer);
                 ctx.request_paint();
             }
+            Event::MouseDown(mouse_event) if mouse_event.button.is_right() => {
+                self.right_mouse_down(ctx, mouse_event, data);
+            }
             Event::MouseDown(mouse_event) => {
                 self.mouse_down(ctx, mouse_event, data);
             }
@@ -170,9 +1659,25 @@ impl Widget<LapceTabData> for SearchContent {
         data: &LapceTabData,
         env: &Env,
     ) {
-        if !old_data.search.matches.same(&data.search.matches) {
+        if !old_data.search.matches.same(&data.search.matches)
+            || !old_data.search.context.same(&data.search.context)
+            || !old_data.search.collapsed.same(&data.search.collapsed)
+            || old_data.search.query != data.search.query
+            || !old_data.search.pinned.same(&data.search.pinned)
+            || old_data.search.pinned_collapsed != data.search.pinned_collapsed
+        {
             ctx.request_layout();
         }
+        if old_data.search.selected != data.search.selected
+            || old_data.search.state != data.search.state
+            || old_data.search.error != data.search.error
+            || old_data.search.replace != data.search.replace
+            || old_data.search.preserve_case != data.search.preserve_case
+            || old_data.search.replace_error != data.search.replace_error
+            || stale_changed(old_data, data)
+        {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -187,8 +1692,9 @@ impl Widget<LapceTabData> for SearchContent {
             .search
             .matches
             .iter()
-            .map(|(_, matches)| matches.len() + 1)
-            .sum::<usize>();
+            .map(|(path, matches)| data.search.group_rows(path, matches.len()))
+            .sum::<usize>()
+            + data.search.header_rows();
         let height = line_height * n as f64;
         Size::new(bc.max().width, height)
     }
@@ -196,13 +1702,23 @@ impl Widget<LapceTabData> for SearchContent {
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
         let line_height = data.config.editor.line_height as f64;
 
-        if ctx.is_hot() {
+        let pinned_rows = data.search.pinned_rows();
+        let summary_rows = data.search.summary_rows();
+        let n = (self.mouse_pos.y / line_height).floor() as usize;
+        let hovered = if ctx.is_hot() { Some(n) } else { None };
+        let selected = data
+            .search
+            .selected_row()
+            .filter(|row| Some(*row) != hovered);
+        for row in hovered.into_iter().chain(selected) {
+            if summary_rows > 0 && row == pinned_rows {
+                continue;
+            }
             let size = ctx.size();
-            let n = (self.mouse_pos.y / line_height).floor() as usize;
             ctx.fill(
                 Size::new(size.width, line_height)
                     .to_rect()
-                    .with_origin(Point::new(0.0, line_height * n as f64)),
+                    .with_origin(Point::new(0.0, line_height * row as f64)),
                 data.config
                     .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
             );
@@ -214,20 +1730,120 @@ impl Widget<LapceTabData> for SearchContent {
 
         let focus_color = data.config.get_color_unchecked(LapceTheme::EDITOR_FOCUS);
         let padding = (line_height - 14.0) / 2.0;
-        let mut i = 0;
+        let dim_color = data.config.get_color_unchecked(LapceTheme::EDITOR_DIM);
+        let size = ctx.size();
+        if pinned_rows > 0 && min < pinned_rows {
+            self.paint_pinned(ctx, data);
+        }
+        if summary_rows > 0 && min <= pinned_rows {
+            let y = line_height * pinned_rows as f64;
+            let text_layout = ctx
+                .text()
+                .new_text_layout(data.search.summary())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    if data.search.error.is_some()
+                        || data.search.replace_error.is_some()
+                    {
+                        data.config
+                            .get_color_unchecked(LapceTheme::LAPCE_ERROR)
+                            .clone()
+                    } else {
+                        dim_color.clone()
+                    },
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    padding,
+                    y + (line_height - text_layout.size().height) / 2.0,
+                ),
+            );
+        }
+
+        let mut i = pinned_rows + summary_rows;
         for (path, matches) in data.search.matches.iter() {
-            if matches.len() + 1 + i < min {
-                i += matches.len() + 1;
+            let collapsed = data.search.collapsed.contains(path);
+            let rows = data.search.group_rows(path, matches.len());
+            if i + rows <= min {
+                i += rows;
                 continue;
             }
+            if i > max {
+                return;
+            }
 
-            let svg = file_svg_new(path);
+            let icon_name = if collapsed {
+                "chevron-right.svg"
+            } else {
+                "chevron-down.svg"
+            };
             let rect = Size::new(line_height, line_height)
                 .to_rect()
                 .with_origin(Point::new(0.0, line_height * i as f64))
                 .inflate(-padding, -padding);
+            ctx.draw_svg(
+                &get_svg(icon_name).unwrap(),
+                rect,
+                Some(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+                ),
+            );
+
+            let svg = file_svg_new(path);
+            let rect = Size::new(line_height, line_height)
+                .to_rect()
+                .with_origin(Point::new(line_height, line_height * i as f64))
+                .inflate(-padding, -padding);
             ctx.draw_svg(&svg, rect, None);
 
+            let count_layout = ctx
+                .text()
+                .new_text_layout(matches.len().to_string())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(dim_color.clone())
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &count_layout,
+                Point::new(
+                    size.width - count_layout.size().width - padding,
+                    line_height * i as f64
+                        + (line_height - count_layout.size().height) / 2.0,
+                ),
+            );
+            if data
+                .search
+                .is_stale(path, data.main_split.open_files.get(path).map(|b| b.rev))
+            {
+                let stale_layout = ctx
+                    .text()
+                    .new_text_layout("stale")
+                    .font(FontFamily::SYSTEM_UI, 11.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::LAPCE_WARN)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                ctx.draw_text(
+                    &stale_layout,
+                    Point::new(
+                        size.width
+                            - count_layout.size().width
+                            - stale_layout.size().width
+                            - padding
+                            - 5.0,
+                        line_height * i as f64
+                            + (line_height - stale_layout.size().height) / 2.0,
+                    ),
+                );
+            }
+
             let text_layout = ctx
                 .text()
                 .new_text_layout(
@@ -244,26 +1860,26 @@ impl Widget<LapceTabData> for SearchContent {
             ctx.draw_text(
                 &text_layout,
                 Point::new(
-                    line_height,
+                    line_height * 2.0,
                     line_height * i as f64
                         + (line_height - text_layout.size().height) / 2.0,
                 ),
             );
 
-            let mut path = path.clone();
+            let mut relative = path.clone();
             if let Some(workspace_path) = data.workspace.path.as_ref() {
-                path = path
+                relative = relative
                     .strip_prefix(workspace_path)
-                    .unwrap_or(&path)
+                    .unwrap_or(&relative)
                     .to_path_buf();
             }
-            let folder = path
+            let folder = relative
                 .parent()
                 .and_then(|s| s.to_str())
                 .unwrap_or("")
                 .to_string();
             if folder != "" {
-                let x = text_layout.size().width + line_height + 5.0;
+                let x = text_layout.size().width + line_height * 2.0 + 5.0;
 
                 let text_layout = ctx
                     .text()
@@ -286,43 +1902,1025 @@ impl Widget<LapceTabData> for SearchContent {
                 );
             }
 
-            for (line_number, (start, end), line) in matches {
+            if collapsed {
+                i += 1;
+                continue;
+            }
+
+            for result_line in data.search.result_lines(path) {
                 i += 1;
                 if i > max {
                     return;
                 }
+                if i < min {
+                    continue;
+                }
 
-                if i >= min {
-                    let mut text_layout = ctx
-                        .text()
-                        .new_text_layout(format!("{line_number}: {line}"))
-                        .font(FontFamily::SYSTEM_UI, 13.0)
-                        .text_color(
-                            data.config
-                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                                .clone(),
+                let index = match result_line {
+                    ResultLine::Match(index) => index,
+                    ResultLine::Context(line_number) => {
+                        let line = data
+                            .search
+                            .context
+                            .get(path)
+                            .and_then(|context| context.get(&line_number))
+                            .map(|line| context_preview(line))
+                            .unwrap_or_default();
+                        let text_layout = ctx
+                            .text()
+                            .new_text_layout(format!("{line_number}: {line}"))
+                            .font(FontFamily::SYSTEM_UI, 13.0)
+                            .text_color(dim_color.clone())
+                            .build()
+                            .unwrap();
+                        ctx.draw_text(
+                            &text_layout,
+                            Point::new(
+                                line_height,
+                                line_height * i as f64
+                                    + (line_height - text_layout.size().height)
+                                        / 2.0,
+                            ),
                         );
-                    let prefix = line_number.to_string().len() + 2;
-                    text_layout = text_layout.range_attribute(
-                        *start + prefix..*end + prefix,
-                        TextAttribute::TextColor(focus_color.clone()),
-                    );
-                    text_layout = text_layout.range_attribute(
-                        *start + prefix..*end + prefix,
-                        TextAttribute::Weight(FontWeight::BOLD),
-                    );
-                    let text_layout = text_layout.build().unwrap();
-                    ctx.draw_text(
-                        &text_layout,
-                        Point::new(
-                            line_height,
-                            line_height * i as f64
-                                + (line_height - text_layout.size().height) / 2.0,
-                        ),
+                        continue;
+                    }
+                };
+                let (line_number, (start, end), line) = &matches[index];
+                let replacement = data.search.replacement(line, *start, *end);
+                let (line, start, end) = match_preview(line, *start, *end);
+                let (line, replaced) = match replacement {
+                    Some(replacement) => {
+                        let (line, new) = replace_preview(&line, end, &replacement);
+                        (line, Some(new))
+                    }
+                    None => (line, None),
+                };
+                let mut text_layout = ctx
+                    .text()
+                    .new_text_layout(format!("{line_number}: {line}"))
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                            .clone(),
                     );
-                }
+                let prefix = line_number.to_string().len() + 2;
+                // the replacement shows after the match, which is dimmed
+                let (old_color, new) = match replaced {
+                    Some((new_start, new_end)) => {
+                        (dim_color, new_start + prefix..new_end + prefix)
+                    }
+                    None => (focus_color, start + prefix..end + prefix),
+                };
+                text_layout = text_layout.range_attribute(
+                    start + prefix..end + prefix,
+                    TextAttribute::TextColor(old_color.clone()),
+                );
+                text_layout = text_layout.range_attribute(
+                    new.clone(),
+                    TextAttribute::TextColor(focus_color.clone()),
+                );
+                text_layout = text_layout
+                    .range_attribute(new, TextAttribute::Weight(FontWeight::BOLD));
+                let text_layout = text_layout.build().unwrap();
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(
+                        line_height,
+                        line_height * i as f64
+                            + (line_height - text_layout.size().height) / 2.0,
+                    ),
+                );
             }
             i += 1;
         }
     }
 }
+
+/// Previews a clicked result, or keeps it open when double clicked. With Cmd
+/// on macOS and Ctrl elsewhere, it's opened to the side instead.
+fn jump_command(
+    location: EditorLocationNew,
+    mouse_event: &MouseEvent,
+) -> LapceUICommand {
+    let to_the_side = if cfg!(target_os = "macos") {
+        mouse_event.mods.meta()
+    } else {
+        mouse_event.mods.ctrl()
+    };
+    if to_the_side {
+        LapceUICommand::JumpToLocationToSide(location)
+    } else {
+        LapceUICommand::PreviewLocation(location, mouse_event.count > 1)
+    }
+}
+
+/// The path relative to the workspace, if it's in it.
+pub fn relative_path(path: &Path, workspace: Option<&Path>) -> String {
+    workspace
+        .and_then(|workspace| path.strip_prefix(workspace).ok())
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// What the search input gets from a selection: single line ones only,
+/// escaped for regex searches.
+pub fn search_prefill(selected: &str, regex: bool) -> Option<String> {
+    if selected.is_empty() || selected.contains(|c| c == '\n' || c == '\r') {
+        return None;
+    }
+    Some(if regex {
+        regex::escape(selected)
+    } else {
+        selected.to_string()
+    })
+}
+
+/// Whether the files with stale matches changed, after an edit or a save.
+pub fn stale_changed(old_data: &LapceTabData, data: &LapceTabData) -> bool {
+    if old_data.search.stale.same(&data.search.stale)
+        && old_data.search.revisions.same(&data.search.revisions)
+        && old_data.search.matches.same(&data.search.matches)
+        && old_data
+            .main_split
+            .open_files
+            .ptr_eq(&data.main_split.open_files)
+    {
+        return false;
+    }
+    old_data.search.stale_files(&old_data.main_split.open_files)
+        != data.search.stale_files(&data.main_split.open_files)
+}
+
+/// The buttons of the search panel's header: collapsing or expanding the
+/// results, refreshing them, only the changed files when there are some, and
+/// clearing the search.
+pub fn header_actions(data: &LapceTabData) -> Vec<PanelHeaderAction> {
+    let search = &data.search;
+    let mut actions = Vec::new();
+    if !search.matches.is_empty() {
+        actions.push(if search.all_collapsed() {
+            PanelHeaderAction::new(
+                "chevron-down.svg",
+                "Expand All",
+                LapceWorkbenchCommand::SearchExpandAll,
+            )
+        } else {
+            PanelHeaderAction::new(
+                "chevron-right.svg",
+                "Collapse All",
+                LapceWorkbenchCommand::SearchCollapseAll,
+            )
+        });
+    }
+    if search.query.is_empty() {
+        return actions;
+    }
+    actions.push(
+        if search.stale_files(&data.main_split.open_files).is_empty() {
+            PanelHeaderAction::new(
+                "refresh.svg",
+                "Refresh",
+                LapceWorkbenchCommand::SearchRerun,
+            )
+        } else {
+            PanelHeaderAction::new(
+                "refresh.svg",
+                "Refresh Changed Files",
+                LapceWorkbenchCommand::RefreshSearch,
+            )
+        },
+    );
+    actions.push(PanelHeaderAction::new(
+        "clear-all.svg",
+        "Clear Results",
+        LapceWorkbenchCommand::SearchClearResults,
+    ));
+    actions
+}
+
+/// The matches of the regex in the text as the proxy reports them: the first
+/// match of each line, as (line number from 1, range in bytes, line).
+pub fn find_matches(
+    regex: &Regex,
+    text: &str,
+) -> Vec<(usize, (usize, usize), String)> {
+    text.split_inclusive('\n')
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let m = regex.find(line)?;
+            Some((i + 1, (m.start(), m.end()), line.to_string()))
+        })
+        .collect()
+}
+
+/// The lines within `n` lines of the matches found in the text which don't
+/// match themselves, as the proxy reports them with the matches.
+pub fn find_context(
+    text: &str,
+    matches: &[(usize, (usize, usize), String)],
+    n: usize,
+) -> Vec<(usize, String)> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let matched: HashSet<usize> = matches.iter().map(|(line, _, _)| *line).collect();
+    let around: HashSet<usize> = matched
+        .iter()
+        .flat_map(|line| line.saturating_sub(n).max(1)..=line + n)
+        .filter(|line| !matched.contains(line))
+        .collect();
+    text.split_inclusive('\n')
+        .enumerate()
+        .filter(|(i, _)| around.contains(&(i + 1)))
+        .map(|(i, line)| (i + 1, line.to_string()))
+        .collect()
+}
+
+/// The matches and the lines of context of a search the proxy answered. With
+/// context, they come apart as `{"matches", "context"}`.
+pub fn search_results(
+    results: serde_json::Value,
+    context: usize,
+) -> Option<(
+    HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>,
+    HashMap<PathBuf, Vec<(usize, String)>>,
+)> {
+    if context == 0 {
+        return Some((serde_json::from_value(results).ok()?, HashMap::new()));
+    }
+    let matches = serde_json::from_value(results["matches"].clone()).ok()?;
+    let context =
+        serde_json::from_value(results["context"].clone()).unwrap_or_default();
+    Some((matches, context))
+}
+
+/// The include pattern that finds only the file at `path` in the workspace
+/// at `root`, with the characters special to globs escaped.
+pub fn include_pattern(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut pattern = String::new();
+    for component in relative.components() {
+        pattern.push('/');
+        for c in component.as_os_str().to_str()?.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+    }
+    Some(pattern)
+}
+
+/// How many characters of a result line are shown on each side of the match.
+const PREVIEW_CONTEXT: usize = 60;
+/// The most characters of a result line shown in a row, ellipses aside.
+const PREVIEW_MAX_CHARS: usize = 200;
+
+/// The part of a result line shown in its row: a window around the match,
+/// with ellipses where the line was cut, and the match's byte range in it.
+/// Lines of minified files run for kilobytes, which would make slow and
+/// unreadable rows.
+pub fn match_preview(
+    line: &str,
+    start: usize,
+    end: usize,
+) -> (String, usize, usize) {
+    let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+    let start = floor_char_boundary(line, start.min(line.len()));
+    let end = floor_char_boundary(line, end.min(line.len())).max(start);
+    // a long match is cut too, to leave room for what's around it
+    let end = end.min(byte_after_chars(
+        line,
+        start,
+        PREVIEW_MAX_CHARS - 2 * PREVIEW_CONTEXT,
+    ));
+    let from = byte_before_chars(line, start, PREVIEW_CONTEXT);
+    let to = byte_after_chars(line, end, PREVIEW_CONTEXT);
+
+    let mut preview = String::new();
+    if from > 0 {
+        preview.push('…');
+    }
+    let shift = preview.len();
+    preview.push_str(&line[from..to]);
+    if to < line.len() {
+        preview.push('…');
+    }
+    (preview, start - from + shift, end - from + shift)
+}
+
+/// The part of a line of context shown in its row: its start, with an
+/// ellipsis where it was cut.
+pub fn context_preview(line: &str) -> String {
+    let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+    let end = byte_after_chars(line, 0, PREVIEW_MAX_CHARS);
+    if end < line.len() {
+        format!("{}…", &line[..end])
+    } else {
+        line.to_string()
+    }
+}
+
+/// The preview with the replacement of its match inserted after the match
+/// ending at `end`, and the byte range of the replacement in it. Line breaks
+/// of the replacement show as "↵", to keep it on its row.
+pub fn replace_preview(
+    preview: &str,
+    end: usize,
+    replacement: &str,
+) -> (String, (usize, usize)) {
+    let replacement = replacement.replace("\r\n", "↵").replace('\n', "↵");
+    let mut line = preview[..end].to_string();
+    line.push_str(&replacement);
+    line.push_str(&preview[end..]);
+    (line, (end, end + replacement.len()))
+}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+/// The byte offset `n` characters after `from`, or the end of `s`.
+fn byte_after_chars(s: &str, from: usize, n: usize) -> usize {
+    s[from..]
+        .char_indices()
+        .nth(n)
+        .map(|(i, _)| from + i)
+        .unwrap_or(s.len())
+}
+
+/// The byte offset `n` characters before `to`, or the start of `s`.
+fn byte_before_chars(s: &str, to: usize, n: usize) -> usize {
+    if n == 0 {
+        return to;
+    }
+    s[..to]
+        .char_indices()
+        .rev()
+        .nth(n - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::Modifiers;
+
+    use super::*;
+    use crate::harness::{mouse_down, Harness, HarnessWidget, RecordingCtx};
+
+    impl HarnessWidget<LapceTabData> for SearchContent {
+        fn event(
+            &mut self,
+            ctx: &mut RecordingCtx,
+            event: &Event,
+            data: &mut LapceTabData,
+        ) {
+            if let Event::MouseDown(mouse_event) = event {
+                self.mouse_down(ctx, mouse_event, data);
+            }
+        }
+    }
+
+    /// A search for `query` which found matches on the given lines.
+    fn search(query: &str, files: &[(&str, &[usize])]) -> SearchData {
+        let mut search = SearchData::new();
+        search.query = query.to_string();
+        search.matches = Arc::new(
+            files
+                .iter()
+                .map(|(path, lines)| {
+                    (
+                        PathBuf::from(path),
+                        lines
+                            .iter()
+                            .map(|line| (*line, (0, 1), "".to_string()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        );
+        search
+    }
+
+    /// The file whose group comes first in the results.
+    fn first_file(search: &SearchData) -> PathBuf {
+        search.matches.keys().next().unwrap().clone()
+    }
+
+    #[test]
+    fn test_no_summary_before_search() {
+        let search = SearchData::new();
+        assert_eq!(search.summary_rows(), 0);
+        assert_eq!(search.row(0), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(search("a", &[]).summary(), "No results");
+        assert_eq!(
+            search("a", &[("a.rs", &[1])]).summary(),
+            "1 result in 1 file"
+        );
+        assert_eq!(
+            search("a", &[("a.rs", &[1, 2]), ("b.rs", &[3])]).summary(),
+            "3 results in 2 files"
+        );
+    }
+
+    #[test]
+    fn test_summary_while_searching() {
+        let mut search = SearchData::new();
+        search.start("a".to_string());
+        assert_eq!(search.summary(), "Searching…");
+        assert_eq!(search.row(0), Some(SearchRow::Summary));
+        let id = search.search_id;
+        search.add_matches(
+            id,
+            PathBuf::from("a.rs"),
+            vec![(1, (0, 1), "".into())],
+            Vec::new(),
+        );
+        assert_eq!(search.summary(), "1 result in 1 file, searching…");
+        search.finish(id, &HashMap::new());
+        assert_eq!(search.summary(), "1 result in 1 file");
+    }
+
+    #[test]
+    fn test_stale_results_are_dropped() {
+        let mut search = SearchData::new();
+        let old = search.start("a".to_string());
+        let new = search.start("ab".to_string());
+        let matches = vec![(1, (0, 1), "".to_string())];
+        search.add_matches(old, PathBuf::from("a.rs"), matches.clone(), Vec::new());
+        search.fail(old, "Search cancelled".to_string());
+        assert!(search.matches.is_empty());
+        assert_eq!(search.error, None);
+        assert!(search.is_searching());
+
+        search.add_matches(new, PathBuf::from("b.rs"), matches.clone(), Vec::new());
+        search.finish(new, &HashMap::new());
+        search.add_matches(new, PathBuf::from("c.rs"), matches, Vec::new());
+        assert_eq!(search.matches.len(), 1);
+        assert!(search.matches.contains_key(&PathBuf::from("b.rs")));
+    }
+
+    #[test]
+    fn test_finish_merges_unstreamed_matches() {
+        let mut search = SearchData::new();
+        let id = search.start("a".to_string());
+        let matches = self::search("a", &[("a.rs", &[1]), ("b.rs", &[2])]).matches;
+        search.finish(id, &matches);
+        assert_eq!(search.matches.len(), 2);
+        assert!(!search.is_searching());
+        assert_eq!(search.state, SearchState::Done { found: 2 });
+    }
+
+    #[test]
+    fn test_status() {
+        let mut search = SearchData::new();
+        assert_eq!(search.status(), None);
+        let id = search.start("a".to_string());
+        assert_eq!(search.status(), Some("Searching… 0 matches".to_string()));
+        let matches = vec![(1, (0, 1), "".to_string())];
+        search.add_matches(id, PathBuf::from("a.rs"), matches.clone(), Vec::new());
+        assert_eq!(search.status(), Some("Searching… 1 match".to_string()));
+        search.add_matches(id, PathBuf::from("b.rs"), matches, Vec::new());
+        search.finish(id, &HashMap::new());
+        assert_eq!(search.status(), Some("2 matches".to_string()));
+
+        search.remove_matches(id, &PathBuf::from("a.rs"));
+        assert_eq!(search.state, SearchState::Done { found: 1 });
+        search.clear();
+        assert_eq!(search.status(), None);
+    }
+
+    #[test]
+    fn test_summary_shows_error() {
+        let mut search = search("(", &[]);
+        search.error = Some("Invalid regex".to_string());
+        assert_eq!(search.summary(), "Invalid regex");
+    }
+
+    #[test]
+    fn test_set_include() {
+        let mut search = SearchData::new();
+        assert!(search.set_include(" src/**/*.rs, ,*.toml "));
+        assert_eq!(search.include, vec!["src/**/*.rs", "*.toml"]);
+        assert!(!search.set_include("src/**/*.rs,*.toml"));
+        assert!(search.set_include(""));
+        assert!(search.include.is_empty());
+    }
+
+    #[test]
+    fn test_rows() {
+        let search = search("a", &[("a.rs", &[3, 7])]);
+        let path = PathBuf::from("a.rs");
+        assert_eq!(search.row(0), Some(SearchRow::Summary));
+        assert_eq!(search.row(1), Some(SearchRow::File(path.clone())));
+        assert_eq!(search.row(2), Some(SearchRow::Match(path.clone(), 0)));
+        assert_eq!(search.row(3), Some(SearchRow::Match(path, 1)));
+        assert_eq!(search.row(4), None);
+    }
+
+    #[test]
+    fn test_rows_of_second_file() {
+        let search = search("a", &[("a.rs", &[1, 2]), ("b.rs", &[3])]);
+        let first = first_file(&search);
+        let first_rows = search.group_rows(&first, search.matches[&first].len());
+        match search.row(1 + first_rows + 1) {
+            Some(SearchRow::Match(path, 0)) => assert_ne!(path, first),
+            row => panic!("unexpected row {:?}", row),
+        }
+    }
+
+    #[test]
+    fn test_collapsed_file_takes_one_row() {
+        let mut search = search("a", &[("a.rs", &[1, 2]), ("b.rs", &[3])]);
+        let first = first_file(&search);
+        search.toggle_collapsed(&first);
+        assert_eq!(search.group_rows(&first, 2), 1);
+        match search.row(2) {
+            Some(SearchRow::File(path)) => assert_ne!(path, first),
+            row => panic!("unexpected row {:?}", row),
+        }
+    }
+
+    #[test]
+    fn test_select_skips_file_rows() {
+        let mut search = search("a", &[("a.rs", &[1, 2])]);
+        let path = PathBuf::from("a.rs");
+        search.select_next();
+        assert_eq!(search.selected, Some((path.clone(), 0)));
+        assert_eq!(search.selected_row(), Some(2));
+        search.select_next();
+        search.select_next();
+        assert_eq!(search.selected, Some((path.clone(), 1)));
+        search.select_previous();
+        assert_eq!(search.selected, Some((path, 0)));
+    }
+
+    #[test]
+    fn test_selection_follows_matches() {
+        let mut search = search("a", &[("a.rs", &[1, 2, 3])]);
+        let path = PathBuf::from("a.rs");
+        search.selected = Some((path.clone(), 2));
+        search.matches = self::search("a", &[("a.rs", &[1])]).matches;
+        search.update_selection();
+        assert_eq!(search.selected, Some((path, 0)));
+        search.matches = Arc::new(HashMap::new());
+        search.update_selection();
+        assert_eq!(search.selected, None);
+    }
+
+    #[test]
+    fn test_edited_and_saved_files_are_stale() {
+        let mut search = search("a", &[("a.rs", &[1]), ("b.rs", &[2])]);
+        let (a, b) = (PathBuf::from("a.rs"), PathBuf::from("b.rs"));
+        search.record_revision(&a, Some(3));
+        search.record_revision(&b, None);
+        // kept from when the matches were found
+        search.record_revision(&a, Some(4));
+        assert!(!search.is_stale(&a, Some(3)));
+        assert!(search.is_stale(&a, Some(4)));
+        assert!(!search.is_stale(&b, None));
+        assert!(!search.is_stale(&b, Some(0)));
+        assert!(search.mark_stale(&b));
+        assert!(search.is_stale(&b, None));
+        assert!(!search.mark_stale(&PathBuf::from("c.rs")));
+    }
+
+    #[test]
+    fn test_changed_files_are_stale() {
+        let mut search = search("a", &[("a.rs", &[1]), ("b.rs", &[2])]);
+        let (a, b) = (PathBuf::from("a.rs"), PathBuf::from("b.rs"));
+        let mut changes = FileChanges::default();
+        changes.created.push(PathBuf::from("c.rs"));
+        assert!(!search.mark_changed(&changes));
+        changes.removed.push(a.clone());
+        assert!(search.mark_changed(&changes));
+        assert!(search.is_stale(&a, None));
+        assert!(!search.is_stale(&b, None));
+
+        let bulk = FileChanges {
+            bulk: true,
+            ..Default::default()
+        };
+        assert!(search.mark_changed(&bulk));
+        assert!(search.is_stale(&b, None));
+    }
+
+    #[test]
+    fn test_refresh_file_keeps_its_place() {
+        let mut search = search("a", &[("a.rs", &[1]), ("b.rs", &[2])]);
+        let path = first_file(&search);
+        search.toggle_collapsed(&path);
+        search.mark_stale(&path);
+        search.refresh_file(
+            search.search_id,
+            &path,
+            vec![(5, (0, 1), "a".into())],
+            Vec::new(),
+            2,
+        );
+        assert_eq!(first_file(&search), path);
+        assert_eq!(search.matches[&path][0].0, 5);
+        assert!(search.collapsed.contains(&path));
+        assert!(!search.is_stale(&path, Some(2)));
+        // results of an earlier search are dropped
+        search.refresh_file(search.search_id - 1, &path, Vec::new(), Vec::new(), 0);
+        assert!(search.matches.contains_key(&path));
+        search.refresh_file(search.search_id, &path, Vec::new(), Vec::new(), 0);
+        assert!(!search.matches.contains_key(&path));
+    }
+
+    #[test]
+    fn test_context_rows() {
+        let mut search = search("a", &[("a.rs", &[3, 5, 9])]);
+        let path = PathBuf::from("a.rs");
+        // the context of the first two matches overlaps on line 4
+        let lines = [2, 4, 6, 8, 10];
+        search.context = Arc::new(
+            vec![(
+                path.clone(),
+                lines.iter().map(|line| (*line, "".to_string())).collect(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            search.result_lines(&path),
+            vec![
+                ResultLine::Context(2),
+                ResultLine::Match(0),
+                ResultLine::Context(4),
+                ResultLine::Match(1),
+                ResultLine::Context(6),
+                ResultLine::Context(8),
+                ResultLine::Match(2),
+                ResultLine::Context(10),
+            ]
+        );
+        assert_eq!(search.group_rows(&path, 3), 9);
+        assert_eq!(search.row(2), Some(SearchRow::Context(path.clone(), 2)));
+        assert_eq!(search.row(3), Some(SearchRow::Match(path.clone(), 0)));
+        assert_eq!(search.row(8), Some(SearchRow::Match(path.clone(), 2)));
+        assert_eq!(search.row(10), None);
+
+        search.select_next();
+        search.select_next();
+        assert_eq!(search.selected, Some((path.clone(), 1)));
+        assert_eq!(search.selected_row(), Some(5));
+        search.select_next();
+        assert_eq!(search.selected_row(), Some(8));
+    }
+
+    #[test]
+    fn test_find_context() {
+        let text = "a\nb\nx\nc\nx\nd\ne\n";
+        let regex = SearchOptions::default().build_regex("x").unwrap();
+        let matches = find_matches(&regex, text);
+        assert_eq!(
+            find_context(text, &matches, 1),
+            vec![
+                (2, "b\n".to_string()),
+                (4, "c\n".to_string()),
+                (6, "d\n".to_string()),
+            ]
+        );
+        assert_eq!(find_context(text, &matches, 5).len(), 5);
+        assert!(find_context(text, &matches, 0).is_empty());
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let regex = SearchOptions::default().build_regex("ab").unwrap();
+        assert_eq!(
+            find_matches(&regex, "ab ab\nno\r\nxAB"),
+            vec![
+                (1, (0, 2), "ab ab\n".to_string()),
+                (3, (1, 3), "xAB".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_include_pattern() {
+        let root = Path::new("/ws");
+        assert_eq!(
+            include_pattern(root, Path::new("/ws/src/a[1]*.rs")).as_deref(),
+            Some("/src/a\\[1\\]\\*.rs")
+        );
+        assert_eq!(include_pattern(root, Path::new("/other/a.rs")), None);
+    }
+
+    #[test]
+    fn test_short_line_preview() {
+        let (preview, start, end) = match_preview("let a = 1;\n", 4, 5);
+        assert_eq!(preview, "let a = 1;");
+        assert_eq!(&preview[start..end], "a");
+    }
+
+    #[test]
+    fn test_preview_of_match_near_start() {
+        let line = format!("abc{}", "x".repeat(1000));
+        let (preview, start, end) = match_preview(&line, 1, 2);
+        assert_eq!(&preview[start..end], "b");
+        assert!(preview.starts_with("abc"));
+        assert!(preview.ends_with('…'));
+        assert_eq!(preview.chars().count(), 2 + PREVIEW_CONTEXT + 1);
+    }
+
+    #[test]
+    fn test_preview_of_match_near_end() {
+        let line = format!("{}abc", "x".repeat(1000));
+        let (preview, start, end) = match_preview(&line, 1001, 1002);
+        assert_eq!(&preview[start..end], "b");
+        assert!(preview.starts_with('…'));
+        assert!(preview.ends_with("abc"));
+        assert_eq!(preview.chars().count(), 1 + PREVIEW_CONTEXT + 2);
+    }
+
+    #[test]
+    fn test_preview_with_multi_byte_characters() {
+        let line = format!("{}needle{}", "é".repeat(100), "ü".repeat(100));
+        let start = "é".len() * 100;
+        let (preview, start, end) = match_preview(&line, start, start + 6);
+        assert_eq!(&preview[start..end], "needle");
+        assert_eq!(
+            preview,
+            format!("…{}needle{}…", "é".repeat(60), "ü".repeat(60))
+        );
+    }
+
+    #[test]
+    fn test_preview_cuts_long_match() {
+        let line = "y".repeat(1000);
+        let (preview, start, end) = match_preview(&line, 0, 1000);
+        assert_eq!(start, 0);
+        assert_eq!(end - start, PREVIEW_MAX_CHARS - 2 * PREVIEW_CONTEXT);
+        assert!(preview.chars().count() <= PREVIEW_MAX_CHARS + 2);
+    }
+
+    #[test]
+    fn test_toggle_collapsed() {
+        let mut search = search("a", &[("a.rs", &[1])]);
+        let path = PathBuf::from("a.rs");
+        search.toggle_collapsed(&path);
+        assert!(search.collapsed.contains(&path));
+        search.toggle_collapsed(&path);
+        assert!(!search.collapsed.contains(&path));
+    }
+
+    #[test]
+    fn test_toggle_pin() {
+        let mut search = search("a", &[("a.rs", &[1, 2])]);
+        search.pinned_stale = false;
+        assert!(search.toggle_pin());
+        assert!(search.is_pinned());
+        // the finished search gives the count right away
+        assert_eq!(search.pinned[0].count, Some(2));
+        assert!(!search.pinned_stale);
+
+        // the same query with other options is another pin
+        search.options.regex = true;
+        assert!(!search.is_pinned());
+        search.state = SearchState::Searching { found: 2 };
+        assert!(search.toggle_pin());
+        assert_eq!(search.pinned.len(), 2);
+        assert_eq!(search.pinned[1].count, None);
+        assert!(search.pinned_stale);
+
+        assert!(search.toggle_pin());
+        assert_eq!(search.pinned.len(), 1);
+        assert!(!SearchData::new().toggle_pin());
+    }
+
+    #[test]
+    fn test_set_pinned_count() {
+        let mut search = search("a", &[]);
+        search.toggle_pin();
+        let mut pin = search.pinned[0].clone();
+        pin.count = Some(5);
+        search.set_pinned_count(&pin, Some(3));
+        assert_eq!(search.pinned[0].count, Some(3));
+        // the pin was removed while it was counted
+        search.unpin(0);
+        search.set_pinned_count(&pin, Some(1));
+        assert!(search.pinned.is_empty());
+    }
+
+    #[test]
+    fn test_rows_with_pins() {
+        let mut search = search("a", &[("a.rs", &[3])]);
+        search.toggle_pin();
+        let path = PathBuf::from("a.rs");
+        assert_eq!(search.row(0), Some(SearchRow::PinnedHeader));
+        assert_eq!(search.row(1), Some(SearchRow::Pinned(0)));
+        assert_eq!(search.row(2), Some(SearchRow::Summary));
+        assert_eq!(search.row(3), Some(SearchRow::File(path.clone())));
+        search.select_next();
+        assert_eq!(search.selected_row(), Some(4));
+
+        search.pinned_collapsed = true;
+        assert_eq!(search.row(1), Some(SearchRow::Summary));
+        assert_eq!(search.row(3), Some(SearchRow::Match(path, 0)));
+    }
+
+    #[test]
+    fn test_replacement_preview() {
+        let mut search = search("(\\w+)_(\\w+)", &[]);
+        search.options.regex = true;
+        search.matches = Arc::new(
+            vec![(
+                PathBuf::from("a.rs"),
+                vec![(1, (4, 11), "let foo_bar = 1;\n".to_string())],
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert!(search.set_replace("\\u$1\\u$2"));
+        let (_, (start, end), line) = &search.matches[&PathBuf::from("a.rs")][0];
+        assert_eq!(
+            search.replacement(line, *start, *end),
+            Some("FooBar".to_string())
+        );
+        assert_eq!(
+            search.replaced_lines(&PathBuf::from("a.rs")),
+            vec![(
+                1,
+                "let foo_bar = 1;".to_string(),
+                "let FooBar = 1;".to_string()
+            )]
+        );
+        assert_eq!(
+            replace_preview("let foo_bar = 1;", 11, "Foo\nBar"),
+            ("let foo_barFoo↵Bar = 1;".to_string(), (11, 20))
+        );
+
+        // no preview while the replace field is empty
+        search.set_replace("");
+        assert_eq!(search.replacement(line, *start, *end), None);
+    }
+
+    #[test]
+    fn test_invalid_replacement() {
+        let mut search = search("(a)", &[("a.rs", &[1])]);
+        search.options.regex = true;
+        search.set_replace("$2");
+        assert!(search.replacer.is_none());
+        assert!(search.summary().contains("no group 2"));
+        assert!(search.replaced_lines(&PathBuf::from("a.rs")).is_empty());
+        search.set_replace("$1");
+        assert!(search.replace_error.is_none());
+        assert_eq!(search.summary(), "1 result in 1 file");
+    }
+
+    #[test]
+    fn test_collapse_and_expand_all() {
+        let mut search = search("a", &[("a.rs", &[1]), ("b.rs", &[2])]);
+        assert!(!search.all_collapsed());
+        search.collapse_all();
+        assert!(search.all_collapsed());
+        assert_eq!(search.row(3), None);
+        search.expand_all();
+        assert!(search.collapsed.is_empty());
+    }
+
+    #[test]
+    fn test_results_text() {
+        let mut search = search("b", &[("/ws/src/a.rs", &[3, 7])]);
+        Arc::make_mut(&mut search.matches)
+            .get_mut(&PathBuf::from("/ws/src/a.rs"))
+            .unwrap()[1] = (7, (6, 7), "    éb = 1;\n".to_string());
+        assert_eq!(
+            search.match_text(&PathBuf::from("/ws/src/a.rs"), 1),
+            Some("7:6: éb = 1;".to_string())
+        );
+        assert_eq!(
+            search.results_text(Some(Path::new("/ws"))),
+            "src/a.rs\n    3:1: \n    7:6: éb = 1;\n"
+        );
+        assert_eq!(
+            search.results_text(None),
+            "/ws/src/a.rs\n    3:1: \n    7:6: éb = 1;\n"
+        );
+        search.context = Arc::new(
+            vec![(
+                PathBuf::from("/ws/src/a.rs"),
+                vec![(4, "  c\n".to_string())].into_iter().collect(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            search.results_text(None),
+            "/ws/src/a.rs\n    3:1: \n    4- c\n    7:6: éb = 1;\n"
+        );
+    }
+
+    #[test]
+    fn test_search_prefill() {
+        assert_eq!(search_prefill("foo", false), Some("foo".to_string()));
+        assert_eq!(search_prefill("a.b", true), Some("a\\.b".to_string()));
+        assert_eq!(search_prefill("a.b", false), Some("a.b".to_string()));
+        assert_eq!(search_prefill("", false), None);
+        assert_eq!(search_prefill("foo\nbar", false), None);
+        assert_eq!(search_prefill("foo\r\n", false), None);
+    }
+
+    /// Clicks the results on the row, `count` times, and returns the
+    /// commands the click sent to the tab.
+    fn click(
+        harness: &mut Harness,
+        row: usize,
+        count: u8,
+        mods: Modifiers,
+    ) -> Vec<&LapceUICommand> {
+        let line_height = harness.data.config.editor.line_height as f64;
+        let pos = Point::new(10.0, (row as f64 + 0.5) * line_height);
+        harness.clear();
+        harness.send(&mut SearchContent::new(), &mouse_down(pos, count, mods));
+        let tab_id = harness.data.id;
+        harness
+            .ui_commands()
+            .into_iter()
+            .map(|(cmd, target)| {
+                assert_eq!(target, Target::Widget(tab_id));
+                cmd
+            })
+            .collect()
+    }
+
+    fn harness_with_results() -> Harness {
+        let mut harness = Harness::new();
+        harness.data.search = Arc::new(search("a", &[("a.rs", &[3, 5])]));
+        harness
+    }
+
+    #[test]
+    fn test_click_match_previews() {
+        let mut harness = harness_with_results();
+        match click(&mut harness, 3, 1, Modifiers::empty())[..] {
+            [LapceUICommand::PreviewLocation(location, false)] => {
+                assert_eq!(location.path, PathBuf::from("a.rs"));
+                assert_eq!(location.position.unwrap().line, 4);
+            }
+            ref commands => panic!("expected a preview, got {:?}", commands),
+        }
+        assert_eq!(
+            harness.data.search.selected,
+            Some((PathBuf::from("a.rs"), 1))
+        );
+        assert_eq!(harness.data.history.searches, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_double_click_match_keeps_preview() {
+        let mut harness = harness_with_results();
+        assert!(matches!(
+            click(&mut harness, 2, 2, Modifiers::empty())[..],
+            [LapceUICommand::PreviewLocation(_, true)]
+        ));
+    }
+
+    #[test]
+    fn test_click_match_to_the_side() {
+        let mut harness = harness_with_results();
+        let mods = if cfg!(target_os = "macos") {
+            Modifiers::META
+        } else {
+            Modifiers::CONTROL
+        };
+        assert!(matches!(
+            click(&mut harness, 2, 1, mods)[..],
+            [LapceUICommand::JumpToLocationToSide(_)]
+        ));
+    }
+
+    #[test]
+    fn test_click_file_toggles_collapsed() {
+        let mut harness = harness_with_results();
+        let path = PathBuf::from("a.rs");
+        assert!(click(&mut harness, 1, 1, Modifiers::empty()).is_empty());
+        assert!(harness.data.search.collapsed.contains(&path));
+        // the matches are folded away under the file
+        assert_eq!(harness.data.search.row(2), None);
+
+        click(&mut harness, 1, 1, Modifiers::empty());
+        assert!(!harness.data.search.collapsed.contains(&path));
+    }
+
+    #[test]
+    fn test_click_summary_does_nothing() {
+        let mut harness = harness_with_results();
+        assert!(click(&mut harness, 0, 1, Modifiers::empty()).is_empty());
+        assert_eq!(harness.data.search.selected, None);
+        assert!(harness.data.history.searches.is_empty());
+    }
+}
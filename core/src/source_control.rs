@@ -9,16 +9,16 @@ use druid::{
     theme,
     widget::{CrossAxisAlignment, Flex, FlexParams, Label, Scroll},
     Affine, BoxConstraints, Color, Command, Cursor, Data, Env, Event, EventCtx,
-    FontDescriptor, FontFamily, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point,
-    Rect, RenderContext, Size, Target, TextLayout, UpdateCtx, Widget, WidgetExt,
-    WidgetId, WidgetPod, WindowId,
+    FontDescriptor, FontFamily, FontWeight, LayoutCtx, LifeCycle, LifeCycleCtx,
+    PaintCtx, Point, Rect, RenderContext, Size, Target, TextLayout, UpdateCtx,
+    Widget, WidgetExt, WidgetId, WidgetPod, WindowId,
 };
-use lapce_proxy::dispatch::FileDiff;
+use lapce_proxy::dispatch::{FileDiff, GitFileCommand};
 
 use crate::{
     command::{
-        CommandExecuted, CommandTarget, LapceCommand, LapceUICommand,
-        LAPCE_UI_COMMAND,
+        CommandExecuted, CommandTarget, LapceCommand, LapceCommandNew,
+        LapceUICommand, LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
     },
     config::LapceTheme,
     data::{FocusArea, LapceTabData, PanelKind},
@@ -26,7 +26,10 @@ use crate::{
     keypress::KeyPressFocus,
     movement::Movement,
     palette::svg_tree_size,
-    panel::{LapcePanel, PanelHeaderKind, PanelPosition, PanelProperty},
+    panel::{
+        LapcePanel, PanelHeaderAction, PanelHeaderKind, PanelPosition, PanelProperty,
+    },
+    proxy::LapceProxy,
     scroll::LapceScrollNew,
     split::{LapceSplitNew, SplitDirection, SplitMoveDirection},
     state::Mode,
@@ -46,9 +49,14 @@ pub struct SourceControlData {
     pub file_list_id: WidgetId,
     pub file_list_index: usize,
     pub editor_view_id: WidgetId,
-    pub file_diffs: Vec<(FileDiff, bool)>,
+    /// The changes of the working tree that aren't staged.
+    pub file_diffs: Vec<FileDiff>,
+    /// The staged changes, which the next commit is made of.
+    pub staged_diffs: Vec<FileDiff>,
     pub branch: String,
     pub branches: Vec<String>,
+    /// How far the branch is ahead and behind of its upstream, if it has one.
+    pub ahead_behind: Option<(usize, usize)>,
 }
 
 impl SourceControlData {
@@ -64,8 +72,101 @@ impl SourceControlData {
             split_id: WidgetId::next(),
             split_direction: SplitDirection::Horizontal,
             file_diffs: Vec::new(),
+            staged_diffs: Vec::new(),
             branch: "".to_string(),
             branches: Vec::new(),
+            ahead_behind: None,
+        }
+    }
+
+    /// The branches for the branch picker, with whether each is the current
+    /// one, which comes first.
+    pub fn branch_items(&self) -> Vec<(String, bool)> {
+        let mut branches: Vec<(String, bool)> = self
+            .branches
+            .iter()
+            .map(|b| (b.to_string(), *b == self.branch))
+            .collect();
+        branches.sort_by_key(|(_, current)| !current);
+        branches
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.file_diffs.is_empty() || !self.staged_diffs.is_empty()
+    }
+
+    /// The files with changes, staged or not.
+    pub fn changed_paths(&self) -> Vec<&PathBuf> {
+        let mut paths = Vec::new();
+        for diff in self.staged_diffs.iter().chain(self.file_diffs.iter()) {
+            if !paths.contains(&diff.path()) {
+                paths.push(diff.path());
+            }
+        }
+        paths
+    }
+
+    /// The lines of the list: the staged changes, then the others, each
+    /// under a title when there are some.
+    pub fn rows(&self) -> Vec<SourceControlRow> {
+        let mut rows = Vec::new();
+        for (staged, diffs) in
+            [(true, &self.staged_diffs), (false, &self.file_diffs)]
+        {
+            if diffs.is_empty() {
+                continue;
+            }
+            rows.push(SourceControlRow::Group {
+                staged,
+                count: diffs.len(),
+            });
+            rows.extend(diffs.iter().map(|diff| SourceControlRow::File {
+                staged,
+                diff: diff.clone(),
+            }));
+        }
+        rows
+    }
+
+    /// The changes `command` acts on, the staged ones to unstage and the
+    /// others to stage or discard: the ones of `paths`, or else the selected
+    /// file or group.
+    pub fn diffs_for(
+        &self,
+        command: GitFileCommand,
+        paths: Option<&[PathBuf]>,
+    ) -> Vec<FileDiff> {
+        let staged = command == GitFileCommand::Unstage;
+        let diffs = if staged {
+            &self.staged_diffs
+        } else {
+            &self.file_diffs
+        };
+        if let Some(paths) = paths {
+            return diffs
+                .iter()
+                .filter(|diff| diff.paths().iter().any(|p| paths.contains(p)))
+                .cloned()
+                .collect();
+        }
+        match self.rows().get(self.file_list_index) {
+            Some(SourceControlRow::Group { staged: s, .. }) if *s == staged => {
+                diffs.clone()
+            }
+            Some(SourceControlRow::File { staged: s, diff }) if *s == staged => {
+                vec![diff.clone()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Keeps the selection on the list after its changes were updated.
+    pub fn set_diffs(&mut self, diffs: Vec<FileDiff>, staged: Vec<FileDiff>) {
+        self.file_diffs = diffs;
+        self.staged_diffs = staged;
+        let len = self.rows().len();
+        if self.file_list_index >= len {
+            self.file_list_index = len.saturating_sub(1);
         }
     }
 
@@ -105,6 +206,67 @@ impl SourceControlData {
     }
 }
 
+/// A line of the list of the source control panel.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SourceControlRow {
+    /// The title of the staged or the other changes, with how many there are.
+    Group {
+        staged: bool,
+        count: usize,
+    },
+    File {
+        staged: bool,
+        diff: FileDiff,
+    },
+}
+
+impl SourceControlRow {
+    pub fn staged(&self) -> bool {
+        match self {
+            SourceControlRow::Group { staged, .. }
+            | SourceControlRow::File { staged, .. } => *staged,
+        }
+    }
+
+    /// The buttons of the line, the rightmost first. They act on the file,
+    /// or on all the files of the group. Those an older proxy can't run are
+    /// left out.
+    pub fn actions(
+        &self,
+        proxy: &LapceProxy,
+    ) -> Vec<(&'static str, LapceWorkbenchCommand)> {
+        let actions = if self.staged() {
+            vec![("remove.svg", LapceWorkbenchCommand::SourceControlUnstage)]
+        } else {
+            vec![
+                ("add.svg", LapceWorkbenchCommand::SourceControlStage),
+                ("discard.svg", LapceWorkbenchCommand::SourceControlDiscard),
+            ]
+        };
+        actions
+            .into_iter()
+            .filter(|(_, command)| {
+                command
+                    .required_capability()
+                    .map(|capability| proxy.has_capability(capability))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+}
+
+/// The buttons of the source control panel's header.
+pub fn header_actions(data: &LapceTabData) -> Vec<PanelHeaderAction> {
+    if !data.source_control.has_changes() {
+        return Vec::new();
+    }
+    vec![PanelHeaderAction::new(
+        "check.svg",
+        "Commit",
+        LapceWorkbenchCommand::SourceControlCommit,
+    )]
+}
+
 impl KeyPressFocus for SourceControlData {
     fn get_mode(&self) -> Mode {
         Mode::Normal
@@ -146,7 +308,7 @@ impl KeyPressFocus for SourceControlData {
             LapceCommand::Up | LapceCommand::ListPrevious => {
                 self.file_list_index = Movement::Up.update_index(
                     self.file_list_index,
-                    self.file_diffs.len(),
+                    self.rows().len(),
                     1,
                     true,
                 );
@@ -154,23 +316,39 @@ impl KeyPressFocus for SourceControlData {
             LapceCommand::Down | LapceCommand::ListNext => {
                 self.file_list_index = Movement::Down.update_index(
                     self.file_list_index,
-                    self.file_diffs.len(),
+                    self.rows().len(),
                     1,
                     true,
                 );
             }
             LapceCommand::ListExpand => {
-                if self.file_diffs.len() > 0 {
-                    self.file_diffs[self.file_list_index].1 =
-                        !self.file_diffs[self.file_list_index].1;
+                // stages the selection, or unstages it
+                if let Some(row) = self.rows().get(self.file_list_index) {
+                    let command = if row.staged() {
+                        LapceWorkbenchCommand::SourceControlUnstage
+                    } else {
+                        LapceWorkbenchCommand::SourceControlStage
+                    };
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: command.to_string(),
+                            data: None,
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
                 }
             }
             LapceCommand::ListSelect => {
-                if self.file_diffs.len() > 0 {
+                if let Some(SourceControlRow::File { diff, .. }) =
+                    self.rows().get(self.file_list_index)
+                {
                     ctx.submit_command(Command::new(
                         LAPCE_UI_COMMAND,
                         LapceUICommand::OpenFileDiff(
-                            self.file_diffs[self.file_list_index].0.path().clone(),
+                            diff.path().clone(),
                             "head".to_string(),
                         ),
                         Target::Auto,
@@ -187,14 +365,14 @@ impl KeyPressFocus for SourceControlData {
 
 pub struct SourceControlFileList {
     widget_id: WidgetId,
-    mouse_down: Option<usize>,
+    mouse_pos: Option<Point>,
 }
 
 impl SourceControlFileList {
     pub fn new(widget_id: WidgetId) -> Self {
         Self {
             widget_id,
-            mouse_down: None,
+            mouse_pos: None,
         }
     }
 
@@ -205,6 +383,21 @@ impl SourceControlFileList {
         data.focus_area = FocusArea::Panel(PanelKind::SourceControl);
         data.focus = self.widget_id;
     }
+
+    /// Where the `i`th button of the line is, from the right, left of the
+    /// status of the change.
+    fn action_rect(width: f64, line: usize, i: usize, line_height: f64) -> Rect {
+        let x = width - 30.0 - (i + 1) as f64 * line_height;
+        Size::new(line_height, line_height)
+            .to_rect()
+            .with_origin(Point::new(x, line as f64 * line_height))
+    }
+
+    fn hovered_line(&self, line_height: f64) -> Option<usize> {
+        self.mouse_pos
+            .filter(|pos| pos.y >= 0.0)
+            .map(|pos| (pos.y / line_height).floor() as usize)
+    }
 }
 
 impl Widget<LapceTabData> for SourceControlFileList {
@@ -221,51 +414,48 @@ impl Widget<LapceTabData> for SourceControlFileList {
     ) {
         match event {
             Event::MouseMove(mouse_event) => {
+                self.mouse_pos = Some(mouse_event.pos);
                 ctx.set_cursor(&druid::Cursor::Pointer);
-                ctx.set_handled();
-            }
-            Event::MouseUp(mouse_event) => {
-                let line_height = data.config.editor.line_height as f64;
-                let y = mouse_event.pos.y;
-                if y > 0.0 {
-                    let line = (y / line_height).floor() as usize;
-                    if line < data.source_control.file_diffs.len()
-                        && mouse_event.pos.x < line_height
-                    {
-                        if let Some(mouse_down) = self.mouse_down {
-                            if mouse_down == line {
-                                let source_control =
-                                    Arc::make_mut(&mut data.source_control);
-                                source_control.file_diffs[line].1 =
-                                    !source_control.file_diffs[line].1;
-                            }
-                        }
-                    }
-                }
-                self.mouse_down = None;
+                ctx.request_paint();
                 ctx.set_handled();
             }
             Event::MouseDown(mouse_event) => {
-                self.mouse_down = None;
-                let source_control = Arc::make_mut(&mut data.source_control);
                 let line_height = data.config.editor.line_height as f64;
+                let width = ctx.size().width;
                 let y = mouse_event.pos.y;
-                if y > 0.0 {
-                    let line = (y / line_height).floor() as usize;
-                    if line < source_control.file_diffs.len() {
-                        source_control.file_list_index = line;
-                        if mouse_event.pos.x < line_height {
-                            self.mouse_down = Some(line);
-                        } else {
-                            ctx.submit_command(Command::new(
-                                LAPCE_UI_COMMAND,
-                                LapceUICommand::OpenFileDiff(
-                                    source_control.file_diffs[line].0.path().clone(),
-                                    "head".to_string(),
-                                ),
-                                Target::Widget(data.id),
-                            ));
-                        }
+                let rows = data.source_control.rows();
+                let line = (y / line_height).floor() as usize;
+                if let Some(row) = rows.get(line).filter(|_| y > 0.0) {
+                    let source_control = Arc::make_mut(&mut data.source_control);
+                    source_control.file_list_index = line;
+                    // the buttons act on the selected line
+                    let action =
+                        row.actions(&data.proxy).into_iter().enumerate().find(
+                            |(i, _)| {
+                                Self::action_rect(width, line, *i, line_height)
+                                    .contains(mouse_event.pos)
+                            },
+                        );
+                    if let Some((_, (_, command))) = action {
+                        ctx.submit_command(Command::new(
+                            LAPCE_NEW_COMMAND,
+                            LapceCommandNew {
+                                cmd: command.to_string(),
+                                data: None,
+                                palette_desc: None,
+                                target: CommandTarget::Workbench,
+                            },
+                            Target::Widget(data.id),
+                        ));
+                    } else if let SourceControlRow::File { diff, .. } = row {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::OpenFileDiff(
+                                diff.path().clone(),
+                                "head".to_string(),
+                            ),
+                            Target::Widget(data.id),
+                        ));
                     }
                 }
                 self.request_focus(ctx, data);
@@ -310,6 +500,10 @@ impl Widget<LapceTabData> for SourceControlFileList {
             LifeCycle::FocusChanged(_) => {
                 ctx.request_paint();
             }
+            LifeCycle::HotChanged(false) => {
+                self.mouse_pos = None;
+                ctx.request_paint();
+            }
             _ => (),
         }
     }
@@ -321,10 +515,15 @@ impl Widget<LapceTabData> for SourceControlFileList {
         data: &LapceTabData,
         env: &Env,
     ) {
-        if data.source_control.file_diffs.len()
-            != old_data.source_control.file_diffs.len()
+        if data.source_control.file_diffs != old_data.source_control.file_diffs
+            || data.source_control.staged_diffs
+                != old_data.source_control.staged_diffs
         {
             ctx.request_layout();
+        } else if data.source_control.file_list_index
+            != old_data.source_control.file_list_index
+        {
+            ctx.request_paint();
         }
     }
 
@@ -336,7 +535,7 @@ impl Widget<LapceTabData> for SourceControlFileList {
         env: &Env,
     ) -> Size {
         let line_height = data.config.editor.line_height as f64;
-        let height = line_height * data.source_control.file_diffs.len() as f64;
+        let height = line_height * data.source_control.rows().len() as f64;
         Size::new(bc.max().width, height)
     }
 
@@ -345,9 +544,10 @@ impl Widget<LapceTabData> for SourceControlFileList {
 
         let line_height = data.config.editor.line_height as f64;
 
-        let diffs = &data.source_control.file_diffs;
+        let rows = data.source_control.rows();
+        let hovered = self.hovered_line(line_height);
 
-        if ctx.is_focused() && diffs.len() > 0 {
+        if ctx.is_focused() && rows.len() > 0 {
             let rect = Size::new(ctx.size().width, line_height)
                 .to_rect()
                 .with_origin(Point::new(
@@ -364,84 +564,86 @@ impl Widget<LapceTabData> for SourceControlFileList {
         let start_line = (rect.y0 / line_height).floor() as usize;
         let end_line = (rect.y1 / line_height).ceil() as usize;
         for line in start_line..end_line {
-            if line >= diffs.len() {
+            if line >= rows.len() {
                 break;
             }
             let y = line_height * line as f64;
-            let (diff, checked) = diffs[line].clone();
-            let mut path = diff.path().clone();
-            if let Some(workspace_path) = data.workspace.path.as_ref() {
-                path = path
-                    .strip_prefix(workspace_path)
-                    .unwrap_or(&path)
-                    .to_path_buf();
-            }
-            {
+            let diff = match &rows[line] {
+                SourceControlRow::Group { staged, count } => {
+                    let title = if *staged { "Staged Changes" } else { "Changes" };
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(title)
+                        .font(FontFamily::SYSTEM_UI, 13.0)
+                        .default_attribute(FontWeight::BOLD)
+                        .text_color(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                                .clone(),
+                        )
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(
+                        &text_layout,
+                        Point::new(
+                            10.0,
+                            y + (line_height - text_layout.size().height) / 2.0,
+                        ),
+                    );
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(count.to_string())
+                        .font(FontFamily::SYSTEM_UI, 13.0)
+                        .text_color(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                                .clone(),
+                        )
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(
+                        &text_layout,
+                        Point::new(
+                            self_size.width - 10.0 - text_layout.size().width,
+                            y + (line_height - text_layout.size().height) / 2.0,
+                        ),
+                    );
+                    None
+                }
+                SourceControlRow::File { diff, .. } => Some(diff),
+            };
+
+            if let Some(diff) = diff {
+                let mut path = diff.path().clone();
+                if let Some(workspace_path) = data.workspace.path.as_ref() {
+                    path = path
+                        .strip_prefix(workspace_path)
+                        .unwrap_or(&path)
+                        .to_path_buf();
+                }
+                let svg = file_svg_new(&path);
                 let width = 13.0;
                 let height = 13.0;
-                let origin = Point::new(
-                    (line_height - width) / 2.0 + 5.0,
-                    (line_height - height) / 2.0 + y,
-                );
-                let rect = Size::new(width, height).to_rect().with_origin(origin);
-                ctx.stroke(rect, &Color::rgb8(0, 0, 0), 1.0);
-
-                if checked {
-                    let mut path = BezPath::new();
-                    path.move_to((origin.x + 3.0, origin.y + 7.0));
-                    path.line_to((origin.x + 6.0, origin.y + 9.5));
-                    path.line_to((origin.x + 10.0, origin.y + 3.0));
-                    ctx.stroke(path, &Color::rgb8(0, 0, 0), 2.0);
-                }
-            }
-            let svg = file_svg_new(&path);
-            let width = 13.0;
-            let height = 13.0;
-            let rect = Size::new(width, height).to_rect().with_origin(Point::new(
-                (line_height - width) / 2.0 + line_height,
-                (line_height - height) / 2.0 + y,
-            ));
-            ctx.draw_svg(&svg, rect, None);
-
-            let file_name = path
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_string();
-
-            let text_layout = ctx
-                .text()
-                .new_text_layout(file_name)
-                .font(FontFamily::SYSTEM_UI, 13.0)
-                .text_color(
-                    data.config
-                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                        .clone(),
-                )
-                .build()
-                .unwrap();
-            ctx.draw_text(
-                &text_layout,
-                Point::new(
-                    line_height * 2.0,
-                    y + (line_height - text_layout.size().height) / 2.0,
-                ),
-            );
-            let folder = path
-                .parent()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_string();
-            if folder != "" {
-                let x = text_layout.size().width;
+                let rect =
+                    Size::new(width, height).to_rect().with_origin(Point::new(
+                        (line_height - width) / 2.0 + line_height,
+                        (line_height - height) / 2.0 + y,
+                    ));
+                ctx.draw_svg(&svg, rect, None);
+
+                let file_name = path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("")
+                    .to_string();
 
                 let text_layout = ctx
                     .text()
-                    .new_text_layout(folder)
+                    .new_text_layout(file_name)
                     .font(FontFamily::SYSTEM_UI, 13.0)
                     .text_color(
                         data.config
-                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
                             .clone(),
                     )
                     .build()
@@ -449,45 +651,207 @@ impl Widget<LapceTabData> for SourceControlFileList {
                 ctx.draw_text(
                     &text_layout,
                     Point::new(
-                        line_height * 2.0 + x + 5.0,
+                        line_height * 2.0,
                         y + (line_height - text_layout.size().height) / 2.0,
                     ),
                 );
+                let folder = path
+                    .parent()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("")
+                    .to_string();
+                if folder != "" {
+                    let x = text_layout.size().width;
+
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(folder)
+                        .font(FontFamily::SYSTEM_UI, 13.0)
+                        .text_color(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                                .clone(),
+                        )
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(
+                        &text_layout,
+                        Point::new(
+                            line_height * 2.0 + x + 5.0,
+                            y + (line_height - text_layout.size().height) / 2.0,
+                        ),
+                    );
+                }
+
+                let (svg, color) = match diff {
+                    FileDiff::Modified(_) => (
+                        "diff-modified.svg",
+                        data.config.get_color_unchecked(
+                            LapceTheme::SOURCE_CONTROL_MODIFIED,
+                        ),
+                    ),
+                    FileDiff::Added(_) => (
+                        "diff-added.svg",
+                        data.config
+                            .get_color_unchecked(LapceTheme::SOURCE_CONTROL_ADDED),
+                    ),
+                    FileDiff::Deleted(_) => (
+                        "diff-removed.svg",
+                        data.config
+                            .get_color_unchecked(LapceTheme::SOURCE_CONTROL_REMOVED),
+                    ),
+                    FileDiff::Renamed(_, _) => (
+                        "diff-renamed.svg",
+                        data.config.get_color_unchecked(
+                            LapceTheme::SOURCE_CONTROL_MODIFIED,
+                        ),
+                    ),
+                };
+                let svg = get_svg(svg).unwrap();
+
+                let svg_size = 15.0;
+                let rect =
+                    Size::new(svg_size, svg_size)
+                        .to_rect()
+                        .with_origin(Point::new(
+                            self_size.width - svg_size - 10.0,
+                            line as f64 * line_height
+                                + (line_height - svg_size) / 2.0,
+                        ));
+                ctx.draw_svg(&svg, rect, Some(&color.clone().with_alpha(0.9)));
             }
 
-            let (svg, color) = match diff {
-                FileDiff::Modified(_) => (
-                    "diff-modified.svg",
-                    data.config
-                        .get_color_unchecked(LapceTheme::SOURCE_CONTROL_MODIFIED),
-                ),
-                FileDiff::Added(_) => (
-                    "diff-added.svg",
-                    data.config
-                        .get_color_unchecked(LapceTheme::SOURCE_CONTROL_ADDED),
-                ),
-                FileDiff::Deleted(_) => (
-                    "diff-removed.svg",
-                    data.config
-                        .get_color_unchecked(LapceTheme::SOURCE_CONTROL_REMOVED),
-                ),
-                FileDiff::Renamed(_, _) => (
-                    "diff-renamed.svg",
-                    data.config
-                        .get_color_unchecked(LapceTheme::SOURCE_CONTROL_MODIFIED),
-                ),
+            let actions = if hovered == Some(line) {
+                rows[line].actions(&data.proxy)
+            } else {
+                Vec::new()
             };
-            let svg = get_svg(svg).unwrap();
-
-            let svg_size = 15.0;
-            let rect =
-                Size::new(svg_size, svg_size)
-                    .to_rect()
-                    .with_origin(Point::new(
-                        self_size.width - svg_size - 10.0,
-                        line as f64 * line_height + (line_height - svg_size) / 2.0,
-                    ));
-            ctx.draw_svg(&svg, rect, Some(&color.clone().with_alpha(0.9)));
+            if !actions.is_empty() {
+                let background = if ctx.is_focused()
+                    && line == data.source_control.file_list_index
+                {
+                    LapceTheme::PANEL_CURRENT
+                } else {
+                    LapceTheme::PANEL_BACKGROUND
+                };
+                let left = Self::action_rect(
+                    self_size.width,
+                    line,
+                    actions.len() - 1,
+                    line_height,
+                );
+                // over the end of a long path
+                ctx.fill(
+                    Rect::new(left.x0, left.y0, self_size.width - 30.0, left.y1),
+                    data.config.get_color_unchecked(background),
+                );
+                let icon_padding = 4.0;
+                for (i, (icon, _)) in actions.iter().enumerate() {
+                    let rect =
+                        Self::action_rect(self_size.width, line, i, line_height);
+                    if rect.contains(self.mouse_pos.unwrap_or_default()) {
+                        ctx.fill(
+                            rect,
+                            data.config.get_color_unchecked(
+                                LapceTheme::EDITOR_CURRENT_LINE,
+                            ),
+                        );
+                    }
+                    if let Some(svg) = get_svg(icon) {
+                        ctx.draw_svg(
+                            &svg,
+                            rect.inflate(-icon_padding, -icon_padding),
+                            Some(
+                                data.config.get_color_unchecked(
+                                    LapceTheme::EDITOR_FOREGROUND,
+                                ),
+                            ),
+                        );
+                    }
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch_items() {
+        let mut source_control = SourceControlData::new();
+        source_control.branches = vec![
+            "dev".to_string(),
+            "main".to_string(),
+            "origin/main".to_string(),
+        ];
+        source_control.branch = "main".to_string();
+        assert_eq!(
+            source_control.branch_items(),
+            vec![
+                ("main".to_string(), true),
+                ("dev".to_string(), false),
+                ("origin/main".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rows_and_the_diffs_they_act_on() {
+        let (a, b, c) = (
+            PathBuf::from("/w/a.rs"),
+            PathBuf::from("/w/b.rs"),
+            PathBuf::from("/w/c.rs"),
+        );
+        let mut source_control = SourceControlData::new();
+        source_control.set_diffs(
+            vec![FileDiff::Modified(a.clone()), FileDiff::Added(b.clone())],
+            vec![FileDiff::Renamed(c.clone(), PathBuf::from("/w/d.rs"))],
+        );
+        let rows = source_control.rows();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[0],
+            SourceControlRow::Group {
+                staged: true,
+                count: 1
+            }
+        );
+        assert_eq!(
+            rows[2],
+            SourceControlRow::Group {
+                staged: false,
+                count: 2
+            }
+        );
+
+        // the group of the changes
+        source_control.file_list_index = 2;
+        assert_eq!(
+            source_control.diffs_for(GitFileCommand::Stage, None),
+            source_control.file_diffs
+        );
+        assert!(source_control
+            .diffs_for(GitFileCommand::Unstage, None)
+            .is_empty());
+        // a staged file
+        source_control.file_list_index = 1;
+        assert_eq!(
+            source_control.diffs_for(GitFileCommand::Unstage, None),
+            source_control.staged_diffs
+        );
+        assert!(source_control
+            .diffs_for(GitFileCommand::Discard, None)
+            .is_empty());
+        assert_eq!(
+            source_control.diffs_for(GitFileCommand::Discard, Some(&[b.clone()])),
+            vec![FileDiff::Added(b)]
+        );
+
+        source_control.file_list_index = 4;
+        source_control.set_diffs(Vec::new(), Vec::new());
+        assert!(!source_control.has_changes());
+        assert_eq!(source_control.file_list_index, 0);
+    }
+}
@@ -1,36 +1,43 @@
 use crate::{
+    buffer::{BufferContent, LocalBufferKind},
     command::{
-        CommandTarget, LapceCommandNew, LapceUICommand, LapceWorkbenchCommand,
-        LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+        CommandCtx, CommandTarget, LapceCommandNew, LapceUICommand,
+        LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
     },
     config::{Config, LapceTheme},
     data::{
-        EditorContent, FocusArea, LapceEditorData, LapceTabData, PanelData,
-        PanelKind,
+        ClosedEditor, EditorContent, FocusArea, LapceEditorData, LapceTabData,
+        PanelData, PanelKind,
     },
-    editor::{EditorLocation, LapceEditorView},
+    editor::{EditorLocation, EditorLocationNew, LapceEditorView},
     keypress::{DefaultKeyPressHandler, KeyPress},
     scroll::{LapcePadding, LapceScroll},
     svg::logo_svg,
-    terminal::{LapceTerminal, LapceTerminalData, LapceTerminalView},
+    terminal::{LapceTerminal, LapceTerminalData, LapceTerminalView, TerminalTask},
 };
-use std::{cmp::Ordering, sync::Arc};
+use std::{cmp::Ordering, path::PathBuf, str::FromStr, sync::Arc, thread};
 
 use druid::{
     kurbo::{Line, Rect},
     piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder},
     widget::IdentityWrapper,
-    Command, FontFamily, Target, WidgetId, WindowId,
+    Command, FontFamily, Selector, SingleUse, Target, WidgetId, WindowId,
 };
 use druid::{
-    theme, BoxConstraints, Cursor, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle,
-    LifeCycleCtx, PaintCtx, Point, RenderContext, Size, UpdateCtx, Widget,
-    WidgetExt, WidgetPod,
+    theme, BoxConstraints, Cursor, Data, Env, Event, EventCtx, KbKey, KeyEvent,
+    LayoutCtx, LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point, RenderContext,
+    Size, UpdateCtx, Widget, WidgetExt, WidgetPod,
 };
-use lapce_proxy::terminal::TermId;
+use lapce_proxy::{protocol::CAPABILITY_TERMINAL_CWD, terminal::TermId};
+use serde::{Deserialize, Serialize};
 use strum::EnumMessage;
 
-#[derive(Debug)]
+/// Hands the widget of an editor over to the split it's moved to, so that it
+/// keeps its state instead of being recreated.
+const SPLIT_EDITOR_TRANSFER: Selector<SingleUse<ChildWidgetNew>> =
+    Selector::new("lapce.split-editor-transfer");
+
+#[derive(Clone, Copy, Debug)]
 pub enum SplitMoveDirection {
     Up,
     Down,
@@ -38,19 +45,84 @@ pub enum SplitMoveDirection {
     Left,
 }
 
+/// What to do with the unsaved changes of the editors being closed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloseChoice {
+    Save,
+    DontSave,
+    Cancel,
+}
+
+/// The editors of a split a close is for.
 #[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloseRequest {
+    Editor(WidgetId),
+    /// All the editors but this one.
+    Others(WidgetId),
+    All,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Data, Serialize, Deserialize)]
 pub enum SplitDirection {
     Vertical,
     Horizontal,
 }
 
+/// The structure of a split and of the splits nested in it, which a split
+/// can be built back from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplitDescription {
+    pub direction: SplitDirection,
+    pub children: Vec<SplitChildDescription>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplitChildDescription {
+    /// The raw id of the child's widget, which finds the child again when
+    /// the description is applied while the app still runs.
+    #[serde(default)]
+    pub id: Option<u64>,
+    pub kind: SplitChildKind,
+    pub flex: bool,
+    pub params: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SplitChildKind {
+    Editor(BufferContent),
+    Terminal,
+    Split(SplitDescription),
+    /// Any other widget, like the input of the search panel, which can't be
+    /// built from a description.
+    Widget,
+}
+
+/// The children of a nested split, as (id, flex, params). They're kept in the
+/// tab data because the parent can't reach into the widget of a nested split.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NestedSplit {
+    pub direction: SplitDirection,
+    pub children: Vec<(WidgetId, bool, f64)>,
+}
+
 pub struct LapceSplitNew {
     split_id: WidgetId,
     children: Vec<ChildWidgetNew>,
     children_ids: Vec<WidgetId>,
     direction: SplitDirection,
     show_border: bool,
-    commands: Vec<(LapceCommandNew, PietTextLayout, Rect, PietTextLayout)>,
+    hints: EmptyEditorHints,
+    /// The split this one is nested in, for the terminal splits created by
+    /// splitting across the panel's direction.
+    parent_id: Option<WidgetId>,
+    /// The editor being dragged by its header.
+    drag: Option<WidgetId>,
+    /// Where the dragged editor would be dropped: the child under the mouse,
+    /// and whether it goes after that child rather than before it.
+    drop_target: Option<(usize, bool)>,
+    /// The close waiting for the answer to whether to save the editors
+    /// before they're closed.
+    confirm_close: Option<CloseRequest>,
 }
 
 pub struct ChildWidgetNew {
@@ -61,6 +133,181 @@ pub struct ChildWidgetNew {
 }
 
 impl LapceSplitNew {
+    /// Builds the split, and the splits nested in it, from a description,
+    /// creating the editors and terminals it holds.
+    pub fn from_description(
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        split_id: WidgetId,
+        description: &SplitDescription,
+    ) -> Self {
+        let mut split =
+            LapceSplitNew::new(split_id).direction(description.direction);
+        for child in description.children.iter() {
+            let (widget, child_id) =
+                match LapceSplitNew::build_child(ctx, data, split_id, &child.kind) {
+                    Some(built) => built,
+                    None => continue,
+                };
+            split = if child.flex {
+                split.with_flex_child(widget, Some(child_id), child.params)
+            } else {
+                split.with_child(widget, Some(child_id), child.params)
+            };
+        }
+        split.update_editors_order(data);
+        split
+    }
+
+    /// Creates the widget of a child of a description, with the id it's
+    /// known by. `None` for the kinds that can't be built.
+    fn build_child(
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        split_id: WidgetId,
+        kind: &SplitChildKind,
+    ) -> Option<(Box<dyn Widget<LapceTabData>>, WidgetId)> {
+        let built: (Box<dyn Widget<LapceTabData>>, WidgetId) = match kind {
+            SplitChildKind::Editor(content) => {
+                // a file is opened in the editor once it's in the data
+                let initial = match content {
+                    BufferContent::File(_) => {
+                        BufferContent::Local(LocalBufferKind::Empty)
+                    }
+                    BufferContent::Local(_) => content.clone(),
+                };
+                let editor_data = LapceEditorData::new(
+                    None,
+                    Some(split_id),
+                    initial,
+                    &data.config,
+                );
+                let view_id = editor_data.view_id;
+                let editor = LapceEditorView::new(&editor_data);
+                data.main_split
+                    .editors
+                    .insert(view_id, Arc::new(editor_data));
+                if let BufferContent::File(path) = content {
+                    open_file(ctx, data, view_id, path);
+                }
+                (editor.boxed(), view_id)
+            }
+            SplitChildKind::Terminal => {
+                let terminal_data = Arc::new(LapceTerminalData::new(
+                    data.workspace.clone(),
+                    None,
+                    split_id,
+                    ctx.get_external_handle(),
+                    data.proxy.clone(),
+                ));
+                Arc::make_mut(&mut data.terminal)
+                    .terminals
+                    .insert(terminal_data.term_id, terminal_data.clone());
+                (
+                    LapceTerminalView::new(&terminal_data).boxed(),
+                    terminal_data.widget_id,
+                )
+            }
+            SplitChildKind::Split(nested) => {
+                let nested_id = WidgetId::next();
+                let mut nested =
+                    LapceSplitNew::from_description(ctx, data, nested_id, nested);
+                nested.parent_id = Some(split_id);
+                nested.update_nested_split(data);
+                (nested.boxed(), nested_id)
+            }
+            SplitChildKind::Widget => return None,
+        };
+        Some(built)
+    }
+
+    /// Whether the split holds the terminals of a tab of the terminal
+    /// panel.
+    fn is_terminal_tab(&self, data: &LapceTabData) -> bool {
+        data.terminal.tab_index(self.split_id).is_some()
+    }
+
+    /// Takes the key the split gets while it's empty. Enter starts a
+    /// terminal in an empty terminal tab, the other keys go to the
+    /// shortcuts unless this returns true.
+    fn empty_key_down<C: CommandCtx>(
+        &self,
+        ctx: &mut C,
+        data: &LapceTabData,
+        key_event: &KeyEvent,
+    ) -> bool {
+        ctx.set_handled();
+        if key_event.key == KbKey::Enter && self.is_terminal_tab(data) {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::InitTerminalPanel(true, None),
+                Target::Widget(self.split_id),
+            ));
+            return true;
+        }
+        false
+    }
+
+    /// Where the focus is when the split itself is focused, empty.
+    fn focus_area(&self, data: &LapceTabData) -> FocusArea {
+        if self.is_terminal_tab(data) {
+            FocusArea::Panel(PanelKind::Terminal)
+        } else {
+            FocusArea::Editor
+        }
+    }
+
+    /// The size its children don't shrink below along its direction. Only
+    /// the splits of editors and terminals have one, not the ones of the
+    /// panels.
+    fn min_child_size(&self, data: &LapceTabData) -> f64 {
+        let terminal = self.is_terminal_tab(data)
+            || data
+                .terminal
+                .terminals
+                .values()
+                .any(|t| t.split_id == self.split_id);
+        if terminal {
+            return data.config.ui.terminal_min_size as f64;
+        }
+        let editors = self.split_id == *data.main_split.split_id
+            || self
+                .children_ids
+                .iter()
+                .any(|id| data.main_split.editors.contains_key(id));
+        if editors {
+            data.config.ui.editor_min_size as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether the split, of `size`, has room for one more flex child along
+    /// `direction` without any of them getting smaller than the minimum.
+    /// Across its direction, the new child shares the room with what's
+    /// there.
+    fn has_room(
+        &self,
+        data: &LapceTabData,
+        size: Size,
+        direction: SplitDirection,
+    ) -> bool {
+        let along = |size: Size| match direction {
+            SplitDirection::Vertical => size.width,
+            SplitDirection::Horizontal => size.height,
+        };
+        let mut space = along(size);
+        let flex_count = if direction == self.direction {
+            for child in self.children.iter().filter(|c| !c.flex) {
+                space -= along(child.layout_rect.size());
+            }
+            self.children.iter().filter(|c| c.flex).count() + 1
+        } else {
+            2
+        };
+        space >= self.min_child_size(data) * flex_count as f64
+    }
+
     pub fn new(split_id: WidgetId) -> Self {
         Self {
             split_id,
@@ -68,7 +315,11 @@ impl LapceSplitNew {
             children_ids: Vec::new(),
             direction: SplitDirection::Vertical,
             show_border: true,
-            commands: vec![],
+            hints: EmptyEditorHints::new(),
+            parent_id: None,
+            drag: None,
+            drop_target: None,
+            confirm_close: None,
         }
     }
 
@@ -141,6 +392,28 @@ impl LapceSplitNew {
         self.children.insert(index, child);
     }
 
+    /// Inserts a flex child sharing the space of the child at `from`, which
+    /// keeps half of it, or taking an even share without one. The other
+    /// children keep their ratios.
+    fn insert_flex_child_sharing(
+        &mut self,
+        index: usize,
+        child: Box<dyn Widget<LapceTabData>>,
+        child_id: Option<WidgetId>,
+        from: Option<usize>,
+    ) {
+        let flex_index =
+            |i: usize| self.children[..i].iter().filter(|c| c.flex).count();
+        let mut params = self.flex_params();
+        let at = flex_index(index.min(self.children.len()));
+        let from = from
+            .filter(|from| self.children.get(*from).map(|c| c.flex) == Some(true))
+            .map(flex_index);
+        insert_params(&mut params, at, from);
+        self.insert_flex_child(index, child, child_id, 1.0);
+        self.set_flex_params(&params);
+    }
+
     pub fn even_flex_children(&mut self) {
         for child in self.children.iter_mut() {
             if child.flex {
@@ -149,6 +422,144 @@ impl LapceSplitNew {
         }
     }
 
+    /// Scales the params of the flex children back to adding up to their
+    /// count after one was removed, keeping their ratios.
+    fn normalize_flex_children(&mut self) {
+        let mut params = self.flex_params();
+        normalize_params(&mut params);
+        self.set_flex_params(&params);
+    }
+
+    fn flex_params(&self) -> Vec<f64> {
+        self.children
+            .iter()
+            .filter(|c| c.flex)
+            .map(|c| c.params)
+            .collect()
+    }
+
+    fn set_flex_params(&mut self, params: &[f64]) {
+        for (child, params) in self
+            .children
+            .iter_mut()
+            .filter(|c| c.flex)
+            .zip(params.iter())
+        {
+            child.params = *params;
+        }
+    }
+
+    /// Keeps the editor order and flex params in the main split data in sync
+    /// with the children, so that the layout can be saved.
+    fn update_editors_order(&self, data: &mut LapceTabData) {
+        if self.split_id != *data.main_split.split_id {
+            return;
+        }
+        data.main_split.editors_order = Arc::new(self.children_ids.clone());
+        data.main_split.editors_flex =
+            Arc::new(self.children.iter().map(|c| c.params).collect());
+    }
+
+    /// Records the children of a nested split, see `NestedSplit`.
+    fn update_nested_split(&self, data: &mut LapceTabData) {
+        if self.parent_id.is_none() {
+            return;
+        }
+        let nested = NestedSplit {
+            direction: self.direction,
+            children: self
+                .children_ids
+                .iter()
+                .zip(self.children.iter())
+                .map(|(id, child)| (*id, child.flex, child.params))
+                .collect(),
+        };
+        Arc::make_mut(&mut data.terminal)
+            .nested_splits
+            .insert(self.split_id, nested);
+    }
+
+    /// Describes the split as it is now, down to the splits nested in it.
+    pub fn to_description(&self, data: &LapceTabData) -> SplitDescription {
+        let children: Vec<(WidgetId, bool, f64)> = self
+            .children_ids
+            .iter()
+            .zip(self.children.iter())
+            .map(|(id, child)| (*id, child.flex, child.params))
+            .collect();
+        describe_split(self.direction, &children, data)
+    }
+
+    /// Changes the split to the layout of a description. The children it
+    /// names by id are kept and moved in place, the ones it doesn't name are
+    /// removed, and the rest of it is built. A kept nested split whose
+    /// layout changed is built again.
+    pub fn apply_description(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        description: &SplitDescription,
+    ) {
+        let current: Vec<(WidgetId, ChildWidgetNew)> = self
+            .children_ids
+            .drain(..)
+            .zip(self.children.drain(..))
+            .collect();
+        let (layout, removed) =
+            layout_children(current, description, |id, kind| match kind {
+                SplitChildKind::Split(_) => &describe_child(id, data) == kind,
+                _ => true,
+            });
+        for (id, _) in removed {
+            forget_child(id, data);
+        }
+
+        for (child, kept) in layout {
+            let (child_id, widget) = match kept {
+                Some((child_id, widget)) => {
+                    if let SplitChildKind::Editor(BufferContent::File(path)) =
+                        &child.kind
+                    {
+                        if describe_child(child_id, data) != child.kind {
+                            open_file(ctx, data, child_id, path);
+                        }
+                    }
+                    (child_id, widget.widget)
+                }
+                None => {
+                    let (widget, child_id) = match LapceSplitNew::build_child(
+                        ctx,
+                        data,
+                        self.split_id,
+                        &child.kind,
+                    ) {
+                        Some(built) => built,
+                        None => continue,
+                    };
+                    (child_id, WidgetPod::new(widget))
+                }
+            };
+            self.children_ids.push(child_id);
+            self.children.push(ChildWidgetNew {
+                widget,
+                flex: child.flex,
+                params: child.params,
+                layout_rect: Rect::ZERO,
+            });
+        }
+        self.direction = description.direction;
+
+        if let Some(active) = *data.main_split.active {
+            if !data.main_split.editors.contains_key(&active) {
+                data.main_split.active = Arc::new(None);
+            }
+        }
+        self.update_editors_order(data);
+        self.update_nested_split(data);
+        ctx.children_changed();
+        ctx.request_layout();
+    }
+
     fn paint_bar(&mut self, ctx: &mut PaintCtx, config: &Config) {
         let children_len = self.children.len();
         if children_len <= 1 {
@@ -174,10 +585,126 @@ impl LapceSplitNew {
         }
     }
 
-    pub fn split_editor_close(
+    /// The editors the close is for, none if the editor it names is gone.
+    fn closing(&self, request: CloseRequest) -> Vec<WidgetId> {
+        match request {
+            CloseRequest::Editor(id) | CloseRequest::Others(id)
+                if !self.children_ids.contains(&id) =>
+            {
+                Vec::new()
+            }
+            CloseRequest::Editor(id) => vec![id],
+            CloseRequest::Others(id) => self
+                .children_ids
+                .iter()
+                .filter(|child_id| **child_id != id)
+                .cloned()
+                .collect(),
+            CloseRequest::All => self.children_ids.clone(),
+        }
+    }
+
+    /// Closes the editors, or asks first whether to save the files with
+    /// unsaved changes that only they show, once for all of them. The
+    /// editors stay until the answer comes back.
+    pub fn split_editor_close<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
+        request: CloseRequest,
+    ) {
+        if self.confirm_close.is_some() {
+            return;
+        }
+        let unsaved = unsaved_last_views(data, &self.closing(request));
+        if unsaved.is_empty() {
+            self.close_editors(ctx, data, request);
+            return;
+        }
+        self.confirm_close = Some(request);
+        let split_id = self.split_id;
+        let event_sink = ctx.get_external_handle();
+        thread::spawn(move || {
+            let names: Vec<&str> = unsaved
+                .iter()
+                .map(|path| path.file_name().and_then(|s| s.to_str()).unwrap_or(""))
+                .collect();
+            let message = if names.len() == 1 {
+                format!("Do you want to save the changes to {}?", names[0])
+            } else {
+                format!(
+                    "Do you want to save the changes to these files?\n\n{}",
+                    names.join("\n")
+                )
+            };
+            let choice = match tinyfiledialogs::message_box_yes_no_cancel(
+                "Unsaved changes",
+                &message,
+                tinyfiledialogs::MessageBoxIcon::Question,
+                tinyfiledialogs::YesNoCancel::Cancel,
+            ) {
+                tinyfiledialogs::YesNoCancel::Yes => CloseChoice::Save,
+                tinyfiledialogs::YesNoCancel::No => CloseChoice::DontSave,
+                tinyfiledialogs::YesNoCancel::Cancel => CloseChoice::Cancel,
+            };
+            event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SplitEditorConfirmClose(request, choice),
+                Target::Widget(split_id),
+            );
+        });
+    }
+
+    /// Runs the close that was waiting for the answer, saving the files
+    /// first if asked to. Without saving, the changes stay in the buffers of
+    /// the files, so they're back when they're opened again. Nothing moves
+    /// on a cancel.
+    fn split_editor_confirm_close(
         &mut self,
         ctx: &mut EventCtx,
         data: &mut LapceTabData,
+        request: CloseRequest,
+        choice: CloseChoice,
+    ) {
+        if self.confirm_close != Some(request) {
+            return;
+        }
+        self.confirm_close = None;
+        match choice {
+            CloseChoice::Cancel => return,
+            CloseChoice::Save => {
+                for path in unsaved_last_views(data, &self.closing(request)) {
+                    data.main_split.save_buffer(ctx, &path);
+                }
+            }
+            CloseChoice::DontSave => {}
+        }
+        self.close_editors(ctx, data, request);
+    }
+
+    fn close_editors<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
+        request: CloseRequest,
+    ) {
+        match request {
+            CloseRequest::Editor(widget_id) => {
+                if self.children_ids.contains(&widget_id) {
+                    self.remove_editor(ctx, data, widget_id);
+                }
+            }
+            CloseRequest::Others(widget_id) => {
+                self.remove_other_editors(ctx, data, widget_id)
+            }
+            CloseRequest::All => self.remove_all_editors(ctx, data),
+        }
+    }
+
+    fn remove_editor<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
         widget_id: WidgetId,
     ) {
         if self.children.len() == 0 {
@@ -192,7 +719,14 @@ impl LapceSplitNew {
             }
         }
 
-        if self.children.len() > 1 {
+        let view_id = self.children[index].widget.id();
+        if let Some(previous) = data.main_split.previous_focus(view_id) {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(previous),
+            ));
+        } else if self.children.len() > 1 {
             let new_index = if index >= self.children.len() - 1 {
                 index - 1
             } else {
@@ -212,21 +746,191 @@ impl LapceSplitNew {
                 Target::Widget(self.split_id),
             ));
         }
-        let view_id = self.children[index].widget.id();
+        data.main_split.remember_closed(view_id, index);
         data.main_split.editors.remove(&view_id);
+        data.main_split.forget_focus(view_id);
         self.children.remove(index);
         self.children_ids.remove(index);
-        data.main_split.editors_order = Arc::new(self.children_ids.clone());
+        self.update_editors_panel(data);
+
+        self.normalize_flex_children();
+        self.update_editors_order(data);
+        ctx.children_changed();
+    }
+
+    /// The editors panel stops being listed once its last editor is closed.
+    fn update_editors_panel(&self, data: &mut LapceTabData) {
+        if self.split_id == data.editors_panel.split_id && self.children.is_empty() {
+            data.update_editors_panel(false);
+        }
+    }
+
+    fn remove_other_editors<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
+        widget_id: WidgetId,
+    ) {
+        let index = match self.children_ids.iter().position(|id| id == &widget_id) {
+            Some(index) => index,
+            None => return,
+        };
+
+        for (i, view_id) in self.children_ids.iter().enumerate() {
+            if i != index {
+                data.main_split.remember_closed(*view_id, i);
+                data.main_split.editors.remove(view_id);
+                data.main_split.forget_focus(*view_id);
+            }
+        }
+        let child = self.children.remove(index);
+        self.children.clear();
+        self.children.push(child);
+        self.children_ids = vec![widget_id];
+        self.update_editors_panel(data);
+
+        data.main_split.active = Arc::new(Some(widget_id));
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(widget_id),
+        ));
+        self.normalize_flex_children();
+        self.update_editors_order(data);
+        ctx.children_changed();
+    }
+
+    fn remove_all_editors<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
+    ) {
+        for (i, view_id) in self.children_ids.iter().enumerate() {
+            data.main_split.remember_closed(*view_id, i);
+            data.main_split.editors.remove(view_id);
+            data.main_split.forget_focus(*view_id);
+        }
+        self.children.clear();
+        self.children_ids.clear();
+        self.update_editors_panel(data);
+
+        data.main_split.active = Arc::new(None);
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(self.split_id),
+        ));
+        self.update_editors_order(data);
+        ctx.children_changed();
+    }
 
-        self.even_flex_children();
+    /// Opens a closed editor again at its index, or last if the split has
+    /// fewer children now.
+    fn split_editor_reopen(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        closed: &ClosedEditor,
+    ) {
+        let editor_data = LapceEditorData::new(
+            None,
+            Some(self.split_id),
+            BufferContent::Local(LocalBufferKind::Empty),
+            &data.config,
+        );
+        let view_id = editor_data.view_id;
+        let editor = LapceEditorView::new(&editor_data);
+        data.main_split
+            .editors
+            .insert(view_id, Arc::new(editor_data));
+        let index = closed.index.min(self.children.len());
+        self.insert_flex_child_sharing(index, editor.boxed(), Some(view_id), None);
+        if self.split_id == data.editors_panel.split_id && self.children.len() == 1 {
+            data.update_editors_panel(true);
+        }
+        data.main_split.go_to_location(
+            ctx,
+            Some(view_id),
+            EditorLocationNew {
+                path: closed.path.clone(),
+                position: Some(closed.position),
+                scroll_offset: Some(closed.scroll_offset),
+                hisotry: None,
+                selection_end: None,
+            },
+            &data.config,
+        );
+        self.update_editors_order(data);
         ctx.children_changed();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(view_id),
+        ));
     }
 
-    pub fn split_editor_exchange(
+    /// Splits the editor with a new one showing the location, which the
+    /// panels then keep opening locations to the side in, or previewing
+    /// them in. Without room for it, the editor shows the location itself.
+    fn split_editor_to(
         &mut self,
         ctx: &mut EventCtx,
         data: &mut LapceTabData,
         widget_id: WidgetId,
+        location: &EditorLocationNew,
+        preview: bool,
+    ) {
+        let index = match self.children_ids.iter().position(|id| id == &widget_id) {
+            Some(index) => index,
+            None => return,
+        };
+        if !self.has_room(data, ctx.size(), self.direction) {
+            data.main_split.jump_to_location(
+                ctx,
+                Some(widget_id),
+                location.clone(),
+                &data.config,
+            );
+            return;
+        }
+
+        let mut editor_data = LapceEditorData::new(
+            None,
+            Some(self.split_id),
+            BufferContent::Local(LocalBufferKind::Empty),
+            &data.config,
+        );
+        editor_data.preview = preview;
+        let view_id = editor_data.view_id;
+        let editor = LapceEditorView::new(&editor_data);
+        data.main_split
+            .editors
+            .insert(view_id, Arc::new(editor_data));
+        self.insert_flex_child_sharing(
+            index + 1,
+            editor.boxed(),
+            Some(view_id),
+            Some(index),
+        );
+        data.main_split.go_to_location(
+            ctx,
+            Some(view_id),
+            location.clone(),
+            &data.config,
+        );
+        data.main_split.push_focus_history(view_id);
+        if !preview {
+            data.main_split.side_editor = Arc::new(Some(view_id));
+        }
+        self.update_editors_order(data);
+        ctx.children_changed();
+    }
+
+    pub fn split_editor_exchange<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
+        widget_id: WidgetId,
     ) {
         if self.children.len() <= 1 {
             return;
@@ -254,15 +958,15 @@ impl LapceSplitNew {
         self.children_ids.swap(index, index + 1);
 
         if data.main_split.editors.contains_key(&widget_id) {
-            data.main_split.editors_order = Arc::new(self.children_ids.clone());
+            self.update_editors_order(data);
         }
 
         ctx.request_layout();
     }
 
-    pub fn split_editor_move(
+    pub fn split_editor_move<C: CommandCtx>(
         &mut self,
-        ctx: &mut EventCtx,
+        ctx: &mut C,
         data: &mut LapceTabData,
         direction: &SplitMoveDirection,
         widget_id: WidgetId,
@@ -277,39 +981,23 @@ impl LapceSplitNew {
 
         let new_index = if self.direction == SplitDirection::Vertical {
             match direction {
-                SplitMoveDirection::Left => {
-                    if index == 0 {
-                        return;
-                    }
-                    index - 1
+                SplitMoveDirection::Left if index > 0 => Some(index - 1),
+                SplitMoveDirection::Right if index + 1 < self.children.len() => {
+                    Some(index + 1)
                 }
-                SplitMoveDirection::Right => {
-                    if index >= self.children.len() - 1 {
-                        return;
-                    }
-                    index + 1
-                }
-                _ => index,
+                _ => None,
             }
         } else {
             match direction {
-                SplitMoveDirection::Up => {
-                    if index == 0 {
-                        return;
-                    }
-                    index - 1
-                }
-                SplitMoveDirection::Down => {
-                    if index >= self.children.len() - 1 {
-                        return;
-                    }
-                    index + 1
+                SplitMoveDirection::Up if index > 0 => Some(index - 1),
+                SplitMoveDirection::Down if index + 1 < self.children.len() => {
+                    Some(index + 1)
                 }
-                _ => index,
+                _ => None,
             }
         };
 
-        if new_index != index {
+        if let Some(new_index) = new_index {
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
                 LapceUICommand::Focus,
@@ -320,15 +1008,240 @@ impl LapceSplitNew {
                 LapceUICommand::EnsureCursorVisible(None),
                 Target::Widget(self.children_ids[new_index]),
             ));
-        }
-    }
-
-    pub fn split_terminal(
+        } else if let Some(parent_id) = self.parent_id {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SplitEditorMove(*direction, self.split_id),
+                Target::Widget(parent_id),
+            ));
+        } else {
+            // the move can't be done inside this split, so the tab moves the
+            // focus to the adjacent area instead
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SplitMoveOut(*direction),
+                Target::Widget(data.id),
+            ));
+        }
+    }
+
+    /// The drop target at the position, which is the half of the child under
+    /// it, or none if dropping there wouldn't move the dragged editor.
+    fn drop_target_at(&self, pos: Point) -> Option<(usize, bool)> {
+        let drag = self.drag?;
+        let from = self.children_ids.iter().position(|id| id == &drag)?;
+        let index = self
+            .children
+            .iter()
+            .position(|child| child.layout_rect.contains(pos))?;
+        let rect = self.children[index].layout_rect;
+        let after = match self.direction {
+            SplitDirection::Vertical => pos.x > rect.center().x,
+            SplitDirection::Horizontal => pos.y > rect.center().y,
+        };
+        let to = index + after as usize;
+        if to == from || to == from + 1 {
+            return None;
+        }
+        Some((index, after))
+    }
+
+    fn drop_target_rect(&self) -> Option<Rect> {
+        let (index, after) = self.drop_target?;
+        let rect = self.children.get(index)?.layout_rect;
+        let center = rect.center();
+        Some(match (self.direction, after) {
+            (SplitDirection::Vertical, false) => {
+                rect.with_size(Size::new(center.x - rect.x0, rect.height()))
+            }
+            (SplitDirection::Vertical, true) => {
+                Rect::new(center.x, rect.y0, rect.x1, rect.y1)
+            }
+            (SplitDirection::Horizontal, false) => {
+                rect.with_size(Size::new(rect.width(), center.y - rect.y0))
+            }
+            (SplitDirection::Horizontal, true) => {
+                Rect::new(rect.x0, center.y, rect.x1, rect.y1)
+            }
+        })
+    }
+
+    fn split_editor_drag_end(&mut self, ctx: &mut EventCtx) {
+        self.drag = None;
+        self.drop_target = None;
+        ctx.set_active(false);
+        ctx.clear_cursor();
+        ctx.request_paint();
+    }
+
+    /// Moves the dragged editor to the drop target, if there's one.
+    pub fn split_editor_drop(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+    ) {
+        let drag = self.drag;
+        let target = self.drop_target;
+        self.split_editor_drag_end(ctx);
+        let (drag, (index, after)) = match (drag, target) {
+            (Some(drag), Some(target)) => (drag, target),
+            _ => return,
+        };
+        let from = match self.children_ids.iter().position(|id| id == &drag) {
+            Some(from) => from,
+            None => return,
+        };
+
+        let mut to = index + after as usize;
+        if from < to {
+            to -= 1;
+        }
+        let child = self.children.remove(from);
+        let child_id = self.children_ids.remove(from);
+        self.children.insert(to, child);
+        self.children_ids.insert(to, child_id);
+
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(drag),
+        ));
+        self.update_editors_order(data);
+        ctx.request_layout();
+    }
+
+    /// Takes the editor out of this split and hands its widget over to the
+    /// other split, which keeps its data, scroll offset and focus.
+    pub fn split_editor_transfer(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        view_id: WidgetId,
+        to_split: WidgetId,
+    ) {
+        let index = match self.children_ids.iter().position(|id| id == &view_id) {
+            Some(index) => index,
+            None => return,
+        };
+        let editor = match data.main_split.editors.get_mut(&view_id) {
+            Some(editor) => Arc::make_mut(editor),
+            None => return,
+        };
+        editor.split_id = Some(to_split);
+        if self.split_id == *data.main_split.split_id {
+            editor.main_split_index = Some(index);
+        }
+
+        let child = self.children.remove(index);
+        self.children_ids.remove(index);
+        self.normalize_flex_children();
+        self.update_editors_order(data);
+        if to_split == data.editors_panel.split_id {
+            data.update_editors_panel(true);
+        } else if self.split_id == data.editors_panel.split_id
+            && self.children.is_empty()
+        {
+            data.update_editors_panel(false);
+        }
+        ctx.children_changed();
+        ctx.submit_command(Command::new(
+            SPLIT_EDITOR_TRANSFER,
+            SingleUse::new(child),
+            Target::Widget(to_split),
+        ));
+    }
+
+    /// Adds the editor handed over by another split. An editor coming back
+    /// to the main split goes where it was, if that index is still valid.
+    fn split_editor_receive(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        child: ChildWidgetNew,
+    ) {
+        let view_id = child.widget.id();
+        let mut index = self.children.len();
+        if self.split_id == *data.main_split.split_id {
+            if let Some(editor) = data.main_split.editors.get_mut(&view_id) {
+                let editor = Arc::make_mut(editor);
+                if let Some(prior) = editor.main_split_index.take() {
+                    if prior <= self.children.len() {
+                        index = prior;
+                    }
+                }
+            }
+        }
+
+        let mut params = self.flex_params();
+        let at = self.children[..index].iter().filter(|c| c.flex).count();
+        insert_params(&mut params, at, None);
+        self.children.insert(index, child);
+        self.children_ids.insert(index, view_id);
+        self.set_flex_params(&params);
+        self.update_editors_order(data);
+        ctx.children_changed();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(view_id),
+        ));
+    }
+
+    /// Splits the terminal once the proxy told where its shell is, so that
+    /// the new one starts in the same directory. Without it, the new one
+    /// starts in the workspace.
+    fn request_split_terminal(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        vertical: bool,
+        widget_id: WidgetId,
+    ) {
+        let term_id = data
+            .terminal
+            .terminals
+            .values()
+            .find(|t| t.widget_id == widget_id)
+            .map(|t| t.term_id);
+        let term_id = match term_id {
+            Some(term_id) if data.proxy.has_capability(CAPABILITY_TERMINAL_CWD) => {
+                term_id
+            }
+            _ => {
+                self.split_terminal(ctx, data, vertical, widget_id, None, None);
+                return;
+            }
+        };
+        let event_sink = ctx.get_external_handle();
+        let split_id = self.split_id;
+        data.proxy.terminal_cwd(
+            term_id,
+            Box::new(move |result| {
+                let cwd = result
+                    .ok()
+                    .and_then(|cwd| {
+                        serde_json::from_value::<Option<PathBuf>>(cwd).ok()
+                    })
+                    .flatten();
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitTerminalIn(vertical, widget_id, cwd),
+                    Target::Widget(split_id),
+                );
+            }),
+        );
+    }
+
+    /// Splits the terminal with a new one after it, running the task if
+    /// there's one.
+    pub fn split_terminal(
         &mut self,
         ctx: &mut EventCtx,
         data: &mut LapceTabData,
         vertical: bool,
         widget_id: WidgetId,
+        cwd: Option<PathBuf>,
+        task: Option<Arc<TerminalTask>>,
     ) {
         let mut index = 0;
         for (i, child_id) in self.children_ids.iter().enumerate() {
@@ -338,25 +1251,197 @@ impl LapceSplitNew {
             }
         }
 
-        let terminal_data = Arc::new(LapceTerminalData::new(
+        let direction = if vertical {
+            SplitDirection::Vertical
+        } else {
+            SplitDirection::Horizontal
+        };
+        if direction != self.direction && self.children.len() > 1 {
+            let size = self.children[index].layout_rect.size();
+            if !self.has_room(data, size, direction) {
+                no_room_for_terminal(ctx, data, task);
+                return;
+            }
+            self.split_terminal_nested(ctx, data, direction, index, cwd, task);
+            return;
+        }
+        if !self.has_room(data, ctx.size(), direction) {
+            no_room_for_terminal(ctx, data, task);
+            return;
+        }
+        self.direction = direction;
+
+        let mut terminal_data = LapceTerminalData::with_task(
             data.workspace.clone(),
+            cwd,
+            task,
             self.split_id,
             ctx.get_external_handle(),
             data.proxy.clone(),
-        ));
+        );
+        // the split may be nested in the split of the tab
+        if let Some(terminal) = data
+            .terminal
+            .terminals
+            .values()
+            .find(|t| t.split_id == self.split_id)
+        {
+            terminal_data.tab_id = terminal.tab_id;
+        }
+        let terminal_data = Arc::new(terminal_data);
         let terminal = LapceTerminalView::new(&terminal_data);
         Arc::make_mut(&mut data.terminal)
             .terminals
             .insert(terminal_data.term_id, terminal_data.clone());
 
-        self.insert_flex_child(
+        self.insert_flex_child_sharing(
             index + 1,
             terminal.boxed(),
             Some(terminal_data.widget_id),
+            Some(index),
+        );
+        self.update_nested_split(data);
+        ctx.children_changed();
+        if terminal_data.task.is_some() {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(terminal_data.widget_id),
+            ));
+        }
+    }
+
+    /// Starts the task in the split of a panel tab: beside the terminal last
+    /// focused in the tab, or as its only terminal if it has none yet.
+    fn init_terminal_task(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        task: Arc<TerminalTask>,
+    ) {
+        // the terminal can be in a nested split, which does the splitting
+        let beside = data
+            .terminal
+            .tab_index(self.split_id)
+            .and_then(|index| data.terminal.tab_terminal(index))
+            .map(|t| (t.widget_id, t.split_id));
+        if let Some((widget_id, split_id)) = beside {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SplitTerminalTask(widget_id, task),
+                Target::Widget(split_id),
+            ));
+            return;
+        }
+        if !self.children.is_empty() {
+            return;
+        }
+        let terminal_data = Arc::new(LapceTerminalData::with_task(
+            data.workspace.clone(),
+            None,
+            Some(task),
+            self.split_id,
+            ctx.get_external_handle(),
+            data.proxy.clone(),
+        ));
+        let terminal = LapceTerminalView::new(&terminal_data);
+        self.insert_flex_child(
+            0,
+            terminal.boxed(),
+            Some(terminal_data.widget_id),
             1.0,
         );
-        self.even_flex_children();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(terminal_data.widget_id),
+        ));
+        let terminal_panel = Arc::make_mut(&mut data.terminal);
+        terminal_panel
+            .terminals
+            .insert(terminal_data.term_id, terminal_data.clone());
+        terminal_panel
+            .set_active_terminal(terminal_data.term_id, terminal_data.widget_id);
+        ctx.children_changed();
+    }
+
+    /// Replaces the terminal at `index` with a nested split in `direction`,
+    /// holding the terminal and a new one after it.
+    fn split_terminal_nested(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        direction: SplitDirection,
+        index: usize,
+        cwd: Option<PathBuf>,
+        task: Option<Arc<TerminalTask>>,
+    ) {
+        let widget_id = self.children_ids[index];
+        let terminal_data = match data
+            .terminal
+            .terminals
+            .values()
+            .find(|t| t.widget_id == widget_id)
+        {
+            Some(terminal_data) => terminal_data.clone(),
+            None => return,
+        };
+
+        let split_id = WidgetId::next();
+        let mut split = LapceSplitNew::new(split_id).direction(direction);
+        split.parent_id = Some(self.split_id);
+
+        let mut terminal_data = (*terminal_data).clone();
+        terminal_data.split_id = split_id;
+        let terminal_data = Arc::new(terminal_data);
+        let mut new_terminal_data = LapceTerminalData::with_task(
+            data.workspace.clone(),
+            cwd,
+            task,
+            split_id,
+            ctx.get_external_handle(),
+            data.proxy.clone(),
+        );
+        new_terminal_data.tab_id = terminal_data.tab_id;
+        let new_terminal_data = Arc::new(new_terminal_data);
+        let split = split
+            .with_flex_child(
+                LapceTerminalView::new(&terminal_data).boxed(),
+                Some(terminal_data.widget_id),
+                1.0,
+            )
+            .with_flex_child(
+                LapceTerminalView::new(&new_terminal_data).boxed(),
+                Some(new_terminal_data.widget_id),
+                1.0,
+            );
+        split.update_nested_split(data);
+        let terminal_panel = Arc::make_mut(&mut data.terminal);
+        terminal_panel
+            .terminals
+            .insert(terminal_data.term_id, terminal_data.clone());
+        terminal_panel
+            .terminals
+            .insert(new_terminal_data.term_id, new_terminal_data.clone());
+
+        let params = self.children[index].params;
+        self.children.remove(index);
+        self.children_ids.remove(index);
+        self.insert_flex_child(index, split.boxed(), Some(split_id), params);
+        self.update_nested_split(data);
         ctx.children_changed();
+        // the terminal's view was rebuilt inside the nested split, and the
+        // output of a task is what's looked at next
+        let focus = if new_terminal_data.task.is_some() {
+            &new_terminal_data
+        } else {
+            &terminal_data
+        };
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(focus.widget_id),
+        ));
     }
 
     pub fn split_terminal_close(
@@ -375,8 +1460,24 @@ impl LapceSplitNew {
             self.children.remove(0);
             self.children_ids.remove(0);
 
-            self.even_flex_children();
             ctx.children_changed();
+            if let Some(parent_id) = self.parent_id {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitTerminalCollapse(self.split_id),
+                    Target::Widget(parent_id),
+                ));
+                return;
+            }
+            // the tab goes with its last terminal, unless it's the only one
+            if data.terminal.tabs.len() > 1 {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CloseTerminalTab(self.split_id),
+                    Target::Widget(data.terminal.widget_id),
+                ));
+                return;
+            }
             for (pos, panel) in data.panels.iter_mut() {
                 if panel.active == PanelKind::Terminal {
                     Arc::make_mut(panel).shown = false;
@@ -407,18 +1508,114 @@ impl LapceSplitNew {
         };
         let terminal_id = self.children_ids[index];
         let new_terminal_id = self.children_ids[new_index];
-        ctx.submit_command(Command::new(
-            LAPCE_UI_COMMAND,
-            LapceUICommand::Focus,
-            Target::Widget(new_terminal_id),
-        ));
+        // the focus moves on only from the active terminal: a task's
+        // terminal made another one active before it closes
+        if data.terminal.active_term_id == term_id {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(new_terminal_id),
+            ));
+        }
 
         Arc::make_mut(&mut data.terminal).terminals.remove(&term_id);
         self.children.remove(index);
         self.children_ids.remove(index);
 
-        self.even_flex_children();
+        self.normalize_flex_children();
+        self.update_nested_split(data);
+        ctx.children_changed();
+        self.collapse_into_parent(ctx, data);
+    }
+
+    /// A nested split left with a single terminal is replaced by that
+    /// terminal in the parent split.
+    fn collapse_into_parent(&self, ctx: &mut EventCtx, data: &LapceTabData) {
+        if let Some(parent_id) = self.parent_id {
+            if self.children.len() == 1
+                && data
+                    .terminal
+                    .terminals
+                    .values()
+                    .any(|t| t.widget_id == self.children_ids[0])
+            {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitTerminalCollapse(self.split_id),
+                    Target::Widget(parent_id),
+                ));
+            }
+        }
+    }
+
+    /// Replaces the nested split `split_id` with the only terminal left in
+    /// it, or removes it if it has none left.
+    pub fn split_terminal_collapse(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        split_id: WidgetId,
+    ) {
+        let index = match self.children_ids.iter().position(|id| id == &split_id) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let terminal_data = data
+            .terminal
+            .terminals
+            .values()
+            .find(|t| t.split_id == split_id)
+            .cloned();
+        let params = self.children[index].params;
+        self.children.remove(index);
+        self.children_ids.remove(index);
+        Arc::make_mut(&mut data.terminal)
+            .nested_splits
+            .remove(&split_id);
+        match terminal_data {
+            Some(terminal_data) => {
+                let mut terminal_data = (*terminal_data).clone();
+                terminal_data.split_id = self.split_id;
+                let terminal_data = Arc::new(terminal_data);
+                Arc::make_mut(&mut data.terminal)
+                    .terminals
+                    .insert(terminal_data.term_id, terminal_data.clone());
+                self.insert_flex_child(
+                    index,
+                    LapceTerminalView::new(&terminal_data).boxed(),
+                    Some(terminal_data.widget_id),
+                    params,
+                );
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(terminal_data.widget_id),
+                ));
+            }
+            None if self.children.len() > 0 => {
+                self.normalize_flex_children();
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(
+                        self.children_ids[index.min(self.children.len() - 1)],
+                    ),
+                ));
+            }
+            None => {
+                if let Some(parent_id) = self.parent_id {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SplitTerminalCollapse(self.split_id),
+                        Target::Widget(parent_id),
+                    ));
+                }
+            }
+        }
+        self.update_nested_split(data);
         ctx.children_changed();
+        self.collapse_into_parent(ctx, data);
     }
 
     pub fn split_add_editor(
@@ -427,12 +1624,19 @@ impl LapceSplitNew {
         data: &mut LapceTabData,
         widget_id: WidgetId,
     ) {
-        let editor_data = data.main_split.editors.get(&widget_id).unwrap();
+        let editor_data = match data.main_split.editors.get(&widget_id) {
+            Some(editor_data) => editor_data,
+            None => return,
+        };
         let editor = LapceEditorView::new(&editor_data);
-        self.insert_flex_child(0, editor.boxed(), Some(editor_data.view_id), 1.0);
-        self.even_flex_children();
+        self.insert_flex_child_sharing(
+            0,
+            editor.boxed(),
+            Some(editor_data.view_id),
+            None,
+        );
         ctx.children_changed();
-        data.main_split.editors_order = Arc::new(self.children_ids.clone());
+        self.update_editors_order(data);
 
         ctx.submit_command(Command::new(
             LAPCE_UI_COMMAND,
@@ -441,9 +1645,9 @@ impl LapceSplitNew {
         ));
     }
 
-    pub fn split_editor(
+    pub fn split_editor<C: CommandCtx>(
         &mut self,
-        ctx: &mut EventCtx,
+        ctx: &mut C,
         data: &mut LapceTabData,
         vertical: bool,
         widget_id: WidgetId,
@@ -455,17 +1659,28 @@ impl LapceSplitNew {
                 break;
             }
         }
+        if !self.has_room(data, ctx.size(), self.direction) {
+            show_no_room();
+            return;
+        }
 
         let view_id = self.children[index].widget.id();
         let from_editor = data.main_split.editors.get(&view_id).unwrap();
-        let mut editor_data = LapceEditorData::new(
-            None,
-            Some(self.split_id),
-            from_editor.content.clone(),
-            &data.config,
-        );
-        editor_data.cursor = from_editor.cursor.clone();
+        // the split of a narrowed editor shows the whole file
+        let (content, cursor) = match from_editor.narrow.as_ref() {
+            Some(narrow) => (
+                BufferContent::File(narrow.path.clone()),
+                from_editor
+                    .cursor
+                    .map_offsets(|offset| narrow.to_file(offset)),
+            ),
+            None => (from_editor.content.clone(), from_editor.cursor.clone()),
+        };
+        let mut editor_data =
+            LapceEditorData::new(None, Some(self.split_id), content, &data.config);
+        editor_data.cursor = cursor;
         editor_data.locations = from_editor.locations.clone();
+        editor_data.zoom = from_editor.zoom;
         ctx.submit_command(Command::new(
             LAPCE_UI_COMMAND,
             LapceUICommand::ForceScrollTo(
@@ -476,70 +1691,164 @@ impl LapceSplitNew {
         ));
 
         let editor = LapceEditorView::new(&editor_data);
-        self.insert_flex_child(
+        self.insert_flex_child_sharing(
             index + 1,
             editor.boxed(),
             Some(editor_data.view_id),
-            1.0,
+            Some(index),
         );
-        self.even_flex_children();
         ctx.children_changed();
         data.main_split
             .editors
             .insert(editor_data.view_id, Arc::new(editor_data));
-        data.main_split.editors_order = Arc::new(self.children_ids.clone());
-    }
-}
-
-impl Widget<LapceTabData> for LapceSplitNew {
-    fn id(&self) -> Option<WidgetId> {
-        Some(self.split_id)
+        self.update_editors_order(data);
     }
 
-    fn event(
+    /// Runs the commands that change the editors of the split, as its
+    /// `event` does. Returns false for the other commands.
+    pub fn run_editor_command<C: CommandCtx>(
         &mut self,
-        ctx: &mut EventCtx,
-        event: &Event,
+        ctx: &mut C,
         data: &mut LapceTabData,
-        env: &Env,
-    ) {
-        for child in self.children.iter_mut() {
-            child.widget.event(ctx, event, data, env);
-        }
-        match event {
+        command: &LapceUICommand,
+    ) -> bool {
+        match command {
+            LapceUICommand::SplitEditor(vertical, widget_id) => {
+                self.split_editor(ctx, data, *vertical, *widget_id);
+            }
+            LapceUICommand::SplitEditorEmpty(widget_id) => {
+                self.split_editor_empty(ctx, data, *widget_id);
+            }
+            LapceUICommand::SplitEditorMove(direction, widget_id) => {
+                self.split_editor_move(ctx, data, direction, *widget_id);
+            }
+            LapceUICommand::SplitEditorExchange(widget_id) => {
+                self.split_editor_exchange(ctx, data, *widget_id);
+            }
+            LapceUICommand::SplitEditorClose(widget_id) => {
+                self.split_editor_close(ctx, data, CloseRequest::Editor(*widget_id));
+            }
+            LapceUICommand::SplitEditorCloseOthers(widget_id) => {
+                self.split_editor_close(ctx, data, CloseRequest::Others(*widget_id));
+            }
+            LapceUICommand::SplitEditorCloseAll => {
+                self.split_editor_close(ctx, data, CloseRequest::All);
+            }
+            LapceUICommand::SplitEvenSize => {
+                self.even_flex_children();
+                self.update_editors_order(data);
+                self.update_nested_split(data);
+                ctx.request_layout();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn split_editor_empty<C: CommandCtx>(
+        &mut self,
+        ctx: &mut C,
+        data: &mut LapceTabData,
+        widget_id: WidgetId,
+    ) {
+        let index = self
+            .children_ids
+            .iter()
+            .position(|id| id == &widget_id)
+            .unwrap_or(0);
+        if !self.has_room(data, ctx.size(), self.direction) {
+            show_no_room();
+            return;
+        }
+
+        let editor_data = LapceEditorData::new(
+            None,
+            Some(self.split_id),
+            BufferContent::Local(LocalBufferKind::Empty),
+            &data.config,
+        );
+        let editor = LapceEditorView::new(&editor_data);
+        self.insert_flex_child_sharing(
+            (index + 1).min(self.children.len()),
+            editor.boxed(),
+            Some(editor_data.view_id),
+            Some(index),
+        );
+        ctx.children_changed();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(editor_data.view_id),
+        ));
+        data.main_split
+            .editors
+            .insert(editor_data.view_id, Arc::new(editor_data));
+        self.update_editors_order(data);
+    }
+}
+
+impl Widget<LapceTabData> for LapceSplitNew {
+    fn id(&self) -> Option<WidgetId> {
+        Some(self.split_id)
+    }
+
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        if self.drag.is_some() {
+            // the split owns the mouse until the dragged editor is dropped
+            match event {
+                Event::MouseMove(mouse_event) => {
+                    let drop_target = self.drop_target_at(mouse_event.pos);
+                    if drop_target != self.drop_target {
+                        self.drop_target = drop_target;
+                        ctx.request_paint();
+                    }
+                    ctx.set_cursor(&Cursor::OpenHand);
+                    ctx.set_handled();
+                    return;
+                }
+                Event::MouseUp(_) => {
+                    self.split_editor_drop(ctx, data);
+                    ctx.set_handled();
+                    return;
+                }
+                Event::KeyDown(key_event) if key_event.key == KbKey::Escape => {
+                    self.split_editor_drag_end(ctx);
+                    ctx.set_handled();
+                    return;
+                }
+                _ => (),
+            }
+        }
+        for child in self.children.iter_mut() {
+            child.widget.event(ctx, event, data, env);
+        }
+        match event {
             Event::MouseMove(mouse_event) => {
                 if self.children.len() == 0 {
-                    let mut on_command = false;
-                    for (_, _, rect, _) in &self.commands {
-                        if rect.contains(mouse_event.pos) {
-                            on_command = true;
-                            break;
-                        }
-                    }
-                    if on_command {
-                        ctx.set_cursor(&druid::Cursor::Pointer);
-                    } else {
-                        ctx.clear_cursor();
-                    }
+                    self.hints.mouse_move(ctx, mouse_event.pos);
                 }
             }
             Event::MouseDown(mouse_event) => {
                 if self.children.len() == 0 {
-                    for (cmd, _, rect, _) in &self.commands {
-                        if rect.contains(mouse_event.pos) {
-                            ctx.submit_command(Command::new(
-                                LAPCE_NEW_COMMAND,
-                                cmd.clone(),
-                                Target::Auto,
-                            ));
-                            return;
-                        }
+                    // focused, the empty split takes the keyboard shortcuts
+                    ctx.request_focus();
+                    data.focus = self.split_id;
+                    data.focus_area = self.focus_area(data);
+                    if self.hints.mouse_down(ctx, mouse_event) {
+                        return;
                     }
                 }
             }
             Event::KeyDown(key_event) => {
-                if self.children.len() == 0 {
-                    ctx.set_handled();
+                if self.children.len() == 0
+                    && !self.empty_key_down(ctx, data, key_event)
+                {
                     let mut keypress = data.keypress.clone();
                     Arc::make_mut(&mut keypress).key_down(
                         ctx,
@@ -551,38 +1860,99 @@ impl Widget<LapceTabData> for LapceSplitNew {
             }
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
+                if self.run_editor_command(ctx, data, command) {
+                    return;
+                }
                 match command {
                     LapceUICommand::Focus => {
+                        if self.parent_id.is_some() {
+                            if let Some(child_id) = self.children_ids.first() {
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::Focus,
+                                    Target::Widget(*child_id),
+                                ));
+                            }
+                            return;
+                        }
                         ctx.request_focus();
                         data.focus = self.split_id;
-                        data.focus_area = FocusArea::Editor;
+                        data.focus_area = self.focus_area(data);
                     }
                     LapceUICommand::SplitAddEditor(widget_id) => {
                         self.split_add_editor(ctx, data, *widget_id);
                     }
-                    LapceUICommand::SplitEditor(vertical, widget_id) => {
-                        self.split_editor(ctx, data, *vertical, *widget_id);
+                    LapceUICommand::SplitEditorConfirmClose(request, choice) => {
+                        self.split_editor_confirm_close(
+                            ctx, data, *request, *choice,
+                        );
+                    }
+                    LapceUICommand::SplitEditorReopen(closed) => {
+                        self.split_editor_reopen(ctx, data, closed);
                     }
-                    LapceUICommand::SplitEditorMove(direction, widget_id) => {
-                        self.split_editor_move(ctx, data, direction, *widget_id);
+                    LapceUICommand::SplitEditorTo(widget_id, location, preview) => {
+                        self.split_editor_to(
+                            ctx, data, *widget_id, location, *preview,
+                        );
                     }
-                    LapceUICommand::SplitEditorExchange(widget_id) => {
-                        self.split_editor_exchange(ctx, data, *widget_id);
+                    LapceUICommand::SplitEditorDragStart(widget_id) => {
+                        if self.children.len() > 1
+                            && self.children_ids.contains(widget_id)
+                        {
+                            self.drag = Some(*widget_id);
+                            self.drop_target = None;
+                            ctx.set_active(true);
+                        }
                     }
-                    LapceUICommand::SplitEditorClose(widget_id) => {
-                        self.split_editor_close(ctx, data, *widget_id);
+                    LapceUICommand::SplitEditorTransfer(view_id, to_split) => {
+                        self.split_editor_transfer(ctx, data, *view_id, *to_split);
                     }
                     LapceUICommand::SplitTerminal(vertical, widget_id) => {
-                        self.split_terminal(ctx, data, *vertical, *widget_id);
+                        self.request_split_terminal(
+                            ctx, data, *vertical, *widget_id,
+                        );
+                    }
+                    LapceUICommand::SplitTerminalIn(vertical, widget_id, cwd) => {
+                        // the terminal may have closed in the meantime
+                        if self.children_ids.contains(widget_id) {
+                            self.split_terminal(
+                                ctx,
+                                data,
+                                *vertical,
+                                *widget_id,
+                                cwd.clone(),
+                                None,
+                            );
+                        }
+                    }
+                    LapceUICommand::SplitTerminalTask(widget_id, task) => {
+                        if self.children_ids.contains(widget_id) {
+                            self.split_terminal(
+                                ctx,
+                                data,
+                                true,
+                                *widget_id,
+                                None,
+                                Some(task.clone()),
+                            );
+                        }
+                    }
+                    LapceUICommand::InitTerminalTask(task) => {
+                        self.init_terminal_task(ctx, data, task.clone());
                     }
                     LapceUICommand::SplitTerminalClose(term_id, widget_id) => {
                         self.split_terminal_close(ctx, data, *term_id, *widget_id);
                     }
-                    LapceUICommand::InitTerminalPanel(focus) => {
-                        if data.terminal.terminals.len() == 0 {
+                    LapceUICommand::SplitTerminalCollapse(split_id) => {
+                        self.split_terminal_collapse(ctx, data, *split_id);
+                    }
+                    LapceUICommand::InitTerminalPanel(focus, cwd) => {
+                        let split_id = self.split_id;
+                        if self.children.is_empty() {
                             let terminal_data = Arc::new(LapceTerminalData::new(
                                 data.workspace.clone(),
-                                data.terminal.split_id,
+                                cwd.clone(),
+                                split_id,
                                 ctx.get_external_handle(),
                                 data.proxy.clone(),
                             ));
@@ -601,17 +1971,56 @@ impl Widget<LapceTabData> for LapceSplitNew {
                                 ));
                             }
                             let terminal_panel = Arc::make_mut(&mut data.terminal);
-                            terminal_panel.active = terminal_data.widget_id;
-                            terminal_panel.active_term_id = terminal_data.term_id;
-                            terminal_panel
-                                .terminals
-                                .insert(terminal_data.term_id, terminal_data);
+                            terminal_panel.terminals.insert(
+                                terminal_data.term_id,
+                                terminal_data.clone(),
+                            );
+                            terminal_panel.set_active_terminal(
+                                terminal_data.term_id,
+                                terminal_data.widget_id,
+                            );
                             ctx.children_changed();
+                        } else if !data
+                            .terminal
+                            .terminals
+                            .get(&data.terminal.active_term_id)
+                            .map(|t| t.tab_id == split_id)
+                            .unwrap_or(false)
+                        {
+                            // the terminals can be in nested splits, so the
+                            // active one is picked from the data instead of
+                            // the children of this split
+                            let terminal_data = match data
+                                .terminal
+                                .terminals
+                                .values()
+                                .find(|t| t.tab_id == split_id)
+                            {
+                                Some(terminal_data) => terminal_data.clone(),
+                                None => return,
+                            };
+                            if *focus {
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::Focus,
+                                    Target::Widget(terminal_data.widget_id),
+                                ));
+                            }
+                            Arc::make_mut(&mut data.terminal).set_active_terminal(
+                                terminal_data.term_id,
+                                terminal_data.widget_id,
+                            );
                         }
                     }
                     _ => (),
                 }
             }
+            Event::Command(cmd) if cmd.is(SPLIT_EDITOR_TRANSFER) => {
+                if let Some(child) = cmd.get_unchecked(SPLIT_EDITOR_TRANSFER).take()
+                {
+                    self.split_editor_receive(ctx, data, child);
+                }
+            }
             _ => (),
         }
     }
@@ -635,6 +2044,7 @@ impl Widget<LapceTabData> for LapceSplitNew {
         data: &LapceTabData,
         env: &Env,
     ) {
+        self.hints.update(ctx, old_data, data);
         for child in self.children.iter_mut() {
             child.widget.update(ctx, data, env);
         }
@@ -651,64 +2061,8 @@ impl Widget<LapceTabData> for LapceSplitNew {
 
         let children_len = self.children.len();
         if children_len == 0 {
-            let origin =
-                Point::new(my_size.width / 2.0, my_size.height / 2.0 + 40.0);
-            let line_height = data.config.editor.line_height as f64;
-
-            self.commands = empty_editor_commands(
-                data.config.lapce.modal,
-                data.workspace.path.is_some(),
-            )
-            .iter()
-            .enumerate()
-            .map(|(i, cmd)| {
-                let text_layout = ctx
-                    .text()
-                    .new_text_layout(cmd.palette_desc.as_ref().unwrap().to_string())
-                    .font(FontFamily::SYSTEM_UI, 14.0)
-                    .text_color(
-                        data.config
-                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
-                            .clone(),
-                    )
-                    .build()
-                    .unwrap();
-                let point =
-                    origin - (text_layout.size().width, -line_height * i as f64);
-                let rect = text_layout.size().to_rect().with_origin(point);
-                let mut key = None;
-                for (_, keymaps) in data.keypress.keymaps.iter() {
-                    for keymap in keymaps {
-                        if keymap.command == cmd.cmd {
-                            let mut keymap_str = "".to_string();
-                            for keypress in &keymap.key {
-                                if keymap_str != "" {
-                                    keymap_str += " "
-                                }
-                                keymap_str += &keybinding_to_string(keypress);
-                            }
-                            key = Some(keymap_str);
-                            break;
-                        }
-                    }
-                    if key.is_some() {
-                        break;
-                    }
-                }
-                let key_text_layout = ctx
-                    .text()
-                    .new_text_layout(key.unwrap_or("Unbound".to_string()))
-                    .font(FontFamily::SYSTEM_UI, 14.0)
-                    .text_color(
-                        data.config
-                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
-                            .clone(),
-                    )
-                    .build()
-                    .unwrap();
-                (cmd.clone(), text_layout, rect, key_text_layout)
-            })
-            .collect();
+            let terminal = self.is_terminal_tab(data);
+            self.hints.layout(ctx, my_size, data, terminal);
             return my_size;
         }
 
@@ -747,18 +2101,20 @@ impl Widget<LapceTabData> for LapceSplitNew {
             };
         }
 
-        let mut flex_sum = 0.0;
-        for child in &self.children {
-            if child.flex {
-                flex_sum += child.params;
-            }
-        }
-
+        let flex_params: Vec<f64> = self
+            .children
+            .iter()
+            .filter(|c| c.flex)
+            .map(|c| c.params)
+            .collect();
         let flex_total = if self.direction == SplitDirection::Vertical {
             my_size.width
         } else {
             my_size.height
         } - non_flex_total;
+        let mut flex_sizes =
+            flex_sizes(flex_total, &flex_params, self.min_child_size(data))
+                .into_iter();
 
         let mut x = 0.0;
         let mut y = 0.0;
@@ -767,7 +2123,7 @@ impl Widget<LapceTabData> for LapceSplitNew {
                 child.widget.set_origin(ctx, data, env, Point::new(x, y));
                 child.layout_rect = child.layout_rect.with_origin(Point::new(x, y));
             } else {
-                let flex = flex_total / flex_sum * child.params;
+                let flex = flex_sizes.next().unwrap_or(0.0);
                 let (width, height) = match self.direction {
                     SplitDirection::Vertical => (flex, my_size.height),
                     SplitDirection::Horizontal => (my_size.width, flex),
@@ -811,45 +2167,7 @@ impl Widget<LapceTabData> for LapceSplitNew {
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
         if self.children.len() == 0 {
-            let rect = ctx.size().to_rect();
-            ctx.fill(
-                rect,
-                data.config
-                    .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
-            );
-            ctx.with_save(|ctx| {
-                ctx.clip(rect);
-                let svg = logo_svg();
-                let size = ctx.size();
-                let svg_size = 100.0;
-                let rect = Size::ZERO
-                    .to_rect()
-                    .with_origin(
-                        Point::new(size.width / 2.0, size.height / 2.0)
-                            + (0.0, -svg_size),
-                    )
-                    .inflate(svg_size, svg_size);
-                ctx.draw_svg(
-                    &svg,
-                    rect,
-                    Some(
-                        &data
-                            .config
-                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
-                            .clone()
-                            .with_alpha(0.5),
-                    ),
-                );
-
-                for (cmd, text, rect, keymap) in &self.commands {
-                    ctx.draw_text(text, rect.origin());
-                    ctx.draw_text(
-                        keymap,
-                        rect.origin() + (20.0 + rect.width(), 0.0),
-                    );
-                }
-            });
-
+            self.hints.paint(ctx, data);
             return;
         }
         for child in self.children.iter_mut() {
@@ -858,88 +2176,609 @@ impl Widget<LapceTabData> for LapceSplitNew {
         if self.show_border {
             self.paint_bar(ctx, &data.config);
         }
+        if let Some(rect) = self.drop_target_rect() {
+            let color = data
+                .config
+                .get_color_unchecked(LapceTheme::EDITOR_CARET)
+                .clone();
+            ctx.fill(rect, &color.clone().with_alpha(0.2));
+            ctx.stroke(rect.inset(-1.0), &color, 2.0);
+        }
     }
 }
 
-fn empty_editor_commands(modal: bool, has_workspace: bool) -> Vec<LapceCommandNew> {
-    if !has_workspace {
-        vec![
-            LapceCommandNew {
-                cmd: LapceWorkbenchCommand::PaletteCommand.to_string(),
-                data: None,
-                palette_desc: Some("Show All Commands".to_string()),
-                target: CommandTarget::Workbench,
-            },
-            if modal {
-                LapceCommandNew {
-                    cmd: LapceWorkbenchCommand::DisableModal.to_string(),
-                    data: None,
-                    palette_desc: LapceWorkbenchCommand::DisableModal
-                        .get_message()
-                        .map(|m| m.to_string()),
-                    target: CommandTarget::Workbench,
-                }
+/// A command hint: its description, with its keybinding to the right, and
+/// the row they're on, which is clickable as a whole.
+struct EmptyEditorHint {
+    cmd: LapceCommandNew,
+    text: PietTextLayout,
+    origin: Point,
+    keymap: PietTextLayout,
+    rect: Rect,
+}
+
+/// The logo and the clickable command hints shown where there's no editor
+/// content yet, like an empty split.
+pub struct EmptyEditorHints {
+    commands: Vec<EmptyEditorHint>,
+    size: Size,
+    has_workspace: bool,
+    /// Whether the split is a tab of the terminal panel, whose hints are
+    /// about terminals.
+    terminal: bool,
+    /// The hint under the mouse.
+    hovered: Option<usize>,
+}
+
+impl EmptyEditorHints {
+    pub fn new() -> Self {
+        Self {
+            commands: vec![],
+            size: Size::ZERO,
+            has_workspace: false,
+            terminal: false,
+            hovered: None,
+        }
+    }
+
+    /// Drops the cached hints when the keymaps, the config or the proxy
+    /// they're built from change, so that the next layout rebuilds them.
+    pub fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+    ) {
+        if !Arc::ptr_eq(&old_data.keypress.keymaps, &data.keypress.keymaps)
+            || old_data.config.lapce.modal != data.config.lapce.modal
+            || !Arc::ptr_eq(&old_data.config, &data.config)
+            || old_data.proxy_status != data.proxy_status
+        {
+            self.commands.clear();
+            ctx.request_layout();
+        }
+    }
+
+    pub fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        size: Size,
+        data: &LapceTabData,
+        terminal: bool,
+    ) {
+        if size == self.size
+            && terminal == self.terminal
+            && !self.commands.is_empty()
+        {
+            return;
+        }
+        self.size = size;
+        self.has_workspace = data.workspace.path.is_some();
+        self.terminal = terminal;
+        self.hovered = None;
+
+        let origin = if terminal {
+            Point::new(size.width / 2.0, size.height / 2.0)
+        } else {
+            Point::new(size.width / 2.0, size.height / 2.0 + 40.0)
+        };
+        let line_height = data.config.editor.line_height as f64;
+
+        self.commands = empty_editor_commands(
+            data.config.lapce.modal,
+            data.workspace.path.is_some(),
+            terminal,
+        )
+        .iter()
+        .filter(|cmd| {
+            // hide what an older remote proxy can't do
+            LapceWorkbenchCommand::from_str(&cmd.cmd)
+                .ok()
+                .and_then(|cmd| cmd.required_capability())
+                .map(|capability| data.proxy.has_capability(capability))
+                .unwrap_or(true)
+        })
+        .enumerate()
+        .map(|(i, cmd)| {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(cmd.palette_desc.as_ref().unwrap().to_string())
+                .font(FontFamily::SYSTEM_UI, 14.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let point = origin - (text_layout.size().width, -line_height * i as f64);
+            // the empty terminal panel creates one on enter
+            let mut key = if terminal {
+                Some("Enter".to_string())
             } else {
-                LapceCommandNew {
-                    cmd: LapceWorkbenchCommand::EnableModal.to_string(),
-                    data: None,
-                    palette_desc: LapceWorkbenchCommand::EnableModal
-                        .get_message()
-                        .map(|m| m.to_string()),
-                    target: CommandTarget::Workbench,
+                None
+            };
+            for (_, keymaps) in data.keypress.keymaps.iter() {
+                if key.is_some() {
+                    break;
                 }
-            },
-            LapceCommandNew {
-                cmd: LapceWorkbenchCommand::OpenFolder.to_string(),
-                data: None,
-                palette_desc: Some("Open Folder".to_string()),
-                target: CommandTarget::Workbench,
-            },
-            LapceCommandNew {
-                cmd: LapceWorkbenchCommand::PaletteWorkspace.to_string(),
-                data: None,
-                palette_desc: Some("Open Recent".to_string()),
-                target: CommandTarget::Workbench,
-            },
-        ]
-    } else {
-        vec![
-            LapceCommandNew {
-                cmd: LapceWorkbenchCommand::PaletteCommand.to_string(),
-                data: None,
-                palette_desc: Some("Show All Commands".to_string()),
-                target: CommandTarget::Workbench,
-            },
-            if modal {
+                for keymap in keymaps {
+                    if keymap.command == cmd.cmd {
+                        let mut keymap_str = "".to_string();
+                        for keypress in &keymap.key {
+                            if keymap_str != "" {
+                                keymap_str += " "
+                            }
+                            keymap_str += &keybinding_to_string(keypress);
+                        }
+                        key = Some(keymap_str);
+                        break;
+                    }
+                }
+            }
+            let key_text_layout = ctx
+                .text()
+                .new_text_layout(key.unwrap_or("Unbound".to_string()))
+                .font(FontFamily::SYSTEM_UI, 14.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            let rect = Rect::new(
+                point.x,
+                point.y,
+                point.x + text_size.width + 20.0 + key_text_layout.size().width,
+                point.y + text_size.height,
+            )
+            .inflate(8.0, (line_height - text_size.height).max(0.0) / 2.0);
+            EmptyEditorHint {
+                cmd: cmd.clone(),
+                text: text_layout,
+                origin: point,
+                keymap: key_text_layout,
+                rect,
+            }
+        })
+        .collect();
+    }
+
+    pub fn mouse_move(&mut self, ctx: &mut EventCtx, pos: Point) {
+        let hovered = self
+            .commands
+            .iter()
+            .position(|hint| hint.rect.contains(pos));
+        if hovered.is_some() {
+            ctx.set_cursor(&druid::Cursor::Pointer);
+        } else {
+            ctx.clear_cursor();
+        }
+        if hovered != self.hovered {
+            self.hovered = hovered;
+            ctx.request_paint();
+        }
+    }
+
+    /// Runs the command under the mouse, or opens the file palette on a
+    /// double click elsewhere when there's a workspace. Returns whether it
+    /// ran anything.
+    pub fn mouse_down(&self, ctx: &mut EventCtx, mouse_event: &MouseEvent) -> bool {
+        let cmd = match self
+            .commands
+            .iter()
+            .find(|hint| hint.rect.contains(mouse_event.pos))
+        {
+            Some(hint) => hint.cmd.clone(),
+            None if mouse_event.count == 2
+                && self.has_workspace
+                && !self.terminal =>
+            {
                 LapceCommandNew {
-                    cmd: LapceWorkbenchCommand::DisableModal.to_string(),
+                    cmd: LapceWorkbenchCommand::Palette.to_string(),
                     data: None,
-                    palette_desc: LapceWorkbenchCommand::DisableModal
-                        .get_message()
-                        .map(|m| m.to_string()),
+                    palette_desc: None,
                     target: CommandTarget::Workbench,
                 }
+            }
+            None => return false,
+        };
+        ctx.submit_command(Command::new(LAPCE_NEW_COMMAND, cmd, Target::Auto));
+        true
+    }
+
+    fn paint_logo(&self, ctx: &mut PaintCtx, data: &LapceTabData, size: Size) {
+        let svg = logo_svg();
+        let svg_size = 100.0;
+        let rect = Size::ZERO
+            .to_rect()
+            .with_origin(
+                Point::new(size.width / 2.0, size.height / 2.0) + (0.0, -svg_size),
+            )
+            .inflate(svg_size, svg_size);
+        ctx.draw_svg(
+            &svg,
+            rect,
+            Some(
+                &data
+                    .config
+                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                    .clone()
+                    .with_alpha(0.5),
+            ),
+        );
+    }
+
+    pub fn paint(&self, ctx: &mut PaintCtx, data: &LapceTabData) {
+        let rect = ctx.size().to_rect();
+        ctx.fill(
+            rect,
+            data.config
+                .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
+        );
+        ctx.with_save(|ctx| {
+            ctx.clip(rect);
+            let size = ctx.size();
+            if self.terminal {
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout("No terminals")
+                    .font(FontFamily::SYSTEM_UI, 16.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                let line_height = data.config.editor.line_height as f64;
+                let text_size = text_layout.size();
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(
+                        (size.width - text_size.width) / 2.0,
+                        size.height / 2.0 - line_height - text_size.height,
+                    ),
+                );
             } else {
-                LapceCommandNew {
-                    cmd: LapceWorkbenchCommand::EnableModal.to_string(),
-                    data: None,
-                    palette_desc: LapceWorkbenchCommand::EnableModal
-                        .get_message()
-                        .map(|m| m.to_string()),
-                    target: CommandTarget::Workbench,
+                self.paint_logo(ctx, data, size);
+            }
+
+            for (i, hint) in self.commands.iter().enumerate() {
+                if self.hovered == Some(i) {
+                    ctx.fill(
+                        hint.rect.to_rounded_rect(4.0),
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+                    );
                 }
-            },
-            LapceCommandNew {
-                cmd: LapceWorkbenchCommand::Palette.to_string(),
-                data: None,
-                palette_desc: Some("Go To File".to_string()),
-                target: CommandTarget::Workbench,
-            },
-        ]
+                ctx.draw_text(&hint.text, hint.origin);
+                ctx.draw_text(
+                    &hint.keymap,
+                    hint.origin + (20.0 + hint.text.size().width, 0.0),
+                );
+            }
+        });
+    }
+}
+
+/// Inserts the params of a new flex child at `index`, taking half of the
+/// params at `from`, or the average without it, and scales them all to add up
+/// to their count.
+fn insert_params(params: &mut Vec<f64>, index: usize, from: Option<usize>) {
+    let new = match from {
+        Some(from) if from < params.len() => {
+            params[from] /= 2.0;
+            params[from]
+        }
+        _ if params.is_empty() => 1.0,
+        _ => params.iter().sum::<f64>() / params.len() as f64,
+    };
+    params.insert(index.min(params.len()), new);
+    normalize_params(params);
+}
+
+/// The sizes of the flex children sharing `total` by their params. None is
+/// smaller than `min` while there's room for all of them: the ones that would
+/// be are held at `min`, and the others share what's left.
+fn flex_sizes(total: f64, params: &[f64], min: f64) -> Vec<f64> {
+    let sum: f64 = params.iter().sum();
+    let mut sizes: Vec<f64> = params.iter().map(|p| total / sum * p).collect();
+    if total < min * params.len() as f64 {
+        return sizes;
+    }
+    let mut held = vec![false; params.len()];
+    loop {
+        let held_count = held.iter().filter(|h| **h).count();
+        let rest = total - min * held_count as f64;
+        let free_sum: f64 = params
+            .iter()
+            .zip(held.iter())
+            .filter(|(_, h)| !**h)
+            .map(|(p, _)| p)
+            .sum();
+        if free_sum <= 0.0 {
+            return sizes;
+        }
+        let mut changed = false;
+        for (i, p) in params.iter().enumerate() {
+            if held[i] {
+                sizes[i] = min;
+                continue;
+            }
+            sizes[i] = rest / free_sum * p;
+            if sizes[i] < min {
+                held[i] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return sizes;
+        }
+    }
+}
+
+/// A task whose terminal can't go beside the others opens in a new tab
+/// instead.
+fn no_room_for_terminal(
+    ctx: &mut EventCtx,
+    data: &LapceTabData,
+    task: Option<Arc<TerminalTask>>,
+) {
+    match task {
+        Some(task) => ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::NewTerminalTaskTab(task),
+            Target::Widget(data.terminal.widget_id),
+        )),
+        None => show_no_room(),
+    }
+}
+
+/// Tells that there's no room for another child in a split.
+fn show_no_room() {
+    thread::spawn(|| {
+        tinyfiledialogs::message_box_ok(
+            "Lapce",
+            "Not enough space to split",
+            tinyfiledialogs::MessageBoxIcon::Warning,
+        );
+    });
+}
+
+/// Scales the params to add up to their count, keeping their ratios.
+fn normalize_params(params: &mut [f64]) {
+    let sum: f64 = params.iter().sum();
+    if !(sum.is_finite() && sum > 0.0) {
+        params.iter_mut().for_each(|p| *p = 1.0);
+        return;
     }
+    let scale = params.len() as f64 / sum;
+    params.iter_mut().for_each(|p| *p *= scale);
+}
+
+/// The files of the editors that have unsaved changes and that no editor of
+/// the splits other than them shows, each once.
+fn unsaved_last_views(data: &LapceTabData, view_ids: &[WidgetId]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for view_id in view_ids {
+        let path = match data
+            .main_split
+            .editors
+            .get(view_id)
+            .and_then(|editor| editor.file_path())
+        {
+            Some(path) => path,
+            None => continue,
+        };
+        let dirty = data
+            .main_split
+            .open_files
+            .get(path)
+            .map(|buffer| buffer.dirty)
+            .unwrap_or(false);
+        if !dirty || paths.contains(path) {
+            continue;
+        }
+        let shown_elsewhere = data.main_split.editors.iter().any(|(id, other)| {
+            !view_ids.contains(id)
+                && other.split_id.is_some()
+                && other.file_path() == Some(path)
+        });
+        if !shown_elsewhere {
+            paths.push(path.clone());
+        }
+    }
+    paths
+}
+
+/// Matches the children of a split with the ones of a description, in the
+/// order of the description. A child of the description is paired with the
+/// current child of its id, if there's one and `keep` agrees, and is to be
+/// built otherwise. Also returns the current children that aren't kept.
+fn layout_children<'a, T>(
+    current: Vec<(WidgetId, T)>,
+    description: &'a SplitDescription,
+    mut keep: impl FnMut(WidgetId, &SplitChildKind) -> bool,
+) -> (
+    Vec<(&'a SplitChildDescription, Option<(WidgetId, T)>)>,
+    Vec<(WidgetId, T)>,
+) {
+    let mut current: Vec<Option<(WidgetId, T)>> =
+        current.into_iter().map(Some).collect();
+    let mut layout = Vec::new();
+    for child in description.children.iter() {
+        let index = child.id.and_then(|raw| {
+            current.iter().position(|c| {
+                c.as_ref()
+                    .map(|(id, _)| id.to_raw() == raw && keep(*id, &child.kind))
+                    .unwrap_or(false)
+            })
+        });
+        let kept = index.and_then(|i| current[i].take());
+        if kept.is_none() && child.kind == SplitChildKind::Widget {
+            // it can't be built again
+            continue;
+        }
+        layout.push((child, kept));
+    }
+    (layout, current.into_iter().flatten().collect())
+}
+
+/// Drops the data of a child removed from a split, along with the children
+/// of a nested split.
+fn forget_child(id: WidgetId, data: &mut LapceTabData) {
+    if data.main_split.editors.remove(&id).is_some() {
+        data.main_split.forget_focus(id);
+        return;
+    }
+    let terminal = Arc::make_mut(&mut data.terminal);
+    if let Some(nested) = terminal.nested_splits.remove(&id) {
+        for (child_id, _, _) in nested.children {
+            forget_child(child_id, data);
+        }
+        return;
+    }
+    terminal.terminals.retain(|_, t| t.widget_id != id);
+}
+
+/// Opens a file in the editor `view_id`.
+fn open_file(
+    ctx: &mut EventCtx,
+    data: &mut LapceTabData,
+    view_id: WidgetId,
+    path: &PathBuf,
+) {
+    data.main_split.go_to_location(
+        ctx,
+        Some(view_id),
+        EditorLocationNew {
+            path: path.clone(),
+            position: None,
+            scroll_offset: None,
+            hisotry: None,
+            selection_end: None,
+        },
+        &data.config,
+    );
+}
+
+fn describe_split(
+    direction: SplitDirection,
+    children: &[(WidgetId, bool, f64)],
+    data: &LapceTabData,
+) -> SplitDescription {
+    SplitDescription {
+        direction,
+        children: children
+            .iter()
+            .map(|(id, flex, params)| SplitChildDescription {
+                id: Some(id.to_raw()),
+                kind: describe_child(*id, data),
+                flex: *flex,
+                params: *params,
+            })
+            .collect(),
+    }
+}
+
+fn describe_child(id: WidgetId, data: &LapceTabData) -> SplitChildKind {
+    if let Some(editor) = data.main_split.editors.get(&id) {
+        // a narrowed editor comes back as the whole file
+        let content = match editor.narrow.as_ref() {
+            Some(narrow) => BufferContent::File(narrow.path.clone()),
+            None => editor.content.clone(),
+        };
+        return SplitChildKind::Editor(content);
+    }
+    if let Some(nested) = data.terminal.nested_splits.get(&id) {
+        return SplitChildKind::Split(describe_split(
+            nested.direction,
+            &nested.children,
+            data,
+        ));
+    }
+    if data.terminal.terminals.values().any(|t| t.widget_id == id) {
+        return SplitChildKind::Terminal;
+    }
+    SplitChildKind::Widget
+}
+
+/// The hints of an empty editor, the most common first as they're listed
+/// down from the logo.
+fn empty_editor_commands(
+    modal: bool,
+    has_workspace: bool,
+    terminal: bool,
+) -> Vec<LapceCommandNew> {
+    let hint = |cmd: LapceWorkbenchCommand, desc: Option<&str>| LapceCommandNew {
+        cmd: cmd.to_string(),
+        data: None,
+        palette_desc: desc
+            .map(|d| d.to_string())
+            .or_else(|| cmd.get_message().map(|m| m.to_string())),
+        target: CommandTarget::Workbench,
+    };
+    // an empty tab of the terminal panel
+    if terminal {
+        return vec![hint(
+            LapceWorkbenchCommand::NewTerminal,
+            Some("Create Terminal"),
+        )];
+    }
+    let mut commands = if has_workspace {
+        vec![hint(LapceWorkbenchCommand::Palette, Some("Go To File"))]
+    } else {
+        vec![
+            hint(LapceWorkbenchCommand::OpenFolder, Some("Open Folder")),
+            hint(LapceWorkbenchCommand::PaletteWorkspace, Some("Open Recent")),
+        ]
+    };
+    commands.push(hint(LapceWorkbenchCommand::NewFile, None));
+    commands.push(hint(
+        LapceWorkbenchCommand::PaletteCommand,
+        Some("Show All Commands"),
+    ));
+    commands.push(hint(
+        LapceWorkbenchCommand::ToggleTerminal,
+        Some("Toggle Terminal"),
+    ));
+    commands.push(if modal {
+        hint(LapceWorkbenchCommand::DisableModal, None)
+    } else {
+        hint(LapceWorkbenchCommand::EnableModal, None)
+    });
+    commands
 }
 
 fn keybinding_to_string(keypress: &KeyPress) -> String {
+    if std::env::consts::OS == "macos" {
+        // the mac convention is control, option, shift, command, without
+        // separators, e.g. ⇧⌘P
+        let mut keymap_str = "".to_string();
+        if keypress.mods.ctrl() {
+            keymap_str += "\u{2303}";
+        }
+        if keypress.mods.alt() {
+            keymap_str += "\u{2325}";
+        }
+        if keypress.mods.shift() {
+            keymap_str += "\u{21e7}";
+        }
+        if keypress.mods.meta() {
+            keymap_str += "\u{2318}";
+        }
+        let key = keypress.key.to_string();
+        if key.chars().count() == 1 {
+            keymap_str += &key.to_uppercase();
+        } else {
+            keymap_str += &key;
+        }
+        return keymap_str;
+    }
+
     let mut keymap_str = "".to_string();
     if keypress.mods.ctrl() {
         keymap_str += "Ctrl+";
@@ -962,3 +2801,705 @@ fn keybinding_to_string(keypress: &KeyPress) -> String {
     keymap_str += &keypress.key.to_string();
     keymap_str
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use druid::Modifiers;
+
+    use super::*;
+    use crate::harness::{key_down, Harness, HarnessWidget, RecordingCtx};
+
+    impl HarnessWidget<LapceTabData> for LapceSplitNew {
+        fn event(
+            &mut self,
+            ctx: &mut RecordingCtx,
+            event: &Event,
+            data: &mut LapceTabData,
+        ) {
+            match event {
+                Event::KeyDown(key_event) if self.children.is_empty() => {
+                    self.empty_key_down(ctx, data, key_event);
+                }
+                Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
+                    self.run_editor_command(
+                        ctx,
+                        data,
+                        cmd.get_unchecked(LAPCE_UI_COMMAND),
+                    );
+                }
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_description_round_trip() {
+        let description = SplitDescription {
+            direction: SplitDirection::Vertical,
+            children: vec![
+                SplitChildDescription {
+                    id: None,
+                    kind: SplitChildKind::Editor(BufferContent::File(
+                        PathBuf::from("src/main.rs"),
+                    )),
+                    flex: true,
+                    params: 1.0,
+                },
+                SplitChildDescription {
+                    id: None,
+                    kind: SplitChildKind::Split(SplitDescription {
+                        direction: SplitDirection::Horizontal,
+                        children: vec![
+                            SplitChildDescription {
+                                id: None,
+                                kind: SplitChildKind::Terminal,
+                                flex: true,
+                                params: 1.0,
+                            },
+                            SplitChildDescription {
+                                id: None,
+                                kind: SplitChildKind::Terminal,
+                                flex: true,
+                                params: 2.0,
+                            },
+                        ],
+                    }),
+                    flex: true,
+                    params: 1.0,
+                },
+            ],
+        };
+        let json = serde_json::to_string(&description).unwrap();
+        let parsed: SplitDescription = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, description);
+    }
+
+    fn terminal(id: Option<WidgetId>, params: f64) -> SplitChildDescription {
+        SplitChildDescription {
+            id: id.map(|id| id.to_raw()),
+            kind: SplitChildKind::Terminal,
+            flex: true,
+            params,
+        }
+    }
+
+    #[test]
+    fn test_apply_layout_round_trip() {
+        let (a, b, c) = (WidgetId::next(), WidgetId::next(), WidgetId::next());
+        let layout = vec![(a, 0.5), (b, 1.0), (c, 1.5)];
+        let description = SplitDescription {
+            direction: SplitDirection::Horizontal,
+            children: layout
+                .iter()
+                .map(|(id, params)| terminal(Some(*id), *params))
+                .collect(),
+        };
+
+        // applied to a split that was rearranged, resized, and got a child
+        let d = WidgetId::next();
+        let current = vec![(c, 1.0), (d, 1.0), (a, 1.0), (b, 1.0)];
+        let (applied, removed) = layout_children(current, &description, |_, _| true);
+        let applied: Vec<(WidgetId, f64)> = applied
+            .into_iter()
+            .map(|(child, kept)| (kept.unwrap().0, child.params))
+            .collect();
+        assert_eq!(applied, layout);
+        assert_eq!(removed, vec![(d, 1.0)]);
+
+        // applied to a cleared split, everything is built again in order
+        let (applied, removed) =
+            layout_children(Vec::<(WidgetId, f64)>::new(), &description, |_, _| {
+                true
+            });
+        assert!(removed.is_empty());
+        assert_eq!(
+            applied
+                .iter()
+                .map(|(child, kept)| (child.id, child.params, kept.is_some()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some(a.to_raw()), 0.5, false),
+                (Some(b.to_raw()), 1.0, false),
+                (Some(c.to_raw()), 1.5, false),
+            ]
+        );
+
+        // a child that isn't kept is built again, unless it can't be
+        let mut description = description;
+        description.children[1].kind = SplitChildKind::Widget;
+        let (applied, removed) = layout_children(
+            vec![(a, ()), (b, ()), (c, ())],
+            &description,
+            |id, _| id != a,
+        );
+        assert_eq!(
+            applied
+                .iter()
+                .map(|(_, kept)| kept.as_ref().map(|(id, _)| *id))
+                .collect::<Vec<_>>(),
+            vec![None, Some(b), Some(c)]
+        );
+        assert_eq!(removed, vec![(a, ())]);
+        let (applied, _) =
+            layout_children(Vec::<(WidgetId, ())>::new(), &description, |_, _| true);
+        assert_eq!(applied.len(), 2);
+    }
+
+    fn assert_params(params: &[f64], expected: &[f64]) {
+        assert_eq!(params.len(), expected.len());
+        for (p, e) in params.iter().zip(expected.iter()) {
+            assert!((p - e).abs() < 1e-9, "{:?} != {:?}", params, expected);
+        }
+        let sum: f64 = params.iter().sum();
+        assert!((sum - params.len() as f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_insert_params() {
+        // splitting the second of 1:3 halves it, the first keeps its size
+        let mut params = vec![0.5, 1.5];
+        insert_params(&mut params, 2, Some(1));
+        assert_params(&params, &[0.75, 1.125, 1.125]);
+
+        // without a child to split, an even share
+        let mut params = vec![1.0, 3.0];
+        insert_params(&mut params, 0, None);
+        assert_params(&params, &[1.0, 0.5, 1.5]);
+
+        let mut params = Vec::new();
+        insert_params(&mut params, 0, Some(0));
+        assert_params(&params, &[1.0]);
+    }
+
+    #[test]
+    fn test_normalize_params() {
+        // a child of 1:1:2 is closed
+        let mut params = vec![1.0, 2.0];
+        normalize_params(&mut params);
+        assert_params(&params, &[2.0 / 3.0, 4.0 / 3.0]);
+
+        let mut params = vec![0.0, 0.0];
+        normalize_params(&mut params);
+        assert_params(&params, &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_flex_sizes() {
+        let assert_sizes = |sizes: &[f64], expected: &[f64]| {
+            assert_eq!(sizes.len(), expected.len());
+            for (s, e) in sizes.iter().zip(expected.iter()) {
+                assert!((s - e).abs() < 1e-9, "{:?} != {:?}", sizes, expected);
+            }
+        };
+        assert_sizes(
+            &flex_sizes(900.0, &[1.0, 1.0, 1.0], 200.0),
+            &[300.0, 300.0, 300.0],
+        );
+        // a child dragged too small is held at the minimum
+        assert_sizes(
+            &flex_sizes(900.0, &[0.2, 1.4, 1.4], 200.0),
+            &[200.0, 350.0, 350.0],
+        );
+        assert_sizes(
+            &flex_sizes(1000.0, &[0.1, 0.1, 2.8], 200.0),
+            &[200.0, 200.0, 600.0],
+        );
+        // without room for all of them, they share it by their params
+        assert_sizes(
+            &flex_sizes(300.0, &[1.0, 1.0, 1.0], 200.0),
+            &[100.0, 100.0, 100.0],
+        );
+    }
+
+    #[test]
+    fn test_empty_editor_commands() {
+        let cmds = |modal, has_workspace| {
+            empty_editor_commands(modal, has_workspace, false)
+                .into_iter()
+                .map(|c| c.cmd)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            cmds(true, true),
+            vec![
+                "palette",
+                "new_file",
+                "palette.command",
+                "toggle_terminal",
+                "disable_modal_editing",
+            ]
+        );
+        assert_eq!(
+            cmds(false, false),
+            vec![
+                "open_folder",
+                "palette.workspace",
+                "new_file",
+                "palette.command",
+                "toggle_terminal",
+                "enable_modal_editing",
+            ]
+        );
+        assert!(empty_editor_commands(false, true, false)
+            .iter()
+            .all(|c| c.palette_desc.is_some()));
+
+        let terminal = empty_editor_commands(true, true, true);
+        assert_eq!(terminal.len(), 1);
+        assert_eq!(terminal[0].cmd, "terminal.new");
+        assert_eq!(terminal[0].palette_desc.as_deref(), Some("Create Terminal"));
+    }
+
+    fn file(harness: &Harness, name: &str) -> BufferContent {
+        BufferContent::File(harness.file(name, "fn main() {}\n"))
+    }
+
+    /// A main split with an editor of a file for each name.
+    fn split_of(harness: &mut Harness, names: &[&str]) -> LapceSplitNew {
+        let contents: Vec<BufferContent> =
+            names.iter().map(|name| file(harness, name)).collect();
+        harness.main_split(&contents)
+    }
+
+    #[test]
+    fn test_split_editor() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs"]);
+        let a = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditor(true, a));
+
+        assert_eq!(split.children_ids.len(), 2);
+        assert_eq!(split.children_ids[0], a);
+        let new = split.children_ids[1];
+        let editors = &harness.data.main_split.editors;
+        assert_eq!(editors[&new].content, editors[&a].content);
+        assert_eq!(editors[&new].split_id, Some(split.split_id));
+        assert_eq!(*harness.data.main_split.editors_order, split.children_ids);
+        assert!(harness.ctx.children_changed);
+        // the new editor starts where the first one is scrolled to
+        assert!(harness.ui_commands().iter().any(|(cmd, target)| {
+            matches!(cmd, LapceUICommand::ForceScrollTo(_, _))
+                && *target == Target::Widget(new)
+        }));
+    }
+
+    #[test]
+    fn test_split_editor_shares_space() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let (a, b) = (split.children_ids[0], split.children_ids[1]);
+        harness.run(&mut split, LapceUICommand::SplitEditor(true, a));
+
+        assert_eq!(split.children_ids[0], a);
+        assert_eq!(split.children_ids[2], b);
+        // the new editor takes half of the space of the one it's split from
+        assert_params(&harness.data.main_split.editors_flex, &[0.75, 0.75, 1.5]);
+    }
+
+    #[test]
+    fn test_split_editor_empty() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs"]);
+        let a = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditorEmpty(a));
+
+        assert_eq!(split.children_ids.len(), 2);
+        let new = split.children_ids[1];
+        assert_eq!(
+            harness.data.main_split.editors[&new].content,
+            BufferContent::Local(LocalBufferKind::Empty)
+        );
+        assert_eq!(harness.focused(), Some(Target::Widget(new)));
+    }
+
+    #[test]
+    fn test_close_last_editor_focuses_split() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs"]);
+        let a = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(a));
+
+        assert!(split.children_ids.is_empty());
+        assert!(split.children.is_empty());
+        assert_eq!(*harness.data.main_split.active, None);
+        assert!(!harness.data.main_split.editors.contains_key(&a));
+        assert!(harness.data.main_split.editors_order.is_empty());
+        assert_eq!(harness.focused(), Some(Target::Widget(split.split_id)));
+    }
+
+    #[test]
+    fn test_close_editor_focuses_neighbour() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs", "c.rs"]);
+        let (b, c) = (split.children_ids[1], split.children_ids[2]);
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(b));
+        assert_eq!(harness.focused(), Some(Target::Widget(c)));
+        assert_params(&harness.data.main_split.editors_flex, &[1.0, 1.0]);
+
+        // the last one hands the focus to the one before it
+        harness.clear();
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(c));
+        let a = split.children_ids[0];
+        assert_eq!(split.children_ids, vec![a]);
+        assert_eq!(harness.focused(), Some(Target::Widget(a)));
+    }
+
+    #[test]
+    fn test_close_editor_focuses_previous() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs", "c.rs"]);
+        let (a, c) = (split.children_ids[0], split.children_ids[2]);
+        harness.data.main_split.push_focus_history(a);
+        harness.data.main_split.push_focus_history(c);
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(c));
+        assert_eq!(harness.focused(), Some(Target::Widget(a)));
+    }
+
+    #[test]
+    fn test_close_editor_can_be_reopened() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let b = split.children_ids[1];
+        let path = harness.data.main_split.editors[&b].file_path().cloned();
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(b));
+
+        let closed = harness.data.main_split.closed_editors.last().unwrap();
+        assert_eq!(Some(closed.path.clone()), path);
+        assert_eq!(closed.index, 1);
+        assert_eq!(closed.split_id, split.split_id);
+    }
+
+    #[test]
+    fn test_close_others() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs", "c.rs"]);
+        let b = split.children_ids[1];
+        harness.run(&mut split, LapceUICommand::SplitEditorCloseOthers(b));
+
+        assert_eq!(split.children_ids, vec![b]);
+        assert_eq!(split.children.len(), 1);
+        assert_eq!(*harness.data.main_split.active, Some(b));
+        assert_eq!(*harness.data.main_split.editors_order, vec![b]);
+        assert_eq!(harness.data.main_split.closed_editors.len(), 2);
+        assert_eq!(harness.focused(), Some(Target::Widget(b)));
+    }
+
+    #[test]
+    fn test_close_all() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let ids = split.children_ids.clone();
+        harness.run(&mut split, LapceUICommand::SplitEditorCloseAll);
+
+        assert!(split.children_ids.is_empty());
+        assert_eq!(*harness.data.main_split.active, None);
+        assert!(ids.iter().all(|id| !harness
+            .data
+            .main_split
+            .editors
+            .contains_key(id)));
+        assert_eq!(harness.data.main_split.closed_editors.len(), 2);
+        assert_eq!(harness.focused(), Some(Target::Widget(split.split_id)));
+    }
+
+    #[test]
+    fn test_enter_starts_terminal_in_empty_tab() {
+        let mut harness = Harness::new();
+        let split_id = harness.data.terminal.split_id();
+        let mut split = LapceSplitNew::new(split_id);
+        harness.send(&mut split, &key_down(KbKey::Enter, Modifiers::empty()));
+
+        assert!(harness.ctx.handled);
+        assert!(matches!(
+            harness.ui_commands()[..],
+            [(LapceUICommand::InitTerminalPanel(true, None), target)]
+                if target == Target::Widget(split_id)
+        ));
+
+        // the empty editor split leaves it to the shortcuts
+        harness.clear();
+        let mut split = harness.main_split(&[]);
+        harness.send(&mut split, &key_down(KbKey::Enter, Modifiers::empty()));
+        assert!(harness.ui_commands().is_empty());
+    }
+
+    #[test]
+    fn test_close_others_in_editors_panel() {
+        let mut harness = Harness::new();
+        let contents = vec![file(&harness, "a.rs"), file(&harness, "b.rs")];
+        let mut split = harness.editors_panel(&contents);
+        let b = split.children_ids[1];
+        harness.run(&mut split, LapceUICommand::SplitEditorCloseOthers(b));
+
+        // the editor left keeps the panel listed
+        assert_eq!(split.children_ids, vec![b]);
+        assert!(harness.editors_panel_listed());
+    }
+
+    #[test]
+    fn test_close_all_in_editors_panel() {
+        let mut harness = Harness::new();
+        let contents = vec![file(&harness, "a.rs"), file(&harness, "b.rs")];
+        let mut split = harness.editors_panel(&contents);
+        assert!(harness.editors_panel_listed());
+        harness.run(&mut split, LapceUICommand::SplitEditorCloseAll);
+
+        assert!(split.children_ids.is_empty());
+        assert!(!harness.editors_panel_listed());
+    }
+
+    #[test]
+    fn test_exchange() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let (a, b) = (split.children_ids[0], split.children_ids[1]);
+        harness.run(&mut split, LapceUICommand::SplitEditorExchange(a));
+        assert_eq!(split.children_ids, vec![b, a]);
+        assert_eq!(*harness.data.main_split.editors_order, vec![b, a]);
+        assert_eq!(harness.focused(), Some(Target::Widget(b)));
+
+        // the last editor has nothing to exchange with
+        harness.clear();
+        harness.run(&mut split, LapceUICommand::SplitEditorExchange(a));
+        assert_eq!(split.children_ids, vec![b, a]);
+        assert!(harness.ctx.commands.is_empty());
+    }
+
+    #[test]
+    fn test_move_focus() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let (a, b) = (split.children_ids[0], split.children_ids[1]);
+        harness.run(
+            &mut split,
+            LapceUICommand::SplitEditorMove(SplitMoveDirection::Right, a),
+        );
+        assert_eq!(harness.focused(), Some(Target::Widget(b)));
+        assert!(harness.ui_commands().iter().any(|(cmd, target)| {
+            matches!(cmd, LapceUICommand::EnsureCursorVisible(_))
+                && *target == Target::Widget(b)
+        }));
+        assert_eq!(split.children_ids, vec![a, b]);
+    }
+
+    #[test]
+    fn test_move_focus_out_of_split() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let a = split.children_ids[0];
+        for direction in [SplitMoveDirection::Left, SplitMoveDirection::Up] {
+            harness.clear();
+            harness.run(&mut split, LapceUICommand::SplitEditorMove(direction, a));
+            let commands = harness.ui_commands();
+            assert_eq!(commands.len(), 1);
+            assert!(matches!(commands[0].0, LapceUICommand::SplitMoveOut(_)));
+            assert_eq!(commands[0].1, Target::Widget(harness.data.id));
+        }
+    }
+
+    #[test]
+    fn test_even_size() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let a = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditor(true, a));
+        harness.run(&mut split, LapceUICommand::SplitEvenSize);
+        assert_eq!(*harness.data.main_split.editors_flex, vec![1.0, 1.0, 1.0]);
+        assert!(harness.ctx.layout_requested);
+    }
+
+    /// The contents of the editors a description lists, in order.
+    fn described_editors(description: &SplitDescription) -> Vec<BufferContent> {
+        description
+            .children
+            .iter()
+            .map(|child| match &child.kind {
+                SplitChildKind::Editor(content) => content.clone(),
+                kind => panic!("expected an editor, got {:?}", kind),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_describe_after_split_and_close() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let (a, b) = (file(&harness, "a.rs"), file(&harness, "b.rs"));
+        let first = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditor(true, first));
+
+        let description = split.to_description(&harness.data);
+        assert_eq!(description.direction, SplitDirection::Vertical);
+        assert_eq!(
+            described_editors(&description),
+            vec![a.clone(), a.clone(), b.clone()]
+        );
+        let ids: Vec<Option<u64>> = split
+            .children_ids
+            .iter()
+            .map(|id| Some(id.to_raw()))
+            .collect();
+        assert_eq!(
+            description
+                .children
+                .iter()
+                .map(|c| c.id)
+                .collect::<Vec<_>>(),
+            ids
+        );
+        let params: Vec<f64> =
+            description.children.iter().map(|c| c.params).collect();
+        assert_params(&params, &[0.75, 0.75, 1.5]);
+
+        let new = split.children_ids[1];
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(new));
+        let description = split.to_description(&harness.data);
+        assert_eq!(described_editors(&description), vec![a, b]);
+        let params: Vec<f64> =
+            description.children.iter().map(|c| c.params).collect();
+        assert_params(&params, &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_describe_after_exchange_and_move() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let (a, b) = (file(&harness, "a.rs"), file(&harness, "b.rs"));
+        let first = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditorExchange(first));
+        let exchanged = split.to_description(&harness.data);
+        assert_eq!(described_editors(&exchanged), vec![b, a]);
+
+        // moving the focus leaves the layout as it is
+        harness.run(
+            &mut split,
+            LapceUICommand::SplitEditorMove(SplitMoveDirection::Left, first),
+        );
+        assert_eq!(split.to_description(&harness.data), exchanged);
+    }
+
+    #[test]
+    fn test_describe_empty_editor() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs"]);
+        let first = split.children_ids[0];
+        harness.run(&mut split, LapceUICommand::SplitEditorEmpty(first));
+        assert_eq!(
+            described_editors(&split.to_description(&harness.data))[1],
+            BufferContent::Local(LocalBufferKind::Empty)
+        );
+    }
+
+    #[test]
+    fn test_layout_restores_split() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs", "c.rs"]);
+        let ids = split.children_ids.clone();
+        let saved = split.to_description(&harness.data);
+
+        harness.run(&mut split, LapceUICommand::SplitEditorExchange(ids[0]));
+        harness.run(&mut split, LapceUICommand::SplitEditorClose(ids[2]));
+        assert_eq!(split.children_ids, vec![ids[1], ids[0]]);
+
+        // the editors still there are kept in their saved places, and the
+        // closed one is built again
+        let current = split.children_ids.iter().map(|id| (*id, ())).collect();
+        let data = &harness.data;
+        let (layout, removed) = layout_children(current, &saved, |id, kind| {
+            describe_child(id, data) == *kind
+        });
+        assert!(removed.is_empty());
+        assert_eq!(
+            layout
+                .iter()
+                .map(|(_, kept)| kept.as_ref().map(|(id, _)| *id))
+                .collect::<Vec<_>>(),
+            vec![Some(ids[0]), Some(ids[1]), None]
+        );
+        assert_eq!(layout[2].0.kind, saved.children[2].kind);
+
+        // an editor that shows another file now isn't kept
+        let other = file(&harness, "d.rs");
+        let editor = harness.data.main_split.editors.get_mut(&ids[0]).unwrap();
+        Arc::make_mut(editor).content = other;
+        let current = split.children_ids.iter().map(|id| (*id, ())).collect();
+        let data = &harness.data;
+        let (layout, removed) = layout_children(current, &saved, |id, kind| {
+            describe_child(id, data) == *kind
+        });
+        assert_eq!(removed, vec![(ids[0], ())]);
+        assert!(layout[0].1.is_none());
+    }
+
+    /// Gives the file of the editor unsaved changes.
+    fn make_dirty(harness: &mut Harness, view_id: WidgetId) {
+        let main_split = &mut harness.data.main_split;
+        let path = main_split.editors[&view_id].file_path().cloned().unwrap();
+        Arc::make_mut(main_split.open_files.get_mut(&path).unwrap()).dirty = true;
+    }
+
+    #[test]
+    fn test_unsaved_last_views() {
+        let mut harness = Harness::new();
+        let split = split_of(&mut harness, &["a.rs", "a.rs", "b.rs", "c.rs"]);
+        let ids = split.children_ids.clone();
+        let path = |id: WidgetId| {
+            harness.data.main_split.editors[&id]
+                .file_path()
+                .cloned()
+                .unwrap()
+        };
+        let (a, b) = (path(ids[0]), path(ids[2]));
+        make_dirty(&mut harness, ids[0]);
+        make_dirty(&mut harness, ids[2]);
+        let data = &harness.data;
+
+        // another editor still shows the changes
+        assert!(unsaved_last_views(data, &ids[..1]).is_empty());
+        assert_eq!(unsaved_last_views(data, &ids[..2]), vec![a.clone()]);
+        assert_eq!(unsaved_last_views(data, &ids[2..3]), vec![b.clone()]);
+        // a file without changes is closed without asking
+        assert!(unsaved_last_views(data, &ids[3..]).is_empty());
+        // each file is asked about once
+        assert_eq!(unsaved_last_views(data, &ids), vec![a, b]);
+    }
+
+    #[test]
+    fn test_closing() {
+        let mut harness = Harness::new();
+        let split = split_of(&mut harness, &["a.rs", "b.rs", "c.rs"]);
+        let ids = split.children_ids.clone();
+        assert_eq!(split.closing(CloseRequest::Editor(ids[1])), vec![ids[1]]);
+        assert_eq!(
+            split.closing(CloseRequest::Others(ids[1])),
+            vec![ids[0], ids[2]]
+        );
+        assert_eq!(split.closing(CloseRequest::All), ids);
+        assert!(split
+            .closing(CloseRequest::Others(WidgetId::next()))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_close_waits_for_answer() {
+        let mut harness = Harness::new();
+        let mut split = split_of(&mut harness, &["a.rs", "b.rs"]);
+        let ids = split.children_ids.clone();
+        make_dirty(&mut harness, ids[1]);
+        // the answer about the unsaved file hasn't come back yet
+        split.confirm_close = Some(CloseRequest::Others(ids[0]));
+
+        for command in [
+            LapceUICommand::SplitEditorClose(ids[0]),
+            LapceUICommand::SplitEditorCloseOthers(ids[0]),
+            LapceUICommand::SplitEditorCloseAll,
+        ] {
+            harness.run(&mut split, command);
+            assert_eq!(split.children_ids, ids);
+            assert_eq!(harness.data.main_split.editors.len(), 2);
+            assert!(harness.ctx.commands.is_empty());
+        }
+    }
+}
@@ -73,19 +73,112 @@ pub struct KeyMap {
     pub command: String,
 }
 
+/// A machine to connect to over SSH, written `user@host:port`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SshHost {
+    pub user: String,
+    pub host: String,
+    /// The port, if it's not the one of the SSH config.
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+impl SshHost {
+    /// Parses `user@host:port`, where the user defaults to root and the
+    /// port is optional.
+    pub fn parse(s: &str) -> Option<SshHost> {
+        SshHost::validate(s).ok()
+    }
+
+    /// Like `parse`, but says what's wrong with the input.
+    pub fn validate(s: &str) -> Result<SshHost, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("Enter a host as user@host:port".to_string());
+        }
+        if s.contains(char::is_whitespace) {
+            return Err("The host can't contain spaces".to_string());
+        }
+        let (user, rest) = match s.rsplit_once('@') {
+            Some((user, rest)) => (user.to_string(), rest),
+            None => ("root".to_string(), s),
+        };
+        let (host, port) = match rest.rsplit_once(':') {
+            // more than one colon is an IPv6 address without a port
+            Some((host, port)) if !host.contains(':') => match port.parse::<u16>() {
+                Ok(port) if port > 0 => (host, Some(port)),
+                _ => return Err(format!("\"{}\" is not a valid port", port)),
+            },
+            _ => (rest, None),
+        };
+        if user.is_empty() {
+            return Err("The user is missing before @".to_string());
+        }
+        if host.is_empty() {
+            return Err("The host name is missing".to_string());
+        }
+        Ok(SshHost {
+            user,
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// The destination for `ssh` and `scp`, which take the port apart.
+    pub fn user_host(&self) -> String {
+        format!("{}@{}", self.user, self.host)
+    }
+}
+
+/// Turns what `ssh` printed when it failed to connect into a short reason.
+pub fn ssh_error_message(stderr: &str) -> String {
+    let reasons = [
+        ("Permission denied", "Authentication failed"),
+        (
+            "Host key verification failed",
+            "Host key verification failed",
+        ),
+        ("Could not resolve hostname", "Unknown host"),
+        ("timed out", "Connection timed out"),
+        ("Connection refused", "Host unreachable: connection refused"),
+        ("No route to host", "Host unreachable"),
+        ("Network is unreachable", "Host unreachable"),
+    ];
+    for (pattern, reason) in reasons {
+        if stderr.contains(pattern) {
+            return reason.to_string();
+        }
+    }
+    stderr
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .last()
+        .unwrap_or("Connection failed")
+        .to_string()
+}
+
+impl Display for SshHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.user, self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum LapceWorkspaceType {
     Local,
-    RemoteSSH(String, String),
+    RemoteSSH(SshHost),
 }
 
 impl Display for LapceWorkspaceType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             LapceWorkspaceType::Local => f.write_str("Local"),
-            LapceWorkspaceType::RemoteSSH(user, host) => {
-                write!(f, "ssh://{}@{}", user, host)
-            }
+            LapceWorkspaceType::RemoteSSH(host) => write!(f, "ssh://{}", host),
         }
     }
 }
@@ -107,6 +200,29 @@ impl Default for LapceWorkspace {
     }
 }
 
+impl LapceWorkspace {
+    /// Whether the deleted files go to the `.lapce-trash` folder of the
+    /// workspace, a remote machine having no trash to restore them from.
+    pub fn stages_deleted_files(&self) -> bool {
+        matches!(self.kind, LapceWorkspaceType::RemoteSSH(..))
+    }
+
+    /// The workspace as a file name, for the files kept apart for each
+    /// workspace in the config dir. The end of the path keeps it readable,
+    /// and a hash of the whole workspace tells apart the ones that read the
+    /// same.
+    pub fn file_name(&self) -> String {
+        let name: Vec<char> = self
+            .to_string()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let name: String = name[name.len().saturating_sub(64)..].iter().collect();
+        let identity = format!("{}:{:?}", self.kind, self.path);
+        format!("{}_{:016x}", name, fnv_hash([identity.as_bytes()]))
+    }
+}
+
 impl Display for LapceWorkspace {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -134,6 +250,19 @@ impl Counter {
     }
 }
 
+/// A FNV-1a hash of the bytes, which unlike the std hasher is the same in
+/// every build, for what is kept across sessions.
+pub fn fnv_hash<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for chunk in chunks {
+        for byte in chunk {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
 pub fn hex_to_color(hex: &str) -> Result<Color> {
     let hex = hex.trim_start_matches("#");
     let (r, g, b, a) = match hex.len() {
@@ -171,6 +300,110 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_workspace_file_name() {
+        let workspace = |kind, path: &str| LapceWorkspace {
+            kind,
+            path: Some(PathBuf::from(path)),
+            last_open: 0,
+        };
+        let a = workspace(LapceWorkspaceType::Local, "/home/dev/my-app");
+        let b = workspace(LapceWorkspaceType::Local, "/home/dev/my_app");
+        assert_ne!(a.file_name(), b.file_name());
+        assert!(a.file_name().starts_with("Local__home_dev_my_app_"));
+        assert_eq!(a.file_name(), a.file_name());
+
+        let host = SshHost::parse("dev@example.com").unwrap();
+        let remote =
+            workspace(LapceWorkspaceType::RemoteSSH(host), "/home/dev/my-app");
+        assert_ne!(a.file_name(), remote.file_name());
+
+        let long = workspace(LapceWorkspaceType::Local, &"/dir".repeat(100));
+        assert!(long.file_name().len() < 100);
+    }
+
+    #[test]
+    fn test_parse_ssh_host() {
+        let host = SshHost::parse("dev@example.com:2222").unwrap();
+        assert_eq!(host.user, "dev");
+        assert_eq!(host.host, "example.com");
+        assert_eq!(host.port, Some(2222));
+        assert_eq!(host.to_string(), "dev@example.com:2222");
+        assert_eq!(host.user_host(), "dev@example.com");
+
+        let host = SshHost::parse("example.com").unwrap();
+        assert_eq!(host.to_string(), "root@example.com");
+        assert_eq!(SshHost::parse("root@::1").unwrap().host, "::1");
+        assert_eq!(SshHost::parse("dev@example.com:ssh"), None);
+        assert_eq!(SshHost::parse("dev@"), None);
+    }
+
+    #[test]
+    fn test_validate_ssh_host() {
+        assert!(SshHost::validate("   ").is_err());
+        assert!(SshHost::validate("dev@exa mple.com").is_err());
+        assert_eq!(
+            SshHost::validate("dev@example.com:22x"),
+            Err("\"22x\" is not a valid port".to_string())
+        );
+        assert!(SshHost::validate("dev@example.com:0").is_err());
+        assert!(SshHost::validate("dev@example.com:").is_err());
+        assert!(SshHost::validate("@example.com").is_err());
+        assert!(SshHost::validate("dev@:22").is_err());
+        assert_eq!(
+            SshHost::validate(" dev@example.com ").unwrap().to_string(),
+            "dev@example.com"
+        );
+    }
+
+    #[test]
+    fn test_ssh_error_message() {
+        assert_eq!(
+            ssh_error_message(
+                "dev@example.com: Permission denied (publickey,password).\n"
+            ),
+            "Authentication failed"
+        );
+        assert_eq!(
+            ssh_error_message(
+                "ssh: connect to host example.com port 22: Connection timed out\n"
+            ),
+            "Connection timed out"
+        );
+        assert_eq!(
+            ssh_error_message(
+                "ssh: Could not resolve hostname nope: Name or service not known\n"
+            ),
+            "Unknown host"
+        );
+        assert_eq!(
+            ssh_error_message(
+                "ssh: connect to host 10.0.0.1 port 22: No route to host\n"
+            ),
+            "Host unreachable"
+        );
+        assert_eq!(
+            ssh_error_message("banner\nsomething else\n\n"),
+            "something else"
+        );
+        assert_eq!(ssh_error_message(""), "Connection failed");
+    }
+
+    #[test]
+    fn test_ssh_host_from_old_workspace() {
+        // workspaces saved before the port was added
+        let kind: LapceWorkspaceType =
+            serde_json::from_str(r#"{"RemoteSSH":["dev","example.com"]}"#).unwrap();
+        assert_eq!(
+            kind,
+            LapceWorkspaceType::RemoteSSH(SshHost {
+                user: "dev".to_string(),
+                host: "example.com".to_string(),
+                port: None,
+            })
+        );
+    }
+
     #[test]
     fn test_check_condition() {
         // let rope = Rope::from_str("abc\nabc\n").unwrap();
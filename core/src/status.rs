@@ -1,3 +1,6 @@
+use std::time::Instant;
+
+use druid::kurbo::Arc;
 use druid::piet::Text;
 use druid::piet::TextLayout;
 use druid::piet::TextLayoutBuilder;
@@ -9,11 +12,15 @@ use druid::MouseEvent;
 use druid::Target;
 use druid::Vec2;
 use druid::{
-    kurbo::Line, Event, FontDescriptor, FontFamily, Point, RenderContext, Size,
-    Widget, WidgetId, WindowId,
+    kurbo::Line, Event, FontDescriptor, FontFamily, Point, Rect, RenderContext,
+    Size, Widget, WidgetId, WindowId,
 };
+use lapce_proxy::editorconfig::IndentStyle;
+use lapce_proxy::protocol::ProtocolInfo;
 use lsp_types::DiagnosticSeverity;
+use serde_json::json;
 
+use crate::buffer::BufferContent;
 use crate::command::CommandTarget;
 use crate::command::LapceCommandNew;
 use crate::command::LapceWorkbenchCommand;
@@ -34,6 +41,12 @@ pub struct LapceStatusNew {
     panel_icons: Vec<LapceIcon>,
     mouse_pos: Point,
     icon_size: f64,
+    proxy_rect: Rect,
+    index_rect: Rect,
+    /// Where the progress of the workspace search was last painted.
+    search_rect: Rect,
+    /// When the spinner of the search started turning.
+    spinner_start: Instant,
 }
 
 impl LapceStatusNew {
@@ -43,9 +56,19 @@ impl LapceStatusNew {
             panel_icons: Vec::new(),
             mouse_pos: Point::ZERO,
             icon_size: 13.0,
+            proxy_rect: Rect::ZERO,
+            index_rect: Rect::ZERO,
+            search_rect: Rect::ZERO,
+            spinner_start: Instant::now(),
         }
     }
 
+    /// Whether the spinner of the search turns, which needs animation
+    /// frames.
+    fn spinner_turns(data: &LapceTabData) -> bool {
+        data.search.is_searching() && data.config.ui.animations
+    }
+
     fn panel_icons(&self, self_size: Size, data: &LapceTabData) -> Vec<LapceIcon> {
         let left_panels = data
             .panels
@@ -59,6 +82,12 @@ impl LapceStatusNew {
             .unwrap_or(Vec::new());
         let mut panels = left_panels;
         panels.append(&mut right_panels);
+        panels.retain(|p| {
+            Self::panel_command(p)
+                .required_capability()
+                .map(|capability| data.proxy.has_capability(capability))
+                .unwrap_or(true)
+        });
 
         let panel_icons_size = self_size.height * panels.len() as f64;
         let offset = (self_size.width - panel_icons_size) / 2.0;
@@ -67,16 +96,7 @@ impl LapceStatusNew {
             .iter()
             .enumerate()
             .map(|(i, p)| {
-                let cmd = match p {
-                    PanelKind::FileExplorer => LapceWorkbenchCommand::ToggleTerminal,
-                    PanelKind::SourceControl => {
-                        LapceWorkbenchCommand::ToggleSourceControl
-                    }
-                    PanelKind::Plugin => LapceWorkbenchCommand::TogglePlugin,
-                    PanelKind::Terminal => LapceWorkbenchCommand::ToggleTerminal,
-                    PanelKind::Search => LapceWorkbenchCommand::ToggleSearch,
-                    PanelKind::Problem => LapceWorkbenchCommand::ToggleProblem,
-                };
+                let cmd = Self::panel_command(p);
                 LapceIcon {
                     icon: p.svg_name(),
                     rect: Size::new(self_size.height, self_size.height)
@@ -101,21 +121,134 @@ impl LapceStatusNew {
         icons
     }
 
+    fn panel_command(kind: &PanelKind) -> LapceWorkbenchCommand {
+        match kind {
+            PanelKind::FileExplorer => LapceWorkbenchCommand::ToggleFileExplorer,
+            PanelKind::SourceControl => LapceWorkbenchCommand::ToggleSourceControl,
+            PanelKind::Plugin => LapceWorkbenchCommand::TogglePlugin,
+            PanelKind::Terminal => LapceWorkbenchCommand::ToggleTerminal,
+            PanelKind::Search => LapceWorkbenchCommand::ToggleSearch,
+            PanelKind::Problem => LapceWorkbenchCommand::ToggleProblem,
+            PanelKind::Editors => LapceWorkbenchCommand::ToggleEditorsPanel,
+        }
+    }
+
+    /// What to tell about the protocol of the proxy, if it differs from ours,
+    /// and whether it's an error.
+    fn proxy_message(&self, data: &LapceTabData) -> Option<(String, bool)> {
+        let proxy = data.proxy.protocol()?;
+        let ui = ProtocolInfo::current();
+        if !ui.is_compatible(&proxy) {
+            return Some((
+                format!(
+                    "Incompatible proxy protocol: UI {}, proxy {}. Update lapce-proxy on the remote host.",
+                    ui.version, proxy.version
+                ),
+                true,
+            ));
+        }
+        let missing = ui.missing_capabilities(&proxy);
+        if missing.is_empty() {
+            return None;
+        }
+        if self.proxy_rect.contains(self.mouse_pos) {
+            Some((
+                format!(
+                    "The remote proxy is older, unavailable: {}",
+                    missing.join(", ")
+                ),
+                false,
+            ))
+        } else {
+            Some(("Older proxy".to_string(), false))
+        }
+    }
+
+    /// The indentation of the active file, telling when it's set by an
+    /// `.editorconfig` file.
+    fn indentation(data: &LapceTabData) -> Option<String> {
+        let editor = data.main_split.active_editor()?;
+        let path = match &editor.content {
+            BufferContent::File(path) => path,
+            BufferContent::Local(_) => return None,
+        };
+        let editorconfig = &data.main_split.open_files.get(path)?.editorconfig;
+        let indentation = match editorconfig.indent_style {
+            Some(IndentStyle::Tab) => {
+                format!("Tab Size: {}", editorconfig.tab_width.unwrap_or(8))
+            }
+            Some(IndentStyle::Space) => {
+                format!("Spaces: {}", editorconfig.indent_size.unwrap_or(4))
+            }
+            None => return Some("Spaces: 4".to_string()),
+        };
+        Some(format!("{} (from .editorconfig)", indentation))
+    }
+
+    /// The number of matches of the find in the active file, with a `+`
+    /// while the file isn't searched through yet.
+    fn find_count(data: &LapceTabData) -> Option<String> {
+        data.find.search_string.as_ref()?;
+        let editor = data.main_split.active_editor()?;
+        let path = match &editor.content {
+            BufferContent::File(path) => path,
+            BufferContent::Local(_) => return None,
+        };
+        let buffer = data.main_split.open_files.get(path)?;
+        let (count, done) = buffer.find_count(&data.find);
+        Some(format!(
+            "{}{} {}",
+            count,
+            if done { "" } else { "+" },
+            if count == 1 { "match" } else { "matches" }
+        ))
+    }
+
     fn icon_hit_test(&self, mouse_event: &MouseEvent) -> bool {
         for icon in self.panel_icons.iter() {
             if icon.rect.contains(mouse_event.pos) {
                 return true;
             }
         }
-        false
+        self.index_rect.contains(mouse_event.pos)
+            || self.search_rect.contains(mouse_event.pos)
     }
 
-    fn mouse_down(&self, ctx: &mut EventCtx, mouse_event: &MouseEvent) {
+    fn mouse_down(
+        &self,
+        ctx: &mut EventCtx,
+        mouse_event: &MouseEvent,
+        data: &LapceTabData,
+    ) {
         for icon in self.panel_icons.iter() {
             if icon.rect.contains(mouse_event.pos) {
                 ctx.submit_command(icon.command.clone());
             }
         }
+        if self.index_rect.contains(mouse_event.pos) {
+            ctx.submit_command(Command::new(
+                LAPCE_NEW_COMMAND,
+                LapceCommandNew {
+                    cmd: LapceWorkbenchCommand::ConfigureIndexedFolders.to_string(),
+                    data: None,
+                    palette_desc: None,
+                    target: CommandTarget::Workbench,
+                },
+                Target::Widget(data.id),
+            ));
+        }
+        if self.search_rect.contains(mouse_event.pos) {
+            ctx.submit_command(Command::new(
+                LAPCE_NEW_COMMAND,
+                LapceCommandNew {
+                    cmd: LapceWorkbenchCommand::ShowPanel.to_string(),
+                    data: Some(json!(PanelKind::Search)),
+                    palette_desc: None,
+                    target: CommandTarget::Workbench,
+                },
+                Target::Widget(data.id),
+            ));
+        }
     }
 }
 
@@ -139,7 +272,13 @@ impl Widget<LapceTabData> for LapceStatusNew {
                 }
             }
             Event::MouseDown(mouse_event) => {
-                self.mouse_down(ctx, mouse_event);
+                self.mouse_down(ctx, mouse_event, data);
+            }
+            Event::AnimFrame(_) => {
+                if Self::spinner_turns(data) {
+                    ctx.request_paint_rect(self.search_rect);
+                    ctx.request_anim_frame();
+                }
             }
             _ => {}
         }
@@ -185,6 +324,28 @@ impl Widget<LapceTabData> for LapceStatusNew {
             ctx.request_paint();
             return;
         }
+
+        if old_data.palette.index_scope != data.palette.index_scope {
+            ctx.request_paint();
+        }
+
+        if old_data.search.state != data.search.state {
+            ctx.request_paint();
+            if Self::spinner_turns(data) && !Self::spinner_turns(old_data) {
+                self.spinner_start = Instant::now();
+                ctx.request_anim_frame();
+            }
+        }
+
+        if Self::indentation(old_data) != Self::indentation(data) {
+            ctx.request_paint();
+        }
+
+        if old_data.find.search_string != data.find.search_string
+            || old_data.find.case_matching != data.find.case_matching
+        {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -301,6 +462,136 @@ impl Widget<LapceTabData> for LapceStatusNew {
             left += 10.0 + text_layout.size().width;
         }
 
+        self.proxy_rect = Rect::ZERO;
+        if let Some((message, is_error)) = self.proxy_message(data) {
+            let color = if is_error {
+                LapceTheme::LAPCE_ERROR
+            } else {
+                LapceTheme::LAPCE_WARN
+            };
+            let text_layout = ctx
+                .text()
+                .new_text_layout(message)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(data.config.get_color_unchecked(color).clone())
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+            self.proxy_rect = Rect::new(
+                left + 10.0,
+                0.0,
+                left + 10.0 + text_size.width,
+                size.height,
+            );
+            left += 10.0 + text_size.width;
+        }
+
+        self.index_rect = Rect::ZERO;
+        if let Some(status) = data
+            .palette
+            .index_scope
+            .as_ref()
+            .and_then(|scope| scope.status())
+        {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(status)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+            self.index_rect = Rect::new(
+                left + 10.0,
+                0.0,
+                left + 10.0 + text_size.width,
+                size.height,
+            );
+            left += 10.0 + text_size.width;
+        }
+
+        self.search_rect = Rect::ZERO;
+        if let Some(status) = data.search.status() {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(status)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            let mut x = left + 10.0;
+            if data.search.is_searching() {
+                // an open circle turning, standing still without animations
+                let radius = 5.0;
+                let angle = if data.config.ui.animations {
+                    self.spinner_start.elapsed().as_secs_f64()
+                        * 2.0
+                        * std::f64::consts::PI
+                } else {
+                    0.0
+                };
+                ctx.stroke(
+                    Arc {
+                        center: Point::new(x + radius, size.height / 2.0),
+                        radii: Vec2::new(radius, radius),
+                        start_angle: angle,
+                        sweep_angle: std::f64::consts::FRAC_PI_2 * 3.0,
+                        x_rotation: 0.0,
+                    },
+                    data.config.get_color_unchecked(LapceTheme::EDITOR_DIM),
+                    1.5,
+                );
+                x += radius * 2.0 + 5.0;
+            }
+            ctx.draw_text(&text_layout, Point::new(x, 4.0));
+            self.search_rect =
+                Rect::new(left + 10.0, 0.0, x + text_size.width, size.height);
+            left = x + text_size.width;
+        }
+
+        if let Some(count) = Self::find_count(data) {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(count)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+            left += 10.0 + text_size.width;
+        }
+
+        if let Some(indentation) = Self::indentation(data) {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(indentation)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+        }
+
         let icon_padding = (self.height - self.icon_size) / 2.0;
         for icon in self.panel_icons.iter() {
             if icon.rect.contains(self.mouse_pos) {
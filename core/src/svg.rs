@@ -119,6 +119,8 @@ pub fn completion_svg(
         CompletionItemKind::Interface => "interface",
         CompletionItemKind::Snippet => "snippet",
         CompletionItemKind::Module => "namespace",
+        // the words of the files, without a language server
+        CompletionItemKind::Text => "misc",
         _ => "string",
     };
     let theme_str = match kind_str {
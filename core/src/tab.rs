@@ -1,11 +1,11 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::Arc,
     thread,
 };
 
-use directories::ProjectDirs;
+use directories::{ProjectDirs, UserDirs};
 use druid::{
     kurbo::Line,
     piet::{Text, TextLayout, TextLayoutBuilder},
@@ -15,12 +15,13 @@ use druid::{
     WidgetPod, WindowConfig,
 };
 use itertools::Itertools;
-use lsp_types::{CallHierarchyOptions, DiagnosticSeverity};
+use lapce_proxy::buffer::SaveConflict;
+use lsp_types::{CallHierarchyOptions, DiagnosticSeverity, Url};
 
 use crate::{
     activity::ActivityBar,
     buffer::{
-        BufferContent, BufferId, BufferNew, BufferState, BufferUpdate,
+        BufferContent, BufferId, BufferNew, BufferState, BufferUpdate, EditType,
         LocalBufferKind, UpdateEvent,
     },
     code_action::CodeAction,
@@ -38,14 +39,17 @@ use crate::{
     explorer::FileExplorer,
     menu::Menu,
     movement::{self, CursorMode, Selection},
-    palette::{NewPalette, PaletteViewLens},
+    palette::{NewPalette, PaletteType, PaletteViewLens},
     panel::{PanelHeaderKind, PanelPosition, PanelResizePosition},
     plugin::Plugin,
+    profile::Profile,
+    scratch::new_scratch,
     scroll::LapceScrollNew,
     split::LapceSplitNew,
     state::{LapceWorkspace, LapceWorkspaceType},
     status::LapceStatusNew,
     terminal::TerminalPanel,
+    workspace_edit::WorkspaceEditPreview,
 };
 
 pub struct LapceIcon {
@@ -61,6 +65,7 @@ pub struct LapceTabNew {
     completion: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     palette: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     code_action: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+    workspace_edit: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     status: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
     panels:
         HashMap<PanelKind, WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>>,
@@ -72,13 +77,19 @@ pub struct LapceTabNew {
 
 impl LapceTabNew {
     pub fn new(data: &LapceTabData) -> Self {
-        let mut main_split = LapceSplitNew::new(*data.main_split.split_id);
-        for view_id in data.main_split.editors_order.iter() {
+        let mut main_split = LapceSplitNew::new(*data.main_split.split_id)
+            .direction(data.main_split.split_direction);
+        for (view_id, flex) in data
+            .main_split
+            .editors_order
+            .iter()
+            .zip(data.main_split.editors_flex.iter())
+        {
             let editor = data.main_split.editors.get(view_id).unwrap();
             main_split = main_split.with_flex_child(
                 LapceEditorView::new(editor).boxed(),
                 Some(editor.view_id),
-                1.0,
+                *flex,
             );
         }
         let activity = ActivityBar::new();
@@ -92,6 +103,7 @@ impl LapceTabNew {
         );
         let status = LapceStatusNew::new();
         let code_action = CodeAction::new();
+        let workspace_edit = WorkspaceEditPreview::new(&data.workspace_edit);
 
         let mut panels = HashMap::new();
         let file_explorer = FileExplorer::new(&data.file_explorer);
@@ -118,12 +130,16 @@ impl LapceTabNew {
         let problem = data.problem.new_panel();
         panels.insert(PanelKind::Problem, WidgetPod::new(problem.boxed()));
 
+        let editors_panel = data.editors_panel.new_panel();
+        panels.insert(PanelKind::Editors, WidgetPod::new(editors_panel.boxed()));
+
         Self {
             id: data.id,
             activity: WidgetPod::new(activity),
             main_split: WidgetPod::new(main_split.boxed()),
             completion: WidgetPod::new(completion.boxed()),
             code_action: WidgetPod::new(code_action.boxed()),
+            workspace_edit: WidgetPod::new(workspace_edit.boxed()),
             palette: WidgetPod::new(palette.boxed()),
             status: WidgetPod::new(status.boxed()),
             panels,
@@ -266,11 +282,27 @@ impl Widget<LapceTabData> for LapceTabNew {
                     LapceUICommand::LoadBuffer {
                         path,
                         content,
+                        editorconfig,
                         locations,
                     } => {
                         let buffer =
                             data.main_split.open_files.get_mut(path).unwrap();
-                        Arc::make_mut(buffer).load_content(content);
+                        let buffer = Arc::make_mut(buffer);
+                        buffer.editorconfig = editorconfig.clone();
+                        buffer.load_content(content);
+                        // the unsaved changes of a recovered session
+                        if let Some(backup) =
+                            data.main_split.pending_backups.remove(path)
+                        {
+                            let selection = Selection::region(0, buffer.len());
+                            buffer.edit(
+                                ctx,
+                                &selection,
+                                &backup,
+                                data.proxy.clone(),
+                                EditType::Other,
+                            );
+                        }
                         for (view_id, location) in locations {
                             data.main_split.go_to_location(
                                 ctx,
@@ -281,24 +313,191 @@ impl Widget<LapceTabData> for LapceTabNew {
                         }
                         ctx.set_handled();
                     }
+                    LapceUICommand::FindAllDone(path, generation, occurrences) => {
+                        if let Some(buffer) = data.main_split.open_files.get(path) {
+                            buffer.find_all_done(*generation, occurrences);
+                        }
+                        // the highlights and the count are painted from the
+                        // buffer, which the data doesn't see change
+                        ctx.request_paint();
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateSearch(pattern)
+                        if data.search.prefilled.as_ref() == Some(pattern) =>
+                    {
+                        // a prefilled selection waits for an edit or Enter
+                    }
                     LapceUICommand::UpdateSearch(pattern) => {
+                        if data.search.prefilled.is_some() {
+                            Arc::make_mut(&mut data.search).prefilled = None;
+                        }
+                        let options = data.search.options;
                         if pattern == "" {
                             Arc::make_mut(&mut data.find).unset();
+                            if data.search.is_searching() {
+                                data.proxy.cancel_global_search();
+                            }
+                            Arc::make_mut(&mut data.search).clear();
                         } else {
-                            Arc::make_mut(&mut data.find)
-                                .set_find(pattern, false, false, false);
+                            Arc::make_mut(&mut data.find).set_find(
+                                pattern,
+                                options.case_sensitive,
+                                options.regex,
+                                options.whole_word,
+                            );
+                            // the proxy drops the previous search when it gets
+                            // the new one
+                            let search_id = Arc::make_mut(&mut data.search)
+                                .start(pattern.clone());
+                            data.search.global_search(
+                                search_id,
+                                pattern.clone(),
+                                data.search.context_lines(&data.config, &data.proxy),
+                                &data.proxy,
+                                data.id,
+                                ctx.get_external_handle(),
+                            );
                         }
                     }
-                    LapceUICommand::GlobalSearchResult(pattern, matches) => {
-                        let buffer = data
-                            .main_split
-                            .local_buffers
-                            .get(&LocalBufferKind::Search)
-                            .unwrap();
-                        if &buffer.rope.to_string() == pattern {
-                            Arc::make_mut(&mut data.search).matches =
-                                matches.clone();
+                    LapceUICommand::LargeWorkspace(count) => {
+                        Arc::make_mut(&mut data.palette).large_workspace =
+                            Some(*count);
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(
+                                PaletteType::IndexScope,
+                            )),
+                            Target::Widget(data.palette.widget_id),
+                        ));
+                    }
+                    LapceUICommand::SetIndexScope(scope) => {
+                        Arc::make_mut(&mut data.palette).index_scope =
+                            Some(scope.clone());
+                        let _ = data.db.save_workspace_async(data);
+                    }
+                    LapceUICommand::ExportProfile(profile) => {
+                        let profile = profile.clone();
+                        thread::spawn(move || {
+                            let path = UserDirs::new()
+                                .map(|u| u.home_dir().join("lapce-profile.toml"))
+                                .unwrap_or(PathBuf::from("lapce-profile.toml"));
+                            if let Some(file) = tinyfiledialogs::save_file_dialog(
+                                "Export profile",
+                                path.to_str().unwrap_or(""),
+                            ) {
+                                if let Err(e) = profile.save(Path::new(&file)) {
+                                    log::error!(
+                                        "can't export the profile to {}: {}",
+                                        file,
+                                        e
+                                    );
+                                }
+                            }
+                        });
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ImportProfile(profile, replace) => {
+                        let current = Profile::current(
+                            data.installed_plugins.keys().cloned().collect(),
+                        );
+                        let target = profile.apply_to(&current, *replace);
+                        if let Err(e) = target.write() {
+                            log::error!("can't import the profile: {}", e);
+                        }
+                        for name in target.plugins.iter() {
+                            if current.plugins.contains(name) {
+                                continue;
+                            }
+                            match data.plugins.iter().find(|p| &p.name == name) {
+                                Some(plugin) => data.proxy.install_plugin(plugin),
+                                None => log::warn!(
+                                    "plugin {} of the profile isn't available",
+                                    name
+                                ),
+                            }
                         }
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::ReloadConfig,
+                            Target::Auto,
+                        ));
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateSearchInclude(text) => {
+                        if Arc::make_mut(&mut data.search).set_include(text) {
+                            let pattern = data
+                                .main_split
+                                .local_buffers
+                                .get(&LocalBufferKind::Search)
+                                .unwrap()
+                                .rope
+                                .to_string();
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::UpdateSearch(pattern),
+                                Target::Widget(data.id),
+                            ));
+                        }
+                    }
+                    LapceUICommand::UpdateSearchReplace(text) => {
+                        Arc::make_mut(&mut data.search).set_replace(text);
+                    }
+                    LapceUICommand::SearchMatchesReplaced(search_id, path) => {
+                        Arc::make_mut(&mut data.search)
+                            .remove_matches(*search_id, path);
+                    }
+                    LapceUICommand::SearchFilesRefreshed(
+                        search_id,
+                        paths,
+                        matches,
+                        context,
+                    ) => {
+                        let search = Arc::make_mut(&mut data.search);
+                        for path in paths {
+                            search.refresh_file(
+                                *search_id,
+                                path,
+                                matches.get(path).cloned().unwrap_or_default(),
+                                context.get(path).cloned().unwrap_or_default(),
+                                0,
+                            );
+                        }
+                    }
+                    LapceUICommand::GlobalSearchResultAdded(
+                        search_id,
+                        path,
+                        matches,
+                        context,
+                    ) => {
+                        let search = Arc::make_mut(&mut data.search);
+                        search.add_matches(
+                            *search_id,
+                            path.clone(),
+                            matches.clone(),
+                            context.clone(),
+                        );
+                        search.record_buffer(
+                            path,
+                            data.main_split.open_files.get(path).map(|b| &**b),
+                        );
+                    }
+                    LapceUICommand::GlobalSearchError(search_id, message) => {
+                        Arc::make_mut(&mut data.search)
+                            .fail(*search_id, message.clone());
+                    }
+                    LapceUICommand::GlobalSearchResult(search_id, matches) => {
+                        let search = Arc::make_mut(&mut data.search);
+                        search.finish(*search_id, matches);
+                        for path in matches.keys() {
+                            search.record_buffer(
+                                path,
+                                data.main_split.open_files.get(path).map(|b| &**b),
+                            );
+                        }
+                    }
+                    LapceUICommand::PinnedSearchCount(pin, count) => {
+                        Arc::make_mut(&mut data.search)
+                            .set_pinned_count(pin, *count);
                     }
                     LapceUICommand::LoadBufferHead { path, id, content } => {
                         let buffer =
@@ -315,6 +514,54 @@ impl Widget<LapceTabData> for LapceTabNew {
                             Arc::make_mut(&mut terminal).title = title.to_string();
                         }
                     }
+                    LapceUICommand::TerminalRename(term_id, name) => {
+                        let terminal_panel = Arc::make_mut(&mut data.terminal);
+                        if let Some(terminal) =
+                            terminal_panel.terminals.get_mut(term_id)
+                        {
+                            let name = name.trim();
+                            Arc::make_mut(terminal).user_title = if name.is_empty() {
+                                None
+                            } else {
+                                Some(name.to_string())
+                            };
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::TerminalPasteChoice(term_id, text) => {
+                        let terminal_panel = Arc::make_mut(&mut data.terminal);
+                        if let Some(terminal) =
+                            terminal_panel.terminals.get_mut(term_id)
+                        {
+                            Arc::make_mut(terminal).pending_paste =
+                                Some(Arc::new(text.to_string()));
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::RunPalette(Some(
+                                    PaletteType::TerminalPaste,
+                                )),
+                                Target::Widget(data.palette.widget_id),
+                            ));
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::TerminalPaste(term_id, mode) => {
+                        let terminal_panel = Arc::make_mut(&mut data.terminal);
+                        if let Some(terminal) =
+                            terminal_panel.terminals.get_mut(term_id)
+                        {
+                            let terminal = Arc::make_mut(terminal);
+                            terminal.paste_mode = Some(*mode);
+                            if let Some(text) = terminal.pending_paste.take() {
+                                terminal.paste_as(&text, *mode);
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::TerminalExited(term_id, exit_code) => {
+                        data.terminal_exited(ctx, *term_id, *exit_code);
+                        ctx.set_handled();
+                    }
                     LapceUICommand::CloseTerminal(id) => {
                         let terminal_panel = Arc::make_mut(&mut data.terminal);
                         if let Some(terminal) = terminal_panel.terminals.get_mut(id)
@@ -334,24 +581,25 @@ impl Widget<LapceTabData> for LapceTabNew {
                     LapceUICommand::UpdateInstalledPlugins(plugins) => {
                         data.installed_plugins = Arc::new(plugins.to_owned());
                     }
+                    LapceUICommand::UpdateProxyStatus(status) => {
+                        data.proxy_status = *status;
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateProxyProtocol(_) => {
+                        // features the proxy lacks are hidden now
+                        ctx.request_layout();
+                        ctx.set_handled();
+                    }
                     LapceUICommand::UpdateDiffInfo(diff) => {
+                        data.palette
+                            .refresh_todos(ctx, &data.config.editor.todo_tokens);
+                        data.files_changed(ctx);
                         let source_control = Arc::make_mut(&mut data.source_control);
                         source_control.branch = diff.head.to_string();
                         source_control.branches = diff.branches.clone();
-                        source_control.file_diffs = diff
-                            .diffs
-                            .iter()
-                            .map(|diff| {
-                                let mut checked = true;
-                                for (p, c) in source_control.file_diffs.iter() {
-                                    if p == diff {
-                                        checked = *c;
-                                        break;
-                                    }
-                                }
-                                (diff.clone(), checked)
-                            })
-                            .collect();
+                        source_control.ahead_behind = diff.ahead_behind;
+                        source_control
+                            .set_diffs(diff.diffs.clone(), diff.staged.clone());
 
                         for (path, buffer) in data.main_split.open_files.iter() {
                             buffer.retrieve_file_head(
@@ -432,20 +680,74 @@ impl Widget<LapceTabData> for LapceTabNew {
 
                         ctx.set_handled();
                     }
-                    LapceUICommand::DocumentFormatAndSave(path, rev, result) => {
-                        data.main_split
-                            .document_format_and_save(ctx, path, *rev, result);
+                    LapceUICommand::DocumentFormatAndSave(
+                        path,
+                        rev,
+                        region,
+                        result,
+                    ) => {
+                        data.main_split.document_format_and_save(
+                            ctx, path, *rev, region, result,
+                        );
                         ctx.set_handled();
                     }
-                    LapceUICommand::DocumentFormat(path, rev, result) => {
-                        data.main_split.document_format(ctx, path, *rev, result);
+                    LapceUICommand::DocumentFormat(path, rev, region, result) => {
+                        data.main_split
+                            .document_format(ctx, path, *rev, region, result);
                         ctx.set_handled();
                     }
                     LapceUICommand::BufferSave(path, rev) => {
                         let buffer =
                             data.main_split.open_files.get_mut(path).unwrap();
                         if buffer.rev == *rev {
-                            Arc::make_mut(buffer).dirty = false;
+                            let buffer = Arc::make_mut(buffer);
+                            buffer.dirty = false;
+                            buffer.clear_save_conflict();
+                            buffer.clear_backup();
+                            for (_, editor) in data.main_split.editors.iter_mut() {
+                                if editor.content == buffer.content
+                                    && editor.compare.as_deref() == Some("disk")
+                                {
+                                    Arc::make_mut(editor).compare = None;
+                                }
+                            }
+                        }
+                        data.palette
+                            .refresh_todos(ctx, &data.config.editor.todo_tokens);
+                        data.files_changed(ctx);
+                        if data.search.matches.contains_key(path) {
+                            Arc::make_mut(&mut data.search).mark_stale(path);
+                            if data.config.search.auto_refresh {
+                                data.refresh_search(ctx);
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::SaveScratch(path) => {
+                        if let Some(buffer) = data.main_split.open_files.get(path) {
+                            let path = path.clone();
+                            data.proxy.save(
+                                buffer.rev,
+                                buffer.id,
+                                Box::new(move |result| {
+                                    if let Err(e) = result {
+                                        log::error!(
+                                            "can't save the scratch {:?}: {}",
+                                            path,
+                                            e
+                                        );
+                                    }
+                                }),
+                            );
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::BufferSaveConflict(path, conflict, disk) => {
+                        if let Some(buffer) =
+                            data.main_split.open_files.get_mut(path)
+                        {
+                            Arc::make_mut(buffer)
+                                .set_save_conflict(*conflict, disk.as_deref());
                         }
                         ctx.set_handled();
                     }
@@ -476,6 +778,7 @@ impl Widget<LapceTabData> for LapceTabNew {
                                 position: None,
                                 scroll_offset: None,
                                 hisotry: Some(history.to_string()),
+                                selection_end: None,
                             },
                             &data.config,
                         );
@@ -487,6 +790,7 @@ impl Widget<LapceTabData> for LapceTabNew {
                         ctx.set_handled();
                     }
                     LapceUICommand::OpenFile(path) => {
+                        data.record_file(path);
                         let editor_view_id = data.main_split.active.clone();
                         data.main_split.jump_to_location(
                             ctx,
@@ -496,9 +800,81 @@ impl Widget<LapceTabData> for LapceTabNew {
                                 position: None,
                                 scroll_offset: None,
                                 hisotry: None,
+                                selection_end: None,
                             },
                             &data.config,
                         );
+                        // kept fresh for a session that doesn't end cleanly
+                        let _ = data.db.save_workspace_async(data);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::SplitMoveOut(direction) => {
+                        data.split_move_out(ctx, direction);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::RevealInFileExplorer(path) => {
+                        data.show_panel(ctx, PanelKind::FileExplorer);
+                        let tab_id = data.id;
+                        let proxy = data.proxy.clone();
+                        Arc::make_mut(&mut data.file_explorer).reveal(
+                            path,
+                            tab_id,
+                            proxy,
+                            ctx.get_external_handle(),
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::NewScratchBuffer(extension)
+                        if data.workspace.kind != LapceWorkspaceType::Local =>
+                    {
+                        data.main_split.new_proxy_file(ctx, extension);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::NewScratchBuffer(extension) => {
+                        match new_scratch(&data.workspace, extension) {
+                            Ok(path) => {
+                                let editor_view_id = data.main_split.active.clone();
+                                let editor_view_id =
+                                    data.main_split.jump_to_location(
+                                        ctx,
+                                        *editor_view_id,
+                                        EditorLocationNew {
+                                            path,
+                                            position: None,
+                                            scroll_offset: None,
+                                            hisotry: None,
+                                            selection_end: None,
+                                        },
+                                        &data.config,
+                                    );
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::Focus,
+                                    Target::Widget(editor_view_id),
+                                ));
+                            }
+                            Err(e) => {
+                                log::error!("create scratch buffer error {}", e);
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateLogView => {
+                        // nothing fetches the lines without a log view, so no
+                        // more of these come until one is opened
+                        if data.main_split.editors.values().any(|e| {
+                            e.content == BufferContent::Local(LocalBufferKind::Log)
+                        }) {
+                            data.main_split.refresh_log_view(ctx);
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ShowEnvironment(report) => {
+                        open_report(ctx, data, "environment.txt", report);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ShowWatcherStats(report) => {
+                        open_report(ctx, data, "watcher-stats.txt", report);
                         ctx.set_handled();
                     }
                     LapceUICommand::GoToLocationNew(editor_view_id, location) => {
@@ -520,6 +896,10 @@ impl Widget<LapceTabData> for LapceTabNew {
                         ctx.set_handled();
                     }
                     LapceUICommand::JumpToLocation(editor_view_id, location) => {
+                        // previews in the palette's editor aren't opened
+                        if editor_view_id.is_none() {
+                            data.record_file(&location.path);
+                        }
                         data.main_split.jump_to_location(
                             ctx,
                             *editor_view_id,
@@ -528,6 +908,34 @@ impl Widget<LapceTabData> for LapceTabNew {
                         );
                         ctx.set_handled();
                     }
+                    LapceUICommand::JumpToLocationToSide(location) => {
+                        data.record_file(&location.path);
+                        data.main_split.jump_to_location_to_side(
+                            ctx,
+                            location.clone(),
+                            &data.config,
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::PreviewLocation(location, keep) => {
+                        data.record_file(&location.path);
+                        data.main_split.preview_location(
+                            ctx,
+                            location.clone(),
+                            *keep,
+                            &data.config,
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ShowShownFile(editor_view_id, index) => {
+                        data.main_split.show_shown_file(
+                            ctx,
+                            *editor_view_id,
+                            *index,
+                            &data.config,
+                        );
+                        ctx.set_handled();
+                    }
                     LapceUICommand::JumpToLine(editor_view_id, line) => {
                         data.main_split.jump_to_line(
                             ctx,
@@ -616,6 +1024,7 @@ impl Widget<LapceTabData> for LapceTabNew {
                                         position: Some(l.range.start.clone()),
                                         scroll_offset: None,
                                         hisotry: None,
+                                        selection_end: None,
                                     })
                                     .collect();
                                 ctx.submit_command(Command::new(
@@ -627,6 +1036,82 @@ impl Widget<LapceTabData> for LapceTabNew {
                         }
                         ctx.set_handled();
                     }
+                    LapceUICommand::UpdateEditorconfig(id, editorconfig) => {
+                        for (_, buffer) in data.main_split.open_files.iter_mut() {
+                            if &buffer.id == id {
+                                Arc::make_mut(buffer).editorconfig =
+                                    editorconfig.clone();
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::RefreshExplorer => {
+                        data.file_explorer.refresh(
+                            data.id,
+                            data.proxy.clone(),
+                            ctx.get_external_handle(),
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::FileChanges(changes) => {
+                        // a bulk batch doesn't list the paths, so everything
+                        // is read again
+                        if changes.bulk {
+                            data.file_explorer.refresh(
+                                data.id,
+                                data.proxy.clone(),
+                                ctx.get_external_handle(),
+                            );
+                        } else {
+                            let dirs = changes
+                                .created
+                                .iter()
+                                .chain(changes.removed.iter())
+                                .filter_map(|path| path.parent())
+                                .map(|dir| dir.to_path_buf())
+                                .collect::<HashSet<PathBuf>>();
+                            data.file_explorer.refresh_dirs(
+                                dirs.into_iter().collect(),
+                                data.id,
+                                data.proxy.clone(),
+                                ctx.get_external_handle(),
+                            );
+                        }
+                        data.files_changed(ctx);
+                        if Arc::make_mut(&mut data.search).mark_changed(changes)
+                            && data.config.search.auto_refresh
+                        {
+                            data.refresh_search(ctx);
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::FileDeleted(id, deleted) => {
+                        for (_, buffer) in data.main_split.open_files.iter_mut() {
+                            if &buffer.id == id && buffer.deleted != *deleted {
+                                Arc::make_mut(buffer).deleted = *deleted;
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::FileChanged(id, content) => {
+                        for (_, buffer) in data.main_split.open_files.iter_mut() {
+                            if &buffer.id == id {
+                                let buffer = Arc::make_mut(buffer);
+                                if buffer.rope.to_string() == *content {
+                                    buffer.clear_save_conflict();
+                                } else if buffer.dirty {
+                                    buffer.set_save_conflict(
+                                        SaveConflict::ChangedOnDisk,
+                                        Some(content.as_str()),
+                                    );
+                                } else {
+                                    buffer.set_changed_on_disk(content);
+                                }
+                                break;
+                            }
+                        }
+                        ctx.set_handled();
+                    }
                     LapceUICommand::ReloadBuffer(id, rev, new_content) => {
                         for (_, buffer) in data.main_split.open_files.iter_mut() {
                             if &buffer.id == id {
@@ -664,10 +1149,20 @@ impl Widget<LapceTabData> for LapceTabNew {
                                             }
                                         }
                                     }
+                                } else {
+                                    // reloaded while edits were on their way
+                                    // to the proxy, which didn't take them
+                                    Arc::make_mut(buffer).set_save_conflict(
+                                        SaveConflict::ChangedOnDisk,
+                                        Some(new_content.as_str()),
+                                    );
                                 }
                                 break;
                             }
                         }
+                        data.palette
+                            .refresh_todos(ctx, &data.config.editor.todo_tokens);
+                        data.files_changed(ctx);
                         ctx.set_handled();
                     }
                     LapceUICommand::UpdateSemanticTokens(id, path, rev, tokens) => {
@@ -695,10 +1190,50 @@ impl Widget<LapceTabData> for LapceTabNew {
                         }
                         ctx.set_handled();
                     }
+                    LapceUICommand::UpdateDocumentSymbols(path, rev, symbols) => {
+                        if let Some(buffer) =
+                            data.main_split.open_files.get_mut(path)
+                        {
+                            Arc::make_mut(buffer).document_symbols =
+                                Some((*rev, symbols.clone()));
+                        }
+                        ctx.set_handled();
+                    }
                     LapceUICommand::ShowCodeActions
                     | LapceUICommand::CancelCodeActions => {
                         self.code_action.event(ctx, event, data, env);
                     }
+                    LapceUICommand::PreviewWorkspaceEdit(title, edits) => {
+                        data.preview_workspace_edit(ctx, title, edits);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::WorkspaceEditFileRead(id, path, result) => {
+                        data.workspace_edit_file_read(
+                            ctx,
+                            *id,
+                            path,
+                            result.clone(),
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ApplyWorkspaceEdit => {
+                        data.apply_workspace_edit(ctx);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::WorkspaceEditApplied(id, path, result) => {
+                        data.workspace_edit_applied(ctx, *id, path, result.clone());
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::CancelWorkspaceEdit => {
+                        data.close_workspace_edit(ctx);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::OpenUrl(url) => {
+                        if let Err(e) = open_url(url) {
+                            log::error!("can't open {}: {}", url, e);
+                        }
+                        ctx.set_handled();
+                    }
                     LapceUICommand::Focus => {
                         let dir = data
                             .workspace
@@ -708,8 +1243,8 @@ impl Widget<LapceTabData> for LapceTabNew {
                                 let dir = p.file_name().unwrap().to_str().unwrap();
                                 let dir = match &data.workspace.kind {
                                     LapceWorkspaceType::Local => dir.to_string(),
-                                    LapceWorkspaceType::RemoteSSH(user, host) => {
-                                        format!("{} [{}@{}]", dir, user, host)
+                                    LapceWorkspaceType::RemoteSSH(host) => {
+                                        format!("{} [{}]", dir, host)
                                     }
                                 };
                                 dir
@@ -757,7 +1292,7 @@ impl Widget<LapceTabData> for LapceTabNew {
                         ctx.set_handled();
                     }
                     LapceUICommand::FocusEditor => {
-                        if let Some(active) = *data.main_split.active {
+                        if let Some(active) = data.main_split.last_focused_editor() {
                             ctx.submit_command(Command::new(
                                 LAPCE_UI_COMMAND,
                                 LapceUICommand::Focus,
@@ -778,6 +1313,39 @@ impl Widget<LapceTabData> for LapceTabNew {
                             .update_syntax_tree(*rev, tree.to_owned());
                         ctx.set_handled();
                     }
+                    LapceUICommand::RevertDiffHunk(path, hunk) => {
+                        ctx.set_handled();
+                        // nothing to revert once the text changed it
+                        let revert = data.main_split.open_files.get(path).and_then(
+                            |buffer| {
+                                let old_text = buffer.diff_hunk_old_text(hunk)?;
+                                let selection = Selection::region(
+                                    buffer.offset_of_line(hunk.lines.start),
+                                    buffer.offset_of_line(hunk.lines.end),
+                                );
+                                Some((selection, old_text))
+                            },
+                        );
+                        if let Some((selection, old_text)) = revert {
+                            data.main_split.edit(
+                                ctx,
+                                path,
+                                vec![(&selection, &old_text)],
+                                EditType::Other,
+                            );
+                        }
+                    }
+                    LapceUICommand::CopyDiffHunk(path, hunk) => {
+                        ctx.set_handled();
+                        if let Some(old_text) = data
+                            .main_split
+                            .open_files
+                            .get(path)
+                            .and_then(|buffer| buffer.diff_hunk_old_text(hunk))
+                        {
+                            Application::global().clipboard().put_string(old_text);
+                        }
+                    }
                     LapceUICommand::UpdateHisotryChanges {
                         id,
                         path,
@@ -813,21 +1381,8 @@ impl Widget<LapceTabData> for LapceTabNew {
                             .insert(history.to_string(), HashMap::new());
                     }
                     LapceUICommand::UpdateExplorerItems(index, path, items) => {
-                        let file_explorer = Arc::make_mut(&mut data.file_explorer);
-                        if let Some(node) = file_explorer.get_node_mut(path) {
-                            node.children = items
-                                .iter()
-                                .map(|item| (item.path_buf.clone(), item.clone()))
-                                .collect();
-                            node.read = true;
-                            node.open = true;
-                            node.children_open_count = node.children.len();
-                        }
-                        if let Some(paths) = file_explorer.node_tree(path) {
-                            for path in paths.iter() {
-                                file_explorer.update_node_count(path);
-                            }
-                        }
+                        Arc::make_mut(&mut data.file_explorer)
+                            .update_children(path, items.clone());
                         ctx.set_handled();
                     }
                     _ => (),
@@ -835,10 +1390,12 @@ impl Widget<LapceTabData> for LapceTabNew {
             }
             _ => (),
         }
+        self.workspace_edit.event(ctx, event, data, env);
         self.palette.event(ctx, event, data, env);
         self.completion.event(ctx, event, data, env);
         self.code_action.event(ctx, event, data, env);
         self.main_split.event(ctx, event, data, env);
+        data.main_split.sync_narrowed(ctx);
         self.status.event(ctx, event, data, env);
         for (_, panel) in data.panels.clone().iter() {
             if panel.is_shown() {
@@ -848,7 +1405,9 @@ impl Widget<LapceTabData> for LapceTabNew {
                     .event(ctx, event, data, env);
             }
         }
-        self.activity.event(ctx, event, data, env);
+        if !data.chrome_hidden {
+            self.activity.event(ctx, event, data, env);
+        }
     }
 
     fn lifecycle(
@@ -862,6 +1421,7 @@ impl Widget<LapceTabData> for LapceTabNew {
         self.activity.lifecycle(ctx, event, data, env);
         self.main_split.lifecycle(ctx, event, data, env);
         self.code_action.lifecycle(ctx, event, data, env);
+        self.workspace_edit.lifecycle(ctx, event, data, env);
         self.status.lifecycle(ctx, event, data, env);
         self.completion.lifecycle(ctx, event, data, env);
 
@@ -893,7 +1453,9 @@ impl Widget<LapceTabData> for LapceTabNew {
             ctx.request_layout();
         }
 
-        if !old_data.config.same(&data.config) {
+        if !old_data.config.same(&data.config)
+            || old_data.chrome_hidden != data.chrome_hidden
+        {
             ctx.request_layout();
         }
 
@@ -902,6 +1464,7 @@ impl Widget<LapceTabData> for LapceTabNew {
         self.main_split.update(ctx, data, env);
         self.completion.update(ctx, data, env);
         self.code_action.update(ctx, data, env);
+        self.workspace_edit.update(ctx, data, env);
         self.status.update(ctx, data, env);
         for (_, panel) in data.panels.iter() {
             if panel.is_shown() {
@@ -924,7 +1487,13 @@ impl Widget<LapceTabData> for LapceTabNew {
         let self_size = bc.max();
         self.height = self_size.height;
 
-        let activity_size = self.activity.layout(ctx, bc, data, env);
+        let activity_size = if data.chrome_hidden {
+            self.activity
+                .layout(ctx, &BoxConstraints::tight(Size::ZERO), data, env);
+            Size::ZERO
+        } else {
+            self.activity.layout(ctx, bc, data, env)
+        };
         self.activity.set_origin(ctx, data, env, Point::ZERO);
 
         let status_size = self.status.layout(ctx, bc, data, env);
@@ -1186,6 +1755,17 @@ impl Widget<LapceTabData> for LapceTabNew {
             Point::new((self_size.width - palette_size.width) / 2.0, 0.0),
         );
 
+        let workspace_edit_size = self.workspace_edit.layout(ctx, bc, data, env);
+        self.workspace_edit.set_origin(
+            ctx,
+            data,
+            env,
+            Point::new(
+                (self_size.width - workspace_edit_size.width) / 2.0,
+                (self_size.height - workspace_edit_size.height) / 2.0,
+            ),
+        );
+
         self_size
     }
 
@@ -1228,7 +1808,9 @@ impl Widget<LapceTabData> for LapceTabNew {
                 }
             }
         }
-        self.activity.paint(ctx, data, env);
+        if !data.chrome_hidden {
+            self.activity.paint(ctx, data, env);
+        }
         // if let Some((active_index, (id, kind))) =
         //     data.panels.get(&PanelPosition::LeftTop).and_then(|panel| {
         //         panel
@@ -1255,6 +1837,7 @@ impl Widget<LapceTabData> for LapceTabNew {
         self.completion.paint(ctx, data, env);
         self.code_action.paint(ctx, data, env);
         self.palette.paint(ctx, data, env);
+        self.workspace_edit.paint(ctx, data, env);
     }
 }
 
@@ -1381,8 +1964,8 @@ impl Widget<LapceTabData> for LapceTabHeader {
                 let dir = p.file_name().unwrap().to_str().unwrap();
                 let dir = match &data.workspace.kind {
                     LapceWorkspaceType::Local => dir.to_string(),
-                    LapceWorkspaceType::RemoteSSH(user, host) => {
-                        format!("{} [{}@{}]", dir, user, host)
+                    LapceWorkspaceType::RemoteSSH(host) => {
+                        format!("{} [{}]", dir, host)
                     }
                 };
                 dir
@@ -1434,3 +2017,86 @@ impl Widget<LapceTabData> for LapceTabHeader {
         }
     }
 }
+
+/// Opens a report of the proxy, written to a file in the config dir so that
+/// it opens like the settings.
+fn open_report(
+    ctx: &mut EventCtx,
+    data: &mut LapceTabData,
+    file_name: &str,
+    report: &str,
+) {
+    let proj_dirs = match ProjectDirs::from("", "", "Lapce") {
+        Some(proj_dirs) => proj_dirs,
+        None => return,
+    };
+    let path = proj_dirs.config_dir().join(file_name);
+    if let Err(e) = std::fs::create_dir_all(proj_dirs.config_dir())
+        .and_then(|_| std::fs::write(&path, report))
+    {
+        log::error!("can't write {:?}: {}", path, e);
+        return;
+    }
+    let editor_view_id = data.main_split.active.clone();
+    data.main_split.jump_to_location(
+        ctx,
+        *editor_view_id,
+        EditorLocationNew {
+            path,
+            position: None,
+            scroll_offset: None,
+            hisotry: None,
+            selection_end: None,
+        },
+        &data.config,
+    );
+}
+
+/// The URL as a web page to open. The links come from the language servers,
+/// so other schemes, like `file:`, aren't opened.
+fn web_url(url: &str) -> anyhow::Result<Url> {
+    let url = Url::parse(url)?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(anyhow::anyhow!("won't open a {} URL", scheme)),
+    }
+}
+
+/// Opens the web page with the default browser of the OS. On Windows, the
+/// URL doesn't go through `cmd`, which would parse its `&` and `|`.
+fn open_url(url: &str) -> anyhow::Result<()> {
+    let url = web_url(url)?;
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = std::process::Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = std::process::Command::new("xdg-open");
+    let mut child = command.arg(url.as_str()).spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_url() {
+        assert_eq!(
+            web_url("https://example.com/rules?a=1&b=2")
+                .unwrap()
+                .as_str(),
+            "https://example.com/rules?a=1&b=2"
+        );
+        assert!(web_url("http://example.com").is_ok());
+        assert!(web_url("file:///etc/passwd").is_err());
+        assert!(web_url("javascript:alert(1)").is_err());
+        assert!(web_url("calc.exe & whoami").is_err());
+        assert!(web_url("").is_err());
+    }
+}
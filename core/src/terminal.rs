@@ -24,11 +24,12 @@ use alacritty_terminal::{
 use anyhow::Result;
 use crossbeam_channel::{Receiver, Sender};
 use druid::{
+    kurbo::Line,
     piet::{Text, TextAttribute, TextLayout, TextLayoutBuilder},
     Application, BoxConstraints, Color, Command, Data, Env, Event, EventCtx,
     ExtEventSink, FontFamily, FontWeight, KbKey, LayoutCtx, LifeCycle, LifeCycleCtx,
-    Modifiers, MouseEvent, PaintCtx, Point, Rect, Region, RenderContext, Size,
-    Target, UpdateCtx, Widget, WidgetExt, WidgetId, WidgetPod,
+    Modifiers, MouseButton, MouseEvent, PaintCtx, Point, Rect, Region,
+    RenderContext, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetId, WidgetPod,
 };
 use hashbrown::HashMap;
 use itertools::Itertools;
@@ -46,11 +47,12 @@ use crate::{
     data::{FocusArea, LapceTabData, PanelKind},
     find::Find,
     keypress::KeyPressFocus,
+    menu::MenuItem,
     movement::{LinePosition, Movement},
     palette::{NewPaletteItem, PaletteItem, PaletteItemContent},
     proxy::LapceProxy,
     scroll::LapcePadding,
-    split::{LapceSplitNew, SplitMoveDirection},
+    split::{LapceSplitNew, NestedSplit, SplitMoveDirection},
     state::{Counter, LapceWorkspace, LapceWorkspaceType, Mode, VisualMode},
     svg::get_svg,
     tab::LapceIcon,
@@ -63,14 +65,31 @@ const CTRL_CHARS: &'static [char] = &[
 
 pub type TermConfig = alacritty_terminal::config::Config<HashMap<String, String>>;
 
+/// A tab of the terminal panel, with its own split of terminals.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerminalTab {
+    /// The split the terminals of the tab are in.
+    pub split_id: WidgetId,
+    /// The terminal last focused in the tab, focused again when the tab is.
+    pub active_term_id: Option<TermId>,
+}
+
 #[derive(Clone)]
 pub struct TerminalSplitData {
     pub active: WidgetId,
     pub active_term_id: TermId,
     pub widget_id: WidgetId,
-    pub split_id: WidgetId,
+    /// The tabs of the panel, in the order of the tab strip. There's always
+    /// one at least.
+    pub tabs: Arc<Vec<TerminalTab>>,
+    pub active_tab: usize,
     pub terminals: im::HashMap<TermId, Arc<LapceTerminalData>>,
+    /// The children of the splits nested in the terminal panel.
+    pub nested_splits: im::HashMap<WidgetId, NestedSplit>,
     pub indexed_colors: Arc<HashMap<u8, Color>>,
+    /// The text last selected with the mouse in a terminal, which a middle
+    /// click pastes, like the primary selection of X11.
+    pub primary_selection: Option<Arc<String>>,
 }
 
 impl TerminalSplitData {
@@ -82,9 +101,103 @@ impl TerminalSplitData {
             active_term_id: TermId::next(),
             active: WidgetId::next(),
             widget_id: WidgetId::next(),
-            split_id,
+            tabs: Arc::new(vec![TerminalTab {
+                split_id,
+                active_term_id: None,
+            }]),
+            active_tab: 0,
             terminals,
+            nested_splits: im::HashMap::new(),
             indexed_colors: Arc::new(Self::get_indexed_colors()),
+            primary_selection: None,
+        }
+    }
+
+    /// The split of the terminals of the active tab.
+    pub fn split_id(&self) -> WidgetId {
+        self.tabs[self.active_tab].split_id
+    }
+
+    /// The index of the tab whose terminals are in the split `split_id`.
+    pub fn tab_index(&self, split_id: WidgetId) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.split_id == split_id)
+    }
+
+    /// Adds an empty tab after the active one and makes it the active one.
+    /// Returns the split of its terminals.
+    pub fn add_tab(&mut self) -> WidgetId {
+        let split_id = WidgetId::next();
+        let index = (self.active_tab + 1).min(self.tabs.len());
+        Arc::make_mut(&mut self.tabs).insert(
+            index,
+            TerminalTab {
+                split_id,
+                active_term_id: None,
+            },
+        );
+        self.active_tab = index;
+        split_id
+    }
+
+    /// Removes the tab with its terminals, and returns the terminals. When
+    /// it was the active one, the tab after it becomes active.
+    pub fn remove_tab(&mut self, split_id: WidgetId) -> Vec<Arc<LapceTerminalData>> {
+        let index = match self.tab_index(split_id) {
+            Some(index) if self.tabs.len() > 1 => index,
+            _ => return Vec::new(),
+        };
+        Arc::make_mut(&mut self.tabs).remove(index);
+        if self.active_tab > index || self.active_tab >= self.tabs.len() {
+            self.active_tab -= 1;
+        }
+        let terminals: Vec<Arc<LapceTerminalData>> = self
+            .terminals
+            .values()
+            .filter(|t| t.tab_id == split_id)
+            .cloned()
+            .collect();
+        for terminal in terminals.iter() {
+            self.terminals.remove(&terminal.term_id);
+            self.nested_splits.remove(&terminal.split_id);
+        }
+        terminals
+    }
+
+    /// The terminal to focus in the tab: the one last focused in it, or else
+    /// any of its terminals.
+    pub fn tab_terminal(&self, index: usize) -> Option<&Arc<LapceTerminalData>> {
+        let tab = self.tabs.get(index)?;
+        tab.active_term_id
+            .and_then(|term_id| self.terminals.get(&term_id))
+            .filter(|t| t.tab_id == tab.split_id)
+            .or_else(|| self.terminals.values().find(|t| t.tab_id == tab.split_id))
+    }
+
+    /// Makes the terminal the active one, and its tab.
+    pub fn set_active_terminal(&mut self, term_id: TermId, widget_id: WidgetId) {
+        self.active = widget_id;
+        self.active_term_id = term_id;
+        let index = self
+            .terminals
+            .get(&term_id)
+            .and_then(|t| self.tab_index(t.tab_id));
+        if let Some(index) = index {
+            self.active_tab = index;
+            Arc::make_mut(&mut self.tabs)[index].active_term_id = Some(term_id);
+        }
+    }
+
+    /// What the tab strip shows for the tab: its number, with the title of
+    /// its terminal when it has one.
+    pub fn tab_title(&self, index: usize) -> String {
+        match self
+            .tab_terminal(index)
+            .filter(|t| !t.display_title().is_empty())
+        {
+            Some(terminal) => {
+                format!("{}: {}", index + 1, terminal.display_title())
+            }
+            None => format!("{}", index + 1),
         }
     }
 
@@ -396,6 +509,13 @@ impl KeyPressFocus for LapceTerminalViewData {
                     Target::Widget(self.terminal.split_id),
                 ));
             }
+            LapceCommand::SplitHorizontal => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitTerminal(false, self.terminal.widget_id),
+                    Target::Widget(self.terminal.split_id),
+                ));
+            }
             LapceCommand::SplitLeft => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -416,6 +536,26 @@ impl KeyPressFocus for LapceTerminalViewData {
                     Target::Widget(self.terminal.split_id),
                 ));
             }
+            LapceCommand::SplitUp => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEditorMove(
+                        SplitMoveDirection::Up,
+                        self.terminal.widget_id,
+                    ),
+                    Target::Widget(self.terminal.split_id),
+                ));
+            }
+            LapceCommand::SplitDown => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEditorMove(
+                        SplitMoveDirection::Down,
+                        self.terminal.widget_id,
+                    ),
+                    Target::Widget(self.terminal.split_id),
+                ));
+            }
             LapceCommand::SplitExchange => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -423,6 +563,36 @@ impl KeyPressFocus for LapceTerminalViewData {
                     Target::Widget(self.terminal.split_id),
                 ));
             }
+            LapceCommand::SplitClose => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitTerminalClose(
+                        self.terminal.term_id,
+                        self.terminal.widget_id,
+                    ),
+                    Target::Widget(self.terminal.split_id),
+                ));
+                self.terminal.proxy.terminal_close(self.terminal.term_id);
+            }
+            LapceCommand::ClearTerminal => {
+                {
+                    let mut raw = self.terminal.raw.lock();
+                    let term = &mut raw.term;
+                    self.terminal.clear_selection(term);
+                    term.clear_screen(ansi::ClearMode::Saved);
+                }
+                // the shell clears the screen and prints its prompt again
+                if self.terminal.mode == Mode::Terminal {
+                    self.receive_char(ctx, "\x0c");
+                }
+            }
+            LapceCommand::SplitEvenSize => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SplitEvenSize,
+                    Target::Widget(self.terminal.split_id),
+                ));
+            }
             LapceCommand::ClipboardCopy => {
                 if self.terminal.mode == Mode::Visual {
                     self.terminal_mut().mode = Mode::Normal;
@@ -435,8 +605,20 @@ impl KeyPressFocus for LapceTerminalViewData {
                 self.terminal.clear_selection(term);
             }
             LapceCommand::ClipboardPaste => {
+                if self.terminal.mode != Mode::Terminal {
+                    return CommandExecuted::Yes;
+                }
                 if let Some(s) = Application::global().clipboard().get_string() {
-                    self.receive_char(ctx, &s);
+                    if !self.terminal.paste(&s) {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::TerminalPasteChoice(
+                                self.terminal.term_id,
+                                s,
+                            ),
+                            Target::Widget(self.terminal.proxy.tab_id),
+                        ));
+                    }
                 }
             }
             LapceCommand::SearchForward => {
@@ -508,27 +690,133 @@ impl RawTerminal {
     }
 }
 
+/// How a multi-line paste goes into a terminal whose program didn't enable
+/// bracketed paste, which would run each line as it comes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PasteMode {
+    AsIs,
+    /// On one line, the lines joined with `;`.
+    Joined,
+    /// Written to a temporary script of the proxy, which is then sourced.
+    TempFile,
+}
+
+impl PasteMode {
+    pub fn description(&self) -> &'static str {
+        match self {
+            PasteMode::AsIs => "Paste as-is",
+            PasteMode::Joined => "Paste joined (newlines \u{2192} ;)",
+            PasteMode::TempFile => "Paste via temp file + source",
+        }
+    }
+}
+
+/// Where the terminal of a task opens, from `tasks.output-placement`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaskPlacement {
+    /// Beside the active terminal, in its split.
+    PanelSplit,
+    /// In a new tab of the terminal panel.
+    PanelTab,
+    /// In a new tab of the terminal panel, maximized over the editors.
+    EditorArea,
+}
+
+impl TaskPlacement {
+    pub fn from_config(placement: &str) -> Self {
+        match placement {
+            "panel-tab" => TaskPlacement::PanelTab,
+            "editor-area" => TaskPlacement::EditorArea,
+            _ => TaskPlacement::PanelSplit,
+        }
+    }
+}
+
+/// A task of the settings run in a terminal, with what to restore once it
+/// exits and its terminal closes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerminalTask {
+    pub name: String,
+    pub command: String,
+    pub cwd: Option<PathBuf>,
+    /// Whether the terminal closes once the task exits with 0.
+    pub auto_close: bool,
+    pub placement: TaskPlacement,
+    /// The terminal active when the task started, focused again when the
+    /// terminal of the task closes.
+    pub previous_term_id: Option<TermId>,
+    /// Whether the terminal panel was shown when the task started.
+    pub panel_shown: bool,
+    /// Whether it was maximized.
+    pub panel_maximized: bool,
+}
+
+impl TerminalTask {
+    /// The title of the terminal once the task exited with the code, which
+    /// is unknown when the proxy can't tell it.
+    pub fn exit_title(&self, exit_code: Option<i32>) -> String {
+        match exit_code {
+            Some(0) => format!("{}: done", self.name),
+            Some(code) => format!("{}: exit code {}", self.name, code),
+            None => format!("{}: exited", self.name),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct LapceTerminalData {
     pub term_id: TermId,
     pub view_id: WidgetId,
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
+    /// The split of the panel tab the terminal is in, which is `split_id`
+    /// unless the terminal is in a nested split.
+    pub tab_id: WidgetId,
+    /// The title the shell set with the OSC 0 and 2 escapes, usually the
+    /// command it runs.
     pub title: String,
+    /// The name the user gave the terminal, shown rather than `title`.
+    pub user_title: Option<String>,
     pub mode: Mode,
     pub visual_mode: VisualMode,
     pub raw: Arc<Mutex<RawTerminal>>,
     pub proxy: Arc<LapceProxy>,
+    /// How multi-line pastes go, once picked for the terminal.
+    pub paste_mode: Option<PasteMode>,
+    /// The paste waiting for the way to paste it to be picked.
+    pub pending_paste: Option<Arc<String>>,
+    /// The task running in the terminal instead of a shell.
+    pub task: Option<Arc<TerminalTask>>,
 }
 
 impl LapceTerminalData {
+    /// A terminal whose shell starts in `cwd`, or else in the workspace.
     pub fn new(
         workspace: Arc<LapceWorkspace>,
+        cwd: Option<PathBuf>,
+        split_id: WidgetId,
+        event_sink: ExtEventSink,
+        proxy: Arc<LapceProxy>,
+    ) -> Self {
+        Self::with_task(workspace, cwd, None, split_id, event_sink, proxy)
+    }
+
+    /// A terminal running the task if there's one, in the folder of the
+    /// task, or else a shell as `new` does.
+    pub fn with_task(
+        workspace: Arc<LapceWorkspace>,
+        cwd: Option<PathBuf>,
+        task: Option<Arc<TerminalTask>>,
         split_id: WidgetId,
         event_sink: ExtEventSink,
         proxy: Arc<LapceProxy>,
     ) -> Self {
-        let cwd = workspace.path.as_ref().map(|p| p.clone());
+        let cwd = task
+            .as_ref()
+            .and_then(|task| task.cwd.clone())
+            .or(cwd)
+            .or_else(|| workspace.path.clone());
+        let command = task.as_ref().map(|task| task.command.clone());
         let widget_id = WidgetId::next();
         let view_id = WidgetId::next();
         let term_id = TermId::next();
@@ -541,7 +829,7 @@ impl LapceTerminalData {
         let local_proxy = proxy.clone();
         let local_raw = raw.clone();
         std::thread::spawn(move || {
-            local_proxy.new_terminal(term_id, cwd, local_raw);
+            local_proxy.new_terminal(term_id, cwd, command, local_raw);
         });
 
         Self {
@@ -549,11 +837,94 @@ impl LapceTerminalData {
             widget_id,
             view_id,
             split_id,
-            title: "".to_string(),
+            tab_id: split_id,
+            title: task
+                .as_ref()
+                .map(|task| task.name.clone())
+                .unwrap_or_default(),
+            user_title: None,
             mode: Mode::Terminal,
             visual_mode: VisualMode::Normal,
             raw,
             proxy,
+            paste_mode: None,
+            pending_paste: None,
+            task,
+        }
+    }
+
+    /// The name the user gave the terminal, or else the title from the
+    /// shell.
+    pub fn display_title(&self) -> &str {
+        self.user_title.as_deref().unwrap_or(&self.title)
+    }
+
+    /// Writes the pasted text to the shell. A multi-line paste into a
+    /// program that didn't enable bracketed paste goes the way picked for
+    /// the terminal, and it returns false when none was picked yet.
+    pub fn paste(&self, text: &str) -> bool {
+        let bracketed = self
+            .raw
+            .lock()
+            .term
+            .mode()
+            .contains(TermMode::BRACKETED_PASTE);
+        if bracketed {
+            let text = text.replace("\x1b[201~", "");
+            self.proxy.terminal_write(
+                self.term_id,
+                &format!("\x1b[200~{}\x1b[201~", text),
+            );
+            return true;
+        }
+        if !is_multi_line(text) {
+            self.proxy.terminal_write(self.term_id, text);
+            return true;
+        }
+        match self.paste_mode {
+            Some(mode) => {
+                self.paste_as(text, mode);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn paste_as(&self, text: &str, mode: PasteMode) {
+        match mode {
+            PasteMode::AsIs => {
+                let text = text.replace("\r\n", "\n").replace('\n', "\r");
+                self.proxy.terminal_write(self.term_id, &text);
+            }
+            PasteMode::Joined => {
+                self.proxy.terminal_write(self.term_id, &join_lines(text));
+            }
+            PasteMode::TempFile => {
+                let proxy = self.proxy.clone();
+                let term_id = self.term_id;
+                let python = self.title.contains("python");
+                let extension = if python {
+                    "py"
+                } else if cfg!(windows) {
+                    "ps1"
+                } else {
+                    "sh"
+                };
+                self.proxy.write_temp_file(
+                    text,
+                    extension,
+                    Box::new(move |result| match result {
+                        Ok(path) => {
+                            let path = path.as_str().unwrap_or("");
+                            let command = source_command(path, python);
+                            proxy.terminal_write(term_id, &format!("{}\r", command));
+                        }
+                        Err(e) => {
+                            log::error!("can't write the paste to a file: {}", e);
+                        }
+                    }),
+                );
+            }
         }
     }
 
@@ -650,17 +1021,177 @@ impl LapceTerminalData {
     }
 }
 
+/// The terminal panel: a strip of tabs, each with its own split of
+/// terminals, over the split of the active tab.
 pub struct TerminalPanel {
     widget_id: WidgetId,
-    split: WidgetPod<LapceTabData, LapceSplitNew>,
+    /// The splits of the tabs, in the order of the tabs.
+    splits: Vec<WidgetPod<LapceTabData, LapceSplitNew>>,
+    tab_height: f64,
+    /// Where the tabs are in the strip, in the order of the tabs.
+    tab_rects: Vec<Rect>,
+    /// The button adding a tab, after the tabs.
+    new_tab_rect: Rect,
+    mouse_pos: Point,
+    /// Whether a terminal was started. Until then, one is started for the
+    /// panel, and after the last one closes the panel shows how to start
+    /// another.
+    had_terminals: bool,
 }
 
 impl TerminalPanel {
     pub fn new(data: &LapceTabData) -> Self {
-        let split = LapceSplitNew::new(data.terminal.split_id);
+        let splits = data
+            .terminal
+            .tabs
+            .iter()
+            .map(|tab| WidgetPod::new(LapceSplitNew::new(tab.split_id)))
+            .collect();
         Self {
             widget_id: data.terminal.widget_id,
-            split: WidgetPod::new(split),
+            splits,
+            tab_height: 25.0,
+            tab_rects: Vec::new(),
+            new_tab_rect: Rect::ZERO,
+            mouse_pos: Point::ZERO,
+            had_terminals: false,
+        }
+    }
+
+    /// Makes the tab the active one and focuses its terminal, or starts one
+    /// in it if it has none.
+    fn focus_tab(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        index: usize,
+    ) {
+        if index >= data.terminal.tabs.len() {
+            return;
+        }
+        Arc::make_mut(&mut data.terminal).active_tab = index;
+        match data.terminal.tab_terminal(index) {
+            Some(terminal) => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(terminal.widget_id),
+                ));
+            }
+            None => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::InitTerminalPanel(true, None),
+                    Target::Widget(data.terminal.tabs[index].split_id),
+                ));
+            }
+        }
+        ctx.request_layout();
+    }
+
+    /// Adds an empty tab after the active one, whose split gets `init`.
+    fn add_tab(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        init: LapceUICommand,
+    ) {
+        let split_id = Arc::make_mut(&mut data.terminal).add_tab();
+        self.splits.insert(
+            data.terminal.active_tab,
+            WidgetPod::new(LapceSplitNew::new(split_id)),
+        );
+        ctx.children_changed();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            init,
+            Target::Widget(split_id),
+        ));
+        ctx.request_layout();
+    }
+
+    fn new_tab(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        cwd: Option<PathBuf>,
+    ) {
+        self.add_tab(ctx, data, LapceUICommand::InitTerminalPanel(true, cwd));
+    }
+
+    /// Closes the tab with its terminals. The last tab is replaced by an
+    /// empty one and the panel is hidden, as when its last terminal exits.
+    fn close_tab(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        split_id: WidgetId,
+    ) {
+        let index = match data.terminal.tab_index(split_id) {
+            Some(index) => index,
+            None => return,
+        };
+        let last = data.terminal.tabs.len() == 1;
+        if last {
+            let new_split_id = Arc::make_mut(&mut data.terminal).add_tab();
+            self.splits
+                .push(WidgetPod::new(LapceSplitNew::new(new_split_id)));
+        }
+        for terminal in Arc::make_mut(&mut data.terminal).remove_tab(split_id) {
+            data.proxy.terminal_close(terminal.term_id);
+        }
+        self.splits.remove(index);
+        ctx.children_changed();
+        ctx.request_layout();
+        if !last {
+            // the tab of a task closing after the panel was hidden again
+            // leaves the focus where it is
+            if data
+                .panels
+                .values()
+                .any(|panel| panel.is_shown() && panel.active == PanelKind::Terminal)
+            {
+                self.focus_tab(ctx, data, data.terminal.active_tab);
+            }
+            return;
+        }
+        for (_, panel) in data.panels.iter_mut() {
+            if panel.active == PanelKind::Terminal {
+                Arc::make_mut(panel).shown = false;
+            }
+        }
+        if let Some(active) = *data.main_split.active {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::Focus,
+                Target::Widget(active),
+            ));
+        }
+    }
+
+    fn tab_at(&self, pos: Point) -> Option<usize> {
+        self.tab_rects.iter().position(|rect| rect.contains(pos))
+    }
+
+    fn mouse_down(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        mouse_event: &MouseEvent,
+    ) {
+        if self.new_tab_rect.contains(mouse_event.pos) {
+            self.new_tab(ctx, data, None);
+            return;
+        }
+        if let Some(index) = self.tab_at(mouse_event.pos) {
+            match mouse_event.button {
+                MouseButton::Left => self.focus_tab(ctx, data, index),
+                MouseButton::Middle => {
+                    let split_id = data.terminal.tabs[index].split_id;
+                    self.close_tab(ctx, data, split_id);
+                }
+                _ => {}
+            }
         }
     }
 }
@@ -678,24 +1209,100 @@ impl Widget<LapceTabData> for TerminalPanel {
         env: &Env,
     ) {
         match event {
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = mouse_event.pos;
+                if mouse_event.pos.y < self.tab_height {
+                    if self.tab_at(mouse_event.pos).is_some()
+                        || self.new_tab_rect.contains(mouse_event.pos)
+                    {
+                        ctx.set_cursor(&druid::Cursor::Pointer);
+                    } else {
+                        ctx.clear_cursor();
+                    }
+                    ctx.request_paint();
+                }
+            }
+            Event::MouseDown(mouse_event) if mouse_event.pos.y < self.tab_height => {
+                self.mouse_down(ctx, data, mouse_event);
+                ctx.set_handled();
+                return;
+            }
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
                 match command {
                     LapceUICommand::Focus => {
-                        if data.terminal.terminals.len() > 0 {
+                        if data
+                            .terminal
+                            .terminals
+                            .contains_key(&data.terminal.active_term_id)
+                        {
                             ctx.submit_command(Command::new(
                                 LAPCE_UI_COMMAND,
                                 LapceUICommand::Focus,
                                 Target::Widget(data.terminal.active),
                             ));
+                        } else if data.terminal.terminals.len() > 0 {
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::InitTerminalPanel(true, None),
+                                Target::Widget(data.terminal.split_id()),
+                            ));
+                        } else {
+                            // the empty tab shows how to start one
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::Focus,
+                                Target::Widget(data.terminal.split_id()),
+                            ));
                         }
                     }
+                    LapceUICommand::NewTerminalTab(cwd) => {
+                        self.new_tab(ctx, data, cwd.clone());
+                        ctx.set_handled();
+                        return;
+                    }
+                    LapceUICommand::NewTerminalTaskTab(task) => {
+                        self.add_tab(
+                            ctx,
+                            data,
+                            LapceUICommand::InitTerminalTask(task.clone()),
+                        );
+                        ctx.set_handled();
+                        return;
+                    }
+                    LapceUICommand::FocusTerminalTab(index) => {
+                        self.focus_tab(ctx, data, *index);
+                        ctx.set_handled();
+                        return;
+                    }
+                    LapceUICommand::CloseTerminalTab(split_id) => {
+                        self.close_tab(ctx, data, *split_id);
+                        ctx.set_handled();
+                        return;
+                    }
                     _ => (),
                 }
             }
             _ => (),
         }
-        self.split.event(ctx, event, data, env);
+        // like the tabs of the window, only the active tab takes input
+        match event {
+            Event::MouseDown(_)
+            | Event::MouseUp(_)
+            | Event::MouseMove(_)
+            | Event::Wheel(_)
+            | Event::KeyDown(_)
+            | Event::KeyUp(_) => {
+                if let Some(split) = self.splits.get_mut(data.terminal.active_tab) {
+                    split.event(ctx, event, data, env);
+                }
+            }
+            _ => {
+                for split in self.splits.iter_mut() {
+                    split.event(ctx, event, data, env);
+                }
+            }
+        }
     }
 
     fn lifecycle(
@@ -705,7 +1312,9 @@ impl Widget<LapceTabData> for TerminalPanel {
         data: &LapceTabData,
         env: &Env,
     ) {
-        self.split.lifecycle(ctx, event, data, env);
+        for split in self.splits.iter_mut() {
+            split.lifecycle(ctx, event, data, env);
+        }
     }
 
     fn update(
@@ -715,17 +1324,24 @@ impl Widget<LapceTabData> for TerminalPanel {
         data: &LapceTabData,
         env: &Env,
     ) {
-        if data.terminal.terminals.len() == 0 {
+        if !data.terminal.terminals.is_empty() {
+            self.had_terminals = true;
+        } else if !self.had_terminals {
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
-                LapceUICommand::InitTerminalPanel(true),
-                Target::Widget(data.terminal.split_id),
+                LapceUICommand::InitTerminalPanel(true, None),
+                Target::Widget(data.terminal.split_id()),
             ));
         }
+        if old_data.terminal.active_tab != data.terminal.active_tab {
+            ctx.request_layout();
+        }
         if !data.terminal.same(&old_data.terminal) {
             ctx.request_paint();
         }
-        self.split.update(ctx, data, env);
+        for split in self.splits.iter_mut() {
+            split.update(ctx, data, env);
+        }
     }
 
     fn layout(
@@ -735,13 +1351,120 @@ impl Widget<LapceTabData> for TerminalPanel {
         data: &LapceTabData,
         env: &Env,
     ) -> Size {
-        self.split.layout(ctx, bc, data, env);
-        self.split.set_origin(ctx, data, env, Point::ZERO);
-        bc.max()
+        let self_size = bc.max();
+        let n = self.splits.len();
+        let tab_width = ((self_size.width - self.tab_height) / n as f64).min(150.0);
+        self.tab_rects = (0..n)
+            .map(|i| {
+                Size::new(tab_width, self.tab_height)
+                    .to_rect()
+                    .with_origin(Point::new(tab_width * i as f64, 0.0))
+            })
+            .collect();
+        self.new_tab_rect = Size::new(self.tab_height, self.tab_height)
+            .to_rect()
+            .with_origin(Point::new(tab_width * n as f64, 0.0));
+
+        let split_bc = BoxConstraints::tight(Size::new(
+            self_size.width,
+            (self_size.height - self.tab_height).max(0.0),
+        ));
+        for split in self.splits.iter_mut() {
+            split.layout(ctx, &split_bc, data, env);
+            split.set_origin(ctx, data, env, Point::new(0.0, self.tab_height));
+        }
+        self_size
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
-        self.split.paint(ctx, data, env);
+        let size = ctx.size();
+        ctx.fill(
+            Size::new(size.width, self.tab_height).to_rect(),
+            data.config
+                .get_color_unchecked(LapceTheme::LAPCE_INACTIVE_TAB),
+        );
+        let is_hot = ctx.is_hot();
+        for (i, rect) in self.tab_rects.iter().enumerate() {
+            let active = i == data.terminal.active_tab;
+            if active {
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_ACTIVE_TAB),
+                );
+            } else if is_hot && rect.contains(self.mouse_pos) {
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+                );
+            }
+            let text_layout = ctx
+                .text()
+                .new_text_layout(data.terminal.tab_title(i))
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(if active {
+                            LapceTheme::EDITOR_FOREGROUND
+                        } else {
+                            LapceTheme::EDITOR_DIM
+                        })
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.with_save(|ctx| {
+                ctx.clip(rect.inflate(-5.0, 0.0));
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(
+                        rect.x0 + 5.0,
+                        rect.y0 + (rect.height() - text_layout.size().height) / 2.0,
+                    ),
+                );
+            });
+            ctx.stroke(
+                Line::new(
+                    Point::new(rect.x1 - 0.5, rect.y0),
+                    Point::new(rect.x1 - 0.5, rect.y1),
+                ),
+                data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER),
+                1.0,
+            );
+        }
+
+        if is_hot && self.new_tab_rect.contains(self.mouse_pos) {
+            ctx.fill(
+                self.new_tab_rect,
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+            );
+        }
+        let text_layout = ctx
+            .text()
+            .new_text_layout("+")
+            .font(FontFamily::SYSTEM_UI, 15.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        let text_size = text_layout.size();
+        ctx.draw_text(
+            &text_layout,
+            Point::new(
+                self.new_tab_rect.x0
+                    + (self.new_tab_rect.width() - text_size.width) / 2.0,
+                (self.tab_height - text_size.height) / 2.0,
+            ),
+        );
+
+        if let Some(split) = self.splits.get_mut(data.terminal.active_tab) {
+            split.paint(ctx, data, env);
+        }
     }
 }
 
@@ -1018,7 +1741,7 @@ impl Widget<LapceTabData> for LapceTerminalHeader {
             let term = data.terminal.terminals.get(&self.term_id).unwrap();
             let text_layout = ctx
                 .text()
-                .new_text_layout(term.title.clone())
+                .new_text_layout(term.display_title().to_string())
                 .font(FontFamily::SYSTEM_UI, data.config.editor.font_size as f64)
                 .text_color(
                     data.config
@@ -1074,8 +1797,8 @@ impl LapceTerminal {
 
     pub fn request_focus(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
         ctx.request_focus();
-        Arc::make_mut(&mut data.terminal).active = self.widget_id;
-        Arc::make_mut(&mut data.terminal).active_term_id = self.term_id;
+        Arc::make_mut(&mut data.terminal)
+            .set_active_terminal(self.term_id, self.widget_id);
         data.focus = self.widget_id;
         data.focus_area = FocusArea::Panel(PanelKind::Terminal);
         for (pos, panel) in data.panels.iter_mut() {
@@ -1086,6 +1809,123 @@ impl LapceTerminal {
             }
         }
     }
+
+    /// The cell under the mouse, and the side of it the mouse is on.
+    fn grid_point(
+        &self,
+        ctx: &mut EventCtx,
+        pos: Point,
+        config: &Config,
+        term: &Term<EventProxy>,
+    ) -> (alacritty_terminal::index::Point, Side) {
+        let char_width = config.editor_text_width(ctx.text(), "W");
+        let line_height = config.editor.line_height as f64;
+        let col = (pos.x / char_width).max(0.0);
+        let column = (col.floor() as usize).min(term.last_column().0);
+        let side = if col.fract() < 0.5 {
+            Side::Left
+        } else {
+            Side::Right
+        };
+        let line = (pos.y / line_height).floor() as i32
+            - term.grid().display_offset() as i32;
+        let line = line
+            .max(term.topmost_line().0)
+            .min(term.bottommost_line().0);
+        (
+            alacritty_terminal::index::Point::new(
+                alacritty_terminal::index::Line(line),
+                alacritty_terminal::index::Column(column),
+            ),
+            side,
+        )
+    }
+
+    fn mouse_down(
+        &self,
+        ctx: &mut EventCtx,
+        mouse_event: &MouseEvent,
+        data: &mut LapceTabData,
+        term_data: &mut LapceTerminalViewData,
+    ) {
+        match mouse_event.button {
+            MouseButton::Left => {
+                ctx.set_active(true);
+                let ty = match mouse_event.count {
+                    2 => SelectionType::Semantic,
+                    3 => SelectionType::Lines,
+                    _ => SelectionType::Simple,
+                };
+                let mut raw = term_data.terminal.raw.lock();
+                let term = &mut raw.term;
+                let (point, side) =
+                    self.grid_point(ctx, mouse_event.pos, &data.config, term);
+                term_data.terminal.start_selection(term, ty, point, side);
+                ctx.request_paint();
+            }
+            MouseButton::Middle if cfg!(target_os = "linux") => {
+                if term_data.terminal.mode != Mode::Terminal {
+                    return;
+                }
+                if let Some(text) = data.terminal.primary_selection.as_ref() {
+                    if !term_data.terminal.paste(text) {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::TerminalPasteChoice(
+                                self.term_id,
+                                text.to_string(),
+                            ),
+                            Target::Widget(data.id),
+                        ));
+                    }
+                }
+            }
+            MouseButton::Right => {
+                let item = |text: &str, command: LapceCommand| MenuItem {
+                    text: text.to_string(),
+                    command: LapceCommandNew {
+                        cmd: command.to_string(),
+                        palette_desc: None,
+                        data: None,
+                        target: CommandTarget::Focus,
+                    },
+                };
+                let menu_items = vec![
+                    item("Copy", LapceCommand::ClipboardCopy),
+                    item("Paste", LapceCommand::ClipboardPaste),
+                    item("Clear", LapceCommand::ClearTerminal),
+                    item("Split Terminal", LapceCommand::SplitVertical),
+                    item("Close Terminal", LapceCommand::SplitClose),
+                ];
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowMenu(
+                        mouse_event.window_pos,
+                        Arc::new(menu_items),
+                    ),
+                    Target::Auto,
+                ));
+            }
+            _ => (),
+        }
+    }
+
+    /// Ends the selection made with the mouse, which becomes the primary
+    /// selection. A click without a drag leaves no selection.
+    fn mouse_up(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
+        ctx.set_active(false);
+        let terminal = data.terminal.terminals.get(&self.term_id).unwrap().clone();
+        let mut raw = terminal.raw.lock();
+        let term = &mut raw.term;
+        match term.selection_to_string().filter(|s| !s.is_empty()) {
+            Some(text) => {
+                Arc::make_mut(&mut data.terminal).primary_selection =
+                    Some(Arc::new(text));
+            }
+            None => terminal.clear_selection(term),
+        }
+        ctx.request_paint();
+    }
 }
 
 impl Widget<LapceTabData> for LapceTerminal {
@@ -1110,6 +1950,20 @@ impl Widget<LapceTabData> for LapceTerminal {
         match event {
             Event::MouseDown(mouse_event) => {
                 self.request_focus(ctx, data);
+                self.mouse_down(ctx, mouse_event, data, &mut term_data);
+            }
+            Event::MouseMove(mouse_event) if ctx.is_active() => {
+                let mut raw = term_data.terminal.raw.lock();
+                let term = &mut raw.term;
+                let (point, side) =
+                    self.grid_point(ctx, mouse_event.pos, &data.config, term);
+                if let Some(selection) = term.selection.as_mut() {
+                    selection.update(point, side);
+                }
+                ctx.request_paint();
+            }
+            Event::MouseUp(mouse_event) if ctx.is_active() => {
+                self.mouse_up(ctx, data);
             }
             Event::Wheel(wheel_event) => {
                 data.terminal
@@ -1448,6 +2302,29 @@ impl Widget<LapceTabData> for LapceTerminal {
     }
 }
 
+/// Whether the text has more than a line, not counting a last line break.
+fn is_multi_line(text: &str) -> bool {
+    text.trim_end_matches(|c| c == '\r' || c == '\n')
+        .contains('\n')
+}
+
+/// The lines of the text that aren't blank, trimmed and joined with `; `.
+fn join_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .join("; ")
+}
+
+/// The command to run the script at `path`, in the shell or in Python.
+fn source_command(path: &str, python: bool) -> String {
+    if python {
+        format!("exec(open({:?}).read())", path)
+    } else {
+        format!(". '{}'", path.replace('\'', "'\\''"))
+    }
+}
+
 #[derive(Clone)]
 pub struct EventProxy {
     term_id: TermId,
@@ -1471,7 +2348,60 @@ impl EventListener for EventProxy {
                     Target::Widget(self.proxy.tab_id),
                 );
             }
+            alacritty_terminal::event::Event::ResetTitle => {
+                self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::UpdateTerminalTitle(
+                        self.term_id,
+                        "".to_string(),
+                    ),
+                    Target::Widget(self.proxy.tab_id),
+                );
+            }
             _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> TerminalTask {
+        TerminalTask {
+            name: "test".to_string(),
+            command: "cargo test".to_string(),
+            cwd: None,
+            auto_close: true,
+            placement: TaskPlacement::PanelSplit,
+            previous_term_id: None,
+            panel_shown: true,
+            panel_maximized: false,
+        }
+    }
+
+    #[test]
+    fn test_task_placement_from_config() {
+        assert_eq!(
+            TaskPlacement::from_config("panel-split"),
+            TaskPlacement::PanelSplit
+        );
+        assert_eq!(
+            TaskPlacement::from_config("panel-tab"),
+            TaskPlacement::PanelTab
+        );
+        assert_eq!(
+            TaskPlacement::from_config("editor-area"),
+            TaskPlacement::EditorArea
+        );
+        assert_eq!(TaskPlacement::from_config(""), TaskPlacement::PanelSplit);
+    }
+
+    #[test]
+    fn test_task_exit_title() {
+        let task = task();
+        assert_eq!(task.exit_title(Some(0)), "test: done");
+        assert_eq!(task.exit_title(Some(101)), "test: exit code 101");
+        assert_eq!(task.exit_title(None), "test: exited");
+    }
+}
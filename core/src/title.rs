@@ -1,30 +1,93 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Instant};
 
 use druid::{
     kurbo::Line,
     piet::{Text, TextLayout, TextLayoutBuilder},
-    BoxConstraints, Color, Command, Env, Event, EventCtx, FontFamily, LayoutCtx,
-    LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point, Rect, RenderContext, Size,
-    Target, UpdateCtx, Widget,
+    BoxConstraints, Color, Command, Data, Env, Event, EventCtx, FontFamily,
+    LayoutCtx, LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point, Rect,
+    RenderContext, Size, Target, UpdateCtx, Widget, WidgetId,
 };
+#[cfg(not(target_os = "macos"))]
+use druid::{kurbo::Vec2, WindowState};
 use serde_json::json;
 use strum::EnumMessage;
 
 use crate::{
+    buffer::BufferContent,
     command::{
-        CommandTarget, LapceCommandNew, LapceUICommand, LapceWorkbenchCommand,
-        LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+        CommandCtx, CommandTarget, LapceCommandNew, LapceUICommand,
+        LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
     },
     config::LapceTheme,
     data::LapceWindowData,
     menu::MenuItem,
+    proxy::ProxyStatus,
     state::LapceWorkspaceType,
     svg::get_svg,
 };
 
+/// The width of each of the minimize, maximize and close buttons.
+#[cfg(not(target_os = "macos"))]
+const WINDOW_CONTROL_WIDTH: f64 = 40.0;
+
+/// The workspace folder of the tab, which names it in the tab switcher.
+fn tab_name(data: &LapceWindowData, tab_id: &WidgetId) -> String {
+    data.tabs
+        .get(tab_id)
+        .and_then(|tab| tab.workspace.path.as_ref())
+        .and_then(|path| path.file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("Untitled")
+        .to_string()
+}
+
+/// The file of the active editor of the active tab, and whether it has
+/// unsaved changes.
+fn active_file(data: &LapceWindowData) -> Option<(PathBuf, bool)> {
+    let tab = data.tabs.get(&data.active_id)?;
+    let editor = tab.main_split.active_editor()?;
+    match &editor.content {
+        BufferContent::File(path) => {
+            let dirty = tab
+                .main_split
+                .open_files
+                .get(path)
+                .map(|buffer| buffer.dirty)
+                .unwrap_or(false);
+            Some((path.clone(), dirty))
+        }
+        BufferContent::Local(_) => None,
+    }
+}
+
+/// The connection of the active tab to its proxy, if its workspace is
+/// remote.
+fn remote_status(data: &LapceWindowData) -> Option<ProxyStatus> {
+    let tab = data.tabs.get(&data.active_id)?;
+    match &tab.workspace.kind {
+        LapceWorkspaceType::Local => None,
+        LapceWorkspaceType::RemoteSSH(_) => Some(tab.proxy_status),
+    }
+}
+
+/// The parts of the title bar whose menus the commands of the palette open,
+/// for when the mouse isn't at hand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TitleMenu {
+    Workspace,
+    Branch,
+    Remote,
+}
+
 pub struct Title {
     mouse_pos: Point,
     commands: Vec<(Rect, Command)>,
+    /// What clicking each part with a menu does, and where it was painted.
+    menus: Vec<(TitleMenu, Rect, Command)>,
+    /// Where the window controls start, past which the parts are cut off.
+    right: f64,
+    /// Where the pulse of a connecting remote box starts from.
+    started: Instant,
 }
 
 impl Title {
@@ -32,6 +95,9 @@ impl Title {
         Self {
             mouse_pos: Point::ZERO,
             commands: Vec::new(),
+            menus: Vec::new(),
+            right: 0.0,
+            started: Instant::now(),
         }
     }
 
@@ -44,12 +110,519 @@ impl Title {
         false
     }
 
-    fn mouse_down(&self, ctx: &mut EventCtx, mouse_event: &MouseEvent) {
+    /// Whether the point moves the window when dragged, which is anywhere
+    /// but the buttons, and the traffic lights on macOS.
+    fn drag_hit_test(&self, pos: Point) -> bool {
+        #[cfg(target_os = "macos")]
+        {
+            if pos.x < 70.0 {
+                return false;
+            }
+        }
+        !self.commands.iter().any(|(rect, _)| rect.contains(pos))
+    }
+
+    fn mouse_down<C: CommandCtx>(
+        &self,
+        ctx: &mut C,
+        mouse_event: &MouseEvent,
+        data: &LapceWindowData,
+    ) {
         for (rect, command) in self.commands.iter() {
             if rect.contains(mouse_event.pos) {
                 ctx.submit_command(command.clone());
+                return;
             }
         }
+        if !self.drag_hit_test(mouse_event.pos) {
+            return;
+        }
+        if mouse_event.count == 2 {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::WindowMaximizeToggle,
+                Target::Auto,
+            ));
+        } else {
+            ctx.handle_titlebar(true);
+        }
+    }
+
+    /// Runs what the click or the `ShowTitleMenu` command asks for, which
+    /// needs nothing of the window but to drag it.
+    fn click_or_command<C: CommandCtx>(
+        &self,
+        ctx: &mut C,
+        event: &Event,
+        data: &LapceWindowData,
+    ) {
+        match event {
+            Event::MouseDown(mouse_event) => {
+                self.mouse_down(ctx, mouse_event, data);
+            }
+            Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
+                if let LapceUICommand::ShowTitleMenu(menu) =
+                    cmd.get_unchecked(LAPCE_UI_COMMAND)
+                {
+                    self.show_menu(ctx, data, *menu);
+                    ctx.set_handled();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Keeps what clicking the part does, to run it from the keyboard too.
+    fn add_menu(&mut self, menu: TitleMenu, rect: Rect, command: Command) {
+        self.commands.push((rect, command.clone()));
+        self.menus.push((menu, rect, command));
+    }
+
+    /// Does what clicking the part does. A menu opens under the part, or at
+    /// the top left of the window when the part is cut off or hidden.
+    fn show_menu<C: CommandCtx>(
+        &self,
+        ctx: &mut C,
+        data: &LapceWindowData,
+        menu: TitleMenu,
+    ) {
+        let (rect, command) = match self.menus.iter().find(|(m, _, _)| *m == menu) {
+            Some((_, rect, command)) => (*rect, command.clone()),
+            None => return,
+        };
+        let command = match command.get(LAPCE_UI_COMMAND) {
+            Some(LapceUICommand::ShowMenu(_, items)) => {
+                let origin = if data.chrome_hidden() || rect.x0 >= self.right {
+                    Point::ZERO
+                } else {
+                    Point::new(rect.x0, rect.y1)
+                };
+                Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowMenu(origin, items.clone()),
+                    Target::Auto,
+                )
+            }
+            _ => command,
+        };
+        ctx.submit_command(command);
+    }
+
+    /// Paints the minimize, maximize and close buttons at the right end, and
+    /// returns where they start, which is as far as the other items can go.
+    /// macOS has its traffic lights instead.
+    /// Paints a segment per tab from `x` to switch to it, with a button to
+    /// close it on hover, and one to create a tab. When the segments take more
+    /// than half of the room left before `right`, only the active one shows,
+    /// with a menu of all of them. Returns where the next item goes.
+    fn paint_tabs(
+        &mut self,
+        ctx: &mut PaintCtx,
+        data: &LapceWindowData,
+        x: f64,
+        right: f64,
+    ) -> f64 {
+        let size = ctx.size();
+        let padding = 10.0;
+        let close_width = size.height;
+        let mut x = x;
+
+        if data.tabs_order.len() > 1 {
+            let layouts: Vec<(WidgetId, _)> = data
+                .tabs_order
+                .iter()
+                .map(|tab_id| {
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(tab_name(data, tab_id))
+                        .font(FontFamily::SYSTEM_UI, 13.0)
+                        .text_color(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                                .clone(),
+                        )
+                        .build()
+                        .unwrap();
+                    (*tab_id, text_layout)
+                })
+                .collect();
+            let width: f64 = layouts
+                .iter()
+                .map(|(_, layout)| layout.size().width + padding * 2.0 + close_width)
+                .sum();
+            let collapsed = width > (right - x) / 2.0;
+
+            for (tab_id, text_layout) in layouts.iter() {
+                let active = *tab_id == data.active_id;
+                if collapsed && !active {
+                    continue;
+                }
+                let rect = Size::new(
+                    text_layout.size().width + padding * 2.0 + close_width,
+                    size.height,
+                )
+                .to_rect()
+                .with_origin(Point::new(x, 0.0));
+                let hovered = ctx.is_hot() && rect.contains(self.mouse_pos);
+                if active || hovered {
+                    ctx.fill(
+                        rect,
+                        data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
+                    );
+                }
+                ctx.draw_text(
+                    text_layout,
+                    Point::new(
+                        x + padding,
+                        (size.height - text_layout.size().height) / 2.0,
+                    ),
+                );
+                if hovered {
+                    // closing the last tab is refused by the window
+                    let close_rect = Size::new(close_width, size.height)
+                        .to_rect()
+                        .with_origin(Point::new(rect.x1 - close_width, 0.0));
+                    ctx.draw_svg(
+                        &get_svg("close.svg").unwrap(),
+                        close_rect.inflate(-8.0, -8.0),
+                        Some(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+                        ),
+                    );
+                    self.commands.push((
+                        close_rect,
+                        Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::CloseTabId(*tab_id),
+                            Target::Auto,
+                        ),
+                    ));
+                }
+                self.commands.push((
+                    rect,
+                    Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::FocusTabId(*tab_id),
+                        Target::Auto,
+                    ),
+                ));
+                x += rect.width();
+            }
+
+            if collapsed {
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout(format!("{} tabs", layouts.len()))
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(
+                        x + padding,
+                        (size.height - text_layout.size().height) / 2.0,
+                    ),
+                );
+                let rect =
+                    Size::new(text_layout.size().width + padding * 2.0, size.height)
+                        .to_rect()
+                        .with_origin(Point::new(x, 0.0));
+                let menu_items = layouts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (tab_id, _))| MenuItem {
+                        text: tab_name(data, tab_id),
+                        command: LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::FocusTab.to_string(),
+                            palette_desc: None,
+                            data: Some(json!(i)),
+                            target: CommandTarget::Workbench,
+                        },
+                    })
+                    .collect();
+                self.commands.push((
+                    rect,
+                    Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowMenu(
+                            Point::new(rect.x0, rect.y1),
+                            Arc::new(menu_items),
+                        ),
+                        Target::Auto,
+                    ),
+                ));
+                x += rect.width();
+            }
+        }
+
+        let new_rect = Size::new(size.height, size.height)
+            .to_rect()
+            .with_origin(Point::new(x, 0.0));
+        if ctx.is_hot() && new_rect.contains(self.mouse_pos) {
+            ctx.fill(
+                new_rect,
+                data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
+            );
+        }
+        let text_layout = ctx
+            .text()
+            .new_text_layout("+")
+            .font(FontFamily::SYSTEM_UI, 15.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        ctx.draw_text(
+            &text_layout,
+            Point::new(
+                x + (size.height - text_layout.size().width) / 2.0,
+                (size.height - text_layout.size().height) / 2.0,
+            ),
+        );
+        self.commands.push((
+            new_rect,
+            Command::new(LAPCE_UI_COMMAND, LapceUICommand::NewTab, Target::Auto),
+        ));
+        x += new_rect.width();
+
+        let line_color = data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER);
+        let line = Line::new(Point::new(x, 0.0), Point::new(x, size.height));
+        ctx.stroke(line, line_color, 1.0);
+        x
+    }
+
+    /// Paints the name of the active file centered, and its folder in the
+    /// workspace, between `left` and `right` as far as they fit.
+    fn paint_active_file(
+        &mut self,
+        ctx: &mut PaintCtx,
+        data: &LapceWindowData,
+        left: f64,
+        right: f64,
+    ) {
+        let (path, dirty) = match active_file(data) {
+            Some(file) => file,
+            None => return,
+        };
+        let size = ctx.size();
+        let tab = data.tabs.get(&data.active_id).unwrap();
+
+        let mut name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("")
+            .to_string();
+        if dirty {
+            name += " \u{25cf}";
+        }
+        let folder = path
+            .parent()
+            .map(|parent| match tab.workspace.path.as_ref() {
+                Some(workspace_path) => {
+                    parent.strip_prefix(workspace_path).unwrap_or(parent)
+                }
+                None => parent,
+            })
+            .and_then(|folder| folder.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        let name_layout = ctx
+            .text()
+            .new_text_layout(name)
+            .font(FontFamily::SYSTEM_UI, 13.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        let folder_layout = ctx
+            .text()
+            .new_text_layout(folder)
+            .font(FontFamily::SYSTEM_UI, 13.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+
+        let padding = 15.0;
+        let gap = 8.0;
+        let name_width = name_layout.size().width;
+        let folder_width = folder_layout.size().width;
+        let available = right - left - padding * 2.0;
+        let (width, show_folder) =
+            if folder_width > 0.0 && name_width + gap + folder_width <= available {
+                (name_width + gap + folder_width, true)
+            } else if name_width <= available {
+                (name_width, false)
+            } else {
+                return;
+            };
+        // centered in the window if it can be, else as close as it fits
+        let x = ((size.width - width) / 2.0)
+            .max(left + padding)
+            .min(right - padding - width);
+
+        ctx.draw_text(
+            &name_layout,
+            Point::new(x, (size.height - name_layout.size().height) / 2.0),
+        );
+        if show_folder {
+            ctx.draw_text(
+                &folder_layout,
+                Point::new(
+                    x + name_width + gap,
+                    (size.height - folder_layout.size().height) / 2.0,
+                ),
+            );
+        }
+
+        self.commands.push((
+            Rect::new(x, 0.0, x + name_width, size.height),
+            Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::RevealInFileExplorer(path),
+                Target::Widget(data.active_id),
+            ),
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    fn paint_window_controls(
+        &mut self,
+        ctx: &mut PaintCtx,
+        data: &LapceWindowData,
+    ) -> f64 {
+        ctx.size().width
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn paint_window_controls(
+        &mut self,
+        ctx: &mut PaintCtx,
+        data: &LapceWindowData,
+    ) -> f64 {
+        let size = ctx.size();
+        let maximized =
+            matches!(ctx.window().get_window_state(), WindowState::Maximized);
+        let controls = [
+            LapceUICommand::WindowMinimize,
+            LapceUICommand::WindowMaximizeToggle,
+            LapceUICommand::WindowClose,
+        ];
+        let right = size.width - WINDOW_CONTROL_WIDTH * controls.len() as f64;
+
+        let foreground = data
+            .config
+            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND);
+        for (i, command) in controls.iter().enumerate() {
+            let rect = Size::new(WINDOW_CONTROL_WIDTH, size.height)
+                .to_rect()
+                .with_origin(Point::new(
+                    right + WINDOW_CONTROL_WIDTH * i as f64,
+                    0.0,
+                ));
+            let hovered = ctx.is_hot() && rect.contains(self.mouse_pos);
+            let mut color = foreground;
+            if hovered {
+                if let LapceUICommand::WindowClose = command {
+                    ctx.fill(rect, &Color::rgb8(232, 17, 35));
+                    color = &Color::WHITE;
+                } else {
+                    ctx.fill(
+                        rect,
+                        data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
+                    );
+                }
+            }
+
+            let icon = Size::new(10.0, 10.0)
+                .to_rect()
+                .with_origin(rect.center() - (5.0, 5.0));
+            match command {
+                LapceUICommand::WindowMinimize => {
+                    let y = icon.center().y + 0.5;
+                    ctx.stroke(
+                        Line::new(Point::new(icon.x0, y), Point::new(icon.x1, y)),
+                        color,
+                        1.0,
+                    );
+                }
+                LapceUICommand::WindowMaximizeToggle if maximized => {
+                    // the restore icon: a window in front of another
+                    let front = icon.with_size((8.0, 8.0)) + Vec2::new(0.5, 2.5);
+                    ctx.stroke(front, color, 1.0);
+                    let back_x = icon.x0 + 2.5;
+                    let back_y = icon.y0 + 0.5;
+                    let back_x1 = icon.x1 + 0.5;
+                    ctx.stroke(
+                        Line::new(
+                            Point::new(back_x, front.y0),
+                            Point::new(back_x, back_y),
+                        ),
+                        color,
+                        1.0,
+                    );
+                    ctx.stroke(
+                        Line::new(
+                            Point::new(back_x, back_y),
+                            Point::new(back_x1, back_y),
+                        ),
+                        color,
+                        1.0,
+                    );
+                    ctx.stroke(
+                        Line::new(
+                            Point::new(back_x1, back_y),
+                            Point::new(back_x1, front.y1 - 2.0),
+                        ),
+                        color,
+                        1.0,
+                    );
+                }
+                LapceUICommand::WindowMaximizeToggle => {
+                    ctx.stroke(icon + Vec2::new(0.5, 0.5), color, 1.0);
+                }
+                _ => {
+                    ctx.stroke(
+                        Line::new(
+                            Point::new(icon.x0, icon.y0),
+                            Point::new(icon.x1, icon.y1),
+                        ),
+                        color,
+                        1.0,
+                    );
+                    ctx.stroke(
+                        Line::new(
+                            Point::new(icon.x0, icon.y1),
+                            Point::new(icon.x1, icon.y0),
+                        ),
+                        color,
+                        1.0,
+                    );
+                }
+            }
+
+            self.commands.push((
+                rect,
+                Command::new(LAPCE_UI_COMMAND, command.clone(), Target::Auto),
+            ));
+        }
+        right
     }
 }
 
@@ -71,9 +644,20 @@ impl Widget<LapceWindowData> for Title {
                     ctx.clear_cursor();
                     ctx.request_paint();
                 }
+                // Windows asks where the title bar is as the mouse moves
+                ctx.window()
+                    .handle_titlebar(self.drag_hit_test(mouse_event.pos));
             }
-            Event::MouseDown(mouse_event) => {
-                self.mouse_down(ctx, mouse_event);
+            Event::MouseDown(_) | Event::Command(_) => {
+                self.click_or_command(ctx, event, data);
+            }
+            Event::AnimFrame(_) => {
+                if remote_status(data) == Some(ProxyStatus::Connecting)
+                    && data.config.ui.animations
+                {
+                    ctx.request_paint();
+                    ctx.request_anim_frame();
+                }
             }
             _ => {}
         }
@@ -86,6 +670,13 @@ impl Widget<LapceWindowData> for Title {
         data: &LapceWindowData,
         env: &Env,
     ) {
+        match event {
+            LifeCycle::HotChanged(_) => ctx.request_paint(),
+            LifeCycle::WidgetAdded if data.config.ui.animations => {
+                ctx.request_anim_frame()
+            }
+            _ => (),
+        }
     }
 
     fn update(
@@ -95,6 +686,22 @@ impl Widget<LapceWindowData> for Title {
         data: &LapceWindowData,
         env: &Env,
     ) {
+        if active_file(old_data) != active_file(data)
+            || old_data.active_id != data.active_id
+            || !old_data.tabs_order.same(&data.tabs_order)
+        {
+            ctx.request_paint();
+        }
+
+        let status = remote_status(data);
+        if remote_status(old_data) != status
+            || old_data.config.ui.animations != data.config.ui.animations
+        {
+            ctx.request_paint();
+            if status == Some(ProxyStatus::Connecting) && data.config.ui.animations {
+                ctx.request_anim_frame();
+            }
+        }
     }
 
     fn layout(
@@ -117,6 +724,14 @@ impl Widget<LapceWindowData> for Title {
         );
 
         self.commands.clear();
+        self.menus.clear();
+
+        // the window controls come first, so that they win the clicks if the
+        // window is too narrow for what's on the left
+        let right = self.paint_window_controls(ctx, data);
+        self.right = right;
+        ctx.save().unwrap();
+        ctx.clip(Size::new(right, size.height).to_rect());
 
         let mut x = 0.0;
         #[cfg(target_os = "macos")]
@@ -126,12 +741,21 @@ impl Widget<LapceWindowData> for Title {
 
         let command_rect = Size::ZERO.to_rect().with_origin(Point::new(x, 0.0));
         let tab = data.tabs.get(&data.active_id).unwrap();
+        let status = remote_status(data);
         let remote_text = match &tab.workspace.kind {
             LapceWorkspaceType::Local => None,
-            LapceWorkspaceType::RemoteSSH(_, host) => {
+            LapceWorkspaceType::RemoteSSH(ssh) => {
+                let host = &ssh.host;
+                let text = match tab.proxy_status {
+                    ProxyStatus::Connected => format!("SSH: {host}"),
+                    ProxyStatus::Connecting => format!("SSH: {host} (connecting)"),
+                    ProxyStatus::Disconnected => {
+                        format!("SSH: {host} (disconnected)")
+                    }
+                };
                 let text_layout = ctx
                     .text()
-                    .new_text_layout(format!("SSH: {host}"))
+                    .new_text_layout(text)
                     .font(FontFamily::SYSTEM_UI, 13.0)
                     .text_color(
                         data.config
@@ -155,17 +779,49 @@ impl Widget<LapceWindowData> for Title {
         )
         .to_rect()
         .with_origin(Point::new(x, 0.0));
-        ctx.fill(remote_rect, &Color::rgb8(64, 120, 242));
+        let remote_color =
+            match status {
+                Some(ProxyStatus::Connecting) if !data.config.ui.animations => {
+                    Some(Color::rgb8(64, 120, 242))
+                }
+                Some(ProxyStatus::Connecting) => {
+                    let t = self.started.elapsed().as_secs_f64();
+                    Some(Color::rgb8(64, 120, 242).with_alpha(
+                        0.5 + 0.5 * (t * std::f64::consts::PI).cos().abs(),
+                    ))
+                }
+                Some(ProxyStatus::Connected) => Some(Color::rgb8(80, 161, 79)),
+                Some(ProxyStatus::Disconnected) => Some(
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_ERROR)
+                        .clone(),
+                ),
+                None => None,
+            };
+        // a local workspace only gets an icon, to connect to a host
+        let icon_color = match remote_color.as_ref() {
+            Some(color) => {
+                ctx.fill(remote_rect, color);
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND)
+            }
+            None => {
+                if remote_rect.contains(self.mouse_pos) {
+                    ctx.fill(
+                        remote_rect,
+                        data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
+                    );
+                }
+                data.config.get_color_unchecked(LapceTheme::EDITOR_DIM)
+            }
+        };
         let remote_svg = get_svg("remote.svg").unwrap();
         ctx.draw_svg(
             &remote_svg,
             remote_rect
                 .with_origin(Point::new(x + 5.0, 0.0))
                 .inflate(-5.0, -5.0),
-            Some(
-                data.config
-                    .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
-            ),
+            Some(icon_color),
         );
         if let Some(text_layout) = remote_text.as_ref() {
             ctx.draw_text(
@@ -179,19 +835,25 @@ impl Widget<LapceWindowData> for Title {
         x += remote_rect.width();
         let command_rect =
             command_rect.with_size(Size::new(x - command_rect.x0, size.height));
-        self.commands.push((
+        let remote_cmd = if status == Some(ProxyStatus::Disconnected) {
+            LapceWorkbenchCommand::ReconnectWorkspace
+        } else {
+            LapceWorkbenchCommand::ConnectSshHost
+        };
+        self.add_menu(
+            TitleMenu::Remote,
             command_rect,
             Command::new(
                 LAPCE_NEW_COMMAND,
                 LapceCommandNew {
-                    cmd: LapceWorkbenchCommand::ConnectSshHost.to_string(),
+                    cmd: remote_cmd.to_string(),
                     palette_desc: None,
                     data: None,
                     target: CommandTarget::Workbench,
                 },
                 Target::Widget(data.active_id),
             ),
-        ));
+        );
 
         let command_rect = Size::ZERO.to_rect().with_origin(Point::new(x, 0.0));
 
@@ -235,7 +897,7 @@ impl Widget<LapceWindowData> for Title {
             Point::new(x, (size.height - text_layout.size().height) / 2.0),
         );
         x += text_layout.size().width + padding;
-        let menu_items = vec![
+        let mut menu_items = vec![
             MenuItem {
                 text: LapceWorkbenchCommand::OpenFolder
                     .get_message()
@@ -261,9 +923,24 @@ impl Widget<LapceWindowData> for Title {
                 },
             },
         ];
+        if tab.workspace.path.is_some() {
+            menu_items.push(MenuItem {
+                text: LapceWorkbenchCommand::CloseWorkspace
+                    .get_message()
+                    .unwrap()
+                    .to_string(),
+                command: LapceCommandNew {
+                    cmd: LapceWorkbenchCommand::CloseWorkspace.to_string(),
+                    palette_desc: None,
+                    data: None,
+                    target: CommandTarget::Workbench,
+                },
+            });
+        }
         let command_rect =
             command_rect.with_size(Size::new(x - command_rect.x0, size.height));
-        self.commands.push((
+        self.add_menu(
+            TitleMenu::Workspace,
             command_rect,
             Command::new(
                 LAPCE_UI_COMMAND,
@@ -273,12 +950,14 @@ impl Widget<LapceWindowData> for Title {
                 ),
                 Target::Auto,
             ),
-        ));
+        );
 
         let line_color = data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER);
         let line = Line::new(Point::new(x, 0.0), Point::new(x, size.height));
         ctx.stroke(line, line_color, 1.0);
 
+        x = self.paint_tabs(ctx, data, x, right);
+
         if tab.source_control.branch != "" {
             let command_rect = Size::ZERO.to_rect().with_origin(Point::new(x, 0.0));
 
@@ -298,7 +977,7 @@ impl Widget<LapceWindowData> for Title {
             x += size.height;
 
             let mut branch = tab.source_control.branch.clone();
-            if tab.source_control.file_diffs.len() > 0 {
+            if tab.source_control.has_changes() {
                 branch += "*";
             }
             let text_layout = ctx
@@ -318,38 +997,233 @@ impl Widget<LapceWindowData> for Title {
             );
             x += text_layout.size().width + padding;
 
-            let command_rect =
-                command_rect.with_size(Size::new(x - command_rect.x0, size.height));
-            let menu_items = tab
-                .source_control
-                .branches
+            // the counts share the padding after the branch
+            let branch_end = if tab.source_control.ahead_behind.is_some() {
+                x - padding / 2.0
+            } else {
+                x
+            };
+            let command_rect = command_rect
+                .with_size(Size::new(branch_end - command_rect.x0, size.height));
+            self.add_menu(
+                TitleMenu::Branch,
+                command_rect,
+                Command::new(
+                    LAPCE_NEW_COMMAND,
+                    LapceCommandNew {
+                        cmd: LapceWorkbenchCommand::BranchMenu.to_string(),
+                        palette_desc: None,
+                        data: None,
+                        target: CommandTarget::Workbench,
+                    },
+                    Target::Widget(data.active_id),
+                ),
+            );
+
+            // without an upstream there's nothing to sync with
+            if let Some((ahead, behind)) = tab.source_control.ahead_behind {
+                let sync_rect = Size::new(0.0, size.height)
+                    .to_rect()
+                    .with_origin(Point::new(branch_end, 0.0));
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout(format!("\u{2191}{} \u{2193}{}", ahead, behind))
+                    .font(FontFamily::SYSTEM_UI, 13.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap();
+                let sync_rect = sync_rect.with_size(Size::new(
+                    text_layout.size().width + padding * 1.5,
+                    size.height,
+                ));
+                if ctx.is_hot() && sync_rect.contains(self.mouse_pos) {
+                    ctx.fill(
+                        sync_rect,
+                        data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
+                    );
+                }
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(x, (size.height - text_layout.size().height) / 2.0),
+                );
+                x = sync_rect.x1;
+
+                let menu_items: Vec<MenuItem> = [
+                    LapceWorkbenchCommand::GitFetch,
+                    LapceWorkbenchCommand::GitPull,
+                    LapceWorkbenchCommand::GitPush,
+                ]
                 .iter()
-                .map(|b| MenuItem {
-                    text: b.to_string(),
+                .filter(|cmd| {
+                    cmd.required_capability()
+                        .map(|capability| tab.proxy.has_capability(capability))
+                        .unwrap_or(true)
+                })
+                .map(|cmd| MenuItem {
+                    text: cmd.get_message().unwrap().to_string(),
                     command: LapceCommandNew {
-                        cmd: LapceWorkbenchCommand::CheckoutBranch.to_string(),
+                        cmd: cmd.to_string(),
                         palette_desc: None,
-                        data: Some(json!(b.to_string())),
+                        data: None,
                         target: CommandTarget::Workbench,
                     },
                 })
                 .collect();
-            self.commands.push((
-                command_rect,
-                Command::new(
-                    LAPCE_UI_COMMAND,
-                    LapceUICommand::ShowMenu(
-                        Point::new(command_rect.x0, command_rect.y1),
-                        Arc::new(menu_items),
-                    ),
-                    Target::Auto,
-                ),
-            ));
+                if !menu_items.is_empty() {
+                    self.commands.push((
+                        sync_rect,
+                        Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::ShowMenu(
+                                Point::new(sync_rect.x0, sync_rect.y1),
+                                Arc::new(menu_items),
+                            ),
+                            Target::Auto,
+                        ),
+                    ));
+                }
+            }
 
             let line_color =
                 data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER);
             let line = Line::new(Point::new(x, 0.0), Point::new(x, size.height));
             ctx.stroke(line, line_color, 1.0);
         }
+
+        self.paint_active_file(ctx, data, x, right);
+        ctx.restore().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::Modifiers;
+
+    use super::*;
+    use crate::harness::{mouse_down, Harness, HarnessWidget, RecordingCtx};
+
+    impl HarnessWidget<LapceWindowData> for Title {
+        fn event(
+            &mut self,
+            ctx: &mut RecordingCtx,
+            event: &Event,
+            data: &mut LapceWindowData,
+        ) {
+            self.click_or_command(ctx, event, data);
+        }
+    }
+
+    /// A title with its branch part at `rect`. The parts are laid out as
+    /// they're painted, which takes a window, so it's added as `paint` does.
+    fn title_with_branch(rect: Rect) -> Title {
+        let mut title = Title::new();
+        title.add_menu(
+            TitleMenu::Branch,
+            rect,
+            Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowMenu(
+                    Point::new(rect.x0, rect.y1),
+                    Arc::new(Vec::new()),
+                ),
+                Target::Auto,
+            ),
+        );
+        title.right = 800.0;
+        title
+    }
+
+    /// Where the menu the title asked for opens.
+    fn menu_origin(harness: &Harness) -> Option<Point> {
+        harness
+            .ui_commands()
+            .into_iter()
+            .find_map(|(cmd, _)| match cmd {
+                LapceUICommand::ShowMenu(origin, _) => Some(*origin),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_click_part_shows_its_menu() {
+        let mut harness = Harness::new();
+        let mut window = harness.window();
+        let mut title = title_with_branch(Rect::new(100.0, 0.0, 200.0, 28.0));
+        let click = mouse_down(Point::new(150.0, 14.0), 1, Modifiers::empty());
+        harness.send_window(&mut title, &mut window, &click);
+
+        assert_eq!(menu_origin(&harness), Some(Point::new(100.0, 28.0)));
+        assert_eq!(harness.ctx.titlebar, None);
+    }
+
+    #[test]
+    fn test_click_elsewhere_drags_window() {
+        let mut harness = Harness::new();
+        let mut window = harness.window();
+        let mut title = title_with_branch(Rect::new(100.0, 0.0, 200.0, 28.0));
+        let pos = Point::new(500.0, 14.0);
+        harness.send_window(
+            &mut title,
+            &mut window,
+            &mouse_down(pos, 1, Modifiers::empty()),
+        );
+        assert_eq!(harness.ctx.titlebar, Some(true));
+        assert!(harness.ui_commands().is_empty());
+
+        harness.clear();
+        harness.send_window(
+            &mut title,
+            &mut window,
+            &mouse_down(pos, 2, Modifiers::empty()),
+        );
+        assert!(matches!(
+            harness.ui_commands()[..],
+            [(LapceUICommand::WindowMaximizeToggle, _)]
+        ));
+    }
+
+    #[test]
+    fn test_title_menu_from_the_keyboard() {
+        let mut harness = Harness::new();
+        let mut window = harness.window();
+        let mut title = title_with_branch(Rect::new(100.0, 0.0, 200.0, 28.0));
+        let show = Event::Command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ShowTitleMenu(TitleMenu::Branch),
+            Target::Auto,
+        ));
+        harness.send_window(&mut title, &mut window, &show);
+        assert_eq!(menu_origin(&harness), Some(Point::new(100.0, 28.0)));
+        assert!(harness.ctx.handled);
+
+        // cut off by the window controls, it opens at the top left
+        harness.clear();
+        let mut title = title_with_branch(Rect::new(900.0, 0.0, 1000.0, 28.0));
+        harness.send_window(&mut title, &mut window, &show);
+        assert_eq!(menu_origin(&harness), Some(Point::ZERO));
+
+        // and so it does with the title bar hidden in fullscreen
+        harness.clear();
+        let mut title = title_with_branch(Rect::new(100.0, 0.0, 200.0, 28.0));
+        window.fullscreen = true;
+        Arc::make_mut(&mut window.config)
+            .window
+            .fullscreen_hides_chrome = true;
+        harness.send_window(&mut title, &mut window, &show);
+        assert_eq!(menu_origin(&harness), Some(Point::ZERO));
+
+        // a part that isn't shown has no menu
+        harness.clear();
+        let show = Event::Command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ShowTitleMenu(TitleMenu::Remote),
+            Target::Auto,
+        ));
+        harness.send_window(&mut title, &mut window, &show);
+        assert_eq!(menu_origin(&harness), None);
     }
 }
@@ -0,0 +1,188 @@
+use std::{path::PathBuf, sync::Arc};
+
+use xi_rope::{spans::Spans, Rope};
+
+use crate::buffer::{line_styles, Style};
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Finds the whole word occurrences of the todo tokens in the line, returned as
+/// byte ranges together with the token.
+pub fn find_todo_tokens(
+    line: &str,
+    tokens: &[String],
+) -> Vec<(usize, usize, String)> {
+    let mut found = Vec::new();
+    for token in tokens.iter().filter(|t| !t.is_empty()) {
+        for (start, _) in line.match_indices(token.as_str()) {
+            let end = start + token.len();
+            let before = line[..start].chars().next_back();
+            let after = line[end..].chars().next();
+            if before.map(|c| !is_word_char(c)).unwrap_or(true)
+                && after.map(|c| !is_word_char(c)).unwrap_or(true)
+            {
+                found.push((start, end, token.to_string()));
+            }
+        }
+    }
+    found.sort_by_key(|(start, _, _)| *start);
+    found
+}
+
+/// Only keeps the occurrences that sit inside a comment span of the line.
+pub fn filter_comments(
+    found: Vec<(usize, usize, String)>,
+    line_styles: &[(usize, usize, Style)],
+) -> Vec<(usize, usize, String)> {
+    found
+        .into_iter()
+        .filter(|(start, _, _)| {
+            line_styles.iter().any(|(style_start, style_end, style)| {
+                style_start <= start
+                    && start < style_end
+                    && style
+                        .fg_color
+                        .as_ref()
+                        .map(|c| c.starts_with("comment"))
+                        .unwrap_or(false)
+            })
+        })
+        .collect()
+}
+
+/// The regex used to search the todo tokens across the workspace.
+pub fn todo_pattern(tokens: &[String]) -> Option<String> {
+    let tokens: Vec<String> = tokens
+        .iter()
+        .filter(|t| !t.is_empty())
+        .map(|t| regex::escape(t))
+        .collect();
+    if tokens.is_empty() {
+        return None;
+    }
+    Some(format!(r"\b({})\b", tokens.join("|")))
+}
+
+/// An open file to look for todos in, taken from its buffer so that its
+/// lines are scanned away from the UI thread.
+pub struct TodoBuffer {
+    pub path: PathBuf,
+    pub rope: Rope,
+    /// The highlights of the file, to only keep the tokens in comments.
+    pub styles: Option<Arc<Spans<Style>>>,
+}
+
+impl TodoBuffer {
+    /// The todo tokens of the file, with their line, their byte offset in
+    /// the line and the content of the line.
+    pub fn todos(&self, tokens: &[String]) -> Vec<(usize, usize, String, String)> {
+        let mut todos = Vec::new();
+        let mut offset = 0;
+        for (line, content) in self.rope.lines_raw(..).enumerate() {
+            let mut found = find_todo_tokens(&content, tokens);
+            if !found.is_empty() {
+                if let Some(styles) = self.styles.as_ref() {
+                    let end = offset + content.len();
+                    found =
+                        filter_comments(found, &line_styles(styles, offset, end));
+                }
+            }
+            for (start, _, token) in found {
+                todos.push((line, start, token, content.to_string()));
+            }
+            offset += content.len();
+        }
+        todos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::{spans::SpansBuilder, Interval};
+
+    use super::*;
+
+    fn tokens() -> Vec<String> {
+        vec!["TODO".to_string(), "FIXME".to_string(), "".to_string()]
+    }
+
+    fn style(color: &str) -> Style {
+        Style {
+            fg_color: Some(color.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_find_todo_tokens() {
+        assert_eq!(
+            find_todo_tokens("// TODO: fix FIXME, not TODOS or MY_TODO", &tokens()),
+            vec![(3, 7, "TODO".to_string()), (13, 18, "FIXME".to_string())]
+        );
+        assert_eq!(
+            find_todo_tokens("é TODO", &tokens()),
+            vec![(3, 7, "TODO".to_string())]
+        );
+        assert!(find_todo_tokens("éTODO", &tokens()).is_empty());
+        assert!(find_todo_tokens("TODO", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_comments() {
+        let found = vec![(3, 7, "TODO".to_string()), (20, 24, "TODO".to_string())];
+        let styles = vec![(0, 10, style("comment.line")), (15, 30, style("string"))];
+        assert_eq!(
+            filter_comments(found.clone(), &styles),
+            vec![(3, 7, "TODO".to_string())]
+        );
+        assert!(filter_comments(found, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_todo_pattern() {
+        assert_eq!(
+            todo_pattern(&["TODO".to_string(), "C++".to_string()]),
+            Some(r"\b(TODO|C\+\+)\b".to_string())
+        );
+        assert_eq!(todo_pattern(&["".to_string()]), None);
+        assert_eq!(todo_pattern(&[]), None);
+    }
+
+    #[test]
+    fn test_todo_buffer() {
+        let rope =
+            Rope::from("let a = 1; // TODO\nlet todo = \"TODO\";\n// FIXME\n");
+        let mut buffer = TodoBuffer {
+            path: PathBuf::from("/w/a.rs"),
+            rope,
+            styles: None,
+        };
+        let lines = |buffer: &TodoBuffer| {
+            buffer
+                .todos(&tokens())
+                .into_iter()
+                .map(|(line, start, token, _)| (line, start, token))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(&buffer),
+            vec![
+                (0, 14, "TODO".to_string()),
+                (1, 12, "TODO".to_string()),
+                (2, 3, "FIXME".to_string()),
+            ]
+        );
+
+        // the todo in the string isn't in a comment
+        let mut styles = SpansBuilder::new(buffer.rope.len());
+        styles.add_span(Interval::new(11, 18), style("comment"));
+        styles.add_span(Interval::new(30, 36), style("string"));
+        styles.add_span(Interval::new(38, 46), style("comment"));
+        buffer.styles = Some(Arc::new(styles.build()));
+        assert_eq!(
+            lines(&buffer),
+            vec![(0, 14, "TODO".to_string()), (2, 3, "FIXME".to_string())]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use xi_rope::Rope;
+
+use crate::state::fnv_hash;
+
+/// The most undo steps kept for a file across sessions, whatever their size.
+pub const MAX_STEPS: usize = 500;
+
+/// An edit that takes the text one undo step back: the bytes `start..end`
+/// are replaced by `text`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UndoStep {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl UndoStep {
+    /// The step from `newer` back to `older`, spanning what differs between
+    /// them.
+    pub fn between(newer: &str, older: &str) -> Self {
+        let prefix = newer
+            .char_indices()
+            .zip(older.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((i, _), _)| i)
+            .unwrap_or_else(|| newer.len().min(older.len()));
+        let prefix = floor_char_boundary(older, prefix);
+        let suffix = newer[prefix..]
+            .chars()
+            .rev()
+            .zip(older[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+        Self {
+            start: prefix,
+            end: newer.len() - suffix,
+            text: older[prefix..older.len() - suffix].to_string(),
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> Option<String> {
+        if self.start > self.end
+            || !text.is_char_boundary(self.start)
+            || !text.is_char_boundary(self.end)
+        {
+            return None;
+        }
+        Some(format!(
+            "{}{}{}",
+            &text[..self.start],
+            self.text,
+            &text[self.end..]
+        ))
+    }
+
+    /// The edit redoing this step, made on the text it leads to.
+    pub fn inverse(&self, newer: &str) -> Self {
+        Self {
+            start: self.start,
+            end: self.start + self.text.len(),
+            text: newer[self.start..self.end].to_string(),
+        }
+    }
+
+    /// About what the step takes to keep.
+    pub fn size(&self) -> usize {
+        self.text.len() + 2 * std::mem::size_of::<usize>()
+    }
+}
+
+/// The undo steps of a file, the latest first, for the text whose hash is
+/// `content_hash`. A file changed outside of the editor has another hash,
+/// and its history is dropped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UndoHistory {
+    pub content_hash: u64,
+    pub steps: Vec<UndoStep>,
+}
+
+impl UndoHistory {
+    /// Keeps the latest steps that fit in `max_size` bytes.
+    pub fn new(content: &Rope, mut steps: Vec<UndoStep>, max_size: usize) -> Self {
+        let mut size = 0;
+        let keep = steps
+            .iter()
+            .take(MAX_STEPS)
+            .take_while(|step| {
+                size += step.size();
+                size <= max_size
+            })
+            .count();
+        steps.truncate(keep);
+        Self {
+            content_hash: content_hash(content),
+            steps,
+        }
+    }
+
+    pub fn matches(&self, content: &Rope) -> bool {
+        self.content_hash == content_hash(content)
+    }
+}
+
+pub fn content_hash(content: &Rope) -> u64 {
+    fnv_hash(content.iter_chunks(..).map(|chunk| chunk.as_bytes()))
+}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_round_trip() {
+        let step = UndoStep::between("hello brave world", "hello world");
+        assert_eq!(step.start, 6);
+        assert_eq!(step.end, 12);
+        assert_eq!(step.text, "");
+        assert_eq!(step.apply("hello brave world").unwrap(), "hello world");
+        let redo = step.inverse("hello brave world");
+        assert_eq!(redo.apply("hello world").unwrap(), "hello brave world");
+    }
+
+    #[test]
+    fn test_step_with_multi_byte_characters() {
+        let step = UndoStep::between("aéb", "aüb");
+        assert_eq!(step.text, "ü");
+        assert_eq!(step.apply("aéb").unwrap(), "aüb");
+        let step = UndoStep::between("ééé", "éé");
+        assert_eq!(step.apply("ééé").unwrap(), "éé");
+    }
+
+    #[test]
+    fn test_step_on_other_text_fails() {
+        let step = UndoStep::between("aéb", "ab");
+        assert_eq!(step.apply("a"), None);
+    }
+
+    #[test]
+    fn test_history_keeps_latest_steps_in_budget() {
+        let content = Rope::from("x");
+        let step = |text: &str| UndoStep {
+            start: 0,
+            end: 0,
+            text: text.to_string(),
+        };
+        let steps = vec![step("new"), step(&"o".repeat(100)), step("old")];
+        let history = UndoHistory::new(&content, steps, 64);
+        assert_eq!(history.steps, vec![step("new")]);
+        assert!(history.matches(&content));
+        assert!(!history.matches(&Rope::from("y")));
+    }
+}
@@ -5,8 +5,10 @@ use crate::{
     data::{LapceTabData, LapceTabLens, LapceWindowData},
     editor::EditorUIState,
     explorer::{FileExplorer, FileExplorerState},
+    logging,
     menu::Menu,
     panel::{LapcePanel, PanelPosition, PanelProperty},
+    recovery::{self, SessionRecovery},
     state::{LapceWorkspace, LapceWorkspaceType},
     tab::{LapceTabHeader, LapceTabNew},
     theme::OldLapceTheme,
@@ -20,10 +22,19 @@ use druid::{
     widget::{LensWrap, WidgetExt},
     BoxConstraints, Command, Env, Event, EventCtx, FontDescriptor, FontFamily,
     LayoutCtx, Lens, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect, RenderContext,
-    Size, Target, UpdateCtx, Widget, WidgetId, WidgetPod, WindowId,
+    Size, Target, UpdateCtx, Widget, WidgetId, WidgetPod, WindowId, WindowState,
 };
 use parking_lot::Mutex;
-use std::{collections::HashMap, ops::Index, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::Index,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Arc,
+};
+
+/// How close to the top edge the mouse reveals the hidden title bar.
+const TITLE_REVEAL_EDGE: f64 = 2.0;
 
 pub struct LapceWindowNew {
     pub title: WidgetPod<LapceWindowData, Box<dyn Widget<LapceWindowData>>>,
@@ -35,6 +46,10 @@ pub struct LapceWindowNew {
             LensWrap<LapceWindowData, LapceTabData, LapceTabLens, LapceTabHeader>,
         >,
     >,
+    /// The window state to go back to when leaving fullscreen.
+    restore_state: Option<WindowState>,
+    /// Whether the hidden title bar is shown because the mouse is on it.
+    title_revealed: bool,
 }
 
 impl LapceWindowNew {
@@ -64,6 +79,8 @@ impl LapceWindowNew {
             tabs,
             tab_headers,
             menu: WidgetPod::new(menu.boxed()),
+            restore_state: None,
+            title_revealed: false,
         }
     }
 
@@ -78,14 +95,61 @@ impl LapceWindowNew {
             let tab = data.tabs.get(&data.active_id).unwrap();
             tab.db.save_workspace(&tab);
         }
+        self.open_tab(ctx, data, workspace, replace_current);
+    }
+
+    /// Builds the active tab again from the layout of its workspace saved
+    /// before, as a panic left its widgets and data halfway through a change.
+    /// The unsaved changes are backed up right away, and put back in the
+    /// files of the new tab.
+    fn reload_tab(&mut self, ctx: &mut EventCtx, data: &mut LapceWindowData) {
+        let tab = data.tabs.get(&data.active_id).unwrap();
+        let workspace = (*tab.workspace).clone();
+        log::error!("reloading the tab of {} after a panic", workspace);
+        let unsaved: Vec<(PathBuf, String)> = tab
+            .main_split
+            .open_files
+            .iter()
+            .filter(|(_, buffer)| buffer.loaded && buffer.dirty)
+            .map(|(path, buffer)| (path.clone(), buffer.rope.to_string()))
+            .collect();
+        for (path, content) in unsaved.iter() {
+            if let Err(e) = recovery::write_backup(&workspace, path, content) {
+                log::error!("can't back up {:?}: {}", path, e);
+            }
+        }
+
+        self.open_tab(ctx, data, workspace, true);
+        let tab_id = data.active_id;
+        let tab = data.tabs.get_mut(&tab_id).unwrap();
+        for (path, content) in unsaved {
+            tab.main_split
+                .pending_backups
+                .insert(path.clone(), Arc::new(content));
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::OpenFile(path),
+                Target::Widget(tab_id),
+            ));
+        }
+    }
+
+    fn open_tab(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceWindowData,
+        workspace: LapceWorkspace,
+        replace_current: bool,
+    ) {
         let tab_id = WidgetId::next();
-        let tab_data = LapceTabData::new(
+        let mut tab_data = LapceTabData::new(
             tab_id,
             workspace,
             data.db.clone(),
             data.keypress.clone(),
             ctx.get_external_handle(),
         );
+        tab_data.chrome_hidden = data.chrome_hidden();
         let tab = LapceTabNew::new(&tab_data).lens(LapceTabLens(tab_id));
         let tab_header = LapceTabHeader::new().lens(LapceTabLens(tab_id));
         data.tabs.insert(tab_id, tab_data);
@@ -114,7 +178,82 @@ impl LapceWindowNew {
         ctx.children_changed();
         ctx.set_handled();
         ctx.request_layout();
-        return;
+    }
+
+    /// Opens the workspaces, files and terminals picked of the session that
+    /// didn't end cleanly, and puts back the unsaved changes.
+    fn recover_session(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceWindowData,
+        recovery: &SessionRecovery,
+    ) {
+        let mut active_tab = None;
+        for (i, (workspace, info, terminals, active)) in
+            recovery.restored_workspaces().into_iter().enumerate()
+        {
+            if let Some(info) = info.as_ref() {
+                if let Err(e) = data.db.insert_workspace(&workspace, info) {
+                    log::error!("can't restore the layout of {}: {}", workspace, e);
+                }
+            }
+            self.new_tab(ctx, data, workspace, i == 0);
+            let tab = data.tabs.get(&data.active_id).unwrap();
+            if terminals > 0 {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::InitTerminalPanel(false, None),
+                    Target::Widget(tab.terminal.split_id()),
+                ));
+                for _ in 1..terminals {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::NewTerminalTab(None),
+                        Target::Widget(tab.terminal.widget_id),
+                    ));
+                }
+            }
+            if active {
+                active_tab = Some(data.active_id);
+            }
+        }
+        if let Some(tab_id) = active_tab {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::FocusTabId(tab_id),
+                Target::Auto,
+            ));
+        }
+
+        for backup in recovery.restored_backups() {
+            let tab_id = data
+                .tabs
+                .values()
+                .find(|tab| {
+                    tab.workspace
+                        .path
+                        .as_ref()
+                        .map(|root| backup.path.starts_with(root))
+                        .unwrap_or(false)
+                })
+                .map(|tab| tab.id)
+                .or(active_tab)
+                .unwrap_or(data.active_id);
+            let tab = data.tabs.get_mut(&tab_id).unwrap();
+            tab.main_split
+                .pending_backups
+                .insert(backup.path.clone(), Arc::new(backup.content.clone()));
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::OpenFile(backup.path.clone()),
+                Target::Widget(tab_id),
+            ));
+        }
+
+        if let Err(e) = recovery.archive() {
+            log::error!("can't keep the recovery data: {}", e);
+        }
+        data.db.save_tabs(data);
     }
 
     pub fn close_index_tab(
@@ -183,6 +322,32 @@ impl Widget<LapceWindowData> for LapceWindowNew {
         data: &mut LapceWindowData,
         env: &Env,
     ) {
+        if data.chrome_hidden() {
+            if let Event::MouseMove(mouse_event) = event {
+                let revealed = if self.title_revealed {
+                    mouse_event.pos.y < self.title.layout_rect().height()
+                } else {
+                    mouse_event.pos.y <= TITLE_REVEAL_EDGE
+                };
+                if revealed != self.title_revealed {
+                    self.title_revealed = revealed;
+                    ctx.request_paint();
+                }
+            }
+            if self.title_revealed {
+                match event {
+                    Event::MouseDown(_)
+                    | Event::MouseUp(_)
+                    | Event::MouseMove(_)
+                    | Event::Wheel(_) => {
+                        self.menu.event(ctx, event, data, env);
+                        self.title.event(ctx, event, data, env);
+                        return;
+                    }
+                    _ => (),
+                }
+            }
+        }
         match event {
             Event::WindowConnected => {
                 ctx.submit_command(Command::new(
@@ -191,6 +356,11 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                     Target::Widget(data.active_id),
                 ));
             }
+            Event::WindowCloseRequested => {
+                for (_, tab) in data.tabs.iter() {
+                    tab.db.save_workspace(tab);
+                }
+            }
             Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
                 match command {
@@ -203,12 +373,68 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                                 .unwrap_or_default(),
                         );
                         for (_, tab) in data.tabs.iter_mut() {
-                            tab.config = Arc::new(
+                            let config = Arc::new(
                                 Config::load(&tab.workspace.clone())
                                     .unwrap_or_default(),
                             );
+                            if config.files.exclude != tab.config.files.exclude {
+                                tab.proxy.set_file_excludes(&config.files.exclude);
+                                tab.file_explorer.refresh(
+                                    tab.id,
+                                    tab.proxy.clone(),
+                                    ctx.get_external_handle(),
+                                );
+                            }
+                            tab.config = config;
                         }
                         Arc::make_mut(&mut data.keypress).update_keymaps();
+                        logging::configure(&data.config.log);
+                        data.update_chrome();
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ToggleFullScreen => {
+                        // druid has no fullscreen state of its own, and the
+                        // window has no system title bar, so fullscreen is a
+                        // maximized window with the chrome hidden
+                        let window = ctx.window().clone();
+                        if data.fullscreen {
+                            window.set_window_state(
+                                self.restore_state
+                                    .take()
+                                    .unwrap_or(WindowState::Restored),
+                            );
+                        } else {
+                            self.restore_state = Some(window.get_window_state());
+                            window.set_window_state(WindowState::Maximized);
+                        }
+                        data.fullscreen = !data.fullscreen;
+                        data.update_chrome();
+                        self.title_revealed = false;
+                        ctx.request_layout();
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::WindowMinimize => {
+                        ctx.window().set_window_state(WindowState::Minimized);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::WindowMaximizeToggle => {
+                        // fullscreen is a maximized window already
+                        if !data.fullscreen {
+                            let window = ctx.window().clone();
+                            let state = match window.get_window_state() {
+                                WindowState::Maximized => WindowState::Restored,
+                                _ => WindowState::Maximized,
+                            };
+                            window.set_window_state(state);
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::WindowClose => {
+                        // goes through druid so that the workspaces are saved
+                        // on WindowCloseRequested
+                        ctx.submit_command(
+                            druid::commands::CLOSE_WINDOW.to(ctx.window_id()),
+                        );
                         ctx.set_handled();
                     }
                     LapceUICommand::ReloadWindow => {
@@ -216,6 +442,19 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                         self.new_tab(ctx, data, (*tab.workspace).clone(), true);
                         return;
                     }
+                    LapceUICommand::RecoverSession(recovery) => {
+                        self.recover_session(ctx, data, recovery);
+                        ctx.set_handled();
+                        return;
+                    }
+                    LapceUICommand::DiscardRecovery(recovery) => {
+                        if let Err(e) = recovery.archive() {
+                            log::error!("can't keep the recovery data: {}", e);
+                        }
+                        data.db.save_tabs(data);
+                        ctx.set_handled();
+                        return;
+                    }
                     LapceUICommand::HideMenu => {
                         ctx.set_handled();
                         let menu = Arc::make_mut(&mut data.menu);
@@ -235,6 +474,10 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                         ));
                     }
                     LapceUICommand::SetWorkspace(workspace) => {
+                        if let LapceWorkspaceType::RemoteSSH(host) = &workspace.kind
+                        {
+                            Config::add_recent_ssh_host(host);
+                        }
                         let mut workspaces =
                             Config::recent_workspaces().unwrap_or(Vec::new());
 
@@ -272,6 +515,12 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                         self.new_tab(ctx, data, LapceWorkspace::default(), false);
                         return;
                     }
+                    LapceUICommand::CloseWorkspace => {
+                        // the proxy of the workspace is stopped along with its
+                        // terminals and watchers
+                        self.new_tab(ctx, data, LapceWorkspace::default(), true);
+                        return;
+                    }
                     LapceUICommand::CloseTab => {
                         self.close_tab(ctx, data);
                         return;
@@ -280,6 +529,16 @@ impl Widget<LapceWindowData> for LapceWindowNew {
                         self.close_tab_id(ctx, data, *tab_id);
                         return;
                     }
+                    LapceUICommand::FocusTabIndex(index) => {
+                        if let Some(tab) = self.tabs.get(*index) {
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::FocusTabId(tab.id()),
+                                Target::Auto,
+                            ));
+                        }
+                        ctx.set_handled();
+                    }
                     LapceUICommand::FocusTabId(tab_id) => {
                         for (i, tab) in self.tabs.iter().enumerate() {
                             if tab_id == &tab.id() {
@@ -353,7 +612,19 @@ impl Widget<LapceWindowData> for LapceWindowNew {
             _ => (),
         }
         self.menu.event(ctx, event, data, env);
-        self.tabs[data.active].event(ctx, event, data, env);
+        // a panic in a tab is logged by the panic hook, and caught here so
+        // that it doesn't take the other tabs and windows down with it. What
+        // the tab was changing is left halfway, so the tab is built again.
+        let active = data.active;
+        if panic::catch_unwind(AssertUnwindSafe(|| {
+            self.tabs[active].event(ctx, event, data, env);
+        }))
+        .is_err()
+        {
+            log::error!("recovered from a panic while handling {:?}", event);
+            self.reload_tab(ctx, data);
+            return;
+        }
         match event {
             Event::MouseDown(_)
             | Event::MouseUp(_)
@@ -372,7 +643,14 @@ impl Widget<LapceWindowData> for LapceWindowNew {
         for tab_header in self.tab_headers.iter_mut() {
             tab_header.event(ctx, event, data, env);
         }
-        self.title.event(ctx, event, data, env);
+        match event {
+            Event::MouseDown(_)
+            | Event::MouseUp(_)
+            | Event::MouseMove(_)
+            | Event::Wheel(_)
+                if data.chrome_hidden() => {}
+            _ => self.title.event(ctx, event, data, env),
+        }
     }
 
     fn lifecycle(
@@ -440,14 +718,20 @@ impl Widget<LapceWindowData> for LapceWindowNew {
 
         let title_size = self.title.layout(ctx, bc, data, env);
         self.title.set_origin(ctx, data, env, Point::ZERO);
+        // with the chrome hidden, the title is an overlay on top of the tabs
+        let title_height = if data.chrome_hidden() {
+            0.0
+        } else {
+            title_size.height
+        };
 
         let (tab_size, tab_origin) = if self.tabs.len() > 1 {
             let tab_height = 25.0;
             let tab_size = Size::new(
                 self_size.width,
-                self_size.height - tab_height - title_size.height,
+                self_size.height - tab_height - title_height,
             );
-            let tab_origin = Point::new(0.0, tab_height + title_size.height);
+            let tab_origin = Point::new(0.0, tab_height + title_height);
 
             let num = self.tabs.len();
             let section = self_size.width / num as f64;
@@ -456,10 +740,10 @@ impl Widget<LapceWindowData> for LapceWindowNew {
             for (i, tab_header) in self.tab_headers.iter_mut().enumerate() {
                 let bc = BoxConstraints::tight(Size::new(section, tab_height));
                 tab_header.layout(ctx, &bc, data, env);
-                let mut origin = Point::new(section * i as f64, title_size.height);
+                let mut origin = Point::new(section * i as f64, title_height);
                 let header = tab_header.widget().child();
                 if let Some(o) = header.origin() {
-                    origin = Point::new(o.x, title_size.height);
+                    origin = Point::new(o.x, title_height);
                     drag = Some((i, header.mouse_pos));
                 }
                 tab_header.set_origin(ctx, data, env, origin);
@@ -486,16 +770,11 @@ impl Widget<LapceWindowData> for LapceWindowNew {
             for (i, tab_header) in self.tab_headers.iter_mut().enumerate() {
                 let bc = BoxConstraints::tight(Size::new(self_size.width, 0.0));
                 tab_header.layout(ctx, &bc, data, env);
-                tab_header.set_origin(
-                    ctx,
-                    data,
-                    env,
-                    Point::new(0.0, title_size.height),
-                );
+                tab_header.set_origin(ctx, data, env, Point::new(0.0, title_height));
             }
             let tab_size =
-                Size::new(self_size.width, self_size.height - title_size.height);
-            (tab_size, Point::new(0.0, title_size.height))
+                Size::new(self_size.width, self_size.height - title_height);
+            (tab_size, Point::new(0.0, title_height))
         };
 
         let start = std::time::SystemTime::now();
@@ -519,7 +798,11 @@ impl Widget<LapceWindowData> for LapceWindowNew {
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceWindowData, env: &Env) {
         let start = std::time::SystemTime::now();
 
-        let title_height = self.title.layout_rect().height();
+        let title_height = if data.chrome_hidden() {
+            0.0
+        } else {
+            self.title.layout_rect().height()
+        };
 
         let tab_height = 25.0;
         let size = ctx.size();
@@ -577,12 +860,15 @@ impl Widget<LapceWindowData> for LapceWindowNew {
             ctx.stroke(line, line_color, 1.0);
         }
 
-        self.title.paint(ctx, data, env);
-        let line = Line::new(
-            Point::new(0.0, title_height - 0.5),
-            Point::new(size.width, title_height - 0.5),
-        );
-        ctx.stroke(line, line_color, 1.0);
+        if !data.chrome_hidden() || self.title_revealed {
+            self.title.paint(ctx, data, env);
+            let title_height = self.title.layout_rect().height();
+            let line = Line::new(
+                Point::new(0.0, title_height - 0.5),
+                Point::new(size.width, title_height - 0.5),
+            );
+            ctx.stroke(line, line_color, 1.0);
+        }
 
         self.menu.paint(ctx, data, env);
     }
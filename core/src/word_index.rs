@@ -0,0 +1,248 @@
+use std::{collections::HashSet, ops::Range};
+
+use lsp_types::{CompletionItem, CompletionItemKind};
+use xi_rope::Rope;
+
+/// Words shorter than this aren't worth completing.
+const MIN_WORD_LEN: usize = 3;
+
+/// How many lines around the cursor count as near it.
+const NEAR_LINES: usize = 100;
+
+/// How many words are offered at most.
+const MAX_ITEMS: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct WordEntry {
+    /// How many times the word is in the buffer.
+    count: usize,
+    /// The edit that last touched a line with the word, 0 if it was only
+    /// loaded.
+    last_edit: u64,
+}
+
+/// The words of a buffer, for completion without a language server. It's
+/// updated from the lines each edit touches, not by scanning the buffer.
+#[derive(Clone, Debug, Default)]
+pub struct WordIndex {
+    words: im::HashMap<String, WordEntry>,
+    edits: u64,
+}
+
+impl WordIndex {
+    pub fn new(rope: &Rope) -> Self {
+        let mut index = WordIndex::default();
+        index.add(&rope.slice_to_cow(0..rope.len()), 0);
+        index
+    }
+
+    /// Updates the index after an edit replaced the `old_lines` of `old` with
+    /// the `new_lines` of `new`.
+    pub fn update(
+        &mut self,
+        old: &Rope,
+        old_lines: Range<usize>,
+        new: &Rope,
+        new_lines: Range<usize>,
+    ) {
+        self.edits += 1;
+        self.remove(&lines(old, old_lines));
+        let edit = self.edits;
+        self.add(&lines(new, new_lines), edit);
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// The words starting with `prefix`, ignoring the case, with the edit
+    /// that last touched each. `prefix` itself is left out.
+    pub fn matches<'a>(
+        &'a self,
+        prefix: &str,
+    ) -> impl Iterator<Item = (&'a str, u64)> + 'a {
+        let prefix = prefix.to_lowercase();
+        self.words.iter().filter_map(move |(word, entry)| {
+            if word.len() > prefix.len() && word.to_lowercase().starts_with(&prefix)
+            {
+                Some((word.as_str(), entry.last_edit))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn add(&mut self, text: &str, edit: u64) {
+        for word in words(text) {
+            let entry = self.words.entry(word.to_string()).or_insert(WordEntry {
+                count: 0,
+                last_edit: edit,
+            });
+            entry.count += 1;
+            entry.last_edit = entry.last_edit.max(edit);
+        }
+    }
+
+    fn remove(&mut self, text: &str) {
+        for word in words(text) {
+            if let Some(entry) = self.words.get_mut(word) {
+                if entry.count > 1 {
+                    entry.count -= 1;
+                } else {
+                    self.words.remove(word);
+                }
+            }
+        }
+    }
+}
+
+/// The words of the text: runs of letters, digits and underscores that
+/// don't start with a digit.
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| {
+            word.chars().count() >= MIN_WORD_LEN
+                && !word.starts_with(|c: char| c.is_ascii_digit())
+        })
+}
+
+/// How near to `line` each word of the lines around it is, the nearest
+/// first. Only the lines around are read.
+pub fn near_words(rope: &Rope, line: usize) -> Vec<(String, usize)> {
+    let start = line.saturating_sub(NEAR_LINES);
+    let end = line + NEAR_LINES + 1;
+    let mut near: std::collections::HashMap<String, usize> =
+        std::collections::HashMap::new();
+    for (i, text) in lines(rope, start..end).split('\n').enumerate() {
+        let distance = (start + i).max(line) - (start + i).min(line);
+        for word in words(text) {
+            let d = near.entry(word.to_string()).or_insert(distance);
+            *d = (*d).min(distance);
+        }
+    }
+    let mut near: Vec<(String, usize)> = near.into_iter().collect();
+    near.sort_by_key(|(_, distance)| *distance);
+    near
+}
+
+/// The words to complete `prefix` with, as completion items in their order:
+/// the ones near the cursor at `line`, the nearest first, then the others of
+/// the buffer, the latest edited first, then the ones of the `others`
+/// buffers.
+pub fn completion_items(
+    prefix: &str,
+    rope: &Rope,
+    line: usize,
+    index: &WordIndex,
+    others: &[&WordIndex],
+) -> Vec<CompletionItem> {
+    let lower = prefix.to_lowercase();
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+    for (word, _) in near_words(rope, line) {
+        if word.len() > prefix.len()
+            && word.to_lowercase().starts_with(&lower)
+            && seen.insert(word.clone())
+        {
+            words.push(word);
+        }
+    }
+
+    let mut recent: Vec<(&str, u64)> = index
+        .matches(prefix)
+        .filter(|(word, _)| !seen.contains(*word))
+        .collect();
+    recent.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (word, _) in recent {
+        seen.insert(word.to_string());
+        words.push(word.to_string());
+    }
+
+    let mut other: Vec<&str> = others
+        .iter()
+        .flat_map(|index| index.matches(prefix).map(|(word, _)| word))
+        .filter(|word| !seen.contains(*word))
+        .collect();
+    other.sort();
+    other.dedup();
+    words.extend(other.into_iter().map(|word| word.to_string()));
+
+    words
+        .into_iter()
+        .take(MAX_ITEMS)
+        .enumerate()
+        .map(|(i, word)| CompletionItem {
+            label: word,
+            kind: Some(CompletionItemKind::Text),
+            sort_text: Some(format!("{:05}", i)),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn lines(rope: &Rope, lines: Range<usize>) -> String {
+    let start = rope.offset_of_line(lines.start);
+    let end = rope.offset_of_line(lines.end);
+    rope.slice_to_cow(start..end).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let old = Rope::from("let value = 1;\nlet other = value;\n");
+        let mut index = WordIndex::new(&old);
+        assert_eq!(index.len(), 3);
+
+        // the second line becomes "let another = 2;"
+        let new = Rope::from("let value = 1;\nlet another = 2;\n");
+        index.update(&old, 1..2, &new, 1..2);
+        let mut words: Vec<(&str, u64)> = index.matches("").collect();
+        words.sort();
+        assert_eq!(words, vec![("another", 1), ("let", 1), ("value", 0)]);
+
+        let new_2 = Rope::from("let another = 2;\n");
+        index.update(&new, 0..1, &new_2, 0..0);
+        let words: Vec<&str> = index.matches("va").map(|(w, _)| w).collect();
+        assert!(words.is_empty());
+        assert_eq!(index.matches("AN").count(), 1);
+    }
+
+    #[test]
+    fn test_near_words() {
+        let rope = Rope::from("alpha\nbeta\ngamma\nalpha\n");
+        let near = near_words(&rope, 2);
+        assert_eq!(near[0], ("gamma".to_string(), 0));
+        assert!(near.contains(&("alpha".to_string(), 1)));
+        assert!(near.contains(&("beta".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_completion_items() {
+        let mut text = "counter_far\n".to_string();
+        text += &"\n".repeat(200);
+        text += "count\ncounter_near\n";
+        let old = Rope::from(text.as_str());
+        let mut index = WordIndex::new(&old);
+        // counter_edited is typed on the first line
+        let new = Rope::from(format!("counter_edited\n{}", text).as_str());
+        index.update(&old, 0..0, &new, 0..1);
+        let other = WordIndex::new(&Rope::from("counter_other counter_near"));
+
+        let line = new.line_of_offset(new.len()) - 1;
+        let items = completion_items("count", &new, line, &index, &[&other]);
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "counter_near",
+                "counter_edited",
+                "counter_far",
+                "counter_other"
+            ]
+        );
+        assert_eq!(items[1].sort_text.as_deref(), Some("00001"));
+    }
+}
@@ -0,0 +1,931 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use druid::{
+    kurbo::BezPath,
+    piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder},
+    Affine, BoxConstraints, Color, Command, Cursor, Data, Env, Event, EventCtx,
+    FontFamily, LayoutCtx, LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point,
+    Rect, RenderContext, Size, Target, UpdateCtx, Vec2, Widget, WidgetId,
+};
+use lsp_types::{
+    DocumentChangeOperation, DocumentChanges, OneOf, Position, TextEdit, Url,
+    WorkspaceEdit,
+};
+
+use crate::{
+    command::{CommandExecuted, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND},
+    config::LapceTheme,
+    data::LapceTabData,
+    keypress::KeyPressFocus,
+    movement::Movement,
+    search::relative_path,
+    state::Mode,
+};
+
+/// A change of a workspace edit to a few neighbouring lines of a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditHunk {
+    /// The edits making the hunk, as (start, end, text) with the offsets into
+    /// the text of the file.
+    pub edits: Vec<(usize, usize, String)>,
+    /// The first line of the hunk, from 0.
+    pub line: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+    pub excluded: bool,
+}
+
+/// The edits of a workspace edit to one file.
+#[derive(Clone, Debug)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    pub edits: Vec<TextEdit>,
+    /// The text the hunks were made from, none until the file is read.
+    pub content: Option<String>,
+    pub hunks: Vec<EditHunk>,
+    pub excluded: bool,
+    /// Whether the proxy is editing the file.
+    pub applying: bool,
+    /// Why the file can't be edited, e.g. it changed since it was read.
+    pub error: Option<String>,
+}
+
+impl FileEdit {
+    pub fn new(path: PathBuf, edits: Vec<TextEdit>) -> Self {
+        Self {
+            path,
+            edits,
+            content: None,
+            hunks: Vec::new(),
+            excluded: false,
+            applying: false,
+            error: None,
+        }
+    }
+
+    pub fn set_content(&mut self, content: String) {
+        self.hunks = edit_hunks(&content, &self.edits);
+        self.content = Some(content);
+    }
+
+    /// The edits of the hunks that aren't excluded, none if the file is.
+    pub fn included_edits(&self) -> Vec<(usize, usize, String)> {
+        if self.excluded {
+            return Vec::new();
+        }
+        self.hunks
+            .iter()
+            .filter(|hunk| !hunk.excluded)
+            .flat_map(|hunk| hunk.edits.iter().cloned())
+            .collect()
+    }
+}
+
+/// The preview of a workspace edit, e.g. from a code action, where files and
+/// hunks can be left out before it's applied.
+#[derive(Clone)]
+pub struct WorkspaceEditData {
+    pub widget_id: WidgetId,
+    pub tab_id: WidgetId,
+    /// Tells the previews apart, so files read for an older one are dropped.
+    pub id: u64,
+    pub title: String,
+    pub shown: bool,
+    /// Whether it's applied once its files are read, without being shown.
+    pub apply_when_read: bool,
+    pub files: Vec<FileEdit>,
+    /// The row with the keyboard focus.
+    pub active: usize,
+}
+
+impl KeyPressFocus for WorkspaceEditData {
+    fn get_mode(&self) -> Mode {
+        Mode::Insert
+    }
+
+    fn check_condition(&self, condition: &str) -> bool {
+        match condition {
+            "list_focus" => true,
+            "workspace_edit_focus" => true,
+            _ => false,
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        ctx: &mut EventCtx,
+        command: &LapceCommand,
+        count: Option<usize>,
+        env: &Env,
+    ) -> CommandExecuted {
+        match command {
+            LapceCommand::Down | LapceCommand::ListNext => {
+                self.active = Movement::Down.update_index(
+                    self.active,
+                    self.rows().len(),
+                    1,
+                    true,
+                );
+            }
+            LapceCommand::Up | LapceCommand::ListPrevious => {
+                self.active = Movement::Up.update_index(
+                    self.active,
+                    self.rows().len(),
+                    1,
+                    true,
+                );
+            }
+            LapceCommand::ListSelect => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ApplyWorkspaceEdit,
+                    Target::Widget(self.tab_id),
+                ));
+            }
+            LapceCommand::WorkspaceEditCancel => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::CancelWorkspaceEdit,
+                    Target::Widget(self.tab_id),
+                ));
+            }
+            _ => return CommandExecuted::No,
+        }
+        CommandExecuted::Yes
+    }
+
+    fn receive_char(&mut self, ctx: &mut EventCtx, c: &str) {
+        if c == " " {
+            self.toggle(self.active);
+        }
+    }
+}
+
+impl WorkspaceEditData {
+    pub fn new(tab_id: WidgetId) -> Self {
+        Self {
+            widget_id: WidgetId::next(),
+            tab_id,
+            id: 0,
+            title: "".to_string(),
+            shown: false,
+            apply_when_read: false,
+            files: Vec::new(),
+            active: 0,
+        }
+    }
+
+    /// Starts a new preview of the edits, by file.
+    pub fn set(&mut self, title: &str, edits: Vec<(PathBuf, Vec<TextEdit>)>) {
+        self.id += 1;
+        self.title = title.to_string();
+        self.files = edits
+            .into_iter()
+            .map(|(path, edits)| FileEdit::new(path, edits))
+            .collect();
+        self.active = 0;
+    }
+
+    pub fn file_mut(&mut self, path: &PathBuf) -> Option<&mut FileEdit> {
+        self.files.iter_mut().find(|file| &file.path == path)
+    }
+
+    /// Whether every file was read, or failed to be.
+    pub fn is_read(&self) -> bool {
+        self.files
+            .iter()
+            .all(|file| file.content.is_some() || file.error.is_some())
+    }
+
+    /// Whether applying it would edit anything.
+    pub fn can_apply(&self) -> bool {
+        self.is_read()
+            && self.files.iter().any(|file| {
+                !file.applying
+                    && file.error.is_none()
+                    && !file.included_edits().is_empty()
+            })
+    }
+
+    /// The rows of the list, as the index of the file and of its hunk, none
+    /// for the row of the file itself.
+    pub fn rows(&self) -> Vec<(usize, Option<usize>)> {
+        let mut rows = Vec::new();
+        for (i, file) in self.files.iter().enumerate() {
+            rows.push((i, None));
+            for j in 0..file.hunks.len() {
+                rows.push((i, Some(j)));
+            }
+        }
+        rows
+    }
+
+    /// How many lines the row takes: one for a file, one per line of the diff
+    /// for a hunk.
+    pub fn row_lines(&self, row: (usize, Option<usize>)) -> usize {
+        match row {
+            (i, Some(j)) => {
+                let hunk = &self.files[i].hunks[j];
+                (hunk.removed.len() + hunk.added.len()).max(1)
+            }
+            (_, None) => 1,
+        }
+    }
+
+    /// Excludes the file or the hunk of the row, or includes it back.
+    pub fn toggle(&mut self, row: usize) {
+        match self.rows().get(row) {
+            Some((i, Some(j))) => {
+                let hunk = &mut self.files[*i].hunks[*j];
+                hunk.excluded = !hunk.excluded;
+            }
+            Some((i, None)) => {
+                let file = &mut self.files[*i];
+                file.excluded = !file.excluded;
+            }
+            None => {}
+        }
+    }
+
+    /// How many hunks would be applied.
+    pub fn change_count(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| !file.excluded)
+            .map(|file| file.hunks.iter().filter(|hunk| !hunk.excluded).count())
+            .sum()
+    }
+}
+
+/// The edits of the workspace edit by file, in the order of their paths.
+/// Creating, renaming and deleting files isn't supported, so those
+/// operations are left out.
+pub fn workspace_edits(edit: &WorkspaceEdit) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let edits: HashMap<Url, Vec<TextEdit>> =
+        if let Some(changes) = edit.changes.as_ref() {
+            changes.clone()
+        } else {
+            let document_edits = match edit.document_changes.as_ref() {
+                Some(DocumentChanges::Edits(edits)) => edits.iter().collect(),
+                Some(DocumentChanges::Operations(ops)) => ops
+                    .iter()
+                    .filter_map(|o| match o {
+                        DocumentChangeOperation::Op(_) => None,
+                        DocumentChangeOperation::Edit(e) => Some(e),
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            let mut edits: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+            for e in document_edits {
+                edits
+                    .entry(e.text_document.uri.clone())
+                    .or_default()
+                    .extend(e.edits.iter().map(|e| match e {
+                        OneOf::Left(e) => e.clone(),
+                        OneOf::Right(e) => e.text_edit.clone(),
+                    }));
+            }
+            edits
+        };
+    let mut edits: Vec<(PathBuf, Vec<TextEdit>)> = edits
+        .into_iter()
+        .filter_map(|(url, edits)| Some((url.to_file_path().ok()?, edits)))
+        .collect();
+    edits.sort_by(|a, b| a.0.cmp(&b.0));
+    edits
+}
+
+/// The offset of the position in `content`. Its column counts UTF-16 code
+/// units, as in LSP, and stops at the end of its line.
+pub fn offset_of_position(content: &str, position: &Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match content[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return content.len(),
+        }
+    }
+    let line_end = line_end(content, line_start);
+    let mut units = 0;
+    for (i, c) in content[line_start..line_end].char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_end
+}
+
+fn line_start(content: &str, offset: usize) -> usize {
+    content[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0)
+}
+
+fn line_end(content: &str, offset: usize) -> usize {
+    content[offset..]
+        .find('\n')
+        .map(|i| offset + i)
+        .unwrap_or(content.len())
+}
+
+/// The edits as hunks, one for each group of edits on the same or
+/// overlapping lines.
+pub fn edit_hunks(content: &str, edits: &[TextEdit]) -> Vec<EditHunk> {
+    let mut edits: Vec<(usize, usize, String)> = edits
+        .iter()
+        .map(|edit| {
+            let start = offset_of_position(content, &edit.range.start);
+            let end = offset_of_position(content, &edit.range.end).max(start);
+            (start, end, edit.new_text.clone())
+        })
+        .collect();
+    // stable, so insertions at the same place keep their order
+    edits.sort_by_key(|(start, end, _)| (*start, *end));
+
+    let mut hunks = Vec::new();
+    let mut group: Vec<(usize, usize, String)> = Vec::new();
+    let mut group_end = 0;
+    for edit in edits {
+        if !group.is_empty() && line_start(content, edit.0) > group_end {
+            hunks.push(edit_hunk(content, std::mem::take(&mut group)));
+        }
+        group_end = group_end.max(line_end(content, edit.1));
+        group.push(edit);
+    }
+    if !group.is_empty() {
+        hunks.push(edit_hunk(content, group));
+    }
+    hunks
+}
+
+fn edit_hunk(content: &str, edits: Vec<(usize, usize, String)>) -> EditHunk {
+    let start = line_start(content, edits[0].0);
+    let end = edits
+        .iter()
+        .map(|(_, end, _)| line_end(content, *end))
+        .max()
+        .unwrap_or(start);
+    let added = apply_edits(&content[..end], &edits);
+    EditHunk {
+        line: content[..start].matches('\n').count(),
+        removed: content[start..end].lines().map(|l| l.to_string()).collect(),
+        added: added[start..].lines().map(|l| l.to_string()).collect(),
+        edits,
+        excluded: false,
+    }
+}
+
+/// The text with the edits, sorted by their offsets, applied. An edit
+/// overlapping the one before it starts where that one ends.
+pub fn apply_edits(content: &str, edits: &[(usize, usize, String)]) -> String {
+    let mut result = String::new();
+    let mut offset = 0;
+    for (start, end, text) in edits {
+        let start = (*start).max(offset);
+        result.push_str(&content[offset..start]);
+        result.push_str(text);
+        offset = (*end).max(start);
+    }
+    result.push_str(&content[offset..]);
+    result
+}
+
+/// The modal previewing a workspace edit, with a checkbox for each file and
+/// hunk, and buttons to apply or cancel it.
+pub struct WorkspaceEditPreview {
+    widget_id: WidgetId,
+    /// How far the list is scrolled down.
+    scroll: f64,
+    list_rect: Rect,
+    apply_rect: Rect,
+    cancel_rect: Rect,
+}
+
+impl WorkspaceEditPreview {
+    pub fn new(data: &WorkspaceEditData) -> Self {
+        Self {
+            widget_id: data.widget_id,
+            scroll: 0.0,
+            list_rect: Rect::ZERO,
+            apply_rect: Rect::ZERO,
+            cancel_rect: Rect::ZERO,
+        }
+    }
+
+    /// The row at `pos` in the list, and whether `pos` is on its checkbox.
+    fn row_at(&self, data: &LapceTabData, pos: Point) -> Option<(usize, bool)> {
+        if !self.list_rect.contains(pos) {
+            return None;
+        }
+        let line_height = data.config.editor.line_height as f64;
+        let y = pos.y - self.list_rect.y0 + self.scroll;
+        let edit = &data.workspace_edit;
+        let mut top = 0.0;
+        for (n, row) in edit.rows().into_iter().enumerate() {
+            let bottom = top + edit.row_lines(row) as f64 * line_height;
+            if y < bottom {
+                let checkbox_x =
+                    self.list_rect.x0 + row_indent(row, line_height) + line_height;
+                let on_checkbox = y < top + line_height && pos.x < checkbox_x;
+                return Some((n, on_checkbox));
+            }
+            top = bottom;
+        }
+        None
+    }
+
+    fn mouse_down(
+        &mut self,
+        ctx: &mut EventCtx,
+        mouse_event: &MouseEvent,
+        data: &mut LapceTabData,
+    ) {
+        if self.apply_rect.contains(mouse_event.pos) {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ApplyWorkspaceEdit,
+                Target::Widget(data.id),
+            ));
+        } else if self.cancel_rect.contains(mouse_event.pos) {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::CancelWorkspaceEdit,
+                Target::Widget(data.id),
+            ));
+        } else if let Some((row, on_checkbox)) = self.row_at(data, mouse_event.pos) {
+            let edit = Arc::make_mut(&mut data.workspace_edit);
+            edit.active = row;
+            if on_checkbox {
+                edit.toggle(row);
+            }
+        }
+        ctx.request_focus();
+    }
+
+    /// Scrolls the list so the active row is in view.
+    fn ensure_active_visible(&mut self, data: &LapceTabData) {
+        let line_height = data.config.editor.line_height as f64;
+        let edit = &data.workspace_edit;
+        let mut top = 0.0;
+        for (n, row) in edit.rows().into_iter().enumerate() {
+            let bottom = top + edit.row_lines(row) as f64 * line_height;
+            if n == edit.active {
+                if top < self.scroll {
+                    self.scroll = top;
+                } else if bottom > self.scroll + self.list_rect.height() {
+                    self.scroll = bottom - self.list_rect.height();
+                }
+                return;
+            }
+            top = bottom;
+        }
+    }
+
+    fn list_height(&self, data: &LapceTabData) -> f64 {
+        let line_height = data.config.editor.line_height as f64;
+        let edit = &data.workspace_edit;
+        edit.rows()
+            .into_iter()
+            .map(|row| edit.row_lines(row) as f64 * line_height)
+            .sum()
+    }
+
+    fn paint_row(
+        &self,
+        ctx: &mut PaintCtx,
+        data: &LapceTabData,
+        row: (usize, Option<usize>),
+        y: f64,
+    ) {
+        let line_height = data.config.editor.line_height as f64;
+        let x = row_indent(row, line_height);
+        let file = &data.workspace_edit.files[row.0];
+        let color = |name: &str| data.config.get_color_unchecked(name).clone();
+        match row.1 {
+            None => {
+                paint_checkbox(ctx, data, Point::new(x, y), !file.excluded);
+                let path = relative_path(
+                    &file.path,
+                    data.workspace.path.as_ref().map(|p| p.as_path()),
+                );
+                let text_layout =
+                    ui_text(ctx, path, color(LapceTheme::EDITOR_FOREGROUND));
+                let text_x = x + line_height + 5.0;
+                let text_size = text_layout.size();
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(text_x, y + (line_height - text_size.height) / 2.0),
+                );
+                let (status, status_color) = match &file.error {
+                    Some(error) => (error.clone(), LapceTheme::LAPCE_ERROR),
+                    None if file.applying => {
+                        ("applying…".to_string(), LapceTheme::EDITOR_DIM)
+                    }
+                    None if file.content.is_none() => {
+                        ("reading…".to_string(), LapceTheme::EDITOR_DIM)
+                    }
+                    None => (
+                        match file.hunks.len() {
+                            1 => "1 change".to_string(),
+                            n => format!("{} changes", n),
+                        },
+                        LapceTheme::EDITOR_DIM,
+                    ),
+                };
+                let text_layout = ui_text(ctx, status, color(status_color));
+                ctx.draw_text(
+                    &text_layout,
+                    Point::new(
+                        text_x + text_size.width + 10.0,
+                        y + (line_height - text_layout.size().height) / 2.0,
+                    ),
+                );
+            }
+            Some(j) => {
+                let hunk = &file.hunks[j];
+                let included = !file.excluded && !hunk.excluded;
+                paint_checkbox(ctx, data, Point::new(x, y), included);
+                // both sides are numbered from the first line of the hunk
+                let lines = hunk
+                    .removed
+                    .iter()
+                    .enumerate()
+                    .map(|(i, l)| (i, "-", l, LapceTheme::SOURCE_CONTROL_REMOVED))
+                    .chain(hunk.added.iter().enumerate().map(|(i, l)| {
+                        (i, "+", l, LapceTheme::SOURCE_CONTROL_ADDED)
+                    }));
+                let text_x = x + line_height + 5.0;
+                for (n, (i, sign, line, line_color)) in lines.enumerate() {
+                    let y = y + n as f64 * line_height;
+                    let text = format!("{:>5} {} {}", hunk.line + i + 1, sign, line);
+                    let mut line_color = color(line_color);
+                    if !included {
+                        line_color = line_color.with_alpha(0.5);
+                    }
+                    let text_layout = ctx
+                        .text()
+                        .new_text_layout(text)
+                        .font(
+                            data.config.editor.font_family(),
+                            data.config.editor.font_size as f64,
+                        )
+                        .text_color(line_color)
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(
+                        &text_layout,
+                        Point::new(
+                            text_x,
+                            y + (line_height - text_layout.size().height) / 2.0,
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn paint_button(
+        &self,
+        ctx: &mut PaintCtx,
+        data: &LapceTabData,
+        rect: Rect,
+        text: &str,
+        enabled: bool,
+    ) {
+        ctx.stroke(
+            rect,
+            data.config.get_color_unchecked(LapceTheme::LAPCE_BORDER),
+            1.0,
+        );
+        let color = if enabled {
+            LapceTheme::EDITOR_FOREGROUND
+        } else {
+            LapceTheme::EDITOR_DIM
+        };
+        let text_layout = ui_text(
+            ctx,
+            text.to_string(),
+            data.config.get_color_unchecked(color).clone(),
+        );
+        let size = text_layout.size();
+        ctx.draw_text(
+            &text_layout,
+            rect.center() - Vec2::new(size.width / 2.0, size.height / 2.0),
+        );
+    }
+}
+
+fn ui_text(ctx: &mut PaintCtx, text: String, color: Color) -> PietTextLayout {
+    ctx.text()
+        .new_text_layout(text)
+        .font(FontFamily::SYSTEM_UI, 13.0)
+        .text_color(color)
+        .build()
+        .unwrap()
+}
+
+/// Hunks are indented under their file.
+fn row_indent(row: (usize, Option<usize>), line_height: f64) -> f64 {
+    match row.1 {
+        Some(_) => 10.0 + line_height,
+        None => 10.0,
+    }
+}
+
+fn paint_checkbox(
+    ctx: &mut PaintCtx,
+    data: &LapceTabData,
+    origin: Point,
+    checked: bool,
+) {
+    let line_height = data.config.editor.line_height as f64;
+    let size = 13.0;
+    let origin = Point::new(
+        origin.x + (line_height - size) / 2.0,
+        origin.y + (line_height - size) / 2.0,
+    );
+    let color = data
+        .config
+        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND);
+    let rect = Size::new(size, size).to_rect().with_origin(origin);
+    ctx.stroke(rect, color, 1.0);
+    if checked {
+        let mut path = BezPath::new();
+        path.move_to((origin.x + 3.0, origin.y + 7.0));
+        path.line_to((origin.x + 6.0, origin.y + 9.5));
+        path.line_to((origin.x + 10.0, origin.y + 3.0));
+        ctx.stroke(path, color, 2.0);
+    }
+}
+
+impl Widget<LapceTabData> for WorkspaceEditPreview {
+    fn id(&self) -> Option<WidgetId> {
+        Some(self.widget_id)
+    }
+
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        if !data.workspace_edit.shown {
+            return;
+        }
+        match event {
+            Event::MouseMove(mouse_event) => {
+                let clickable = self.apply_rect.contains(mouse_event.pos)
+                    || self.cancel_rect.contains(mouse_event.pos)
+                    || self
+                        .row_at(data, mouse_event.pos)
+                        .map(|(_, on_checkbox)| on_checkbox)
+                        .unwrap_or(false);
+                if clickable {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+                ctx.set_handled();
+            }
+            Event::MouseDown(mouse_event) => {
+                self.mouse_down(ctx, mouse_event, data);
+                ctx.set_handled();
+            }
+            Event::Wheel(wheel_event) => {
+                let max =
+                    (self.list_height(data) - self.list_rect.height()).max(0.0);
+                self.scroll =
+                    (self.scroll + wheel_event.wheel_delta.y).max(0.0).min(max);
+                ctx.request_paint();
+                ctx.set_handled();
+            }
+            Event::KeyDown(key_event) => {
+                let mut keypress = data.keypress.clone();
+                Arc::make_mut(&mut keypress).key_down(
+                    ctx,
+                    key_event,
+                    Arc::make_mut(&mut data.workspace_edit),
+                    env,
+                );
+                data.keypress = keypress;
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(LAPCE_UI_COMMAND) => {
+                let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
+                if let LapceUICommand::Focus = command {
+                    ctx.request_focus();
+                    ctx.set_handled();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if old_data.workspace_edit.id != data.workspace_edit.id {
+            self.scroll = 0.0;
+        }
+        if old_data.workspace_edit.active != data.workspace_edit.active {
+            self.ensure_active_visible(data);
+        }
+        if !old_data.workspace_edit.same(&data.workspace_edit) {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        if !data.workspace_edit.shown {
+            return Size::ZERO;
+        }
+        let line_height = data.config.editor.line_height as f64;
+        let width = (bc.max().width - 100.0).min(800.0).max(0.0);
+        let list_height = self
+            .list_height(data)
+            .min(bc.max().height - 100.0 - line_height * 3.0)
+            .max(line_height);
+        let height = list_height + line_height * 3.0;
+
+        self.list_rect = Size::new(width, list_height)
+            .to_rect()
+            .with_origin(Point::new(0.0, line_height * 1.5));
+        let button_size = Size::new(80.0, line_height);
+        let button_y = height - line_height * 1.25;
+        self.cancel_rect = button_size
+            .to_rect()
+            .with_origin(Point::new(width - 10.0 - button_size.width, button_y));
+        self.apply_rect = button_size.to_rect().with_origin(Point::new(
+            self.cancel_rect.x0 - 10.0 - button_size.width,
+            button_y,
+        ));
+        let max = (self.list_height(data) - list_height).max(0.0);
+        self.scroll = self.scroll.min(max);
+        Size::new(width, height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        if !data.workspace_edit.shown {
+            return;
+        }
+        let edit = &data.workspace_edit;
+        let line_height = data.config.editor.line_height as f64;
+        let rect = ctx.size().to_rect();
+        ctx.blurred_rect(
+            rect,
+            5.0,
+            data.config
+                .get_color_unchecked(LapceTheme::LAPCE_DROPDOWN_SHADOW),
+        );
+        ctx.fill(
+            rect,
+            data.config
+                .get_color_unchecked(LapceTheme::PALETTE_BACKGROUND),
+        );
+
+        let files = edit.files.len();
+        let changes = edit.change_count();
+        let summary = format!(
+            "{}: {} {} in {} {}",
+            edit.title,
+            changes,
+            if changes == 1 { "change" } else { "changes" },
+            files,
+            if files == 1 { "file" } else { "files" },
+        );
+        let text_layout = ui_text(
+            ctx,
+            summary,
+            data.config
+                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                .clone(),
+        );
+        ctx.draw_text(
+            &text_layout,
+            Point::new(10.0, (line_height * 1.5 - text_layout.size().height) / 2.0),
+        );
+
+        let list_rect = self.list_rect;
+        let scroll = self.scroll;
+        ctx.with_save(|ctx| {
+            ctx.clip(list_rect);
+            ctx.transform(Affine::translate((list_rect.x0, list_rect.y0 - scroll)));
+            let mut y = 0.0;
+            for (n, row) in edit.rows().into_iter().enumerate() {
+                let height = edit.row_lines(row) as f64 * line_height;
+                if y + height >= scroll && y <= scroll + list_rect.height() {
+                    if n == edit.active {
+                        ctx.fill(
+                            Size::new(list_rect.width(), height)
+                                .to_rect()
+                                .with_origin(Point::new(0.0, y)),
+                            data.config
+                                .get_color_unchecked(LapceTheme::PALETTE_CURRENT),
+                        );
+                    }
+                    self.paint_row(ctx, data, row, y);
+                }
+                y += height;
+            }
+        });
+
+        self.paint_button(ctx, data, self.apply_rect, "Apply", edit.can_apply());
+        self.paint_button(ctx, data, self.cancel_rect, "Cancel", true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Range;
+
+    use super::*;
+
+    fn text_edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit::new(
+            Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            new_text.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_offset_of_position() {
+        let content = "aé😀b\nxy";
+        assert_eq!(offset_of_position(content, &Position::new(0, 0)), 0);
+        assert_eq!(offset_of_position(content, &Position::new(0, 2)), 3);
+        // the emoji is two UTF-16 code units
+        assert_eq!(offset_of_position(content, &Position::new(0, 4)), 7);
+        assert_eq!(offset_of_position(content, &Position::new(0, 9)), 8);
+        assert_eq!(offset_of_position(content, &Position::new(1, 1)), 10);
+        assert_eq!(offset_of_position(content, &Position::new(5, 0)), 11);
+    }
+
+    #[test]
+    fn test_edit_hunks() {
+        let content = "fn foo() {}\nfoo();\nbar();\n\nfoo(foo());\n";
+        let edits = vec![
+            text_edit((4, 4), (4, 7), "baz"),
+            text_edit((0, 3), (0, 6), "baz"),
+            text_edit((1, 0), (1, 3), "baz"),
+            text_edit((4, 0), (4, 3), "baz"),
+        ];
+        let hunks = edit_hunks(content, &edits);
+        assert_eq!(hunks.len(), 3);
+        assert_eq!(hunks[0].line, 0);
+        assert_eq!(hunks[0].removed, vec!["fn foo() {}"]);
+        assert_eq!(hunks[0].added, vec!["fn baz() {}"]);
+        assert_eq!(hunks[1].line, 1);
+        assert_eq!(hunks[1].added, vec!["baz();"]);
+        // the edits on the same line make one hunk
+        assert_eq!(hunks[2].line, 4);
+        assert_eq!(hunks[2].edits.len(), 2);
+        assert_eq!(hunks[2].removed, vec!["foo(foo());"]);
+        assert_eq!(hunks[2].added, vec!["baz(baz());"]);
+
+        let edits: Vec<(usize, usize, String)> = hunks
+            .iter()
+            .filter(|hunk| hunk.line != 1)
+            .flat_map(|hunk| hunk.edits.clone())
+            .collect();
+        assert_eq!(
+            apply_edits(content, &edits),
+            "fn baz() {}\nfoo();\nbar();\n\nbaz(baz());\n"
+        );
+    }
+
+    #[test]
+    fn test_excluded_edits() {
+        let mut file = FileEdit::new(
+            PathBuf::from("a.rs"),
+            vec![
+                text_edit((0, 0), (0, 1), "x"),
+                text_edit((2, 0), (2, 1), "z"),
+            ],
+        );
+        file.set_content("a\nb\nc\n".to_string());
+        assert_eq!(file.hunks.len(), 2);
+        file.hunks[0].excluded = true;
+        assert_eq!(file.included_edits(), vec![(4, 5, "z".to_string())]);
+        file.excluded = true;
+        assert!(file.included_edits().is_empty());
+    }
+}
@@ -1,23 +1,75 @@
 use anyhow::{anyhow, Result};
 use crossbeam_channel::Sender;
-use std::ffi::OsString;
 use std::fs::File;
 use std::io::Read;
-use std::io::Write;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::{borrow::Cow, path::Path, time::SystemTime};
-use std::{fs, str::FromStr};
 
 use lsp_types::*;
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
 use xi_rope::{
     interval::IntervalBounds, rope::Rope, Cursor, Delta, DeltaBuilder, Interval,
     LinesMetric, RopeDelta, RopeInfo, Transformer,
 };
 
+use crate::editorconfig::Properties;
+use crate::save::save_file;
+
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct BufferId(pub usize);
 
+/// Why a save was refused, so it doesn't overwrite a version of the file the
+/// UI hasn't seen.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveConflict {
+    /// The save is for another revision of the buffer than the proxy has,
+    /// e.g. one made before the file was reloaded from disk.
+    StaleRevision,
+    /// The file changed on disk since it was read or saved.
+    ChangedOnDisk,
+}
+
+impl std::fmt::Display for SaveConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveConflict::StaleRevision => write!(f, "not the right rev"),
+            SaveConflict::ChangedOnDisk => write!(f, "the file changed on disk"),
+        }
+    }
+}
+
+impl std::error::Error for SaveConflict {}
+
+impl SaveConflict {
+    /// The error the proxy responds to the save with, carrying the text on
+    /// disk.
+    pub fn to_error(&self, disk: Option<String>) -> Value {
+        json!({
+            "code": 0,
+            "message": self.to_string(),
+            "data": {
+                "conflict": self,
+                "content": disk,
+            },
+        })
+    }
+
+    /// The conflict and the text on disk in the error a save got, if it was
+    /// refused for a conflict.
+    pub fn from_error(error: &Value) -> Option<(SaveConflict, Option<String>)> {
+        let data = error.get("data")?;
+        let conflict = serde_json::from_value(data.get("conflict")?.clone()).ok()?;
+        let disk = data
+            .get("content")
+            .and_then(|content| content.as_str())
+            .map(|content| content.to_string());
+        Some((conflict, disk))
+    }
+}
+
 pub struct Buffer {
     pub language_id: String,
     pub id: BufferId,
@@ -25,8 +77,14 @@ pub struct Buffer {
     pub path: PathBuf,
     pub rev: u64,
     pub dirty: bool,
+    /// Whether an edit of the UI was refused, e.g. for coming after a
+    /// reload, so the text isn't the one of the UI until it's synced.
+    stale: bool,
     sender: Sender<(BufferId, u64)>,
     pub mod_time: Option<SystemTime>,
+    /// The `.editorconfig` properties of the file, resolved again when an
+    /// `.editorconfig` file above it changes.
+    pub editorconfig: Properties,
 }
 
 impl Buffer {
@@ -42,6 +100,7 @@ impl Buffer {
         };
         let language_id = language_id_from_path(&path).unwrap_or("").to_string();
         let mod_time = get_mod_time(&path);
+        let editorconfig = Properties::resolve(&path);
         Buffer {
             id,
             rope,
@@ -50,34 +109,48 @@ impl Buffer {
             rev: 0,
             sender,
             dirty: false,
+            stale: false,
             mod_time,
+            editorconfig,
         }
     }
 
     pub fn save(&mut self, rev: u64) -> Result<()> {
-        if self.rev != rev {
-            return Err(anyhow!("not the right rev"));
+        if self.rev != rev || self.stale {
+            return Err(SaveConflict::StaleRevision.into());
         }
-        self.dirty = false;
-        let tmp_extension = self.path.extension().map_or_else(
-            || OsString::from("swp"),
-            |ext| {
-                let mut ext = ext.to_os_string();
-                ext.push(".swp");
-                ext
-            },
-        );
-        let tmp_path = &self.path.with_extension(tmp_extension);
-
-        let mut f = File::create(tmp_path)?;
-        for chunk in self.rope.iter_chunks(..self.rope.len()) {
-            f.write_all(chunk.as_bytes())?;
+        if get_mod_time(&self.path) != self.mod_time {
+            return Err(SaveConflict::ChangedOnDisk.into());
+        }
+        if self.editorconfig.charset.as_deref() == Some("utf-8-bom")
+            && !self.rope.to_string().starts_with('\u{feff}')
+        {
+            save_file(&self.path, &Rope::from(format!("\u{feff}{}", self.rope)))?;
+        } else {
+            save_file(&self.path, &self.rope)?;
         }
-        fs::rename(tmp_path, &self.path)?;
+        self.dirty = false;
         self.mod_time = get_mod_time(&self.path);
         Ok(())
     }
 
+    /// Takes the text of the UI at `rev`, to settle a save conflict. The
+    /// file on disk counts as seen, so the next save overwrites it.
+    pub fn sync(&mut self, rev: u64, content: &str) {
+        self.rope = Rope::from(content);
+        self.rev = rev;
+        self.stale = false;
+        self.mod_time = get_mod_time(&self.path);
+        self.dirty = load_file(&self.path)
+            .map(|rope| rope.to_string() != content)
+            .unwrap_or(true);
+    }
+
+    /// The text of the file on disk.
+    pub fn disk_content(&self) -> Option<String> {
+        load_file(&self.path).ok().map(|rope| rope.to_string())
+    }
+
     pub fn reload(&mut self) {
         let rope = if let Ok(rope) = load_file(&self.path) {
             rope
@@ -96,6 +169,7 @@ impl Buffer {
         rev: u64,
     ) -> Option<TextDocumentContentChangeEvent> {
         if self.rev + 1 != rev {
+            self.stale = true;
             return None;
         }
         self.rev += 1;
@@ -1,16 +1,27 @@
-use crate::buffer::{get_mod_time, Buffer, BufferId};
+use crate::buffer::{get_mod_time, Buffer, BufferId, SaveConflict};
+use crate::editorconfig::{self, Properties};
+use crate::exclude::FileExcludes;
+use crate::format::{run_format_command, FormatCommand};
 use crate::lsp::LspCatalog;
 use crate::plugin::{PluginCatalog, PluginDescription};
-use crate::terminal::{TermId, Terminal};
+use crate::protocol::{ProtocolInfo, PROTOCOL_VERSION};
+use crate::save::{replace_content, replace_lines, write_file};
+use crate::shell_env::environment_report;
+use crate::terminal::{process_cwd, TermId, Terminal};
+use crate::trash;
+use crate::watcher::{
+    ChangeBatch, ChangeKind, WatchFilter, WatcherStats, BATCH_INTERVAL,
+    BULK_THRESHOLD,
+};
 use alacritty_terminal::event_loop::Msg;
 use alacritty_terminal::term::SizeInfo;
 use anyhow::{anyhow, Context, Result};
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use git2::{DiffOptions, Oid, Repository};
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, DiffOptions, Oid, Repository};
 use grep_matcher::Matcher;
-use grep_regex::RegexMatcher;
-use grep_searcher::sinks::UTF8;
-use grep_searcher::Searcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{Searcher, SearcherBuilder, Sink, SinkContext, SinkMatch};
 use jsonrpc_lite::{self, JsonRpc};
 use lapce_rpc::{self, Call, RequestId, RpcObject};
 use lsp_types::{CompletionItem, Position, TextDocumentContentChangeEvent};
@@ -21,10 +32,13 @@ use serde_json::json;
 use serde_json::Value;
 use std::{cmp, fs};
 use std::{collections::HashMap, io};
-use std::{collections::HashSet, io::BufRead};
-use std::{path::PathBuf, sync::atomic::AtomicBool};
+use std::{collections::HashSet, io::BufRead, io::Write};
+use std::{path::Path, path::PathBuf, sync::atomic::AtomicBool};
 use std::{sync::atomic, thread};
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use xi_rope::{RopeDelta, RopeInfo};
 
 #[derive(Clone)]
@@ -34,76 +48,30 @@ pub struct Dispatcher {
     pub workspace: Arc<Mutex<Option<PathBuf>>>,
     pub buffers: Arc<Mutex<HashMap<BufferId, Buffer>>>,
     pub terminals: Arc<Mutex<HashMap<TermId, mio::channel::Sender<Msg>>>>,
+    /// The process ids of the shells of the terminals, where they're known.
+    terminal_pids: Arc<Mutex<HashMap<TermId, u32>>>,
     open_files: Arc<Mutex<HashMap<String, BufferId>>>,
     plugins: Arc<Mutex<PluginCatalog>>,
     pub lsp: Arc<Mutex<LspCatalog>>,
     pub watcher: Arc<Mutex<Option<notify::RecommendedWatcher>>>,
+    /// The events of the watcher, handled in batches.
+    watch_sender: Sender<notify::Event>,
+    watcher_stats: Arc<Mutex<WatcherStats>>,
     last_diff: Arc<Mutex<DiffInfo>>,
+    /// The id of the global search that's running, the others stop at the
+    /// next file they look at.
+    global_search_id: Arc<atomic::AtomicU64>,
+    /// The `files.exclude` patterns of the UI.
+    file_excludes: Arc<Mutex<Vec<String>>>,
 }
 
 impl notify::EventHandler for Dispatcher {
     fn handle_event(&mut self, event: notify::Result<notify::Event>) {
-        if let Ok(event) = event {
-            for path in event.paths.iter() {
-                if let Some(path) = path.to_str() {
-                    if let Some(buffer_id) = self.open_files.lock().get(path) {
-                        match event.kind {
-                            notify::EventKind::Create(_)
-                            | notify::EventKind::Modify(_) => {
-                                if let Some(buffer) =
-                                    self.buffers.lock().get_mut(buffer_id)
-                                {
-                                    if get_mod_time(&buffer.path) == buffer.mod_time
-                                    {
-                                        return;
-                                    }
-                                    if !buffer.dirty {
-                                        buffer.reload();
-                                        self.lsp.lock().update(
-                                            buffer,
-                                            &TextDocumentContentChangeEvent {
-                                                range: None,
-                                                range_length: None,
-                                                text: buffer.get_document(),
-                                            },
-                                            buffer.rev,
-                                        );
-                                        self.sender.send(json!({
-                                            "method": "reload_buffer",
-                                            "params": {
-                                                "buffer_id": buffer_id,
-                                                "rev": buffer.rev,
-                                                "new_content": buffer.get_document(),
-                                            },
-                                        }));
-                                    }
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
-                }
-            }
-            match event.kind {
-                notify::EventKind::Create(_)
-                | notify::EventKind::Modify(_)
-                | notify::EventKind::Remove(_) => {
-                    if let Some(workspace) = self.workspace.lock().clone() {
-                        if let Some(diff) = git_diff_new(&workspace) {
-                            if diff != *self.last_diff.lock() {
-                                self.send_notification(
-                                    "diff_info",
-                                    json!({
-                                        "diff": diff,
-                                    }),
-                                );
-                                *self.last_diff.lock() = diff;
-                            }
-                        }
-                    }
-                }
-                _ => (),
+        match event {
+            Ok(event) => {
+                self.watch_sender.send(event);
             }
+            Err(e) => eprintln!("watcher error {}", e),
         }
     }
 }
@@ -121,9 +89,13 @@ pub enum Notification {
         delta: RopeDelta,
         rev: u64,
     },
+    /// Starts a terminal with the login shell, or running `command` with
+    /// the shell of the system when there's one.
     NewTerminal {
         term_id: TermId,
         cwd: Option<PathBuf>,
+        #[serde(default)]
+        command: Option<String>,
     },
     InstallPlugin {
         plugin: PluginDescription,
@@ -144,6 +116,10 @@ pub enum Notification {
     TerminalClose {
         term_id: TermId,
     },
+    CancelGlobalSearch {},
+    SetFileExcludes {
+        patterns: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -163,8 +139,25 @@ pub enum Request {
         buffer_id: BufferId,
         position: Position,
     },
+    /// Without a `search_id`, the matches come in the response. With one,
+    /// they're sent file by file as `global_search_result` notifications and
+    /// the response only tells that the search is done.
     GlobalSearch {
         pattern: String,
+        #[serde(default)]
+        search_id: u64,
+        #[serde(default)]
+        case_sensitive: bool,
+        #[serde(default)]
+        whole_word: bool,
+        #[serde(default)]
+        regex: bool,
+        /// Patterns of the only files to search, found even when excluded.
+        #[serde(default)]
+        include: Vec<String>,
+        /// How many lines around each match come with it.
+        #[serde(default)]
+        context: usize,
     },
     CompletionResolve {
         buffer_id: BufferId,
@@ -193,21 +186,135 @@ pub enum Request {
     GetDocumentFormatting {
         buffer_id: BufferId,
     },
+    /// The files of the workspace. `folders` restricts them to these
+    /// top-level folders, and `query` to the paths containing it, ignoring
+    /// case, up to `limit` of them.
     GetFiles {
         path: String,
+        #[serde(default)]
+        folders: Option<Vec<String>>,
+        #[serde(default)]
+        query: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Counts the files of the workspace, stopping past `limit`.
+    CountFiles {
+        limit: usize,
     },
     ReadDir {
         path: PathBuf,
     },
+    /// The size of the file in bytes.
+    GetFileSize {
+        path: PathBuf,
+    },
+    /// Checks out the branch, after creating it from the head if `create`.
+    /// Uncommitted changes it would overwrite make it fail.
+    GitCheckout {
+        branch: String,
+        #[serde(default)]
+        create: bool,
+    },
+    /// Runs `git fetch`, `git pull` or `git push` in the workspace. It fails
+    /// rather than asks for credentials.
+    GitRemote {
+        command: GitRemoteCommand,
+    },
+    /// Stages, unstages or discards the changes of the files.
+    GitFile {
+        command: GitFileCommand,
+        diffs: Vec<FileDiff>,
+    },
+    /// Moves the file or the folder to the trash of the OS, or to the
+    /// `.lapce-trash` folder of the workspace if `staging`.
+    DeletePath {
+        path: PathBuf,
+        #[serde(default)]
+        staging: bool,
+    },
+    /// The items deleted from the workspace in the last `retention_days`.
+    ListDeleted {
+        #[serde(default)]
+        staging: bool,
+        retention_days: u64,
+    },
+    /// Restores the deleted item with the `id`, or the latest one deleted
+    /// from `path`.
+    RestoreDeleted {
+        #[serde(default)]
+        id: Option<String>,
+        #[serde(default)]
+        path: Option<PathBuf>,
+        #[serde(default)]
+        staging: bool,
+    },
     Save {
         rev: u64,
         buffer_id: BufferId,
     },
+    /// Replaces the text of the buffer with the one of the UI, after a save
+    /// conflict.
+    SyncBuffer {
+        buffer_id: BufferId,
+        rev: u64,
+        content: String,
+    },
+    GetEnvironment {},
+    /// A report of what the file watcher went through.
+    GetWatcherStats {},
+    /// Replaces lines of a file that isn't open, given as (line number from
+    /// 1, text, replacement). It fails without writing anything when a line
+    /// isn't the text anymore.
+    ReplaceLines {
+        path: PathBuf,
+        lines: Vec<(usize, String, String)>,
+    },
+    /// The text of a file that isn't open.
+    ReadFile {
+        path: PathBuf,
+    },
+    /// Replaces the text of a file that isn't open. It fails without writing
+    /// anything when the file isn't `old` anymore.
+    ReplaceContent {
+        path: PathBuf,
+        old: String,
+        new: String,
+    },
+    /// Writes the text to a file, which is created if it doesn't exist.
+    WriteFile {
+        path: PathBuf,
+        content: String,
+    },
+    /// The working directory of the shell of the terminal, null when it can't
+    /// be told.
+    TerminalCwd {
+        term_id: TermId,
+    },
+    /// Writes the content to a new file in the temporary directory of the
+    /// proxy, and answers its path.
+    WriteTempFile {
+        content: String,
+        extension: String,
+    },
+    /// Formats the text of the file at `path` with a command line tool, in
+    /// the workspace, and answers the formatted text as `output`.
+    FormatWithCommand {
+        format: FormatCommand,
+        path: PathBuf,
+        content: String,
+    },
+    Handshake {
+        version: String,
+        capabilities: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewBufferResponse {
     pub content: String,
+    #[serde(default)]
+    pub editorconfig: Properties,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -220,7 +327,42 @@ pub struct BufferHeadResponse {
 pub struct DiffInfo {
     pub head: String,
     pub branches: Vec<String>,
+    /// The changes of the working tree that aren't staged.
     pub diffs: Vec<FileDiff>,
+    /// The changes of the index, which the next commit is made of.
+    #[serde(default)]
+    pub staged: Vec<FileDiff>,
+    /// How many commits the head is ahead and behind of the branch it
+    /// tracks, if it tracks one.
+    #[serde(default)]
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitRemoteCommand {
+    Fetch,
+    Pull,
+    Push,
+}
+
+impl GitRemoteCommand {
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            GitRemoteCommand::Fetch => &["fetch"],
+            GitRemoteCommand::Pull => &["pull", "--no-edit"],
+            GitRemoteCommand::Push => &["push"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitFileCommand {
+    Stage,
+    Unstage,
+    /// Puts the files back as they're staged, deleting the untracked ones.
+    Discard,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -240,6 +382,16 @@ impl FileDiff {
             | FileDiff::Renamed(_, p) => p,
         }
     }
+
+    /// The paths the change is about, both of them for a rename.
+    pub fn paths(&self) -> Vec<&PathBuf> {
+        match &self {
+            FileDiff::Modified(p) | FileDiff::Added(p) | FileDiff::Deleted(p) => {
+                vec![p]
+            }
+            FileDiff::Renamed(new, old) => vec![new, old],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -250,6 +402,9 @@ pub struct FileNodeItem {
     pub open: bool,
     pub children: HashMap<PathBuf, FileNodeItem>,
     pub children_open_count: usize,
+    /// Whether the item matches the `files.exclude` patterns.
+    #[serde(default)]
+    pub excluded: bool,
 }
 
 impl std::cmp::PartialOrd for FileNodeItem {
@@ -279,6 +434,7 @@ impl Dispatcher {
     pub fn new(sender: Sender<Value>) -> Dispatcher {
         let plugins = PluginCatalog::new();
         let (git_sender, git_receiver) = unbounded();
+        let (watch_sender, watch_receiver) = unbounded();
         let dispatcher = Dispatcher {
             sender: Arc::new(sender),
             git_sender,
@@ -286,10 +442,15 @@ impl Dispatcher {
             buffers: Arc::new(Mutex::new(HashMap::new())),
             open_files: Arc::new(Mutex::new(HashMap::new())),
             terminals: Arc::new(Mutex::new(HashMap::new())),
+            terminal_pids: Arc::new(Mutex::new(HashMap::new())),
             plugins: Arc::new(Mutex::new(plugins)),
             lsp: Arc::new(Mutex::new(LspCatalog::new())),
             watcher: Arc::new(Mutex::new(None)),
+            watch_sender,
+            watcher_stats: Arc::new(Mutex::new(WatcherStats::default())),
             last_diff: Arc::new(Mutex::new(DiffInfo::default())),
+            global_search_id: Arc::new(atomic::AtomicU64::new(0)),
+            file_excludes: Arc::new(Mutex::new(Vec::new())),
         };
         *dispatcher.watcher.lock() =
             Some(notify::recommended_watcher(dispatcher.clone()).unwrap());
@@ -312,6 +473,7 @@ impl Dispatcher {
         });
 
         dispatcher.start_update_process(git_receiver);
+        dispatcher.start_watch_process(watch_receiver);
 
         dispatcher
     }
@@ -321,6 +483,7 @@ impl Dispatcher {
             let rpc: RpcObject = msg.into();
             if rpc.is_response() {
             } else {
+                let id = rpc.get_id();
                 match rpc.into_rpc::<Notification, Request>() {
                     Ok(Call::Request(id, request)) => {
                         self.handle_request(id, request);
@@ -342,7 +505,20 @@ impl Dispatcher {
                         }
                         self.handle_notification(notification);
                     }
-                    Err(e) => {}
+                    Err(e) => {
+                        // answer the requests this proxy doesn't know, so
+                        // that a newer UI isn't left waiting
+                        if let Some(id) = id {
+                            self.respond(
+                                id,
+                                Err(anyhow!(
+                                    "unsupported by proxy protocol {}: {}",
+                                    PROTOCOL_VERSION,
+                                    e
+                                )),
+                            );
+                        }
+                    }
                 }
             }
         }
@@ -420,15 +596,218 @@ impl Dispatcher {
         }));
     }
 
+    /// Resolves the `.editorconfig` properties of the open files under `dir`
+    /// again, after an `.editorconfig` file in it changed, and sends the
+    /// ones that differ.
+    fn reload_editorconfig(&self, dir: &Path) {
+        for buffer in self.buffers.lock().values_mut() {
+            if !buffer.path.starts_with(dir) {
+                continue;
+            }
+            let editorconfig = Properties::resolve(&buffer.path);
+            if editorconfig != buffer.editorconfig {
+                buffer.editorconfig = editorconfig.clone();
+                self.send_notification(
+                    "editorconfig_changed",
+                    json!({
+                        "buffer_id": buffer.id,
+                        "editorconfig": editorconfig,
+                    }),
+                );
+            }
+        }
+    }
+
+    fn watch(&self, path: &Path, mode: notify::RecursiveMode) {
+        if let Err(e) = self.watcher.lock().as_mut().unwrap().watch(path, mode) {
+            eprintln!("can't watch {:?}: {}", path, e);
+            return;
+        }
+        let mut stats = self.watcher_stats.lock();
+        if !stats.watched.iter().any(|p| p == path) {
+            stats.watched.push(path.to_path_buf());
+        }
+    }
+
+    /// Handles the events of the watcher in batches, one every
+    /// `BATCH_INTERVAL` at most, so that a build writing thousands of files
+    /// costs a few notifications.
+    fn start_watch_process(&self, receiver: Receiver<notify::Event>) {
+        let dispatcher = self.clone();
+        thread::spawn(move || {
+            let mut filter = None;
+            while let Ok(event) = receiver.recv() {
+                let mut events = vec![event];
+                let deadline = Instant::now() + BATCH_INTERVAL;
+                while let Some(timeout) =
+                    deadline.checked_duration_since(Instant::now())
+                {
+                    match receiver.recv_timeout(timeout) {
+                        Ok(event) => events.push(event),
+                        Err(_) => break,
+                    }
+                }
+                dispatcher.handle_watch_events(events, &mut filter);
+            }
+        });
+    }
+
+    /// Tells the UI about the open files that changed, and sends the other
+    /// changes that aren't ignored as one `file_changes` batch. `filter` is
+    /// kept between the batches, and made again when the workspace or the
+    /// exclude patterns change.
+    fn handle_watch_events(
+        &self,
+        events: Vec<notify::Event>,
+        filter: &mut Option<(PathBuf, Vec<String>, WatchFilter)>,
+    ) {
+        let mut batch = ChangeBatch::default();
+        {
+            let mut stats = self.watcher_stats.lock();
+            for event in events.iter() {
+                stats.events += 1;
+                for path in event.paths.iter() {
+                    if let Some(kind) = ChangeKind::of(&event.kind, path) {
+                        stats.paths += 1;
+                        batch.add(path.clone(), kind);
+                    }
+                }
+            }
+        }
+
+        let mut editorconfig_dirs = HashSet::new();
+        for (path, kind) in batch.iter() {
+            if editorconfig::is_editorconfig(path) {
+                if let Some(dir) = path.parent() {
+                    editorconfig_dirs.insert(dir.to_path_buf());
+                }
+            }
+            self.open_file_changed(path, *kind);
+        }
+        for dir in editorconfig_dirs {
+            self.reload_editorconfig(&dir);
+        }
+
+        let workspace = match self.workspace.lock().clone() {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        let patterns = self.file_excludes.lock().clone();
+        if filter
+            .as_ref()
+            .map(|(w, p, _)| w != &workspace || p != &patterns)
+            .unwrap_or(true)
+        {
+            let excludes = FileExcludes::new(&workspace, &patterns);
+            *filter = Some((
+                workspace.clone(),
+                patterns,
+                WatchFilter::new(&workspace, excludes),
+            ));
+        }
+        let filter = &mut filter.as_mut().unwrap().2;
+
+        let before = batch.len();
+        let mut git_changed = false;
+        batch.retain(|path| {
+            filter.forget(path);
+            git_changed |= filter.is_git_path(path);
+            !filter.is_ignored(path)
+        });
+        let len = batch.len();
+        let changes = batch.take(BULK_THRESHOLD);
+        {
+            let mut stats = self.watcher_stats.lock();
+            stats.ignored += (before - len) as u64;
+            if !changes.is_empty() {
+                stats.record_batch(len, changes.bulk);
+            }
+        }
+        if !changes.is_empty() {
+            self.send_notification(
+                "file_changes",
+                json!({
+                    "changes": changes,
+                }),
+            );
+        }
+
+        // ignored files don't change the status
+        if git_changed || !changes.is_empty() {
+            self.watcher_stats.lock().git_refreshes += 1;
+            if let Some(diff) = git_diff_new(&workspace) {
+                if diff != *self.last_diff.lock() {
+                    self.send_notification(
+                        "diff_info",
+                        json!({
+                            "diff": diff,
+                        }),
+                    );
+                    *self.last_diff.lock() = diff;
+                }
+            }
+        }
+    }
+
+    /// Tells the UI when the file of a buffer was deleted, created again or
+    /// changed on disk.
+    fn open_file_changed(&self, path: &Path, kind: ChangeKind) {
+        let buffer_id = match path
+            .to_str()
+            .and_then(|p| self.open_files.lock().get(p).cloned())
+        {
+            Some(buffer_id) => buffer_id,
+            None => return,
+        };
+        if kind != ChangeKind::Modified {
+            self.send_notification(
+                "file_deleted",
+                json!({
+                    "buffer_id": buffer_id,
+                    "deleted": !path.exists(),
+                }),
+            );
+        }
+        if kind == ChangeKind::Removed {
+            return;
+        }
+        if let Some(buffer) = self.buffers.lock().get_mut(&buffer_id) {
+            let mod_time = get_mod_time(&buffer.path);
+            if mod_time == buffer.mod_time {
+                return;
+            }
+            let content = match buffer.disk_content() {
+                Some(content) => content,
+                None => return,
+            };
+            // e.g. saved by another editor as it is
+            if content == buffer.get_document() {
+                buffer.mod_time = mod_time;
+                return;
+            }
+            // the UI decides whether to reload, and until then a save is
+            // refused
+            self.send_notification(
+                "file_changed",
+                json!({
+                    "buffer_id": buffer_id,
+                    "content": content,
+                }),
+            );
+        }
+    }
+
+    /// The exclude patterns applied to the workspace, once there's one.
+    fn file_excludes(&self) -> Option<FileExcludes> {
+        let workspace = self.workspace.lock().clone()?;
+        Some(FileExcludes::new(&workspace, &self.file_excludes.lock()))
+    }
+
     fn handle_notification(&self, rpc: Notification) {
         match rpc {
             Notification::Initialize { workspace } => {
                 *self.workspace.lock() = Some(workspace.clone());
-                self.watcher
-                    .lock()
-                    .as_mut()
-                    .unwrap()
-                    .watch(&workspace, notify::RecursiveMode::Recursive);
+                self.watch(&workspace, notify::RecursiveMode::Recursive);
                 if let Some(diff) = git_diff_new(&workspace) {
                     self.send_notification(
                         "diff_info",
@@ -469,21 +848,36 @@ impl Dispatcher {
                     );
                 });
             }
-            Notification::NewTerminal { term_id, cwd } => {
-                let mut terminal = Terminal::new(term_id, cwd, 50, 10);
+            Notification::NewTerminal {
+                term_id,
+                cwd,
+                command,
+            } => {
+                let mut terminal =
+                    Terminal::new(term_id, cwd, command.as_deref(), 50, 10);
                 let tx = terminal.tx.clone();
                 self.terminals.lock().insert(term_id, tx);
+                if let Some(pid) = terminal.shell_pid() {
+                    self.terminal_pids.lock().insert(term_id, pid);
+                }
                 let dispatcher = self.clone();
                 std::thread::spawn(move || {
                     terminal.run(dispatcher);
                     eprintln!("terminal exit");
                 });
             }
+            Notification::CancelGlobalSearch {} => {
+                self.global_search_id.store(0, atomic::Ordering::SeqCst);
+            }
+            Notification::SetFileExcludes { patterns } => {
+                *self.file_excludes.lock() = patterns;
+            }
             Notification::TerminalClose { term_id } => {
                 let mut terminals = self.terminals.lock();
                 if let Some(tx) = terminals.remove(&term_id) {
                     tx.send(Msg::Shutdown);
                 }
+                self.terminal_pids.lock().remove(&term_id);
             }
             Notification::TerminalWrite { term_id, content } => {
                 let terminals = self.terminals.lock();
@@ -513,6 +907,15 @@ impl Dispatcher {
                 if let Some(workspace) = self.workspace.lock().clone() {
                     if let Err(e) = git_commit(&workspace, &message, diffs) {
                         eprintln!("git commit error {e}");
+                    } else if let Some(diff) = git_diff_new(&workspace) {
+                        // the commit puts the branch ahead of its upstream
+                        self.send_notification(
+                            "diff_info",
+                            json!({
+                                "diff": diff,
+                            }),
+                        );
+                        *self.last_diff.lock() = diff;
                     }
                 }
             }
@@ -522,19 +925,29 @@ impl Dispatcher {
     fn handle_request(&self, id: RequestId, rpc: Request) {
         match rpc {
             Request::NewBuffer { buffer_id, path } => {
-                self.watcher
-                    .lock()
-                    .as_mut()
-                    .unwrap()
-                    .watch(&path, notify::RecursiveMode::Recursive);
+                self.watch(&path, notify::RecursiveMode::Recursive);
                 self.open_files
                     .lock()
                     .insert(path.to_str().unwrap().to_string(), buffer_id);
                 let buffer = Buffer::new(buffer_id, path, self.git_sender.clone());
                 let content = buffer.rope.to_string();
+                let editorconfig = buffer.editorconfig.clone();
+                // the ones in the workspace are already watched
+                let workspace = self.workspace.lock().clone();
+                for file in editorconfig.files.iter().filter(|file| {
+                    workspace
+                        .as_ref()
+                        .map(|w| !file.starts_with(w))
+                        .unwrap_or(true)
+                }) {
+                    self.watch(file, notify::RecursiveMode::NonRecursive);
+                }
                 self.buffers.lock().insert(buffer_id, buffer);
                 self.git_sender.send((buffer_id, 0));
-                let resp = NewBufferResponse { content };
+                let resp = NewBufferResponse {
+                    content,
+                    editorconfig,
+                };
                 self.sender.send(json!({
                     "id": id,
                     "result": resp,
@@ -623,20 +1036,32 @@ impl Dispatcher {
             }
             Request::ReadDir { path } => {
                 let local_dispatcher = self.clone();
+                let excludes = self.file_excludes();
                 thread::spawn(move || {
+                    let is_excluded = |path: &PathBuf, is_dir: bool| {
+                        excludes
+                            .as_ref()
+                            .map(|e| e.is_excluded(path, is_dir))
+                            .unwrap_or(false)
+                    };
                     let result = fs::read_dir(path)
                         .map(|entries| {
                             let items = entries
                                 .into_iter()
                                 .filter_map(|entry| {
                                     entry
-                                        .map(|e| FileNodeItem {
-                                            path_buf: e.path(),
-                                            is_dir: e.path().is_dir(),
-                                            open: false,
-                                            read: false,
-                                            children: HashMap::new(),
-                                            children_open_count: 0,
+                                        .map(|e| {
+                                            let path = e.path();
+                                            let is_dir = path.is_dir();
+                                            FileNodeItem {
+                                                excluded: is_excluded(&path, is_dir),
+                                                path_buf: path,
+                                                is_dir,
+                                                open: false,
+                                                read: false,
+                                                children: HashMap::new(),
+                                                children_open_count: 0,
+                                            }
                                         })
                                         .ok()
                                 })
@@ -647,72 +1072,373 @@ impl Dispatcher {
                     local_dispatcher.respond(id, result);
                 });
             }
-            Request::GetFiles { path } => {
-                if let Some(workspace) = self.workspace.lock().clone() {
+            Request::GetFileSize { path } => {
+                let result = fs::metadata(path)
+                    .map(|metadata| json!(metadata.len()))
+                    .map_err(|e| anyhow!(e));
+                self.respond(id, result);
+            }
+            Request::DeletePath { path, staging } => {
+                let result = match self.workspace.lock().clone() {
+                    Some(workspace) => trash::delete(&workspace, &path, staging),
+                    None => Err(anyhow!("no workspace")),
+                };
+                self.respond(id, result.map(|_| json!({})));
+            }
+            Request::ListDeleted {
+                staging,
+                retention_days,
+            } => {
+                let result = match self.workspace.lock().clone() {
+                    Some(workspace) => {
+                        trash::list(&workspace, staging, retention_days)
+                    }
+                    None => Err(anyhow!("no workspace")),
+                };
+                self.respond(id, result.map(|items| json!(items)));
+            }
+            Request::RestoreDeleted {
+                id: item_id,
+                path,
+                staging,
+            } => {
+                let result = match self.workspace.lock().clone() {
+                    Some(workspace) => trash::restore(
+                        &workspace,
+                        staging,
+                        item_id.as_deref(),
+                        path.as_deref(),
+                    ),
+                    None => Err(anyhow!("no workspace")),
+                };
+                self.respond(id, result.map(|item| json!(item)));
+            }
+            Request::GitCheckout { branch, create } => {
+                let workspace = self.workspace.lock().clone();
+                let result = match workspace.as_ref() {
+                    Some(workspace) => git_checkout(workspace, &branch, create),
+                    None => Err(anyhow!("no workspace")),
+                };
+                if let (Ok(_), Some(workspace)) = (&result, workspace.as_ref()) {
+                    if let Some(diff) = git_diff_new(workspace) {
+                        self.send_notification(
+                            "diff_info",
+                            json!({
+                                "diff": diff,
+                            }),
+                        );
+                        *self.last_diff.lock() = diff;
+                    }
+                }
+                self.respond(id, result.map(|_| json!({})));
+            }
+            Request::GitRemote { command } => {
+                let workspace = match self.workspace.lock().clone() {
+                    Some(workspace) => workspace,
+                    None => {
+                        self.respond(id, Err(anyhow!("no workspace")));
+                        return;
+                    }
+                };
+                let local_dispatcher = self.clone();
+                // the network can take a while
+                thread::spawn(move || {
+                    let result = git_remote(&workspace, command);
+                    if let Some(diff) = git_diff_new(&workspace) {
+                        local_dispatcher.send_notification(
+                            "diff_info",
+                            json!({
+                                "diff": diff,
+                            }),
+                        );
+                        *local_dispatcher.last_diff.lock() = diff;
+                    }
+                    local_dispatcher.respond(id, result.map(|_| json!({})));
+                });
+            }
+            Request::GitFile { command, diffs } => {
+                let workspace = self.workspace.lock().clone();
+                let result = match workspace.as_ref() {
+                    Some(workspace) => match command {
+                        GitFileCommand::Stage => git_stage(workspace, diffs),
+                        GitFileCommand::Unstage => git_unstage(workspace, diffs),
+                        GitFileCommand::Discard => git_discard(workspace, diffs),
+                    },
+                    None => Err(anyhow!("no workspace")),
+                };
+                if let Some(workspace) = workspace.as_ref() {
+                    if let Some(diff) = git_diff_new(workspace) {
+                        self.send_notification(
+                            "diff_info",
+                            json!({
+                                "diff": diff,
+                            }),
+                        );
+                        *self.last_diff.lock() = diff;
+                    }
+                }
+                self.respond(id, result.map(|_| json!({})));
+            }
+            Request::GetFiles {
+                path,
+                folders,
+                query,
+                limit,
+            } => {
+                if let Some(excludes) = self.file_excludes() {
+                    let excludes = excludes.with_folders(folders);
+                    let workspace =
+                        self.workspace.lock().clone().unwrap_or_default();
                     let local_dispatcher = self.clone();
                     thread::spawn(move || {
-                        let mut items = Vec::new();
-                        for result in ignore::Walk::new(workspace) {
-                            if let Ok(path) = result {
-                                if let Some(file_type) = path.file_type() {
-                                    if file_type.is_file() {
-                                        items.push(path.into_path());
-                                    }
-                                }
-                            }
-                        }
+                        let query = query.map(|q| q.to_lowercase());
+                        let items: Vec<PathBuf> = excludes
+                            .files()
+                            .filter(|path| match query.as_ref() {
+                                Some(query) => path
+                                    .strip_prefix(&workspace)
+                                    .unwrap_or(path)
+                                    .to_str()
+                                    .map(|p| p.to_lowercase().contains(query))
+                                    .unwrap_or(false),
+                                None => true,
+                            })
+                            .take(limit.unwrap_or(usize::MAX))
+                            .collect();
                         local_dispatcher
                             .respond(id, Ok(serde_json::to_value(items).unwrap()));
                     });
                 }
             }
+            Request::CountFiles { limit } => {
+                if let Some(excludes) = self.file_excludes() {
+                    let local_dispatcher = self.clone();
+                    thread::spawn(move || {
+                        let count = excludes.files().take(limit + 1).count();
+                        local_dispatcher.respond(
+                            id,
+                            Ok(json!({
+                                "count": count.min(limit),
+                                "complete": count <= limit,
+                            })),
+                        );
+                    });
+                }
+            }
             Request::Save { rev, buffer_id } => {
                 let mut buffers = self.buffers.lock();
                 let buffer = buffers.get_mut(&buffer_id).unwrap();
-                let resp = buffer.save(rev).map(|r| json!({}));
-                self.lsp.lock().save_buffer(buffer);
-                self.respond(id, resp);
+                match buffer.save(rev) {
+                    Ok(()) => {
+                        self.lsp.lock().save_buffer(buffer);
+                        self.respond(id, Ok(json!({})));
+                    }
+                    Err(e) => match e.downcast_ref::<SaveConflict>() {
+                        Some(conflict) => {
+                            let _ = self.sender.send(json!({
+                                "id": id,
+                                "error": conflict.to_error(buffer.disk_content()),
+                            }));
+                        }
+                        None => self.respond(id, Err(e)),
+                    },
+                }
+            }
+            Request::SyncBuffer {
+                buffer_id,
+                rev,
+                content,
+            } => {
+                let mut buffers = self.buffers.lock();
+                let buffer = buffers.get_mut(&buffer_id).unwrap();
+                buffer.sync(rev, &content);
+                self.lsp.lock().update(
+                    buffer,
+                    &TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: buffer.get_document(),
+                    },
+                    buffer.rev,
+                );
+                self.respond(id, Ok(json!({})));
             }
-            Request::GlobalSearch { pattern } => {
-                if let Some(workspace) = self.workspace.lock().clone() {
+            Request::GlobalSearch {
+                pattern,
+                search_id,
+                case_sensitive,
+                whole_word,
+                regex,
+                include,
+                context,
+            } => {
+                if let Some(excludes) = self.file_excludes() {
+                    let excludes = excludes.with_include(&include);
+                    if search_id != 0 {
+                        self.global_search_id
+                            .store(search_id, atomic::Ordering::SeqCst);
+                    }
                     let local_dispatcher = self.clone();
                     thread::spawn(move || {
-                        let matcher = RegexMatcher::new(&pattern).unwrap();
+                        let pattern = if regex {
+                            pattern
+                        } else {
+                            escape_regex(&pattern)
+                        };
+                        let matcher = match RegexMatcherBuilder::new()
+                            .case_insensitive(!case_sensitive)
+                            .word(whole_word)
+                            .build(&pattern)
+                        {
+                            Ok(matcher) => matcher,
+                            Err(e) => {
+                                local_dispatcher.respond(
+                                    id,
+                                    Err(anyhow!("Invalid regex: {}", e)),
+                                );
+                                return;
+                            }
+                        };
                         let mut matches = HashMap::new();
-                        for result in ignore::Walk::new(workspace) {
-                            if let Ok(path) = result {
-                                if let Some(file_type) = path.file_type() {
-                                    if file_type.is_file() {
-                                        let path = path.into_path();
-                                        let mut line_matches = Vec::new();
-                                        Searcher::new().search_path(
-                                            &matcher,
-                                            path.clone(),
-                                            UTF8(|lnum, line| {
-                                                let mymatch = matcher
-                                                    .find(line.as_bytes())?
-                                                    .unwrap();
-                                                line_matches.push((
-                                                    lnum,
-                                                    (mymatch.start(), mymatch.end()),
-                                                    line.to_string(),
-                                                ));
-                                                Ok(true)
-                                            }),
-                                        );
-                                        if line_matches.len() > 0 {
-                                            matches
-                                                .insert(path.clone(), line_matches);
-                                        }
-                                    }
+                        let mut contexts = HashMap::new();
+                        for path in excludes.files() {
+                            if search_id != 0
+                                && local_dispatcher
+                                    .global_search_id
+                                    .load(atomic::Ordering::SeqCst)
+                                    != search_id
+                            {
+                                local_dispatcher
+                                    .respond(id, Err(anyhow!("Search cancelled")));
+                                return;
+                            }
+                            let mut sink = SearchSink::new(&matcher);
+                            SearcherBuilder::new()
+                                .before_context(context)
+                                .after_context(context)
+                                .build()
+                                .search_path(&matcher, path.clone(), &mut sink);
+                            if sink.matches.is_empty() {
+                                continue;
+                            }
+                            if search_id == 0 {
+                                matches.insert(path.clone(), sink.matches);
+                                if context > 0 {
+                                    contexts.insert(path.clone(), sink.context);
                                 }
+                            } else {
+                                local_dispatcher.send_notification(
+                                    "global_search_result",
+                                    json!({
+                                        "search_id": search_id,
+                                        "path": path,
+                                        "matches": sink.matches,
+                                        "context": sink.context,
+                                    }),
+                                );
                             }
                         }
-                        local_dispatcher
-                            .respond(id, Ok(serde_json::to_value(matches).unwrap()));
+                        // with context, the matches that aren't streamed come
+                        // with the lines around them
+                        let result = if context > 0 {
+                            json!({
+                                "matches": matches,
+                                "context": contexts,
+                            })
+                        } else {
+                            serde_json::to_value(matches).unwrap()
+                        };
+                        local_dispatcher.respond(id, Ok(result));
                     });
                 }
             }
+            Request::Handshake {
+                version,
+                capabilities,
+            } => {
+                let ui = ProtocolInfo {
+                    version,
+                    capabilities,
+                };
+                let info = ProtocolInfo::current();
+                if !info.is_compatible(&ui) {
+                    eprintln!(
+                        "incompatible UI protocol {}, proxy is {}",
+                        ui.version, info.version
+                    );
+                }
+                self.respond(id, Ok(serde_json::to_value(info).unwrap()));
+            }
+            Request::GetEnvironment {} => {
+                let mut programs = vec![("git".to_string(), "git".to_string())];
+                if let Ok(shell) = std::env::var("SHELL") {
+                    programs.push(("shell".to_string(), shell));
+                }
+                programs.extend(self.lsp.lock().commands());
+                let local_dispatcher = self.clone();
+                thread::spawn(move || {
+                    let report = environment_report(&programs);
+                    local_dispatcher.respond(id, Ok(json!(report)));
+                });
+            }
+            Request::GetWatcherStats {} => {
+                let report = self.watcher_stats.lock().report();
+                self.respond(id, Ok(json!(report)));
+            }
+            Request::ReplaceLines { path, lines } => {
+                let result = replace_lines(&path, &lines)
+                    .map(|_| json!({}))
+                    .with_context(|| format!("can't replace in {:?}", path));
+                self.respond(id, result);
+            }
+            Request::ReadFile { path } => {
+                let result = fs::read_to_string(&path)
+                    .map(|content| json!(content))
+                    .map_err(|e| anyhow!(e));
+                self.respond(id, result);
+            }
+            Request::ReplaceContent { path, old, new } => {
+                let result = replace_content(&path, &old, &new).map(|_| json!({}));
+                self.respond(id, result);
+            }
+            Request::WriteFile { path, content } => {
+                let result = write_file(&path, &content)
+                    .map(|_| json!({}))
+                    .with_context(|| format!("can't write {:?}", path));
+                self.respond(id, result);
+            }
+            Request::TerminalCwd { term_id } => {
+                let cwd = self
+                    .terminal_pids
+                    .lock()
+                    .get(&term_id)
+                    .and_then(|pid| process_cwd(*pid));
+                self.respond(id, Ok(json!(cwd)));
+            }
+            Request::WriteTempFile { content, extension } => {
+                let result = write_temp_file(&content, &extension)
+                    .map(|path| json!(path))
+                    .context("can't write the temporary file");
+                self.respond(id, result);
+            }
+            Request::FormatWithCommand {
+                format,
+                path,
+                content,
+            } => {
+                let workspace = self.workspace.lock().clone();
+                let local_dispatcher = self.clone();
+                thread::spawn(move || {
+                    let result = run_format_command(
+                        &format,
+                        &path,
+                        &content,
+                        workspace.as_deref(),
+                    )
+                    .map(|output| json!({ "output": output }));
+                    local_dispatcher.respond(id, result);
+                });
+            }
         }
     }
 }
@@ -737,6 +1463,28 @@ fn git_commit(
             .ok_or(anyhow!("workspace path can't changed to str"))?,
     )?;
     let mut index = repo.index()?;
+    add_to_index(workspace_path, &mut index, diffs)?;
+    let tree = index.write_tree()?;
+    let tree = repo.find_tree(tree)?;
+    let signature = repo.signature()?;
+    let parent = repo.head()?.peel_to_commit()?;
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )?;
+    Ok(())
+}
+
+/// Puts the changes of the working tree in the index, and writes it.
+fn add_to_index(
+    workspace_path: &PathBuf,
+    index: &mut git2::Index,
+    diffs: Vec<FileDiff>,
+) -> Result<()> {
     for diff in diffs {
         match diff {
             FileDiff::Modified(p) | FileDiff::Added(p) => {
@@ -752,21 +1500,126 @@ fn git_commit(
         }
     }
     index.write()?;
-    let tree = index.write_tree()?;
-    let tree = repo.find_tree(tree)?;
-    let signature = repo.signature()?;
-    let parent = repo.head()?.peel_to_commit()?;
-    repo.commit(
-        Some("HEAD"),
-        &signature,
-        &signature,
-        message,
-        &tree,
-        &[&parent],
-    )?;
     Ok(())
 }
 
+fn git_stage(workspace_path: &PathBuf, diffs: Vec<FileDiff>) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    add_to_index(workspace_path, &mut repo.index()?, diffs)
+}
+
+/// Puts the files of the head back in the index, or takes them out of it
+/// before the first commit.
+fn git_unstage(workspace_path: &PathBuf, diffs: Vec<FileDiff>) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let paths = relative_paths(workspace_path, &diffs)?;
+    match repo
+        .head()
+        .and_then(|head| head.peel(git2::ObjectType::Commit))
+    {
+        Ok(head) => repo.reset_default(Some(&head), paths)?,
+        Err(_) => {
+            let mut index = repo.index()?;
+            for path in paths {
+                index.remove_path(path)?;
+            }
+            index.write()?;
+        }
+    }
+    Ok(())
+}
+
+fn git_discard(workspace_path: &PathBuf, diffs: Vec<FileDiff>) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let index = repo.index()?;
+    let mut checkout = CheckoutBuilder::new();
+    let mut tracked = false;
+    for path in relative_paths(workspace_path, &diffs)? {
+        if index.get_path(path, 0).is_some() {
+            checkout.path(path);
+            tracked = true;
+        } else {
+            // an untracked folder comes as one change
+            let path = workspace_path.join(path);
+            if path.is_dir() {
+                fs::remove_dir_all(path)?;
+            } else if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    if tracked {
+        repo.checkout_index(None, Some(checkout.force()))?;
+    }
+    Ok(())
+}
+
+fn relative_paths<'a>(
+    workspace_path: &PathBuf,
+    diffs: &'a [FileDiff],
+) -> Result<Vec<&'a Path>> {
+    let mut paths = Vec::new();
+    for diff in diffs {
+        for path in diff.paths() {
+            paths.push(path.strip_prefix(workspace_path)?);
+        }
+    }
+    Ok(paths)
+}
+
+fn git_checkout(workspace_path: &PathBuf, branch: &str, create: bool) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let branch = if create {
+        let head = repo.head()?.peel_to_commit()?;
+        repo.branch(branch, &head, false)?;
+        branch.to_string()
+    } else if repo.find_branch(branch, BranchType::Local).is_ok() {
+        branch.to_string()
+    } else {
+        // a remote branch is checked out as a local one tracking it
+        let remote = repo.find_branch(branch, BranchType::Remote)?;
+        let name = branch
+            .split_once('/')
+            .map(|(_, name)| name)
+            .unwrap_or(branch)
+            .to_string();
+        let commit = remote.get().peel_to_commit()?;
+        let mut local = repo.branch(&name, &commit, false)?;
+        local.set_upstream(Some(branch))?;
+        name
+    };
+    let refname = format!("refs/heads/{}", branch);
+    let target = repo.revparse_single(&refname)?;
+    repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+    repo.set_head(&refname)?;
+    Ok(())
+}
+
+/// Runs the git command line, which knows the credential helpers and the ssh
+/// setup of the user, unlike libgit2.
+fn git_remote(workspace_path: &PathBuf, command: GitRemoteCommand) -> Result<()> {
+    let output = std::process::Command::new("git")
+        .args(command.args())
+        .current_dir(workspace_path)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{}", stderr.trim()));
+    }
+    Ok(())
+}
+
+/// How many commits the local branch is ahead and behind of its upstream,
+/// `None` without one.
+fn git_ahead_behind(repo: &Repository, branch: &str) -> Option<(usize, usize)> {
+    let local = repo.find_branch(branch, BranchType::Local).ok()?;
+    let upstream = local.upstream().ok()?;
+    repo.graph_ahead_behind(local.get().target()?, upstream.get().target()?)
+        .ok()
+}
+
 fn git_delta_format(
     workspace_path: &PathBuf,
     delta: &git2::DiffDelta,
@@ -801,16 +1654,10 @@ fn git_diff_new(workspace_path: &PathBuf) -> Option<DiffInfo> {
         branches.push(branch.ok()?.0.name().ok()??.to_string());
     }
 
-    let mut deltas = Vec::new();
     let mut diff_options = DiffOptions::new();
     let diff = repo
         .diff_index_to_workdir(None, Some(diff_options.include_untracked(true)))
         .ok()?;
-    for delta in diff.deltas() {
-        if let Some(delta) = git_delta_format(workspace_path, &delta) {
-            deltas.push(delta);
-        }
-    }
     let cached_diff = repo
         .diff_tree_to_index(
             repo.find_tree(repo.revparse_single("HEAD^{tree}").ok()?.id())
@@ -820,11 +1667,23 @@ fn git_diff_new(workspace_path: &PathBuf) -> Option<DiffInfo> {
             None,
         )
         .ok()?;
-    for delta in cached_diff.deltas() {
-        if let Some(delta) = git_delta_format(workspace_path, &delta) {
-            deltas.push(delta);
-        }
-    }
+    let ahead_behind = git_ahead_behind(&repo, &name);
+    Some(DiffInfo {
+        head: name,
+        branches,
+        diffs: git_file_diffs(workspace_path, &diff),
+        staged: git_file_diffs(workspace_path, &cached_diff),
+        ahead_behind,
+    })
+}
+
+/// The changes of the files in the diff by path, with a file added and one
+/// deleted with the same content as a rename.
+fn git_file_diffs(workspace_path: &PathBuf, diff: &git2::Diff) -> Vec<FileDiff> {
+    let deltas: Vec<(git2::Delta, git2::Oid, PathBuf)> = diff
+        .deltas()
+        .filter_map(|delta| git_delta_format(workspace_path, &delta))
+        .collect();
     let mut renames = Vec::new();
     let mut renamed_deltas = HashSet::new();
 
@@ -866,11 +1725,7 @@ fn git_diff_new(workspace_path: &PathBuf) -> Option<DiffInfo> {
         | FileDiff::Renamed(p, _)
         | FileDiff::Deleted(p) => p.clone(),
     });
-    Some(DiffInfo {
-        head: name,
-        branches,
-        diffs: file_diffs,
-    })
+    file_diffs
 }
 
 // fn git_diff(workspace_path: &PathBuf) -> Option<Vec<String>> {
@@ -999,3 +1854,209 @@ fn file_git_diff(
         line_changes,
     ))
 }
+
+/// Collects the first match of each line of a file, as (line number from 1,
+/// range in bytes, line), and the lines of context the searcher reports
+/// around them, which don't match themselves.
+struct SearchSink<'a> {
+    matcher: &'a RegexMatcher,
+    matches: Vec<(usize, (usize, usize), String)>,
+    context: Vec<(usize, String)>,
+}
+
+impl<'a> SearchSink<'a> {
+    fn new(matcher: &'a RegexMatcher) -> Self {
+        Self {
+            matcher,
+            matches: Vec::new(),
+            context: Vec::new(),
+        }
+    }
+}
+
+impl Sink for SearchSink<'_> {
+    type Error = io::Error;
+
+    fn matched(
+        &mut self,
+        _searcher: &Searcher,
+        mat: &SinkMatch<'_>,
+    ) -> Result<bool, io::Error> {
+        let line = std::str::from_utf8(mat.bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let line_number = mat.line_number().unwrap_or(0) as usize;
+        if let Some(m) = self.matcher.find(line.as_bytes())? {
+            self.matches
+                .push((line_number, (m.start(), m.end()), line.to_string()));
+        }
+        Ok(true)
+    }
+
+    fn context(
+        &mut self,
+        _searcher: &Searcher,
+        context: &SinkContext<'_>,
+    ) -> Result<bool, io::Error> {
+        // a line that isn't text is left out rather than ending the search
+        if let (Some(line_number), Ok(line)) =
+            (context.line_number(), std::str::from_utf8(context.bytes()))
+        {
+            self.context.push((line_number as usize, line.to_string()));
+        }
+        Ok(true)
+    }
+}
+
+/// Escapes the characters that have a meaning in a regex, so that the
+/// pattern matches literally.
+/// Writes the content to a file with a new name in the temporary directory.
+pub(crate) fn write_temp_file(content: &str, extension: &str) -> Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let path = std::env::temp_dir().join(format!(
+        "lapce-{}-{}.{}",
+        std::process::id(),
+        nanos,
+        extension
+    ));
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?
+        .write_all(content.as_bytes())?;
+    Ok(path)
+}
+
+fn escape_regex(pattern: &str) -> String {
+    let mut escaped = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_ahead_behind() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let repo = Repository::init(&dir).unwrap();
+        let sig = git2::Signature::now("lapce", "lapce@lapce.dev").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let first = repo
+            .commit(Some("HEAD"), &sig, &sig, "1", &tree, &[])
+            .unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        assert_eq!(git_ahead_behind(&repo, &branch), None);
+
+        repo.remote("origin", "https://example.com/lapce.git")
+            .unwrap();
+        repo.reference(&format!("refs/remotes/origin/{}", branch), first, true, "")
+            .unwrap();
+        repo.find_branch(&branch, BranchType::Local)
+            .unwrap()
+            .set_upstream(Some(&format!("origin/{}", branch)))
+            .unwrap();
+        let parent = repo.find_commit(first).unwrap();
+        for message in ["2", "3"] {
+            let parent = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])
+                .unwrap();
+        }
+        assert_eq!(git_ahead_behind(&repo, &branch), Some((2, 0)));
+
+        let remote = repo
+            .commit(None, &sig, &sig, "remote", &tree, &[&parent])
+            .unwrap();
+        repo.reference(&format!("refs/remotes/origin/{}", branch), remote, true, "")
+            .unwrap();
+        assert_eq!(git_ahead_behind(&repo, &branch), Some((2, 1)));
+    }
+
+    #[test]
+    fn test_git_stage_unstage_and_discard() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let repo = Repository::init(&dir).unwrap();
+        let sig = git2::Signature::now("lapce", "lapce@lapce.dev").unwrap();
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "1", &tree, &[])
+            .unwrap();
+
+        fs::write(dir.join("a.txt"), "b\n").unwrap();
+        fs::write(dir.join("b.txt"), "b\n").unwrap();
+        let modified = FileDiff::Modified(dir.join("a.txt"));
+        let added = FileDiff::Added(dir.join("b.txt"));
+        let diff = git_diff_new(&dir).unwrap();
+        assert_eq!(diff.diffs, vec![modified.clone(), added.clone()]);
+        assert!(diff.staged.is_empty());
+
+        git_stage(&dir, vec![modified.clone()]).unwrap();
+        let diff = git_diff_new(&dir).unwrap();
+        assert_eq!(diff.diffs, vec![added.clone()]);
+        assert_eq!(diff.staged, vec![modified.clone()]);
+
+        git_unstage(&dir, vec![modified.clone()]).unwrap();
+        let diff = git_diff_new(&dir).unwrap();
+        assert_eq!(diff.diffs, vec![modified.clone(), added.clone()]);
+        assert!(diff.staged.is_empty());
+
+        git_discard(&dir, vec![modified, added]).unwrap();
+        let diff = git_diff_new(&dir).unwrap();
+        assert!(diff.diffs.is_empty());
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a\n");
+        assert!(!dir.join("b.txt").exists());
+    }
+
+    #[test]
+    fn test_write_temp_file() {
+        let first = write_temp_file("echo 1\necho 2\n", "sh").unwrap();
+        let second = write_temp_file("", "sh").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.extension().unwrap(), "sh");
+        assert_eq!(fs::read_to_string(&first).unwrap(), "echo 1\necho 2\n");
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn test_search_context() {
+        let matcher = RegexMatcherBuilder::new().build("x").unwrap();
+        let mut sink = SearchSink::new(&matcher);
+        SearcherBuilder::new()
+            .before_context(1)
+            .after_context(1)
+            .build()
+            .search_slice(&matcher, b"a\nb\nax\nc\nx\nd\ne\n", &mut sink)
+            .unwrap();
+        assert_eq!(
+            sink.matches,
+            vec![
+                (3, (1, 2), "ax\n".to_string()),
+                (5, (0, 1), "x\n".to_string())
+            ]
+        );
+        // the line between the matches comes once
+        assert_eq!(
+            sink.context,
+            vec![
+                (2, "b\n".to_string()),
+                (4, "c\n".to_string()),
+                (6, "d\n".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use globset::GlobBuilder;
+use serde::{Deserialize, Serialize};
+use xi_rope::Rope;
+
+pub const FILE_NAME: &str = ".editorconfig";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// The `.editorconfig` properties of a file, from the files found up the
+/// directory tree. `None` is a property that isn't set, so the settings of
+/// the editor apply.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Properties {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub charset: Option<String>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    pub max_line_length: Option<usize>,
+    /// The `.editorconfig` files that were read, the nearest first.
+    pub files: Vec<PathBuf>,
+}
+
+impl Properties {
+    /// Reads the `.editorconfig` files from the folder of `path` up to the
+    /// one with `root = true`. The nearer files win over the farther ones,
+    /// and the later sections of a file over the earlier ones.
+    pub fn resolve(path: &Path) -> Properties {
+        let mut files = Vec::new();
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            let file = d.join(FILE_NAME);
+            if let Ok(content) = std::fs::read_to_string(&file) {
+                let (root, sections) = parse(&content);
+                files.push((file, sections));
+                if root {
+                    break;
+                }
+            }
+            dir = d.parent();
+        }
+
+        let mut values = HashMap::new();
+        for (file, sections) in files.iter().rev() {
+            let dir = file.parent().unwrap();
+            let relative = match path.strip_prefix(dir) {
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+            for (pattern, pairs) in sections {
+                if !section_matches(pattern, relative) {
+                    continue;
+                }
+                for (key, value) in pairs {
+                    if value == "unset" {
+                        values.remove(key);
+                    } else {
+                        values.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+
+        let mut properties = Properties::from_values(&values);
+        properties.files = files.into_iter().map(|(file, _)| file).collect();
+        properties
+    }
+
+    fn from_values(values: &HashMap<String, String>) -> Properties {
+        let get = |key: &str| values.get(key).map(|v| v.as_str());
+        let number = |key: &str| get(key).and_then(|v| v.parse::<usize>().ok());
+        let boolean = |key: &str| match get(key) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+
+        let indent_style = match get("indent_style") {
+            Some("tab") => Some(IndentStyle::Tab),
+            Some("space") => Some(IndentStyle::Space),
+            _ => None,
+        };
+        let mut tab_width = number("tab_width");
+        let mut indent_size = match get("indent_size") {
+            Some("tab") => tab_width,
+            _ => number("indent_size"),
+        };
+        if indent_size.is_none() && indent_style == Some(IndentStyle::Tab) {
+            indent_size = tab_width;
+        }
+        if tab_width.is_none() {
+            tab_width = indent_size;
+        }
+
+        Properties {
+            indent_style,
+            indent_size,
+            tab_width,
+            end_of_line: match get("end_of_line") {
+                Some("lf") => Some(LineEnding::Lf),
+                Some("crlf") => Some(LineEnding::Crlf),
+                Some("cr") => Some(LineEnding::Cr),
+                _ => None,
+            },
+            charset: get("charset").map(|c| c.to_string()),
+            trim_trailing_whitespace: boolean("trim_trailing_whitespace"),
+            insert_final_newline: boolean("insert_final_newline"),
+            max_line_length: number("max_line_length"),
+            files: Vec::new(),
+        }
+    }
+
+    /// What one level of indentation is, if the file sets it.
+    pub fn indent_unit(&self) -> Option<String> {
+        match self.indent_style? {
+            IndentStyle::Tab => Some("\t".to_string()),
+            IndentStyle::Space => Some(" ".repeat(self.indent_size.unwrap_or(4))),
+        }
+    }
+
+    pub fn line_ending(&self) -> &'static str {
+        self.end_of_line.unwrap_or(LineEnding::Lf).as_str()
+    }
+
+    /// The edits to make to `rope` before it's saved: the trailing
+    /// whitespace to trim and the final newline to insert, as the offsets
+    /// to replace and the text to put there.
+    pub fn save_edits(&self, rope: &Rope) -> Vec<(usize, usize, String)> {
+        let text = rope.to_string();
+        let mut edits = Vec::new();
+        if self.trim_trailing_whitespace == Some(true) {
+            let mut start = 0;
+            for line in text.split_inclusive('\n') {
+                let content =
+                    line.trim_end_matches(|c: char| c == '\n' || c == '\r');
+                let trimmed =
+                    content.trim_end_matches(|c: char| c == ' ' || c == '\t');
+                if trimmed.len() < content.len() {
+                    edits.push((
+                        start + trimmed.len(),
+                        start + content.len(),
+                        "".to_string(),
+                    ));
+                }
+                start += line.len();
+            }
+        }
+        if self.insert_final_newline == Some(true)
+            && !text.is_empty()
+            && !text.ends_with('\n')
+            && !text.ends_with('\r')
+        {
+            edits.push((text.len(), text.len(), self.line_ending().to_string()));
+        }
+        edits
+    }
+}
+
+/// Whether `path` is an `.editorconfig` file.
+pub fn is_editorconfig(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()) == Some(FILE_NAME)
+}
+
+type Section = (String, Vec<(String, String)>);
+
+/// The `root` property of the preamble and the sections of the file, with
+/// the keys and the values lowercased.
+fn parse(content: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            sections.push((line[1..line.len() - 1].to_string(), Vec::new()));
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+            match sections.last_mut() {
+                Some((_, pairs)) => pairs.push((key, value)),
+                None => {
+                    if key == "root" {
+                        root = value == "true";
+                    }
+                }
+            }
+        }
+    }
+    (root, sections)
+}
+
+/// Whether the section applies to the file at `relative`, its path from the
+/// folder of the `.editorconfig` file. A pattern without a `/` matches the
+/// file name at any depth.
+fn section_matches(pattern: &str, relative: &Path) -> bool {
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .map(|glob| glob.compile_matcher().is_match(relative))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_nearest_wins() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join(FILE_NAME),
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 4\n\
+             end_of_line = lf\n\n[*.{rs,toml}]\nmax_line_length = 100\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("src").join(FILE_NAME),
+            "[*.rs]\nindent_style = tab\nindent_size = unset\n",
+        )
+        .unwrap();
+
+        let properties = Properties::resolve(&dir.join("src").join("main.rs"));
+        assert_eq!(properties.indent_style, Some(IndentStyle::Tab));
+        assert_eq!(properties.indent_size, None);
+        assert_eq!(properties.end_of_line, Some(LineEnding::Lf));
+        assert_eq!(properties.max_line_length, Some(100));
+        assert_eq!(properties.indent_unit(), Some("\t".to_string()));
+        assert_eq!(properties.files.len(), 2);
+
+        let properties = Properties::resolve(&dir.join("README.md"));
+        assert_eq!(properties.indent_unit(), Some("    ".to_string()));
+        assert_eq!(properties.max_line_length, None);
+    }
+
+    #[test]
+    fn test_section_matches() {
+        assert!(section_matches("*.rs", Path::new("src/main.rs")));
+        assert!(section_matches("Makefile", Path::new("Makefile")));
+        assert!(section_matches("/src/*.rs", Path::new("src/main.rs")));
+        assert!(!section_matches("src/*.rs", Path::new("src/a/main.rs")));
+        assert!(section_matches("src/**/*.rs", Path::new("src/a/main.rs")));
+    }
+
+    #[test]
+    fn test_save_edits() {
+        let properties = Properties {
+            trim_trailing_whitespace: Some(true),
+            insert_final_newline: Some(true),
+            ..Default::default()
+        };
+        let edits = properties.save_edits(&Rope::from("a  \nb\t\r\nc"));
+        assert_eq!(
+            edits,
+            vec![
+                (1, 3, "".to_string()),
+                (5, 6, "".to_string()),
+                (9, 9, "\n".to_string()),
+            ]
+        );
+        assert!(Properties::default()
+            .save_edits(&Rope::from("a "))
+            .is_empty());
+    }
+}
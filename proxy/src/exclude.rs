@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+
+/// The `files.exclude` patterns of the workspace, in gitignore syntax. What
+/// they match is hidden from the explorer, the file palette and the searches,
+/// except for files picked by a search's include patterns.
+#[derive(Clone)]
+pub struct FileExcludes {
+    root: PathBuf,
+    exclude: Gitignore,
+    include: Option<Gitignore>,
+    folders: Option<Vec<String>>,
+}
+
+impl FileExcludes {
+    pub fn new(root: &Path, patterns: &[String]) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            exclude: build_matcher(root, patterns),
+            include: None,
+            folders: None,
+        }
+    }
+
+    /// Only walks the top-level folders named in `folders`, besides the files
+    /// at the top level.
+    pub fn with_folders(mut self, folders: Option<Vec<String>>) -> Self {
+        self.folders = folders;
+        self
+    }
+
+    /// Only keeps the files matching `patterns`, whether they're excluded or
+    /// not. Empty patterns keep every file that isn't excluded.
+    pub fn with_include(mut self, patterns: &[String]) -> Self {
+        if !patterns.is_empty() {
+            self.include = Some(build_matcher(&self.root, patterns));
+        }
+        self
+    }
+
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        if !path.starts_with(&self.root) || path == self.root {
+            return false;
+        }
+        if let Some(include) = self.include.as_ref() {
+            // folders are walked through, as files in them can be included
+            return !is_dir && !matches(include, path, false);
+        }
+        matches(&self.exclude, path, is_dir)
+    }
+
+    /// The files of the workspace that aren't ignored by git or excluded.
+    pub fn files(&self) -> impl Iterator<Item = PathBuf> {
+        let excludes = self.clone();
+        WalkBuilder::new(&self.root)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                !excludes.is_excluded(entry.path(), is_dir)
+                    && (!is_dir || excludes.is_walked_folder(entry.path()))
+            })
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+            .map(|entry| entry.into_path())
+    }
+
+    fn is_walked_folder(&self, path: &Path) -> bool {
+        let folders = match self.folders.as_ref() {
+            Some(folders) => folders,
+            None => return true,
+        };
+        if path.parent() != Some(self.root.as_path()) {
+            return true;
+        }
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| folders.iter().any(|f| f == name))
+            .unwrap_or(false)
+    }
+}
+
+fn build_matcher(root: &Path, patterns: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        if let Err(e) = builder.add_line(None, pattern) {
+            eprintln!("invalid files.exclude pattern {}: {}", pattern, e);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+fn matches(matcher: &Gitignore, path: &Path, is_dir: bool) -> bool {
+    matcher
+        .matched_path_or_any_parents(path, is_dir)
+        .is_ignore()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excludes(patterns: &[&str]) -> FileExcludes {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        FileExcludes::new(Path::new("/ws"), &patterns)
+    }
+
+    #[test]
+    fn test_excluded_paths() {
+        let excludes = excludes(&["node_modules", "*.log", "/build"]);
+        assert!(excludes.is_excluded(Path::new("/ws/node_modules"), true));
+        assert!(excludes.is_excluded(Path::new("/ws/a/node_modules/b.js"), false));
+        assert!(excludes.is_excluded(Path::new("/ws/a/out.log"), false));
+        assert!(excludes.is_excluded(Path::new("/ws/build"), true));
+        assert!(!excludes.is_excluded(Path::new("/ws/a/build"), true));
+        assert!(!excludes.is_excluded(Path::new("/ws/src/main.rs"), false));
+        assert!(!excludes.is_excluded(Path::new("/ws"), true));
+        assert!(!excludes.is_excluded(Path::new("/other/out.log"), false));
+    }
+
+    #[test]
+    fn test_include_punches_through() {
+        let excludes = excludes(&["node_modules"])
+            .with_include(&["node_modules/lib/*.js".to_string()]);
+        assert!(!excludes.is_excluded(Path::new("/ws/node_modules"), true));
+        assert!(!excludes.is_excluded(Path::new("/ws/node_modules/lib/a.js"), false));
+        assert!(excludes.is_excluded(Path::new("/ws/node_modules/lib/a.ts"), false));
+        assert!(excludes.is_excluded(Path::new("/ws/src/main.rs"), false));
+    }
+
+    #[test]
+    fn test_walked_folders() {
+        let excludes = excludes(&[]).with_folders(Some(vec!["src".to_string()]));
+        assert!(excludes.is_walked_folder(Path::new("/ws/src")));
+        assert!(excludes.is_walked_folder(Path::new("/ws/src/target")));
+        assert!(!excludes.is_walked_folder(Path::new("/ws/target")));
+        assert!(excludes(&[]).is_walked_folder(Path::new("/ws/target")));
+    }
+}
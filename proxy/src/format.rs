@@ -0,0 +1,217 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dispatch::write_temp_file;
+
+/// A command line tool that formats the files of a language, like
+/// `rustfmt`, from the `[format.<language>]` config.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FormatCommand {
+    pub command: String,
+    /// Its arguments, where `${file}` is the path of the file formatted.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Whether the text goes through its standard input and output, rather
+    /// than a temporary file whose path ends the arguments, formatted in
+    /// place.
+    pub stdin: bool,
+    /// How many milliseconds it gets before it's killed.
+    pub timeout: u64,
+}
+
+/// Formats `content`, the text of the file at `path`, with the command run
+/// in `cwd`, and returns the formatted text. A command that fails, tells
+/// anything on its standard error or runs out of time is an error with what
+/// it said.
+pub fn run_format_command(
+    format: &FormatCommand,
+    path: &Path,
+    content: &str,
+    cwd: Option<&Path>,
+) -> Result<String> {
+    let file = path.to_string_lossy();
+    let mut args: Vec<String> = format
+        .args
+        .iter()
+        .map(|arg| arg.replace("${file}", &file))
+        .collect();
+    let temp = if format.stdin {
+        None
+    } else {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("txt");
+        let temp = write_temp_file(content, extension)?;
+        args.push(temp.to_string_lossy().to_string());
+        Some(temp)
+    };
+
+    let result = run(format, &args, content, cwd).and_then(|output| match &temp {
+        Some(temp) => Ok(std::fs::read_to_string(temp)?),
+        None => Ok(output),
+    });
+    if let Some(temp) = temp {
+        let _ = std::fs::remove_file(temp);
+    }
+    let formatted = result?;
+    // a tool that prints nothing would empty the file
+    if formatted.is_empty() && !content.trim().is_empty() {
+        return Err(anyhow!("{} gave no output", format.command));
+    }
+    Ok(formatted)
+}
+
+fn run(
+    format: &FormatCommand,
+    args: &[String],
+    content: &str,
+    cwd: Option<&Path>,
+) -> Result<String> {
+    let mut command = Command::new(&format.command);
+    command
+        .args(args)
+        .stdin(if format.stdin {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("can't run {}: {}", format.command, e))?;
+
+    // written and read on their own threads, so a full pipe doesn't block
+    // the tool
+    if let Some(mut stdin) = child.stdin.take() {
+        let content = content.to_string();
+        thread::spawn(move || stdin.write_all(content.as_bytes()));
+    }
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let timeout = Duration::from_millis(format.timeout);
+    let status = match wait_timeout(&mut child, timeout)? {
+        Some(status) => status,
+        None => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!(
+                "{} didn't finish in {} ms and was stopped",
+                format.command,
+                format.timeout
+            ));
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr);
+    if !status.success() {
+        let message = if stderr.trim().is_empty() {
+            status.to_string()
+        } else {
+            stderr.trim().to_string()
+        };
+        return Err(anyhow!("{}: {}", format.command, message));
+    }
+    if !stderr.trim().is_empty() {
+        return Err(anyhow!("{}: {}", format.command, stderr.trim()));
+    }
+    String::from_utf8(stdout)
+        .map_err(|_| anyhow!("{} printed invalid UTF-8", format.command))
+}
+
+fn read_all<R: Read + Send + 'static>(
+    reader: Option<R>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut reader) = reader {
+            let _ = reader.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+fn wait_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> Result<Option<std::process::ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, stdin: bool, timeout: u64) -> FormatCommand {
+        FormatCommand {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            stdin,
+            timeout,
+        }
+    }
+
+    #[test]
+    fn test_format_through_stdin() {
+        let format = sh("tr a-z A-Z", true, 5000);
+        let formatted =
+            run_format_command(&format, Path::new("a.rs"), "fn a() {}\n", None);
+        assert_eq!(formatted.unwrap(), "FN A() {}\n");
+    }
+
+    #[test]
+    fn test_format_in_temporary_file() {
+        // the path of the temporary file comes as `$0` of the script
+        let format = sh("sed -i.bak 's/a/b/' \"$0\" && rm \"$0.bak\"", false, 5000);
+        let formatted =
+            run_format_command(&format, Path::new("a.rs"), "fn a() {}\n", None);
+        assert_eq!(formatted.unwrap(), "fn b() {}\n");
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let path = Path::new("a.rs");
+        let error = run_format_command(
+            &sh("echo bad >&2; exit 1", true, 5000),
+            path,
+            "a",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "sh: bad");
+        let error = run_format_command(
+            &sh("cat; echo warning >&2", true, 5000),
+            path,
+            "a",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "sh: warning");
+        assert!(
+            run_format_command(&sh("true", true, 5000), path, "a", None).is_err()
+        );
+        let start = Instant::now();
+        let error = run_format_command(&sh("sleep 5", true, 100), path, "a", None)
+            .unwrap_err();
+        assert!(error.to_string().contains("was stopped"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
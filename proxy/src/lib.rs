@@ -1,8 +1,16 @@
 pub mod buffer;
 pub mod dispatch;
+pub mod editorconfig;
+pub mod exclude;
+pub mod format;
 pub mod lsp;
 pub mod plugin;
+pub mod protocol;
+pub mod save;
+pub mod shell_env;
 pub mod terminal;
+pub mod trash;
+pub mod watcher;
 
 use dispatch::Dispatcher;
 
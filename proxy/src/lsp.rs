@@ -51,6 +51,7 @@ pub struct LspState {
 
 pub struct LspClient {
     language_id: String,
+    exec_path: String,
     options: Option<Value>,
     state: Arc<Mutex<LspState>>,
     dispatcher: Dispatcher,
@@ -87,6 +88,19 @@ impl LspCatalog {
         self.clients.insert(language_id.to_string(), client);
     }
 
+    /// The language servers started so far, as (language id, command) pairs.
+    pub fn commands(&self) -> Vec<(String, String)> {
+        let mut commands: Vec<(String, String)> = self
+            .clients
+            .iter()
+            .map(|(language_id, client)| {
+                (language_id.to_string(), client.exec_path.clone())
+            })
+            .collect();
+        commands.sort();
+        commands
+    }
+
     pub fn new_buffer(
         &self,
         buffer_id: &BufferId,
@@ -348,6 +362,7 @@ impl LspClient {
         let lsp_client = Arc::new(LspClient {
             dispatcher,
             language_id,
+            exec_path: exec_path.to_string(),
             options,
             state: Arc::new(Mutex::new(LspState {
                 next_id: 0,
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+/// The version of the messages between the UI and the proxy. The major
+/// version changes when a message changes incompatibly, the minor one when
+/// messages are added.
+pub const PROTOCOL_VERSION: &str = "1.19";
+
+pub const CAPABILITY_GLOBAL_SEARCH: &str = "global_search";
+pub const CAPABILITY_FILE_WATCHING: &str = "file_watching";
+pub const CAPABILITY_TERMINAL: &str = "terminal";
+pub const CAPABILITY_GIT: &str = "git";
+pub const CAPABILITY_ENVIRONMENT: &str = "environment";
+pub const CAPABILITY_TERMINAL_CWD: &str = "terminal_cwd";
+pub const CAPABILITY_REPLACE: &str = "replace";
+pub const CAPABILITY_WORKSPACE_EDIT: &str = "workspace_edit";
+pub const CAPABILITY_SEARCH_CONTEXT: &str = "search_context";
+pub const CAPABILITY_TEMP_FILE: &str = "temp_file";
+pub const CAPABILITY_WATCHER_STATS: &str = "watcher_stats";
+pub const CAPABILITY_FORMAT_COMMAND: &str = "format_command";
+pub const CAPABILITY_GIT_FILE: &str = "git_file";
+pub const CAPABILITY_WRITE_FILE: &str = "write_file";
+/// The matches of a search with a `search_id` come file by file, and the
+/// search can be cancelled.
+pub const CAPABILITY_SEARCH_STREAM: &str = "search_stream";
+pub const CAPABILITY_COUNT_FILES: &str = "count_files";
+pub const CAPABILITY_FILE_SIZE: &str = "file_size";
+pub const CAPABILITY_GIT_CHECKOUT: &str = "git_checkout";
+pub const CAPABILITY_GIT_REMOTE: &str = "git_remote";
+/// Deleting to the trash, listing and restoring what was deleted.
+pub const CAPABILITY_TRASH: &str = "trash";
+pub const CAPABILITY_SYNC_BUFFER: &str = "sync_buffer";
+/// Terminals running a command instead of a shell, whose exit code comes
+/// with their `close_terminal`.
+pub const CAPABILITY_TASKS: &str = "tasks";
+
+/// The capabilities of the proxies from before the handshake existed.
+const LEGACY_CAPABILITIES: &[&str] = &[
+    CAPABILITY_GLOBAL_SEARCH,
+    CAPABILITY_FILE_WATCHING,
+    CAPABILITY_TERMINAL,
+    CAPABILITY_GIT,
+];
+
+/// The optional features this build of the proxy supports.
+pub const CAPABILITIES: &[&str] = &[
+    CAPABILITY_GLOBAL_SEARCH,
+    CAPABILITY_FILE_WATCHING,
+    CAPABILITY_TERMINAL,
+    CAPABILITY_GIT,
+    CAPABILITY_ENVIRONMENT,
+    CAPABILITY_TERMINAL_CWD,
+    CAPABILITY_REPLACE,
+    CAPABILITY_WORKSPACE_EDIT,
+    CAPABILITY_SEARCH_CONTEXT,
+    CAPABILITY_TEMP_FILE,
+    CAPABILITY_WATCHER_STATS,
+    CAPABILITY_FORMAT_COMMAND,
+    CAPABILITY_GIT_FILE,
+    CAPABILITY_WRITE_FILE,
+    CAPABILITY_SEARCH_STREAM,
+    CAPABILITY_COUNT_FILES,
+    CAPABILITY_FILE_SIZE,
+    CAPABILITY_GIT_CHECKOUT,
+    CAPABILITY_GIT_REMOTE,
+    CAPABILITY_TRASH,
+    CAPABILITY_SYNC_BUFFER,
+    CAPABILITY_TASKS,
+];
+
+/// What each side reports about itself in the handshake.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolInfo {
+    pub version: String,
+    pub capabilities: Vec<String>,
+}
+
+impl ProtocolInfo {
+    pub fn current() -> Self {
+        Self {
+            version: PROTOCOL_VERSION.to_string(),
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// A proxy from before the handshake existed, which doesn't answer it.
+    pub fn legacy() -> Self {
+        Self {
+            version: "0.0".to_string(),
+            capabilities: LEGACY_CAPABILITIES
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.version == "0.0"
+    }
+
+    fn major(&self) -> &str {
+        self.version.split('.').next().unwrap_or("")
+    }
+
+    /// The legacy proxies speak the same protocol minus the newer messages, so
+    /// they're compatible once the features they lack are disabled.
+    pub fn is_compatible(&self, other: &ProtocolInfo) -> bool {
+        self.is_legacy() || other.is_legacy() || self.major() == other.major()
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// The capabilities of `self` that `other` lacks.
+    pub fn missing_capabilities(&self, other: &ProtocolInfo) -> Vec<String> {
+        self.capabilities
+            .iter()
+            .filter(|c| !other.has_capability(c))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,465 @@
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use xi_rope::Rope;
+
+/// How a file ended up saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveMethod {
+    /// Written to a temporary file next to it, which then replaced it.
+    Atomic,
+    /// Written over in place.
+    Direct,
+}
+
+/// Saves the text to the file at `path`, or to the file it links to.
+///
+/// The text goes to a temporary file in the same folder, synced and renamed
+/// over the file, so a crash never leaves it half written. The new file gets
+/// the permissions, owner and extended attributes of the old one. When that
+/// can't be done, e.g. in a read-only folder, for a file owned by someone
+/// else or with other hard links, the file is written over in place instead.
+pub fn save_file(path: &Path, rope: &Rope) -> Result<SaveMethod> {
+    let target = link_target(path);
+    let method = match save_atomic(&target, rope) {
+        Ok(()) => SaveMethod::Atomic,
+        Err(e) => {
+            eprintln!(
+                "can't save {:?} atomically, writing in place: {}",
+                target, e
+            );
+            save_direct(&target, rope)?;
+            SaveMethod::Direct
+        }
+    };
+    eprintln!("saved {:?} {:?}", target, method);
+    Ok(method)
+}
+
+/// The file a symlink points to, so the save doesn't replace the link.
+fn link_target(path: &Path) -> PathBuf {
+    let is_link = fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false);
+    if is_link {
+        // a dangling link is written through, which creates its target
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn save_atomic(path: &Path, rope: &Rope) -> Result<()> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.as_ref().map(|m| m.nlink() > 1).unwrap_or(false) {
+            return Err(anyhow!("the file has other hard links"));
+        }
+    }
+
+    let tmp_path = temp_path(path)?;
+    let result = write_temp(&tmp_path, path, rope, metadata.as_ref())
+        .and_then(|_| fs::rename(&tmp_path, path).map_err(|e| e.into()));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+    // makes the rename itself durable
+    #[cfg(unix)]
+    {
+        if let Some(dir) = path.parent() {
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+    }
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{:?} has no file name", path))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    Ok(path.with_file_name(tmp_name))
+}
+
+fn write_temp(
+    tmp_path: &Path,
+    path: &Path,
+    rope: &Rope,
+    metadata: Option<&Metadata>,
+) -> Result<()> {
+    // left behind by a crash of an earlier save
+    let _ = fs::remove_file(tmp_path);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(tmp_path)?;
+    write_rope(&mut f, rope)?;
+    if let Some(metadata) = metadata {
+        // the owner goes first, as changing it clears the setuid bits
+        #[cfg(unix)]
+        unix::copy_owner(&f, metadata)?;
+        f.set_permissions(metadata.permissions())?;
+        #[cfg(unix)]
+        unix::copy_xattrs(path, tmp_path)?;
+    }
+    f.sync_all()?;
+    Ok(())
+}
+
+fn save_direct(path: &Path, rope: &Rope) -> Result<()> {
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    write_rope(&mut f, rope)?;
+    f.sync_all()?;
+    Ok(())
+}
+
+/// Replaces lines of the file, given by their number from 1, their text and
+/// their replacement, keeping their line endings. Nothing is written when a
+/// line isn't the text it's expected to be, as the file changed since.
+pub fn replace_lines(path: &Path, lines: &[(usize, String, String)]) -> Result<()> {
+    let content = fs::read_to_string(path)?;
+    let mut file_lines: Vec<String> = content
+        .split_inclusive('\n')
+        .map(|l| l.to_string())
+        .collect();
+    for (line_number, old, new) in lines {
+        let line = line_number
+            .checked_sub(1)
+            .and_then(|i| file_lines.get_mut(i))
+            .filter(|line| line.trim_end_matches(|c| c == '\n' || c == '\r') == old)
+            .ok_or_else(|| anyhow!("line {} changed", line_number))?;
+        let ending = line[old.len()..].to_string();
+        *line = format!("{}{}", new, ending);
+    }
+    save_file(path, &Rope::from(file_lines.concat()))?;
+    Ok(())
+}
+
+/// Replaces the text of the file with `new`. Nothing is written when the file
+/// isn't `old` anymore, as it changed since `new` was made from it.
+pub fn replace_content(path: &Path, old: &str, new: &str) -> Result<()> {
+    if fs::read_to_string(path)? != old {
+        return Err(anyhow!("the file changed on disk"));
+    }
+    save_file(path, &Rope::from(new))?;
+    Ok(())
+}
+
+/// Writes the text to the file the way a save does, creating it if needed,
+/// like for "Save As".
+pub fn write_file(path: &Path, content: &str) -> Result<()> {
+    save_file(path, &Rope::from(content))?;
+    Ok(())
+}
+
+fn write_rope(f: &mut File, rope: &Rope) -> io::Result<()> {
+    for chunk in rope.iter_chunks(..rope.len()) {
+        f.write_all(chunk.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::CString;
+    use std::fs::{File, Metadata};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    pub fn copy_owner(f: &File, metadata: &Metadata) -> Result<()> {
+        let current = f.metadata()?;
+        if current.uid() == metadata.uid() && current.gid() == metadata.gid() {
+            return Ok(());
+        }
+        if unsafe { libc::fchown(f.as_raw_fd(), metadata.uid(), metadata.gid()) }
+            != 0
+        {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub fn copy_xattrs(from: &Path, to: &Path) -> Result<()> {
+        let from = c_path(from)?;
+        let to = c_path(to)?;
+        let names = match xattr_names(&from) {
+            Ok(names) => names,
+            // the filesystem doesn't have them
+            Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for name in names {
+            let value = xattr_value(&from, &name)?;
+            // e.g. a security label the new file got anyway
+            if xattr_value(&to, &name).ok().as_ref() == Some(&value) {
+                continue;
+            }
+            if unsafe { sys::setxattr(&to, &name, &value) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        Ok(())
+    }
+
+    fn c_path(path: &Path) -> Result<CString> {
+        Ok(CString::new(path.as_os_str().as_bytes())?)
+    }
+
+    fn xattr_names(path: &CString) -> io::Result<Vec<CString>> {
+        let list = read_sized(|buf| unsafe { sys::listxattr(path, buf) })?;
+        Ok(list
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| CString::new(name).ok())
+            .collect())
+    }
+
+    fn xattr_value(path: &CString, name: &CString) -> io::Result<Vec<u8>> {
+        read_sized(|buf| unsafe { sys::getxattr(path, name, buf) })
+    }
+
+    /// Calls `f` once for the size of the result, then once to read it.
+    fn read_sized(f: impl Fn(&mut [u8]) -> libc::ssize_t) -> io::Result<Vec<u8>> {
+        let size = f(&mut []);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0; size as usize];
+        let size = f(&mut buf);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(size as usize);
+        Ok(buf)
+    }
+
+    #[cfg(target_os = "linux")]
+    mod sys {
+        use std::ffi::CString;
+
+        pub unsafe fn listxattr(path: &CString, buf: &mut [u8]) -> libc::ssize_t {
+            libc::listxattr(path.as_ptr(), buf.as_mut_ptr() as *mut _, buf.len())
+        }
+
+        pub unsafe fn getxattr(
+            path: &CString,
+            name: &CString,
+            buf: &mut [u8],
+        ) -> libc::ssize_t {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+            )
+        }
+
+        pub unsafe fn setxattr(
+            path: &CString,
+            name: &CString,
+            value: &[u8],
+        ) -> libc::c_int {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const _,
+                value.len(),
+                0,
+            )
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use std::ffi::CString;
+
+        pub unsafe fn listxattr(path: &CString, buf: &mut [u8]) -> libc::ssize_t {
+            libc::listxattr(path.as_ptr(), buf.as_mut_ptr() as *mut _, buf.len(), 0)
+        }
+
+        pub unsafe fn getxattr(
+            path: &CString,
+            name: &CString,
+            buf: &mut [u8],
+        ) -> libc::ssize_t {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                0,
+                0,
+            )
+        }
+
+        pub unsafe fn setxattr(
+            path: &CString,
+            name: &CString,
+            value: &[u8],
+        ) -> libc::c_int {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const _,
+                value.len(),
+                0,
+                0,
+            )
+        }
+    }
+
+    /// Other unixes are saved without their extended attributes.
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    mod sys {
+        use std::ffi::CString;
+
+        pub unsafe fn listxattr(_path: &CString, _buf: &mut [u8]) -> libc::ssize_t {
+            0
+        }
+
+        pub unsafe fn getxattr(
+            _path: &CString,
+            _name: &CString,
+            _buf: &mut [u8],
+        ) -> libc::ssize_t {
+            0
+        }
+
+        pub unsafe fn setxattr(
+            _path: &CString,
+            _name: &CString,
+            _value: &[u8],
+        ) -> libc::c_int {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_new_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let path = dir.join("a.txt");
+        let method = save_file(&path, &Rope::from("hello")).unwrap();
+        assert_eq!(method, SaveMethod::Atomic);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let path = dir.join("run.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let method = save_file(&path, &Rope::from("new")).unwrap();
+        assert_eq!(method, SaveMethod::Atomic);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replace_lines() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let path = dir.join("a.txt");
+        fs::write(&path, "a\r\nb\nc").unwrap();
+        let lines = vec![
+            (1, "a".to_string(), "x".to_string()),
+            (3, "c".to_string(), "z".to_string()),
+        ];
+        replace_lines(&path, &lines).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x\r\nb\nz");
+
+        // the first line isn't "a" anymore
+        assert!(replace_lines(&path, &lines).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x\r\nb\nz");
+    }
+
+    #[test]
+    fn test_replace_content() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let path = dir.join("a.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+        replace_content(&path, "a\nb\n", "a\nc\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nc\n");
+
+        // the file isn't "a\nb\n" anymore
+        assert!(replace_content(&path, "a\nb\n", "x").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nc\n");
+    }
+
+    #[test]
+    fn test_write_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("a.txt");
+        write_file(&path, "a\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        write_file(&path, "b\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\n");
+        assert!(write_file(&temp.path().join("missing/a.txt"), "a").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_through_symlink() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        save_file(&link, &Rope::from("new")).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_hard_link_in_place() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        let path = dir.join("a.txt");
+        let other = dir.join("b.txt");
+        fs::write(&path, "old").unwrap();
+        fs::hard_link(&path, &other).unwrap();
+        let method = save_file(&path, &Rope::from("new")).unwrap();
+        assert_eq!(method, SaveMethod::Direct);
+        assert_eq!(fs::read_to_string(&other).unwrap(), "new");
+    }
+}
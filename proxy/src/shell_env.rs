@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc::channel,
+    thread,
+    time::Duration,
+};
+
+const ENV_MARKER: &str = "_LAPCE_SHELL_ENV_";
+const IGNORED_VARS: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+/// Runs the user's login shell once to capture the environment its profile
+/// sets up, which a process launched from the dock or a desktop entry misses.
+#[cfg(unix)]
+pub fn login_shell_env() -> Option<HashMap<String, String>> {
+    let shell = env::var("SHELL").ok()?;
+    let mut child = Command::new(shell)
+        .arg("-i")
+        .arg("-l")
+        .arg("-c")
+        .arg(format!("printf '{}'; command env -0", ENV_MARKER))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut child_stdout = child.stdout.take()?;
+    let (sender, receiver) = channel();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = sender.send(child_stdout.read_to_end(&mut output).map(|_| output));
+    });
+    let output = receiver.recv_timeout(Duration::from_secs(5));
+    // a profile that waits for input or hangs isn't left running
+    if output.is_err() {
+        let _ = child.kill();
+    } else {
+        // it's done once it sent the output, so no other thread is left
+        // when the caller sets the environment
+        let _ = reader.join();
+    }
+    let _ = child.wait();
+    let output = output.ok()?.ok()?;
+    let stdout = String::from_utf8_lossy(&output);
+    // anything the profile prints before the marker isn't part of the env
+    let (_, vars) = stdout.split_once(ENV_MARKER)?;
+    Some(
+        vars.split('\0')
+            .filter_map(|var| var.split_once('='))
+            .filter(|(name, _)| !name.is_empty() && !IGNORED_VARS.contains(name))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    )
+}
+
+#[cfg(not(unix))]
+pub fn login_shell_env() -> Option<HashMap<String, String>> {
+    None
+}
+
+/// Merges the login shell environment into the one of this process, which
+/// the language servers, terminals and plugins inherit. It sets variables,
+/// so it has to run before any other thread starts.
+pub fn apply_login_shell_env() {
+    if let Some(vars) = login_shell_env() {
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+    }
+}
+
+/// Where the program resolves to with the PATH of this process.
+pub fn which(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return if path.is_file() {
+            Some(path.to_path_buf())
+        } else {
+            None
+        };
+    }
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", program, ext)))
+            .find(|path| path.is_file())
+    })
+}
+
+/// A plain text report of the effective PATH, and of where each program
+/// resolved, given as (label, program) pairs.
+pub fn environment_report(programs: &[(String, String)]) -> String {
+    let mut report = "PATH\n".to_string();
+    if let Some(path) = env::var_os("PATH") {
+        for dir in env::split_paths(&path) {
+            report += &format!("    {}\n", dir.display());
+        }
+    }
+
+    report += "\nPrograms\n";
+    let width = programs
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    for (label, program) in programs {
+        let resolved = match which(program) {
+            Some(path) => path.display().to_string(),
+            None => format!("MISSING ({} not found)", program),
+        };
+        report += &format!("    {:width$}  {}\n", label, resolved, width = width);
+    }
+    report
+}
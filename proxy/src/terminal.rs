@@ -1,9 +1,9 @@
 use std::{
     borrow::Cow,
     collections::{HashMap, VecDeque},
-    fs::File,
+    fs::{self, File},
     io::{self, ErrorKind, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::atomic::{self, AtomicU64},
 };
 
@@ -55,6 +55,8 @@ pub type TermConfig = alacritty_terminal::config::Config;
 
 pub struct Terminal {
     term_id: TermId,
+    /// Where the command of a task writes its exit code.
+    status_path: Option<PathBuf>,
     poll: mio::Poll,
     pty: alacritty_terminal::tty::Pty,
     rx: Receiver<Msg>,
@@ -62,9 +64,11 @@ pub struct Terminal {
 }
 
 impl Terminal {
+    /// A terminal running the login shell, or `command` when there's one.
     pub fn new(
         term_id: TermId,
         cwd: Option<PathBuf>,
+        command: Option<&str>,
         width: usize,
         height: usize,
     ) -> Terminal {
@@ -72,11 +76,22 @@ impl Terminal {
         let mut config = TermConfig::default();
         config.pty_config.working_directory =
             cwd.or_else(|| BaseDirs::new().map(|d| PathBuf::from(d.home_dir())));
-        config.pty_config.shell =
-            std::env::var("SHELL").ok().map(|shell| Program::WithArgs {
+        let status_path = command.map(|_| {
+            std::env::temp_dir().join(format!(
+                "lapce-task-{}-{}.status",
+                std::process::id(),
+                term_id.0
+            ))
+        });
+        config.pty_config.shell = match (command, status_path.as_ref()) {
+            (Some(command), Some(status_path)) => {
+                Some(task_program(command, status_path))
+            }
+            _ => std::env::var("SHELL").ok().map(|shell| Program::WithArgs {
                 program: shell.to_string(),
                 args: vec!["-l".to_string()],
-            });
+            }),
+        };
         setup_env(&config);
 
         #[cfg(target_os = "macos")]
@@ -91,6 +106,7 @@ impl Terminal {
 
         Terminal {
             term_id,
+            status_path,
             poll,
             pty,
             tx,
@@ -98,6 +114,17 @@ impl Terminal {
         }
     }
 
+    /// The process id of the shell.
+    #[cfg(unix)]
+    pub fn shell_pid(&self) -> Option<u32> {
+        Some(self.pty.child().id())
+    }
+
+    #[cfg(not(unix))]
+    pub fn shell_pid(&self) -> Option<u32> {
+        None
+    }
+
     pub fn run(&mut self, dispatcher: Dispatcher) {
         let mut tokens = (0..).map(Into::into);
         let poll_opts = PollOpt::edge() | PollOpt::oneshot();
@@ -133,6 +160,7 @@ impl Terminal {
                                 "close_terminal",
                                 json!({
                                     "term_id": self.term_id,
+                                    "exit_code": self.exit_code(),
                                 }),
                             );
                             break 'event_loop;
@@ -185,6 +213,16 @@ impl Terminal {
         let _ = self.pty.deregister(&self.poll);
     }
 
+    /// The exit code of the command of a task, once it exited.
+    fn exit_code(&self) -> Option<i32> {
+        let path = self.status_path.as_ref()?;
+        let code = fs::read_to_string(path)
+            .ok()
+            .and_then(|status| status.trim().parse().ok());
+        let _ = fs::remove_file(path);
+        code
+    }
+
     /// Drain the channel.
     ///
     /// Returns `false` when a shutdown message was received.
@@ -283,6 +321,50 @@ impl Writing {
     }
 }
 
+/// The program running the command line of a task, which writes the exit
+/// code of the command to `status_path` before it exits.
+#[cfg(not(windows))]
+fn task_program(command: &str, status_path: &Path) -> Program {
+    Program::WithArgs {
+        program: "/bin/sh".to_string(),
+        args: vec!["-c".to_string(), task_script(command, status_path)],
+    }
+}
+
+/// On Windows the exit code isn't told, so the output of a task never
+/// closes on its own.
+#[cfg(windows)]
+fn task_program(command: &str, _status_path: &Path) -> Program {
+    Program::WithArgs {
+        program: "powershell".to_string(),
+        args: vec![
+            "-NoProfile".to_string(),
+            "-Command".to_string(),
+            command.to_string(),
+        ],
+    }
+}
+
+/// The command in a subshell, so that an `exit` in it still gets its code
+/// written. It's on lines of its own, so that a trailing comment doesn't
+/// hide the rest.
+#[cfg(not(windows))]
+fn task_script(command: &str, status_path: &Path) -> String {
+    let status_path = status_path.to_string_lossy().replace('\'', "'\\''");
+    format!("(\n{}\n)\nprintf '%s' \"$?\" > '{}'", command, status_path)
+}
+
+/// The working directory of the process, where the system tells it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn process_cwd(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn process_cwd(_pid: u32) -> Option<PathBuf> {
+    None
+}
+
 #[derive(Default)]
 pub struct State {
     write_list: VecDeque<Cow<'static, [u8]>>,
@@ -325,3 +407,30 @@ fn set_locale_environment() {
         .replace("-", "_");
     std::env::set_var("LC_ALL", locale + ".UTF-8");
 }
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    /// The exit code the script of the command writes.
+    fn run_task(command: &str) -> String {
+        let temp = tempfile::tempdir().unwrap();
+        let status_path = temp.path().join("it's.status");
+        Command::new("/bin/sh")
+            .arg("-c")
+            .arg(task_script(command, &status_path))
+            .output()
+            .unwrap();
+        fs::read_to_string(&status_path).unwrap()
+    }
+
+    #[test]
+    fn test_task_script_writes_exit_code() {
+        assert_eq!(run_task("true"), "0");
+        assert_eq!(run_task("false"), "1");
+        assert_eq!(run_task("exit 3"), "3");
+        assert_eq!(run_task("echo done # and a comment"), "0");
+    }
+}
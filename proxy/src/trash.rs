@@ -0,0 +1,283 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// The folder of the workspace the deleted files are moved to when there's
+/// no trash of the OS to use, like on a remote machine.
+pub const STAGING_DIR: &str = ".lapce-trash";
+
+const INDEX_FILE: &str = "index.json";
+
+/// A deleted file or folder that can be restored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeletedItem {
+    /// What the trash knows the item by.
+    pub id: String,
+    /// Where the item was before it was deleted.
+    pub path: PathBuf,
+    /// When it was deleted, in seconds since the epoch.
+    pub time: i64,
+}
+
+/// Moves the file or the folder to the trash of the OS, or to the staging
+/// folder of the workspace if `staging`.
+pub fn delete(workspace: &Path, path: &Path, staging: bool) -> Result<()> {
+    if staging {
+        stage(workspace, path).map(|_| ())
+    } else {
+        ::trash::delete(path).map_err(|e| anyhow!("{}", e))
+    }
+}
+
+/// The items deleted from the workspace in the last `retention_days`, the
+/// latest first. The staged items older than that are removed for good.
+pub fn list(
+    workspace: &Path,
+    staging: bool,
+    retention_days: u64,
+) -> Result<Vec<DeletedItem>> {
+    let since = now() - (retention_days * 24 * 60 * 60) as i64;
+    if staging {
+        purge(workspace, since)?;
+    }
+    let mut items = items(workspace, staging)?;
+    items.retain(|item| item.time >= since);
+    Ok(items)
+}
+
+/// Moves the item back to where it was, the one with the `id`, or else the
+/// latest one deleted from `path`. Nothing is overwritten: it fails if
+/// there's a file at that path again.
+pub fn restore(
+    workspace: &Path,
+    staging: bool,
+    id: Option<&str>,
+    path: Option<&Path>,
+) -> Result<DeletedItem> {
+    let item = items(workspace, staging)?
+        .into_iter()
+        .find(|item| {
+            id.map(|id| item.id == id).unwrap_or(true)
+                && path.map(|path| item.path == path).unwrap_or(true)
+        })
+        .ok_or_else(|| anyhow!("the item isn't in the trash anymore"))?;
+    if item.path.exists() {
+        return Err(anyhow!("{} already exists", item.path.display()));
+    }
+    if let Some(parent) = item.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if staging {
+        let dir = workspace.join(STAGING_DIR);
+        fs::rename(dir.join(&item.id), &item.path)?;
+        let mut index = read_index(&dir);
+        index.retain(|i| i.id != item.id);
+        write_index(&dir, &index)?;
+    } else {
+        os::restore(&item.id)?;
+    }
+    Ok(item)
+}
+
+/// All the items deleted from the workspace, the latest first.
+fn items(workspace: &Path, staging: bool) -> Result<Vec<DeletedItem>> {
+    let mut items = if staging {
+        read_index(&workspace.join(STAGING_DIR))
+    } else {
+        os::list()?
+            .into_iter()
+            .filter(|item| item.path.starts_with(workspace))
+            .collect()
+    };
+    items.sort_by_key(|item| -item.time);
+    Ok(items)
+}
+
+fn stage(workspace: &Path, path: &Path) -> Result<DeletedItem> {
+    let dir = workspace.join(STAGING_DIR);
+    if path.starts_with(&dir) {
+        return Err(anyhow!("{} is in the trash already", path.display()));
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} can't be deleted", path.display()))?
+        .to_string_lossy()
+        .to_string();
+    fs::create_dir_all(&dir)?;
+    // keeps the staged files out of git
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(gitignore, "*\n")?;
+    }
+
+    let time = now();
+    let mut id = format!("{}-{}", time, name);
+    let mut n = 1;
+    while dir.join(&id).exists() {
+        id = format!("{}-{}-{}", time, n, name);
+        n += 1;
+    }
+    fs::rename(path, dir.join(&id))?;
+
+    let item = DeletedItem {
+        id,
+        path: path.to_path_buf(),
+        time,
+    };
+    let mut index = read_index(&dir);
+    index.push(item.clone());
+    write_index(&dir, &index)?;
+    Ok(item)
+}
+
+/// Removes the staged items deleted before `since`.
+fn purge(workspace: &Path, since: i64) -> Result<()> {
+    let dir = workspace.join(STAGING_DIR);
+    let mut index = read_index(&dir);
+    let len = index.len();
+    index.retain(|item| {
+        if item.time >= since {
+            return true;
+        }
+        let path = dir.join(&item.id);
+        let _ = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        false
+    });
+    if index.len() < len {
+        write_index(&dir, &index)?;
+    }
+    Ok(())
+}
+
+fn read_index(dir: &Path) -> Vec<DeletedItem> {
+    fs::read_to_string(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_index(dir: &Path, items: &[DeletedItem]) -> Result<()> {
+    fs::write(dir.join(INDEX_FILE), serde_json::to_string(items)?)?;
+    Ok(())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"))))]
+mod os {
+    use ::trash::os_limited;
+    use anyhow::{anyhow, Result};
+
+    use super::DeletedItem;
+
+    pub fn list() -> Result<Vec<DeletedItem>> {
+        Ok(os_limited::list()
+            .map_err(|e| anyhow!("{}", e))?
+            .into_iter()
+            .map(|item| DeletedItem {
+                id: item.id.to_string_lossy().to_string(),
+                path: item.original_path(),
+                time: item.time_deleted,
+            })
+            .collect())
+    }
+
+    pub fn restore(id: &str) -> Result<()> {
+        let item = os_limited::list()
+            .map_err(|e| anyhow!("{}", e))?
+            .into_iter()
+            .find(|item| item.id.to_string_lossy() == id)
+            .ok_or_else(|| anyhow!("the item isn't in the trash anymore"))?;
+        os_limited::restore_all(vec![item]).map_err(|e| anyhow!("{}", e))
+    }
+}
+
+/// The trash of macOS can't be listed, the Finder restores its items.
+#[cfg(target_os = "macos")]
+mod os {
+    use anyhow::{anyhow, Result};
+
+    use super::DeletedItem;
+
+    pub fn list() -> Result<Vec<DeletedItem>> {
+        Ok(Vec::new())
+    }
+
+    pub fn restore(_id: &str) -> Result<()> {
+        Err(anyhow!("restore the file from the Trash in the Finder"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_and_restore() {
+        let temp = tempfile::tempdir().unwrap();
+        let workspace = temp.path().to_path_buf();
+        let path = workspace.join("src").join("main.rs");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "fn main() {}").unwrap();
+
+        delete(&workspace, &path, true).unwrap();
+        assert!(!path.exists());
+        let items = list(&workspace, true, 30).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, path);
+
+        fs::write(&path, "new").unwrap();
+        assert!(restore(&workspace, true, Some(&items[0].id), None).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let item = restore(&workspace, true, None, Some(&path)).unwrap();
+        assert_eq!(item, items[0]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}");
+        assert!(list(&workspace, true, 30).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_purge() {
+        let temp = tempfile::tempdir().unwrap();
+        let workspace = temp.path().to_path_buf();
+        let dir = workspace.join(STAGING_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1-old.txt"), "").unwrap();
+        fs::write(dir.join("2-new.txt"), "").unwrap();
+        let day = 24 * 60 * 60;
+        write_index(
+            &dir,
+            &[
+                DeletedItem {
+                    id: "1-old.txt".to_string(),
+                    path: workspace.join("old.txt"),
+                    time: now() - 10 * day,
+                },
+                DeletedItem {
+                    id: "2-new.txt".to_string(),
+                    path: workspace.join("new.txt"),
+                    time: now() - day,
+                },
+            ],
+        )
+        .unwrap();
+
+        let items = list(&workspace, true, 7).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, "2-new.txt");
+        assert!(!dir.join("1-old.txt").exists());
+        assert!(dir.join("2-new.txt").exists());
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::exclude::FileExcludes;
+
+/// How long the events of the watcher gather before they're sent as one
+/// batch.
+pub const BATCH_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Batches with more paths than this are sent as bulk.
+pub const BULK_THRESHOLD: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+impl ChangeKind {
+    /// The change of the event on the path, if it changed anything.
+    pub fn of(kind: &notify::EventKind, path: &Path) -> Option<ChangeKind> {
+        match kind {
+            notify::EventKind::Create(_) => Some(ChangeKind::Created),
+            notify::EventKind::Remove(_) => Some(ChangeKind::Removed),
+            // a rename comes as one event for each of the two paths
+            notify::EventKind::Modify(notify::event::ModifyKind::Name(_)) => {
+                if path.exists() {
+                    Some(ChangeKind::Created)
+                } else {
+                    Some(ChangeKind::Removed)
+                }
+            }
+            notify::EventKind::Modify(_) => Some(ChangeKind::Modified),
+            _ => None,
+        }
+    }
+
+    /// What the path went through when `next` follows `self`, none if it's
+    /// back to how it was.
+    fn then(self, next: ChangeKind) -> Option<ChangeKind> {
+        match (self, next) {
+            (ChangeKind::Created, ChangeKind::Removed) => None,
+            (ChangeKind::Created, _) => Some(ChangeKind::Created),
+            (ChangeKind::Removed, ChangeKind::Created) => Some(ChangeKind::Modified),
+            (ChangeKind::Removed, _) => Some(ChangeKind::Removed),
+            (ChangeKind::Modified, next) => Some(next),
+        }
+    }
+}
+
+/// The paths of the workspace that changed since the last batch.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileChanges {
+    pub created: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    /// Too many paths changed to list them, like during a build, so they're
+    /// left out and everything that depends on the files should be
+    /// refreshed as a whole.
+    #[serde(default)]
+    pub bulk: bool,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        !self.bulk
+            && self.created.is_empty()
+            && self.modified.is_empty()
+            && self.removed.is_empty()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.created
+            .iter()
+            .chain(self.modified.iter())
+            .chain(self.removed.iter())
+    }
+}
+
+/// The changes gathered for the next batch, the last one of each path.
+#[derive(Default)]
+pub struct ChangeBatch {
+    changes: HashMap<PathBuf, ChangeKind>,
+}
+
+impl ChangeBatch {
+    pub fn add(&mut self, path: PathBuf, kind: ChangeKind) {
+        match self.changes.get(&path) {
+            Some(old) => match old.then(kind) {
+                Some(kind) => {
+                    self.changes.insert(path, kind);
+                }
+                None => {
+                    self.changes.remove(&path);
+                }
+            },
+            None => {
+                self.changes.insert(path, kind);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &ChangeKind)> {
+        self.changes.iter()
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&Path) -> bool) {
+        self.changes.retain(|path, _| f(path));
+    }
+
+    /// Empties the batch into the changes to send, bulk past `threshold`
+    /// paths.
+    pub fn take(&mut self, threshold: usize) -> FileChanges {
+        let mut changes = FileChanges::default();
+        if self.changes.len() > threshold {
+            self.changes.clear();
+            changes.bulk = true;
+            return changes;
+        }
+        for (path, kind) in self.changes.drain() {
+            match kind {
+                ChangeKind::Created => changes.created.push(path),
+                ChangeKind::Modified => changes.modified.push(path),
+                ChangeKind::Removed => changes.removed.push(path),
+            }
+        }
+        changes.created.sort();
+        changes.modified.sort();
+        changes.removed.sort();
+        changes
+    }
+}
+
+/// Tells the changes worth sending from the ones of paths ignored by git,
+/// excluded by `files.exclude` or in the `.git` folder.
+pub struct WatchFilter {
+    root: PathBuf,
+    excludes: FileExcludes,
+    /// The `.gitignore` of each folder looked at, none if it has none.
+    gitignores: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl WatchFilter {
+    pub fn new(root: &Path, excludes: FileExcludes) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            excludes,
+            gitignores: HashMap::new(),
+        }
+    }
+
+    pub fn is_git_path(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root)
+            .map(|p| p.starts_with(".git"))
+            .unwrap_or(false)
+    }
+
+    pub fn is_ignored(&mut self, path: &Path) -> bool {
+        if !path.starts_with(&self.root) || path == self.root {
+            return true;
+        }
+        if self.is_git_path(path) {
+            return true;
+        }
+        let is_dir = path.is_dir();
+        if self.excludes.is_excluded(path, is_dir) {
+            return true;
+        }
+        // the deepest `.gitignore` with a say decides
+        let root = self.root.clone();
+        for dir in path.ancestors().skip(1) {
+            if let Some(gitignore) = self.gitignore(dir) {
+                let matched = gitignore.matched_path_or_any_parents(path, is_dir);
+                if matched.is_ignore() {
+                    return true;
+                }
+                if matched.is_whitelist() {
+                    return false;
+                }
+            }
+            if dir == root {
+                break;
+            }
+        }
+        false
+    }
+
+    /// Reads the ignore files the changed path is or holds again next time:
+    /// the `.gitignore` of its folder, the `.git/info/exclude` of the root,
+    /// or those of a folder and the folders in it, which may be gone or
+    /// replaced.
+    pub fn forget(&mut self, path: &Path) {
+        if path.file_name().and_then(|n| n.to_str()) == Some(".gitignore") {
+            if let Some(dir) = path.parent() {
+                self.gitignores.remove(dir);
+            }
+            return;
+        }
+        if path == self.root.join(".git").join("info").join("exclude") {
+            self.gitignores.remove(&self.root);
+            return;
+        }
+        self.gitignores.retain(|dir, _| !dir.starts_with(path));
+    }
+
+    fn gitignore(&mut self, dir: &Path) -> Option<&Gitignore> {
+        let root = self.root.clone();
+        self.gitignores
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new(dir);
+                let mut found = false;
+                let mut files = vec![dir.join(".gitignore")];
+                if dir == root {
+                    files.push(dir.join(".git").join("info").join("exclude"));
+                }
+                for file in files.iter().filter(|file| file.is_file()) {
+                    found = true;
+                    if let Some(e) = builder.add(file) {
+                        eprintln!("can't read {:?}: {}", file, e);
+                    }
+                }
+                if !found {
+                    return None;
+                }
+                builder.build().ok()
+            })
+            .as_ref()
+    }
+}
+
+/// What the watcher went through, to tell what's behind a heavy load.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WatcherStats {
+    /// The paths watched, besides the workspace.
+    pub watched: Vec<PathBuf>,
+    pub events: u64,
+    /// The paths of the events, the ignored ones included.
+    pub paths: u64,
+    pub ignored: u64,
+    pub batches: u64,
+    pub bulk_batches: u64,
+    pub last_batch: usize,
+    pub largest_batch: usize,
+    /// The git statuses computed again.
+    pub git_refreshes: u64,
+}
+
+impl WatcherStats {
+    pub fn record_batch(&mut self, len: usize, bulk: bool) {
+        self.batches += 1;
+        if bulk {
+            self.bulk_batches += 1;
+        }
+        self.last_batch = len;
+        self.largest_batch = self.largest_batch.max(len);
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        report.push_str(&format!(
+            "batch interval: {}ms\n",
+            BATCH_INTERVAL.as_millis()
+        ));
+        report.push_str(&format!("bulk threshold: {} paths\n", BULK_THRESHOLD));
+        report.push_str(&format!("events: {}\n", self.events));
+        report.push_str(&format!("paths: {}\n", self.paths));
+        report.push_str(&format!("ignored paths: {}\n", self.ignored));
+        report.push_str(&format!(
+            "batches: {} ({} bulk)\n",
+            self.batches, self.bulk_batches
+        ));
+        report.push_str(&format!("last batch: {} paths\n", self.last_batch));
+        report.push_str(&format!("largest batch: {} paths\n", self.largest_batch));
+        report.push_str(&format!("git status refreshes: {}\n", self.git_refreshes));
+        report.push_str("watched:\n");
+        for path in self.watched.iter() {
+            report.push_str(&format!("  {}\n", path.display()));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_batch() {
+        let mut batch = ChangeBatch::default();
+        batch.add(PathBuf::from("/w/a"), ChangeKind::Created);
+        batch.add(PathBuf::from("/w/a"), ChangeKind::Modified);
+        batch.add(PathBuf::from("/w/b"), ChangeKind::Removed);
+        batch.add(PathBuf::from("/w/b"), ChangeKind::Created);
+        batch.add(PathBuf::from("/w/c"), ChangeKind::Created);
+        batch.add(PathBuf::from("/w/c"), ChangeKind::Removed);
+        batch.add(PathBuf::from("/w/d"), ChangeKind::Modified);
+        batch.add(PathBuf::from("/w/d"), ChangeKind::Removed);
+        let changes = batch.take(10);
+        assert_eq!(changes.created, vec![PathBuf::from("/w/a")]);
+        assert_eq!(changes.modified, vec![PathBuf::from("/w/b")]);
+        assert_eq!(changes.removed, vec![PathBuf::from("/w/d")]);
+        assert!(!changes.bulk);
+        assert_eq!(batch.len(), 0);
+
+        for i in 0..11 {
+            batch.add(PathBuf::from(format!("/w/{}", i)), ChangeKind::Modified);
+        }
+        let changes = batch.take(10);
+        assert!(changes.bulk);
+        assert_eq!(changes.paths().count(), 0);
+        assert!(!changes.is_empty());
+    }
+
+    #[test]
+    fn test_filter() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(root.join(".gitignore"), "/target\n*.log\n").unwrap();
+        fs::write(root.join("src/.gitignore"), "gen/\n!keep.log\n").unwrap();
+
+        let excludes = FileExcludes::new(&root, &["*.tmp".to_string()]);
+        let mut filter = WatchFilter::new(&root, excludes);
+        assert!(filter.is_ignored(&root.join("target/debug/app")));
+        assert!(filter.is_ignored(&root.join("build.log")));
+        assert!(filter.is_ignored(&root.join("src/gen/a.rs")));
+        assert!(filter.is_ignored(&root.join("src/a.tmp")));
+        assert!(filter.is_ignored(&root.join(".git/index")));
+        assert!(!filter.is_ignored(&root.join("src/keep.log")));
+        assert!(!filter.is_ignored(&root.join("src/main.rs")));
+
+        fs::write(root.join(".gitignore"), "").unwrap();
+        assert!(filter.is_ignored(&root.join("build.log")));
+        filter.forget(&root.join(".gitignore"));
+        assert!(!filter.is_ignored(&root.join("build.log")));
+    }
+
+    #[test]
+    fn test_filter_forgets_removed_folders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(
+            root.join("src/.gitignore"),
+            "gen/
+",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::write(
+            root.join(".git/info/exclude"),
+            "*.bak
+",
+        )
+        .unwrap();
+
+        let excludes = FileExcludes::new(&root, &[]);
+        let mut filter = WatchFilter::new(&root, excludes);
+        assert!(filter.is_ignored(&root.join("src/gen/a.rs")));
+        assert!(filter.is_ignored(&root.join("a.bak")));
+
+        // the folder is replaced by one without a `.gitignore`, with only
+        // the event of the folder
+        fs::remove_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        assert!(filter.is_ignored(&root.join("src/gen/a.rs")));
+        filter.forget(&root.join("src"));
+        assert!(!filter.is_ignored(&root.join("src/gen/a.rs")));
+
+        fs::write(root.join(".git/info/exclude"), "").unwrap();
+        filter.forget(&root.join(".git/info/exclude"));
+        assert!(!filter.is_ignored(&root.join("a.bak")));
+    }
+}